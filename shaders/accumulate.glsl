@@ -0,0 +1,33 @@
+precision highp float;
+
+// Folds the newest frame into the running average of the frames before it
+uniform sampler2D u_frame;
+uniform sampler2D u_history;
+// 1 / (frames averaged so far + 1); 1 starts a new average
+uniform float u_weight;
+// Regions where the average restarted after the rest (objects moved there),
+// as (min, max) in window pixels, and the weight of the frame inside each.
+// shaders.rs #defines MAX_REJECT_REGIONS from motion.rs.
+uniform int u_region_count;
+uniform vec4 u_regions[MAX_REJECT_REGIONS];
+uniform float u_region_weights[MAX_REJECT_REGIONS];
+
+varying vec2 v_texCoord;
+
+void main() {
+    // A pixel in several regions follows the youngest, which weighs most
+    float weight = u_weight;
+    for (int i = 0; i < MAX_REJECT_REGIONS; i++) {
+        if (i >= u_region_count) {
+            break;
+        }
+        vec4 region = u_regions[i];
+        if (all(greaterThanEqual(gl_FragCoord.xy, region.xy))
+            && all(lessThan(gl_FragCoord.xy, region.zw))) {
+            weight = max(weight, u_region_weights[i]);
+        }
+    }
+
+    vec4 history = texture2D(u_history, v_texCoord);
+    gl_FragColor = mix(history, texture2D(u_frame, v_texCoord), weight);
+}
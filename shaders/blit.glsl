@@ -0,0 +1,10 @@
+precision mediump float;
+
+// Copies the reduced-resolution render onto the canvas
+uniform sampler2D u_source;
+
+varying vec2 v_texCoord;
+
+void main() {
+    gl_FragColor = texture2D(u_source, v_texCoord);
+}
@@ -1,64 +1,200 @@
+// Expensive features are compiled in only when shaders.rs prepends their
+// #define: USE_FOG (fog volumes), USE_AO (ambient occlusion) and USE_GRID
+// (the instanced sphere grid). Without one, its uniforms are ignored.
+// Written in GLSL ES 1.00; on WebGL2 shaders.rs compiles it as 3.00 behind
+// a header mapping the renamed keywords, so it avoids 3.00's reserved words
+// and built-in function names.
 precision highp float;
 
 uniform vec2 u_resolution;
+// Window position of the first rendered pixel (non-zero when letterboxed)
+uniform vec2 u_viewport_origin;
 uniform vec3 u_camera_pos;
 uniform float u_time;
 uniform vec3 u_camera_forward;
 uniform vec3 u_camera_right;
 uniform vec3 u_camera_up;
+// tan(vertical field of view / 2): half the image height at unit distance
+uniform float u_tan_half_fov;
+// Lens radius (0 = pinhole) and distance along u_camera_forward to the plane
+// in focus
+uniform float u_aperture;
+uniform float u_focus_distance;
+uniform vec3 u_background_color;
+uniform float u_ambient;
+// Per-frame Halton sample in [0, 1)^2 used to jitter primary rays
+uniform vec2 u_sample_offset;
+// Frames drawn since the camera or scene last changed
+uniform float u_frames_since_change;
+// 0 = shaded color, 1 = G-buffer depth, 2 = G-buffer normals
+uniform int u_output_mode;
+// 1 = primary rays that escape write alpha 0 instead of the sky
+uniform int u_transparent_background;
+// Alpha of a fully shadowed shadow-catcher surface
+uniform float u_shadow_catcher_opacity;
+// HitRecord.object_id of the object to highlight; x is 0 for none
+uniform vec2 u_selected_object;
+// Quality settings (quality.rs); each loop below has a fixed upper bound
+uniform int u_max_bounces;          // <= MAX_BOUNCES
+uniform int u_samples_per_pixel;    // <= MAX_SAMPLES
+uniform int u_shadows;
+uniform int u_ao_samples;           // <= 4
+// Radius lights are spread over for soft shadows; 0 = point lights
+uniform float u_soft_shadow_radius;
+uniform int u_volume_steps;         // <= MAX_VOLUME_STEPS
+// Linear exposure multiplier applied before tone mapping
+uniform float u_exposure;
+// Scene units per meter; the distances in meters below are scaled by it
+uniform float u_world_scale;
+// 1: procedural textures are filtered over each pixel's footprint
+uniform int u_texture_filtering;
+
+const float PI = 3.14159265;
+const int MAX_SAMPLES = 16;
+const int MAX_BOUNCES = 16;
+const int MAX_VOLUME_STEPS = 32;
+// Reach of ambient occlusion rays, in meters
+const float AO_DISTANCE = 1.0;
+// Offset keeping secondary rays off the surface they leave, in meters
+const float RAY_EPSILON = 0.001;
+// Highlight color of the selected object (u_selected_object)
+const vec3 SELECTION_COLOR = vec3(1.0, 0.55, 0.1);
+
+// G-buffer encodings, decoded in gbuffer.rs. Alpha is 1 on hits, 0 on misses.
+// Depth: view-space distance along u_camera_forward / max_distance, packed
+//        base 255 across rgb (most significant byte in r).
+// Normals: outward world-space normal * 0.5 + 0.5 in rgb.
+// Rays end after GBUFFER_FAR meters
+const float GBUFFER_FAR = 100.0;
+// Exposure metering (u_output_mode 3): log2 luminance before exposure,
+// mapped from this range to [0, 1] and packed like depth
+const float LOG_LUMINANCE_MIN = -16.0;
+const float LOG_LUMINANCE_MAX = 16.0;
+
+// Scaled to scene units at the start of main()
+float ray_epsilon;
+float max_distance;
 
 // Scene data structures
 struct Material {
     vec3 albedo;
-    // 0: Lambertian, 1: Metal, 2: Dielectric
+    // 0: Lambertian, 1: Metal, 2: Dielectric, 3: Emissive (glows with
+    // albedo * emission_strength and reflects nothing)
     int material_type;
     float roughness;
     float ior;
+    // Added to the shaded color of any material type
+    vec3 emission;
+    float emission_strength;
+    // Below 1.0 the surface is partially see-through (ignored by dielectrics)
+    float opacity;
+    // Invisible except for the shadows cast onto it
+    bool shadow_catcher;
+    // 0: textures use world coordinates, 1: coordinates relative to the object
+    int texture_space;
+    // Dielectrics only: IOR spread across R, G and B as a fraction of ior - 1
+    float dispersion;
+    // Checker texture replacing albedo: cell size in scene units (0 = none)
+    // and the two cell colors
+    float checker_scale;
+    vec3 checker_a;
+    vec3 checker_b;
 };
 
 struct Sphere {
     vec3 center;
     float radius;
-    vec3 albedo;
-    int material_type;
-    float roughness;
-    float ior;
+    // Per-axis stretch making it an ellipsoid, (1, 1, 1) for a sphere
+    vec3 scale;
+    // Slot in u_sphere_textures sampled for the albedo, -1 for none
+    float image_texture;
+    Material material;
 };
 
 struct Plane {
     vec3 point;
     vec3 normal;
-    vec3 albedo;
-    int material_type;
+    Material material;
 };
 
+// rotation (a quaternion) and scale act about the center, see Box in scene.rs
 struct Box {
     vec3 center;
     vec3 size;
-    vec3 albedo;
-    int material_type;
-    float roughness;
-    float ior;
+    vec4 rotation;
+    vec3 scale;
+    Material material;
 };
 
+// rotation (a quaternion) and scale act about the middle of the axis
 struct Cylinder {
     vec3 base;
     vec3 axis;
     float radius;
-    vec3 albedo;
-    int material_type;
-    float roughness;
-    float ior;
+    vec4 rotation;
+    vec3 scale;
+    Material material;
+};
+
+// Tip at apex, widening along axis (direction and height) to a closed base
+// of radius around apex + axis
+struct Cone {
+    vec3 apex;
+    vec3 axis;
+    float radius;
+    Material material;
+};
+
+// A disk of radius around center, with a hole of inner_radius (0 for none)
+struct Disk {
+    vec3 center;
+    vec3 normal;
+    float radius;
+    float inner_radius;
+    Material material;
 };
 
 struct Triangle {
     vec3 v0;
     vec3 v1;
     vec3 v2;
-    vec3 albedo;
-    int material_type;
-    float roughness;
-    float ior;
+    Material material;
+};
+
+// One operand of a Csg: kind is its ObjectType::to_u32 (0 sphere, 2 box),
+// size its semi-axes for a sphere, its scaled size for a box, and rotation
+// a quaternion about the center
+struct CsgShape {
+    int kind;
+    vec3 center;
+    vec3 size;
+    vec4 rotation;
+    Material material;
+};
+
+// Two operands combined by op: 0 union, 1 a minus b, 2 intersection
+struct Csg {
+    int op;
+    CsgShape a;
+    CsgShape b;
+};
+
+// Spheres repeated at every multiple of cell_size. A cell_size component <= 0
+// keeps a single layer on that axis; extent < 0 repeats without bound.
+struct InstancedGrid {
+    vec3 cell_size;
+    float radius;
+    float extent;
+    Material material;
+};
+
+// Stars in a fraction `density` of the sky cells; `offset` shifts the cell
+// hash per seed and `twinkle` is the depth of each star's flicker
+struct Starfield {
+    float density;
+    float brightness;
+    vec2 offset;
+    float twinkle;
 };
 
 struct Light {
@@ -67,6 +203,14 @@ struct Light {
     float intensity;
 };
 
+// Sphere of uniform fog; `density` is extinction per unit length
+struct Volume {
+    vec3 center;
+    float radius;
+    float density;
+    vec3 color;
+};
+
 struct Ray {
     vec3 origin;
     vec3 direction;
@@ -78,27 +222,112 @@ struct HitRecord {
     float t;
     bool front_face;
     Material material;
+    // Reference point of the object hit, for object-space textures
+    vec3 object_origin;
+    // Segmentation id of the object hit as (object type + 1, index), see gbuffer.rs
+    vec2 object_id;
+    // Light slots that shade the object hit, one bit per slot
+    float light_mask;
+    // Image texture slot of the sphere hit, -1 for none
+    float image_texture;
 };
 
 // Scene uniforms
 uniform int u_sphere_count;
-uniform Sphere u_spheres[10];
-
 uniform int u_plane_count;
-uniform Plane u_planes[5];
-
 uniform int u_box_count;
-uniform Box u_boxes[5];
-
 uniform int u_cylinder_count;
-uniform Cylinder u_cylinders[5];
-
 uniform int u_triangle_count;
-uniform Triangle u_triangles[10];
+uniform int u_cone_count;
+uniform int u_disk_count;
+
+#ifdef DATA_TEXTURE
+// Objects packed by Scene::pack_data, one row of DATA_TEXELS texels per
+// object: spheres first, then planes, boxes, cylinders, triangles, cones,
+// disks and the triangles' BVH nodes. Rows wrap every DATA_OBJECTS_PER_ROW
+// objects
+// highp, since texel reads return the sampler's precision
+uniform highp sampler2D u_scene_data;
+uniform float u_scene_data_rows;
+uniform int u_bvh_node_count;
+
+const float DATA_TEXELS = 10.0;
+const float DATA_OBJECTS_PER_ROW = 128.0;
+const int MAX_SPHERES = 256;
+const int MAX_PLANES = 256;
+const int MAX_BOXES = 256;
+const int MAX_CYLINDERS = 256;
+const int MAX_CONES = 256;
+const int MAX_DISKS = 256;
+// Triangles are found through the BVH, see bvh.rs. shaders.rs #defines its
+// BVH_STACK_SIZE and BVH_LEAF_TRIANGLES from the constants there.
+// Most nodes one ray visits, as loops need a constant bound
+const int MAX_BVH_STEPS = 2048;
+#else
+const int MAX_SPHERES = 10;
+const int MAX_PLANES = 5;
+const int MAX_BOXES = 5;
+const int MAX_CYLINDERS = 5;
+const int MAX_TRIANGLES = 10;
+const int MAX_CONES = 5;
+const int MAX_DISKS = 8;
+
+uniform Sphere u_spheres[MAX_SPHERES];
+uniform Plane u_planes[MAX_PLANES];
+uniform Box u_boxes[MAX_BOXES];
+uniform Cylinder u_cylinders[MAX_CYLINDERS];
+uniform Triangle u_triangles[MAX_TRIANGLES];
+uniform Cone u_cones[MAX_CONES];
+uniform Disk u_disks[MAX_DISKS];
+#endif
 
+// Images spheres sample for their albedo, see Sphere.image_texture
+uniform sampler2D u_sphere_textures[4];
+
+uniform int u_grid_enabled;
+uniform InstancedGrid u_grid;
+
+uniform int u_starfield_enabled;
+uniform Starfield u_starfield;
+
+#ifndef DATA_TEXTURE
+// Per-slot object data: x is the object's index in the scene (slots are
+// assigned by priority, see HitRecord.object_id) and y its light linking
+// mask (HitRecord.light_mask)
+uniform vec2 u_sphere_slots[MAX_SPHERES];
+uniform vec2 u_plane_slots[MAX_PLANES];
+uniform vec2 u_box_slots[MAX_BOXES];
+uniform vec2 u_cylinder_slots[MAX_CYLINDERS];
+uniform vec2 u_triangle_slots[MAX_TRIANGLES];
+uniform vec2 u_cone_slots[MAX_CONES];
+uniform vec2 u_disk_slots[MAX_DISKS];
+#endif
+
+// Combinations are uniforms in both modes, see Scene::drawn_csg. Their
+// operands are left out of the object arrays above.
+const int MAX_CSG = 4;
+uniform int u_csg_count;
+uniform Csg u_csgs[MAX_CSG];
+// Per combination: (index of a, light mask of a, index of b, light mask of b)
+uniform vec4 u_csg_slots[MAX_CSG];
+
+#if __VERSION__ >= 300
+// On WebGL2 lights and volumes come from one uniform buffer, laid out as
+// LightBlock in scene.rs
+layout(std140) uniform SceneLights {
+    Light u_lights[4];
+    Volume u_volumes[3];
+    int u_light_count;
+    int u_volume_count;
+};
+#else
 uniform int u_light_count;
 uniform Light u_lights[4];
 
+uniform int u_volume_count;
+uniform Volume u_volumes[3];
+#endif
+
 varying vec2 v_texCoord;
 
 // Pseudo-random number generator
@@ -120,7 +349,7 @@ vec3 reflectRay(vec3 v, vec3 n) {
     return v - 2.0 * dot(v, n) * n;
 }
 
-bool refract(vec3 uv, vec3 n, float ni_over_nt, out vec3 refracted) {
+bool refractRay(vec3 uv, vec3 n, float ni_over_nt, out vec3 refracted) {
     float dt = dot(uv, n);
     float discriminant = 1.0 - ni_over_nt * ni_over_nt * (1.0 - dt * dt);
     if (discriminant > 0.0) {
@@ -136,7 +365,43 @@ float schlick(float cosine, float ref_idx) {
     return r0 + (1.0 - r0) * pow((1.0 - cosine), 5.0);
 }
 
+// The ray is scaled into the ellipsoid's local space, where it is a unit
+// sphere; the map is linear, so distances along the ray carry over
+bool hitEllipsoid(Sphere sphere, Ray ray, float t_min, float t_max, out HitRecord rec) {
+    vec3 semi_axes = sphere.radius * sphere.scale;
+    vec3 oc = (ray.origin - sphere.center) / semi_axes;
+    vec3 direction = ray.direction / semi_axes;
+    float a = dot(direction, direction);
+    float b = dot(oc, direction);
+    float c = dot(oc, oc) - 1.0;
+    float discriminant = b * b - a * c;
+    if (discriminant <= 0.0) return false;
+
+    float root = sqrt(discriminant);
+    float t = (-b - root) / a;
+    if (t >= t_max || t <= t_min) {
+        t = (-b + root) / a;
+        if (t >= t_max || t <= t_min) return false;
+    }
+
+    rec.t = t;
+    rec.point = ray.origin + t * ray.direction;
+    // The unit sphere's normal is its local point; normals go back through
+    // the inverse transpose of the scale, which divides by it again
+    vec3 outward_normal = normalize((oc + t * direction) / semi_axes);
+    rec.front_face = dot(ray.direction, outward_normal) < 0.0;
+    rec.normal = rec.front_face ? outward_normal : -outward_normal;
+    rec.material = sphere.material;
+    rec.object_origin = sphere.center;
+    return true;
+}
+
 bool hitSphere(Sphere sphere, Ray ray, float t_min, float t_max, out HitRecord rec) {
+    // Unscaled spheres keep the plain sphere test, so they render exactly as
+    // before ellipsoids
+    if (sphere.scale != vec3(1.0)) {
+        return hitEllipsoid(sphere, ray, t_min, t_max, rec);
+    }
     vec3 oc = ray.origin - sphere.center;
     float a = dot(ray.direction, ray.direction);
     float b = dot(oc, ray.direction);
@@ -151,10 +416,8 @@ bool hitSphere(Sphere sphere, Ray ray, float t_min, float t_max, out HitRecord r
             vec3 outward_normal = (rec.point - sphere.center) / sphere.radius;
             rec.front_face = dot(ray.direction, outward_normal) < 0.0;
             rec.normal = rec.front_face ? outward_normal : -outward_normal;
-            rec.material.albedo = sphere.albedo;
-            rec.material.material_type = sphere.material_type;
-            rec.material.roughness = sphere.roughness;
-            rec.material.ior = sphere.ior;
+            rec.material = sphere.material;
+            rec.object_origin = sphere.center;
             return true;
         }
         temp = (-b + sqrt(discriminant)) / a;
@@ -164,10 +427,8 @@ bool hitSphere(Sphere sphere, Ray ray, float t_min, float t_max, out HitRecord r
             vec3 outward_normal = (rec.point - sphere.center) / sphere.radius;
             rec.front_face = dot(ray.direction, outward_normal) < 0.0;
             rec.normal = rec.front_face ? outward_normal : -outward_normal;
-            rec.material.albedo = sphere.albedo;
-            rec.material.material_type = sphere.material_type;
-            rec.material.roughness = sphere.roughness;
-            rec.material.ior = sphere.ior;
+            rec.material = sphere.material;
+            rec.object_origin = sphere.center;
             return true;
         }
     }
@@ -183,17 +444,43 @@ bool hitPlane(Plane plane, Ray ray, float t_min, float t_max, out HitRecord rec)
             rec.point = ray.origin + t * ray.direction;
             rec.front_face = denom < 0.0;
             rec.normal = rec.front_face ? plane.normal : -plane.normal;
-            rec.material.albedo = plane.albedo;
-            rec.material.material_type = plane.material_type;
-            rec.material.roughness = 0.0;
-            rec.material.ior = 1.0;
+            rec.material = plane.material;
+            rec.object_origin = plane.point;
             return true;
         }
     }
     return false;
 }
 
-bool hitBox(Box box_obj, Ray ray, float t_min, float t_max, out HitRecord rec) {
+vec3 rotateByQuat(vec4 q, vec3 v) {
+    vec3 t = 2.0 * cross(q.xyz, v);
+    return v + q.w * t + cross(q.xyz, t);
+}
+
+bool isIdentityTransform(vec4 rotation, vec3 scale) {
+    return rotation == vec4(0.0, 0.0, 0.0, 1.0) && scale == vec3(1.0);
+}
+
+// The ray in the own space of an object scaled, then rotated, about pivot.
+// The map is linear, so distances along the ray carry over.
+Ray toObjectSpace(Ray ray, vec3 pivot, vec4 rotation, vec3 scale) {
+    vec4 inverse = vec4(-rotation.xyz, rotation.w);
+    Ray local;
+    local.origin = pivot + rotateByQuat(inverse, ray.origin - pivot) / scale;
+    local.direction = rotateByQuat(inverse, ray.direction) / scale;
+    return local;
+}
+
+// Takes a hit found with toObjectSpace back to the world ray. Normals go
+// through the inverse transpose, which divides by the scale; it keeps which
+// side of the surface the ray is on.
+void fromObjectSpace(Ray ray, vec4 rotation, vec3 scale, inout HitRecord rec) {
+    rec.point = ray.origin + rec.t * ray.direction;
+    rec.normal = normalize(rotateByQuat(rotation, rec.normal / scale));
+}
+
+// The box as if it had no rotation or scale
+bool hitBoxLocal(Box box_obj, Ray ray, float t_min, float t_max, out HitRecord rec) {
     vec3 m = 1.0 / ray.direction;
     vec3 n = m * (ray.origin - box_obj.center);
     vec3 k = abs(m) * box_obj.size * 0.5;
@@ -227,15 +514,26 @@ bool hitBox(Box box_obj, Ray ray, float t_min, float t_max, out HitRecord rec) {
     
     rec.front_face = dot(ray.direction, rec.normal) < 0.0;
     rec.normal = rec.front_face ? rec.normal : -rec.normal;
-    rec.material.albedo = box_obj.albedo;
-    rec.material.material_type = box_obj.material_type;
-    rec.material.roughness = box_obj.roughness;
-    rec.material.ior = box_obj.ior;
+    rec.material = box_obj.material;
+    rec.object_origin = box_obj.center;
     
     return true;
 }
 
-bool hitCylinder(Cylinder cylinder, Ray ray, float t_min, float t_max, out HitRecord rec) {
+bool hitBox(Box box_obj, Ray ray, float t_min, float t_max, out HitRecord rec) {
+    // Untransformed boxes skip the change of space, so they render exactly
+    // as before transforms
+    if (isIdentityTransform(box_obj.rotation, box_obj.scale)) {
+        return hitBoxLocal(box_obj, ray, t_min, t_max, rec);
+    }
+    Ray local = toObjectSpace(ray, box_obj.center, box_obj.rotation, box_obj.scale);
+    if (!hitBoxLocal(box_obj, local, t_min, t_max, rec)) return false;
+    fromObjectSpace(ray, box_obj.rotation, box_obj.scale, rec);
+    return true;
+}
+
+// The cylinder as if it had no rotation or scale
+bool hitCylinderLocal(Cylinder cylinder, Ray ray, float t_min, float t_max, out HitRecord rec) {
     vec3 oc = ray.origin - cylinder.base;
     vec3 axis = normalize(cylinder.axis);
     
@@ -266,119 +564,1138 @@ bool hitCylinder(Cylinder cylinder, Ray ray, float t_min, float t_max, out HitRe
     rec.normal = normalize(hit_point - center_line_point);
     rec.front_face = dot(ray.direction, rec.normal) < 0.0;
     rec.normal = rec.front_face ? rec.normal : -rec.normal;
-    rec.material.albedo = cylinder.albedo;
-    rec.material.material_type = cylinder.material_type;
-    rec.material.roughness = cylinder.roughness;
-    rec.material.ior = cylinder.ior;
+    rec.material = cylinder.material;
+    rec.object_origin = cylinder.base;
     
     return true;
 }
 
+bool hitCylinder(Cylinder cylinder, Ray ray, float t_min, float t_max, out HitRecord rec) {
+    if (isIdentityTransform(cylinder.rotation, cylinder.scale)) {
+        return hitCylinderLocal(cylinder, ray, t_min, t_max, rec);
+    }
+    vec3 pivot = cylinder.base + 0.5 * cylinder.axis;
+    Ray local = toObjectSpace(ray, pivot, cylinder.rotation, cylinder.scale);
+    if (!hitCylinderLocal(cylinder, local, t_min, t_max, rec)) return false;
+    fromObjectSpace(ray, cylinder.rotation, cylinder.scale, rec);
+    return true;
+}
+
+bool hitCone(Cone cone, Ray ray, float t_min, float t_max, out HitRecord rec) {
+    float height = length(cone.axis);
+    vec3 axis = cone.axis / height;
+    // Squared cosine of the half angle
+    float k = height * height / (height * height + cone.radius * cone.radius);
+    vec3 co = ray.origin - cone.apex;
+    float d_axis = dot(ray.direction, axis);
+    float co_axis = dot(co, axis);
+
+    float a = d_axis * d_axis - k * dot(ray.direction, ray.direction);
+    float b = 2.0 * (d_axis * co_axis - k * dot(ray.direction, co));
+    float c = co_axis * co_axis - k * dot(co, co);
+
+    float t = t_max + 1.0;
+    bool on_side = false;
+    float discriminant = b * b - 4.0 * a * c;
+    if (abs(a) > 1e-8 && discriminant >= 0.0) {
+        float sqrt_discriminant = sqrt(discriminant);
+        float t1 = (-b - sqrt_discriminant) / (2.0 * a);
+        float t2 = (-b + sqrt_discriminant) / (2.0 * a);
+        // The quadric is a double cone: a root whose height along the axis
+        // is below 0 lies on the mirrored cone behind the apex, and one above
+        // the height is past the base. Both are rejected per root, so a ray
+        // that first meets the mirrored cone still finds the real one
+        float h1 = co_axis + t1 * d_axis;
+        float h2 = co_axis + t2 * d_axis;
+        if (t1 >= t_min && t1 <= t_max && h1 >= 0.0 && h1 <= height) {
+            t = t1;
+            on_side = true;
+        }
+        if (t2 >= t_min && t2 < t && h2 >= 0.0 && h2 <= height) {
+            t = t2;
+            on_side = true;
+        }
+    }
+
+    // The base cap, a disk at the full height
+    if (abs(d_axis) > 1e-8) {
+        float t_cap = (height - co_axis) / d_axis;
+        vec3 from_center = ray.origin + t_cap * ray.direction - (cone.apex + cone.axis);
+        if (t_cap >= t_min && t_cap < t && dot(from_center, from_center) <= cone.radius * cone.radius) {
+            t = t_cap;
+            on_side = false;
+        }
+    }
+    if (t > t_max) return false;
+
+    rec.t = t;
+    rec.point = ray.origin + t * ray.direction;
+    vec3 outward_normal = axis;
+    if (on_side) {
+        // Gradient of the cone's implicit function, pointing out of the solid
+        vec3 cp = rec.point - cone.apex;
+        outward_normal = normalize(k * cp - dot(cp, axis) * axis);
+    }
+    rec.front_face = dot(ray.direction, outward_normal) < 0.0;
+    rec.normal = rec.front_face ? outward_normal : -outward_normal;
+    rec.material = cone.material;
+    rec.object_origin = cone.apex;
+    return true;
+}
+
+bool hitDisk(Disk disk, Ray ray, float t_min, float t_max, out HitRecord rec) {
+    vec3 normal = normalize(disk.normal);
+    float denom = dot(normal, ray.direction);
+    if (abs(denom) <= 0.0001) return false;
+    float t = dot(disk.center - ray.origin, normal) / denom;
+    if (t < t_min || t > t_max) return false;
+
+    vec3 point = ray.origin + t * ray.direction;
+    vec3 from_center = point - disk.center;
+    float distance_squared = dot(from_center, from_center);
+    // An inner radius of 0 passes every point, leaving a solid disk
+    if (distance_squared > disk.radius * disk.radius ||
+        distance_squared < disk.inner_radius * disk.inner_radius) return false;
+
+    rec.t = t;
+    rec.point = point;
+    // Both sides shade alike: the normal always faces the incoming ray,
+    // and front_face records which side was hit
+    rec.front_face = denom < 0.0;
+    rec.normal = rec.front_face ? normal : -normal;
+    rec.material = disk.material;
+    rec.object_origin = disk.center;
+    return true;
+}
+
+// Where the ray is inside a CSG operand, from entry to exit. Behind the
+// origin too, so a ray starting inside still knows it is
+bool csgSpan(CsgShape shape, Ray world_ray, out vec2 span) {
+    span = vec2(0.0);
+    Ray ray = toObjectSpace(world_ray, shape.center, shape.rotation, vec3(1.0));
+    if (shape.kind == 0) {
+        vec3 oc = (ray.origin - shape.center) / shape.size;
+        vec3 direction = ray.direction / shape.size;
+        float a = dot(direction, direction);
+        float b = dot(oc, direction);
+        float c = dot(oc, oc) - 1.0;
+        float discriminant = b * b - a * c;
+        if (discriminant <= 0.0) return false;
+        float root = sqrt(discriminant);
+        span = vec2(-b - root, -b + root) / a;
+        return true;
+    }
+    vec3 m = 1.0 / ray.direction;
+    vec3 n = m * (ray.origin - shape.center);
+    vec3 k = abs(m) * shape.size * 0.5;
+    vec3 t1 = -n - k;
+    vec3 t2 = -n + k;
+    float t_near = max(max(t1.x, t1.y), t1.z);
+    float t_far = min(min(t2.x, t2.y), t2.z);
+    if (t_near > t_far) return false;
+    span = vec2(t_near, t_far);
+    return true;
+}
+
+// Outward normal of a CSG operand at a point on its surface
+vec3 csgNormal(CsgShape shape, vec3 point) {
+    vec4 inverse = vec4(-shape.rotation.xyz, shape.rotation.w);
+    vec3 p = rotateByQuat(inverse, point - shape.center);
+    vec3 normal;
+    if (shape.kind == 0) {
+        normal = normalize(p / (shape.size * shape.size));
+    } else {
+        vec3 d = p / (shape.size * 0.5);
+        vec3 abs_d = abs(d);
+        float max_component = max(max(abs_d.x, abs_d.y), abs_d.z);
+        if (abs_d.x == max_component) {
+            normal = vec3(sign(d.x), 0.0, 0.0);
+        } else if (abs_d.y == max_component) {
+            normal = vec3(0.0, sign(d.y), 0.0);
+        } else {
+            normal = vec3(0.0, 0.0, sign(d.z));
+        }
+    }
+    return rotateByQuat(shape.rotation, normal);
+}
+
+// The combination's surface is made of the operands' boundaries: each
+// operand enters and leaves once, and a boundary counts where the operation
+// keeps it (union: outside the other operand, intersection: inside it, a
+// minus b: a outside b and b inside a). on_b tells which operand was hit.
+bool hitCsg(Csg csg, Ray ray, float t_min, float t_max, out HitRecord rec, out bool on_b) {
+    vec2 span_a;
+    vec2 span_b;
+    bool hit_a = csgSpan(csg.a, ray, span_a);
+    bool hit_b = csgSpan(csg.b, ray, span_b);
+    float closest = t_max;
+    bool found = false;
+    on_b = false;
+    for (int i = 0; i < 4; i++) {
+        bool from_b = i >= 2;
+        if (!(from_b ? hit_b : hit_a)) continue;
+        vec2 span = from_b ? span_b : span_a;
+        vec2 other = from_b ? span_a : span_b;
+        bool other_hit = from_b ? hit_a : hit_b;
+        float t = (i == 0 || i == 2) ? span.x : span.y;
+        if (t <= t_min || t >= closest) continue;
+
+        bool inside_other = other_hit && t > other.x && t < other.y;
+        bool keep;
+        if (csg.op == 0) {
+            keep = !inside_other;
+        } else if (csg.op == 1) {
+            keep = from_b ? inside_other : !inside_other;
+        } else {
+            keep = inside_other;
+        }
+        if (keep) {
+            closest = t;
+            found = true;
+            on_b = from_b;
+        }
+    }
+    if (!found) return false;
+
+    CsgShape shape = csg.a;
+    if (on_b) shape = csg.b;
+    rec.t = closest;
+    rec.point = ray.origin + closest * ray.direction;
+    vec3 outward_normal = csgNormal(shape, rec.point);
+    // Subtracting b turns its surface inside out
+    if (csg.op == 1 && on_b) outward_normal = -outward_normal;
+    rec.front_face = dot(ray.direction, outward_normal) < 0.0;
+    rec.normal = rec.front_face ? outward_normal : -outward_normal;
+    rec.material = shape.material;
+    rec.object_origin = shape.center;
+    return true;
+}
+
+// Reorders v so the ray direction's dominant axis becomes z (see hitTriangle)
+vec3 permuteAxes(vec3 v, int kz, bool swap_xy) {
+    vec3 p = kz == 0 ? v.yzx : (kz == 1 ? v.zxy : v);
+    return swap_xy ? p.yxz : p;
+}
+
 bool hitTriangle(Triangle triangle, Ray ray, float t_min, float t_max, out HitRecord rec) {
-    // Möller-Trumbore intersection algorithm (double-sided)
-    vec3 edge1 = triangle.v1 - triangle.v0;
-    vec3 edge2 = triangle.v2 - triangle.v0;
-    vec3 h = cross(ray.direction, edge2);
-    float a = dot(edge1, h);
-    
-    if (abs(a) < 0.00001) return false; // Ray is parallel to triangle
-    
-    float f = 1.0 / a;
-    vec3 s = ray.origin - triangle.v0;
-    float u = f * dot(s, h);
-    
-    if (u < 0.0 || u > 1.0) return false;
-    
-    vec3 q = cross(s, edge1);
-    float v = f * dot(ray.direction, q);
-    
-    if (v < 0.0 || u + v > 1.0) return false;
-    
-    float t = f * dot(edge2, q);
-    
+    // Watertight intersection (Woop, Benthin and Wald 2013): shear the
+    // triangle into a space where the ray runs along +z from the origin and
+    // test the 2D edge functions. Points on a shared edge give the same edge
+    // value for both triangles, so there are no gaps between neighbors.
+    vec3 abs_dir = abs(ray.direction);
+    int kz = abs_dir.x > abs_dir.y ? (abs_dir.x > abs_dir.z ? 0 : 2) : (abs_dir.y > abs_dir.z ? 1 : 2);
+    bool swap_xy = permuteAxes(ray.direction, kz, false).z < 0.0;
+
+    vec3 d = permuteAxes(ray.direction, kz, swap_xy);
+    float sx = d.x / d.z;
+    float sy = d.y / d.z;
+    float sz = 1.0 / d.z;
+
+    vec3 a = permuteAxes(triangle.v0 - ray.origin, kz, swap_xy);
+    vec3 b = permuteAxes(triangle.v1 - ray.origin, kz, swap_xy);
+    vec3 c = permuteAxes(triangle.v2 - ray.origin, kz, swap_xy);
+
+    float ax = a.x - sx * a.z;
+    float ay = a.y - sy * a.z;
+    float bx = b.x - sx * b.z;
+    float by = b.y - sy * b.z;
+    float cx = c.x - sx * c.z;
+    float cy = c.y - sy * c.z;
+
+    float u = cx * by - cy * bx;
+    float v = ax * cy - ay * cx;
+    float w = bx * ay - by * ax;
+
+    // Double-sided: all edge functions must share a sign (zero counts as both)
+    if ((u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0)) return false;
+
+    float det = u + v + w;
+    if (det == 0.0) return false;
+
+    float t = (u * a.z * sz + v * b.z * sz + w * c.z * sz) / det;
     if (t < t_min || t > t_max) return false;
     
     rec.t = t;
     rec.point = ray.origin + t * ray.direction;
     
     // Calculate normal (ensure consistent orientation)
+    vec3 edge1 = triangle.v1 - triangle.v0;
+    vec3 edge2 = triangle.v2 - triangle.v0;
     vec3 normal = normalize(cross(edge1, edge2));
     rec.front_face = dot(ray.direction, normal) < 0.0;
     rec.normal = rec.front_face ? normal : -normal;
     
-    rec.material.albedo = triangle.albedo;
-    rec.material.material_type = triangle.material_type;
-    rec.material.roughness = triangle.roughness;
-    rec.material.ior = triangle.ior;
+    rec.material = triangle.material;
+    rec.object_origin = triangle.v0;
     
     return true;
 }
 
+// Narrows [t0, t1] to where the ray is inside [lo, hi] along one axis
+void clipSlab(float origin, float direction, float lo, float hi, inout float t0, inout float t1) {
+    if (abs(direction) < 1e-8) {
+        if (origin < lo || origin > hi) {
+            t1 = -1.0;
+        }
+        return;
+    }
+    float ta = (lo - origin) / direction;
+    float tb = (hi - origin) / direction;
+    t0 = max(t0, min(ta, tb));
+    t1 = min(t1, max(ta, tb));
+}
+
+// Half-size of the grid's bounds along an axis with the given cell size
+float gridHalfExtent(float cell) {
+    if (cell <= 0.0) {
+        return u_grid.radius;
+    }
+    if (u_grid.extent < 0.0) {
+        return 1e9;
+    }
+    return (u_grid.extent + 0.5) * cell;
+}
+
+// First cell boundary crossed along an axis, and the t between boundaries
+void gridAxisSetup(float p, float direction, float cell, float index, float step_dir,
+                   float t_start, out float t_next, out float t_delta) {
+    if (cell <= 0.0 || abs(direction) < 1e-8) {
+        t_next = 1e30;
+        t_delta = 1e30;
+        return;
+    }
+    float boundary = (index + 0.5 * step_dir) * cell;
+    t_next = t_start + (boundary - p) / direction;
+    t_delta = cell / abs(direction);
+}
+
+// Walks the cells along the ray (3D DDA) and tests each cell's sphere. The
+// radius is clamped to half a cell on the CPU, so every sphere lies inside its
+// own cell and the first hit found in traversal order is the nearest one.
+bool hitInstancedGrid(Ray ray, float t_min, float t_max, out HitRecord rec) {
+    if (u_grid_enabled == 0) {
+        return false;
+    }
+
+    vec3 cell = u_grid.cell_size;
+    float t0 = t_min;
+    float t1 = t_max;
+    clipSlab(ray.origin.x, ray.direction.x, -gridHalfExtent(cell.x), gridHalfExtent(cell.x), t0, t1);
+    clipSlab(ray.origin.y, ray.direction.y, -gridHalfExtent(cell.y), gridHalfExtent(cell.y), t0, t1);
+    clipSlab(ray.origin.z, ray.direction.z, -gridHalfExtent(cell.z), gridHalfExtent(cell.z), t0, t1);
+    if (t0 > t1) {
+        return false;
+    }
+
+    vec3 p = ray.origin + ray.direction * t0;
+    vec3 index = vec3(
+        cell.x > 0.0 ? floor(p.x / cell.x + 0.5) : 0.0,
+        cell.y > 0.0 ? floor(p.y / cell.y + 0.5) : 0.0,
+        cell.z > 0.0 ? floor(p.z / cell.z + 0.5) : 0.0
+    );
+    vec3 step_dir = sign(ray.direction);
+    vec3 t_next;
+    vec3 t_delta;
+    gridAxisSetup(p.x, ray.direction.x, cell.x, index.x, step_dir.x, t0, t_next.x, t_delta.x);
+    gridAxisSetup(p.y, ray.direction.y, cell.y, index.y, step_dir.y, t0, t_next.y, t_delta.y);
+    gridAxisSetup(p.z, ray.direction.z, cell.z, index.z, step_dir.z, t0, t_next.z, t_delta.z);
+
+    Sphere sphere;
+    sphere.radius = u_grid.radius;
+    sphere.scale = vec3(1.0);
+    sphere.material = u_grid.material;
+
+    for (int i = 0; i < 64; i++) {
+        sphere.center = index * max(cell, vec3(0.0));
+        if (hitSphere(sphere, ray, t_min, t_max, rec)) {
+            return true;
+        }
+
+        float t_cell_end;
+        if (t_next.x < t_next.y && t_next.x < t_next.z) {
+            t_cell_end = t_next.x;
+            index.x += step_dir.x;
+            t_next.x += t_delta.x;
+        } else if (t_next.y < t_next.z) {
+            t_cell_end = t_next.y;
+            index.y += step_dir.y;
+            t_next.y += t_delta.y;
+        } else {
+            t_cell_end = t_next.z;
+            index.z += step_dir.z;
+            t_next.z += t_delta.z;
+        }
+        if (t_cell_end > t1) {
+            break;
+        }
+    }
+    return false;
+}
+
+#ifdef DATA_TEXTURE
+// Row of object i of each kind in u_scene_data
+int sphereRow(int i) {
+    return i;
+}
+
+int planeRow(int i) {
+    return u_sphere_count + i;
+}
+
+int boxRow(int i) {
+    return planeRow(u_plane_count + i);
+}
+
+int cylinderRow(int i) {
+    return boxRow(u_box_count + i);
+}
+
+int triangleRow(int i) {
+    return cylinderRow(u_cylinder_count + i);
+}
+
+int coneRow(int i) {
+    return triangleRow(u_triangle_count + i);
+}
+
+int diskRow(int i) {
+    return coneRow(u_cone_count + i);
+}
+
+int bvhNodeRow(int i) {
+    return diskRow(u_disk_count + i);
+}
+
+vec4 dataTexel(int row, float texel) {
+    // Exact, as DATA_OBJECTS_PER_ROW is a power of two
+    float y = floor(float(row) / DATA_OBJECTS_PER_ROW);
+    float x = (float(row) - y * DATA_OBJECTS_PER_ROW) * DATA_TEXELS + texel;
+    return texture2D(u_scene_data, vec2((x + 0.5) / (DATA_TEXELS * DATA_OBJECTS_PER_ROW), (y + 0.5) / u_scene_data_rows));
+}
+
+// The data* object functions only read geometry; the material and the rest
+// are read once, for the closest hit, by dataHit
+Sphere dataSphere(int row) {
+    Sphere sphere;
+    vec4 t0 = dataTexel(row, 0.0);
+    sphere.center = t0.xyz;
+    sphere.radius = t0.w;
+    sphere.scale = dataTexel(row, 1.0).xyz;
+    return sphere;
+}
+
+Plane dataPlane(int row) {
+    Plane plane;
+    plane.point = dataTexel(row, 0.0).xyz;
+    plane.normal = dataTexel(row, 1.0).xyz;
+    return plane;
+}
+
+Box dataBox(int row) {
+    Box box_obj;
+    box_obj.center = dataTexel(row, 0.0).xyz;
+    box_obj.size = dataTexel(row, 1.0).xyz;
+    box_obj.rotation = dataTexel(row, 2.0);
+    box_obj.scale = dataTexel(row, 3.0).xyz;
+    return box_obj;
+}
+
+Cylinder dataCylinder(int row) {
+    Cylinder cylinder;
+    vec4 t0 = dataTexel(row, 0.0);
+    cylinder.base = t0.xyz;
+    cylinder.radius = t0.w;
+    cylinder.axis = dataTexel(row, 1.0).xyz;
+    cylinder.rotation = dataTexel(row, 2.0);
+    cylinder.scale = dataTexel(row, 3.0).xyz;
+    return cylinder;
+}
+
+Triangle dataTriangle(int row) {
+    Triangle triangle;
+    triangle.v0 = dataTexel(row, 0.0).xyz;
+    triangle.v1 = dataTexel(row, 1.0).xyz;
+    triangle.v2 = dataTexel(row, 2.0).xyz;
+    return triangle;
+}
+
+Cone dataCone(int row) {
+    Cone cone;
+    vec4 t0 = dataTexel(row, 0.0);
+    cone.apex = t0.xyz;
+    cone.radius = t0.w;
+    cone.axis = dataTexel(row, 1.0).xyz;
+    return cone;
+}
+
+Disk dataDisk(int row) {
+    Disk disk;
+    vec4 t0 = dataTexel(row, 0.0);
+    vec4 t1 = dataTexel(row, 1.0);
+    disk.center = t0.xyz;
+    disk.radius = t0.w;
+    disk.normal = t1.xyz;
+    disk.inner_radius = t1.w;
+    return disk;
+}
+
+int dataMaterialType(int row) {
+    return int(dataTexel(row, 4.0).w);
+}
+
+Material dataMaterial(int row) {
+    vec4 t4 = dataTexel(row, 4.0);
+    vec4 t5 = dataTexel(row, 5.0);
+    vec4 t6 = dataTexel(row, 6.0);
+    vec4 t7 = dataTexel(row, 7.0);
+    vec4 t8 = dataTexel(row, 8.0);
+    Material material;
+    material.albedo = t4.rgb;
+    material.material_type = int(t4.w);
+    material.emission = t5.rgb;
+    material.emission_strength = t5.w;
+    material.roughness = t6.x;
+    material.ior = t6.y;
+    material.opacity = t6.z;
+    material.shadow_catcher = t6.w > 0.5;
+    material.checker_a = t7.rgb;
+    material.checker_scale = t7.w;
+    material.checker_b = t8.rgb;
+    material.texture_space = int(t8.w);
+    material.dispersion = dataTexel(row, 9.0).z;
+    return material;
+}
+
+// Fills in the rest of a hit on the object at `row`: its material, scene
+// index, light mask and image texture
+void dataHit(int row, inout HitRecord rec) {
+    vec4 ids = dataTexel(row, 9.0);
+    rec.material = dataMaterial(row);
+    rec.object_id.y = ids.x;
+    rec.light_mask = ids.y;
+    rec.image_texture = rec.object_id.x == 1.0 ? dataTexel(row, 1.0).w : -1.0;
+}
+
+// Whether the ray enters the box between t_min and t_max; inv_dir is one
+// over its direction
+bool hitAabb(vec3 box_min, vec3 box_max, Ray ray, vec3 inv_dir, float t_min, float t_max) {
+    vec3 t1 = (box_min - ray.origin) * inv_dir;
+    vec3 t2 = (box_max - ray.origin) * inv_dir;
+    vec3 t_small = min(t1, t2);
+    vec3 t_big = max(t1, t2);
+    float t_near = max(max(t_small.x, t_small.y), max(t_small.z, t_min));
+    float t_far = min(min(t_big.x, t_big.y), min(t_big.z, t_max));
+    return t_near <= t_far;
+}
+
+// Closest triangle hit before closest_so_far, found by walking the BVH from
+// its root. Returns the triangle's row, or -1 with rec untouched on a miss
+int hitTrianglesBvh(Ray ray, float t_min, float closest_so_far, inout HitRecord rec) {
+    // Axis-parallel rays would divide by zero
+    vec3 direction = mix(ray.direction, vec3(1e-8), step(abs(ray.direction), vec3(1e-8)));
+    vec3 inv_dir = 1.0 / direction;
+    HitRecord temp_rec;
+    int hit_row = -1;
+    // Second children still to visit
+    int stack[BVH_STACK_SIZE];
+    int stack_size = 0;
+    int node = 0;
+    for (int visit = 0; visit < MAX_BVH_STEPS; visit++) {
+        int row = bvhNodeRow(node);
+        vec4 t0 = dataTexel(row, 0.0);
+        vec4 t1 = dataTexel(row, 1.0);
+        bool descend = false;
+        if (hitAabb(t0.xyz, t1.xyz, ray, inv_dir, t_min, closest_so_far)) {
+            int count = int(t1.w + 0.5);
+            if (count == 0) {
+                descend = true;
+            } else {
+                int first = int(t0.w + 0.5);
+                for (int i = 0; i < BVH_LEAF_TRIANGLES; i++) {
+                    if (i >= count) break;
+                    int triangle_row = triangleRow(first + i);
+                    if (hitTriangle(dataTriangle(triangle_row), ray, t_min, closest_so_far, temp_rec)) {
+                        closest_so_far = temp_rec.t;
+                        rec = temp_rec;
+                        hit_row = triangle_row;
+                    }
+                }
+            }
+        }
+        if (descend) {
+            // The first child follows its parent
+            if (stack_size < BVH_STACK_SIZE) {
+                stack[stack_size] = int(t0.w + 0.5);
+                stack_size++;
+            }
+            node++;
+        } else if (stack_size > 0) {
+            stack_size--;
+            node = stack[stack_size];
+        } else {
+            break;
+        }
+    }
+    return hit_row;
+}
+#endif
+
 bool hitWorld(Ray ray, float t_min, float t_max, out HitRecord rec) {
     HitRecord temp_rec;
     bool hit_anything = false;
     float closest_so_far = t_max;
+#ifdef DATA_TEXTURE
+    // Row of the closest object hit, -1 for none
+    int hit_row = -1;
+#endif
     
     // Check spheres
-    for (int i = 0; i < 10; i++) {
+    for (int i = 0; i < MAX_SPHERES; i++) {
         if (i >= u_sphere_count) break;
-        if (hitSphere(u_spheres[i], ray, t_min, closest_so_far, temp_rec)) {
+#ifdef DATA_TEXTURE
+        Sphere sphere = dataSphere(sphereRow(i));
+#else
+        Sphere sphere = u_spheres[i];
+#endif
+        if (hitSphere(sphere, ray, t_min, closest_so_far, temp_rec)) {
             hit_anything = true;
             closest_so_far = temp_rec.t;
             rec = temp_rec;
+#ifdef DATA_TEXTURE
+            rec.object_id.x = 1.0;
+            hit_row = sphereRow(i);
+#else
+            rec.object_id = vec2(1.0, u_sphere_slots[i].x);
+            rec.light_mask = u_sphere_slots[i].y;
+            rec.image_texture = sphere.image_texture;
+#endif
         }
     }
     
     // Check planes
-    for (int i = 0; i < 5; i++) {
+    for (int i = 0; i < MAX_PLANES; i++) {
         if (i >= u_plane_count) break;
-        if (hitPlane(u_planes[i], ray, t_min, closest_so_far, temp_rec)) {
+#ifdef DATA_TEXTURE
+        Plane plane = dataPlane(planeRow(i));
+#else
+        Plane plane = u_planes[i];
+#endif
+        if (hitPlane(plane, ray, t_min, closest_so_far, temp_rec)) {
             hit_anything = true;
             closest_so_far = temp_rec.t;
             rec = temp_rec;
+#ifdef DATA_TEXTURE
+            rec.object_id.x = 2.0;
+            hit_row = planeRow(i);
+#else
+            rec.object_id = vec2(2.0, u_plane_slots[i].x);
+            rec.light_mask = u_plane_slots[i].y;
+            rec.image_texture = -1.0;
+#endif
         }
     }
     
     // Check boxes
-    for (int i = 0; i < 5; i++) {
+    for (int i = 0; i < MAX_BOXES; i++) {
         if (i >= u_box_count) break;
-        if (hitBox(u_boxes[i], ray, t_min, closest_so_far, temp_rec)) {
+#ifdef DATA_TEXTURE
+        Box box_obj = dataBox(boxRow(i));
+#else
+        Box box_obj = u_boxes[i];
+#endif
+        if (hitBox(box_obj, ray, t_min, closest_so_far, temp_rec)) {
             hit_anything = true;
             closest_so_far = temp_rec.t;
             rec = temp_rec;
+#ifdef DATA_TEXTURE
+            rec.object_id.x = 3.0;
+            hit_row = boxRow(i);
+#else
+            rec.object_id = vec2(3.0, u_box_slots[i].x);
+            rec.light_mask = u_box_slots[i].y;
+            rec.image_texture = -1.0;
+#endif
         }
     }
     
     // Check cylinders
-    for (int i = 0; i < 5; i++) {
+    for (int i = 0; i < MAX_CYLINDERS; i++) {
         if (i >= u_cylinder_count) break;
-        if (hitCylinder(u_cylinders[i], ray, t_min, closest_so_far, temp_rec)) {
+#ifdef DATA_TEXTURE
+        Cylinder cylinder = dataCylinder(cylinderRow(i));
+#else
+        Cylinder cylinder = u_cylinders[i];
+#endif
+        if (hitCylinder(cylinder, ray, t_min, closest_so_far, temp_rec)) {
             hit_anything = true;
             closest_so_far = temp_rec.t;
             rec = temp_rec;
+#ifdef DATA_TEXTURE
+            rec.object_id.x = 4.0;
+            hit_row = cylinderRow(i);
+#else
+            rec.object_id = vec2(4.0, u_cylinder_slots[i].x);
+            rec.light_mask = u_cylinder_slots[i].y;
+            rec.image_texture = -1.0;
+#endif
         }
     }
     
     // Check triangles
-    for (int i = 0; i < 10; i++) {
+#ifdef DATA_TEXTURE
+    if (u_bvh_node_count > 0) {
+        int triangle_row = hitTrianglesBvh(ray, t_min, closest_so_far, rec);
+        if (triangle_row >= 0) {
+            hit_anything = true;
+            closest_so_far = rec.t;
+            rec.object_id.x = 5.0;
+            hit_row = triangle_row;
+        }
+    }
+#else
+    for (int i = 0; i < MAX_TRIANGLES; i++) {
         if (i >= u_triangle_count) break;
         if (hitTriangle(u_triangles[i], ray, t_min, closest_so_far, temp_rec)) {
             hit_anything = true;
             closest_so_far = temp_rec.t;
             rec = temp_rec;
+            rec.object_id = vec2(5.0, u_triangle_slots[i].x);
+            rec.light_mask = u_triangle_slots[i].y;
+            rec.image_texture = -1.0;
         }
     }
+#endif
+
+    // Check cones
+    for (int i = 0; i < MAX_CONES; i++) {
+        if (i >= u_cone_count) break;
+#ifdef DATA_TEXTURE
+        Cone cone = dataCone(coneRow(i));
+#else
+        Cone cone = u_cones[i];
+#endif
+        if (hitCone(cone, ray, t_min, closest_so_far, temp_rec)) {
+            hit_anything = true;
+            closest_so_far = temp_rec.t;
+            rec = temp_rec;
+            // Object type 6, see ObjectType::to_u32
+#ifdef DATA_TEXTURE
+            rec.object_id.x = 7.0;
+            hit_row = coneRow(i);
+#else
+            rec.object_id = vec2(7.0, u_cone_slots[i].x);
+            rec.light_mask = u_cone_slots[i].y;
+            rec.image_texture = -1.0;
+#endif
+        }
+    }
+
+    // Check disks
+    for (int i = 0; i < MAX_DISKS; i++) {
+        if (i >= u_disk_count) break;
+#ifdef DATA_TEXTURE
+        Disk disk = dataDisk(diskRow(i));
+#else
+        Disk disk = u_disks[i];
+#endif
+        if (hitDisk(disk, ray, t_min, closest_so_far, temp_rec)) {
+            hit_anything = true;
+            closest_so_far = temp_rec.t;
+            rec = temp_rec;
+            // Object type 7, see ObjectType::to_u32
+#ifdef DATA_TEXTURE
+            rec.object_id.x = 8.0;
+            hit_row = diskRow(i);
+#else
+            rec.object_id = vec2(8.0, u_disk_slots[i].x);
+            rec.light_mask = u_disk_slots[i].y;
+            rec.image_texture = -1.0;
+#endif
+        }
+    }
+
+    // Check CSG combinations
+    for (int i = 0; i < MAX_CSG; i++) {
+        if (i >= u_csg_count) break;
+        bool on_b;
+        if (hitCsg(u_csgs[i], ray, t_min, closest_so_far, temp_rec, on_b)) {
+            hit_anything = true;
+            closest_so_far = temp_rec.t;
+            rec = temp_rec;
+            // Segmentation and light linking follow the operand hit
+            vec4 slot = u_csg_slots[i];
+            int kind = on_b ? u_csgs[i].b.kind : u_csgs[i].a.kind;
+            rec.object_id = vec2(float(kind) + 1.0, on_b ? slot.z : slot.x);
+            rec.light_mask = on_b ? slot.w : slot.y;
+            rec.image_texture = -1.0;
+#ifdef DATA_TEXTURE
+            hit_row = -1;
+#endif
+        }
+    }
+
+#ifdef USE_GRID
+    // Check the instanced sphere grid
+    if (hitInstancedGrid(ray, t_min, closest_so_far, temp_rec)) {
+        hit_anything = true;
+        closest_so_far = temp_rec.t;
+        rec = temp_rec;
+        // The whole grid is one segment, GRID_SEGMENT_ID
+        rec.object_id = vec2(255.0, 0.0);
+        rec.light_mask = 15.0;
+        rec.image_texture = -1.0;
+#ifdef DATA_TEXTURE
+        hit_row = -1;
+#endif
+    }
+#endif
+
+#ifdef DATA_TEXTURE
+    if (hit_row >= 0) {
+        dataHit(hit_row, rec);
+    }
+#endif
     
     return hit_anything;
 }
 
-vec3 rayColor(Ray ray, vec2 seed) {
+// Whether light slot `light` is set in a HitRecord.light_mask. GLSL ES 1.0
+// has no bitwise operators, so the bit is found by division.
+bool lightLinked(float mask, int light) {
+    float bit = 1.0;
+    for (int i = 0; i < 4; i++) {
+        if (i >= light) break;
+        bit *= 2.0;
+    }
+    return mod(floor(mask / bit), 2.0) >= 1.0;
+}
+
+// Sky cells per unit of direction; a star spans roughly a pixel at 90 degrees
+const float STAR_CELLS = 150.0;
+
+vec3 starfield(vec3 direction) {
+    vec3 p = direction * STAR_CELLS;
+    vec3 cell = floor(p);
+    vec2 key = cell.xy + cell.z * vec2(37.1, 17.3) + u_starfield.offset;
+    if (random(key) >= u_starfield.density) {
+        return vec3(0.0);
+    }
+
+    // One star per cell, kept off the cell edges so it isn't clipped
+    vec3 star = cell + 0.2 + 0.6 * vec3(random(key + 1.0), random(key + 2.0), random(key + 3.0));
+    float dist = length(p - normalize(star) * STAR_CELLS);
+    float glow = max(1.0 - dist / 0.35, 0.0);
+    glow *= glow;
+
+    float phase = random(key + 4.0) * 6.2831853;
+    float flicker = 1.0 - u_starfield.twinkle * (0.5 + 0.5 * sin(u_time * 3.0 + phase));
+    vec3 tint = mix(vec3(0.8, 0.85, 1.0), vec3(1.0, 0.9, 0.75), random(key + 5.0));
+    return tint * glow * flicker * u_starfield.brightness;
+}
+
+// Color seen by rays that leave the scene, primary and secondary alike
+vec3 skyColor(vec3 direction) {
+    vec3 unit_direction = normalize(direction);
+    if (u_starfield_enabled == 1) {
+        return u_background_color + starfield(unit_direction);
+    }
+
+    // Improved sky with sun
+    float t = 0.5 * (unit_direction.y + 1.0);
+
+    // Sky gradient
+    vec3 sky_color = mix(vec3(1.0, 1.0, 1.0), u_background_color, t);
+
+    // Add sun
+    vec3 sun_dir = normalize(vec3(0.7, 0.7, 0.0));
+    float sun_dot = max(dot(unit_direction, sun_dir), 0.0);
+    if (sun_dot > 0.995) {
+        sky_color += vec3(2.0, 1.8, 1.0) * pow(sun_dot, 100.0);
+    }
+    return sky_color;
+}
+
+// Point at which a texture is evaluated for a hit: world space, or relative
+// to the object so the pattern moves with it
+vec3 texturePoint(HitRecord rec) {
+    return rec.material.texture_space == 1 ? rec.point - rec.object_origin : rec.point;
+}
+
+// Width in world units of the patch of surface one pixel covers at a hit.
+// The image spans 2 * u_tan_half_fov over its height at unit distance, so a
+// pixel subtends about that over height radians; the patch grows with
+// distance and stretches at grazing angles. Bounced rays are treated as if
+// they came straight from the camera.
+float textureFootprint(Ray ray, HitRecord rec) {
+    float pixel_angle = 2.0 * u_tan_half_fov / u_resolution.y;
+    float cos_theta = max(abs(dot(ray.direction, rec.normal)), 0.05);
+    return pixel_angle * rec.t / cos_theta;
+}
+
+// Checker of unit cells: 0 on cells whose coordinates sum to an even number,
+// 1 on the others. With filtering on it is box-filtered analytically over a
+// footprint of `width` cells, so cells smaller than a pixel fade to 0.5
+// instead of aliasing into moire while nearby cells stay sharp.
+float checker(vec3 p, float width) {
+    if (u_texture_filtering == 1) {
+        vec3 w = vec3(max(width, 1e-4));
+        // Per axis, the square wave's average over [p - w/2, p + w/2]
+        vec3 i = 2.0 * (abs(fract((p - 0.5 * w) * 0.5) - 0.5)
+                      - abs(fract((p + 0.5 * w) * 0.5) - 0.5)) / w;
+        return 0.5 - 0.5 * i.x * i.y * i.z;
+    }
+    return mod(floor(p.x) + floor(p.y) + floor(p.z), 2.0);
+}
+
+// Linear color of sphere texture `slot` at `uv`. Sampler arrays can only be
+// indexed by a loop counter in GLSL ES 1.0.
+vec3 sampleSphereTexture(float slot, vec2 uv) {
+    for (int i = 0; i < 4; i++) {
+        if (float(i) == slot) {
+            // Images are sRGB-encoded
+            return pow(texture2D(u_sphere_textures[i], uv).rgb, vec3(2.2));
+        }
+    }
+    return vec3(1.0);
+}
+
+// Replaces the albedo of a textured hit: spheres with an image sample it
+// with a latitude/longitude mapping, checker materials take the pattern's
+// color. A plane would sit on a checker cell boundary along its normal
+// wherever it passes through a whole number of cells, so that axis is
+// pinned to mid-cell.
+void applyTexture(Ray ray, inout HitRecord rec) {
+    if (rec.image_texture >= 0.0) {
+        vec3 d = normalize(rec.point - rec.object_origin);
+        vec2 uv = vec2(0.5 + atan(d.z, d.x) / (2.0 * PI), 0.5 - asin(clamp(d.y, -1.0, 1.0)) / PI);
+        rec.material.albedo = sampleSphereTexture(rec.image_texture, uv);
+        return;
+    }
+    if (rec.material.checker_scale <= 0.0) return;
+    vec3 p = texturePoint(rec) / rec.material.checker_scale;
+    if (rec.object_id.x == 2.0) {
+        p += rec.normal * (0.5 - dot(p, rec.normal));
+    }
+    float width = textureFootprint(ray, rec) / rec.material.checker_scale;
+    rec.material.albedo = mix(rec.material.checker_a, rec.material.checker_b, checker(p, width));
+}
+
+// Fraction of light surviving along a shadow ray. Partially opaque surfaces
+// let (1 - opacity) through; anything else blocks the light completely.
+float shadowTransmittance(Ray ray, float t_max) {
+    float transmittance = 1.0;
+    for (int i = 0; i < 4; i++) {
+        HitRecord rec;
+        if (!hitWorld(ray, ray_epsilon, t_max, rec)) {
+            return transmittance;
+        }
+        if (rec.material.shadow_catcher) {
+            // Catchers receive shadows but never cast them
+        } else if (rec.material.material_type == 2 || rec.material.opacity >= 1.0) {
+            return 0.0;
+        } else {
+            transmittance *= 1.0 - rec.material.opacity;
+        }
+        ray.origin = rec.point;
+        t_max -= rec.t;
+    }
+    return 0.0; // Too many layers: treat as blocked
+}
+
+// Fraction of the direct light at a shadow-catcher point that is blocked,
+// weighted by each light's unshadowed contribution
+float catcherOcclusion(HitRecord rec) {
+    float total = 0.0;
+    float visible = 0.0;
+    for (int i = 0; i < 4; i++) {
+        if (i >= u_light_count) break;
+        if (!lightLinked(rec.light_mask, i)) continue;
+        vec3 to_light = u_lights[i].position - rec.point;
+        float light_distance = length(to_light);
+        vec3 light_dir = to_light / light_distance;
+        float cos_theta = max(dot(rec.normal, light_dir), 0.0);
+        float attenuation = 1.0 / (1.0 + 0.1 * light_distance + 0.01 * light_distance * light_distance);
+        float weight = dot(u_lights[i].color, vec3(0.2126, 0.7152, 0.0722)) *
+                       u_lights[i].intensity * cos_theta * attenuation;
+        if (weight <= 0.0) continue;
+
+        Ray shadow_ray;
+        shadow_ray.origin = rec.point + rec.normal * ray_epsilon;
+        shadow_ray.direction = light_dir;
+        total += weight;
+        visible += weight * shadowTransmittance(shadow_ray, light_distance - ray_epsilon);
+    }
+    return total > 0.0 ? 1.0 - visible / total : 0.0;
+}
+
+// Fraction of short hemisphere rays from a surface that escape; dims the
+// ambient term in creases and contact areas
+float ambientOcclusion(HitRecord rec, vec2 seed) {
+    float open = 0.0;
+    for (int i = 0; i < 4; i++) {
+        if (i >= u_ao_samples) break;
+        Ray ao_ray;
+        ao_ray.origin = rec.point + rec.normal * ray_epsilon;
+        ao_ray.direction = normalize(rec.normal + randomInUnitSphere(seed + float(i) * 7.0 + 300.0));
+        HitRecord ao_rec;
+        if (!hitWorld(ao_ray, ray_epsilon, AO_DISTANCE * u_world_scale, ao_rec)) {
+            open += 1.0;
+        }
+    }
+    return open / float(u_ao_samples);
+}
+
+// Distances at which a ray enters and leaves a volume's sphere
+bool volumeInterval(Volume volume, Ray ray, out float t0, out float t1) {
+    t0 = 0.0;
+    t1 = 0.0;
+    vec3 oc = ray.origin - volume.center;
+    float b = dot(oc, ray.direction);
+    float c = dot(oc, oc) - volume.radius * volume.radius;
+    float h = b * b - c;
+    if (h <= 0.0) return false;
+    h = sqrt(h);
+    t0 = -b - h;
+    t1 = -b + h;
+    return true;
+}
+
+// Single scattering through the fog volumes along a ray up to t_max. Returns
+// the light scattered toward the ray origin and sets `transmittance` to the
+// fraction of whatever lies behind that still shows through. Only the
+// brightest light scatters; overlapping volumes are composited one after
+// the other rather than marched together.
+vec3 marchVolumes(Ray ray, float t_max, vec2 seed, out float transmittance) {
+    transmittance = 1.0;
+    vec3 scattered = vec3(0.0);
+    if (u_volume_count == 0) return scattered;
+
+    // Copied out of the array, which may only be indexed by loop counters
+    vec3 light_position = vec3(0.0);
+    vec3 light_radiance = vec3(0.0);
+    float brightest = 0.0;
+    for (int i = 0; i < 4; i++) {
+        if (i >= u_light_count) break;
+        float power = dot(u_lights[i].color, vec3(0.2126, 0.7152, 0.0722)) * u_lights[i].intensity;
+        if (power > brightest) {
+            brightest = power;
+            light_position = u_lights[i].position;
+            light_radiance = u_lights[i].color * u_lights[i].intensity;
+        }
+    }
+
+    for (int v = 0; v < 3; v++) {
+        if (v >= u_volume_count) break;
+        Volume volume = u_volumes[v];
+        if (volume.density <= 0.0) continue;
+        float t0;
+        float t1;
+        if (!volumeInterval(volume, ray, t0, t1)) continue;
+        t0 = max(t0, 0.0);
+        t1 = min(t1, t_max);
+        if (t1 <= t0) continue;
+
+        float step_length = (t1 - t0) / float(u_volume_steps);
+        float step_transmittance = exp(-volume.density * step_length);
+        // A random start within the first step turns banding into noise
+        // that frame accumulation averages away
+        float t = t0 + step_length * random(seed + float(v) * 13.0 + 500.0);
+        for (int s = 0; s < MAX_VOLUME_STEPS; s++) {
+            if (s >= u_volume_steps) break;
+            vec3 p = ray.origin + ray.direction * t;
+            vec3 in_light = vec3(u_ambient);
+            if (brightest > 0.0) {
+                vec3 to_light = light_position - p;
+                float light_distance = length(to_light);
+                Ray light_ray;
+                light_ray.origin = p;
+                light_ray.direction = to_light / light_distance;
+
+                // Fog between the sample and the light dims it too
+                float l0;
+                float l1;
+                volumeInterval(volume, light_ray, l0, l1);
+                float visibility = exp(-volume.density * clamp(l1, 0.0, light_distance));
+                if (u_shadows == 1) {
+                    visibility *= shadowTransmittance(light_ray, light_distance);
+                }
+                float attenuation = 1.0 / (1.0 + 0.1 * light_distance + 0.01 * light_distance * light_distance);
+                in_light += light_radiance * attenuation * visibility;
+            }
+            scattered += transmittance * (1.0 - step_transmittance) * volume.color * in_light;
+            transmittance *= step_transmittance;
+            t += step_length;
+        }
+    }
+    return scattered;
+}
+
+// `coverage` is 1 if the path hit a surface before escaping, 0 if the
+// primary ray went straight to the sky. Shadow catchers seen by the primary
+// ray give partial coverage: the strength of the shadow on them, and fog
+// in front of the sky covers as much as it hides.
+vec3 rayColor(Ray ray, vec2 seed, out float coverage) {
     vec3 color = vec3(1.0);
     vec3 accumulated_color = vec3(0.0);
+    coverage = 0.0;
+    float catcher_alpha = 0.0;
+    // Fog volumes are marched along the camera ray only (including where it
+    // continues straight through see-through surfaces). Rays that bounced
+    // skip them, so reflections and refractions don't show volumes; marching
+    // every bounce would multiply the cost by the path length.
+    bool camera_ray = true;
+    // Which channel the path carries after passing through dispersive glass
+    // (-1 until then). Each path follows one channel, picked at random and
+    // weighted by 3, so the channels separate and composite over samples.
+    int dispersion_channel = -1;
+    // Set after a diffuse bounce, whose direct lighting already sampled the
+    // emissive spheres; hitting one then would count its light twice
+    bool after_diffuse = false;
     
-    for (int depth = 0; depth < 10; depth++) { // Increased depth for better quality
+    for (int depth = 0; depth < MAX_BOUNCES; depth++) {
+        if (depth >= u_max_bounces) break;
         HitRecord rec;
-        if (hitWorld(ray, 0.001, 100.0, rec)) {
+        bool hit = hitWorld(ray, ray_epsilon, max_distance, rec);
+#ifdef USE_FOG
+        if (camera_ray) {
+            float fog_transmittance;
+            accumulated_color += color * marchVolumes(ray, hit ? rec.t : max_distance, seed + float(depth), fog_transmittance);
+            color *= fog_transmittance;
+            if (coverage == 0.0) {
+                catcher_alpha = 1.0 - (1.0 - catcher_alpha) * fog_transmittance;
+            }
+        }
+#endif
+        if (hit) {
+            // Partially opaque surfaces: with probability (1 - opacity) the ray
+            // passes straight through, which averages to an opacity blend
+            if (rec.material.material_type != 2 && rec.material.opacity < 1.0 &&
+                random(seed + float(depth + 200)) > rec.material.opacity) {
+                ray.origin = rec.point - rec.normal * ray_epsilon;
+                continue;
+            }
+            // Shadow catchers: pass through, darkened by the shadow on them
+            if (rec.material.shadow_catcher) {
+                float shadow = catcherOcclusion(rec) * u_shadow_catcher_opacity;
+                color *= 1.0 - shadow;
+                if (coverage == 0.0) {
+                    catcher_alpha = 1.0 - (1.0 - catcher_alpha) * (1.0 - shadow);
+                }
+                ray.origin = rec.point - rec.normal * ray_epsilon;
+                continue;
+            }
+            coverage = 1.0;
+            applyTexture(ray, rec);
+            // The selected object, seen directly: tinted toward the highlight
+            // color, with a rim glowing toward its silhouette
+            if (camera_ray && u_selected_object.x > 0.0 && rec.object_id == u_selected_object) {
+                float rim = 1.0 - abs(dot(rec.normal, ray.direction));
+                rec.material.albedo = mix(rec.material.albedo, SELECTION_COLOR, 0.35);
+                accumulated_color += color * SELECTION_COLOR * rim * rim;
+            }
+            camera_ray = false;
+
+            accumulated_color += color * rec.material.emission * rec.material.emission_strength;
             
             if (rec.material.material_type == 0) { // Lambertian - Proper diffuse
                 vec3 target = rec.point + rec.normal + randomInUnitSphere(seed + float(depth));
@@ -389,24 +1706,77 @@ vec3 rayColor(Ray ray, vec2 seed) {
                 vec3 light_contribution = vec3(0.0);
                 for (int i = 0; i < 4; i++) {
                     if (i >= u_light_count) break;
-                    vec3 light_dir = normalize(u_lights[i].position - rec.point);
-                    float light_distance = length(u_lights[i].position - rec.point);
+                    if (!lightLinked(rec.light_mask, i)) continue;
+                    // Soft shadows aim each shadow ray at a random point of
+                    // a small sphere around the light
+                    vec3 light_position = u_lights[i].position;
+                    if (u_soft_shadow_radius > 0.0) {
+                        light_position += randomInUnitSphere(seed + float(depth * 4 + i) + 400.0) * u_soft_shadow_radius;
+                    }
+                    vec3 light_dir = normalize(light_position - rec.point);
+                    float light_distance = length(light_position - rec.point);
                     
                     // Shadow ray
                     Ray shadow_ray;
-                    shadow_ray.origin = rec.point + rec.normal * 0.001;
+                    shadow_ray.origin = rec.point + rec.normal * ray_epsilon;
                     shadow_ray.direction = light_dir;
-                    HitRecord shadow_rec;
                     
-                    if (!hitWorld(shadow_ray, 0.001, light_distance - 0.001, shadow_rec)) {
+                    float visibility = 1.0;
+                    if (u_shadows == 1) {
+                        visibility = shadowTransmittance(shadow_ray, light_distance - ray_epsilon);
+                    }
+                    if (visibility > 0.0) {
                         float cos_theta = max(dot(rec.normal, light_dir), 0.0);
                         float attenuation = 1.0 / (1.0 + 0.1 * light_distance + 0.01 * light_distance * light_distance);
-                        light_contribution += u_lights[i].color * u_lights[i].intensity * cos_theta * attenuation;
+                        light_contribution += u_lights[i].color * u_lights[i].intensity * cos_theta * attenuation * visibility;
+                    }
+                }
+
+                // Emissive spheres act as crude area lights, each sampled at
+                // a random point inside it. Ellipsoids count as big as the
+                // sphere around them
+                for (int i = 0; i < MAX_SPHERES; i++) {
+                    if (i >= u_sphere_count) break;
+#ifdef DATA_TEXTURE
+                    if (dataMaterialType(sphereRow(i)) != 3) continue;
+                    Sphere emitter = dataSphere(sphereRow(i));
+                    emitter.material = dataMaterial(sphereRow(i));
+#else
+                    Sphere emitter = u_spheres[i];
+                    if (emitter.material.material_type != 3) continue;
+#endif
+                    vec3 emitter_point = emitter.center + randomInUnitSphere(seed + float(depth * 10 + i) + 600.0) * (emitter.radius * emitter.scale);
+                    float emitter_radius = emitter.radius * max(max(emitter.scale.x, emitter.scale.y), emitter.scale.z);
+                    float center_distance = length(emitter.center - rec.point);
+                    if (center_distance <= emitter_radius) continue;
+                    vec3 emitter_dir = normalize(emitter_point - rec.point);
+                    float cos_theta = dot(rec.normal, emitter_dir);
+                    if (cos_theta <= 0.0) continue;
+
+                    // The emitter's surface is at least this far along any
+                    // ray toward a point inside it
+                    Ray shadow_ray;
+                    shadow_ray.origin = rec.point + rec.normal * ray_epsilon;
+                    shadow_ray.direction = emitter_dir;
+                    float visibility = 1.0;
+                    if (u_shadows == 1) {
+                        visibility = shadowTransmittance(shadow_ray, center_distance - emitter_radius - ray_epsilon);
                     }
+                    // Radiance times the solid angle the sphere covers, over pi
+                    float solid_angle = emitter_radius * emitter_radius / (center_distance * center_distance);
+                    light_contribution += emitter.material.albedo * emitter.material.emission_strength * cos_theta * solid_angle * visibility;
                 }
                 
+                float ambient = u_ambient;
+#ifdef USE_AO
+                if (u_ao_samples > 0) {
+                    ambient *= ambientOcclusion(rec, seed + float(depth));
+                }
+#endif
+
                 // Combine direct lighting with indirect
-                color *= rec.material.albedo * (0.1 + light_contribution); // 0.1 is ambient
+                color *= rec.material.albedo * (ambient + light_contribution);
+                after_diffuse = true;
                 
             } else if (rec.material.material_type == 1) { // Metal - Proper reflection
                 vec3 reflected = reflectRay(normalize(ray.direction), rec.normal);
@@ -421,34 +1791,49 @@ vec3 rayColor(Ray ray, vec2 seed) {
                 float cos_theta = abs(dot(normalize(ray.direction), rec.normal));
                 float fresnel = 0.04 + (1.0 - 0.04) * pow(1.0 - cos_theta, 5.0);
                 color *= rec.material.albedo * fresnel;
+                after_diffuse = false;
                 
             } else if (rec.material.material_type == 2) { // Glass - Proper refraction
                 vec3 unit_direction = normalize(ray.direction);
                 float cos_theta = min(dot(-unit_direction, rec.normal), 1.0);
                 float sin_theta = sqrt(1.0 - cos_theta * cos_theta);
                 
-                float ni_over_nt = rec.front_face ? (1.0 / rec.material.ior) : rec.material.ior;
+                float ior = rec.material.ior;
+                if (rec.material.dispersion > 0.0) {
+                    if (dispersion_channel < 0) {
+                        float pick = random(seed + float(depth + 300)) * 3.0;
+                        dispersion_channel = pick < 1.0 ? 0 : (pick < 2.0 ? 1 : 2);
+                        color *= 3.0 * vec3(dispersion_channel == 0 ? 1.0 : 0.0,
+                                            dispersion_channel == 1 ? 1.0 : 0.0,
+                                            dispersion_channel == 2 ? 1.0 : 0.0);
+                    }
+                    // Red bends least and blue most, green keeps the base IOR
+                    float spread = (ior - 1.0) * rec.material.dispersion;
+                    ior += spread * 0.5 * float(dispersion_channel - 1);
+                }
+                
+                float ni_over_nt = rec.front_face ? (1.0 / ior) : ior;
                 bool cannot_refract = ni_over_nt * sin_theta > 1.0;
                 
                 // Schlick approximation for fresnel
-                float r0 = (1.0 - rec.material.ior) / (1.0 + rec.material.ior);
+                float r0 = (1.0 - ior) / (1.0 + ior);
                 r0 = r0 * r0;
                 float fresnel = r0 + (1.0 - r0) * pow(1.0 - cos_theta, 5.0);
                 
                 if (cannot_refract || fresnel > random(seed + float(depth))) {
                     // Reflect
                     vec3 reflected = reflectRay(unit_direction, rec.normal);
-                    ray.origin = rec.point + rec.normal * 0.001;
+                    ray.origin = rec.point + rec.normal * ray_epsilon;
                     ray.direction = reflected;
                 } else {
                     // Refract
                     vec3 refracted;
-                    if (refract(unit_direction, rec.normal, ni_over_nt, refracted)) {
-                        ray.origin = rec.point - rec.normal * 0.001;
+                    if (refractRay(unit_direction, rec.normal, ni_over_nt, refracted)) {
+                        ray.origin = rec.point - rec.normal * ray_epsilon;
                         ray.direction = refracted;
                     } else {
                         vec3 reflected = reflectRay(unit_direction, rec.normal);
-                        ray.origin = rec.point + rec.normal * 0.001;
+                        ray.origin = rec.point + rec.normal * ray_epsilon;
                         ray.direction = reflected;
                     }
                 }
@@ -460,24 +1845,24 @@ vec3 rayColor(Ray ray, vec2 seed) {
                 } else {
                     color *= vec3(0.98); // Slight reflection loss
                 }
+                after_diffuse = false;
+            } else if (rec.material.material_type == 3) { // Emissive - Light source
+                if (!after_diffuse) {
+                    accumulated_color += color * rec.material.albedo * rec.material.emission_strength;
+                }
+                break;
             }
             
         } else {
-            // Improved sky with sun
-            vec3 unit_direction = normalize(ray.direction);
-            float t = 0.5 * (unit_direction.y + 1.0);
-            
-            // Sky gradient
-            vec3 sky_color = mix(vec3(1.0, 1.0, 1.0), vec3(0.5, 0.7, 1.0), t);
-            
-            // Add sun
-            vec3 sun_dir = normalize(vec3(0.7, 0.7, 0.0));
-            float sun_dot = max(dot(unit_direction, sun_dir), 0.0);
-            if (sun_dot > 0.995) {
-                sky_color += vec3(2.0, 1.8, 1.0) * pow(sun_dot, 100.0);
+            // Let the page show through where nothing was hit; secondary rays
+            // still pick up the sky so reflections stay lit
+            if (coverage == 0.0 && u_transparent_background == 1) {
+                // Only catcher shadows and fog remain
+                coverage = catcher_alpha;
+                break;
             }
-            
-            accumulated_color += color * sky_color;
+
+            accumulated_color += color * skyColor(ray.direction);
             break;
         }
         
@@ -494,9 +1879,48 @@ vec3 rayColor(Ray ray, vec2 seed) {
     return accumulated_color;
 }
 
+vec3 packUnitFloat(float v) {
+    vec3 enc = fract(v * vec3(1.0, 255.0, 65025.0));
+    enc -= enc.yzz * vec3(1.0 / 255.0, 1.0 / 255.0, 0.0);
+    return enc;
+}
+
+vec4 gbufferOutput(Ray ray) {
+    HitRecord rec;
+    if (!hitWorld(ray, ray_epsilon, max_distance, rec)) {
+        // Segmentation masks are opaque, with black for the background
+        return u_output_mode == 4 ? vec4(0.0, 0.0, 0.0, 1.0) : vec4(0.0);
+    }
+
+    if (u_output_mode == 4) {
+        vec2 id = rec.object_id;
+        return vec4(vec3(id.x, floor(id.y / 256.0), mod(id.y, 256.0)) / 255.0, 1.0);
+    }
+
+    if (u_output_mode == 1) {
+        float depth = rec.t * dot(ray.direction, u_camera_forward);
+        return vec4(packUnitFloat(clamp(depth / max_distance, 0.0, 0.999999)), 1.0);
+    }
+
+    vec3 outward_normal = rec.front_face ? rec.normal : -rec.normal;
+    return vec4(outward_normal * 0.5 + 0.5, 1.0);
+}
+
+#ifdef NAN_GUARD
+// NaN fails every comparison and infinity exceeds any float, so both land
+// outside the range
+bool isFiniteColor(vec3 c) {
+    return all(lessThan(abs(c), vec3(1e30)));
+}
+#endif
+
 void main() {
-    vec2 uv = (gl_FragCoord.xy / u_resolution.xy) * 2.0 - 1.0;
+    ray_epsilon = RAY_EPSILON * u_world_scale;
+    max_distance = GBUFFER_FAR * u_world_scale;
+
+    vec2 uv = ((gl_FragCoord.xy - u_viewport_origin) / u_resolution.xy) * 2.0 - 1.0;
     uv.x *= u_resolution.x / u_resolution.y;
+    uv *= u_tan_half_fov;
     
     // Create ray direction using camera basis vectors
     vec3 ray_dir = normalize(u_camera_forward + uv.x * u_camera_right + uv.y * u_camera_up);
@@ -505,11 +1929,27 @@ void main() {
     ray.origin = u_camera_pos;
     ray.direction = ray_dir;
 
+    // G-buffer and segmentation passes use the unjittered primary ray only
+    if (u_output_mode == 1 || u_output_mode == 2 || u_output_mode == 4) {
+        gl_FragColor = gbufferOutput(ray);
+        return;
+    }
+
     // Reduced sampling for better performance
     vec3 color = vec3(0.0);
+    float alpha = 0.0;
+#ifdef NAN_GUARD
+    bool nan_found = false;
+#endif
 
-    for (int i = 0; i < 2; i++) {
-        vec2 offset = vec2(float(i) * 0.5, fract(float(i) * 0.618)) / u_resolution;
+    float samples = float(u_samples_per_pixel);
+    vec2 pixel_rotation = vec2(random(gl_FragCoord.xy), random(gl_FragCoord.xy + 71.0));
+    for (int i = 0; i < MAX_SAMPLES; i++) {
+        if (i >= u_samples_per_pixel) break;
+        // Rotate the frame's Halton sample per ray and by a per-pixel hash, so
+        // neighbouring pixels don't share one pattern, and map it to +-0.5 pixels
+        vec2 jitter = fract(u_sample_offset + pixel_rotation + vec2(float(i) / samples, float(i) * 0.618)) - 0.5;
+        vec2 offset = jitter * 2.0 * u_tan_half_fov / u_resolution.y;
         vec2 sample_uv = uv + offset;
         
         // Create ray direction using camera basis vectors
@@ -519,11 +1959,56 @@ void main() {
         sample_ray.origin = u_camera_pos;
         sample_ray.direction = sample_ray_dir;
         
-        vec2 seed = gl_FragCoord.xy + u_time + float(i);
-        color += rayColor(sample_ray, seed);
+        // Each frame of an unchanged view gets fresh noise, even while the
+        // clock is paused, so frames can be accumulated
+        float frame_seed = fract(u_frames_since_change * 0.618034) * 97.0;
+        vec2 seed = gl_FragCoord.xy + u_time + float(i) + frame_seed;
+
+        if (u_aperture > 0.0) {
+            // Thin lens: start from a random point on the aperture disk and
+            // aim where the pinhole ray meets the focal plane
+            vec3 focus_point = u_camera_pos + sample_ray_dir * (u_focus_distance / dot(sample_ray_dir, u_camera_forward));
+            float lens_radius = u_aperture * sqrt(random(seed + 900.0));
+            float lens_angle = 2.0 * PI * random(seed + 901.0);
+            sample_ray.origin += lens_radius * (cos(lens_angle) * u_camera_right + sin(lens_angle) * u_camera_up);
+            sample_ray.direction = normalize(focus_point - sample_ray.origin);
+        }
+
+        float coverage;
+        vec3 sample_color = rayColor(sample_ray, seed, coverage);
+#ifdef NAN_GUARD
+        if (!isFiniteColor(sample_color) || coverage != coverage) {
+            nan_found = true;
+        }
+#endif
+        color += sample_color;
+        alpha += coverage;
+    }
+
+#ifdef NAN_GUARD
+    if (nan_found && u_output_mode == 0) {
+        gl_FragColor = vec4(1.0, 0.0, 1.0, 1.0);
+        return;
+    }
+#endif
+
+    // Average over the samples that hit something; misses added no color
+    alpha /= samples;
+    if (u_transparent_background == 1) {
+        color /= max(alpha * samples, 1.0);
+    } else {
+        color /= samples;
+        alpha = 1.0;
+    }
+
+    if (u_output_mode == 3) {
+        float log_luminance = log2(max(dot(color, vec3(0.2126, 0.7152, 0.0722)), 1e-5));
+        float unit = (log_luminance - LOG_LUMINANCE_MIN) / (LOG_LUMINANCE_MAX - LOG_LUMINANCE_MIN);
+        gl_FragColor = vec4(packUnitFloat(clamp(unit, 0.0, 0.999999)), 1.0);
+        return;
     }
 
-    color /= 2.0;
+    color *= u_exposure;
     
     // Better tone mapping (ACES approximation)
     color = (color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14);
@@ -531,5 +2016,6 @@ void main() {
     // Gamma correction
     color = pow(color, vec3(1.0/2.2));
     
-    gl_FragColor = vec4(color, 1.0);
+    // The canvas expects premultiplied alpha
+    gl_FragColor = vec4(color * alpha, alpha);
 }
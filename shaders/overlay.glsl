@@ -0,0 +1,22 @@
+precision mediump float;
+
+// Draws the raytraced frame together with a reference image for comparison
+uniform sampler2D u_source;
+uniform sampler2D u_reference;
+uniform int u_mode;     // 1 = wipe, 2 = difference
+uniform float u_split;  // Wipe position across the image, 0 to 1
+uniform float u_gain;   // Amplification of the difference
+
+varying vec2 v_texCoord;
+
+void main() {
+    vec4 render = texture2D(u_source, v_texCoord);
+    // Reference rows are stored top row first
+    vec3 reference = texture2D(u_reference, vec2(v_texCoord.x, 1.0 - v_texCoord.y)).rgb;
+
+    if (u_mode == 1) {
+        gl_FragColor = v_texCoord.x < u_split ? vec4(reference, 1.0) : render;
+    } else {
+        gl_FragColor = vec4(min(abs(render.rgb - reference) * u_gain, vec3(1.0)), 1.0);
+    }
+}
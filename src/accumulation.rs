@@ -0,0 +1,241 @@
+//! Progressive accumulation: each frame is traced into its own buffer and
+//! folded into a running average held in a ping-pong pair of textures, so a
+//! still view converges instead of flickering with sampling noise.
+//!
+//! Where only some objects moved, the average restarts inside the regions
+//! the motion tracker reports and carries on everywhere else: each region
+//! counts its own frames, and the accumulate shader weights the new frame by
+//! the count of the youngest region a pixel lies in.
+//!
+//! The buffers are float where the context can render into float textures.
+//! Elsewhere they fall back to bytes, whose rounding stops the average from
+//! improving after a few dozen frames.
+
+use web_sys::{WebGlBuffer, WebGlProgram, WebGlRenderingContext};
+
+use crate::error::RaytracerError;
+use crate::motion::{MAX_REJECT_REGIONS, Region};
+use crate::webgl::{self, RenderTarget};
+
+pub struct Accumulation {
+    // Where the newest frame is traced
+    frame: RenderTarget,
+    // history[current] holds the average; the other one receives the next
+    history: [RenderTarget; 2],
+    current: usize,
+    samples: u32,
+    // Regions restarted since the average began, with the frames averaged
+    // inside each since
+    regions: Vec<(Region, u32)>,
+}
+
+impl Accumulation {
+    /// Buffers for a `width` x `height` render, holding no frames yet.
+    pub fn new(
+        gl: &WebGlRenderingContext,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, RaytracerError> {
+        let frame = create_target(gl, width, height)?;
+        let history = match create_history(gl, width, height) {
+            Ok(history) => history,
+            Err(e) => {
+                frame.delete(gl);
+                return Err(e);
+            }
+        };
+        Ok(Self {
+            frame,
+            history,
+            current: 0,
+            samples: 0,
+            regions: Vec::new(),
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.frame.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.frame.height
+    }
+
+    /// Frames averaged so far.
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// Drops the average; the next frame starts a new one.
+    pub fn reset(&mut self) {
+        self.samples = 0;
+        self.regions.clear();
+    }
+
+    /// Drops the average inside `regions` (image pixels, origin top-left)
+    /// only; the next frame starts a new one there. Past
+    /// `MAX_REJECT_REGIONS` live regions they are merged into one, which
+    /// restarts the pixels between them too.
+    pub fn reject(&mut self, regions: &[Region]) {
+        if self.samples == 0 {
+            return;
+        }
+        self.regions
+            .extend(regions.iter().map(|&region| (region, 0)));
+        if self.regions.len() > MAX_REJECT_REGIONS {
+            let merged = self
+                .regions
+                .iter()
+                .fold(self.regions[0], |(a, a_count), (b, b_count)| {
+                    (
+                        [
+                            a[0].min(b[0]),
+                            a[1].min(b[1]),
+                            a[2].max(b[2]),
+                            a[3].max(b[3]),
+                        ],
+                        a_count.min(*b_count),
+                    )
+                });
+            self.regions = vec![merged];
+        }
+    }
+
+    /// The buffer the next frame is traced into.
+    pub fn frame(&self) -> &RenderTarget {
+        &self.frame
+    }
+
+    /// The averaged image.
+    pub fn average(&self) -> &RenderTarget {
+        &self.history[self.current]
+    }
+
+    /// Folds the traced frame into the average with `program` (the
+    /// accumulate shader) drawn over `quad_buffer`. Leaves the other buffer
+    /// bound to the framebuffer, so callers rebind what they draw into.
+    pub fn add_frame(
+        &mut self,
+        gl: &WebGlRenderingContext,
+        program: &WebGlProgram,
+        quad_buffer: &WebGlBuffer,
+    ) {
+        let next = 1 - self.current;
+        gl.bind_framebuffer(
+            WebGlRenderingContext::FRAMEBUFFER,
+            Some(&self.history[next].framebuffer),
+        );
+        gl.viewport(0, 0, self.frame.width as i32, self.frame.height as i32);
+        gl.use_program(Some(program));
+
+        gl.active_texture(WebGlRenderingContext::TEXTURE1);
+        gl.bind_texture(
+            WebGlRenderingContext::TEXTURE_2D,
+            Some(&self.history[self.current].texture),
+        );
+        let history_location = gl.get_uniform_location(program, "u_history");
+        gl.uniform1i(history_location.as_ref(), 1);
+
+        gl.active_texture(WebGlRenderingContext::TEXTURE0);
+        gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&self.frame.texture));
+        let frame_location = gl.get_uniform_location(program, "u_frame");
+        gl.uniform1i(frame_location.as_ref(), 0);
+
+        // The first frame replaces whatever the history held
+        let weight_location = gl.get_uniform_location(program, "u_weight");
+        gl.uniform1f(weight_location.as_ref(), 1.0 / (self.samples + 1) as f32);
+
+        // GL window coordinates start at the bottom-left corner
+        let height = self.frame.height as f32;
+        let mut regions = [0.0; 4 * MAX_REJECT_REGIONS];
+        let mut weights = [0.0; MAX_REJECT_REGIONS];
+        for (i, ([min_x, min_y, max_x, max_y], count)) in self.regions.iter().enumerate() {
+            regions[4 * i..4 * i + 4].copy_from_slice(&[
+                *min_x,
+                height - max_y,
+                *max_x,
+                height - min_y,
+            ]);
+            weights[i] = 1.0 / (count + 1) as f32;
+        }
+        let count_location = gl.get_uniform_location(program, "u_region_count");
+        gl.uniform1i(count_location.as_ref(), self.regions.len() as i32);
+        let regions_location = gl.get_uniform_location(program, "u_regions");
+        gl.uniform4fv_with_f32_array(regions_location.as_ref(), &regions);
+        let weights_location = gl.get_uniform_location(program, "u_region_weights");
+        gl.uniform1fv_with_f32_array(weights_location.as_ref(), &weights);
+
+        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(quad_buffer));
+        let position_location = gl.get_attrib_location(program, "a_position");
+        gl.enable_vertex_attrib_array(position_location as u32);
+        gl.vertex_attrib_pointer_with_i32(
+            position_location as u32,
+            2,
+            WebGlRenderingContext::FLOAT,
+            false,
+            0,
+            0,
+        );
+        gl.draw_arrays(WebGlRenderingContext::TRIANGLES, 0, 6);
+
+        self.current = next;
+        self.samples = self.samples.saturating_add(1);
+        for (_, count) in &mut self.regions {
+            *count = count.saturating_add(1);
+        }
+    }
+
+    /// GPU memory held by the three buffers.
+    pub fn bytes(&self) -> usize {
+        self.frame.bytes() + self.history.iter().map(RenderTarget::bytes).sum::<usize>()
+    }
+
+    pub fn delete(&self, gl: &WebGlRenderingContext) {
+        self.frame.delete(gl);
+        for target in &self.history {
+            target.delete(gl);
+        }
+    }
+}
+
+fn create_history(
+    gl: &WebGlRenderingContext,
+    width: u32,
+    height: u32,
+) -> Result<[RenderTarget; 2], RaytracerError> {
+    let first = create_target(gl, width, height)?;
+    match create_target(gl, width, height) {
+        Ok(second) => Ok([first, second]),
+        Err(e) => {
+            first.delete(gl);
+            Err(e)
+        }
+    }
+}
+
+/// A float target if the context can render into one, else a byte target.
+/// The average is shown stretched over the content area, so it's filtered
+/// linearly where the format allows.
+fn create_target(
+    gl: &WebGlRenderingContext,
+    width: u32,
+    height: u32,
+) -> Result<RenderTarget, RaytracerError> {
+    let target = match RenderTarget::new_float(gl, width, height) {
+        Ok(target) => target,
+        Err(_) => RenderTarget::new(gl, width, height)?,
+    };
+    if !target.is_float() || webgl::supports_float_filtering(gl) {
+        for filter in [
+            WebGlRenderingContext::TEXTURE_MIN_FILTER,
+            WebGlRenderingContext::TEXTURE_MAG_FILTER,
+        ] {
+            gl.tex_parameteri(
+                WebGlRenderingContext::TEXTURE_2D,
+                filter,
+                WebGlRenderingContext::LINEAR as i32,
+            );
+        }
+    }
+    Ok(target)
+}
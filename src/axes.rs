@@ -0,0 +1,301 @@
+//! Axis convention conversion for imported data.
+//!
+//! The renderer works in a right-handed space with +Y up and -Z forward.
+//! `ImportAxes` describes how a source file's axes map onto that, and
+//! converts everything an importer produces.
+
+use std::fmt;
+
+use crate::math::{Quat, Vec3};
+use crate::scene::{Mesh, Scene, Triangle};
+
+/// Error for an axis specification that doesn't describe a valid basis.
+#[derive(Clone, Debug)]
+pub struct AxesError(String);
+
+impl fmt::Display for AxesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for AxesError {}
+
+/// A change of basis from a source convention into the renderer's, plus a
+/// uniform scale (e.g. 0.01 for centimeter data).
+#[derive(Clone, Copy, Debug)]
+pub struct ImportAxes {
+    // Rows: the source-space vectors that become +X, +Y and +Z
+    rows: [Vec3; 3],
+    scale: f32,
+}
+
+impl Default for ImportAxes {
+    fn default() -> Self {
+        Self {
+            rows: [
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+            ],
+            scale: 1.0,
+        }
+    }
+}
+
+impl ImportAxes {
+    /// Builds the conversion from the source's up and forward axes, each one
+    /// of `x`, `y`, `z` with an optional `+`/`-` sign. Right-handed sources
+    /// have right = forward x up; `left_handed` mirrors that.
+    pub fn new(up: &str, forward: &str, scale: f32, left_handed: bool) -> Result<Self, AxesError> {
+        let up = parse_axis(up)?;
+        let forward = parse_axis(forward)?;
+        if up.cross(&forward).length_squared() == 0.0 {
+            return Err(AxesError(
+                "Up and forward must be different axes".to_string(),
+            ));
+        }
+        if !(scale.is_finite() && scale > 0.0) {
+            return Err(AxesError(format!(
+                "Import scale must be positive, got {}",
+                scale
+            )));
+        }
+
+        let right = if left_handed {
+            up.cross(&forward)
+        } else {
+            forward.cross(&up)
+        };
+        Ok(Self {
+            rows: [right, up, -forward],
+            scale,
+        })
+    }
+
+    /// True if the conversion mirrors space, so triangle winding must be
+    /// reversed to keep faces pointing the same way.
+    pub fn flips_handedness(&self) -> bool {
+        let [x, y, z] = self.rows;
+        x.dot(&y.cross(&z)) < 0.0
+    }
+
+    /// Rotates (and mirrors) `v` without scaling it; for normals and other
+    /// unit directions.
+    pub fn direction(&self, v: Vec3) -> Vec3 {
+        let [x, y, z] = self.rows;
+        Vec3::new(x.dot(&v), y.dot(&v), z.dot(&v))
+    }
+
+    /// Converts a position, or a vector whose length matters.
+    pub fn point(&self, p: Vec3) -> Vec3 {
+        self.direction(p) * self.scale
+    }
+
+    /// Converts an axis-aligned extent; the basis only permutes axes, so
+    /// sizes stay positive.
+    pub fn size(&self, size: Vec3) -> Vec3 {
+        let s = self.direction(size);
+        Vec3::new(s.x.abs(), s.y.abs(), s.z.abs()) * self.scale
+    }
+
+    /// Converts per-axis scale factors, which the basis only permutes.
+    pub fn factors(&self, factors: Vec3) -> Vec3 {
+        let f = self.direction(factors);
+        Vec3::new(f.x.abs(), f.y.abs(), f.z.abs())
+    }
+
+    /// Converts XYZ Euler angles. The turn keeps its angle about the
+    /// converted axis, reversed when the basis mirrors space.
+    pub fn rotation(&self, rotation: Vec3) -> Vec3 {
+        let q = Quat::from_euler(rotation);
+        let axis = self.direction(Vec3::new(q.x, q.y, q.z));
+        let axis = if self.flips_handedness() { -axis } else { axis };
+        Quat {
+            x: axis.x,
+            y: axis.y,
+            z: axis.z,
+            w: q.w,
+        }
+        .to_euler()
+    }
+
+    /// Converts a length such as a radius.
+    pub fn length(&self, length: f32) -> f32 {
+        length * self.scale
+    }
+
+    pub fn triangle(&self, triangle: &mut Triangle) {
+        triangle.v0 = self.point(triangle.v0);
+        triangle.v1 = self.point(triangle.v1);
+        triangle.v2 = self.point(triangle.v2);
+        if self.flips_handedness() {
+            std::mem::swap(&mut triangle.v1, &mut triangle.v2);
+        }
+    }
+
+    pub fn mesh(&self, mesh: &mut Mesh) {
+        for triangle in mesh.triangles.iter_mut().chain(&mut mesh.low_detail) {
+            self.triangle(triangle);
+        }
+        mesh.center = self.point(mesh.center);
+        mesh.world_scale = mesh.world_scale.map(|world_scale| world_scale * self.scale);
+    }
+
+    /// Converts every object and light of a freshly imported scene.
+    pub fn scene(&self, scene: &mut Scene) {
+        for sphere in &mut scene.spheres {
+            sphere.center = self.point(sphere.center);
+            sphere.radius = self.length(sphere.radius);
+            sphere.scale = self.factors(sphere.scale);
+        }
+        for plane in &mut scene.planes {
+            plane.point = self.point(plane.point);
+            plane.normal = self.direction(plane.normal);
+        }
+        for box_obj in &mut scene.boxes {
+            box_obj.center = self.point(box_obj.center);
+            box_obj.size = self.size(box_obj.size);
+            box_obj.rotation = self.rotation(box_obj.rotation);
+            box_obj.scale = self.factors(box_obj.scale);
+        }
+        for cylinder in &mut scene.cylinders {
+            cylinder.base = self.point(cylinder.base);
+            cylinder.axis = self.point(cylinder.axis);
+            cylinder.radius = self.length(cylinder.radius);
+            cylinder.rotation = self.rotation(cylinder.rotation);
+            cylinder.scale = self.factors(cylinder.scale);
+        }
+        for cone in &mut scene.cones {
+            cone.apex = self.point(cone.apex);
+            cone.axis = self.point(cone.axis);
+            cone.radius = self.length(cone.radius);
+        }
+        for disk in &mut scene.disks {
+            disk.center = self.point(disk.center);
+            disk.normal = self.direction(disk.normal);
+            disk.radius = self.length(disk.radius);
+            disk.inner_radius = self.length(disk.inner_radius);
+        }
+        for triangle in &mut scene.triangles {
+            self.triangle(triangle);
+        }
+        for mesh in &mut scene.meshes {
+            for triangle in &mut mesh.low_detail {
+                self.triangle(triangle);
+            }
+        }
+        for light in &mut scene.lights {
+            light.position = self.point(light.position);
+        }
+        for volume in &mut scene.volumes {
+            volume.center = self.point(volume.center);
+            volume.radius = self.length(volume.radius);
+            // Density is per unit length
+            volume.density /= self.scale;
+        }
+        scene.world_scale *= self.scale;
+    }
+}
+
+fn parse_axis(axis: &str) -> Result<Vec3, AxesError> {
+    let trimmed = axis.trim().to_ascii_lowercase();
+    let (sign, name) = match trimmed.strip_prefix('-') {
+        Some(name) => (-1.0, name),
+        None => (1.0, trimmed.strip_prefix('+').unwrap_or(&trimmed)),
+    };
+    let unit = match name {
+        "x" => Vec3::new(1.0, 0.0, 0.0),
+        "y" => Vec3::new(0.0, 1.0, 0.0),
+        "z" => Vec3::new(0.0, 0.0, 1.0),
+        _ => {
+            return Err(AxesError(format!(
+                "Unknown axis '{}', expected x, y or z with an optional sign",
+                axis
+            )));
+        }
+    };
+    Ok(unit * sign)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        assert!(
+            (actual - expected).length() < 1e-5,
+            "expected {:?}, got {:?}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn z_up_becomes_y_up() {
+        let axes = ImportAxes::new("z", "y", 1.0, false).unwrap();
+        assert!(!axes.flips_handedness());
+        assert_close(axes.point(Vec3::new(0.0, 0.0, 1.0)), Vec3::new(0.0, 1.0, 0.0));
+        assert_close(axes.point(Vec3::new(0.0, 1.0, 0.0)), Vec3::new(0.0, 0.0, -1.0));
+        assert_close(axes.point(Vec3::new(1.0, 0.0, 0.0)), Vec3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn negative_y_forward() {
+        // Blender's convention: Z up, -Y forward
+        let axes = ImportAxes::new("+z", "-y", 1.0, false).unwrap();
+        assert!(!axes.flips_handedness());
+        assert_close(axes.point(Vec3::new(0.0, -1.0, 0.0)), Vec3::new(0.0, 0.0, -1.0));
+        assert_close(axes.point(Vec3::new(0.0, 0.0, 1.0)), Vec3::new(0.0, 1.0, 0.0));
+        assert_close(axes.point(Vec3::new(1.0, 0.0, 0.0)), Vec3::new(-1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn scale_applies_to_points_and_lengths_only() {
+        let axes = ImportAxes::new("y", "-z", 0.01, false).unwrap();
+        assert_close(axes.point(Vec3::new(100.0, 200.0, 300.0)), Vec3::new(1.0, 2.0, 3.0));
+        assert_close(axes.direction(Vec3::new(0.0, 1.0, 0.0)), Vec3::new(0.0, 1.0, 0.0));
+        assert!((axes.length(50.0) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn left_handed_flips_winding_and_keeps_the_normal() {
+        // Y up, +Z forward, left-handed, as Unity stores it
+        let axes = ImportAxes::new("y", "z", 1.0, true).unwrap();
+        assert!(axes.flips_handedness());
+
+        let mut triangle = Triangle::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Material::lambertian(Vec3::one()),
+        );
+        let normal = triangle.normal();
+        axes.triangle(&mut triangle);
+        assert_close(triangle.normal(), axes.direction(normal));
+    }
+
+    #[test]
+    fn right_handed_keeps_winding() {
+        let axes = ImportAxes::new("z", "-y", 1.0, false).unwrap();
+        let mut triangle = Triangle::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Material::lambertian(Vec3::one()),
+        );
+        let (v1, normal) = (triangle.v1, triangle.normal());
+        axes.triangle(&mut triangle);
+        assert_close(triangle.v1, axes.point(v1));
+        assert_close(triangle.normal(), axes.direction(normal));
+    }
+
+    #[test]
+    fn rejects_invalid_specifications() {
+        assert!(ImportAxes::new("y", "-y", 1.0, false).is_err());
+        assert!(ImportAxes::new("w", "z", 1.0, false).is_err());
+        assert!(ImportAxes::new("y", "z", 0.0, false).is_err());
+        assert!(ImportAxes::new("y", "z", f32::NAN, false).is_err());
+    }
+}
@@ -0,0 +1,391 @@
+//! Import of the JSON a Blender export script writes, in Blender's own
+//! coordinates (Z up); the renderer's import axes convert them afterwards.
+//!
+//! ```json
+//! {
+//!   "unit_settings": {"scale_length": 1.0},
+//!   "objects": [
+//!     {
+//!       "name": "Crate", "type": "MESH", "mesh_type": "cube",
+//!       "location": [0, 0, 1], "rotation_euler": [0, 0, 0.6], "scale": [1, 1, 1],
+//!       "material": {
+//!         "base_color": [0.8, 0.5, 0.2, 1.0], "metallic": 0.0,
+//!         "roughness": 0.5, "transmission": 0.0, "ior": 1.5
+//!       }
+//!     },
+//!     {
+//!       "name": "Lamp", "type": "LIGHT", "light_type": "POINT",
+//!       "location": [4, -2, 5], "color": [1.0, 0.9, 0.8], "energy": 1000
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Every field may be left out. `rotation_euler` is in radians,
+//! XYZ order, as Blender stores it. Meshes are Blender's default primitives
+//! placed by their object transform:
+//!
+//! - `sphere` (also when `mesh_type` is missing): radius 1, scaled by the
+//!   largest scale factor
+//! - `cube`: from -1 to 1 on each axis, a box. Its rotation is folded into
+//!   its size while it is a multiple of 90 degrees about each axis, and kept
+//!   as the box's rotation otherwise
+//! - `cylinder`: radius 1 and depth 2 along its local Z, radius scaled by
+//!   the larger of the X and Y factors
+//! - `plane`: an infinite plane through its location, facing its local +Z
+//!
+//! The material's transmission (at least 0.5) makes it a dielectric with
+//! `ior`, otherwise its metallic factor (at least 0.5) makes it metal with
+//! `roughness`, otherwise it's Lambertian; the base color's alpha becomes
+//! its opacity. Objects without one are grey. Lights take `color` and
+//! `energy` as they are. `POINT`, `SPOT` and `AREA` lights become point
+//! lights at their location. `SUN` lights, other mesh types and other
+//! object types are skipped. Samples of each object type are in
+//! `fixtures/blender`.
+
+use serde::Deserialize;
+#[cfg(all(feature = "webgl", target_arch = "wasm32"))]
+use web_sys::console;
+
+use crate::material::{Material, MaterialType};
+use crate::math::Vec3;
+use crate::scene::{Box, Cylinder, Light, Plane, Scene, SceneError, Sphere};
+
+/// Corners of the default cube as bits of their index: bit 0 set is +X,
+/// bit 1 +Y, bit 2 +Z. Each face is wound counter-clockwise seen from
+/// outside.
+pub const CUBE_FACES: [[usize; 4]; 6] = [
+    [1, 3, 7, 5],
+    [0, 4, 6, 2],
+    [2, 6, 7, 3],
+    [0, 1, 5, 4],
+    [4, 5, 7, 6],
+    [0, 2, 3, 1],
+];
+
+#[derive(Deserialize)]
+struct Export {
+    #[serde(default)]
+    unit_settings: UnitSettings,
+    #[serde(default)]
+    objects: Vec<Object>,
+}
+
+#[derive(Default, Deserialize)]
+struct UnitSettings {
+    scale_length: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct Object {
+    #[serde(default, rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    mesh_type: Option<String>,
+    light_type: Option<String>,
+    #[serde(default)]
+    location: [f32; 3],
+    #[serde(default)]
+    rotation_euler: [f32; 3],
+    #[serde(default = "unit_scale")]
+    scale: [f32; 3],
+    material: Option<BlenderMaterial>,
+    color: Option<[f32; 3]>,
+    energy: Option<f32>,
+}
+
+fn unit_scale() -> [f32; 3] {
+    [1.0; 3]
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct BlenderMaterial {
+    base_color: Vec<f32>,
+    metallic: f32,
+    roughness: f32,
+    transmission: f32,
+    ior: f32,
+}
+
+// Blender's Principled BSDF defaults
+impl Default for BlenderMaterial {
+    fn default() -> Self {
+        Self {
+            base_color: vec![0.8, 0.8, 0.8, 1.0],
+            metallic: 0.0,
+            roughness: 0.5,
+            transmission: 0.0,
+            ior: 1.5,
+        }
+    }
+}
+
+impl BlenderMaterial {
+    fn to_material(&self) -> Material {
+        let channel = |i: usize, default: f32| self.base_color.get(i).copied().unwrap_or(default);
+        let albedo = Vec3::new(channel(0, 0.8), channel(1, 0.8), channel(2, 0.8));
+        let material_type = if self.transmission >= 0.5 {
+            MaterialType::Dielectric
+        } else if self.metallic >= 0.5 {
+            MaterialType::Metal
+        } else {
+            MaterialType::Lambertian
+        };
+        let mut material = Material::new(material_type, albedo, self.roughness, self.ior);
+        material.opacity = channel(3, 1.0);
+        material.clamped()
+    }
+}
+
+pub fn import(json_data: &str) -> Result<Scene, SceneError> {
+    let export: Export = serde_json::from_str(json_data)
+        .map_err(|e| SceneError::new(format!("Failed to parse Blender JSON: {}", e)))?;
+
+    let mut scene = Scene::new();
+
+    // Blender's unit scale is meters per Blender unit
+    if let Some(scale_length) = export.unit_settings.scale_length.filter(|&s| s > 0.0) {
+        scene.world_scale = (1.0 / scale_length) as f32;
+    }
+
+    for object in &export.objects {
+        match object.kind.as_str() {
+            "MESH" => add_mesh(&mut scene, object),
+            "LIGHT" => add_light(&mut scene, object),
+            _ => {}
+        }
+    }
+    Ok(scene)
+}
+
+fn add_mesh(scene: &mut Scene, object: &Object) {
+    let location = vec3(object.location);
+    let [sx, sy, sz] = object.scale;
+    let [x_axis, y_axis, z_axis] = rotation_columns(object.rotation_euler);
+    let material = object.material.as_ref().map_or_else(
+        || Material::lambertian(Vec3::new(0.7, 0.7, 0.7)),
+        BlenderMaterial::to_material,
+    );
+    let name = object.name.clone();
+
+    match object.mesh_type.as_deref().unwrap_or("sphere") {
+        "sphere" => {
+            let radius = sx.abs().max(sy.abs()).max(sz.abs());
+            let mut sphere = Sphere::new(location, radius, material);
+            sphere.meta.name = name;
+            scene.add_sphere(sphere);
+        }
+        "cube" => {
+            // The cube is symmetric, so a negative scale changes nothing
+            let mut box_obj = if [x_axis, y_axis, z_axis].iter().all(is_axis_aligned) {
+                let axes = [x_axis * sx, y_axis * sy, z_axis * sz];
+                let extent = axes.iter().fold(Vec3::zero(), |extent, axis| {
+                    extent + Vec3::new(axis.x.abs(), axis.y.abs(), axis.z.abs())
+                });
+                Box::new(location, extent * 2.0, material)
+            } else {
+                let size = Vec3::new(sx.abs(), sy.abs(), sz.abs()) * 2.0;
+                let mut box_obj = Box::new(location, size, material);
+                box_obj.rotation = vec3(object.rotation_euler);
+                box_obj
+            };
+            box_obj.meta.name = name;
+            scene.add_box(box_obj);
+        }
+        "cylinder" => {
+            let axis = z_axis * (2.0 * sz);
+            let radius = sx.abs().max(sy.abs());
+            let mut cylinder = Cylinder::new(location - axis * 0.5, axis, radius, material);
+            cylinder.meta.name = name;
+            scene.add_cylinder(cylinder);
+        }
+        "plane" => {
+            let mut plane = Plane::new(location, z_axis, material);
+            plane.meta.name = name;
+            scene.add_plane(plane);
+        }
+        mesh_type => skipped(&object.name, &format!("mesh_type '{}'", mesh_type)),
+    }
+}
+
+fn add_light(scene: &mut Scene, object: &Object) {
+    match object.light_type.as_deref().unwrap_or("POINT") {
+        "POINT" | "SPOT" | "AREA" => {}
+        light_type => {
+            skipped(&object.name, &format!("light_type '{}'", light_type));
+            return;
+        }
+    }
+    let color = object.color.map_or(Vec3::one(), vec3);
+    let mut light = Light::new(vec3(object.location), color, object.energy.unwrap_or(10.0));
+    light.name = object.name.clone();
+    scene.add_light(light);
+}
+
+// Only a browser has a console to warn in; native builds (tests included)
+// skip quietly
+#[cfg(all(feature = "webgl", target_arch = "wasm32"))]
+fn skipped(name: &str, what: &str) {
+    console::warn_1(&format!("Blender import: skipped '{}', unsupported {}", name, what).into());
+}
+
+#[cfg(not(all(feature = "webgl", target_arch = "wasm32")))]
+fn skipped(_name: &str, _what: &str) {}
+
+fn vec3([x, y, z]: [f32; 3]) -> Vec3 {
+    Vec3::new(x, y, z)
+}
+
+/// Where an XYZ Euler rotation takes the X, Y and Z axes.
+fn rotation_columns([x, y, z]: [f32; 3]) -> [Vec3; 3] {
+    let (sx, cx) = x.sin_cos();
+    let (sy, cy) = y.sin_cos();
+    let (sz, cz) = z.sin_cos();
+    [
+        Vec3::new(cy * cz, cy * sz, -sy),
+        Vec3::new(sx * sy * cz - cx * sz, sx * sy * sz + cx * cz, sx * cy),
+        Vec3::new(cx * sy * cz + sx * sz, cx * sy * sz - sx * cz, cx * cy),
+    ]
+}
+
+/// Whether `axis`, a unit vector, lies along X, Y or Z.
+fn is_axis_aligned(axis: &Vec3) -> bool {
+    [axis.x, axis.y, axis.z]
+        .iter()
+        .any(|component| component.abs() > 1.0 - 1e-5)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        assert!(
+            (actual - expected).length() < 1e-5,
+            "expected {:?}, got {:?}",
+            expected,
+            actual
+        );
+    }
+
+    /// Every file in `fixtures/blender`, imported, by file name.
+    fn fixtures() -> Vec<(String, Scene)> {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/blender");
+        let mut fixtures: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .map(|path| {
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                let json = std::fs::read_to_string(&path).unwrap();
+                let scene = import(&json).unwrap_or_else(|e| panic!("{}: {}", name, e));
+                (name, scene)
+            })
+            .collect();
+        fixtures.sort_by(|a, b| a.0.cmp(&b.0));
+        fixtures
+    }
+
+    fn fixture(name: &str) -> Scene {
+        fixtures()
+            .into_iter()
+            .find(|(fixture, _)| fixture == name)
+            .unwrap_or_else(|| panic!("no fixture {}", name))
+            .1
+    }
+
+    #[test]
+    fn every_fixture_imports() {
+        let fixtures = fixtures();
+        assert!(!fixtures.is_empty());
+        for (name, scene) in &fixtures {
+            // (spheres, planes, boxes, cylinders, lights)
+            let counts = (
+                scene.spheres.len(),
+                scene.planes.len(),
+                scene.boxes.len(),
+                scene.cylinders.len(),
+                scene.lights.len(),
+            );
+            let expected = match name.as_str() {
+                "cubes.json" => (0, 0, 2, 0, 0),
+                "cylinders.json" => (0, 0, 0, 2, 0),
+                "lights.json" => (0, 0, 0, 0, 3),
+                "planes.json" => (0, 2, 0, 0, 0),
+                "spheres.json" => (4, 0, 0, 0, 0),
+                _ => panic!("{} has no expected object counts here", name),
+            };
+            assert_eq!(counts, expected, "{}", name);
+            assert!(scene.validate().is_empty(), "{}: {:?}", name, scene.validate());
+        }
+    }
+
+    #[test]
+    fn right_angle_cube_rotation_folds_into_the_size() {
+        let scene = fixture("cubes.json");
+        let crate_box = &scene.boxes[0];
+        assert_eq!(crate_box.meta.name, "Crate");
+        assert_close(crate_box.center, Vec3::new(0.0, 0.0, 0.5));
+        assert_close(crate_box.size, Vec3::new(1.0, 2.0, 1.0));
+        assert_close(crate_box.rotation, Vec3::zero());
+
+        let tilted = &scene.boxes[1];
+        assert_close(tilted.size, Vec3::one());
+        assert_close(tilted.rotation, Vec3::new(0.3, 0.0, std::f32::consts::FRAC_PI_4));
+        assert_eq!(tilted.material.opacity, 0.5);
+    }
+
+    #[test]
+    fn cylinders_run_along_their_local_z() {
+        let scene = fixture("cylinders.json");
+        let pillar = &scene.cylinders[0];
+        assert_close(pillar.base, Vec3::zero());
+        assert_close(pillar.axis, Vec3::new(0.0, 0.0, 3.0));
+        assert_eq!(pillar.radius, 0.25);
+
+        let pipe = &scene.cylinders[1];
+        assert_close(pipe.base, Vec3::new(1.0, 0.0, 0.2));
+        assert_close(pipe.axis, Vec3::new(2.0, 0.0, 0.0));
+        assert!(matches!(pipe.material.material_type, MaterialType::Metal));
+    }
+
+    #[test]
+    fn planes_face_their_local_z() {
+        let scene = fixture("planes.json");
+        assert_close(scene.planes[0].normal, Vec3::new(0.0, 0.0, 1.0));
+        assert_close(scene.planes[1].normal, Vec3::new(0.0, -1.0, 0.0));
+        assert_close(scene.planes[1].point, Vec3::new(0.0, 5.0, 0.0));
+    }
+
+    #[test]
+    fn sphere_materials_follow_the_principled_factors() {
+        let scene = fixture("spheres.json");
+        let types: Vec<_> = scene.spheres.iter().map(|s| s.material.material_type).collect();
+        assert!(matches!(
+            types.as_slice(),
+            [
+                MaterialType::Lambertian,
+                MaterialType::Metal,
+                MaterialType::Dielectric,
+                MaterialType::Lambertian
+            ]
+        ));
+        assert_eq!(scene.spheres[1].radius, 0.5);
+        assert_eq!(scene.spheres[2].material.ior, 1.45);
+        // No mesh_type and no material: a grey sphere
+        assert_eq!(scene.spheres[3].meta.name, "Legacy");
+        assert_close(scene.spheres[3].material.albedo, Vec3::new(0.7, 0.7, 0.7));
+    }
+
+    #[test]
+    fn sun_lights_and_cameras_are_skipped() {
+        let scene = fixture("lights.json");
+        let names: Vec<_> = scene.lights.iter().map(|light| light.name.as_str()).collect();
+        assert_eq!(names, ["Key", "Spot", "Softbox"]);
+        assert_eq!(scene.lights[0].intensity, 30.0);
+        assert_close(scene.lights[2].color, Vec3::one());
+        assert_close(scene.lights[1].position, Vec3::new(-3.0, -2.0, 4.0));
+    }
+}
@@ -0,0 +1,157 @@
+//! Bounding volume hierarchy over the uploaded triangles, so the `DATA_TEXTURE`
+//! shader tests a ray against a few leaves instead of every triangle.
+//!
+//! Nodes are split at the median centroid along their longest axis and laid
+//! out depth first: an interior node's first child directly follows it, so
+//! only the second child's index is stored. `Scene::pack_data` writes the
+//! triangles in leaf order followed by one row per node, and `hitTrianglesBvh`
+//! in the fragment shader walks them with a fixed-size stack.
+
+use serde::Serialize;
+
+use crate::math::Aabb;
+use crate::scene::Triangle;
+
+/// Most triangles in a leaf; the shader's leaf loop runs this many times.
+pub const BVH_LEAF_TRIANGLES: usize = 4;
+/// Size of the shader's traversal stack. Median splits halve every node, so
+/// the depth stays far below it for any triangle count the texture holds.
+pub const BVH_STACK_SIZE: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BvhNode {
+    pub bounds: Aabb,
+    /// Interior nodes: index of the second child. Leaves: position of their
+    /// first triangle in `Bvh::order`.
+    pub offset: usize,
+    /// Triangles in a leaf, 0 for interior nodes.
+    pub count: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bvh {
+    pub nodes: Vec<BvhNode>,
+    /// Triangle indices in leaf order.
+    pub order: Vec<usize>,
+    /// Levels below the root.
+    pub depth: usize,
+}
+
+impl Bvh {
+    /// Builds the hierarchy over triangles with the given bounds. Empty for
+    /// no triangles.
+    pub fn build(bounds: &[Aabb]) -> Self {
+        let mut items: Vec<(usize, Aabb)> = bounds.iter().copied().enumerate().collect();
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity((2 * bounds.len() / BVH_LEAF_TRIANGLES).max(1)),
+            order: Vec::with_capacity(bounds.len()),
+            depth: 0,
+        };
+        if !items.is_empty() {
+            bvh.build_node(&mut items, 0);
+        }
+        bvh
+    }
+
+    fn build_node(&mut self, items: &mut [(usize, Aabb)], depth: usize) {
+        self.depth = self.depth.max(depth);
+        let bounds = items[1..]
+            .iter()
+            .fold(items[0].1, |bounds, (_, item)| bounds.union(item));
+        let index = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bounds,
+            offset: self.order.len(),
+            count: items.len(),
+        });
+        if items.len() <= BVH_LEAF_TRIANGLES {
+            self.order
+                .extend(items.iter().map(|&(triangle, _)| triangle));
+            return;
+        }
+
+        let mut centroids = Aabb::new(items[0].1.center(), items[0].1.center());
+        for (_, item) in items.iter() {
+            centroids.include_point(item.center());
+        }
+        let extent = centroids.size();
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        let key = |item: &(usize, Aabb)| {
+            let center = item.1.center();
+            [center.x, center.y, center.z][axis]
+        };
+        let middle = items.len() / 2;
+        items.select_nth_unstable_by(middle, |a, b| key(a).total_cmp(&key(b)));
+
+        let (left, right) = items.split_at_mut(middle);
+        self.build_node(left, depth + 1);
+        self.nodes[index].offset = self.nodes.len();
+        self.nodes[index].count = 0;
+        self.build_node(right, depth + 1);
+    }
+}
+
+/// Shape and cost of the last BVH build, for `get_bvh_stats`.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct BvhStats {
+    pub triangles: usize,
+    pub nodes: usize,
+    pub depth: usize,
+    pub build_ms: f64,
+    /// Builds since the renderer started.
+    pub builds: u32,
+}
+
+/// What the triangles handed to [`BvhCache::get`] were picked from: equal
+/// keys mean equal triangles.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BvhKey {
+    /// `Scene::triangles_revision` of the scene they came from
+    pub revision: u32,
+    pub low_detail: bool,
+    /// Their indices when the camera picked them, over the upload cap
+    pub selection: Option<Vec<usize>>,
+}
+
+/// The BVH of the last uploaded triangles. It is rebuilt whenever the scene
+/// records a change to them, and kept otherwise.
+#[derive(Debug, Default)]
+pub struct BvhCache {
+    key: Option<BvhKey>,
+    bvh: Bvh,
+    stats: BvhStats,
+}
+
+impl BvhCache {
+    /// The BVH over `triangles`, built again only if `key` changed since the
+    /// last call. `now` is a millisecond clock timing the build.
+    pub fn get(&mut self, key: BvhKey, triangles: &[&Triangle], now: impl Fn() -> f64) -> &Bvh {
+        if self.key.as_ref() != Some(&key) {
+            let start = now();
+            let bounds: Vec<Aabb> = triangles
+                .iter()
+                .map(|t| Aabb::from_points(&[t.v0, t.v1, t.v2]))
+                .collect();
+            self.bvh = Bvh::build(&bounds);
+            self.key = Some(key);
+            self.stats = BvhStats {
+                triangles: triangles.len(),
+                nodes: self.bvh.nodes.len(),
+                depth: self.bvh.depth,
+                build_ms: now() - start,
+                builds: self.stats.builds + 1,
+            };
+        }
+        &self.bvh
+    }
+
+    pub fn stats(&self) -> BvhStats {
+        self.stats
+    }
+}
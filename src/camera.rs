@@ -1,4 +1,84 @@
-use crate::math::{Mat4, Vec3};
+//! Perspective camera matching the ray setup in the fragment shader.
+
+use serde::{Deserialize, Serialize};
+
+use crate::math::{Aabb, Mat4, Vec3};
+
+/// Clip planes in meters, scaled by the scene's world scale.
+pub const DEFAULT_NEAR: f32 = 0.1;
+pub const DEFAULT_FAR: f32 = 100.0;
+
+/// Vertical field of view in degrees until one is set.
+pub const DEFAULT_FOV: f32 = 90.0;
+/// Field of view limits in degrees; a full 180 would put the image plane at
+/// infinity.
+pub const MIN_FOV: f32 = 1.0;
+pub const MAX_FOV: f32 = 179.0;
+
+/// Closest `Camera::frame_bounds` puts the camera to the framed box's center,
+/// in near-plane distances, so a tiny object isn't framed from inside the
+/// near plane.
+const MIN_FRAME_DISTANCE: f32 = 10.0;
+
+/// Distance to the plane in focus until one is set; only matters once the
+/// aperture is opened.
+pub const DEFAULT_FOCUS_DISTANCE: f32 = 5.0;
+
+/// The camera values the shader sees, used to detect movement between frames.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraState {
+    pub position: Vec3,
+    pub forward: Vec3,
+    pub right: Vec3,
+    pub up: Vec3,
+    // Vertical field of view in radians
+    pub fov: f32,
+    pub aperture: f32,
+    pub focus_distance: f32,
+}
+
+impl CameraState {
+    /// True if the position moved more than `position_epsilon`, any basis
+    /// vector turned by more than `direction_epsilon` (per component) or the
+    /// field of view or lens changed at all.
+    pub fn differs_from(
+        &self,
+        other: &CameraState,
+        position_epsilon: f32,
+        direction_epsilon: f32,
+    ) -> bool {
+        let exceeds = |a: Vec3, b: Vec3, epsilon: f32| {
+            let d = a - b;
+            d.x.abs() > epsilon || d.y.abs() > epsilon || d.z.abs() > epsilon
+        };
+        exceeds(self.position, other.position, position_epsilon)
+            || exceeds(self.forward, other.forward, direction_epsilon)
+            || exceeds(self.right, other.right, direction_epsilon)
+            || exceeds(self.up, other.up, direction_epsilon)
+            || self.fov != other.fov
+            || self.aperture != other.aperture
+            || self.focus_distance != other.focus_distance
+    }
+
+    /// True if this frame's state differs from `last` (or there is no
+    /// `last`), in which case it becomes the new `last`. Movements under
+    /// the thresholds are measured from the last recorded state, so slow
+    /// drift still adds up to a change.
+    pub fn changed_since(
+        self,
+        last: &mut Option<CameraState>,
+        position_epsilon: f32,
+        direction_epsilon: f32,
+    ) -> bool {
+        let changed = last.is_none_or(|last| {
+            last.differs_from(&self, position_epsilon, direction_epsilon)
+        });
+        if changed {
+            *last = Some(self);
+        }
+        changed
+    }
+}
 
 pub struct Camera {
     position: Vec3,
@@ -12,6 +92,11 @@ pub struct Camera {
     aspect_ratio: f32,
     near: f32,
     far: f32,
+    // Lens radius; 0 is a pinhole with everything in focus
+    aperture: f32,
+    focus_distance: f32,
+    // Yaw and pitch the basis vectors were last computed from
+    basis_angles: Option<(f32, f32)>,
 }
 
 impl Camera {
@@ -24,10 +109,13 @@ impl Camera {
             forward: Vec3::new(0.0, 0.0, -1.0),
             yaw: 0.0,
             pitch: 0.0,
-            fov: 45.0_f32.to_radians(),
+            fov: DEFAULT_FOV.to_radians(),
             aspect_ratio,
-            near: 0.1,
-            far: 100.0,
+            near: DEFAULT_NEAR,
+            far: DEFAULT_FAR,
+            aperture: 0.0,
+            focus_distance: DEFAULT_FOCUS_DISTANCE,
+            basis_angles: None,
         };
 
         camera.update_vectors();
@@ -38,8 +126,22 @@ impl Camera {
         self.position
     }
 
+    pub fn state(&self) -> CameraState {
+        CameraState {
+            position: self.position,
+            forward: self.forward,
+            right: self.right,
+            up: self.up,
+            fov: self.fov,
+            aperture: self.aperture,
+            focus_distance: self.focus_distance,
+        }
+    }
+
     pub fn view_matrix(&self) -> Mat4 {
-        Mat4::look_at(self.position, self.position + self.forward, self.up)
+        // Built from the actual basis so projected points land where the
+        // shader draws them
+        Mat4::view_from_basis(self.position, self.right, self.up, self.forward)
     }
 
     pub fn projection_matrix(&self) -> Mat4 {
@@ -69,15 +171,107 @@ impl Camera {
         self.update_vectors();
     }
 
+    /// Points the camera by yaw and pitch in radians, with pitch clamped like
+    /// `rotate`'s.
+    pub fn set_orientation(&mut self, yaw: f32, pitch: f32) {
+        self.yaw = yaw;
+        self.pitch = pitch.clamp(-89.0_f32.to_radians(), 89.0_f32.to_radians());
+        self.update_vectors();
+    }
+
+    /// Yaw in radians, turning left from looking down -z.
+    pub fn yaw(&self) -> f32 {
+        self.yaw
+    }
+
+    /// Pitch in radians, positive looking up.
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+
     pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
         self.aspect_ratio = aspect_ratio;
     }
 
+    pub fn aspect_ratio(&self) -> f32 {
+        self.aspect_ratio
+    }
+
+    /// Sets the vertical field of view in degrees, clamped to `MIN_FOV` to
+    /// `MAX_FOV`. Invalid values are ignored.
     pub fn set_fov(&mut self, fov: f32) {
-        self.fov = fov.to_radians();
+        if fov.is_finite() {
+            self.fov = fov.clamp(MIN_FOV, MAX_FOV).to_radians();
+        }
+    }
+
+    /// Vertical field of view in radians.
+    pub fn fov(&self) -> f32 {
+        self.fov
+    }
+
+    /// Sets the clip planes to their defaults for a scene with
+    /// `world_scale` units per meter.
+    pub fn apply_world_scale(&mut self, world_scale: f32) {
+        self.near = DEFAULT_NEAR * world_scale;
+        self.far = DEFAULT_FAR * world_scale;
+    }
+
+    /// Sets the radius of the lens rays start from; 0 (or anything invalid)
+    /// makes a pinhole.
+    pub fn set_aperture(&mut self, aperture: f32) {
+        self.aperture = if aperture.is_finite() { aperture.max(0.0) } else { 0.0 };
+    }
+
+    pub fn aperture(&self) -> f32 {
+        self.aperture
+    }
+
+    /// Sets the distance along the view direction to the plane in focus.
+    /// Invalid distances are ignored.
+    pub fn set_focus_distance(&mut self, distance: f32) {
+        if distance.is_finite() && distance > 0.0 {
+            self.focus_distance = distance;
+        }
+    }
+
+    pub fn focus_distance(&self) -> f32 {
+        self.focus_distance
+    }
+
+    pub fn near(&self) -> f32 {
+        self.near
+    }
+
+    pub fn far(&self) -> f32 {
+        self.far
+    }
+
+    /// Moves the camera back from `bounds` along `direction` until the box's
+    /// bounding sphere fits the narrower of the two fields of view, and looks
+    /// at its center. The camera stays at least `MIN_FRAME_DISTANCE` near
+    /// planes away, however small the box.
+    pub fn frame_bounds(&mut self, bounds: &Aabb, direction: Vec3) {
+        let center = bounds.center();
+        let radius = bounds.bounding_radius().max(0.001);
+
+        let half_vertical = self.fov * 0.5;
+        let half_horizontal = (half_vertical.tan() * self.aspect_ratio).atan();
+        let half_fov = half_vertical.min(half_horizontal);
+        let distance = (radius / half_fov.sin()).max(self.near * MIN_FRAME_DISTANCE);
+
+        self.position = center + direction.normalize() * distance;
+        self.look_at(center);
     }
 
     fn update_vectors(&mut self) {
+        // Repeating the trig for unchanged angles would only add rounding noise
+        if self.basis_angles == Some((self.yaw, self.pitch)) {
+            self.target = self.position + self.forward;
+            return;
+        }
+        self.basis_angles = Some((self.yaw, self.pitch));
+
         // Start with base forward direction
         let mut forward = Vec3::new(0.0, 0.0, -1.0);
 
@@ -109,25 +303,120 @@ impl Camera {
         self.target = self.position + self.forward;
     }
 
+    /// Turns the camera toward `target`. The angles invert `update_vectors`,
+    /// which turns (0, 0, -1) by pitch about x and then by yaw about y, so
+    /// the forward vector comes out as the direction to `target`. Pitch is
+    /// clamped like `rotate`'s, so a target straight above or below is
+    /// looked at from 1 degree short. A target on the camera's position
+    /// leaves the view as it is.
     pub fn look_at(&mut self, target: Vec3) {
-        self.target = target;
         let direction = (target - self.position).normalize();
+        if direction.length_squared() == 0.0 {
+            return;
+        }
+        self.target = target;
 
         // Calculate yaw and pitch from direction
         self.yaw = (-direction.x).atan2(-direction.z);
-        self.pitch = direction.y.asin();
+        self.pitch = direction
+            .y
+            .clamp(-1.0, 1.0)
+            .asin()
+            .clamp(-89.0_f32.to_radians(), 89.0_f32.to_radians());
 
         self.update_vectors();
     }
 
+    /// True if a sphere overlaps the view frustum. The far plane is ignored,
+    /// since the shader traces rays without one.
+    pub fn sphere_in_frustum(&self, center: Vec3, radius: f32) -> bool {
+        let offset = center - self.position;
+        let x = offset.dot(&self.right);
+        let y = offset.dot(&self.up);
+        let z = offset.dot(&self.forward);
+
+        if z + radius < self.near {
+            return false;
+        }
+
+        // Signed distance to each side plane through the eye
+        let half_vertical = self.fov * 0.5;
+        let half_horizontal = (half_vertical.tan() * self.aspect_ratio).atan();
+        let (sin_v, cos_v) = half_vertical.sin_cos();
+        let (sin_h, cos_h) = half_horizontal.sin_cos();
+
+        y.abs() * cos_v - z * sin_v <= radius && x.abs() * cos_h - z * sin_h <= radius
+    }
+
+    pub fn view_projection_matrix(&self) -> Mat4 {
+        self.projection_matrix() * self.view_matrix()
+    }
+
+    /// Screen-space bounds `(min_x, min_y, max_x, max_y)` of `bounds` in a
+    /// `width` x `height` image (origin top-left), or `None` if the box is
+    /// entirely behind the near plane. Parts behind the camera are clipped
+    /// away first, so boxes straddling it still get finite bounds.
+    pub fn project_bounds(
+        &self,
+        bounds: &Aabb,
+        width: f32,
+        height: f32,
+    ) -> Option<(f32, f32, f32, f32)> {
+        // Corner index pairs of the box edges (see `Aabb::corners`)
+        const EDGES: [(usize, usize); 12] = [
+            (0, 1),
+            (2, 3),
+            (4, 5),
+            (6, 7),
+            (0, 2),
+            (1, 3),
+            (4, 6),
+            (5, 7),
+            (0, 4),
+            (1, 5),
+            (2, 6),
+            (3, 7),
+        ];
+
+        let view_projection = self.view_projection_matrix();
+        let clip = bounds.corners().map(|corner| view_projection.transform_point(corner));
+        let in_front = |p: &[f32; 4]| p[3] >= self.near;
+
+        let mut points = Vec::with_capacity(24);
+        points.extend(clip.iter().filter(|p| in_front(p)).copied());
+        for (a, b) in EDGES {
+            let (pa, pb) = (clip[a], clip[b]);
+            if in_front(&pa) != in_front(&pb) {
+                let t = (self.near - pa[3]) / (pb[3] - pa[3]);
+                points.push(std::array::from_fn(|i| pa[i] + (pb[i] - pa[i]) * t));
+            }
+        }
+        if points.is_empty() {
+            return None;
+        }
+
+        let mut min = (f32::INFINITY, f32::INFINITY);
+        let mut max = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for p in points {
+            let x = (p[0] / p[3] + 1.0) * 0.5 * width;
+            let y = (1.0 - p[1] / p[3]) * 0.5 * height;
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+        }
+        Some((min.0, min.1, max.0, max.1))
+    }
+
+    /// Direction of the ray through `(x, y)` in a `width` x `height` image
+    /// with its origin at the top left, as the shader traces it.
     pub fn get_ray_direction(&self, x: f32, y: f32, width: f32, height: f32) -> Vec3 {
         // Convert screen coordinates to normalized device coordinates
         let ndc_x = (2.0 * x / width) - 1.0;
         let ndc_y = 1.0 - (2.0 * y / height);
 
-        // Convert to camera space
+        // Convert to camera space. The shader takes the aspect from the
+        // render size, which rounding can put slightly off the camera's.
         let tan_half_fov = (self.fov / 2.0).tan();
-        let camera_x = ndc_x * tan_half_fov * self.aspect_ratio;
+        let camera_x = ndc_x * tan_half_fov * (width / height);
         let camera_y = ndc_y * tan_half_fov;
 
         // Calculate ray direction in world space
@@ -144,15 +433,9 @@ impl Camera {
         self.position
     }
 
+    /// Same as `look_at`.
     pub fn set_target(&mut self, target: Vec3) {
-        self.target = target;
-        let direction = (target - self.position).normalize();
-
-        // Calculate yaw and pitch from direction
-        self.yaw = direction.z.atan2(direction.x);
-        self.pitch = direction.y.asin();
-
-        self.update_vectors();
+        self.look_at(target);
     }
 
     pub fn get_target(&self) -> Vec3 {
@@ -171,3 +454,465 @@ impl Camera {
         self.up
     }
 }
+
+/// An eased move of the camera to a new position and view over a fixed time.
+/// The position follows a straight line while the view direction turns along
+/// the shortest arc, so the view never swings through the scene the way
+/// interpolating the look-at point would.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraFlight {
+    from_position: Vec3,
+    to_position: Vec3,
+    from_forward: Vec3,
+    to_forward: Vec3,
+    // Field of view in radians
+    from_fov: f32,
+    to_fov: f32,
+    start_ms: f64,
+    duration_ms: f64,
+}
+
+impl CameraFlight {
+    /// A flight from where `camera` is now to `position`, looking at
+    /// `target`, starting at `start_ms`.
+    pub fn new(
+        camera: &Camera,
+        position: Vec3,
+        target: Vec3,
+        start_ms: f64,
+        duration_ms: f64,
+    ) -> Self {
+        let to_forward = (target - position).normalize();
+        Self {
+            from_position: camera.position,
+            to_position: position,
+            from_forward: camera.forward,
+            // A target on the position itself keeps the current direction
+            to_forward: if to_forward.length_squared() > 0.0 { to_forward } else { camera.forward },
+            from_fov: camera.fov,
+            to_fov: camera.fov,
+            start_ms,
+            duration_ms: duration_ms.max(0.0),
+        }
+    }
+
+    /// Also eases the field of view to `fov` degrees, clamped like
+    /// `Camera::set_fov`'s. Without this the flight leaves it alone.
+    pub fn with_fov(mut self, fov: f32) -> Self {
+        if fov.is_finite() {
+            self.to_fov = fov.clamp(MIN_FOV, MAX_FOV).to_radians();
+        }
+        self
+    }
+
+    /// Puts `camera` where the flight is at `now_ms`. Returns true once the
+    /// flight has arrived, with the camera exactly at its end.
+    pub fn apply(&self, camera: &mut Camera, now_ms: f64) -> bool {
+        let progress = if self.duration_ms > 0.0 {
+            ((now_ms - self.start_ms) / self.duration_ms).clamp(0.0, 1.0) as f32
+        } else {
+            1.0
+        };
+        if progress >= 1.0 {
+            camera.set_position(self.to_position);
+            camera.look_at(self.to_position + self.to_forward);
+            if self.to_fov != self.from_fov {
+                camera.fov = self.to_fov;
+            }
+            return true;
+        }
+
+        // Ease in and out so the camera neither jerks off nor stops dead
+        let t = progress * progress * (3.0 - 2.0 * progress);
+        let position = self.from_position + (self.to_position - self.from_position) * t;
+        camera.set_position(position);
+        camera.look_at(position + slerp(self.from_forward, self.to_forward, t));
+        // Untouched otherwise, so `set_fov` during a plain flight sticks
+        if self.to_fov != self.from_fov {
+            camera.fov = self.from_fov + (self.to_fov - self.from_fov) * t;
+        }
+        false
+    }
+}
+
+/// Turns unit vector `from` toward unit vector `to` by fraction `t` of the
+/// angle between them.
+fn slerp(from: Vec3, to: Vec3, t: f32) -> Vec3 {
+    let angle = from.dot(&to).clamp(-1.0, 1.0).acos();
+    let mut axis = from.cross(&to);
+    if axis.length_squared() < 1e-12 {
+        if angle < std::f32::consts::FRAC_PI_2 {
+            return to;
+        }
+        // Opposite directions: turn about the part of world up (or of x when
+        // looking straight up or down) perpendicular to `from`
+        let up = if from.y.abs() < 0.99 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        axis = up - from * up.dot(&from);
+    }
+    let axis = axis.normalize();
+
+    // Rodrigues' rotation formula
+    let (sin, cos) = (angle * t).sin_cos();
+    from * cos + axis.cross(&from) * sin + axis * (axis.dot(&from) * (1.0 - cos))
+}
+
+/// A named viewpoint saved with the scene. Angles are in degrees, as in the
+/// camera API. The aspect ratio is what the view was saved at; the camera's
+/// own follows the canvas, so applying a preset leaves it alone.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraPreset {
+    pub position: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub fov: f32,
+    pub aspect_ratio: f32,
+}
+
+impl CameraPreset {
+    /// The view `camera` has now.
+    pub fn capture(camera: &Camera) -> Self {
+        Self {
+            position: camera.position,
+            yaw: camera.yaw.to_degrees(),
+            pitch: camera.pitch.to_degrees(),
+            fov: camera.fov.to_degrees(),
+            aspect_ratio: camera.aspect_ratio,
+        }
+    }
+
+    /// Unit vector the preset looks along.
+    pub fn forward(&self) -> Vec3 {
+        let (yaw, pitch) = (self.yaw.to_radians(), self.pitch.to_radians());
+        Vec3::new(-yaw.sin() * pitch.cos(), pitch.sin(), -yaw.cos() * pitch.cos())
+    }
+
+    /// Puts `camera` at this view.
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.set_position(self.position);
+        camera.set_orientation(self.yaw.to_radians(), self.pitch.to_radians());
+        camera.set_fov(self.fov);
+    }
+
+    /// An eased flight from where `camera` is now to this view.
+    pub fn flight(&self, camera: &Camera, start_ms: f64, duration_ms: f64) -> CameraFlight {
+        CameraFlight::new(
+            camera,
+            self.position,
+            self.position + self.forward(),
+            start_ms,
+            duration_ms,
+        )
+        .with_fov(self.fov)
+    }
+}
+
+/// A camera pose on a `CameraPath`. Angles are in degrees, as in the
+/// camera API; yaw turns left from looking down -z and pitch looks up.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraKeyframe {
+    /// Seconds from the start of the path
+    pub time: f32,
+    pub position: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    #[serde(default = "default_keyframe_fov")]
+    pub fov: f32,
+}
+
+fn default_keyframe_fov() -> f32 {
+    DEFAULT_FOV
+}
+
+impl CameraKeyframe {
+    fn values(&self) -> [f32; 6] {
+        let p = self.position;
+        [p.x, p.y, p.z, self.yaw, self.pitch, self.fov]
+    }
+
+    fn from_values(time: f32, v: [f32; 6]) -> Self {
+        Self {
+            time,
+            position: Vec3::new(v[0], v[1], v[2]),
+            yaw: v[3],
+            pitch: v[4],
+            fov: v[5],
+        }
+    }
+
+    /// Puts `camera` at this pose.
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.set_position(self.position);
+        camera.set_orientation(self.yaw.to_radians(), self.pitch.to_radians());
+        camera.set_fov(self.fov);
+    }
+}
+
+/// Keyframed camera poses for turntables and walkthroughs, saved with the
+/// scene. Poses between keyframes follow a Catmull-Rom spline, so the camera
+/// passes through every keyframe without stopping at it. Angles are
+/// interpolated as given, so a full turn is written as 0 to 360 rather than
+/// wrapping back to 0.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "UnsortedCameraPath")]
+pub struct CameraPath {
+    // Sorted by time
+    keyframes: Vec<CameraKeyframe>,
+}
+
+/// A path as written in a file, which may list keyframes in any order.
+#[derive(Deserialize)]
+struct UnsortedCameraPath {
+    keyframes: Vec<CameraKeyframe>,
+}
+
+impl From<UnsortedCameraPath> for CameraPath {
+    fn from(unsorted: UnsortedCameraPath) -> Self {
+        let mut path = CameraPath::new();
+        for keyframe in unsorted.keyframes {
+            if keyframe.time.is_finite() {
+                path.insert(keyframe);
+            }
+        }
+        path
+    }
+}
+
+impl CameraPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `keyframe` in time order, replacing one at the same time.
+    pub fn insert(&mut self, keyframe: CameraKeyframe) {
+        match self
+            .keyframes
+            .binary_search_by(|k| k.time.total_cmp(&keyframe.time))
+        {
+            Ok(index) => self.keyframes[index] = keyframe,
+            Err(index) => self.keyframes.insert(index, keyframe),
+        }
+    }
+
+    pub fn keyframes(&self) -> &[CameraKeyframe] {
+        &self.keyframes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    /// Time of the last keyframe in seconds.
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |k| k.time)
+    }
+
+    /// The pose at `time` seconds, holding the first and last keyframes
+    /// outside their range. `None` for an empty path.
+    pub fn evaluate(&self, time: f32) -> Option<CameraKeyframe> {
+        let keyframes = &self.keyframes;
+        let first = keyframes.first()?;
+        let last = keyframes.last()?;
+        if time <= first.time {
+            return Some(CameraKeyframe { time, ..*first });
+        }
+        if time >= last.time {
+            return Some(CameraKeyframe { time, ..*last });
+        }
+
+        // Segment from keyframe i to i + 1, with the end keyframes repeated
+        // as the outer neighbours
+        let i = keyframes.partition_point(|k| k.time <= time) - 1;
+        let k0 = &keyframes[i.saturating_sub(1)];
+        let (k1, k2) = (&keyframes[i], &keyframes[i + 1]);
+        let k3 = &keyframes[(i + 2).min(keyframes.len() - 1)];
+
+        // Tangents scaled to the segment's length, which keeps the speed
+        // continuous across keyframes spaced unevenly in time
+        let dt = k2.time - k1.time;
+        let tangent = |a: &CameraKeyframe, b: &CameraKeyframe, c: usize| {
+            let span = b.time - a.time;
+            if span > 0.0 {
+                (b.values()[c] - a.values()[c]) / span * dt
+            } else {
+                0.0
+            }
+        };
+        let s = (time - k1.time) / dt;
+        let (s2, s3) = (s * s, s * s * s);
+        let h00 = 2.0 * s3 - 3.0 * s2 + 1.0;
+        let h10 = s3 - 2.0 * s2 + s;
+        let h01 = -2.0 * s3 + 3.0 * s2;
+        let h11 = s3 - s2;
+
+        let (v1, v2) = (k1.values(), k2.values());
+        let values = std::array::from_fn(|c| {
+            h00 * v1[c] + h10 * tangent(k0, k2, c) + h01 * v2[c] + h11 * tangent(k1, k3, c)
+        });
+        Some(CameraKeyframe::from_values(time, values))
+    }
+}
+
+/// Playback of a `CameraPath` against scene time.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraPathPlayback {
+    start_ms: f64,
+    looping: bool,
+}
+
+impl CameraPathPlayback {
+    pub fn new(start_ms: f64, looping: bool) -> Self {
+        Self { start_ms, looping }
+    }
+
+    /// Puts `camera` where `path` is at `now_ms`. Returns true once a
+    /// non-looping playback has reached the last keyframe, or if the path is
+    /// empty.
+    pub fn apply(&self, path: &CameraPath, camera: &mut Camera, now_ms: f64) -> bool {
+        let elapsed = ((now_ms - self.start_ms) / 1000.0).max(0.0);
+        let duration = f64::from(path.duration());
+        let (time, done) = if self.looping && duration > 0.0 {
+            (elapsed % duration, false)
+        } else {
+            (elapsed, elapsed >= duration)
+        };
+        match path.evaluate(time as f32) {
+            Some(pose) => {
+                pose.apply(camera);
+                done
+            }
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        assert!(
+            (actual - expected).length() < 1e-4,
+            "expected {:?}, got {:?}",
+            expected,
+            actual
+        );
+    }
+
+    fn assert_orthonormal(camera: &Camera) {
+        let (forward, right, up) = (camera.get_forward(), camera.get_right(), camera.get_up());
+        for v in [forward, right, up] {
+            assert!(v.is_finite(), "basis vector {:?} is not finite", v);
+            assert!((v.length() - 1.0).abs() < 1e-4, "basis vector {:?} is not unit", v);
+        }
+        assert!(forward.dot(&right).abs() < 1e-4);
+        assert!(forward.dot(&up).abs() < 1e-4);
+        assert!(right.dot(&up).abs() < 1e-4);
+    }
+
+    #[test]
+    fn look_at_points_forward_at_the_target() {
+        let position = Vec3::new(1.0, 2.0, 3.0);
+        let targets = [
+            Vec3::new(1.0, 2.0, -5.0),
+            Vec3::new(6.0, 2.0, 3.0),
+            Vec3::new(-4.0, 0.0, 8.0),
+            Vec3::new(1.5, 7.0, 2.0),
+            Vec3::new(-3.0, -6.0, -1.0),
+        ];
+        for target in targets {
+            let mut camera = Camera::new(position, Vec3::new(0.0, 0.0, 0.0), 1.0);
+            camera.look_at(target);
+            assert_close(camera.get_forward(), (target - position).normalize());
+            assert_close(camera.get_target(), position + camera.get_forward());
+            assert_orthonormal(&camera);
+        }
+    }
+
+    #[test]
+    fn set_target_matches_look_at() {
+        let position = Vec3::new(0.0, 1.0, 4.0);
+        let target = Vec3::new(2.0, -1.0, -3.0);
+        let mut looked = Camera::new(position, Vec3::new(0.0, 0.0, 0.0), 1.0);
+        let mut targeted = Camera::new(position, Vec3::new(0.0, 0.0, 0.0), 1.0);
+        looked.look_at(target);
+        targeted.set_target(target);
+        assert_eq!(looked.yaw(), targeted.yaw());
+        assert_eq!(looked.pitch(), targeted.pitch());
+        assert_close(targeted.get_forward(), looked.get_forward());
+    }
+
+    #[test]
+    fn look_at_recovers_yaw_and_pitch() {
+        let position = Vec3::new(0.5, 1.0, -2.0);
+        for yaw_degrees in [-170.0_f32, -90.0, -30.0, 0.0, 45.0, 120.0, 179.0] {
+            for pitch_degrees in [-80.0_f32, -20.0, 0.0, 35.0, 85.0] {
+                let (yaw, pitch) = (yaw_degrees.to_radians(), pitch_degrees.to_radians());
+                let mut camera = Camera::new(position, Vec3::new(0.0, 0.0, 0.0), 1.0);
+                camera.set_orientation(yaw, pitch);
+                let forward = camera.get_forward();
+
+                camera.look_at(position + forward * 3.0);
+                assert!((camera.yaw() - yaw).abs() < 1e-4, "yaw {} -> {}", yaw, camera.yaw());
+                assert!((camera.pitch() - pitch).abs() < 1e-4);
+                assert_close(camera.get_forward(), forward);
+                // A preset saved from it points the same way
+                assert_close(CameraPreset::capture(&camera).forward(), forward);
+            }
+        }
+    }
+
+    #[test]
+    fn look_at_straight_up_keeps_a_usable_basis() {
+        for target in [Vec3::new(0.0, 10.0, 0.0), Vec3::new(0.0, -10.0, 0.0)] {
+            let mut camera = Camera::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 1.0);
+            camera.look_at(target);
+            assert_orthonormal(&camera);
+            assert!(camera.pitch().abs() <= 89.0_f32.to_radians() + 1e-6);
+            assert!(camera.get_forward().dot(&target.normalize()) > 0.99);
+        }
+    }
+
+    #[test]
+    fn look_at_own_position_keeps_the_view() {
+        let position = Vec3::new(1.0, 1.0, 1.0);
+        let mut camera = Camera::new(position, Vec3::new(0.0, 0.0, 0.0), 1.0);
+        camera.look_at(Vec3::new(4.0, 1.0, -3.0));
+        let forward = camera.get_forward();
+        camera.look_at(position);
+        assert_close(camera.get_forward(), forward);
+        assert_orthonormal(&camera);
+    }
+
+    #[test]
+    fn camera_change_is_reported_once_per_move() {
+        let mut camera = Camera::new(Vec3::new(0.0, 1.0, 4.0), Vec3::new(0.0, 0.0, 0.0), 1.0);
+        let mut last = None;
+        let changed = |camera: &Camera, last: &mut Option<CameraState>| {
+            camera.state().changed_since(last, 1e-3, 1e-3)
+        };
+
+        // The first frame always counts as a change; rendering again without
+        // input doesn't
+        assert!(changed(&camera, &mut last));
+        assert!(!changed(&camera, &mut last));
+        assert!(!changed(&camera, &mut last));
+
+        camera.move_absolute(0.5, 1.0, 4.0);
+        assert!(changed(&camera, &mut last));
+        assert!(!changed(&camera, &mut last));
+
+        // Steps under the threshold add up until they cross it
+        camera.move_absolute(0.5006, 1.0, 4.0);
+        assert!(!changed(&camera, &mut last));
+        camera.move_absolute(0.5012, 1.0, 4.0);
+        assert!(changed(&camera, &mut last));
+
+        camera.set_fov(30.0);
+        assert!(changed(&camera, &mut last));
+        assert!(!changed(&camera, &mut last));
+    }
+}
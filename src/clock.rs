@@ -0,0 +1,44 @@
+use js_sys::Date;
+
+/// Scene time in milliseconds that can be paused independently of wall-clock time.
+///
+/// Frame timing (FPS) keeps using `Date::now()`; everything animated inside the
+/// scene reads this clock instead so pausing freezes it.
+pub struct Clock {
+    paused_at: Option<f64>,
+    paused_total: f64,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Self {
+            paused_at: None,
+            paused_total: 0.0,
+        }
+    }
+
+    pub fn now(&self) -> f64 {
+        self.paused_at.unwrap_or_else(Date::now) - self.paused_total
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        match (paused, self.paused_at) {
+            (true, None) => self.paused_at = Some(Date::now()),
+            (false, Some(paused_at)) => {
+                self.paused_total += Date::now() - paused_at;
+                self.paused_at = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Pauses the clock at `time_ms`. Resuming continues from there.
+    pub fn pause_at(&mut self, time_ms: f64) {
+        self.paused_at = Some(time_ms);
+        self.paused_total = 0.0;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+}
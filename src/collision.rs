@@ -0,0 +1,382 @@
+//! Sphere sweeps against the scene, used to keep the camera out of objects.
+//!
+//! Every primitive is treated as a convex solid (cylinders and cones as
+//! capped ones, planes, triangles and disks as zero-thickness sheets, rings
+//! keeping their hole) and swept against by conservative advancement: step
+//! forward by the gap to the surface until the gap closes. Along a straight path the distance to a
+//! convex solid stops shrinking for good once it stops shrinking at all, so a
+//! primitive can be dropped as soon as the path no longer approaches it.
+//!
+//! Objects combined by CSG collide as their whole operands, except that
+//! subtracted ones are left out: the camera stays out of what was cut away
+//! as well.
+
+use crate::math::{Aabb, Quat, Transform, Vec3};
+use crate::scene::{
+    Box, Cone, CsgOperation, Cylinder, Disk, ObjectRef, ObjectType, Plane, Scene, Sphere, Triangle,
+};
+
+/// Gap at which a sweep counts as touching a surface.
+const CONTACT_SKIN: f32 = 1e-4;
+/// Advancement steps per primitive before giving up on a (grazing) approach.
+const MAX_SWEEP_STEPS: usize = 32;
+/// Contacts resolved per move; each one can redirect the remaining motion.
+const MAX_SLIDES: usize = 3;
+
+/// A sweep stopped at `t` (fraction of the motion) against a surface facing
+/// `normal`.
+#[derive(Clone, Copy, Debug)]
+pub struct Contact {
+    pub t: f32,
+    pub normal: Vec3,
+}
+
+/// Moves a sphere of `radius` from `start` by `motion`, stopping at the first
+/// surface and sliding along it with whatever motion is left. Returns the end
+/// position. Surfaces the sphere already overlaps only block motion into them.
+/// Instanced grids are not collided with.
+pub fn slide(scene: &Scene, start: Vec3, motion: Vec3, radius: f32) -> Vec3 {
+    let mut position = start;
+    let mut remaining = motion;
+    for _ in 0..MAX_SLIDES {
+        if remaining.length_squared() < CONTACT_SKIN * CONTACT_SKIN {
+            break;
+        }
+        let Some(contact) = sweep(scene, position, remaining, radius) else {
+            position = position + remaining;
+            break;
+        };
+        position = position + remaining * contact.t;
+
+        // Keep only the part of the rest that runs along the surface
+        let rest = remaining * (1.0 - contact.t);
+        remaining = rest - contact.normal * rest.dot(&contact.normal).min(0.0);
+    }
+    position
+}
+
+/// First contact of a sphere of `radius` moving from `start` by `motion`.
+pub fn sweep(scene: &Scene, start: Vec3, motion: Vec3, radius: f32) -> Option<Contact> {
+    let mut path = Aabb::new(start, start);
+    path.include_point(start + motion);
+    let path = path.expanded(radius + CONTACT_SKIN);
+
+    let mut first: Option<Contact> = None;
+    let mut consider = |bounds: Option<Aabb>, closest: &dyn Fn(Vec3) -> Vec3| {
+        if bounds.is_some_and(|bounds| !bounds.intersects(&path)) {
+            return;
+        }
+        if let Some(contact) = advance(start, motion, radius, closest)
+            && first.is_none_or(|first| contact.t < first.t)
+        {
+            first = Some(contact);
+        }
+    };
+
+    // Subtracted operands are empty space
+    let subtracted: Vec<ObjectRef> = scene
+        .drawn_csg()
+        .iter()
+        .filter(|csg| csg.op == CsgOperation::Subtract)
+        .map(|csg| csg.b)
+        .collect();
+    let solid = |kind, index| !subtracted.contains(&ObjectRef { kind, index });
+
+    for (i, sphere) in scene.spheres.iter().enumerate() {
+        if solid(ObjectType::Sphere, i) {
+            consider(Some(sphere.bounds()), &|p| closest_on_sphere(sphere, p));
+        }
+    }
+    for plane in &scene.planes {
+        consider(None, &|p| closest_on_plane(plane, p));
+    }
+    for (i, box_obj) in scene.boxes.iter().enumerate() {
+        if solid(ObjectType::Box, i) {
+            consider(Some(box_obj.bounds()), &|p| closest_on_box(box_obj, p));
+        }
+    }
+    for cylinder in &scene.cylinders {
+        consider(Some(cylinder.bounds()), &|p| closest_on_cylinder(cylinder, p));
+    }
+    for triangle in &scene.triangles {
+        consider(Some(triangle.bounds()), &|p| closest_on_triangle(triangle, p));
+    }
+    for cone in &scene.cones {
+        consider(Some(cone.bounds()), &|p| closest_on_cone(cone, p));
+    }
+    for disk in &scene.disks {
+        consider(Some(disk.bounds()), &|p| closest_on_disk(disk, p));
+    }
+    first
+}
+
+/// Conservative advancement against one convex solid given by its
+/// closest-point function.
+fn advance(
+    start: Vec3,
+    motion: Vec3,
+    radius: f32,
+    closest: &dyn Fn(Vec3) -> Vec3,
+) -> Option<Contact> {
+    let length = motion.length();
+    if length == 0.0 {
+        return None;
+    }
+
+    let mut t = 0.0;
+    for _ in 0..MAX_SWEEP_STEPS {
+        let position = start + motion * t;
+        let offset = position - closest(position);
+        let distance = offset.length();
+        // Inside the solid: nothing to push against, let the camera out
+        if distance == 0.0 {
+            return None;
+        }
+        let normal = offset / distance;
+        if motion.dot(&normal) >= 0.0 {
+            return None;
+        }
+
+        let gap = distance - radius;
+        if gap <= CONTACT_SKIN {
+            return Some(Contact { t, normal });
+        }
+        t += gap / length;
+        if t >= 1.0 {
+            return None;
+        }
+    }
+    // Still closing in after every step: stop where it is known to be clear
+    let normal = (start + motion * t - closest(start + motion * t)).normalize();
+    Some(Contact { t, normal })
+}
+
+/// An ellipsoid's closest point has no closed form, so it is swept as the
+/// sphere around it, which keeps the camera clear of it.
+fn closest_on_sphere(sphere: &Sphere, p: Vec3) -> Vec3 {
+    let radius = sphere.bounding_radius();
+    let offset = p - sphere.center;
+    let distance = offset.length();
+    if distance <= radius {
+        return p;
+    }
+    sphere.center + offset * (radius / distance)
+}
+
+fn closest_on_plane(plane: &Plane, p: Vec3) -> Vec3 {
+    p - plane.normal * (p - plane.point).dot(&plane.normal)
+}
+
+/// Clamped in the box's own axes, along which its scale only stretches
+/// its size.
+fn closest_on_box(box_obj: &Box, p: Vec3) -> Vec3 {
+    let rotation = Quat::from_euler(box_obj.rotation);
+    let half = box_obj.size * box_obj.scale * 0.5;
+    let local = rotation.inverse().rotate(p - box_obj.center);
+    let clamped = Vec3::new(
+        local.x.clamp(-half.x, half.x),
+        local.y.clamp(-half.y, half.y),
+        local.z.clamp(-half.z, half.z),
+    );
+    box_obj.center + rotation.rotate(clamped)
+}
+
+/// Unequal scale factors make a cylinder elliptic, so a scaled one is swept
+/// as the cylinder its largest factor gives, which encloses it.
+fn closest_on_cylinder(cylinder: &Cylinder, p: Vec3) -> Vec3 {
+    let (base, axis, radius) = match cylinder.transform() {
+        Some(transform) => {
+            let s = transform.scale;
+            let factor = s.x.max(s.y).max(s.z);
+            let enclosing = Transform {
+                scale: Vec3::new(factor, factor, factor),
+                ..transform
+            };
+            let base = enclosing.point(cylinder.base);
+            (base, enclosing.point(cylinder.base + cylinder.axis) - base, cylinder.radius * factor)
+        }
+        None => (cylinder.base, cylinder.axis, cylinder.radius),
+    };
+    let length = axis.length();
+    if length == 0.0 {
+        return base;
+    }
+    let axis = axis / length;
+    let offset = p - base;
+    let height = offset.dot(&axis).clamp(0.0, length);
+    let radial = offset - axis * offset.dot(&axis);
+    let radial_length = radial.length();
+    let radial = if radial_length > radius {
+        radial * (radius / radial_length)
+    } else {
+        radial
+    };
+    base + axis * height + radial
+}
+
+fn closest_on_cone(cone: &Cone, p: Vec3) -> Vec3 {
+    let length = cone.axis.length();
+    if length == 0.0 {
+        return cone.apex;
+    }
+    let axis = cone.axis / length;
+    let offset = p - cone.apex;
+    let height = offset.dot(&axis);
+    let radial = offset - axis * height;
+    let radial_length = radial.length();
+    let outward = if radial_length > 0.0 {
+        radial / radial_length
+    } else {
+        Vec3::zero()
+    };
+
+    // In the half-plane through the axis and p the cone is the triangle of
+    // the apex (0, 0), the base center (length, 0) and the rim (length, radius)
+    let inside = height <= length && radial_length * length <= cone.radius * height;
+    let (height, radial_length) = if inside {
+        (height, radial_length)
+    } else {
+        let slant = (height * length + radial_length * cone.radius)
+            / (length * length + cone.radius * cone.radius);
+        let slant = slant.clamp(0.0, 1.0);
+        let side = (length * slant, cone.radius * slant);
+        let base = (length, radial_length.min(cone.radius));
+        let distance = |(h, r): (f32, f32)| (h - height).powi(2) + (r - radial_length).powi(2);
+        if distance(side) < distance(base) {
+            side
+        } else {
+            base
+        }
+    };
+    cone.apex + axis * height + outward * radial_length
+}
+
+fn closest_on_disk(disk: &Disk, p: Vec3) -> Vec3 {
+    let offset = p - disk.center;
+    let radial = offset - disk.normal * offset.dot(&disk.normal);
+    let radial_length = radial.length();
+    let outward = if radial_length > 0.0 {
+        radial / radial_length
+    } else {
+        // On the axis every direction is as close to a ring's inner edge
+        let other = if disk.normal.x.abs() < 0.9 {
+            Vec3::new(1.0, 0.0, 0.0)
+        } else {
+            Vec3::new(0.0, 1.0, 0.0)
+        };
+        disk.normal.cross(&other).normalize()
+    };
+    disk.center + outward * radial_length.clamp(disk.inner_radius, disk.radius)
+}
+
+/// Closest point on a triangle (Ericson, Real-Time Collision Detection 5.1.5).
+fn closest_on_triangle(triangle: &Triangle, p: Vec3) -> Vec3 {
+    let (a, b, c) = (triangle.v0, triangle.v1, triangle.v2);
+    let ab = b - a;
+    let ac = c - a;
+
+    let ap = p - a;
+    let d1 = ab.dot(&ap);
+    let d2 = ac.dot(&ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+
+    let bp = p - b;
+    let d3 = ab.dot(&bp);
+    let d4 = ac.dot(&bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+
+    let cp = p - c;
+    let d5 = ab.dot(&cp);
+    let d6 = ac.dot(&cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+
+    let denom = 1.0 / (va + vb + vc);
+    a + ab * (vb * denom) + ac * (vc * denom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+
+    fn grey() -> Material {
+        Material::lambertian(Vec3::new(0.5, 0.5, 0.5))
+    }
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        assert!(
+            (actual - expected).length() < 1e-3,
+            "expected {:?}, got {:?}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn head_on_approach_stops_at_radius_distance() {
+        let mut scene = Scene::new();
+        scene.add_sphere(Sphere::new(Vec3::zero(), 1.0, grey()));
+
+        let start = Vec3::new(0.0, 0.0, 5.0);
+        let end = slide(&scene, start, Vec3::new(0.0, 0.0, -10.0), 0.5);
+        assert_close(end, Vec3::new(0.0, 0.0, 1.5));
+
+        let contact = sweep(&scene, start, Vec3::new(0.0, 0.0, -10.0), 0.5).expect("contact");
+        assert!((contact.t - 0.35).abs() < 1e-3);
+        assert_close(contact.normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn slides_along_the_ground_plane() {
+        let mut scene = Scene::new();
+        scene.add_plane(Plane::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), grey()));
+
+        // Lands a quarter of the way, then keeps only the motion along the floor
+        let end = slide(&scene, Vec3::new(0.0, 1.0, 0.0), Vec3::new(4.0, -2.0, 0.0), 0.5);
+        assert_close(end, Vec3::new(4.0, 0.5, 0.0));
+
+        // Walking on the floor doesn't stick to it
+        let walked = slide(&scene, end, Vec3::new(0.0, 0.0, 3.0), 0.5);
+        assert_close(walked, Vec3::new(4.0, 0.5, 3.0));
+    }
+
+    #[test]
+    fn grazing_contact_keeps_the_probe_outside() {
+        let mut scene = Scene::new();
+        scene.add_sphere(Sphere::new(Vec3::zero(), 1.0, grey()));
+
+        // The path passes 1.49 from the center, just inside the 1.5 it may reach
+        let start = Vec3::new(-5.0, 0.0, 1.49);
+        let motion = Vec3::new(10.0, 0.0, 0.0);
+        assert!(sweep(&scene, start, motion, 0.5).is_some());
+        let end = slide(&scene, start, motion, 0.5);
+        assert!(end.length() >= 1.5 - 1e-3, "ended inside at {:?}", end);
+        assert!(end.x > 0.0, "stuck at {:?}", end);
+
+        // Just outside it the sphere isn't touched at all
+        let clear = Vec3::new(-5.0, 0.0, 1.51);
+        assert!(sweep(&scene, clear, motion, 0.5).is_none());
+        assert_close(slide(&scene, clear, motion, 0.5), clear + motion);
+    }
+}
@@ -0,0 +1,31 @@
+/// sRGB electro-optical transfer function: encoded value to linear light.
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Decodes an 8-bit sRGB channel to an 8-bit linear one.
+pub fn srgb8_to_linear8(value: u8) -> u8 {
+    (srgb_to_linear(value as f32 / 255.0) * 255.0).round() as u8
+}
+
+/// Converts hue (0..1, wrapping), saturation and value to RGB.
+pub fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> (f32, f32, f32) {
+    let h = hue.rem_euclid(1.0) * 6.0;
+    let c = value * saturation;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let m = value - c;
+
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    (r + m, g + m, b + m)
+}
@@ -0,0 +1,282 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+use web_sys::{
+    Element, Event, EventTarget, HtmlCanvasElement, KeyboardEvent, MouseEvent, WheelEvent,
+};
+
+use crate::camera::Camera;
+use crate::error::RaytracerError;
+
+/// Longest frame delta applied in one update, so a stalled tab doesn't
+/// teleport the camera when it resumes.
+const MAX_FRAME_DELTA_S: f32 = 0.1;
+
+/// `KeyboardEvent.code` values for each movement direction.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub forward: String,
+    pub backward: String,
+    pub left: String,
+    pub right: String,
+    pub up: String,
+    pub down: String,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            forward: "KeyW".to_string(),
+            backward: "KeyS".to_string(),
+            left: "KeyA".to_string(),
+            right: "KeyD".to_string(),
+            up: "KeyE".to_string(),
+            down: "KeyQ".to_string(),
+        }
+    }
+}
+
+/// Options accepted by `attach_default_controls`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ControlOptions {
+    /// Movement speed in meters per second
+    pub move_speed: f32,
+    /// Radians of rotation per pixel of mouse movement
+    pub look_sensitivity: f32,
+    /// Meters of dolly per wheel pixel
+    pub zoom_speed: f32,
+    pub invert_y: bool,
+    pub bindings: KeyBindings,
+}
+
+impl Default for ControlOptions {
+    fn default() -> Self {
+        Self {
+            move_speed: 5.0,
+            look_sensitivity: 0.003,
+            zoom_speed: 0.01,
+            invert_y: false,
+            bindings: KeyBindings::default(),
+        }
+    }
+}
+
+/// Input gathered by the event listeners between two frames.
+#[derive(Default)]
+struct InputState {
+    pressed: HashSet<String>,
+    look_delta: (f32, f32),
+    zoom_delta: f32,
+    pointer_locked: bool,
+}
+
+struct Listener {
+    target: EventTarget,
+    kind: &'static str,
+    callback: Closure<dyn FnMut(Event)>,
+}
+
+/// Built-in WASD + mouse-look controller.
+///
+/// Clicking the canvas captures the pointer; while captured, mouse movement
+/// rotates the camera. Keys are read from the whole document (the canvas is not
+/// focusable) but ignored while a text field has focus. The wheel dollies the
+/// camera along its view direction. Listeners are removed on drop.
+pub struct Controls {
+    options: ControlOptions,
+    // Scene units per meter, converting the speeds to scene units
+    world_scale: f32,
+    state: Rc<RefCell<InputState>>,
+    listeners: Vec<Listener>,
+}
+
+impl Controls {
+    pub fn attach(
+        canvas: &HtmlCanvasElement,
+        options: ControlOptions,
+    ) -> Result<Self, RaytracerError> {
+        let document = canvas
+            .owner_document()
+            .ok_or_else(|| RaytracerError::graphics("Canvas is not attached to a document"))?;
+        let state = Rc::new(RefCell::new(InputState::default()));
+        let mut controls = Self {
+            options,
+            world_scale: 1.0,
+            state,
+            listeners: Vec::new(),
+        };
+
+        let state = controls.state.clone();
+        controls.listen(document.as_ref(), "keydown", move |event| {
+            let Some(event) = event.dyn_ref::<KeyboardEvent>() else {
+                return;
+            };
+            if is_text_entry(event.target()) {
+                return;
+            }
+            state.borrow_mut().pressed.insert(event.code());
+        })?;
+
+        let state = controls.state.clone();
+        controls.listen(document.as_ref(), "keyup", move |event| {
+            if let Some(event) = event.dyn_ref::<KeyboardEvent>() {
+                state.borrow_mut().pressed.remove(&event.code());
+            }
+        })?;
+
+        // Focus moving into a text field would otherwise leave keys stuck down
+        let state = controls.state.clone();
+        controls.listen(document.as_ref(), "focusin", move |event| {
+            if is_text_entry(event.target()) {
+                state.borrow_mut().pressed.clear();
+            }
+        })?;
+
+        let lock_target = canvas.clone();
+        controls.listen(canvas.as_ref(), "click", move |_| {
+            lock_target.request_pointer_lock();
+        })?;
+
+        let state = controls.state.clone();
+        let lock_canvas: Element = canvas.clone().into();
+        let lock_document = document.clone();
+        controls.listen(document.as_ref(), "pointerlockchange", move |_| {
+            let locked = lock_document.pointer_lock_element().as_ref() == Some(&lock_canvas);
+            state.borrow_mut().pointer_locked = locked;
+        })?;
+
+        let state = controls.state.clone();
+        controls.listen(document.as_ref(), "mousemove", move |event| {
+            let Some(event) = event.dyn_ref::<MouseEvent>() else {
+                return;
+            };
+            let mut state = state.borrow_mut();
+            if state.pointer_locked {
+                state.look_delta.0 += event.movement_x() as f32;
+                state.look_delta.1 += event.movement_y() as f32;
+            }
+        })?;
+
+        let state = controls.state.clone();
+        controls.listen(canvas.as_ref(), "wheel", move |event| {
+            let Some(event) = event.dyn_ref::<WheelEvent>() else {
+                return;
+            };
+            event.prevent_default();
+            state.borrow_mut().zoom_delta += event.delta_y() as f32;
+        })?;
+
+        Ok(controls)
+    }
+
+    /// Sets the scene units per meter the speeds are converted with.
+    pub fn set_world_scale(&mut self, world_scale: f32) {
+        self.world_scale = world_scale;
+    }
+
+    /// Movement speed in scene units per second.
+    pub fn move_speed(&self) -> f32 {
+        self.options.move_speed * self.world_scale
+    }
+
+    /// Applies the input gathered since the last frame. `delta_s` is the
+    /// measured frame time, so movement speed doesn't depend on the frame rate.
+    pub fn update(&mut self, camera: &mut Camera, delta_s: f32) {
+        let delta_s = delta_s.clamp(0.0, MAX_FRAME_DELTA_S);
+        let mut state = self.state.borrow_mut();
+        let bindings = &self.options.bindings;
+
+        let axis = |positive: &str, negative: &str| {
+            state.pressed.contains(positive) as i32 as f32
+                - state.pressed.contains(negative) as i32 as f32
+        };
+        let forward = axis(&bindings.forward, &bindings.backward);
+        let right = axis(&bindings.right, &bindings.left);
+        let up = axis(&bindings.up, &bindings.down);
+
+        let step = self.move_speed() * delta_s;
+        if forward != 0.0 || right != 0.0 || up != 0.0 {
+            camera.move_relative(forward * step, right * step, up * step);
+        }
+
+        let (dx, dy) = std::mem::take(&mut state.look_delta);
+        if dx != 0.0 || dy != 0.0 {
+            let sensitivity = self.options.look_sensitivity;
+            let pitch_sign = if self.options.invert_y { 1.0 } else { -1.0 };
+            camera.rotate(dx * sensitivity, dy * sensitivity * pitch_sign);
+        }
+
+        let zoom = std::mem::take(&mut state.zoom_delta);
+        if zoom != 0.0 {
+            camera.move_relative(-zoom * self.options.zoom_speed * self.world_scale, 0.0, 0.0);
+        }
+    }
+
+    fn listen(
+        &mut self,
+        target: &EventTarget,
+        kind: &'static str,
+        handler: impl FnMut(Event) + 'static,
+    ) -> Result<(), RaytracerError> {
+        let callback = Closure::<dyn FnMut(Event)>::new(handler);
+        target.add_event_listener_with_callback(kind, callback.as_ref().unchecked_ref())?;
+        self.listeners.push(Listener {
+            target: target.clone(),
+            kind,
+            callback,
+        });
+        Ok(())
+    }
+}
+
+impl Drop for Controls {
+    fn drop(&mut self) {
+        for listener in &self.listeners {
+            let _ = listener.target.remove_event_listener_with_callback(
+                listener.kind,
+                listener.callback.as_ref().unchecked_ref(),
+            );
+        }
+        if self.state.borrow().pointer_locked
+            && let Some(document) = web_sys::window().and_then(|w| w.document())
+        {
+            document.exit_pointer_lock();
+        }
+    }
+}
+
+/// True if `target` is a control that takes typed text.
+fn is_text_entry(target: Option<EventTarget>) -> bool {
+    let Some(element) = target.and_then(|t| t.dyn_into::<web_sys::HtmlElement>().ok()) else {
+        return false;
+    };
+    if element.is_content_editable() {
+        return true;
+    }
+    match element.tag_name().as_str() {
+        "TEXTAREA" | "SELECT" => true,
+        // Sliders, checkboxes and buttons don't swallow WASD
+        "INPUT" => element
+            .dyn_ref::<web_sys::HtmlInputElement>()
+            .map(|input| {
+                !matches!(
+                    input.type_().as_str(),
+                    "range"
+                        | "checkbox"
+                        | "radio"
+                        | "button"
+                        | "submit"
+                        | "reset"
+                        | "color"
+                        | "file"
+                )
+            })
+            .unwrap_or(true),
+        _ => false,
+    }
+}
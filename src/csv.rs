@@ -0,0 +1,80 @@
+use crate::material::Material;
+use crate::math::Vec3;
+use crate::scene::Sphere;
+
+const DEFAULT_COLOR: Vec3 = Vec3::new(0.7, 0.7, 0.7);
+
+/// Parses rows of `x,y,z[,radius][,r,g,b]` into Lambertian spheres.
+///
+/// Fields may be separated by commas with any surrounding whitespace. A
+/// non-numeric first row is treated as a header, and blank lines or lines
+/// starting with `#` are skipped. Malformed rows don't abort the import; they
+/// are returned as warnings naming their 1-based line number.
+pub fn parse_spheres_csv(csv: &str, default_radius: f32) -> (Vec<Sphere>, Vec<String>) {
+    let mut spheres = Vec::new();
+    let mut warnings = Vec::new();
+    let mut seen_data = false;
+
+    for (line_index, line) in csv.lines().enumerate() {
+        let line_number = line_index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let values: Result<Vec<f32>, _> = fields.iter().map(|field| field.parse::<f32>()).collect();
+
+        let values = match values {
+            Ok(values) => values,
+            Err(_) if !seen_data && line.chars().any(char::is_alphabetic) => {
+                // Header row
+                seen_data = true;
+                continue;
+            }
+            Err(_) => {
+                warnings.push(format!(
+                    "line {}: non-numeric field in '{}'",
+                    line_number, line
+                ));
+                continue;
+            }
+        };
+        seen_data = true;
+
+        if values.iter().any(|value| !value.is_finite()) {
+            warnings.push(format!(
+                "line {}: non-finite value in '{}'",
+                line_number, line
+            ));
+            continue;
+        }
+
+        let (radius, color) = match values.len() {
+            3 => (default_radius, DEFAULT_COLOR),
+            4 => (values[3], DEFAULT_COLOR),
+            6 => (default_radius, Vec3::new(values[3], values[4], values[5])),
+            7 => (values[3], Vec3::new(values[4], values[5], values[6])),
+            count => {
+                warnings.push(format!(
+                    "line {}: expected 3, 4, 6 or 7 fields, found {}",
+                    line_number, count
+                ));
+                continue;
+            }
+        };
+
+        if radius <= 0.0 {
+            warnings.push(format!("line {}: radius must be positive", line_number));
+            continue;
+        }
+
+        spheres.push(Sphere::new(
+            Vec3::new(values[0], values[1], values[2]),
+            radius,
+            Material::lambertian(color),
+        ));
+    }
+
+    (spheres, warnings)
+}
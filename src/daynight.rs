@@ -0,0 +1,159 @@
+use crate::math::Vec3;
+use crate::scene::{Light, Scene};
+
+/// One point of the day/night cycle. `phase` runs from 0 (midnight) to 1.
+#[derive(Clone, Copy, Debug)]
+pub struct DayNightKeyframe {
+    pub phase: f32,
+    pub sky: Vec3,
+    pub sun_color: Vec3,
+    pub sun_intensity: f32,
+    pub ambient: f32,
+}
+
+pub const KEYFRAMES: [DayNightKeyframe; 5] = [
+    // Midnight: dim bluish moonlight
+    DayNightKeyframe {
+        phase: 0.0,
+        sky: Vec3::new(0.02, 0.03, 0.08),
+        sun_color: Vec3::new(0.6, 0.7, 1.0),
+        sun_intensity: 10.0,
+        ambient: 0.02,
+    },
+    // Dawn: warm, low sun
+    DayNightKeyframe {
+        phase: 0.25,
+        sky: Vec3::new(0.9, 0.55, 0.4),
+        sun_color: Vec3::new(1.0, 0.6, 0.35),
+        sun_intensity: 120.0,
+        ambient: 0.06,
+    },
+    // Noon: matches the default scene
+    DayNightKeyframe {
+        phase: 0.5,
+        sky: Vec3::new(0.5, 0.7, 1.0),
+        sun_color: Vec3::new(1.0, 1.0, 0.9),
+        sun_intensity: 200.0,
+        ambient: 0.1,
+    },
+    // Dusk
+    DayNightKeyframe {
+        phase: 0.75,
+        sky: Vec3::new(0.85, 0.45, 0.35),
+        sun_color: Vec3::new(1.0, 0.5, 0.25),
+        sun_intensity: 100.0,
+        ambient: 0.05,
+    },
+    DayNightKeyframe {
+        phase: 1.0,
+        sky: Vec3::new(0.02, 0.03, 0.08),
+        sun_color: Vec3::new(0.6, 0.7, 1.0),
+        sun_intensity: 10.0,
+        ambient: 0.02,
+    },
+];
+
+/// Distance of the sun light from the origin along its arc.
+const SUN_DISTANCE: f32 = 15.0;
+
+/// Interpolates the keyframes at `phase` (wrapped into [0, 1)).
+pub fn sample(phase: f32) -> DayNightKeyframe {
+    let phase = phase.rem_euclid(1.0);
+    let next = KEYFRAMES
+        .iter()
+        .position(|keyframe| keyframe.phase > phase)
+        .unwrap_or(KEYFRAMES.len() - 1);
+    let a = KEYFRAMES[next - 1];
+    let b = KEYFRAMES[next];
+    let t = (phase - a.phase) / (b.phase - a.phase);
+
+    DayNightKeyframe {
+        phase,
+        sky: a.sky + (b.sky - a.sky) * t,
+        sun_color: a.sun_color + (b.sun_color - a.sun_color) * t,
+        sun_intensity: a.sun_intensity + (b.sun_intensity - a.sun_intensity) * t,
+        ambient: a.ambient + (b.ambient - a.ambient) * t,
+    }
+}
+
+/// Position of the sun at `phase`: rises in +X at dawn, overhead at noon and
+/// sets in -X at dusk, staying below the horizon at night.
+pub fn sun_position(phase: f32) -> Vec3 {
+    let angle = (phase - 0.25) * std::f32::consts::TAU;
+    Vec3::new(
+        angle.cos() * SUN_DISTANCE,
+        angle.sin() * SUN_DISTANCE,
+        SUN_DISTANCE * 0.3,
+    )
+}
+
+/// Drives the scene background, a designated sun light and the ambient level.
+pub struct DayNightCycle {
+    duration_ms: f64,
+    start_time: f64,
+    sun_index: usize,
+    saved_background: Vec3,
+    // `None` when the sun light was created by the cycle
+    saved_sun: Option<Light>,
+    saved_ambient: f32,
+}
+
+impl DayNightCycle {
+    /// Starts a cycle at `now`, using the first light as the sun (or adding one).
+    pub fn start(scene: &mut Scene, ambient: f32, duration_s: f64, now: f64) -> Self {
+        let saved_sun = scene.lights.first().cloned();
+        if saved_sun.is_none() {
+            scene.add_light(Light::new(sun_position(0.5), Vec3::one(), 200.0));
+        }
+
+        Self {
+            duration_ms: duration_s.max(0.001) * 1000.0,
+            start_time: now,
+            sun_index: 0,
+            saved_background: scene.background_color,
+            saved_sun,
+            saved_ambient: ambient,
+        }
+    }
+
+    pub fn set_duration(&mut self, duration_s: f64, now: f64) {
+        // Keep the current phase when the speed changes
+        let phase = self.phase(now);
+        self.duration_ms = duration_s.max(0.001) * 1000.0;
+        self.start_time = now - phase as f64 * self.duration_ms;
+    }
+
+    pub fn phase(&self, now: f64) -> f32 {
+        ((now - self.start_time) / self.duration_ms).rem_euclid(1.0) as f32
+    }
+
+    pub fn apply(&self, scene: &mut Scene, ambient: &mut f32, now: f64) {
+        let phase = self.phase(now);
+        let state = sample(phase);
+
+        scene.background_color = state.sky;
+        *ambient = state.ambient;
+        if let Some(sun) = scene.lights.get_mut(self.sun_index) {
+            sun.position = sun_position(phase);
+            sun.color = state.sun_color;
+            sun.intensity = state.sun_intensity;
+        }
+    }
+
+    /// Restores the background, ambient level and sun light captured at start.
+    pub fn restore(self, scene: &mut Scene, ambient: &mut f32) {
+        scene.background_color = self.saved_background;
+        *ambient = self.saved_ambient;
+
+        match self.saved_sun {
+            Some(sun) => {
+                if let Some(light) = scene.lights.get_mut(self.sun_index) {
+                    *light = sun;
+                }
+            }
+            None => {
+                scene.remove_light(self.sun_index);
+            }
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! The error type returned by the JavaScript API.
+//!
+//! Across the wasm boundary an error becomes a JS `Error` with a stable
+//! `code` (e.g. `"scene_parse"`) and a `details` object holding the
+//! variant's fields, so callers can switch on `error.code` instead of
+//! matching message text.
+
+use std::fmt;
+
+use serde_json::{Value, json};
+#[cfg(feature = "webgl")]
+use wasm_bindgen::prelude::*;
+
+#[cfg(feature = "webgl")]
+use crate::axes::AxesError;
+use crate::scene::{SceneError, SceneProblem};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RaytracerError {
+    /// The canvas or its WebGL context could not be set up
+    ContextCreation {
+        reason: String,
+    },
+    ShaderCompile {
+        log: String,
+    },
+    ProgramLink {
+        log: String,
+    },
+    /// Scene, mesh or options data that could not be read; `path` locates
+    /// the problem in the document when known and is empty otherwise
+    SceneParse {
+        path: String,
+        message: String,
+    },
+    /// Scene data that parsed but holds values the renderer can't draw, each
+    /// listed with where it is
+    InvalidScene {
+        problems: Vec<SceneProblem>,
+    },
+    InvalidArgument {
+        name: &'static str,
+        reason: String,
+    },
+    IndexOutOfRange {
+        kind: &'static str,
+        index: usize,
+        len: usize,
+    },
+    /// The object exists but is locked against edits
+    Locked {
+        kind: &'static str,
+        index: usize,
+    },
+    Unsupported {
+        feature: String,
+    },
+    /// A WebGL or other browser call failed
+    Graphics {
+        message: String,
+    },
+}
+
+impl RaytracerError {
+    pub fn invalid_argument(name: &'static str, reason: impl Into<String>) -> Self {
+        Self::InvalidArgument {
+            name,
+            reason: reason.into(),
+        }
+    }
+
+    pub fn scene_parse(path: impl Into<String>, message: impl fmt::Display) -> Self {
+        Self::SceneParse {
+            path: path.into(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn graphics(message: impl Into<String>) -> Self {
+        Self::Graphics {
+            message: message.into(),
+        }
+    }
+
+    /// Stable identifier exposed to JavaScript as `error.code`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::ContextCreation { .. } => "context_creation",
+            Self::ShaderCompile { .. } => "shader_compile",
+            Self::ProgramLink { .. } => "program_link",
+            Self::SceneParse { .. } => "scene_parse",
+            Self::InvalidScene { .. } => "invalid_scene",
+            Self::InvalidArgument { .. } => "invalid_argument",
+            Self::IndexOutOfRange { .. } => "index_out_of_range",
+            Self::Locked { .. } => "locked",
+            Self::Unsupported { .. } => "unsupported",
+            Self::Graphics { .. } => "graphics",
+        }
+    }
+
+    /// The variant's fields, exposed to JavaScript as `error.details`.
+    pub fn details(&self) -> Value {
+        match self {
+            Self::ContextCreation { reason } => json!({ "reason": reason }),
+            Self::ShaderCompile { log } | Self::ProgramLink { log } => json!({ "log": log }),
+            Self::SceneParse { path, message } => json!({ "path": path, "message": message }),
+            Self::InvalidScene { problems } => json!({ "problems": problems }),
+            Self::InvalidArgument { name, reason } => json!({ "name": name, "reason": reason }),
+            Self::IndexOutOfRange { kind, index, len } => {
+                json!({ "kind": kind, "index": index, "len": len })
+            }
+            Self::Locked { kind, index } => json!({ "kind": kind, "index": index }),
+            Self::Unsupported { feature } => json!({ "feature": feature }),
+            Self::Graphics { message } => json!({ "message": message }),
+        }
+    }
+}
+
+impl fmt::Display for RaytracerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ContextCreation { reason } => {
+                write!(f, "Failed to create the WebGL context: {}", reason)
+            }
+            Self::ShaderCompile { log } => write!(f, "Shader failed to compile: {}", log),
+            Self::ProgramLink { log } => write!(f, "Shader program failed to link: {}", log),
+            Self::SceneParse { path, message } if path.is_empty() => f.write_str(message),
+            Self::SceneParse { path, message } => write!(f, "{}: {}", path, message),
+            Self::InvalidScene { problems } => {
+                write!(f, "The scene has {} invalid values:", problems.len())?;
+                for problem in problems {
+                    write!(f, "\n{}", problem)?;
+                }
+                Ok(())
+            }
+            Self::InvalidArgument { name, reason } => write!(f, "Invalid {}: {}", name, reason),
+            Self::IndexOutOfRange { kind, index, len } => {
+                write!(f, "No {} at index {} (there are {})", kind, index, len)
+            }
+            Self::Locked { kind, index } => write!(f, "The {} at index {} is locked", kind, index),
+            Self::Unsupported { feature } => write!(f, "Not supported: {}", feature),
+            Self::Graphics { message } => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for RaytracerError {}
+
+impl From<SceneError> for RaytracerError {
+    fn from(error: SceneError) -> Self {
+        Self::scene_parse("", error)
+    }
+}
+
+#[cfg(feature = "webgl")]
+impl From<AxesError> for RaytracerError {
+    fn from(error: AxesError) -> Self {
+        Self::invalid_argument("axes", error.to_string())
+    }
+}
+
+/// Exceptions thrown by web-sys calls.
+#[cfg(feature = "webgl")]
+impl From<JsValue> for RaytracerError {
+    fn from(value: JsValue) -> Self {
+        let message = value
+            .as_string()
+            .or_else(|| {
+                value
+                    .dyn_ref::<js_sys::Error>()
+                    .map(|error| String::from(error.message()))
+            })
+            .unwrap_or_else(|| format!("{:?}", value));
+        Self::graphics(message)
+    }
+}
+
+#[cfg(feature = "webgl")]
+impl From<RaytracerError> for JsValue {
+    fn from(error: RaytracerError) -> Self {
+        let js_error = js_sys::Error::new(&error.to_string());
+        let details = js_sys::JSON::parse(&error.details().to_string()).unwrap_or(JsValue::NULL);
+        // Setting properties on a fresh Error object can't fail
+        let _ = js_sys::Reflect::set(&js_error, &"code".into(), &error.code().into());
+        let _ = js_sys::Reflect::set(&js_error, &"details".into(), &details);
+        js_error.into()
+    }
+}
@@ -0,0 +1,47 @@
+//! Automatic exposure (eye adaptation).
+//!
+//! A tiny metering render gives the log2 luminance of a sparse grid of
+//! pixels before exposure. Their average picks the exposure that maps the
+//! scene to middle grey, and the exposure in force eases toward it.
+
+/// Side of the metering render in pixels.
+pub const METER_SIZE: u32 = 16;
+/// Scene luminance the log-average is mapped to.
+const MIDDLE_GREY: f32 = 0.18;
+
+/// Adaptation settings: `speed` is the rate (per second) at which the gap to
+/// the target closes, and the exposure stays within `min_ev..=max_ev` stops.
+#[derive(Clone, Copy, Debug)]
+pub struct AutoExposure {
+    pub speed: f32,
+    pub min_ev: f32,
+    pub max_ev: f32,
+}
+
+impl AutoExposure {
+    pub fn new(speed: f32, min_ev: f32, max_ev: f32) -> Self {
+        Self {
+            speed: speed.max(0.0),
+            min_ev: min_ev.min(max_ev),
+            max_ev: max_ev.max(min_ev),
+        }
+    }
+
+    /// Exposure in stops that brings the log-average of `log_luminances`
+    /// (log2 values) to middle grey, or `None` without samples.
+    pub fn target_ev(&self, log_luminances: &[f32]) -> Option<f32> {
+        if log_luminances.is_empty() {
+            return None;
+        }
+        let average = log_luminances.iter().sum::<f32>() / log_luminances.len() as f32;
+        Some((MIDDLE_GREY.log2() - average).clamp(self.min_ev, self.max_ev))
+    }
+
+    /// Moves `current_ev` toward `target_ev` over `delta_s` seconds. The
+    /// step is exponential in time, so it doesn't depend on the frame rate.
+    pub fn adapt(&self, current_ev: f32, target_ev: f32, delta_s: f32) -> f32 {
+        let blend = 1.0 - (-self.speed * delta_s.max(0.0)).exp();
+        let ev = current_ev + (target_ev - current_ev) * blend;
+        ev.clamp(self.min_ev, self.max_ev)
+    }
+}
@@ -0,0 +1,120 @@
+//! Decoding of the G-buffer passes written by the fragment shader.
+//!
+//! Both passes are rendered into RGBA8 targets (float render targets are not
+//! guaranteed on WebGL1), with alpha 1 where the primary ray hit something and
+//! 0 where it escaped:
+//! - depth: view-space distance along the camera's forward axis divided by
+//!   the far distance (`GBUFFER_FAR` meters in scene units), packed base 255
+//!   across RGB with the most significant byte in R. Decodes to world units;
+//!   misses decode to `f32::INFINITY`.
+//! - normals: outward world-space normal mapped to RGB as `n * 0.5 + 0.5`.
+//!   Decodes to three floats in [-1, 1] per pixel; misses decode to zero.
+//!
+//! The exposure meter uses the same targets: log2 of the pixel's luminance
+//! before exposure, mapped from `LOG_LUMINANCE_MIN..LOG_LUMINANCE_MAX` to
+//! [0, 1] and packed like depth. Every pixel counts, sky included.
+//!
+//! The segmentation pass writes each object's id as a flat, opaque color:
+//! the id is a 24-bit number packed into RGB with the most significant byte
+//! in R. Objects use their `ObjectType::object_id`; the instanced sphere grid
+//! is one segment, `GRID_SEGMENT_ID`. Background pixels are black (id 0).
+
+use std::collections::BTreeMap;
+
+use crate::math::Vec3;
+use crate::scene::{ObjectType, Scene};
+
+/// Where rays end, in meters. Must match `GBUFFER_FAR` in the fragment shader.
+pub const GBUFFER_FAR: f32 = 100.0;
+
+/// `u_output_mode` values.
+pub const OUTPUT_COLOR: i32 = 0;
+pub const OUTPUT_DEPTH: i32 = 1;
+pub const OUTPUT_NORMALS: i32 = 2;
+pub const OUTPUT_LUMINANCE: i32 = 3;
+pub const OUTPUT_SEGMENTATION: i32 = 4;
+
+/// Segmentation id of every instance of the instanced sphere grid. Must match
+/// `hitWorld` in the fragment shader.
+pub const GRID_SEGMENT_ID: u32 = 0xff_0000;
+
+/// Must match the constants of the same name in the fragment shader.
+pub const LOG_LUMINANCE_MIN: f32 = -16.0;
+pub const LOG_LUMINANCE_MAX: f32 = 16.0;
+
+/// `far` is `GBUFFER_FAR` converted to scene units.
+pub fn decode_depth(rgba: &[u8], far: f32) -> Vec<f32> {
+    rgba.chunks_exact(4)
+        .map(|p| {
+            if p[3] == 0 {
+                return f32::INFINITY;
+            }
+            let [r, g, b] = [p[0], p[1], p[2]].map(|c| c as f32 / 255.0);
+            (r + g / 255.0 + b / 65025.0) * far
+        })
+        .collect()
+}
+
+pub fn decode_log_luminance(rgba: &[u8]) -> Vec<f32> {
+    rgba.chunks_exact(4)
+        .map(|p| {
+            let [r, g, b] = [p[0], p[1], p[2]].map(|c| c as f32 / 255.0);
+            let unit = r + g / 255.0 + b / 65025.0;
+            LOG_LUMINANCE_MIN + unit * (LOG_LUMINANCE_MAX - LOG_LUMINANCE_MIN)
+        })
+        .collect()
+}
+
+pub fn decode_normals(rgba: &[u8]) -> Vec<f32> {
+    let mut normals = Vec::with_capacity(rgba.len() / 4 * 3);
+    for p in rgba.chunks_exact(4) {
+        if p[3] == 0 {
+            normals.extend_from_slice(&[0.0, 0.0, 0.0]);
+        } else {
+            normals.extend(p[..3].iter().map(|&c| c as f32 / 255.0 * 2.0 - 1.0));
+        }
+    }
+    normals
+}
+
+pub fn segmentation_color(id: u32) -> [u8; 3] {
+    [(id >> 16) as u8, (id >> 8) as u8, id as u8]
+}
+
+/// Ids and colors of every segment the pass can produce for `scene` seen
+/// from `camera`; objects past the shader's caps (see
+/// `ObjectType::capacity`) aren't drawn and so aren't listed.
+pub fn segmentation_palette(
+    scene: &Scene,
+    camera: Option<Vec3>,
+    data_texture: bool,
+) -> BTreeMap<u32, [u8; 3]> {
+    let mut palette = BTreeMap::new();
+    for object_type in ObjectType::ALL {
+        let max = object_type.capacity(data_texture);
+        for index in scene.upload_order(object_type, camera, max) {
+            let id = object_type.object_id(index);
+            palette.insert(id, segmentation_color(id));
+        }
+    }
+    // Combined objects keep their own ids
+    for csg in scene.drawn_csg() {
+        for operand in [csg.a, csg.b] {
+            let id = operand.kind.object_id(operand.index);
+            palette.insert(id, segmentation_color(id));
+        }
+    }
+    if scene.instanced_grid.is_some() {
+        palette.insert(GRID_SEGMENT_ID, segmentation_color(GRID_SEGMENT_ID));
+    }
+    palette
+}
+
+/// Reorders RGBA rows from GL's bottom-up order to top-down.
+pub fn flip_rows(rgba: &[u8], width: u32) -> Vec<u8> {
+    rgba.chunks(width as usize * 4)
+        .rev()
+        .flatten()
+        .copied()
+        .collect()
+}
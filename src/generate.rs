@@ -0,0 +1,285 @@
+use crate::color;
+use crate::error::RaytracerError;
+use crate::material::{Material, MaterialType};
+use crate::math::Vec3;
+use crate::scene::Sphere;
+
+/// Material parameter swept across a generated grid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GridRamp {
+    /// Metal, roughness 0 to 1
+    Roughness,
+    /// Dielectric, IOR 1.0 to 2.5
+    Ior,
+    /// Lambertian, full hue circle
+    Hue,
+}
+
+impl GridRamp {
+    pub fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(GridRamp::Roughness),
+            1 => Some(GridRamp::Ior),
+            2 => Some(GridRamp::Hue),
+            _ => None,
+        }
+    }
+
+    fn material(self, t: f32) -> Material {
+        match self {
+            GridRamp::Roughness => {
+                Material::new(MaterialType::Metal, Vec3::new(0.8, 0.8, 0.85), t, 0.0)
+            }
+            GridRamp::Ior => Material::new(
+                MaterialType::Dielectric,
+                Vec3::new(1.0, 1.0, 1.0),
+                0.0,
+                1.0 + 1.5 * t,
+            ),
+            GridRamp::Hue => {
+                // Stop short of 1.0 so the last sphere doesn't repeat the first hue
+                let (r, g, b) = color::hsv_to_rgb(t * 0.9, 0.75, 0.9);
+                Material::new(MaterialType::Lambertian, Vec3::new(r, g, b), 0.0, 0.0)
+            }
+        }
+    }
+}
+
+/// How many spheres a grid with these dimensions holds. Fails if a
+/// dimension is zero or the count doesn't fit in a `usize`, which is only
+/// 32 bits on wasm32.
+pub fn grid_count(dimensions: &[u32]) -> Result<usize, RaytracerError> {
+    if dimensions.contains(&0) {
+        return Err(RaytracerError::invalid_argument(
+            "grid",
+            "a grid needs at least one sphere",
+        ));
+    }
+    dimensions
+        .iter()
+        .try_fold(1usize, |count, &n| count.checked_mul(n as usize))
+        .ok_or_else(|| {
+            let sizes: Vec<String> = dimensions.iter().map(u32::to_string).collect();
+            RaytracerError::invalid_argument(
+                "grid",
+                format!("a {} grid has too many spheres", sizes.join("x")),
+            )
+        })
+}
+
+/// Fails unless `radius` is positive and both it and `spacing` are finite.
+fn check_spacing(spacing: f32, radius: f32) -> Result<(), RaytracerError> {
+    if radius > 0.0 && radius.is_finite() && spacing.is_finite() {
+        Ok(())
+    } else {
+        Err(RaytracerError::invalid_argument(
+            "grid",
+            "the radius must be positive and the spacing finite",
+        ))
+    }
+}
+
+/// Lays out `nx` x `ny` spheres on the XZ plane, centered on the origin and
+/// resting on `ground_y`. The ramp runs over the spheres in row-major order.
+pub fn sphere_grid(
+    nx: u32,
+    ny: u32,
+    spacing: f32,
+    radius: f32,
+    ground_y: f32,
+    ramp: GridRamp,
+) -> Result<Vec<Sphere>, RaytracerError> {
+    let count = grid_count(&[nx, ny])?;
+    check_spacing(spacing, radius)?;
+    let half_x = (nx as f32 - 1.0) * spacing * 0.5;
+    let half_z = (ny as f32 - 1.0) * spacing * 0.5;
+
+    let mut spheres = Vec::with_capacity(count);
+    for row in 0..ny {
+        for column in 0..nx {
+            let index = row as usize * nx as usize + column as usize;
+            let t = if count > 1 {
+                index as f32 / (count - 1) as f32
+            } else {
+                0.0
+            };
+
+            let center = Vec3::new(
+                column as f32 * spacing - half_x,
+                ground_y + radius,
+                row as f32 * spacing - half_z,
+            );
+            spheres.push(Sphere::new(center, radius, ramp.material(t)));
+        }
+    }
+    Ok(spheres)
+}
+
+/// Materials `sphere_block` and `sphere_field` pick from when no material is
+/// given: a few diffuse colors, two metals and glass.
+fn palette_material(index: usize) -> Material {
+    match index % 6 {
+        0 => Material::lambertian(Vec3::new(0.8, 0.3, 0.3)),
+        1 => Material::lambertian(Vec3::new(0.3, 0.45, 0.8)),
+        2 => Material::lambertian(Vec3::new(0.9, 0.8, 0.35)),
+        3 => Material::new(MaterialType::Metal, Vec3::new(0.9, 0.75, 0.4), 0.2, 0.0),
+        4 => Material::new(MaterialType::Metal, Vec3::new(0.9, 0.9, 0.92), 0.05, 0.0),
+        _ => Material::new(MaterialType::Dielectric, Vec3::one(), 0.0, 1.5),
+    }
+}
+
+/// SplitMix64, so a seed gives the same spheres on every platform.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u32) -> Self {
+        Self(u64::from(seed))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[min, max)`.
+    fn range(&mut self, min: f32, max: f32) -> f32 {
+        let t = (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32;
+        min + (max - min) * t
+    }
+
+    fn material(&mut self) -> Material {
+        palette_material((self.next_u64() >> 32) as usize)
+    }
+}
+
+/// The first `limit` spheres of an `nx` x `ny` x `nz` block, centered on the
+/// origin in X and Z with its bottom layer resting on `ground_y`, in X, then
+/// Z, then Y order. Without a `material` each sphere gets one from the
+/// palette, the same ones on every call. Fails like `sphere_grid` on an
+/// empty or overflowing block or a bad spacing or radius.
+pub fn sphere_block(
+    [nx, ny, nz]: [u32; 3],
+    spacing: f32,
+    radius: f32,
+    ground_y: f32,
+    material: Option<Material>,
+    limit: usize,
+) -> Result<Vec<Sphere>, RaytracerError> {
+    let count = grid_count(&[nx, ny, nz])?.min(limit);
+    check_spacing(spacing, radius)?;
+    let half_x = (nx as f32 - 1.0) * spacing * 0.5;
+    let half_z = (nz as f32 - 1.0) * spacing * 0.5;
+    let mut rng = Rng::new(0);
+
+    let (nx, nz) = (nx as usize, nz as usize);
+    let spheres = (0..count)
+        .map(|index| {
+            let (column, row, layer) = (index % nx, index / nx % nz, index / (nx * nz));
+            let center = Vec3::new(
+                column as f32 * spacing - half_x,
+                ground_y + radius + layer as f32 * spacing,
+                row as f32 * spacing - half_z,
+            );
+            let material = material.unwrap_or_else(|| rng.material());
+            Sphere::new(center, radius, material)
+        })
+        .collect();
+    Ok(spheres)
+}
+
+/// `count` spheres with centers uniformly scattered between `min` and `max`
+/// and radii between `radius_min` and `radius_max`; they may overlap. A
+/// `seed` always gives the same spheres, and a smaller `count` the first of
+/// them. Without a `material` each sphere gets a random one from the
+/// palette.
+pub fn sphere_field(
+    count: usize,
+    min: Vec3,
+    max: Vec3,
+    radius_min: f32,
+    radius_max: f32,
+    material: Option<Material>,
+    seed: u32,
+) -> Vec<Sphere> {
+    let mut rng = Rng::new(seed);
+    (0..count)
+        .map(|_| {
+            let center = Vec3::new(
+                rng.range(min.x, max.x),
+                rng.range(min.y, max.y),
+                rng.range(min.z, max.z),
+            );
+            let radius = rng.range(radius_min, radius_max);
+            let palette = rng.material();
+            Sphere::new(center, radius, material.unwrap_or(palette))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_count_rejects_empty_and_overflowing_grids() {
+        assert_eq!(grid_count(&[3, 4]), Ok(12));
+        assert_eq!(grid_count(&[2, 3, 4]), Ok(24));
+        assert!(grid_count(&[0, 4]).is_err());
+        assert!(grid_count(&[4, 4, 0]).is_err());
+        // 2^96 spheres overflow even a 64-bit usize
+        assert!(grid_count(&[u32::MAX, u32::MAX, u32::MAX]).is_err());
+        if usize::BITS == 32 {
+            // Used to wrap to 0 and pass the capacity check
+            assert!(grid_count(&[65536, 65536]).is_err());
+        }
+    }
+
+    #[test]
+    fn sphere_grid_rejects_bad_spacing_and_radius() {
+        let ramp = GridRamp::Hue;
+        assert_eq!(sphere_grid(3, 2, 1.0, 0.4, 0.0, ramp).unwrap().len(), 6);
+        assert!(sphere_grid(3, 2, f32::NAN, 0.4, 0.0, ramp).is_err());
+        assert!(sphere_grid(3, 2, f32::INFINITY, 0.4, 0.0, ramp).is_err());
+        assert!(sphere_grid(3, 2, 1.0, 0.0, 0.0, ramp).is_err());
+        assert!(sphere_grid(0, 2, 1.0, 0.4, 0.0, ramp).is_err());
+    }
+
+    #[test]
+    fn sphere_block_stops_at_the_limit() {
+        let spheres = sphere_block([3, 2, 4], 1.0, 0.25, 0.0, None, 100).unwrap();
+        assert_eq!(spheres.len(), 24);
+        // X first, then Z, then up a layer
+        assert_eq!(spheres[1].center, Vec3::new(0.0, 0.25, -1.5));
+        assert_eq!(spheres[3].center, Vec3::new(-1.0, 0.25, -0.5));
+        assert_eq!(spheres[12].center, Vec3::new(-1.0, 1.25, -1.5));
+
+        let first = sphere_block([3, 2, 4], 1.0, 0.25, 0.0, None, 5).unwrap();
+        assert_eq!(first.len(), 5);
+        assert!(
+            first
+                .iter()
+                .zip(&spheres)
+                .all(|(a, b)| a.center == b.center)
+        );
+    }
+
+    #[test]
+    fn sphere_block_rejects_empty_and_huge_blocks() {
+        // An empty row used to spin through every layer and row
+        assert!(sphere_block([0, u32::MAX, u32::MAX], 1.0, 0.5, 0.0, None, 10).is_err());
+        assert!(sphere_block([u32::MAX; 3], 1.0, 0.5, 0.0, None, 10).is_err());
+        assert!(sphere_block([2, 2, 2], f32::NAN, 0.5, 0.0, None, 10).is_err());
+    }
+
+    #[test]
+    fn sphere_grid_is_centered_and_ramped() {
+        let spheres = sphere_grid(3, 2, 2.0, 0.5, 1.0, GridRamp::Roughness).unwrap();
+        assert_eq!(spheres[0].center, Vec3::new(-2.0, 1.5, -1.0));
+        assert_eq!(spheres[5].center, Vec3::new(2.0, 1.5, 1.0));
+        assert_eq!(spheres[0].material.roughness, 0.0);
+        assert_eq!(spheres[5].material.roughness, 1.0);
+    }
+}
@@ -0,0 +1,793 @@
+//! glTF 2.0 import, either a `.gltf` document with its buffers alongside or
+//! a binary `.glb`.
+//!
+//! Mesh primitives become triangles placed by their node transforms, and
+//! point lights come from `KHR_lights_punctual`. Materials keep only the
+//! PBR base color and metallic factors: mostly metallic ones become metal,
+//! the rest Lambertian. What the renderer can't show (skins, animations,
+//! textures, cameras, ...) is skipped with a warning instead of failing the
+//! import. glTF is right-handed with +Y up like the renderer, so positions
+//! are used unchanged.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::material::Material;
+use crate::math::{Mat4, Vec3};
+use crate::scene::{Light, Scene, SceneError, Triangle};
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_JSON_CHUNK: u32 = 0x4E4F_534A;
+const GLB_BIN_CHUNK: u32 = 0x004E_4942;
+
+const FLOAT: u32 = 5126;
+const UNSIGNED_BYTE: u32 = 5121;
+const UNSIGNED_SHORT: u32 = 5123;
+const UNSIGNED_INT: u32 = 5125;
+
+const TRIANGLES: u32 = 4;
+const TRIANGLE_STRIP: u32 = 5;
+const TRIANGLE_FAN: u32 = 6;
+
+/// Extensions whose absence from this importer doesn't change the geometry.
+const KNOWN_EXTENSIONS: &[&str] = &["KHR_lights_punctual", "KHR_materials_emissive_strength"];
+
+/// The imported scene and what was left out of it.
+pub struct GltfImport {
+    pub scene: Scene,
+    pub warnings: Vec<String>,
+}
+
+/// Imports a `.gltf` document. `bin` stands in for the first buffer when it
+/// names an external file (normally the `.bin` next to the document); buffers
+/// embedded as data URIs need nothing.
+pub fn import(json: &str, bin: &[u8]) -> Result<GltfImport, SceneError> {
+    let document: Document = serde_json::from_str(json)
+        .map_err(|e| SceneError::new(format!("Failed to parse glTF: {}", e)))?;
+    Importer::new(document, Some(bin), false)?.run()
+}
+
+/// Imports a binary `.glb`, whose first buffer is its BIN chunk.
+pub fn import_glb(bytes: &[u8]) -> Result<GltfImport, SceneError> {
+    if bytes.len() < 12 || &bytes[0..4] != GLB_MAGIC {
+        return Err(SceneError::new(
+            "Not a GLB file: the data doesn't start with glTF",
+        ));
+    }
+    let version = read_u32(bytes, 4);
+    if version != 2 {
+        return Err(SceneError::new(format!(
+            "GLB version {} isn't supported, only version 2",
+            version
+        )));
+    }
+    let length = (read_u32(bytes, 8) as usize).min(bytes.len());
+
+    let mut json = None;
+    let mut bin = None;
+    let mut offset = 12;
+    while offset + 8 <= length {
+        let chunk_length = read_u32(bytes, offset) as usize;
+        let chunk_type = read_u32(bytes, offset + 4);
+        let start = offset + 8;
+        let Some(end) = start.checked_add(chunk_length).filter(|&end| end <= length) else {
+            return Err(SceneError::new(format!(
+                "The GLB file is truncated: a chunk at byte {} needs {} bytes",
+                offset, chunk_length
+            )));
+        };
+        match chunk_type {
+            GLB_JSON_CHUNK if json.is_none() => json = Some(&bytes[start..end]),
+            GLB_BIN_CHUNK if bin.is_none() => bin = Some(&bytes[start..end]),
+            // Unknown chunks are to be ignored
+            _ => {}
+        }
+        // Chunks are padded to four bytes
+        offset = end.next_multiple_of(4);
+    }
+
+    let json = json.ok_or_else(|| SceneError::new("The GLB file has no JSON chunk"))?;
+    let json = std::str::from_utf8(json)
+        .map_err(|_| SceneError::new("The GLB file's JSON chunk isn't UTF-8"))?;
+    let document: Document = serde_json::from_str(json)
+        .map_err(|e| SceneError::new(format!("Failed to parse glTF: {}", e)))?;
+    Importer::new(document, bin, true)?.run()
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Document {
+    asset: Asset,
+    scene: Option<usize>,
+    #[serde(default)]
+    scenes: Vec<SceneDef>,
+    #[serde(default)]
+    nodes: Vec<Node>,
+    #[serde(default)]
+    meshes: Vec<MeshDef>,
+    #[serde(default)]
+    materials: Vec<MaterialDef>,
+    #[serde(default)]
+    accessors: Vec<Accessor>,
+    #[serde(default)]
+    buffer_views: Vec<BufferView>,
+    #[serde(default)]
+    buffers: Vec<Buffer>,
+    #[serde(default)]
+    skins: Vec<Value>,
+    #[serde(default)]
+    animations: Vec<Value>,
+    #[serde(default)]
+    cameras: Vec<Value>,
+    #[serde(default)]
+    extensions: DocumentExtensions,
+    #[serde(default)]
+    extensions_required: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Asset {
+    version: String,
+    min_version: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct SceneDef {
+    #[serde(default)]
+    nodes: Vec<usize>,
+}
+
+#[derive(Default, Deserialize)]
+struct Node {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    children: Vec<usize>,
+    mesh: Option<usize>,
+    matrix: Option<[f32; 16]>,
+    translation: Option<[f32; 3]>,
+    rotation: Option<[f32; 4]>,
+    scale: Option<[f32; 3]>,
+    #[serde(default)]
+    extensions: NodeExtensions,
+}
+
+#[derive(Default, Deserialize)]
+struct NodeExtensions {
+    #[serde(rename = "KHR_lights_punctual")]
+    lights_punctual: Option<NodeLight>,
+}
+
+#[derive(Deserialize)]
+struct NodeLight {
+    light: usize,
+}
+
+#[derive(Default, Deserialize)]
+struct DocumentExtensions {
+    #[serde(rename = "KHR_lights_punctual")]
+    lights_punctual: Option<PunctualLights>,
+}
+
+#[derive(Deserialize)]
+struct PunctualLights {
+    #[serde(default)]
+    lights: Vec<PunctualLight>,
+}
+
+#[derive(Deserialize)]
+struct PunctualLight {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    color: Option<[f32; 3]>,
+    intensity: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct MeshDef {
+    #[serde(default)]
+    name: String,
+    primitives: Vec<Primitive>,
+}
+
+#[derive(Deserialize)]
+struct Primitive {
+    attributes: BTreeMap<String, usize>,
+    indices: Option<usize>,
+    material: Option<usize>,
+    mode: Option<u32>,
+    #[serde(default)]
+    extensions: BTreeMap<String, Value>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MaterialDef {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    pbr_metallic_roughness: Pbr,
+    alpha_mode: Option<String>,
+    normal_texture: Option<Value>,
+    occlusion_texture: Option<Value>,
+    emissive_texture: Option<Value>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Pbr {
+    base_color_factor: Option<[f32; 4]>,
+    metallic_factor: Option<f32>,
+    roughness_factor: Option<f32>,
+    base_color_texture: Option<Value>,
+    metallic_roughness_texture: Option<Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Accessor {
+    buffer_view: Option<usize>,
+    #[serde(default)]
+    byte_offset: usize,
+    component_type: u32,
+    count: usize,
+    #[serde(rename = "type")]
+    kind: String,
+    sparse: Option<Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BufferView {
+    buffer: usize,
+    #[serde(default)]
+    byte_offset: usize,
+    byte_length: usize,
+    byte_stride: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Buffer {
+    uri: Option<String>,
+}
+
+struct Importer<'a> {
+    document: Document,
+    // Each buffer's bytes, or why it isn't available
+    buffers: Vec<Result<Cow<'a, [u8]>, String>>,
+    // Converted lazily, as only used materials need warnings
+    materials: Vec<Option<Material>>,
+    scene: Scene,
+    warnings: Vec<String>,
+}
+
+impl<'a> Importer<'a> {
+    fn new(document: Document, bin: Option<&'a [u8]>, glb: bool) -> Result<Self, SceneError> {
+        let version = document
+            .asset
+            .min_version
+            .as_deref()
+            .unwrap_or(&document.asset.version);
+        if !version.starts_with("2.") {
+            return Err(SceneError::new(format!(
+                "glTF version {} isn't supported, only 2.x",
+                version
+            )));
+        }
+
+        let buffers = document
+            .buffers
+            .iter()
+            .enumerate()
+            .map(|(index, buffer)| match buffer.uri.as_deref() {
+                Some(uri) if uri.starts_with("data:") => decode_data_uri(uri)
+                    .map(Cow::Owned)
+                    .ok_or_else(|| format!("buffer {} has an unreadable data URI", index)),
+                // `bin` is the GLB's BIN chunk, or the file a .gltf's first
+                // buffer names
+                _ if index == 0 && (buffer.uri.is_none() || !glb) => bin
+                    .filter(|bin| !bin.is_empty())
+                    .map(Cow::Borrowed)
+                    .ok_or_else(|| format!("buffer {} was not provided", index)),
+                Some(uri) => Err(format!("buffer {} is the external file '{}'", index, uri)),
+                None => Err(format!("buffer {} has no data", index)),
+            })
+            .collect();
+
+        Ok(Self {
+            materials: vec![None; document.materials.len()],
+            document,
+            buffers,
+            scene: Scene::new(),
+            warnings: Vec::new(),
+        })
+    }
+
+    fn run(mut self) -> Result<GltfImport, SceneError> {
+        self.warn_unsupported();
+
+        let roots = match self.document.scenes.get(self.document.scene.unwrap_or(0)) {
+            Some(scene) => scene.nodes.clone(),
+            // Without scenes, draw every node that isn't another's child
+            None => {
+                let mut is_child = vec![false; self.document.nodes.len()];
+                for node in &self.document.nodes {
+                    for &child in &node.children {
+                        if let Some(flag) = is_child.get_mut(child) {
+                            *flag = true;
+                        }
+                    }
+                }
+                (0..is_child.len()).filter(|&i| !is_child[i]).collect()
+            }
+        };
+
+        let mut visited = vec![false; self.document.nodes.len()];
+        let mut stack: Vec<(usize, Mat4)> = roots
+            .into_iter()
+            .rev()
+            .map(|node| (node, Mat4::identity()))
+            .collect();
+        while let Some((index, parent)) = stack.pop() {
+            match visited.get_mut(index) {
+                Some(false) => visited[index] = true,
+                Some(true) => {
+                    self.warnings
+                        .push(format!("node {} is reached twice; imported once", index));
+                    continue;
+                }
+                None => {
+                    self.warnings
+                        .push(format!("node {} doesn't exist; skipped", index));
+                    continue;
+                }
+            }
+
+            let node = &self.document.nodes[index];
+            let world = parent * local_transform(node);
+            for &child in node.children.iter().rev() {
+                stack.push((child, world));
+            }
+            let light = node.extensions.lights_punctual.as_ref().map(|l| l.light);
+            if let Some(mesh) = node.mesh {
+                self.add_mesh(index, mesh, world);
+            }
+            if let Some(light) = light {
+                self.add_light(index, light, world);
+            }
+        }
+
+        self.scene.assign_ids();
+        Ok(GltfImport {
+            scene: self.scene,
+            warnings: self.warnings,
+        })
+    }
+
+    fn warn_unsupported(&mut self) {
+        let document = &self.document;
+        let warnings = &mut self.warnings;
+        for extension in &document.extensions_required {
+            if !KNOWN_EXTENSIONS.contains(&extension.as_str()) {
+                warnings.push(format!(
+                    "the file requires extension {}, which isn't supported; parts of it may be \
+                     missing",
+                    extension
+                ));
+            }
+        }
+        if !document.skins.is_empty() {
+            warnings.push(format!(
+                "{} skin(s) ignored; skinned meshes are imported as modeled",
+                document.skins.len()
+            ));
+        }
+        if !document.animations.is_empty() {
+            warnings.push(format!(
+                "{} animation(s) ignored",
+                document.animations.len()
+            ));
+        }
+        if !document.cameras.is_empty() {
+            warnings.push(format!("{} camera(s) ignored", document.cameras.len()));
+        }
+    }
+
+    fn add_mesh(&mut self, node_index: usize, mesh_index: usize, world: Mat4) {
+        let Some(mesh) = self.document.meshes.get(mesh_index) else {
+            self.warnings.push(format!(
+                "node {} uses mesh {}, which doesn't exist",
+                node_index, mesh_index
+            ));
+            return;
+        };
+        let name = if mesh.name.is_empty() {
+            &self.document.nodes[node_index].name
+        } else {
+            &mesh.name
+        }
+        .clone();
+
+        for primitive_index in 0..mesh.primitives.len() {
+            let primitive = &self.document.meshes[mesh_index].primitives[primitive_index];
+            let material = primitive.material;
+            let triangles = match self.primitive_triangles(primitive) {
+                Ok(triangles) => triangles,
+                Err(reason) => {
+                    self.warnings.push(format!(
+                        "mesh {} primitive {} skipped: {}",
+                        mesh_index, primitive_index, reason
+                    ));
+                    continue;
+                }
+            };
+            let material = self.material(material);
+
+            // A mirroring transform turns faces inside out unless the
+            // winding is flipped with it
+            let mirrored = determinant(&world) < 0.0;
+            for [v0, v1, v2] in triangles {
+                let [v0, v1, v2] = [v0, v1, v2].map(|v| transform(&world, v));
+                let (v1, v2) = if mirrored { (v2, v1) } else { (v1, v2) };
+                let mut triangle = Triangle::new(v0, v1, v2, material);
+                triangle.meta.name = name.clone();
+                self.scene.add_triangle(triangle);
+            }
+        }
+    }
+
+    /// The primitive's triangles in mesh space.
+    fn primitive_triangles(&self, primitive: &Primitive) -> Result<Vec<[Vec3; 3]>, String> {
+        if primitive
+            .extensions
+            .contains_key("KHR_draco_mesh_compression")
+        {
+            return Err("Draco compression isn't supported".to_string());
+        }
+        let mode = primitive.mode.unwrap_or(TRIANGLES);
+        if !matches!(mode, TRIANGLES | TRIANGLE_STRIP | TRIANGLE_FAN) {
+            return Err(format!("mode {} draws points or lines", mode));
+        }
+        let &position = primitive
+            .attributes
+            .get("POSITION")
+            .ok_or("it has no POSITION attribute")?;
+        let positions = self.read_positions(position)?;
+        let indices = match primitive.indices {
+            Some(accessor) => self.read_indices(accessor)?,
+            None => (0..positions.len() as u32).collect(),
+        };
+
+        let corners: Vec<[u32; 3]> = match mode {
+            TRIANGLES => indices
+                .chunks_exact(3)
+                .map(|c| [c[0], c[1], c[2]])
+                .collect(),
+            // Every other strip triangle is wound backwards
+            TRIANGLE_STRIP => indices
+                .windows(3)
+                .enumerate()
+                .map(|(i, w)| {
+                    if i % 2 == 0 {
+                        [w[0], w[1], w[2]]
+                    } else {
+                        [w[1], w[0], w[2]]
+                    }
+                })
+                .collect(),
+            _ => indices
+                .get(1..)
+                .unwrap_or_default()
+                .windows(2)
+                .map(|w| [indices[0], w[0], w[1]])
+                .collect(),
+        };
+        corners
+            .into_iter()
+            .map(|triangle| {
+                let vertex = |index: u32| {
+                    positions.get(index as usize).copied().ok_or_else(|| {
+                        format!("index {} is past its {} vertices", index, positions.len())
+                    })
+                };
+                Ok([
+                    vertex(triangle[0])?,
+                    vertex(triangle[1])?,
+                    vertex(triangle[2])?,
+                ])
+            })
+            .collect()
+    }
+
+    fn read_positions(&self, accessor: usize) -> Result<Vec<Vec3>, String> {
+        let view = self.accessor_view(accessor, "VEC3", &[FLOAT])?;
+        Ok((0..view.count)
+            .map(|i| {
+                let at = view.offset + i * view.stride;
+                Vec3::new(
+                    read_f32(view.data, at),
+                    read_f32(view.data, at + 4),
+                    read_f32(view.data, at + 8),
+                )
+            })
+            .collect())
+    }
+
+    fn read_indices(&self, accessor: usize) -> Result<Vec<u32>, String> {
+        let view = self.accessor_view(
+            accessor,
+            "SCALAR",
+            &[UNSIGNED_BYTE, UNSIGNED_SHORT, UNSIGNED_INT],
+        )?;
+        Ok((0..view.count)
+            .map(|i| {
+                let at = view.offset + i * view.stride;
+                match view.component_type {
+                    UNSIGNED_BYTE => view.data[at] as u32,
+                    UNSIGNED_SHORT => u16::from_le_bytes([view.data[at], view.data[at + 1]]) as u32,
+                    _ => read_u32(view.data, at),
+                }
+            })
+            .collect())
+    }
+
+    /// Where the elements of an accessor lie, checked to be inside its
+    /// buffer view and buffer.
+    fn accessor_view(
+        &self,
+        index: usize,
+        kind: &str,
+        component_types: &[u32],
+    ) -> Result<AccessorView<'_>, String> {
+        let accessor = self
+            .document
+            .accessors
+            .get(index)
+            .ok_or_else(|| format!("accessor {} doesn't exist", index))?;
+        if accessor.sparse.is_some() {
+            return Err(format!(
+                "accessor {} is sparse, which isn't supported",
+                index
+            ));
+        }
+        if accessor.kind != kind || !component_types.contains(&accessor.component_type) {
+            return Err(format!(
+                "accessor {} holds {} of component type {}",
+                index, accessor.kind, accessor.component_type
+            ));
+        }
+        let view_index = accessor
+            .buffer_view
+            .ok_or_else(|| format!("accessor {} has no buffer view", index))?;
+        let view = self
+            .document
+            .buffer_views
+            .get(view_index)
+            .ok_or_else(|| format!("buffer view {} doesn't exist", view_index))?;
+        let data = match self.buffers.get(view.buffer) {
+            Some(Ok(data)) => data.as_ref(),
+            Some(Err(reason)) => return Err(reason.clone()),
+            None => return Err(format!("buffer {} doesn't exist", view.buffer)),
+        };
+
+        let component_size = match accessor.component_type {
+            UNSIGNED_BYTE => 1,
+            UNSIGNED_SHORT => 2,
+            _ => 4,
+        };
+        let element_size = component_size * if kind == "VEC3" { 3 } else { 1 };
+        let stride = view.byte_stride.unwrap_or(element_size).max(element_size);
+        let offset = view.byte_offset.checked_add(accessor.byte_offset);
+        let view_end = view.byte_offset.checked_add(view.byte_length);
+        let end = accessor
+            .count
+            .checked_sub(1)
+            .map_or(Some(0), |last| last.checked_mul(stride))
+            .zip(offset)
+            .and_then(|(last, offset)| last.checked_add(offset)?.checked_add(element_size));
+        match (end, view_end) {
+            (Some(end), Some(view_end)) if end <= view_end && view_end <= data.len() => {
+                Ok(AccessorView {
+                    data,
+                    offset: offset.unwrap_or(0),
+                    stride,
+                    count: accessor.count,
+                    component_type: accessor.component_type,
+                })
+            }
+            _ => Err(format!(
+                "accessor {} reaches past the end of its buffer",
+                index
+            )),
+        }
+    }
+
+    fn material(&mut self, index: Option<usize>) -> Material {
+        // The glTF default material is plain white
+        let default = || Material::lambertian(Vec3::new(1.0, 1.0, 1.0));
+        let Some(index) = index else {
+            return default();
+        };
+        match self.materials.get(index) {
+            Some(Some(material)) => return *material,
+            Some(None) => {}
+            None => {
+                self.warnings
+                    .push(format!("material {} doesn't exist; using white", index));
+                return default();
+            }
+        }
+
+        let definition = &self.document.materials[index];
+        let pbr = &definition.pbr_metallic_roughness;
+        let [r, g, b, alpha] = pbr.base_color_factor.unwrap_or([1.0; 4]);
+        let albedo = Vec3::new(r, g, b);
+        let mut material = if pbr.metallic_factor.unwrap_or(1.0) >= 0.5 {
+            Material::metal(albedo, pbr.roughness_factor.unwrap_or(1.0))
+        } else {
+            Material::lambertian(albedo)
+        };
+        if definition.alpha_mode.as_deref() == Some("BLEND") {
+            material.opacity = alpha;
+        }
+        let material = material.clamped();
+
+        let textured = [
+            &pbr.base_color_texture,
+            &pbr.metallic_roughness_texture,
+            &definition.normal_texture,
+            &definition.occlusion_texture,
+            &definition.emissive_texture,
+        ]
+        .iter()
+        .any(|texture| texture.is_some());
+        if textured {
+            self.warnings.push(format!(
+                "material {} ('{}') has textures, which are ignored; using its base color",
+                index, definition.name
+            ));
+        }
+        self.materials[index] = Some(material);
+        material
+    }
+
+    fn add_light(&mut self, node_index: usize, light_index: usize, world: Mat4) {
+        let light = self
+            .document
+            .extensions
+            .lights_punctual
+            .as_ref()
+            .and_then(|lights| lights.lights.get(light_index));
+        let Some(light) = light else {
+            self.warnings.push(format!(
+                "node {} uses light {}, which doesn't exist",
+                node_index, light_index
+            ));
+            return;
+        };
+        match light.kind.as_str() {
+            "point" => {}
+            "spot" => self.warnings.push(format!(
+                "light {} is a spot light; imported as a point light",
+                light_index
+            )),
+            kind => {
+                self.warnings.push(format!(
+                    "light {} is a {} light, which isn't supported; skipped",
+                    light_index, kind
+                ));
+                return;
+            }
+        }
+
+        let [r, g, b] = light.color.unwrap_or([1.0; 3]);
+        let mut imported = Light::new(
+            transform(&world, Vec3::zero()),
+            Vec3::new(r, g, b),
+            light.intensity.unwrap_or(1.0),
+        );
+        imported.name = if light.name.is_empty() {
+            self.document.nodes[node_index].name.clone()
+        } else {
+            light.name.clone()
+        };
+        self.scene.add_light(imported);
+    }
+}
+
+/// An accessor's elements: element `i` starts at `offset + i * stride` in
+/// `data`.
+struct AccessorView<'a> {
+    data: &'a [u8],
+    offset: usize,
+    stride: usize,
+    count: usize,
+    component_type: u32,
+}
+
+/// The node's transform relative to its parent: its matrix, or translation
+/// times rotation times scale.
+fn local_transform(node: &Node) -> Mat4 {
+    if let Some(matrix) = node.matrix {
+        return Mat4::from_array(matrix);
+    }
+    let [tx, ty, tz] = node.translation.unwrap_or([0.0; 3]);
+    let [x, y, z, w] = node.rotation.unwrap_or([0.0, 0.0, 0.0, 1.0]);
+    let [sx, sy, sz] = node.scale.unwrap_or([1.0; 3]);
+
+    // Columns of the rotation matrix of the unit quaternion, each scaled
+    Mat4::from_array([
+        (1.0 - 2.0 * (y * y + z * z)) * sx,
+        2.0 * (x * y + z * w) * sx,
+        2.0 * (x * z - y * w) * sx,
+        0.0,
+        2.0 * (x * y - z * w) * sy,
+        (1.0 - 2.0 * (x * x + z * z)) * sy,
+        2.0 * (y * z + x * w) * sy,
+        0.0,
+        2.0 * (x * z + y * w) * sz,
+        2.0 * (y * z - x * w) * sz,
+        (1.0 - 2.0 * (x * x + y * y)) * sz,
+        0.0,
+        tx,
+        ty,
+        tz,
+        1.0,
+    ])
+}
+
+fn transform(matrix: &Mat4, point: Vec3) -> Vec3 {
+    let [x, y, z, _] = matrix.transform_point(point);
+    Vec3::new(x, y, z)
+}
+
+/// Determinant of the matrix's linear part, negative if it mirrors.
+fn determinant(matrix: &Mat4) -> f32 {
+    let m = matrix.as_array();
+    let column = |c: usize| Vec3::new(m[c * 4], m[c * 4 + 1], m[c * 4 + 2]);
+    column(0).dot(&column(1).cross(&column(2)))
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn read_f32(bytes: &[u8], at: usize) -> f32 {
+    f32::from_bits(read_u32(bytes, at))
+}
+
+/// The bytes of a base64 `data:` URI, or `None` if it isn't one.
+fn decode_data_uri(uri: &str) -> Option<Vec<u8>> {
+    let (header, data) = uri.split_once(',')?;
+    if !header.ends_with(";base64") {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(data.len() / 4 * 3);
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    for c in data.bytes().take_while(|&c| c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6) | value as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            bytes.push((bits >> bit_count) as u8);
+        }
+    }
+    Some(bytes)
+}
@@ -0,0 +1,803 @@
+//! CPU ray intersection routines mirroring the fragment shader.
+
+use crate::math::{Transform, Vec3};
+use crate::scene::{
+    Box, Cone, CsgOp, CsgOperation, Cylinder, Disk, ObjectRef, ObjectType, Plane, Scene, Sphere,
+    Triangle,
+};
+
+/// Nearest distance rays count hits at, in meters, so a ray doesn't hit the
+/// surface it leaves. Must match `RAY_EPSILON` in the fragment shader.
+pub const RAY_EPSILON: f32 = 0.001;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self { origin, direction }
+    }
+
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+
+    /// The ray in an object's own space. The map is linear, so distances
+    /// along the ray carry over.
+    pub fn in_object_space(&self, transform: &Transform) -> Ray {
+        Ray::new(
+            transform.local_point(self.origin),
+            transform.local_vector(self.direction),
+        )
+    }
+}
+
+/// The object a ray hit first.
+#[derive(Clone, Copy, Debug)]
+pub struct Hit {
+    pub object_type: ObjectType,
+    pub index: usize,
+    pub t: f32,
+}
+
+/// Distance to the nearest object `ray` hits within `[t_min, t_max]`.
+/// Instanced grids are not tested.
+pub fn ray_scene(scene: &Scene, ray: &Ray, t_min: f32, t_max: f32) -> Option<f32> {
+    closest_hit(scene, ray, t_min, t_max).map(|hit| hit.t)
+}
+
+/// Like `ray_scene`, but says which object was hit. Only surfaces count, so
+/// a ray into glass stops at the glass.
+pub fn closest_hit(scene: &Scene, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+    fn hits<'a, T>(
+        object_type: ObjectType,
+        objects: &'a [T],
+        hit: impl Fn(&T) -> Option<f32> + 'a,
+    ) -> impl Iterator<Item = Hit> + 'a {
+        objects
+            .iter()
+            .enumerate()
+            .filter_map(move |(index, object)| {
+                hit(object).map(|t| Hit {
+                    object_type,
+                    index,
+                    t,
+                })
+            })
+    }
+
+    let spheres = hits(ObjectType::Sphere, &scene.spheres, |o| {
+        ray_sphere(ray, o, t_min, t_max)
+    });
+    let planes = hits(ObjectType::Plane, &scene.planes, |o| {
+        ray_plane(ray, o, t_min, t_max)
+    });
+    let boxes = hits(ObjectType::Box, &scene.boxes, |o| {
+        ray_box(ray, o, t_min, t_max)
+    });
+    let cylinders = hits(ObjectType::Cylinder, &scene.cylinders, |o| {
+        ray_cylinder(ray, o, t_min, t_max)
+    });
+    let triangles = hits(ObjectType::Triangle, &scene.triangles, |o| {
+        ray_triangle(ray, o, t_min, t_max)
+    });
+    let cones = hits(ObjectType::Cone, &scene.cones, |o| {
+        ray_cone(ray, o, t_min, t_max)
+    });
+    let disks = hits(ObjectType::Disk, &scene.disks, |o| {
+        ray_disk(ray, o, t_min, t_max)
+    });
+    // Operands of combinations are only drawn as part of them
+    let csg = scene.drawn_csg();
+    let operands: Vec<ObjectRef> = csg.iter().flat_map(|csg| [csg.a, csg.b]).collect();
+    let combinations = csg
+        .iter()
+        .filter_map(|csg| ray_csg(ray, scene, csg, t_min, t_max));
+    spheres
+        .chain(planes)
+        .chain(boxes)
+        .chain(cylinders)
+        .chain(triangles)
+        .chain(cones)
+        .chain(disks)
+        .filter(|hit| {
+            !operands.contains(&ObjectRef {
+                kind: hit.object_type,
+                index: hit.index,
+            })
+        })
+        .chain(combinations)
+        .min_by(|a, b| a.t.total_cmp(&b.t))
+}
+
+pub fn ray_sphere(ray: &Ray, sphere: &Sphere, t_min: f32, t_max: f32) -> Option<f32> {
+    if sphere.is_ellipsoid() {
+        return ray_ellipsoid(ray, sphere, t_min, t_max);
+    }
+    let oc = ray.origin - sphere.center;
+    let a = ray.direction.dot(&ray.direction);
+    let b = oc.dot(&ray.direction);
+    let c = oc.dot(&oc) - sphere.radius * sphere.radius;
+    let discriminant = b * b - a * c;
+    if discriminant <= 0.0 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    [(-b - root) / a, (-b + root) / a]
+        .into_iter()
+        .find(|&t| t > t_min && t < t_max)
+}
+
+pub fn ray_plane(ray: &Ray, plane: &Plane, t_min: f32, t_max: f32) -> Option<f32> {
+    ray_plane_through(ray, plane.point, plane.normal, t_min, t_max)
+}
+
+/// A scaled sphere, as a unit sphere in the space the scale maps onto it.
+/// The map is linear, so distances along the ray carry over.
+fn ray_ellipsoid(ray: &Ray, sphere: &Sphere, t_min: f32, t_max: f32) -> Option<f32> {
+    let (t0, t1) = ellipsoid_span(ray, sphere.center, sphere.scale * sphere.radius)?;
+    [t0, t1].into_iter().find(|&t| t > t_min && t < t_max)
+}
+
+/// Where `ray` enters and leaves the ellipsoid, behind its origin too.
+fn ellipsoid_span(ray: &Ray, center: Vec3, semi_axes: Vec3) -> Option<(f32, f32)> {
+    let local = |v: Vec3| Vec3::new(v.x / semi_axes.x, v.y / semi_axes.y, v.z / semi_axes.z);
+    let oc = local(ray.origin - center);
+    let direction = local(ray.direction);
+    let a = direction.dot(&direction);
+    let b = oc.dot(&direction);
+    let c = oc.dot(&oc) - 1.0;
+    let discriminant = b * b - a * c;
+    if discriminant <= 0.0 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    Some(((-b - root) / a, (-b + root) / a))
+}
+
+/// Where `ray` crosses the plane through `point` with unit `normal`. Rays
+/// (nearly) parallel to the plane miss it.
+pub fn ray_plane_through(
+    ray: &Ray,
+    point: Vec3,
+    normal: Vec3,
+    t_min: f32,
+    t_max: f32,
+) -> Option<f32> {
+    let denom = normal.dot(&ray.direction);
+    if denom.abs() <= 0.0001 {
+        return None;
+    }
+    let t = (point - ray.origin).dot(&normal) / denom;
+    (t >= t_min && t <= t_max).then_some(t)
+}
+
+/// Slab test against the box, in its own space if it is rotated or scaled.
+pub fn ray_box(ray: &Ray, box_obj: &Box, t_min: f32, t_max: f32) -> Option<f32> {
+    let ray = &box_obj
+        .transform()
+        .map_or(*ray, |t| ray.in_object_space(&t));
+    let (t_near, t_far) = box_span(ray, box_obj.center, box_obj.size)?;
+    if t_far < t_min || t_near > t_max {
+        return None;
+    }
+    let t = if t_near > t_min { t_near } else { t_far };
+    (t >= t_min && t <= t_max).then_some(t)
+}
+
+/// Where `ray` enters and leaves the box, behind its origin too.
+fn box_span(ray: &Ray, center: Vec3, size: Vec3) -> Option<(f32, f32)> {
+    let components = |v: Vec3| [v.x, v.y, v.z];
+    let origin = components(ray.origin - center);
+    let direction = components(ray.direction);
+    let half_size = components(size * 0.5);
+
+    let mut t_near = f32::NEG_INFINITY;
+    let mut t_far = f32::INFINITY;
+    for axis in 0..3 {
+        // Parallel to this pair of faces the ray is between them everywhere
+        // or nowhere; the slab math below would give NaN
+        if direction[axis] == 0.0 {
+            if origin[axis].abs() > half_size[axis] {
+                return None;
+            }
+            continue;
+        }
+        let m = 1.0 / direction[axis];
+        let n = m * origin[axis];
+        let k = m.abs() * half_size[axis];
+        t_near = t_near.max(-n - k);
+        t_far = t_far.min(-n + k);
+    }
+    (t_near <= t_far).then_some((t_near, t_far))
+}
+
+/// The first boundary of either operand the combination keeps, like
+/// `hitCsg` in the shader: union keeps what is outside the other operand,
+/// intersection what is inside it, and subtraction `a` outside `b` and `b`
+/// inside `a`. The hit names the operand whose surface it is on.
+pub fn ray_csg(ray: &Ray, scene: &Scene, csg: &CsgOp, t_min: f32, t_max: f32) -> Option<Hit> {
+    let span = |operand: ObjectRef| match operand.kind {
+        ObjectType::Sphere => {
+            let sphere = scene.spheres.get(operand.index)?;
+            ellipsoid_span(ray, sphere.center, sphere.scale * sphere.radius)
+        }
+        ObjectType::Box => {
+            let box_obj = scene.boxes.get(operand.index)?;
+            let ray = box_obj
+                .transform()
+                .map_or(*ray, |t| ray.in_object_space(&t));
+            box_span(&ray, box_obj.center, box_obj.size)
+        }
+        _ => None,
+    };
+    let spans = [span(csg.a), span(csg.b)];
+    let inside = |span: Option<(f32, f32)>, t: f32| span.is_some_and(|(t0, t1)| t > t0 && t < t1);
+
+    let mut closest: Option<Hit> = None;
+    for (this, other, operand, is_b) in [
+        (spans[0], spans[1], csg.a, false),
+        (spans[1], spans[0], csg.b, true),
+    ] {
+        let Some((t0, t1)) = this else { continue };
+        for t in [t0, t1] {
+            if t <= t_min || t >= closest.map_or(t_max, |hit| hit.t) {
+                continue;
+            }
+            let keep = match csg.op {
+                CsgOperation::Union => !inside(other, t),
+                CsgOperation::Subtract => inside(other, t) == is_b,
+                CsgOperation::Intersect => inside(other, t),
+            };
+            if keep {
+                closest = Some(Hit {
+                    object_type: operand.kind,
+                    index: operand.index,
+                    t,
+                });
+            }
+        }
+    }
+    closest
+}
+
+/// The open tube the shader draws: no end caps.
+pub fn ray_cylinder(ray: &Ray, cylinder: &Cylinder, t_min: f32, t_max: f32) -> Option<f32> {
+    let ray = &cylinder
+        .transform()
+        .map_or(*ray, |t| ray.in_object_space(&t));
+    let length = cylinder.axis.length();
+    if length == 0.0 {
+        return None;
+    }
+    let axis = cylinder.axis / length;
+    let oc = ray.origin - cylinder.base;
+    let d_axis = ray.direction.dot(&axis);
+    let oc_axis = oc.dot(&axis);
+
+    let a = ray.direction.dot(&ray.direction) - d_axis * d_axis;
+    let b = 2.0 * (oc.dot(&ray.direction) - d_axis * oc_axis);
+    let c = oc.dot(&oc) - oc_axis * oc_axis - cylinder.radius * cylinder.radius;
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 || a == 0.0 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    let t1 = (-b - root) / (2.0 * a);
+    let t2 = (-b + root) / (2.0 * a);
+
+    // Like the shader, only the nearer root in range is checked against the
+    // tube's length
+    let t = if t1 >= t_min && t1 <= t_max { t1 } else { t2 };
+    if t < t_min || t > t_max {
+        return None;
+    }
+    let projection = (ray.at(t) - cylinder.base).dot(&axis);
+    (0.0..=length).contains(&projection).then_some(t)
+}
+
+/// The closed cone the shader draws, base included. The quadric also holds
+/// the mirror image of the cone beyond its apex; like the shader, hits there
+/// are rejected by their height along the axis.
+pub fn ray_cone(ray: &Ray, cone: &Cone, t_min: f32, t_max: f32) -> Option<f32> {
+    let length = cone.axis.length();
+    if length == 0.0 {
+        return None;
+    }
+    let axis = cone.axis / length;
+    // Squared cosine of the half angle
+    let k = length * length / (length * length + cone.radius * cone.radius);
+    let co = ray.origin - cone.apex;
+    let d_axis = ray.direction.dot(&axis);
+    let co_axis = co.dot(&axis);
+
+    let a = d_axis * d_axis - k * ray.direction.dot(&ray.direction);
+    let b = 2.0 * (d_axis * co_axis - k * ray.direction.dot(&co));
+    let c = co_axis * co_axis - k * co.dot(&co);
+    let roots = if a.abs() > 1e-12 {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            [f32::NAN; 2]
+        } else {
+            let root = discriminant.sqrt();
+            [(-b - root) / (2.0 * a), (-b + root) / (2.0 * a)]
+        }
+    } else {
+        // Parallel to the side: one crossing
+        [-c / b, f32::NAN]
+    };
+
+    let in_range = |t: f32| t >= t_min && t <= t_max;
+    // The quadric is a double cone; roots on the mirrored half behind the apex
+    // or past the base are not on this one
+    let side = roots
+        .into_iter()
+        .filter(|&t| in_range(t) && (0.0..=length).contains(&(co_axis + t * d_axis)));
+    let base = Some((length - co_axis) / d_axis).filter(|&t| {
+        in_range(t)
+            && (ray.at(t) - (cone.apex + cone.axis)).length_squared() <= cone.radius * cone.radius
+    });
+    side.chain(base).min_by(f32::total_cmp)
+}
+
+/// The disk's plane, then the distance from its center. An inner radius of 0
+/// leaves no hole.
+pub fn ray_disk(ray: &Ray, disk: &Disk, t_min: f32, t_max: f32) -> Option<f32> {
+    let t = ray_plane_through(ray, disk.center, disk.normal, t_min, t_max)?;
+    let distance_squared = (ray.at(t) - disk.center).length_squared();
+    (distance_squared <= disk.radius * disk.radius
+        && distance_squared >= disk.inner_radius * disk.inner_radius)
+        .then_some(t)
+}
+
+/// Watertight ray/triangle test (Woop, Benthin and Wald 2013), double-sided.
+///
+/// The triangle is sheared into a space where the ray runs along +z from the
+/// origin, and hits are decided by the signs of three 2D edge functions. Two
+/// triangles sharing an edge compute that edge's function identically, so a
+/// ray through the edge can't slip between them. Returns the hit distance.
+pub fn ray_triangle(ray: &Ray, triangle: &Triangle, t_min: f32, t_max: f32) -> Option<f32> {
+    let dir = [ray.direction.x, ray.direction.y, ray.direction.z];
+
+    // Dominant axis becomes z; swap x and y to keep the winding when it points
+    // backwards
+    let kz = (0..3)
+        .max_by(|&a, &b| dir[a].abs().total_cmp(&dir[b].abs()))
+        .unwrap_or(2);
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+    if dir[kz] < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+
+    let sx = dir[kx] / dir[kz];
+    let sy = dir[ky] / dir[kz];
+    let sz = 1.0 / dir[kz];
+
+    let relative = |v: Vec3| {
+        let p = v - ray.origin;
+        [p.x, p.y, p.z]
+    };
+    let a = relative(triangle.v0);
+    let b = relative(triangle.v1);
+    let c = relative(triangle.v2);
+
+    let (ax, ay) = (a[kx] - sx * a[kz], a[ky] - sy * a[kz]);
+    let (bx, by) = (b[kx] - sx * b[kz], b[ky] - sy * b[kz]);
+    let (cx, cy) = (c[kx] - sx * c[kz], c[ky] - sy * c[kz]);
+
+    let mut u = cx * by - cy * bx;
+    let mut v = ax * cy - ay * cx;
+    let mut w = bx * ay - by * ax;
+
+    // Exactly on an edge in single precision: settle it in double precision
+    if u == 0.0 || v == 0.0 || w == 0.0 {
+        u = (cx as f64 * by as f64 - cy as f64 * bx as f64) as f32;
+        v = (ax as f64 * cy as f64 - ay as f64 * cx as f64) as f32;
+        w = (bx as f64 * ay as f64 - by as f64 * ax as f64) as f32;
+    }
+
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+
+    let det = u + v + w;
+    if det == 0.0 {
+        return None;
+    }
+
+    let t = (u * a[kz] * sz + v * b[kz] * sz + w * c[kz] * sz) / det;
+    (t >= t_min && t <= t_max).then_some(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+
+    fn triangle(v0: Vec3, v1: Vec3, v2: Vec3) -> Triangle {
+        Triangle::new(v0, v1, v2, Material::lambertian(Vec3::one()))
+    }
+
+    #[test]
+    fn rays_through_a_shared_edge_never_miss() {
+        // A unit quad split along its diagonal from (0, 0) to (1, 1)
+        let corner = Vec3::zero();
+        let opposite = Vec3::new(1.0, 1.0, 0.0);
+        let lower = triangle(corner, Vec3::new(1.0, 0.0, 0.0), opposite);
+        let upper = triangle(corner, opposite, Vec3::new(0.0, 1.0, 0.0));
+
+        let eyes = [Vec3::new(0.3, 0.2, 2.0), Vec3::new(-0.7, 1.9, -1.5)];
+        let steps = 300;
+        let mut misses = 0;
+        for eye in eyes {
+            for i in 0..steps {
+                for j in 0..steps {
+                    // Points along the diagonal, pushed across it by at most
+                    // a few ulps
+                    let s = (i as f32 + 0.5) / steps as f32;
+                    let offset = (j as f32 - steps as f32 / 2.0) * f32::EPSILON;
+                    let target = Vec3::new(s + offset, s - offset, 0.0);
+                    let ray = Ray::new(eye, target - eye);
+                    let hit_lower = ray_triangle(&ray, &lower, 0.0, f32::MAX);
+                    let hit_upper = ray_triangle(&ray, &upper, 0.0, f32::MAX);
+                    if hit_lower.is_none() && hit_upper.is_none() {
+                        misses += 1;
+                    }
+                }
+            }
+        }
+        assert_eq!(misses, 0);
+    }
+
+    #[test]
+    fn rays_through_a_shared_vertex_never_miss() {
+        // Four triangles fanned around the origin
+        let rim = [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+        ];
+        let fan: Vec<Triangle> = (0..4)
+            .map(|i| triangle(Vec3::zero(), rim[i], rim[(i + 1) % 4]))
+            .collect();
+
+        for eye in [
+            Vec3::new(0.0, 0.0, 3.0),
+            Vec3::new(0.4, -0.9, 2.0),
+            Vec3::new(2.0, 1.0, -1.0),
+        ] {
+            let ray = Ray::new(eye, Vec3::zero() - eye);
+            let hits = fan
+                .iter()
+                .filter(|t| ray_triangle(&ray, t, 0.0, f32::MAX).is_some())
+                .count();
+            assert!(hits > 0, "ray from {:?} missed the shared vertex", eye);
+        }
+    }
+
+    #[test]
+    fn triangle_hit_distance_and_range() {
+        let t = triangle(
+            Vec3::new(-1.0, -1.0, -2.0),
+            Vec3::new(1.0, -1.0, -2.0),
+            Vec3::new(0.0, 1.0, -2.0),
+        );
+        let ray = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0));
+        let hit = ray_triangle(&ray, &t, 0.0, 10.0).expect("hit");
+        assert!((hit - 2.0).abs() < 1e-6);
+        assert_eq!(ray_triangle(&ray, &t, 0.0, 1.0), None);
+
+        let beside = Ray::new(Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(ray_triangle(&beside, &t, 0.0, 10.0), None);
+    }
+
+    fn ellipsoid(scale: Vec3) -> Sphere {
+        let mut sphere = Sphere::new(Vec3::zero(), 1.0, Material::lambertian(Vec3::one()));
+        sphere.scale = scale;
+        sphere
+    }
+
+    fn assert_hit(hit: Option<f32>, expected: f32) {
+        let t = hit.unwrap_or_else(|| panic!("expected a hit at {}", expected));
+        assert!(
+            (t - expected).abs() < 1e-4,
+            "expected {}, got {}",
+            expected,
+            t
+        );
+    }
+
+    #[test]
+    fn ellipsoid_hits_at_its_semi_axes() {
+        let sphere = ellipsoid(Vec3::new(2.0, 0.5, 1.0));
+        let along = |origin: Vec3, direction: Vec3| {
+            ray_sphere(&Ray::new(origin, direction), &sphere, 0.0, 100.0)
+        };
+        assert_hit(
+            along(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)),
+            3.0,
+        );
+        assert_hit(
+            along(Vec3::new(0.0, -5.0, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+            4.5,
+        );
+        assert_hit(
+            along(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)),
+            4.0,
+        );
+        // Distances are in units of the direction's length
+        assert_hit(
+            along(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0)),
+            1.5,
+        );
+        // Near the tip of the long axis the surface is close to the middle
+        let y = 0.5 * (1.0_f32 - 0.95 * 0.95).sqrt();
+        assert_hit(
+            along(Vec3::new(1.9, -5.0, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+            5.0 - y,
+        );
+    }
+
+    #[test]
+    fn ellipsoid_misses_past_its_short_axis() {
+        let sphere = ellipsoid(Vec3::new(2.0, 0.5, 1.0));
+        let ray = Ray::new(Vec3::new(-5.0, 0.6, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(ray_sphere(&ray, &sphere, 0.0, 100.0), None);
+        // The unscaled sphere would have been hit
+        let round = ellipsoid(Vec3::one());
+        assert!(ray_sphere(&ray, &round, 0.0, 100.0).is_some());
+    }
+
+    #[test]
+    fn ellipsoid_from_inside_hits_the_far_side() {
+        let sphere = ellipsoid(Vec3::new(2.0, 0.5, 1.0));
+        let ray = Ray::new(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0));
+        assert_hit(ray_sphere(&ray, &sphere, RAY_EPSILON, 100.0), 2.0);
+    }
+
+    #[test]
+    fn uniform_scale_matches_a_larger_radius() {
+        let scaled = ellipsoid(Vec3::new(2.0, 2.0, 2.0));
+        let larger = Sphere::new(Vec3::zero(), 2.0, Material::lambertian(Vec3::one()));
+        let ray = Ray::new(Vec3::new(0.7, -0.3, 6.0), Vec3::new(-0.1, 0.05, -1.0));
+        let expected = ray_sphere(&ray, &larger, 0.0, 100.0).unwrap();
+        assert_hit(ray_sphere(&ray, &scaled, 0.0, 100.0), expected);
+    }
+
+    /// Unit spheres at the origin and at x = 1, overlapping between x = 0
+    /// and x = 1, combined by `op`.
+    fn overlapping_spheres(op: CsgOperation) -> (Scene, CsgOp) {
+        let mut scene = Scene::new();
+        let material = Material::lambertian(Vec3::one());
+        scene.add_sphere(Sphere::new(Vec3::zero(), 1.0, material));
+        scene.add_sphere(Sphere::new(Vec3::new(1.0, 0.0, 0.0), 1.0, material));
+        let csg = CsgOp {
+            op,
+            a: ObjectRef {
+                kind: ObjectType::Sphere,
+                index: 0,
+            },
+            b: ObjectRef {
+                kind: ObjectType::Sphere,
+                index: 1,
+            },
+        };
+        scene.add_csg(csg);
+        (scene, csg)
+    }
+
+    /// Where a ray along the x axis from `x` toward the other side hits the
+    /// combination, and which operand it hits.
+    fn csg_hit_along_x(scene: &Scene, csg: &CsgOp, x: f32) -> Option<(usize, f32)> {
+        let ray = Ray::new(Vec3::new(x, 0.0, 0.0), Vec3::new(-x.signum(), 0.0, 0.0));
+        ray_csg(&ray, scene, csg, RAY_EPSILON, 100.0).map(|hit| (hit.index, hit.t))
+    }
+
+    #[test]
+    fn csg_union_keeps_the_outer_surfaces() {
+        let (scene, csg) = overlapping_spheres(CsgOperation::Union);
+        // x = -1 on a, and x = 2 on b from the other side
+        assert_eq!(csg_hit_along_x(&scene, &csg, -5.0), Some((0, 4.0)));
+        assert_eq!(csg_hit_along_x(&scene, &csg, 5.0), Some((1, 3.0)));
+    }
+
+    #[test]
+    fn csg_subtract_carves_b_out_of_a() {
+        let (scene, csg) = overlapping_spheres(CsgOperation::Subtract);
+        // From -x the front of a is untouched
+        assert_eq!(csg_hit_along_x(&scene, &csg, -5.0), Some((0, 4.0)));
+        // From +x, b's own surface is gone and the first surface is the
+        // hollow b leaves in a, at x = 0
+        assert_eq!(csg_hit_along_x(&scene, &csg, 5.0), Some((1, 5.0)));
+    }
+
+    #[test]
+    fn csg_intersect_keeps_only_the_overlap() {
+        let (scene, csg) = overlapping_spheres(CsgOperation::Intersect);
+        // The lens between x = 0 and x = 1
+        assert_eq!(csg_hit_along_x(&scene, &csg, -5.0), Some((1, 5.0)));
+        assert_eq!(csg_hit_along_x(&scene, &csg, 5.0), Some((0, 4.0)));
+
+        // A ray through a alone misses the lens
+        let ray = Ray::new(Vec3::new(-0.9, -5.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert!(ray_csg(&ray, &scene, &csg, RAY_EPSILON, 100.0).is_none());
+    }
+
+    #[test]
+    fn csg_subtracts_a_sphere_from_a_box() {
+        let mut scene = Scene::new();
+        let material = Material::lambertian(Vec3::one());
+        scene.add_box(Box::new(Vec3::zero(), Vec3::new(2.0, 2.0, 2.0), material));
+        scene.add_sphere(Sphere::new(Vec3::new(1.0, 0.0, 0.0), 0.5, material));
+        let csg = CsgOp {
+            op: CsgOperation::Subtract,
+            a: ObjectRef {
+                kind: ObjectType::Box,
+                index: 0,
+            },
+            b: ObjectRef {
+                kind: ObjectType::Sphere,
+                index: 0,
+            },
+        };
+        scene.add_csg(csg);
+
+        let ray = Ray::new(Vec3::new(5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        let hit = ray_csg(&ray, &scene, &csg, RAY_EPSILON, 100.0).unwrap();
+        // The bottom of the scoop, x = 0.5
+        assert_eq!(hit.object_type, ObjectType::Sphere);
+        assert_eq!(hit.t, 4.5);
+
+        // Beside the scoop the box face is still there
+        let ray = Ray::new(Vec3::new(5.0, 0.8, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        let hit = ray_csg(&ray, &scene, &csg, RAY_EPSILON, 100.0).unwrap();
+        assert_eq!(hit.object_type, ObjectType::Box);
+        assert_eq!(hit.t, 4.0);
+    }
+
+    #[test]
+    fn closest_hit_sees_the_combination_not_its_operands() {
+        let (scene, _) = overlapping_spheres(CsgOperation::Subtract);
+        let ray = Ray::new(Vec3::new(5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        let hit = closest_hit(&scene, &ray, RAY_EPSILON, 100.0).unwrap();
+        // Sphere b on its own would be hit at x = 2
+        assert_eq!(
+            (hit.object_type, hit.index, hit.t),
+            (ObjectType::Sphere, 1, 5.0)
+        );
+    }
+
+    fn unit_box() -> Box {
+        Box::new(Vec3::zero(), Vec3::one(), Material::lambertian(Vec3::one()))
+    }
+
+    /// A tube of radius 0.5 from y = -1 to y = 1.
+    fn upright_cylinder() -> Cylinder {
+        Cylinder::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            0.5,
+            Material::lambertian(Vec3::one()),
+        )
+    }
+
+    #[test]
+    fn rotated_box_shows_its_edge() {
+        let mut box_obj = unit_box();
+        box_obj.rotation = Vec3::new(0.0, std::f32::consts::FRAC_PI_4, 0.0);
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_hit(ray_box(&ray, &box_obj, 0.0, 100.0), 5.0 - 0.5_f32.sqrt());
+        // Beyond the edge's reach the unrotated box would still be hit
+        let ray = Ray::new(Vec3::new(0.45, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_hit(
+            ray_box(&ray, &box_obj, 0.0, 100.0),
+            5.0 - (0.5_f32.sqrt() - 0.45),
+        );
+        let ray = Ray::new(Vec3::new(0.75, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(ray_box(&ray, &box_obj, 0.0, 100.0), None);
+    }
+
+    #[test]
+    fn scaled_box_stretches_along_its_axes() {
+        let mut box_obj = unit_box();
+        box_obj.scale = Vec3::new(2.0, 1.0, 1.0);
+        let ray = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_hit(ray_box(&ray, &box_obj, 0.0, 100.0), 4.0);
+        let ray = Ray::new(Vec3::new(-5.0, 0.6, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(ray_box(&ray, &box_obj, 0.0, 100.0), None);
+    }
+
+    #[test]
+    fn rotated_cylinder_lies_on_its_side() {
+        let mut cylinder = upright_cylinder();
+        let down = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        // Looking down an open tube there is nothing to hit
+        assert_eq!(ray_cylinder(&down, &cylinder, 0.0, 100.0), None);
+
+        cylinder.rotation = Vec3::new(0.0, 0.0, std::f32::consts::FRAC_PI_2);
+        assert_hit(ray_cylinder(&down, &cylinder, 0.0, 100.0), 4.5);
+        let front = Ray::new(Vec3::new(0.8, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_hit(ray_cylinder(&front, &cylinder, 0.0, 100.0), 4.5);
+        // Past the end of the axis, now along x
+        let past_end = Ray::new(Vec3::new(1.5, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert_eq!(ray_cylinder(&past_end, &cylinder, 0.0, 100.0), None);
+    }
+
+    #[test]
+    fn uneven_scale_makes_the_cylinder_elliptic() {
+        let mut cylinder = upright_cylinder();
+        cylinder.scale = Vec3::new(2.0, 1.0, 1.0);
+        let from_side = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_hit(ray_cylinder(&from_side, &cylinder, 0.0, 100.0), 4.0);
+        let from_front = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_hit(ray_cylinder(&from_front, &cylinder, 0.0, 100.0), 4.5);
+    }
+
+    /// Apex at y = 1, base of radius 1 at y = -1.
+    fn downward_cone() -> Cone {
+        Cone::new(
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, -2.0, 0.0),
+            1.0,
+            Material::lambertian(Vec3::one()),
+        )
+    }
+
+    #[test]
+    fn cone_from_the_apex_side_skips_the_mirrored_nappe() {
+        let cone = downward_cone();
+        // The double cone's other half is 0.25 wide at y = 1.5; the cone
+        // itself only at y = 0.5
+        let down = Ray::new(Vec3::new(0.25, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert_hit(ray_cone(&down, &cone, 0.0, 100.0), 4.5);
+        // Crosses only the mirrored half, above the apex
+        let above = Ray::new(Vec3::new(-5.0, 1.5, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(ray_cone(&above, &cone, 0.0, 100.0), None);
+        // Halfway down the side is 0.5 wide
+        let side = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_hit(ray_cone(&side, &cone, 0.0, 100.0), 4.5);
+    }
+
+    #[test]
+    fn cone_base_is_capped() {
+        let cone = downward_cone();
+        let up = Ray::new(Vec3::new(0.5, -5.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_hit(ray_cone(&up, &cone, 0.0, 100.0), 4.0);
+        let wide = Ray::new(Vec3::new(1.5, -5.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(ray_cone(&wide, &cone, 0.0, 100.0), None);
+    }
+
+    /// A ring in the y = 0 plane, from radius `inner` to 1.
+    fn ring(inner: f32) -> Disk {
+        let material = Material::lambertian(Vec3::one());
+        Disk::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 1.0, inner, material)
+    }
+
+    #[test]
+    fn disk_is_hit_from_either_side() {
+        let disk = ring(0.0);
+        let down = Ray::new(Vec3::new(0.75, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert_hit(ray_disk(&down, &disk, 0.0, 100.0), 5.0);
+        let up = Ray::new(Vec3::new(0.75, -5.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_hit(ray_disk(&up, &disk, 0.0, 100.0), 5.0);
+        let outside = Ray::new(Vec3::new(1.25, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert_eq!(ray_disk(&outside, &disk, 0.0, 100.0), None);
+        let edge_on = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(ray_disk(&edge_on, &disk, 0.0, 100.0), None);
+    }
+
+    #[test]
+    fn ring_lets_rays_through_its_hole() {
+        let down = |x: f32| Ray::new(Vec3::new(x, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert_eq!(ray_disk(&down(0.25), &ring(0.5), 0.0, 100.0), None);
+        assert_hit(ray_disk(&down(0.75), &ring(0.5), 0.0, 100.0), 5.0);
+        // No inner radius, no hole, down to the very center
+        assert_hit(ray_disk(&down(0.0), &ring(0.0), 0.0, 100.0), 5.0);
+        assert_hit(ray_disk(&down(0.25), &ring(0.0), 0.0, 100.0), 5.0);
+    }
+}
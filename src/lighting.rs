@@ -0,0 +1,128 @@
+use crate::camera::Camera;
+use crate::scene::Light;
+
+/// Light contribution below which a light is treated as having no effect.
+pub const LIGHT_CULL_EPSILON: f32 = 0.01;
+
+/// Distance falloff used by the fragment shader's direct lighting.
+pub fn attenuation(distance: f32) -> f32 {
+    1.0 / (1.0 + 0.1 * distance + 0.01 * distance * distance)
+}
+
+/// Distance at which the light's brightest channel falls below
+/// `LIGHT_CULL_EPSILON`, found by solving the shader's falloff for distance.
+pub fn effective_radius(light: &Light) -> f32 {
+    let strength = light.intensity * light.color.x.max(light.color.y).max(light.color.z);
+    let ratio = strength / LIGHT_CULL_EPSILON;
+    if ratio <= 1.0 {
+        return 0.0;
+    }
+
+    // 0.01 d^2 + 0.1 d + (1 - ratio) = 0
+    let discriminant = 0.01 - 0.04 * (1.0 - ratio);
+    (-0.1 + discriminant.sqrt()) / 0.02
+}
+
+/// Picks up to `max_lights` lights worth shading from `camera`: lights whose
+/// sphere of influence misses the view frustum are dropped, and the rest are
+/// ordered by their estimated contribution at the camera so the brightest
+/// take the limited uniform slots. Returns indices into `lights`.
+pub fn select_lights(lights: &[Light], camera: &Camera, max_lights: usize) -> Vec<usize> {
+    let mut selected: Vec<(usize, f32)> = lights
+        .iter()
+        .enumerate()
+        .filter(|(_, light)| {
+            let radius = effective_radius(light);
+            radius > 0.0 && camera.sphere_in_frustum(light.position, radius)
+        })
+        .map(|(index, light)| {
+            let distance = (light.position - camera.position()).length();
+            let strength = light.intensity * light.color.x.max(light.color.y).max(light.color.z);
+            (index, strength * attenuation(distance))
+        })
+        .collect();
+
+    // Stable, so equally bright lights keep their scene order
+    selected.sort_by(|a, b| b.1.total_cmp(&a.1));
+    selected.truncate(max_lights);
+    selected.into_iter().map(|(index, _)| index).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Vec3;
+
+    /// At the origin looking down -Z.
+    fn camera() -> Camera {
+        Camera::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), 1.0)
+    }
+
+    fn light(x: f32, y: f32, z: f32, intensity: f32) -> Light {
+        Light::new(Vec3::new(x, y, z), Vec3::one(), intensity)
+    }
+
+    #[test]
+    fn effective_radius_is_where_the_falloff_reaches_epsilon() {
+        for intensity in [0.5, 2.0, 80.0, 200.0] {
+            let radius = effective_radius(&light(0.0, 0.0, 0.0, intensity));
+            let contribution = intensity * attenuation(radius);
+            assert!(
+                (contribution - LIGHT_CULL_EPSILON).abs() < 1e-4,
+                "{}",
+                contribution
+            );
+        }
+        assert_eq!(effective_radius(&light(0.0, 0.0, 0.0, 0.0)), 0.0);
+        assert_eq!(
+            effective_radius(&light(0.0, 0.0, 0.0, LIGHT_CULL_EPSILON)),
+            0.0
+        );
+    }
+
+    #[test]
+    fn dim_light_behind_the_camera_is_culled() {
+        let lights = [light(0.0, 0.0, 100.0, 0.5), light(0.0, 0.0, -3.0, 0.5)];
+        assert!(effective_radius(&lights[0]) < 100.0);
+        assert_eq!(select_lights(&lights, &camera(), 4), vec![1]);
+    }
+
+    #[test]
+    fn sun_intensity_light_is_never_culled() {
+        // The default scene's main light
+        let sun = light(0.0, 0.0, 0.0, 200.0);
+        for offset in [
+            Vec3::new(0.0, 0.0, 100.0),
+            Vec3::new(0.0, -200.0, 0.0),
+            Vec3::new(300.0, 50.0, 300.0),
+            Vec3::new(10.0, 10.0, 10.0),
+        ] {
+            let lights = [Light {
+                position: offset,
+                ..sun.clone()
+            }];
+            let mut camera = camera();
+            for target in [Vec3::new(0.0, 0.0, -1.0), Vec3::new(1.0, 0.0, 0.0), -offset] {
+                camera.look_at(target);
+                assert_eq!(
+                    select_lights(&lights, &camera, 4),
+                    vec![0],
+                    "light at {:?}",
+                    offset
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn brightest_lights_take_the_slots() {
+        let lights = [
+            light(0.0, 0.0, -5.0, 10.0),
+            light(0.0, 0.0, -5.0, 40.0),
+            light(0.0, 0.0, -30.0, 40.0),
+            light(0.0, 0.0, -5.0, 20.0),
+        ];
+        assert_eq!(select_lights(&lights, &camera(), 4), vec![1, 3, 0, 2]);
+        assert_eq!(select_lights(&lights, &camera(), 2), vec![1, 3]);
+    }
+}
@@ -0,0 +1,554 @@
+use std::collections::VecDeque;
+
+use js_sys::Function;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use wasm_bindgen::prelude::*;
+
+use crate::camera::CameraPreset;
+use crate::error::RaytracerError;
+use crate::material::Material;
+use crate::math::Vec3;
+use crate::scene::{
+    self, Box, Cone, Cylinder, Disk, Light, Plane, Scene, SceneProblem, Sphere, Triangle, Validate,
+    Volume,
+};
+
+/// Number of objects moved into the scene per `step`.
+pub const SCENE_LOAD_BATCH: usize = 200;
+
+#[derive(Clone, Copy)]
+enum PendingKind {
+    Sphere,
+    Plane,
+    Box,
+    Cylinder,
+    Triangle,
+    Cone,
+    Disk,
+    Light,
+    Volume,
+}
+
+impl PendingKind {
+    /// The document key the kind's objects are listed under.
+    fn key(self) -> &'static str {
+        match self {
+            PendingKind::Sphere => "spheres",
+            PendingKind::Plane => "planes",
+            PendingKind::Box => "boxes",
+            PendingKind::Cylinder => "cylinders",
+            PendingKind::Triangle => "triangles",
+            PendingKind::Cone => "cones",
+            PendingKind::Disk => "disks",
+            PendingKind::Light => "lights",
+            PendingKind::Volume => "volumes",
+        }
+    }
+
+    /// A valid object of this kind, used to work out where a bad one differs.
+    fn template(self) -> Value {
+        let material = Material::lambertian(Vec3::one());
+        let zero = Vec3::zero();
+        let result = match self {
+            PendingKind::Sphere => serde_json::to_value(Sphere::new(zero, 1.0, material)),
+            PendingKind::Plane => serde_json::to_value(Plane::new(zero, Vec3::one(), material)),
+            PendingKind::Box => serde_json::to_value(Box::new(zero, Vec3::one(), material)),
+            PendingKind::Cylinder => {
+                serde_json::to_value(Cylinder::new(zero, Vec3::one(), 1.0, material))
+            }
+            PendingKind::Triangle => {
+                serde_json::to_value(Triangle::new(zero, zero, zero, material))
+            }
+            PendingKind::Cone => serde_json::to_value(Cone::new(zero, Vec3::one(), 1.0, material)),
+            PendingKind::Disk => {
+                serde_json::to_value(Disk::new(zero, Vec3::one(), 1.0, 0.0, material))
+            }
+            PendingKind::Light => serde_json::to_value(Light::new(zero, Vec3::one(), 1.0)),
+            PendingKind::Volume => serde_json::to_value(Volume::new(zero, 1.0, 1.0, Vec3::one())),
+        };
+        result.unwrap_or(Value::Null)
+    }
+}
+
+const CATEGORIES: [(&str, PendingKind); 9] = [
+    ("spheres", PendingKind::Sphere),
+    ("planes", PendingKind::Plane),
+    ("boxes", PendingKind::Box),
+    ("cylinders", PendingKind::Cylinder),
+    ("triangles", PendingKind::Triangle),
+    ("cones", PendingKind::Cone),
+    ("disks", PendingKind::Disk),
+    ("lights", PendingKind::Light),
+    ("volumes", PendingKind::Volume),
+];
+
+/// A scene load spread over several frames.
+///
+/// The JSON document is parsed up front, but each object is only deserialized
+/// and added to the scene when `step` reaches it, so a huge file never blocks
+/// the main thread for longer than one batch.
+pub struct ChunkedSceneLoad {
+    // Objects still to add, with their index in the document's array
+    pending: VecDeque<(PendingKind, usize, Value)>,
+    loaded: usize,
+    total: usize,
+    on_progress: Function,
+}
+
+impl ChunkedSceneLoad {
+    /// Parses `json_data` and returns the empty scene to render while loading
+    /// (carrying the document's background settings) together with the loader.
+    pub fn start(json_data: &str, on_progress: Function) -> Result<(Scene, Self), RaytracerError> {
+        let mut document: Value = serde_json::from_str(json_data)
+            .map_err(|e| RaytracerError::scene_parse("", format!("Failed to parse JSON: {}", e)))?;
+        scene::migrate(&mut document)?;
+
+        let mut scene = Scene::new();
+        if let Some(background) = document.get_mut("background_color") {
+            scene.background_color = serde_json::from_value::<Vec3>(background.take())
+                .map_err(|e| RaytracerError::scene_parse("background_color", e))?;
+        }
+        if let Some(grid) = document.get_mut("instanced_grid") {
+            scene.instanced_grid = serde_json::from_value(grid.take())
+                .map_err(|e| RaytracerError::scene_parse("instanced_grid", e))?;
+        }
+        if let Some(starfield) = document.get_mut("starfield") {
+            scene.starfield = serde_json::from_value(starfield.take())
+                .map_err(|e| RaytracerError::scene_parse("starfield", e))?;
+        }
+        if let Some(camera_path) = document.get_mut("camera_path") {
+            scene.camera_path = serde_json::from_value(camera_path.take())
+                .map_err(|e| RaytracerError::scene_parse("camera_path", e))?;
+        }
+        if let Some(presets) = document.get_mut("camera_presets") {
+            scene.camera_presets = serde_json::from_value(presets.take())
+                .map_err(|e| RaytracerError::scene_parse("camera_presets", e))?;
+        }
+        if let Some(materials) = document.get_mut("materials") {
+            scene.materials = serde_json::from_value(materials.take())
+                .map_err(|e| RaytracerError::scene_parse("materials", e))?;
+        }
+        if let Some(world_scale) = document.get_mut("world_scale") {
+            scene.world_scale = serde_json::from_value(world_scale.take())
+                .map_err(|e| RaytracerError::scene_parse("world_scale", e))?;
+        }
+        if let Some(csg) = document.get_mut("csg") {
+            scene.csg = serde_json::from_value(csg.take())
+                .map_err(|e| RaytracerError::scene_parse("csg", e))?;
+        }
+
+        let mut pending = VecDeque::new();
+        for (key, kind) in CATEGORIES {
+            if let Some(Value::Array(items)) = document.get_mut(key).map(Value::take) {
+                pending.extend(
+                    items
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, item)| (kind, index, item)),
+                );
+            }
+        }
+
+        let total = pending.len();
+        let load = Self {
+            pending,
+            loaded: 0,
+            total,
+            on_progress,
+        };
+
+        Ok((scene, load))
+    }
+
+    /// Moves up to `batch` objects into `scene` and reports progress.
+    ///
+    /// Returns `Ok(true)` once every object has been loaded. On error the
+    /// objects loaded so far stay in the scene.
+    pub fn step(&mut self, scene: &mut Scene, batch: usize) -> Result<bool, RaytracerError> {
+        for _ in 0..batch {
+            let Some((kind, index, value)) = self.pending.pop_front() else {
+                break;
+            };
+
+            add_object(scene, kind, index, value).map_err(|rejected| match rejected {
+                Rejected::Parse(e) => {
+                    RaytracerError::scene_parse(format!("{}[{}]", kind.key(), index), e)
+                }
+                Rejected::Invalid(problems) => RaytracerError::InvalidScene { problems },
+            })?;
+            self.loaded += 1;
+        }
+
+        let _ = self.on_progress.call2(
+            &JsValue::NULL,
+            &JsValue::from(self.loaded as u32),
+            &JsValue::from(self.total as u32),
+        );
+
+        if self.pending.is_empty() {
+            scene.assign_ids();
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
+
+/// Object counts per category after a lenient load.
+#[derive(Debug, Default, Serialize)]
+pub struct LoadedCounts {
+    pub spheres: usize,
+    pub planes: usize,
+    pub boxes: usize,
+    pub cylinders: usize,
+    pub triangles: usize,
+    pub cones: usize,
+    pub disks: usize,
+    pub lights: usize,
+    pub volumes: usize,
+}
+
+/// A part of the document that was skipped, e.g. `triangles[2].v1.y`.
+#[derive(Debug, Serialize)]
+pub struct LoadWarning {
+    pub path: String,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct LenientReport {
+    pub loaded: LoadedCounts,
+    pub warnings: Vec<LoadWarning>,
+}
+
+/// Loads every object of `json_data` that deserializes on its own and passes
+/// `Validate`, skipping (and reporting) the others instead of rejecting the
+/// whole file. Only a document that isn't valid JSON at all, or that needs a
+/// newer format version than `SCENE_VERSION`, is an error.
+pub fn load_lenient(json_data: &str) -> Result<(Scene, LenientReport), RaytracerError> {
+    let mut document: Value = serde_json::from_str(json_data)
+        .map_err(|e| RaytracerError::scene_parse("", format!("Failed to parse JSON: {}", e)))?;
+    scene::migrate(&mut document)?;
+
+    let mut scene = Scene::new();
+    let mut report = LenientReport::default();
+
+    if let Some(background) = document.get_mut("background_color") {
+        match serde_json::from_value::<Vec3>(background.take()) {
+            Ok(color) => scene.background_color = color,
+            Err(e) => report.warnings.push(LoadWarning {
+                path: "background_color".to_string(),
+                message: e.to_string(),
+            }),
+        }
+    }
+    if let Some(grid) = document.get_mut("instanced_grid") {
+        match serde_json::from_value(grid.take()) {
+            Ok(grid) => scene.instanced_grid = grid,
+            Err(e) => report.warnings.push(LoadWarning {
+                path: "instanced_grid".to_string(),
+                message: e.to_string(),
+            }),
+        }
+    }
+    if let Some(starfield) = document.get_mut("starfield") {
+        match serde_json::from_value(starfield.take()) {
+            Ok(starfield) => scene.starfield = starfield,
+            Err(e) => report.warnings.push(LoadWarning {
+                path: "starfield".to_string(),
+                message: e.to_string(),
+            }),
+        }
+    }
+    if let Some(camera_path) = document.get_mut("camera_path") {
+        match serde_json::from_value(camera_path.take()) {
+            Ok(camera_path) => scene.camera_path = camera_path,
+            Err(e) => report.warnings.push(LoadWarning {
+                path: "camera_path".to_string(),
+                message: e.to_string(),
+            }),
+        }
+    }
+    if let Some(world_scale) = document.get_mut("world_scale") {
+        match serde_json::from_value(world_scale.take()) {
+            Ok(world_scale) => scene.world_scale = world_scale,
+            Err(e) => report.warnings.push(LoadWarning {
+                path: "world_scale".to_string(),
+                message: e.to_string(),
+            }),
+        }
+    }
+    if let Some(csg) = document.get_mut("csg") {
+        match serde_json::from_value(csg.take()) {
+            Ok(csg) => scene.csg = csg,
+            Err(e) => report.warnings.push(LoadWarning {
+                path: "csg".to_string(),
+                message: e.to_string(),
+            }),
+        }
+    }
+    if let Some(Value::Object(materials)) = document.get_mut("materials").map(Value::take) {
+        for (name, value) in materials {
+            match serde_json::from_value::<Material>(value) {
+                Ok(material) => {
+                    scene.materials.insert(name, material);
+                }
+                Err(e) => report.warnings.push(LoadWarning {
+                    path: format!("materials.{}", name),
+                    message: e.to_string(),
+                }),
+            }
+        }
+    }
+    if let Some(Value::Object(presets)) = document.get_mut("camera_presets").map(Value::take) {
+        for (name, value) in presets {
+            match serde_json::from_value::<CameraPreset>(value) {
+                Ok(preset) => {
+                    scene.camera_presets.insert(name, preset);
+                }
+                Err(e) => report.warnings.push(LoadWarning {
+                    path: format!("camera_presets.{}", name),
+                    message: e.to_string(),
+                }),
+            }
+        }
+    }
+
+    for (key, kind) in CATEGORIES {
+        let items = match document.get_mut(key).map(Value::take) {
+            Some(Value::Array(items)) => items,
+            Some(Value::Null) | None => continue,
+            Some(_) => {
+                report.warnings.push(LoadWarning {
+                    path: key.to_string(),
+                    message: "expected an array".to_string(),
+                });
+                continue;
+            }
+        };
+
+        for (index, item) in items.into_iter().enumerate() {
+            let element_path = format!("{}[{}]", key, index);
+            let snapshot = item.clone();
+            match add_object(&mut scene, kind, index, item) {
+                Ok(()) => *report.loaded.count_mut(kind) += 1,
+                Err(Rejected::Invalid(problems)) => {
+                    report.warnings.extend(problems.into_iter().map(|problem| LoadWarning {
+                        message: format!("{}, got {}", problem.message, problem.value),
+                        path: problem.path,
+                    }));
+                }
+                Err(Rejected::Parse(e)) => {
+                    let message = e.to_string();
+                    let detail = error_path(&kind.template(), &snapshot, &message);
+                    report.warnings.push(LoadWarning {
+                        path: element_path + &detail.unwrap_or_default(),
+                        message,
+                    });
+                }
+            }
+        }
+    }
+
+    scene.assign_ids();
+    Ok((scene, report))
+}
+
+impl LoadedCounts {
+    fn count_mut(&mut self, kind: PendingKind) -> &mut usize {
+        match kind {
+            PendingKind::Sphere => &mut self.spheres,
+            PendingKind::Plane => &mut self.planes,
+            PendingKind::Box => &mut self.boxes,
+            PendingKind::Cylinder => &mut self.cylinders,
+            PendingKind::Triangle => &mut self.triangles,
+            PendingKind::Cone => &mut self.cones,
+            PendingKind::Disk => &mut self.disks,
+            PendingKind::Light => &mut self.lights,
+            PendingKind::Volume => &mut self.volumes,
+        }
+    }
+}
+
+/// Why `add_object` left an object out.
+enum Rejected {
+    Parse(serde_json::Error),
+    Invalid(Vec<SceneProblem>),
+}
+
+/// Adds `value`, number `index` of its kind in the document, to `scene`.
+fn add_object(
+    scene: &mut Scene,
+    kind: PendingKind,
+    index: usize,
+    value: Value,
+) -> Result<(), Rejected> {
+    // The ids the objects are added under are only final once the whole
+    // document is in, see `Scene::assign_ids`
+    match kind {
+        PendingKind::Sphere => {
+            scene.add_sphere(parse::<Sphere>(value, index)?);
+        }
+        PendingKind::Plane => {
+            scene.add_plane(parse::<Plane>(value, index)?);
+        }
+        PendingKind::Box => {
+            scene.add_box(parse::<Box>(value, index)?);
+        }
+        PendingKind::Cylinder => {
+            scene.add_cylinder(parse::<Cylinder>(value, index)?);
+        }
+        PendingKind::Triangle => {
+            scene.add_triangle(parse::<Triangle>(value, index)?);
+        }
+        PendingKind::Cone => {
+            scene.add_cone(parse::<Cone>(value, index)?);
+        }
+        PendingKind::Disk => {
+            scene.add_disk(parse::<Disk>(value, index)?);
+        }
+        PendingKind::Light => {
+            scene.add_light(parse::<Light>(value, index)?);
+        }
+        PendingKind::Volume => scene.add_volume(parse::<Volume>(value, index)?),
+    }
+    Ok(())
+}
+
+fn parse<T: DeserializeOwned + Validate>(value: Value, index: usize) -> Result<T, Rejected> {
+    let object: T = serde_json::from_value(value).map_err(Rejected::Parse)?;
+    let problems = object.problems(index);
+    if problems.is_empty() {
+        Ok(object)
+    } else {
+        Err(Rejected::Invalid(problems))
+    }
+}
+
+/// Narrows a deserialization error down to a field path (like `.v1.y`) by
+/// comparing the bad value against a known-good `template` of the same type.
+/// serde_json doesn't track paths itself.
+fn error_path(template: &Value, value: &Value, message: &str) -> Option<String> {
+    if let Some(field) = message
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.split('`').next())
+    {
+        return missing_field_path(template, value, field);
+    }
+    mismatch_path(template, value)
+}
+
+fn missing_field_path(template: &Value, value: &Value, field: &str) -> Option<String> {
+    let (Value::Object(expected), Value::Object(actual)) = (template, value) else {
+        return None;
+    };
+    if expected.contains_key(field) && !actual.contains_key(field) {
+        return Some(format!(".{}", field));
+    }
+    expected.iter().find_map(|(key, expected_child)| {
+        let child = missing_field_path(expected_child, actual.get(key)?, field)?;
+        Some(format!(".{}{}", key, child))
+    })
+}
+
+fn mismatch_path(template: &Value, value: &Value) -> Option<String> {
+    match (template, value) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            expected.iter().find_map(|(key, expected_child)| {
+                let child = mismatch_path(expected_child, actual.get(key)?)?;
+                Some(format!(".{}{}", key, child))
+            })
+        }
+        (Value::Number(_), Value::Number(_))
+        | (Value::String(_), Value::String(_))
+        | (Value::Bool(_), Value::Bool(_))
+        | (Value::Array(_), Value::Array(_)) => None,
+        // Wrong kind of value: the error is right here
+        _ => Some(String::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two spheres, four triangles and a light, as a JSON document.
+    fn document() -> Value {
+        let material = Material::lambertian(Vec3::new(0.5, 0.5, 0.5));
+        let mut scene = Scene::new();
+        scene.add_sphere(Sphere::new(Vec3::zero(), 1.0, material));
+        scene.add_sphere(Sphere::new(Vec3::new(3.0, 0.0, 0.0), 0.5, material));
+        for i in 0..4 {
+            let x = i as f32;
+            scene.add_triangle(Triangle::new(
+                Vec3::new(x, 0.0, 0.0),
+                Vec3::new(x + 1.0, 0.0, 0.0),
+                Vec3::new(x, 1.0, 0.0),
+                material,
+            ));
+        }
+        scene.add_light(Light::new(Vec3::new(0.0, 5.0, 0.0), Vec3::one(), 10.0));
+        serde_json::from_str(&scene.to_json()).unwrap()
+    }
+
+    fn load(document: &Value) -> (Scene, LenientReport) {
+        load_lenient(&document.to_string()).unwrap()
+    }
+
+    fn warning_paths(report: &LenientReport) -> Vec<&str> {
+        report.warnings.iter().map(|w| w.path.as_str()).collect()
+    }
+
+    #[test]
+    fn bad_number_is_reported_at_its_field() {
+        let mut document = document();
+        document["triangles"][2]["v1"]["y"] = Value::from("not a number");
+
+        let (scene, report) = load(&document);
+        assert_eq!(warning_paths(&report), vec!["triangles[2].v1.y"]);
+        assert_eq!(report.loaded.spheres, 2);
+        assert_eq!(report.loaded.triangles, 3);
+        assert_eq!(report.loaded.lights, 1);
+        assert_eq!(scene.spheres.len(), 2);
+        assert_eq!(scene.lights.len(), 1);
+        // The triangles either side of the bad one keep their order
+        let xs: Vec<f32> = scene.triangles.iter().map(|t| t.v0.x).collect();
+        assert_eq!(xs, vec![0.0, 1.0, 3.0]);
+    }
+
+    #[test]
+    fn missing_field_is_reported_at_its_field() {
+        let mut document = document();
+        document["triangles"][1]["v2"].as_object_mut().unwrap().remove("z");
+        document["spheres"][0].as_object_mut().unwrap().remove("material");
+
+        let (scene, report) = load(&document);
+        assert_eq!(warning_paths(&report), vec!["spheres[0].material", "triangles[1].v2.z"]);
+        assert_eq!(scene.spheres.len(), 1);
+        assert_eq!(scene.triangles.len(), 3);
+    }
+
+    #[test]
+    fn invalid_values_are_skipped_with_their_problem() {
+        let mut document = document();
+        document["spheres"][1]["radius"] = Value::from(-2.0);
+
+        let (scene, report) = load(&document);
+        assert_eq!(scene.spheres.len(), 1);
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].path.starts_with("spheres[1]"));
+        assert!(report.warnings[0].message.contains("-2"));
+        assert_eq!(report.loaded.triangles, 4);
+    }
+
+    #[test]
+    fn only_unreadable_json_is_an_error() {
+        assert!(load_lenient("{ not json").is_err());
+        let (scene, report) = load_lenient("{}").unwrap();
+        assert!(scene.spheres.is_empty() && report.warnings.is_empty());
+
+        let (_, report) = load_lenient(r#"{"spheres": 3}"#).unwrap();
+        assert_eq!(warning_paths(&report), vec!["spheres"]);
+    }
+}
@@ -1,19 +1,94 @@
+//! Surface materials as the shader understands them.
+
 use crate::math::Vec3;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub enum MaterialType {
+    #[default]
     Lambertian,
     Metal,
     Dielectric,
+    /// A light source: glows with its albedo times `emission_strength` and
+    /// reflects nothing. Emissive spheres also light diffuse surfaces near them.
+    Emissive,
+}
+
+impl MaterialType {
+    /// The JS API's numbering: 0 = Lambertian, 1 = metal, 2 = dielectric,
+    /// 3 = emissive.
+    pub fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Self::Lambertian),
+            1 => Some(Self::Metal),
+            2 => Some(Self::Dielectric),
+            3 => Some(Self::Emissive),
+            _ => None,
+        }
+    }
+}
+
+/// Coordinates textures are evaluated in. `Object` measures from the object's
+/// origin, so the pattern stays fixed to the object as it moves.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextureSpace {
+    #[default]
+    World,
+    Object,
+}
+
+impl TextureSpace {
+    pub fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Self::World),
+            1 => Some(Self::Object),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+/// Procedural patterns that replace a material's albedo.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Texture {
+    /// Alternating cubes of `color_a` and `color_b`, `scale` units on a side.
+    /// On planes only the squares in the plane show.
+    Checker {
+        color_a: Vec3,
+        color_b: Vec3,
+        scale: f32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Material {
     pub material_type: MaterialType,
     pub albedo: Vec3,
     pub roughness: f32,
     pub ior: f32,
+    #[serde(default)]
+    pub emission: Vec3,
+    #[serde(default)]
+    pub emission_strength: f32,
+    // Ignored by dielectrics, which already refract
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+    // Drawn only as the shadows falling on it, for compositing over photos
+    #[serde(default)]
+    pub shadow_catcher: bool,
+    #[serde(default)]
+    pub texture_space: TextureSpace,
+    // How far the dielectric's IOR spreads across red, green and blue, as a
+    // fraction of `ior - 1` (roughly the inverse of an Abbe number); 0 is off
+    #[serde(default)]
+    pub dispersion: f32,
+    // Evaluated in `texture_space`; object space keeps it on moving objects
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texture: Option<Texture>,
+}
+
+fn default_opacity() -> f32 {
+    1.0
 }
 
 impl Material {
@@ -23,6 +98,17 @@ impl Material {
             albedo,
             roughness,
             ior,
+            emission: Vec3::zero(),
+            // An emissive material made from just a color should still glow
+            emission_strength: match material_type {
+                MaterialType::Emissive => 1.0,
+                _ => 0.0,
+            },
+            opacity: 1.0,
+            shadow_catcher: false,
+            texture_space: TextureSpace::World,
+            dispersion: 0.0,
+            texture: None,
         }
     }
 
@@ -38,7 +124,63 @@ impl Material {
         Self::new(MaterialType::Dielectric, Vec3::new(1.0, 1.0, 1.0), 0.0, ior)
     }
 
-    pub fn emissive(color: Vec3) -> Self {
-        Self::new(MaterialType::Lambertian, color, 0.0, 1.0)
+    /// A light source glowing with `color` times `strength`.
+    pub fn emissive(color: Vec3, strength: f32) -> Self {
+        Self {
+            emission_strength: strength,
+            ..Self::new(MaterialType::Emissive, color, 0.0, 1.0)
+        }
+    }
+
+    /// The material with every parameter forced into the range the shader
+    /// handles; non-finite values fall back to neutral ones.
+    pub fn clamped(mut self) -> Self {
+        let finite = |value: f32, fallback: f32| if value.is_finite() { value } else { fallback };
+        let color = |c: Vec3| {
+            Vec3::new(
+                finite(c.x, 0.0).max(0.0),
+                finite(c.y, 0.0).max(0.0),
+                finite(c.z, 0.0).max(0.0),
+            )
+        };
+
+        self.albedo = color(self.albedo);
+        self.roughness = finite(self.roughness, 0.0).clamp(0.0, 1.0);
+        self.ior = finite(self.ior, 1.0).max(1.0);
+        self.emission = color(self.emission);
+        self.emission_strength = finite(self.emission_strength, 0.0).max(0.0);
+        self.opacity = finite(self.opacity, 1.0).clamp(0.0, 1.0);
+        self.dispersion = finite(self.dispersion, 0.0).clamp(0.0, 1.0);
+        self.texture = match self.texture {
+            Some(Texture::Checker {
+                color_a,
+                color_b,
+                scale,
+            }) if scale.is_finite() && scale > 0.0 => Some(Texture::Checker {
+                color_a: color(color_a),
+                color_b: color(color_b),
+                scale,
+            }),
+            _ => None,
+        };
+        self
+    }
+
+    /// Whether every parameter is a finite number.
+    pub fn is_finite(&self) -> bool {
+        self.albedo.is_finite()
+            && self.roughness.is_finite()
+            && self.ior.is_finite()
+            && self.emission.is_finite()
+            && self.emission_strength.is_finite()
+            && self.opacity.is_finite()
+            && self.dispersion.is_finite()
+            && self.texture.is_none_or(|texture| match texture {
+                Texture::Checker {
+                    color_a,
+                    color_b,
+                    scale,
+                } => color_a.is_finite() && color_b.is_finite() && scale.is_finite(),
+            })
     }
 }
@@ -1,6 +1,8 @@
+//! Vector and matrix types shared by the scene model and the renderer.
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Vec3 {
     pub x: f32,
     pub y: f32,
@@ -8,7 +10,7 @@ pub struct Vec3 {
 }
 
 impl Vec3 {
-    pub fn new(x: f32, y: f32, z: f32) -> Self {
+    pub const fn new(x: f32, y: f32, z: f32) -> Self {
         Self { x, y, z }
     }
 
@@ -28,6 +30,10 @@ impl Vec3 {
         self.x * self.x + self.y * self.y + self.z * self.z
     }
 
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
+    }
+
     pub fn normalize(&self) -> Self {
         let len = self.length();
         if len > 0.0 {
@@ -106,6 +112,14 @@ impl std::ops::Div<f32> for Vec3 {
     }
 }
 
+impl std::ops::Div<Vec3> for Vec3 {
+    type Output = Vec3;
+
+    fn div(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x / other.x, self.y / other.y, self.z / other.z)
+    }
+}
+
 impl std::ops::Neg for Vec3 {
     type Output = Vec3;
 
@@ -172,6 +186,31 @@ impl Mat4 {
         Self { data }
     }
 
+    /// View matrix from an explicit camera basis, for cameras whose `right`
+    /// isn't `forward x up`. View space looks down -Z.
+    pub fn view_from_basis(eye: Vec3, right: Vec3, up: Vec3, forward: Vec3) -> Self {
+        let mut data = [0.0; 16];
+
+        data[0] = right.x;
+        data[1] = up.x;
+        data[2] = -forward.x;
+
+        data[4] = right.y;
+        data[5] = up.y;
+        data[6] = -forward.y;
+
+        data[8] = right.z;
+        data[9] = up.z;
+        data[10] = -forward.z;
+
+        data[12] = -right.dot(&eye);
+        data[13] = -up.dot(&eye);
+        data[14] = forward.dot(&eye);
+        data[15] = 1.0;
+
+        Self { data }
+    }
+
     pub fn translation(x: f32, y: f32, z: f32) -> Self {
         let mut mat = Self::identity();
         mat.data[12] = x;
@@ -204,9 +243,24 @@ impl Mat4 {
         mat
     }
 
+    /// The matrix with elements in column-major order, as `as_array` returns them.
+    pub fn from_array(data: [f32; 16]) -> Self {
+        Self { data }
+    }
+
     pub fn as_array(&self) -> [f32; 16] {
         self.data
     }
+
+    /// Multiplies `(point, 1)` by the matrix, returning homogeneous `[x, y, z, w]`.
+    pub fn transform_point(&self, point: Vec3) -> [f32; 4] {
+        let d = &self.data;
+        let mut out = [0.0; 4];
+        for (row, value) in out.iter_mut().enumerate() {
+            *value = d[row] * point.x + d[4 + row] * point.y + d[8 + row] * point.z + d[12 + row];
+        }
+        out
+    }
 }
 
 impl std::ops::Mul for Mat4 {
@@ -215,10 +269,12 @@ impl std::ops::Mul for Mat4 {
     fn mul(self, other: Mat4) -> Mat4 {
         let mut result = [0.0; 16];
 
-        for i in 0..4 {
-            for j in 0..4 {
+        // Column-major: element (row, column) lives at data[column * 4 + row]
+        for column in 0..4 {
+            for row in 0..4 {
                 for k in 0..4 {
-                    result[i * 4 + j] += self.data[i * 4 + k] * other.data[k * 4 + j];
+                    result[column * 4 + row] +=
+                        self.data[k * 4 + row] * other.data[column * 4 + k];
                 }
             }
         }
@@ -227,6 +283,195 @@ impl std::ops::Mul for Mat4 {
     }
 }
 
+/// Rotation as a unit quaternion, `w` being its scalar part.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Quat {
+    pub const fn identity() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 1.0,
+        }
+    }
+
+    /// From Euler angles in radians, turning about X first, then Y, then Z
+    /// (Blender's XYZ order).
+    pub fn from_euler(angles: Vec3) -> Self {
+        let (sx, cx) = (angles.x * 0.5).sin_cos();
+        let (sy, cy) = (angles.y * 0.5).sin_cos();
+        let (sz, cz) = (angles.z * 0.5).sin_cos();
+        Self {
+            x: sx * cy * cz - cx * sy * sz,
+            y: cx * sy * cz + sx * cy * sz,
+            z: cx * cy * sz - sx * sy * cz,
+            w: cx * cy * cz + sx * sy * sz,
+        }
+    }
+
+    /// The XYZ Euler angles `from_euler` turns back into this rotation. At
+    /// a Y angle of ±90 degrees, where X and Z turn about the same axis, X
+    /// is taken as 0.
+    pub fn to_euler(&self) -> Vec3 {
+        let Quat { x, y, z, w } = *self;
+        let sin_y = (-2.0 * (x * z - w * y)).clamp(-1.0, 1.0);
+        if sin_y.abs() < 0.9999 {
+            Vec3::new(
+                (2.0 * (y * z + w * x)).atan2(1.0 - 2.0 * (x * x + y * y)),
+                sin_y.asin(),
+                (2.0 * (x * y + w * z)).atan2(1.0 - 2.0 * (y * y + z * z)),
+            )
+        } else {
+            Vec3::new(
+                0.0,
+                sin_y.asin(),
+                (-2.0 * (x * y - w * z)).atan2(1.0 - 2.0 * (x * x + z * z)),
+            )
+        }
+    }
+
+    /// The opposite rotation.
+    pub fn inverse(&self) -> Self {
+        Self {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+            w: self.w,
+        }
+    }
+
+    pub fn rotate(&self, v: Vec3) -> Vec3 {
+        let axis = Vec3::new(self.x, self.y, self.z);
+        let t = axis.cross(&v) * 2.0;
+        v + t * self.w + axis.cross(&t)
+    }
+}
+
+/// A per-axis scale followed by a rotation, both about `pivot`, taking an
+/// object from its own space into the world.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub pivot: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Transform {
+    pub fn point(&self, p: Vec3) -> Vec3 {
+        self.pivot + self.rotation.rotate((p - self.pivot) * self.scale)
+    }
+
+    pub fn local_point(&self, p: Vec3) -> Vec3 {
+        self.pivot + self.local_vector(p - self.pivot)
+    }
+
+    pub fn local_vector(&self, v: Vec3) -> Vec3 {
+        self.rotation.inverse().rotate(v) / self.scale
+    }
+
+    /// Takes a normal out of object space. Normals go through the inverse
+    /// transpose, which divides by the scale where points multiply.
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        self.rotation.rotate(n / self.scale).normalize()
+    }
+
+    /// World bounds of the object with local `bounds`.
+    pub fn bounds(&self, bounds: &Aabb) -> Aabb {
+        Aabb::from_points(&bounds.corners().map(|corner| self.point(corner)))
+    }
+}
+
+/// Axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_points(points: &[Vec3]) -> Self {
+        let mut aabb = Self::new(points[0], points[0]);
+        for &point in &points[1..] {
+            aabb.include_point(point);
+        }
+        aabb
+    }
+
+    pub fn include_point(&mut self, point: Vec3) {
+        self.min = Vec3::new(
+            self.min.x.min(point.x),
+            self.min.y.min(point.y),
+            self.min.z.min(point.z),
+        );
+        self.max = Vec3::new(
+            self.max.x.max(point.x),
+            self.max.y.max(point.y),
+            self.max.z.max(point.z),
+        );
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        let mut result = *self;
+        result.include_point(other.min);
+        result.include_point(other.max);
+        result
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    /// The box grown by `margin` on every side.
+    pub fn expanded(&self, margin: f32) -> Aabb {
+        let margin = Vec3::new(margin, margin, margin);
+        Aabb::new(self.min - margin, self.max + margin)
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x
+            && self.max.x >= other.min.x
+            && self.min.y <= other.max.y
+            && self.max.y >= other.min.y
+            && self.min.z <= other.max.z
+            && self.max.z >= other.min.z
+    }
+
+    /// Radius of the sphere through the box corners, centered on the box.
+    pub fn bounding_radius(&self) -> f32 {
+        self.size().length() * 0.5
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Vec3::new(a.x, a.y, a.z),
+            Vec3::new(b.x, a.y, a.z),
+            Vec3::new(a.x, b.y, a.z),
+            Vec3::new(b.x, b.y, a.z),
+            Vec3::new(a.x, a.y, b.z),
+            Vec3::new(b.x, a.y, b.z),
+            Vec3::new(a.x, b.y, b.z),
+            Vec3::new(b.x, b.y, b.z),
+        ]
+    }
+}
+
+#[cfg(feature = "webgl")]
 pub fn random_in_unit_sphere() -> Vec3 {
     loop {
         let p = Vec3::new(
@@ -246,3 +491,325 @@ pub fn schlick(cosine: f32, ref_idx: f32) -> f32 {
     let r0 = r0 * r0;
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
 }
+
+/// Low-discrepancy sample sequences and the mappings used to turn them into
+/// directions and lens positions.
+pub mod sampling {
+    use super::Vec3;
+
+    /// Radical inverse of `index` in `base` (the `index`-th Halton sample).
+    pub fn halton(mut index: u32, base: u32) -> f32 {
+        let mut result = 0.0;
+        let mut fraction = 1.0 / base as f32;
+        while index > 0 {
+            result += (index % base) as f32 * fraction;
+            index /= base;
+            fraction /= base as f32;
+        }
+        result
+    }
+
+    /// The `index`-th point of an `count`-point Hammersley set in [0, 1)^2.
+    pub fn hammersley(index: u32, count: u32) -> (f32, f32) {
+        (
+            index as f32 / count.max(1) as f32,
+            index.reverse_bits() as f32 * (1.0 / 4_294_967_296.0),
+        )
+    }
+
+    /// 64 points in [0, 1)^2 spread out by best-candidate sampling on a torus,
+    /// so consecutive entries stay far apart even when tiled.
+    pub const BLUE_NOISE: [[f32; 2]; 64] = [
+        [0.3238, 0.1508], [0.7944, 0.6990], [0.3693, 0.5663], [0.8289, 0.1614],
+        [0.1462, 0.8265], [0.5531, 0.9267], [0.0175, 0.4590], [0.7497, 0.4128],
+        [0.0633, 0.1014], [0.5060, 0.3191], [0.3634, 0.8176], [0.9287, 0.8544],
+        [0.2559, 0.3520], [0.5702, 0.6504], [0.6383, 0.1797], [0.1857, 0.6381],
+        [0.7726, 0.9774], [0.9657, 0.6727], [0.0880, 0.2710], [0.2386, 0.9868],
+        [0.5185, 0.4840], [0.4683, 0.0924], [0.8986, 0.3388], [0.6782, 0.8152],
+        [0.7008, 0.5804], [0.9307, 0.0517], [0.5176, 0.7857], [0.8549, 0.5329],
+        [0.0466, 0.9547], [0.1741, 0.4851], [0.3847, 0.9554], [0.3962, 0.3956],
+        [0.6409, 0.0318], [0.9560, 0.2195], [0.6466, 0.3326], [0.3086, 0.7024],
+        [0.2058, 0.1905], [0.7774, 0.2931], [0.4281, 0.2314], [0.8038, 0.8219],
+        [0.0925, 0.7265], [0.4387, 0.6797], [0.0626, 0.6036], [0.2963, 0.4796],
+        [0.0371, 0.8182], [0.2522, 0.8729], [0.1359, 0.3741], [0.6466, 0.4573],
+        [0.6725, 0.6959], [0.1357, 0.0212], [0.5326, 0.1993], [0.3122, 0.2571],
+        [0.7529, 0.0872], [0.2077, 0.7436], [0.8938, 0.7561], [0.8662, 0.9371],
+        [0.4597, 0.8739], [0.9122, 0.4438], [0.0045, 0.3370], [0.9596, 0.5463],
+        [0.6584, 0.9295], [0.3266, 0.0422], [0.6024, 0.5460], [0.5676, 0.3970],
+    ];
+
+    /// Maps the unit square onto the unit disk with Shirley's concentric
+    /// mapping, which keeps strata compact and area-preserving.
+    pub fn disk_sample(u: f32, v: f32) -> (f32, f32) {
+        let a = 2.0 * u - 1.0;
+        let b = 2.0 * v - 1.0;
+        if a == 0.0 && b == 0.0 {
+            return (0.0, 0.0);
+        }
+
+        let (radius, angle) = if a.abs() > b.abs() {
+            (a, std::f32::consts::FRAC_PI_4 * (b / a))
+        } else {
+            (
+                b,
+                std::f32::consts::FRAC_PI_2 - std::f32::consts::FRAC_PI_4 * (a / b),
+            )
+        };
+        (radius * angle.cos(), radius * angle.sin())
+    }
+
+    /// Cosine-weighted direction on the hemisphere around `normal`.
+    pub fn hemisphere_cosine_sample(u: f32, v: f32, normal: Vec3) -> Vec3 {
+        let (x, y) = disk_sample(u, v);
+        let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+
+        // Orthonormal basis around the normal
+        let normal = normal.normalize();
+        let helper = if normal.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let tangent = helper.cross(&normal).normalize();
+        let bitangent = normal.cross(&tangent);
+
+        (tangent * x + bitangent * y + normal * z).normalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        assert!(
+            (actual - expected).length() < 1e-5,
+            "expected {:?}, got {:?}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn quarter_turns_move_the_axes() {
+        let x = Vec3::new(1.0, 0.0, 0.0);
+        let y = Vec3::new(0.0, 1.0, 0.0);
+        let z = Vec3::new(0.0, 0.0, 1.0);
+        assert_close(Quat::from_euler(Vec3::new(0.0, 0.0, FRAC_PI_2)).rotate(x), y);
+        assert_close(Quat::from_euler(Vec3::new(FRAC_PI_2, 0.0, 0.0)).rotate(y), z);
+        assert_close(Quat::from_euler(Vec3::new(0.0, FRAC_PI_2, 0.0)).rotate(z), x);
+    }
+
+    #[test]
+    fn euler_angles_turn_about_x_then_y_then_z() {
+        let angles = Vec3::new(0.3, -0.7, 1.1);
+        let about_x = Quat::from_euler(Vec3::new(angles.x, 0.0, 0.0));
+        let about_y = Quat::from_euler(Vec3::new(0.0, angles.y, 0.0));
+        let about_z = Quat::from_euler(Vec3::new(0.0, 0.0, angles.z));
+        let v = Vec3::new(0.2, -1.0, 0.5);
+        let stepwise = about_z.rotate(about_y.rotate(about_x.rotate(v)));
+        assert_close(Quat::from_euler(angles).rotate(v), stepwise);
+    }
+
+    #[test]
+    fn euler_angles_round_trip() {
+        for angles in [
+            Vec3::zero(),
+            Vec3::new(0.3, -0.7, 1.1),
+            Vec3::new(-2.5, 1.2, 3.0),
+            Vec3::new(0.0, 1.5, -0.4),
+        ] {
+            assert_close(Quat::from_euler(angles).to_euler(), angles);
+        }
+        // At a Y angle of 90 degrees X folds into Z
+        let locked = Quat::from_euler(Vec3::new(0.4, FRAC_PI_2, 0.2));
+        let angles = locked.to_euler();
+        assert_eq!(angles.x, 0.0);
+        let v = Vec3::new(0.3, 0.6, -0.9);
+        assert_close(Quat::from_euler(angles).rotate(v), locked.rotate(v));
+    }
+
+    #[test]
+    fn inverse_undoes_the_rotation() {
+        let rotation = Quat::from_euler(Vec3::new(0.9, -0.2, 2.4));
+        let v = Vec3::new(1.0, 2.0, 3.0);
+        assert_close(rotation.inverse().rotate(rotation.rotate(v)), v);
+        assert!((rotation.rotate(v).length() - v.length()).abs() < 1e-5);
+    }
+
+    fn sample_transform() -> Transform {
+        Transform {
+            pivot: Vec3::new(1.0, -2.0, 0.5),
+            rotation: Quat::from_euler(Vec3::new(0.4, 1.0, -0.6)),
+            scale: Vec3::new(2.0, 0.5, 1.5),
+        }
+    }
+
+    #[test]
+    fn local_point_inverts_point() {
+        let transform = sample_transform();
+        for p in [Vec3::zero(), Vec3::new(3.0, 1.0, -2.0), transform.pivot] {
+            assert_close(transform.local_point(transform.point(p)), p);
+            assert_close(transform.point(transform.local_point(p)), p);
+        }
+        // The pivot stays put
+        assert_close(transform.point(transform.pivot), transform.pivot);
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_uneven_scale() {
+        let transform = sample_transform();
+        // A tilted plane through the pivot in object space
+        let normal = Vec3::new(1.0, 1.0, 0.0).normalize();
+        let tangent = Vec3::new(1.0, -1.0, 0.0);
+        let world_tangent =
+            transform.point(transform.pivot + tangent) - transform.point(transform.pivot);
+        let world_normal = transform.normal(normal);
+        assert!(world_normal.dot(&world_tangent).abs() < 1e-5);
+        assert!((world_normal.length() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn bounds_enclose_the_turned_corners() {
+        let transform = Transform {
+            pivot: Vec3::zero(),
+            rotation: Quat::from_euler(Vec3::new(0.0, 0.0, FRAC_PI_2 / 2.0)),
+            scale: Vec3::one(),
+        };
+        let unit = Aabb::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::one());
+        let bounds = transform.bounds(&unit);
+        let reach = 2.0_f32.sqrt();
+        assert_close(bounds.min, Vec3::new(-reach, -reach, -1.0));
+        assert_close(bounds.max, Vec3::new(reach, reach, 1.0));
+    }
+
+    fn mean_and_variance(values: &[f32]) -> (f32, f32) {
+        let n = values.len() as f32;
+        let mean = values.iter().sum::<f32>() / n;
+        let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / n;
+        (mean, variance)
+    }
+
+    #[test]
+    fn halton_is_the_radical_inverse() {
+        assert_eq!(sampling::halton(0, 2), 0.0);
+        assert_eq!(sampling::halton(1, 2), 0.5);
+        assert_eq!(sampling::halton(2, 2), 0.25);
+        assert_eq!(sampling::halton(3, 2), 0.75);
+        assert!((sampling::halton(1, 3) - 1.0 / 3.0).abs() < 1e-6);
+        assert!((sampling::halton(5, 3) - 7.0 / 9.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn halton_is_uniform_and_stratified() {
+        for base in [2, 3, 5] {
+            let values: Vec<f32> = (1..=1000).map(|i| sampling::halton(i, base)).collect();
+            assert!(values.iter().all(|v| (0.0..1.0).contains(v)));
+            let (mean, variance) = mean_and_variance(&values);
+            assert!((mean - 0.5).abs() < 0.01, "base {} mean {}", base, mean);
+            assert!((variance - 1.0 / 12.0).abs() < 0.005, "base {} variance {}", base, variance);
+
+            // The first base^2 samples put exactly one in each of base^2 bins
+            let bins = base * base;
+            let mut counts = vec![0; bins as usize];
+            for i in 0..bins {
+                counts[(sampling::halton(i, base) * bins as f32) as usize] += 1;
+            }
+            assert!(counts.iter().all(|&c| c == 1), "base {} bins {:?}", base, counts);
+        }
+    }
+
+    #[test]
+    fn hammersley_fills_every_elementary_cell_once() {
+        let count = 256;
+        let mut cells = [[0; 16]; 16];
+        for i in 0..count {
+            let (u, v) = sampling::hammersley(i, count);
+            assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+            cells[(u * 16.0) as usize][(v * 16.0) as usize] += 1;
+        }
+        assert!(cells.iter().flatten().all(|&c| c == 1));
+
+        let us: Vec<f32> = (0..count).map(|i| sampling::hammersley(i, count).0).collect();
+        let vs: Vec<f32> = (0..count).map(|i| sampling::hammersley(i, count).1).collect();
+        for values in [us, vs] {
+            let (mean, variance) = mean_and_variance(&values);
+            assert!((mean - 0.5).abs() < 0.01);
+            assert!((variance - 1.0 / 12.0).abs() < 0.005);
+        }
+    }
+
+    #[test]
+    fn blue_noise_points_stay_apart() {
+        let table = sampling::BLUE_NOISE;
+        for (i, a) in table.iter().enumerate() {
+            assert!(a.iter().all(|c| (0.0..1.0).contains(c)));
+            for b in &table[i + 1..] {
+                // Distance on the torus, since the table is tiled
+                let dx = (a[0] - b[0]).abs().min(1.0 - (a[0] - b[0]).abs());
+                let dy = (a[1] - b[1]).abs().min(1.0 - (a[1] - b[1]).abs());
+                assert!((dx * dx + dy * dy).sqrt() > 0.05, "{:?} and {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn disk_samples_cover_the_disk_evenly() {
+        let count = 1024;
+        let points: Vec<(f32, f32)> = (0..count)
+            .map(|i| {
+                let (u, v) = sampling::hammersley(i, count);
+                sampling::disk_sample(u, v)
+            })
+            .collect();
+        assert!(points.iter().all(|(x, y)| x * x + y * y <= 1.0 + 1e-5));
+
+        let xs: Vec<f32> = points.iter().map(|p| p.0).collect();
+        let ys: Vec<f32> = points.iter().map(|p| p.1).collect();
+        let r2: Vec<f32> = points.iter().map(|(x, y)| x * x + y * y).collect();
+        // A uniform disk has E[x] = 0, Var[x] = 1/4 and E[r^2] = 1/2
+        for values in [xs, ys] {
+            let (mean, variance) = mean_and_variance(&values);
+            assert!(mean.abs() < 0.01, "mean {}", mean);
+            assert!((variance - 0.25).abs() < 0.01, "variance {}", variance);
+        }
+        let (mean_r2, _) = mean_and_variance(&r2);
+        assert!((mean_r2 - 0.5).abs() < 0.01);
+
+        // Equal areas get equal counts: each quadrant, and the inner half
+        let mut quadrants = [0i32; 4];
+        for (x, y) in &points {
+            quadrants[(*x >= 0.0) as usize * 2 + (*y >= 0.0) as usize] += 1;
+        }
+        assert!(quadrants.iter().all(|&q| (q - 256).abs() <= 8), "{:?}", quadrants);
+        let inner = r2.iter().filter(|&&r| r < 0.5).count() as i32;
+        assert!((inner - 512).abs() <= 16, "{} inside r^2 < 1/2", inner);
+    }
+
+    #[test]
+    fn hemisphere_samples_are_cosine_weighted() {
+        let count = 1024;
+        let normals = [
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(1.0, 2.0, -2.0),
+        ];
+        for normal in normals {
+            let n = normal.normalize();
+            let cosines: Vec<f32> = (0..count)
+                .map(|i| {
+                    let (u, v) = sampling::hammersley(i, count);
+                    let dir = sampling::hemisphere_cosine_sample(u, v, normal);
+                    assert!((dir.length() - 1.0).abs() < 1e-4);
+                    dir.dot(&n)
+                })
+                .collect();
+            assert!(cosines.iter().all(|&c| c >= -1e-5));
+            // For a cosine-weighted hemisphere E[cos] = 2/3 and Var[cos] = 1/18
+            let (mean, variance) = mean_and_variance(&cosines);
+            assert!((mean - 2.0 / 3.0).abs() < 0.01, "mean {}", mean);
+            assert!((variance - 1.0 / 18.0).abs() < 0.005, "variance {}", variance);
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! Screen regions invalidated by moving objects.
+//!
+//! Temporal techniques blend each frame with history from earlier ones. When
+//! an object moves, the history where it was and where it now is no longer
+//! matches the scene, but the rest of the image still does. The tracker finds
+//! those regions from the scene itself, comparing each object's bounds with
+//! the previous frame's, so only they need their history thrown away.
+
+use std::collections::HashMap;
+
+use crate::camera::Camera;
+use crate::math::Aabb;
+use crate::scene::{ObjectType, Scene};
+
+/// Regions reported per frame before they are merged into one.
+pub const MAX_REJECT_REGIONS: usize = 8;
+
+/// A screen rectangle `[min_x, min_y, max_x, max_y]` in image pixels, origin
+/// top-left.
+pub type Region = [f32; 4];
+
+/// What changed in the scene since the tracker's last update.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Motion {
+    pub regions: Vec<Region>,
+    /// Whether the regions cover every change: nothing but the bounds of
+    /// finite objects changed, so the rest of the image still holds. Shadows
+    /// and reflections of the moved objects outside them are not covered.
+    pub contained: bool,
+}
+
+/// Bounds of every finite object as of the previous update.
+#[derive(Default)]
+pub struct MotionTracker {
+    previous: HashMap<u32, Aabb>,
+    // Scene revision as of the previous update
+    revision: u32,
+}
+
+impl MotionTracker {
+    /// A tracker that treats the objects of `scene` as already in place.
+    pub fn new(scene: &Scene) -> Self {
+        Self {
+            previous: object_bounds(scene),
+            revision: scene.revision(),
+        }
+    }
+
+    /// Regions of a `width` x `height` image covering each object that moved,
+    /// appeared or disappeared since the last update, old and new positions
+    /// together, and remembers the current bounds for the next one. Past
+    /// `MAX_REJECT_REGIONS` they are merged into a single region. Planes have
+    /// no bounds and are never reported.
+    pub fn update(&mut self, scene: &Scene, camera: &Camera, width: f32, height: f32) -> Motion {
+        let current = object_bounds(scene);
+
+        let mut moved: Vec<u32> = Vec::new();
+        let mut changed: Vec<Aabb> = Vec::new();
+        for (id, bounds) in &current {
+            match self.previous.get(id) {
+                Some(old) if old == bounds => continue,
+                Some(old) => changed.push(old.union(bounds)),
+                None => changed.push(*bounds),
+            }
+            moved.push(*id);
+        }
+        for (id, old) in &self.previous {
+            if !current.contains_key(id) {
+                changed.push(*old);
+                moved.push(*id);
+            }
+        }
+        let contained = scene
+            .object_changes_since(self.revision)
+            .is_some_and(|ids| ids.iter().all(|id| moved.contains(id)));
+        self.previous = current;
+        self.revision = scene.revision();
+
+        let mut regions: Vec<Region> = changed
+            .iter()
+            .filter_map(|bounds| camera.project_bounds(bounds, width, height))
+            .map(|(min_x, min_y, max_x, max_y)| {
+                [
+                    min_x.max(0.0),
+                    min_y.max(0.0),
+                    max_x.min(width),
+                    max_y.min(height),
+                ]
+            })
+            .filter(|[min_x, min_y, max_x, max_y]| min_x < max_x && min_y < max_y)
+            .collect();
+
+        if regions.len() > MAX_REJECT_REGIONS {
+            let merged = regions.iter().fold(regions[0], |a, b| {
+                [
+                    a[0].min(b[0]),
+                    a[1].min(b[1]),
+                    a[2].max(b[2]),
+                    a[3].max(b[3]),
+                ]
+            });
+            regions = vec![merged];
+        }
+        Motion { regions, contained }
+    }
+}
+
+fn object_bounds(scene: &Scene) -> HashMap<u32, Aabb> {
+    let spheres = scene.spheres.iter().map(|o| o.bounds());
+    let boxes = scene.boxes.iter().map(|o| o.bounds());
+    let cylinders = scene.cylinders.iter().map(|o| o.bounds());
+    let triangles = scene.triangles.iter().map(|o| o.bounds());
+    let cones = scene.cones.iter().map(|o| o.bounds());
+    let disks = scene.disks.iter().map(|o| o.bounds());
+
+    let ids = |object_type: ObjectType| (0..).map(move |index| object_type.object_id(index));
+    ids(ObjectType::Sphere)
+        .zip(spheres)
+        .chain(ids(ObjectType::Box).zip(boxes))
+        .chain(ids(ObjectType::Cylinder).zip(cylinders))
+        .chain(ids(ObjectType::Triangle).zip(triangles))
+        .chain(ids(ObjectType::Cone).zip(cones))
+        .chain(ids(ObjectType::Disk).zip(disks))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::math::Vec3;
+    use crate::scene::{Light, Sphere};
+
+    fn setup() -> (Scene, Camera) {
+        let mut scene = Scene::new();
+        scene.add_sphere(Sphere::new(
+            Vec3::zero(),
+            1.0,
+            Material::lambertian(Vec3::one()),
+        ));
+        scene.add_sphere(Sphere::new(
+            Vec3::new(3.0, 0.0, 0.0),
+            1.0,
+            Material::lambertian(Vec3::one()),
+        ));
+        let camera = Camera::new(Vec3::new(0.0, 0.0, 10.0), Vec3::zero(), 1.0);
+        (scene, camera)
+    }
+
+    #[test]
+    fn a_still_scene_reports_nothing() {
+        let (scene, camera) = setup();
+        let mut tracker = MotionTracker::new(&scene);
+        let motion = tracker.update(&scene, &camera, 100.0, 100.0);
+        assert!(motion.regions.is_empty());
+        assert!(motion.contained);
+    }
+
+    #[test]
+    fn moving_an_object_is_contained_to_its_region() {
+        let (mut scene, camera) = setup();
+        let mut tracker = MotionTracker::new(&scene);
+
+        scene.spheres[0].center = Vec3::new(0.0, 1.0, 0.0);
+        scene.touch(ObjectType::Sphere, 0);
+        let motion = tracker.update(&scene, &camera, 100.0, 100.0);
+        assert!(motion.contained);
+        assert_eq!(motion.regions.len(), 1);
+
+        // The region spans the old and the new position, and not the sphere
+        // that stayed put on the right
+        let [min_x, min_y, max_x, max_y] = motion.regions[0];
+        let now = camera
+            .project_bounds(&scene.spheres[0].bounds(), 100.0, 100.0)
+            .unwrap();
+        assert!(min_x <= now.0 && min_y <= now.1);
+        assert!(max_x >= now.2 && max_y >= now.3);
+        assert!(max_x < 75.0);
+
+        // Reported once
+        assert!(
+            tracker
+                .update(&scene, &camera, 100.0, 100.0)
+                .regions
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn other_changes_are_not_contained() {
+        let (mut scene, camera) = setup();
+        let mut tracker = MotionTracker::new(&scene);
+
+        // A new material shows outside any region that moved
+        scene.spheres[1].material = Material::lambertian(Vec3::new(1.0, 0.0, 0.0));
+        scene.touch(ObjectType::Sphere, 1);
+        assert!(!tracker.update(&scene, &camera, 100.0, 100.0).contained);
+
+        // A light changes the whole image, even alongside a move
+        scene.spheres[0].center = Vec3::new(0.0, 1.0, 0.0);
+        scene.touch(ObjectType::Sphere, 0);
+        scene.add_light(Light::new(Vec3::new(0.0, 5.0, 0.0), Vec3::one(), 10.0));
+        let motion = tracker.update(&scene, &camera, 100.0, 100.0);
+        assert_eq!(motion.regions.len(), 1);
+        assert!(!motion.contained);
+
+        scene.touch_settings();
+        assert!(!tracker.update(&scene, &camera, 100.0, 100.0).contained);
+    }
+
+    #[test]
+    fn removed_objects_are_contained() {
+        let (mut scene, camera) = setup();
+        let mut tracker = MotionTracker::new(&scene);
+
+        assert!(scene.remove_object(ObjectType::Sphere, 1));
+        let motion = tracker.update(&scene, &camera, 100.0, 100.0);
+        assert_eq!(motion.regions.len(), 1);
+        assert!(motion.contained);
+    }
+}
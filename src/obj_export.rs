@@ -0,0 +1,443 @@
+//! Wavefront OBJ and MTL export, for taking scenes back into modeling tools.
+//!
+//! Every primitive is tessellated. Spheres become UV spheres, boxes 12
+//! triangles, cylinders capped prisms (both rotated and scaled as drawn),
+//! cones capped pyramids, disks polygons (rings a band of quads) and planes
+//! large quads; triangles are written as they are. Each object is an
+//! `o`/`g` group named after the object, or its kind and id if it has no
+//! name. Consecutive triangles with the same name (or none) make one
+//! group. Materials are shared by identical objects and described in the
+//! companion MTL. Lights,
+//! volumes, instanced grids and textures have no OBJ equivalent and are
+//! left out, as are primitives too degenerate to tessellate (zero radius,
+//! size or axis, or non-finite values). CSG combinations are not evaluated:
+//! their operands are written whole, as separate objects.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::blender::CUBE_FACES;
+use crate::material::{Material, MaterialType};
+use crate::math::{Transform, Vec3};
+use crate::scene::Scene;
+
+/// Half the side of the quad standing in for an infinite plane, in meters.
+const PLANE_HALF_SIZE_METERS: f32 = 100.0;
+
+/// The scene as OBJ text. `segments` is the number of divisions around
+/// spheres, cylinders, cones and disks (spheres get half as many from pole to
+/// pole), and `mtl_file` the name the MTL from `write_mtl` will be saved
+/// under.
+pub fn write_obj(scene: &Scene, segments: u32, mtl_file: &str) -> String {
+    let materials = MaterialTable::new(scene);
+    let mut writer = ObjWriter {
+        text: String::new(),
+        vertex_count: 0,
+        materials: &materials,
+        transform: None,
+    };
+    let _ = writeln!(writer.text, "# Exported from raytracer");
+    let _ = writeln!(writer.text, "mtllib {}", mtl_file);
+
+    let segments = segments.max(3) as usize;
+    for sphere in &scene.spheres {
+        let scale = sphere.scale;
+        if finite_positive(sphere.radius)
+            && sphere.center.is_finite()
+            && [scale.x, scale.y, scale.z].into_iter().all(finite_positive)
+        {
+            let name = object_name(&sphere.meta.name, "sphere", sphere.meta.id);
+            writer.begin(&name, &sphere.material);
+            writer.sphere(sphere.center, scale * sphere.radius, segments);
+        }
+    }
+    for plane in &scene.planes {
+        if plane.point.is_finite() && finite_positive(plane.normal.length()) {
+            let name = object_name(&plane.meta.name, "plane", plane.meta.id);
+            writer.begin(&name, &plane.material);
+            let half_size = PLANE_HALF_SIZE_METERS * scene.world_scale;
+            writer.plane(plane.point, plane.normal.normalize(), half_size);
+        }
+    }
+    for box_obj in &scene.boxes {
+        let size = box_obj.size;
+        if box_obj.center.is_finite() && [size.x, size.y, size.z].into_iter().all(finite_positive) {
+            let name = object_name(&box_obj.meta.name, "box", box_obj.meta.id);
+            writer.begin(&name, &box_obj.material);
+            writer.transform = box_obj.transform();
+            writer.cube(box_obj.center, size * 0.5);
+        }
+    }
+    for cylinder in &scene.cylinders {
+        if cylinder.base.is_finite()
+            && finite_positive(cylinder.radius)
+            && finite_positive(cylinder.axis.length())
+        {
+            let name = object_name(&cylinder.meta.name, "cylinder", cylinder.meta.id);
+            writer.begin(&name, &cylinder.material);
+            writer.transform = cylinder.transform();
+            writer.cylinder(cylinder.base, cylinder.axis, cylinder.radius, segments);
+        }
+    }
+    for cone in &scene.cones {
+        if cone.apex.is_finite()
+            && finite_positive(cone.radius)
+            && finite_positive(cone.axis.length())
+        {
+            let name = object_name(&cone.meta.name, "cone", cone.meta.id);
+            writer.begin(&name, &cone.material);
+            writer.cone(cone.apex, cone.axis, cone.radius, segments);
+        }
+    }
+    for disk in &scene.disks {
+        if disk.center.is_finite()
+            && finite_positive(disk.radius)
+            && finite_positive(disk.normal.length())
+        {
+            let name = object_name(&disk.meta.name, "disk", disk.meta.id);
+            writer.begin(&name, &disk.material);
+            let inner_radius = disk.inner_radius.clamp(0.0, disk.radius);
+            writer.disk(
+                disk.center,
+                disk.normal.normalize(),
+                disk.radius,
+                inner_radius,
+                segments,
+            );
+        }
+    }
+
+    let mut group = None;
+    let mut material = None;
+    for triangle in &scene.triangles {
+        let vertices = [triangle.v0, triangle.v1, triangle.v2];
+        if !vertices.iter().all(Vec3::is_finite) {
+            continue;
+        }
+        if group.as_ref() != Some(&triangle.meta.name) {
+            let name = object_name(&triangle.meta.name, "triangles", triangle.meta.id);
+            writer.begin(&name, &triangle.material);
+            group = Some(triangle.meta.name.clone());
+            material = Some(materials.index(&triangle.material));
+        } else if material != Some(materials.index(&triangle.material)) {
+            writer.use_material(&triangle.material);
+            material = Some(materials.index(&triangle.material));
+        }
+        let first = writer.vertices(&vertices);
+        writer.face(&[first, first + 1, first + 2]);
+    }
+
+    writer.text
+}
+
+/// The materials `write_obj` refers to, as MTL text.
+pub fn write_mtl(scene: &Scene) -> String {
+    let mut text = String::from("# Exported from raytracer\n");
+    for (index, material) in MaterialTable::new(scene).materials.iter().enumerate() {
+        let [r, g, b] = [material.albedo.x, material.albedo.y, material.albedo.z];
+        let kind = match material.material_type {
+            MaterialType::Lambertian => "lambertian",
+            MaterialType::Metal => "metal",
+            MaterialType::Dielectric => "dielectric",
+            MaterialType::Emissive => "emissive",
+        };
+        let _ = writeln!(text, "\nnewmtl {}", material_name(index));
+        let _ = writeln!(text, "# {}", kind);
+        let _ = writeln!(text, "Kd {} {} {}", r, g, b);
+        match material.material_type {
+            MaterialType::Metal => {
+                let _ = writeln!(text, "Ks {} {} {}", r, g, b);
+            }
+            MaterialType::Dielectric => {
+                let _ = writeln!(text, "Ks 1 1 1");
+            }
+            _ => {
+                let _ = writeln!(text, "Ks 0 0 0");
+            }
+        }
+        // Specular exponents run to 1000 for a mirror
+        let smoothness = 1.0 - material.roughness;
+        let _ = writeln!(text, "Ns {}", smoothness * smoothness * 1000.0);
+        let _ = writeln!(text, "Ni {}", material.ior);
+        let _ = writeln!(text, "d {}", material.opacity);
+
+        let emission = match material.material_type {
+            MaterialType::Emissive => material.albedo * material.emission_strength,
+            _ => material.emission * material.emission_strength,
+        };
+        if emission.length_squared() > 0.0 {
+            let _ = writeln!(text, "Ke {} {} {}", emission.x, emission.y, emission.z);
+        }
+        // MTL illumination models: reflective, refractive, else highlights only
+        let illum = match material.material_type {
+            MaterialType::Metal => 3,
+            MaterialType::Dielectric => 7,
+            _ => 2,
+        };
+        let _ = writeln!(text, "illum {}", illum);
+    }
+    text
+}
+
+/// The scene's distinct materials, in the order objects are written.
+struct MaterialTable {
+    materials: Vec<Material>,
+    // Keyed by the material's JSON, as materials aren't `Eq` or `Hash`
+    indices: HashMap<String, usize>,
+}
+
+impl MaterialTable {
+    fn new(scene: &Scene) -> Self {
+        let mut table = Self {
+            materials: Vec::new(),
+            indices: HashMap::new(),
+        };
+        let materials = scene
+            .spheres
+            .iter()
+            .map(|sphere| &sphere.material)
+            .chain(scene.planes.iter().map(|plane| &plane.material))
+            .chain(scene.boxes.iter().map(|box_obj| &box_obj.material))
+            .chain(scene.cylinders.iter().map(|cylinder| &cylinder.material))
+            .chain(scene.triangles.iter().map(|triangle| &triangle.material))
+            .chain(scene.cones.iter().map(|cone| &cone.material))
+            .chain(scene.disks.iter().map(|disk| &disk.material));
+        for material in materials {
+            let key = serde_json::to_string(material).unwrap_or_default();
+            if !table.indices.contains_key(&key) {
+                table.indices.insert(key, table.materials.len());
+                table.materials.push(*material);
+            }
+        }
+        table
+    }
+
+    fn index(&self, material: &Material) -> usize {
+        let key = serde_json::to_string(material).unwrap_or_default();
+        self.indices.get(&key).copied().unwrap_or_default()
+    }
+}
+
+fn material_name(index: usize) -> String {
+    format!("material_{}", index)
+}
+
+/// An OBJ name for an object: its own with whitespace replaced, or its kind
+/// and id.
+fn object_name(name: &str, kind: &str, id: u32) -> String {
+    if name.trim().is_empty() {
+        format!("{}_{}", kind, id)
+    } else {
+        name.split_whitespace().collect::<Vec<_>>().join("_")
+    }
+}
+
+fn finite_positive(value: f32) -> bool {
+    value.is_finite() && value > 0.0
+}
+
+struct ObjWriter<'a> {
+    text: String,
+    vertex_count: usize,
+    materials: &'a MaterialTable,
+    // Rotation and scale of the object being written, applied to its vertices
+    transform: Option<Transform>,
+}
+
+impl ObjWriter<'_> {
+    /// Starts the group of a new object, without a transform.
+    fn begin(&mut self, name: &str, material: &Material) {
+        let _ = writeln!(self.text, "o {}\ng {}", name, name);
+        self.use_material(material);
+        self.transform = None;
+    }
+
+    fn use_material(&mut self, material: &Material) {
+        let index = self.materials.index(material);
+        let _ = writeln!(self.text, "usemtl {}", material_name(index));
+    }
+
+    /// Writes the vertices and returns the OBJ index of the first.
+    fn vertices(&mut self, vertices: &[Vec3]) -> usize {
+        for v in vertices {
+            let v = self.transform.map_or(*v, |t| t.point(*v));
+            let _ = writeln!(self.text, "v {} {} {}", v.x, v.y, v.z);
+        }
+        let first = self.vertex_count + 1;
+        self.vertex_count += vertices.len();
+        first
+    }
+
+    /// A face through OBJ vertex indices, counter-clockwise seen from the
+    /// side it faces.
+    fn face(&mut self, indices: &[usize]) {
+        self.text.push('f');
+        for index in indices {
+            let _ = write!(self.text, " {}", index);
+        }
+        self.text.push('\n');
+    }
+
+    /// The ellipsoid with `semi_axes`, a UV sphere when they are equal.
+    fn sphere(&mut self, center: Vec3, semi_axes: Vec3, segments: usize) {
+        let rings = (segments / 2).max(2);
+        let mut vertices = vec![center + Vec3::new(0.0, semi_axes.y, 0.0)];
+        for ring in 1..rings {
+            let theta = std::f32::consts::PI * ring as f32 / rings as f32;
+            for segment in 0..segments {
+                let phi = std::f32::consts::TAU * segment as f32 / segments as f32;
+                let direction = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                vertices.push(center + direction * semi_axes);
+            }
+        }
+        vertices.push(center - Vec3::new(0.0, semi_axes.y, 0.0));
+
+        let top = self.vertices(&vertices);
+        let bottom = top + vertices.len() - 1;
+        let at = |ring: usize, segment: usize| top + 1 + (ring - 1) * segments + segment % segments;
+        for segment in 0..segments {
+            self.face(&[top, at(1, segment + 1), at(1, segment)]);
+            for ring in 1..rings - 1 {
+                self.face(&[
+                    at(ring, segment),
+                    at(ring, segment + 1),
+                    at(ring + 1, segment + 1),
+                    at(ring + 1, segment),
+                ]);
+            }
+            self.face(&[bottom, at(rings - 1, segment), at(rings - 1, segment + 1)]);
+        }
+    }
+
+    fn plane(&mut self, point: Vec3, normal: Vec3, half_size: f32) {
+        let (u, v) = perpendicular_basis(normal);
+        let first = self.vertices(&[
+            point - u * half_size - v * half_size,
+            point + u * half_size - v * half_size,
+            point + u * half_size + v * half_size,
+            point - u * half_size + v * half_size,
+        ]);
+        self.face(&[first, first + 1, first + 2, first + 3]);
+    }
+
+    /// The box from `center - half_size` to `center + half_size`.
+    fn cube(&mut self, center: Vec3, half_size: Vec3) {
+        let corners: Vec<Vec3> = (0..8)
+            .map(|index| {
+                let sign = |bit: usize| if index & (1 << bit) != 0 { 1.0 } else { -1.0 };
+                center
+                    + Vec3::new(
+                        half_size.x * sign(0),
+                        half_size.y * sign(1),
+                        half_size.z * sign(2),
+                    )
+            })
+            .collect();
+        let first = self.vertices(&corners);
+        for [a, b, c, d] in CUBE_FACES {
+            self.face(&[first + a, first + b, first + c]);
+            self.face(&[first + a, first + c, first + d]);
+        }
+    }
+
+    fn cylinder(&mut self, base: Vec3, axis: Vec3, radius: f32, segments: usize) {
+        let (u, v) = perpendicular_basis(axis.normalize());
+        let ring: Vec<Vec3> = (0..segments)
+            .map(|segment| {
+                let phi = std::f32::consts::TAU * segment as f32 / segments as f32;
+                base + (u * phi.cos() + v * phi.sin()) * radius
+            })
+            .collect();
+        let top: Vec<Vec3> = ring.iter().map(|&point| point + axis).collect();
+        let bottom = self.vertices(&ring);
+        let top = self.vertices(&top);
+
+        for segment in 0..segments {
+            let next = (segment + 1) % segments;
+            self.face(&[bottom + segment, bottom + next, top + next, top + segment]);
+        }
+        self.face(
+            &(0..segments)
+                .map(|segment| top + segment)
+                .collect::<Vec<_>>(),
+        );
+        self.face(
+            &(0..segments)
+                .rev()
+                .map(|segment| bottom + segment)
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    fn cone(&mut self, apex: Vec3, axis: Vec3, radius: f32, segments: usize) {
+        let (u, v) = perpendicular_basis(axis.normalize());
+        let mut points = vec![apex];
+        points.extend((0..segments).map(|segment| {
+            let phi = std::f32::consts::TAU * segment as f32 / segments as f32;
+            apex + axis + (u * phi.cos() + v * phi.sin()) * radius
+        }));
+        let apex = self.vertices(&points);
+        let rim = apex + 1;
+
+        for segment in 0..segments {
+            let next = (segment + 1) % segments;
+            self.face(&[apex, rim + next, rim + segment]);
+        }
+        self.face(
+            &(0..segments)
+                .map(|segment| rim + segment)
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    /// A polygon facing `normal`, or with a hole of `inner_radius` a ring of
+    /// quads.
+    fn disk(
+        &mut self,
+        center: Vec3,
+        normal: Vec3,
+        radius: f32,
+        inner_radius: f32,
+        segments: usize,
+    ) {
+        let (u, v) = perpendicular_basis(normal);
+        let circle = |radius: f32| -> Vec<Vec3> {
+            (0..segments)
+                .map(|segment| {
+                    let phi = std::f32::consts::TAU * segment as f32 / segments as f32;
+                    center + (u * phi.cos() + v * phi.sin()) * radius
+                })
+                .collect()
+        };
+        let outer = self.vertices(&circle(radius));
+        if inner_radius > 0.0 {
+            let inner = self.vertices(&circle(inner_radius));
+            for segment in 0..segments {
+                let next = (segment + 1) % segments;
+                self.face(&[inner + segment, outer + segment, outer + next, inner + next]);
+            }
+        } else {
+            self.face(
+                &(0..segments)
+                    .map(|segment| outer + segment)
+                    .collect::<Vec<_>>(),
+            );
+        }
+    }
+}
+
+/// Unit vectors `u` and `v` with `u x v = normal`, for a unit `normal`.
+fn perpendicular_basis(normal: Vec3) -> (Vec3, Vec3) {
+    let helper = if normal.x.abs() < 0.9 {
+        Vec3::new(1.0, 0.0, 0.0)
+    } else {
+        Vec3::new(0.0, 1.0, 0.0)
+    };
+    let u = helper.cross(&normal).normalize();
+    let v = normal.cross(&u);
+    (u, v)
+}
@@ -0,0 +1,464 @@
+//! PLY import: the `vertex` and `face` elements of ascii and binary files,
+//! as produced by scanners and photogrammetry tools.
+//!
+//! Vertices need `x`, `y` and `z`; `red`, `green` and `blue` are read when
+//! present, integer colors scaled to 0-1 by their type's range. Faces need a
+//! `vertex_indices` (or `vertex_index`) list. Every other element and
+//! property is read past using its declared type and ignored.
+
+use crate::material::Material;
+use crate::math::Vec3;
+use crate::scene::{SceneError, Triangle};
+
+/// What a PLY file contributes to a mesh.
+pub struct PlyMesh {
+    pub vertices: Vec<Vec3>,
+    pub triangles: Vec<Triangle>,
+}
+
+/// Reads the triangles of a PLY file. Polygons are fanned into triangles
+/// with `material`; if the vertices have colors, each face's albedo is the
+/// average of its corners' colors instead of the material's.
+pub fn parse(data: &[u8], material: Material) -> Result<PlyMesh, SceneError> {
+    let header = Header::parse(data)?;
+    let body = &data[header.body_start..];
+    let mut reader = match header.format {
+        Format::Ascii => {
+            let text = std::str::from_utf8(body)
+                .map_err(|_| SceneError::new("The ascii PLY data isn't valid UTF-8"))?;
+            Reader::Ascii {
+                lines: text.lines().enumerate(),
+                tokens: "".split_whitespace(),
+                first_line: header.lines + 1,
+                line_number: header.lines,
+            }
+        }
+        Format::BinaryLittleEndian | Format::BinaryBigEndian => Reader::Binary {
+            data: body,
+            offset: 0,
+            big_endian: header.format == Format::BinaryBigEndian,
+        },
+    };
+
+    let mut vertices = Vec::new();
+    let mut colors = Vec::new();
+    // Every face's corners back to back, and how many each face has
+    let mut corners = Vec::new();
+    let mut face_sizes = Vec::new();
+
+    for element in &header.elements {
+        let vertex = (element.name == "vertex").then(|| VertexLayout::new(element));
+        let face_indices = match element.name.as_str() {
+            "face" => index_list(element),
+            _ => None,
+        };
+        let mut values = vec![0.0; element.properties.len()];
+
+        for index in 0..element.count {
+            let context =
+                |e: String| SceneError::new(format!("PLY {} {}: {}", element.name, index, e));
+            for (position, property) in element.properties.iter().enumerate() {
+                match property.kind {
+                    PropertyKind::Scalar(scalar) => {
+                        values[position] = reader.value(scalar).map_err(context)?;
+                    }
+                    PropertyKind::List { count, item } => {
+                        let length = reader.value(count).map_err(context)?;
+                        if !(length >= 0.0 && length.fract() == 0.0) {
+                            return Err(context(format!(
+                                "'{}' has a list length of {}",
+                                property.name, length
+                            )));
+                        }
+                        let is_face = face_indices == Some(position);
+                        for _ in 0..length as usize {
+                            let value = reader.value(item).map_err(context)?;
+                            if is_face {
+                                corners.push(value);
+                            }
+                        }
+                        if is_face {
+                            face_sizes.push(length as usize);
+                        }
+                    }
+                }
+            }
+
+            if let Some(layout) = &vertex {
+                let [x, y, z] = layout.position.map(|i| values[i] as f32);
+                vertices.push(Vec3::new(x, y, z));
+                if let Some(color) = layout.color {
+                    let [r, g, b] =
+                        color.map(|(i, scale)| (values[i] / scale).clamp(0.0, 1.0) as f32);
+                    colors.push(Vec3::new(r, g, b));
+                }
+            }
+        }
+    }
+
+    if face_sizes.is_empty() {
+        return Err(SceneError::new(
+            "The PLY file has no faces; point clouds can't be turned into triangles",
+        ));
+    }
+
+    let mut triangles = Vec::new();
+    let mut start = 0;
+    for (face, &size) in face_sizes.iter().enumerate() {
+        let indices = &corners[start..start + size];
+        start += size;
+        if size < 3 {
+            return Err(SceneError::new(format!(
+                "PLY face {} has {} vertices; a face needs at least three",
+                face, size
+            )));
+        }
+        let face_vertices = indices
+            .iter()
+            .map(|&index| {
+                vertices
+                    .get(index as usize)
+                    .filter(|_| index >= 0.0 && index.fract() == 0.0)
+                    .map(|_| index as usize)
+                    .ok_or_else(|| {
+                        SceneError::new(format!(
+                            "PLY face {} refers to vertex {}, but the file has {}",
+                            face,
+                            index,
+                            vertices.len()
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut face_material = material;
+        if !colors.is_empty() {
+            let sum = face_vertices
+                .iter()
+                .fold(Vec3::zero(), |sum, &index| sum + colors[index]);
+            face_material.albedo = sum / size as f32;
+        }
+        // Fanned around the first corner, which splits convex polygons
+        for pair in face_vertices[1..].windows(2) {
+            triangles.push(Triangle::new(
+                vertices[face_vertices[0]],
+                vertices[pair[0]],
+                vertices[pair[1]],
+                face_material,
+            ));
+        }
+    }
+
+    Ok(PlyMesh {
+        vertices,
+        triangles,
+    })
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    /// The value that stands for full intensity in a color of this type.
+    fn color_scale(self) -> f64 {
+        match self {
+            Self::I8 => i8::MAX as f64,
+            Self::U8 => u8::MAX as f64,
+            Self::I16 => i16::MAX as f64,
+            Self::U16 => u16::MAX as f64,
+            Self::I32 => i32::MAX as f64,
+            Self::U32 => u32::MAX as f64,
+            Self::F32 | Self::F64 => 1.0,
+        }
+    }
+
+    /// A value of this type from its little-endian bytes, zero-padded to eight.
+    fn decode(self, bytes: [u8; 8]) -> f64 {
+        let [b0, b1, b2, b3, ..] = bytes;
+        match self {
+            Self::I8 => b0 as i8 as f64,
+            Self::U8 => b0 as f64,
+            Self::I16 => i16::from_le_bytes([b0, b1]) as f64,
+            Self::U16 => u16::from_le_bytes([b0, b1]) as f64,
+            Self::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Self::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Self::F32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            Self::F64 => f64::from_le_bytes(bytes),
+        }
+    }
+}
+
+enum PropertyKind {
+    Scalar(Scalar),
+    List { count: Scalar, item: Scalar },
+}
+
+struct Property {
+    name: String,
+    kind: PropertyKind,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+    // Header line declaring it, for errors
+    line: usize,
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>,
+    // Number of header lines and the byte the data starts at
+    lines: usize,
+    body_start: usize,
+}
+
+impl Header {
+    fn parse(data: &[u8]) -> Result<Self, SceneError> {
+        if !data.starts_with(b"ply") {
+            return Err(SceneError::new(
+                "Not a PLY file: the data doesn't start with 'ply'",
+            ));
+        }
+
+        let mut format = None;
+        let mut elements: Vec<Element> = Vec::new();
+        let mut offset = 0;
+        let mut line_number = 0;
+        loop {
+            let Some(end) = data[offset..].iter().position(|&b| b == b'\n') else {
+                return Err(SceneError::new("The PLY header has no end_header line"));
+            };
+            line_number += 1;
+            let error = |message: String| {
+                SceneError::new(format!("PLY header line {}: {}", line_number, message))
+            };
+            let line = std::str::from_utf8(&data[offset..offset + end])
+                .map_err(|_| error("isn't valid text".to_string()))?;
+            offset += end + 1;
+            let parts: Vec<&str> = line.split_whitespace().collect();
+
+            match parts.as_slice() {
+                [] | ["ply"] | ["comment", ..] | ["obj_info", ..] => {}
+                ["format", kind, version] => {
+                    if !version.starts_with("1.") {
+                        return Err(error(format!("format version {} isn't supported", version)));
+                    }
+                    format = Some(match *kind {
+                        "ascii" => Format::Ascii,
+                        "binary_little_endian" => Format::BinaryLittleEndian,
+                        "binary_big_endian" => Format::BinaryBigEndian,
+                        _ => return Err(error(format!("unknown format '{}'", kind))),
+                    });
+                }
+                ["element", name, count] => {
+                    let count = count
+                        .parse()
+                        .map_err(|_| error(format!("invalid element count '{}'", count)))?;
+                    elements.push(Element {
+                        name: name.to_string(),
+                        count,
+                        properties: Vec::new(),
+                        line: line_number,
+                    });
+                }
+                ["property", rest @ ..] => {
+                    let scalar = |name: &str| {
+                        Scalar::parse(name)
+                            .ok_or_else(|| error(format!("unknown property type '{}'", name)))
+                    };
+                    let (name, kind) = match rest {
+                        ["list", count, item, name] => (
+                            name,
+                            PropertyKind::List {
+                                count: scalar(count)?,
+                                item: scalar(item)?,
+                            },
+                        ),
+                        [kind, name] if *kind != "list" => {
+                            (name, PropertyKind::Scalar(scalar(kind)?))
+                        }
+                        _ => return Err(error(format!("malformed property '{}'", line.trim()))),
+                    };
+                    let element = elements
+                        .last_mut()
+                        .ok_or_else(|| error("a property comes before any element".to_string()))?;
+                    element.properties.push(Property {
+                        name: name.to_string(),
+                        kind,
+                    });
+                }
+                ["end_header"] => break,
+                _ => return Err(error(format!("unexpected '{}'", line.trim()))),
+            }
+        }
+
+        let format = format.ok_or_else(|| SceneError::new("The PLY header has no format line"))?;
+        for element in &elements {
+            let error = |message: &str| {
+                SceneError::new(format!("PLY header line {}: {}", element.line, message))
+            };
+            match element.name.as_str() {
+                "vertex"
+                    if VertexLayout::find(element, "x").is_none()
+                        || VertexLayout::find(element, "y").is_none()
+                        || VertexLayout::find(element, "z").is_none() =>
+                {
+                    return Err(error("the vertex element needs x, y and z properties"));
+                }
+                "face" if index_list(element).is_none() => {
+                    return Err(error("the face element needs a vertex_indices list"));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            format,
+            elements,
+            lines: line_number,
+            body_start: offset,
+        })
+    }
+}
+
+/// Where a vertex element's position and color are among its properties.
+struct VertexLayout {
+    position: [usize; 3],
+    // Each channel's property and the value meaning full intensity
+    color: Option<[(usize, f64); 3]>,
+}
+
+impl VertexLayout {
+    fn new(element: &Element) -> Self {
+        let position = ["x", "y", "z"].map(|name| Self::find(element, name).unwrap_or_default());
+        let channel = |names: [&str; 2]| {
+            let index = names.iter().find_map(|name| Self::find(element, name))?;
+            match element.properties[index].kind {
+                PropertyKind::Scalar(scalar) => Some((index, scalar.color_scale())),
+                PropertyKind::List { .. } => None,
+            }
+        };
+        let color = match [
+            channel(["red", "diffuse_red"]),
+            channel(["green", "diffuse_green"]),
+            channel(["blue", "diffuse_blue"]),
+        ] {
+            [Some(r), Some(g), Some(b)] => Some([r, g, b]),
+            _ => None,
+        };
+        Self { position, color }
+    }
+
+    /// The index of the scalar property `name`.
+    fn find(element: &Element, name: &str) -> Option<usize> {
+        element.properties.iter().position(|property| {
+            property.name == name && matches!(property.kind, PropertyKind::Scalar(_))
+        })
+    }
+}
+
+/// The index of a face element's list of vertex indices.
+fn index_list(element: &Element) -> Option<usize> {
+    element.properties.iter().position(|property| {
+        matches!(property.kind, PropertyKind::List { .. })
+            && matches!(property.name.as_str(), "vertex_indices" | "vertex_index")
+    })
+}
+
+enum Reader<'a> {
+    Ascii {
+        lines: std::iter::Enumerate<std::str::Lines<'a>>,
+        tokens: std::str::SplitWhitespace<'a>,
+        // Line numbers in the whole file, for errors
+        first_line: usize,
+        line_number: usize,
+    },
+    Binary {
+        data: &'a [u8],
+        offset: usize,
+        big_endian: bool,
+    },
+}
+
+impl Reader<'_> {
+    fn value(&mut self, scalar: Scalar) -> Result<f64, String> {
+        match self {
+            Self::Ascii {
+                lines,
+                tokens,
+                first_line,
+                line_number,
+            } => {
+                let token = loop {
+                    if let Some(token) = tokens.next() {
+                        break token;
+                    }
+                    let (index, line) = lines.next().ok_or("the file ends early")?;
+                    *tokens = line.split_whitespace();
+                    *line_number = *first_line + index;
+                };
+                token
+                    .parse::<f64>()
+                    .map_err(|_| format!("line {}: invalid number '{}'", line_number, token))
+            }
+            Self::Binary {
+                data,
+                offset,
+                big_endian,
+            } => {
+                let size = scalar.size();
+                let bytes = data
+                    .get(*offset..*offset + size)
+                    .ok_or_else(|| format!("the file ends early, at data byte {}", offset))?;
+                *offset += size;
+                let mut value = [0; 8];
+                value[..size].copy_from_slice(bytes);
+                if *big_endian {
+                    value[..size].reverse();
+                }
+                Ok(scalar.decode(value))
+            }
+        }
+    }
+}
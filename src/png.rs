@@ -0,0 +1,315 @@
+//! Minimal PNG encoder for frame captures.
+//!
+//! Image data is stored with uncompressed deflate blocks: captures are meant to
+//! be saved or post-processed, and this keeps the encoder dependency-free.
+
+use crate::color;
+use serde::Deserialize;
+
+/// Options accepted by `capture_frame_png_with_options`.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct CaptureOptions {
+    pub color_space: PngColorSpace,
+    /// Defaults to on while the background is transparent
+    pub include_alpha: Option<bool>,
+    /// Keep the reference image overlay in the capture
+    pub include_overlay: bool,
+}
+
+/// Which color-space chunks to write.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PngColorSpace {
+    /// `sRGB` chunk plus the matching `gAMA` fallback
+    #[default]
+    Srgb,
+    /// `gAMA` of 1.0: samples are linear light
+    Linear,
+}
+
+/// Converts a `read_pixels` RGBA buffer `width` pixels wide into the rows
+/// `encode` takes: flipped to run top to bottom, alpha un-premultiplied or
+/// dropped, and decoded to linear light for `PngColorSpace::Linear`.
+pub fn from_readback(
+    rgba: &[u8],
+    width: u32,
+    include_alpha: bool,
+    color_space: PngColorSpace,
+) -> Vec<u8> {
+    let channels = if include_alpha { 4 } else { 3 };
+    let mut pixels = Vec::with_capacity(rgba.len() / 4 * channels);
+
+    // GL rows run bottom to top, PNG rows top to bottom
+    for row in rgba.chunks(width as usize * 4).rev() {
+        for pixel in row.chunks(4) {
+            // The canvas holds premultiplied alpha, PNG wants it straight
+            let alpha = pixel[3];
+            let rgb = pixel[..3].iter().map(|&c| {
+                if include_alpha && alpha > 0 && alpha < 255 {
+                    (c as u32 * 255 / alpha as u32).min(255) as u8
+                } else {
+                    c
+                }
+            });
+
+            // The shader output is already display-encoded
+            match color_space {
+                PngColorSpace::Srgb => pixels.extend(rgb),
+                PngColorSpace::Linear => pixels.extend(rgb.map(color::srgb8_to_linear8)),
+            }
+            if include_alpha {
+                pixels.push(alpha);
+            }
+        }
+    }
+    pixels
+}
+
+/// Encodes 8-bit `pixels` (rows top to bottom, RGBA if `has_alpha`, otherwise RGB).
+pub fn encode(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    has_alpha: bool,
+    color_space: PngColorSpace,
+) -> Vec<u8> {
+    let channels = if has_alpha { 4 } else { 3 };
+    let stride = width as usize * channels;
+
+    let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.push(8); // bit depth
+    header.push(if has_alpha { 6 } else { 2 }); // truecolor (with alpha)
+    header.extend_from_slice(&[0, 0, 0]); // compression, filter, interlace
+    write_chunk(&mut png, b"IHDR", &header);
+
+    match color_space {
+        PngColorSpace::Srgb => {
+            write_chunk(&mut png, b"sRGB", &[0]); // perceptual intent
+            write_chunk(&mut png, b"gAMA", &45455u32.to_be_bytes());
+        }
+        PngColorSpace::Linear => {
+            write_chunk(&mut png, b"gAMA", &100000u32.to_be_bytes());
+        }
+    }
+
+    // Every scanline starts with filter type 0 (none)
+    let mut raw = Vec::with_capacity((stride + 1) * height as usize);
+    for row in pixels.chunks(stride).take(height as usize) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+
+    let mut crc = crc32_update(0xFFFF_FFFF, kind);
+    crc = crc32_update(crc, data);
+    png.extend_from_slice(&(crc ^ 0xFFFF_FFFF).to_be_bytes());
+}
+
+/// Wraps `data` in a zlib stream made of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 65535;
+
+    let mut out = Vec::with_capacity(data.len() + data.len() / MAX_BLOCK * 5 + 11);
+    out.extend_from_slice(&[0x78, 0x01]);
+
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xEDB8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Chunks of `png` in order, checking each CRC.
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(&png[..8], &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let kind: [u8; 4] = rest[4..8].try_into().unwrap();
+            let data = &rest[8..8 + len];
+            let crc = u32::from_be_bytes(rest[8 + len..12 + len].try_into().unwrap());
+            let expected = crc32_update(crc32_update(0xFFFF_FFFF, &kind), data) ^ 0xFFFF_FFFF;
+            assert_eq!(
+                crc,
+                expected,
+                "bad CRC on {}",
+                String::from_utf8_lossy(&kind)
+            );
+            chunks.push((kind, data.to_vec()));
+            rest = &rest[12 + len..];
+        }
+        chunks
+    }
+
+    /// Decodes what `encode` writes: stored deflate blocks and filter 0 rows.
+    /// Returns the width, height, channel count and pixel bytes.
+    fn decode(png: &[u8]) -> (u32, u32, usize, Vec<u8>) {
+        let chunks = chunks(png);
+        let header = &chunks[0].1;
+        assert_eq!(&chunks[0].0, b"IHDR");
+        let width = u32::from_be_bytes(header[..4].try_into().unwrap());
+        let height = u32::from_be_bytes(header[4..8].try_into().unwrap());
+        let channels = match header[9] {
+            2 => 3,
+            6 => 4,
+            other => panic!("unexpected color type {}", other),
+        };
+
+        let zlib: Vec<u8> = chunks
+            .iter()
+            .filter(|(kind, _)| kind == b"IDAT")
+            .flat_map(|(_, data)| data.clone())
+            .collect();
+        let mut raw = Vec::new();
+        let mut at = 2;
+        loop {
+            let last = zlib[at] & 1 == 1;
+            assert_eq!(zlib[at] >> 1, 0, "only stored blocks are written");
+            let len = u16::from_le_bytes([zlib[at + 1], zlib[at + 2]]) as usize;
+            let nlen = u16::from_le_bytes([zlib[at + 3], zlib[at + 4]]);
+            assert_eq!(nlen, !(len as u16));
+            raw.extend_from_slice(&zlib[at + 5..at + 5 + len]);
+            at += 5 + len;
+            if last {
+                break;
+            }
+        }
+        assert_eq!(
+            u32::from_be_bytes(zlib[at..at + 4].try_into().unwrap()),
+            adler32(&raw)
+        );
+
+        let stride = width as usize * channels;
+        let mut pixels = Vec::new();
+        for row in raw.chunks(stride + 1) {
+            assert_eq!(row[0], 0, "filter type");
+            pixels.extend_from_slice(&row[1..]);
+        }
+        (width, height, channels, pixels)
+    }
+
+    /// A 2x2 GL readback: bottom row red and black, top row a linear
+    /// mid-grey (display-encoded as 188) and half-transparent white.
+    fn readback() -> Vec<u8> {
+        vec![
+            255, 0, 0, 255, 0, 0, 0, 255, // bottom row
+            188, 188, 188, 255, 128, 128, 128, 128, // top row
+        ]
+    }
+
+    #[test]
+    fn srgb_capture_keeps_mid_grey_at_188() {
+        let pixels = from_readback(&readback(), 2, false, PngColorSpace::Srgb);
+        let png = encode(&pixels, 2, 2, false, PngColorSpace::Srgb);
+
+        let kinds: Vec<[u8; 4]> = chunks(&png).into_iter().map(|(kind, _)| kind).collect();
+        assert_eq!(
+            kinds,
+            vec![*b"IHDR", *b"sRGB", *b"gAMA", *b"IDAT", *b"IEND"]
+        );
+
+        let (width, height, channels, decoded) = decode(&png);
+        assert_eq!((width, height, channels), (2, 2, 3));
+        // The top row comes first in the PNG
+        assert_eq!(&decoded[..3], &[188, 188, 188]);
+        assert_eq!(&decoded[6..9], &[255, 0, 0]);
+    }
+
+    #[test]
+    fn linear_capture_decodes_mid_grey_to_128() {
+        let pixels = from_readback(&readback(), 2, false, PngColorSpace::Linear);
+        let png = encode(&pixels, 2, 2, false, PngColorSpace::Linear);
+
+        let chunks = chunks(&png);
+        assert!(chunks.iter().all(|(kind, _)| kind != b"sRGB"));
+        let gama = chunks.iter().find(|(kind, _)| kind == b"gAMA").unwrap();
+        assert_eq!(gama.1, 100000u32.to_be_bytes());
+
+        let (_, _, _, decoded) = decode(&png);
+        assert_eq!(&decoded[..3], &[128, 128, 128]);
+    }
+
+    #[test]
+    fn alpha_is_kept_straight() {
+        let pixels = from_readback(&readback(), 2, true, PngColorSpace::Srgb);
+        let (_, _, channels, decoded) = decode(&encode(&pixels, 2, 2, true, PngColorSpace::Srgb));
+        assert_eq!(channels, 4);
+        // Premultiplied 128 at alpha 128 is straight white
+        assert_eq!(&decoded[4..8], &[255, 255, 255, 128]);
+    }
+
+    #[test]
+    fn large_images_span_several_deflate_blocks() {
+        let (width, height) = (300, 100);
+        let rgba: Vec<u8> = (0..width * height * 4)
+            .map(|i| if i % 4 == 3 { 255 } else { (i % 251) as u8 })
+            .collect();
+        let pixels = from_readback(&rgba, width, true, PngColorSpace::Srgb);
+        let (_, _, _, decoded) = decode(&encode(&pixels, width, height, true, PngColorSpace::Srgb));
+
+        let flipped: Vec<u8> = rgba
+            .chunks(width as usize * 4)
+            .rev()
+            .flatten()
+            .copied()
+            .collect();
+        assert_eq!(decoded, flipped);
+    }
+}
@@ -0,0 +1,135 @@
+//! Built-in scenes that exercise specific renderer features.
+
+use crate::material::Material;
+use crate::math::Vec3;
+use crate::scene::{Box, Light, LightLink, ObjectType, Plane, Scene, Sphere, Triangle};
+
+/// Names accepted by `scene_by_name`.
+pub const PRESET_NAMES: [&str; 3] = ["triangle_seam", "light_linking", "prism"];
+
+pub fn scene_by_name(name: &str) -> Option<Scene> {
+    match name {
+        "triangle_seam" => Some(triangle_seam()),
+        "light_linking" => Some(light_linking()),
+        "prism" => Some(prism()),
+        _ => None,
+    }
+}
+
+/// A bright quad split along its diagonal into two triangles, in front of a
+/// dark backdrop. Any gap in triangle intersection shows as a line of
+/// background pixels along the diagonal.
+pub fn triangle_seam() -> Scene {
+    let mut scene = Scene::new();
+    scene.set_background(Vec3::new(0.05, 0.05, 0.08));
+
+    let material = Material::lambertian(Vec3::new(0.9, 0.9, 0.85));
+    let corners = [
+        Vec3::new(-1.5, -0.5, -1.0),
+        Vec3::new(1.5, -0.5, -1.0),
+        Vec3::new(1.5, 2.5, -1.0),
+        Vec3::new(-1.5, 2.5, -1.0),
+    ];
+    scene.add_triangle(Triangle::new(corners[0], corners[1], corners[2], material));
+    scene.add_triangle(Triangle::new(corners[0], corners[2], corners[3], material));
+
+    scene.add_plane(Plane::new(
+        Vec3::new(0.0, -1.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Material::lambertian(Vec3::new(0.2, 0.2, 0.2)),
+    ));
+    scene.add_light(Light::new(
+        Vec3::new(0.0, 3.0, 4.0),
+        Vec3::new(1.0, 1.0, 1.0),
+        80.0,
+    ));
+
+    scene
+}
+
+/// A white sphere on a white floor, lit by a neutral key light and a strong
+/// red rim light linked to the sphere only. With linking working the floor
+/// stays neutral grey while the sphere's edge turns red.
+pub fn light_linking() -> Scene {
+    let mut scene = Scene::new();
+    scene.set_background(Vec3::new(0.05, 0.05, 0.08));
+
+    let white = Material::lambertian(Vec3::new(0.85, 0.85, 0.85));
+    scene.add_plane(Plane::new(
+        Vec3::new(0.0, -1.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        white,
+    ));
+    scene.add_sphere(Sphere::new(Vec3::new(0.0, 0.0, -1.0), 1.0, white));
+
+    scene.add_light(Light::new(
+        Vec3::new(-3.0, 4.0, 3.0),
+        Vec3::new(1.0, 1.0, 1.0),
+        40.0,
+    ));
+    let mut rim = Light::new(Vec3::new(2.5, 1.0, -3.5), Vec3::new(1.0, 0.15, 0.1), 60.0);
+    rim.link = LightLink::Include(vec![ObjectType::Sphere.object_id(0)]);
+    scene.add_light(rim);
+
+    scene
+}
+
+/// A dispersive glass prism lying across the view in front of a bright white
+/// bar, lit from above. Seen through the prism the bar is bent and spread
+/// into a band running from red to blue.
+pub fn prism() -> Scene {
+    let mut scene = Scene::new();
+    scene.set_background(Vec3::new(0.02, 0.02, 0.03));
+
+    scene.add_plane(Plane::new(
+        Vec3::new(0.0, -1.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Material::lambertian(Vec3::new(0.3, 0.3, 0.3)),
+    ));
+
+    let mut glass = Material::dielectric(1.5);
+    glass.dispersion = 0.1;
+    let profile = [
+        Vec3::new(-1.5, 0.8, -1.0),
+        Vec3::new(-1.5, -0.4, -0.3),
+        Vec3::new(-1.5, -0.4, -1.7),
+    ];
+    add_prism(&mut scene, profile, Vec3::new(3.0, 0.0, 0.0), glass);
+
+    let mut bar = Material::lambertian(Vec3::new(1.0, 1.0, 1.0));
+    bar.emission = Vec3::new(1.0, 1.0, 1.0);
+    bar.emission_strength = 4.0;
+    scene.add_box(Box::new(
+        Vec3::new(0.0, 0.2, -4.0),
+        Vec3::new(6.0, 0.15, 0.1),
+        bar,
+    ));
+
+    scene.add_light(Light::new(
+        Vec3::new(0.0, 5.0, 2.0),
+        Vec3::new(1.0, 1.0, 1.0),
+        120.0,
+    ));
+
+    scene
+}
+
+/// Adds the triangular prism swept from `profile` along `axis` as eight
+/// triangles, wound so every face points outwards.
+fn add_prism(scene: &mut Scene, profile: [Vec3; 3], axis: Vec3, material: Material) {
+    let [a, mut b, mut c] = profile;
+    // The near cap must face back along the axis
+    if (b - a).cross(&(c - a)).dot(&axis) > 0.0 {
+        std::mem::swap(&mut b, &mut c);
+    }
+    let near = [a, b, c];
+    let far = near.map(|corner| corner + axis);
+
+    scene.add_triangle(Triangle::new(near[0], near[1], near[2], material));
+    scene.add_triangle(Triangle::new(far[0], far[2], far[1], material));
+    for i in 0..3 {
+        let j = (i + 1) % 3;
+        scene.add_triangle(Triangle::new(near[i], far[j], near[j], material));
+        scene.add_triangle(Triangle::new(near[i], far[i], far[j], material));
+    }
+}
@@ -0,0 +1,472 @@
+//! Quality presets, the automatic governor that picks one for a target frame
+//! rate, and the adaptive render scale that tunes resolution for one.
+
+use serde::Serialize;
+
+/// Most path segments the shader traces per sample.
+pub const MAX_BOUNCES: u32 = 16;
+/// Most primary rays the shader averages per pixel.
+pub const MAX_SAMPLES_PER_PIXEL: u32 = 16;
+
+/// Minimum time a level is kept before the governor may change it again.
+pub const QUALITY_DWELL_MS: f64 = 2000.0;
+/// The governor steps down once FPS falls below this fraction of the target...
+const DOWNGRADE_FACTOR: f64 = 0.9;
+/// ...and up once it exceeds the target by this factor.
+const UPGRADE_FACTOR: f64 = 1.3;
+/// After stepping down, the level just left stays off limits this long, so a
+/// level that can't hold the target isn't retried every dwell period.
+const UPGRADE_BACKOFF_MS: f64 = 10_000.0;
+/// Frames the adaptive render scale is judged over after each change.
+pub const ADAPTIVE_WINDOW_FRAMES: usize = 20;
+/// Smallest render scale the adaptive mode picks.
+const MIN_ADAPTIVE_SCALE: f32 = 0.25;
+/// Adaptive scales are rounded to this step, so small FPS swings don't
+/// reallocate the render buffer.
+const ADAPTIVE_SCALE_STEP: f32 = 0.05;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QualityLevel {
+    Low,
+    Medium,
+    High,
+    Ultra,
+}
+
+impl QualityLevel {
+    pub fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Self::Low),
+            1 => Some(Self::Medium),
+            2 => Some(Self::High),
+            3 => Some(Self::Ultra),
+            _ => None,
+        }
+    }
+
+    pub fn lower(self) -> Option<Self> {
+        match self {
+            Self::Low => None,
+            Self::Medium => Some(Self::Low),
+            Self::High => Some(Self::Medium),
+            Self::Ultra => Some(Self::High),
+        }
+    }
+
+    pub fn higher(self) -> Option<Self> {
+        match self {
+            Self::Low => Some(Self::Medium),
+            Self::Medium => Some(Self::High),
+            Self::High => Some(Self::Ultra),
+            Self::Ultra => None,
+        }
+    }
+
+    pub fn settings(self) -> QualitySettings {
+        match self {
+            Self::Low => QualitySettings {
+                max_bounces: 2,
+                samples_per_pixel: 1,
+                shadows: false,
+                ambient_occlusion_samples: 0,
+                render_scale: 0.5,
+                soft_shadows: false,
+                volume_steps: 4,
+            },
+            Self::Medium => QualitySettings {
+                max_bounces: 4,
+                samples_per_pixel: 1,
+                shadows: true,
+                ambient_occlusion_samples: 0,
+                render_scale: 0.75,
+                soft_shadows: false,
+                volume_steps: 8,
+            },
+            Self::High => QualitySettings {
+                max_bounces: 6,
+                samples_per_pixel: 2,
+                shadows: true,
+                ambient_occlusion_samples: 1,
+                render_scale: 1.0,
+                soft_shadows: false,
+                volume_steps: 16,
+            },
+            Self::Ultra => QualitySettings {
+                max_bounces: 10,
+                samples_per_pixel: 4,
+                shadows: true,
+                ambient_occlusion_samples: 2,
+                render_scale: 1.0,
+                soft_shadows: true,
+                volume_steps: 32,
+            },
+        }
+    }
+}
+
+/// The concrete renderer settings a quality level stands for.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct QualitySettings {
+    /// Path segments traced per sample (1 to `MAX_BOUNCES`)
+    pub max_bounces: u32,
+    /// Primary rays per pixel per frame (1 to `MAX_SAMPLES_PER_PIXEL`)
+    pub samples_per_pixel: u32,
+    /// Trace shadow rays toward lights
+    pub shadows: bool,
+    /// Short occlusion rays darkening the ambient term (at most 4)
+    pub ambient_occlusion_samples: u32,
+    /// Render buffer size relative to the canvas image
+    pub render_scale: f32,
+    /// Sample lights as small spheres instead of points
+    pub soft_shadows: bool,
+    /// Ray-marching steps through each fog volume (at most 32)
+    pub volume_steps: u32,
+}
+
+impl Default for QualitySettings {
+    /// What the renderer does before any level is chosen.
+    fn default() -> Self {
+        Self {
+            max_bounces: 10,
+            samples_per_pixel: 2,
+            shadows: true,
+            ambient_occlusion_samples: 0,
+            render_scale: 1.0,
+            soft_shadows: false,
+            volume_steps: 16,
+        }
+    }
+}
+
+/// Steps the quality level to keep the frame rate near a target.
+///
+/// Changes need the FPS to leave a band around the target (below 90% to step
+/// down, above 130% to step up) and are at least `QUALITY_DWELL_MS` apart, so
+/// the measured FPS has time to reflect the previous change.
+#[derive(Clone, Copy, Debug)]
+pub struct QualityGovernor {
+    pub target_fps: f64,
+    level: QualityLevel,
+    last_change_ms: f64,
+    // The level stepped down from, and until when it may not be re-entered
+    backoff: Option<(QualityLevel, f64)>,
+}
+
+impl QualityGovernor {
+    pub fn new(target_fps: f64, level: QualityLevel, now_ms: f64) -> Self {
+        Self {
+            target_fps,
+            level,
+            last_change_ms: now_ms,
+            backoff: None,
+        }
+    }
+
+    /// Feeds the current rolling FPS; returns the new level when it changes.
+    pub fn update(&mut self, fps: f64, now_ms: f64) -> Option<QualityLevel> {
+        if now_ms - self.last_change_ms < QUALITY_DWELL_MS {
+            return None;
+        }
+
+        let next = if fps < self.target_fps * DOWNGRADE_FACTOR {
+            let next = self.level.lower()?;
+            self.backoff = Some((self.level, now_ms + UPGRADE_BACKOFF_MS));
+            next
+        } else if fps > self.target_fps * UPGRADE_FACTOR {
+            let next = self.level.higher()?;
+            if self
+                .backoff
+                .is_some_and(|(level, until)| level == next && now_ms < until)
+            {
+                return None;
+            }
+            next
+        } else {
+            return None;
+        };
+
+        self.level = next;
+        self.last_change_ms = now_ms;
+        Some(next)
+    }
+}
+
+/// Tunes the render scale for a target frame rate, leaving every other
+/// setting alone.
+///
+/// Tracing cost grows with the pixel count, the square of the scale, so each
+/// change aims straight for the target by scaling with the square root of the
+/// FPS ratio. It only reacts outside the governor's band around the target,
+/// which a change aimed at the target lands inside, so it doesn't oscillate.
+#[derive(Clone, Copy, Debug)]
+pub struct AdaptiveScale {
+    pub target_fps: f64,
+    scale: f32,
+}
+
+impl AdaptiveScale {
+    pub fn new(target_fps: f64, scale: f32) -> Self {
+        Self {
+            target_fps,
+            scale: scale.clamp(MIN_ADAPTIVE_SCALE, 1.0),
+        }
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Feeds the current rolling FPS; returns whether the scale changed.
+    pub fn update(&mut self, fps: f64) -> bool {
+        let ratio = fps / self.target_fps;
+        if !ratio.is_finite() || (DOWNGRADE_FACTOR..=UPGRADE_FACTOR).contains(&ratio) {
+            return false;
+        }
+        let scale = (self.scale * ratio.sqrt() as f32).clamp(MIN_ADAPTIVE_SCALE, 1.0);
+        let scale = (scale / ADAPTIVE_SCALE_STEP).round() * ADAPTIVE_SCALE_STEP;
+        if scale == self.scale {
+            return false;
+        }
+        self.scale = scale;
+        true
+    }
+}
+
+/// What `get_effective_settings` reports.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct QualityReport {
+    /// The preset in force, or `None` before one is chosen
+    pub level: Option<QualityLevel>,
+    /// Whether the governor is adjusting the level
+    pub auto: bool,
+    #[serde(flatten)]
+    pub settings: QualitySettings,
+}
+
+/// Stages the idle boost steps through, one every `delay_frames` idle frames.
+pub const IDLE_BOOST_STAGES: u32 = 3;
+
+/// Raises quality while nothing changes, in stages: first twice the samples
+/// per pixel, then soft shadows and ambient occlusion, then the most samples,
+/// bounces and volume steps the shader takes. Render scale is left alone, so
+/// stepping in and out never resizes buffers.
+///
+/// The boost is applied on top of the interactive settings rather than
+/// replacing them, so dropping back is just not applying it: the frame that
+/// sees the first change is already drawn at interactive cost.
+#[derive(Clone, Copy, Debug)]
+pub struct IdleBoost {
+    pub delay_frames: u32,
+    idle_frames: u32,
+    // Scene revision as of the last frame
+    revision: Option<u32>,
+}
+
+impl IdleBoost {
+    pub fn new(delay_frames: u32) -> Self {
+        Self {
+            delay_frames: delay_frames.max(1),
+            idle_frames: 0,
+            revision: None,
+        }
+    }
+
+    /// Counts a frame, which is idle unless `active` is set or the scene
+    /// revision moved since the last one. Returns whether the stage changed.
+    pub fn update(&mut self, active: bool, revision: u32) -> bool {
+        let stage = self.stage();
+        if active || self.revision != Some(revision) {
+            self.idle_frames = 0;
+        } else {
+            self.idle_frames = self.idle_frames.saturating_add(1);
+        }
+        self.revision = Some(revision);
+        self.stage() != stage
+    }
+
+    pub fn idle_frames(&self) -> u32 {
+        self.idle_frames
+    }
+
+    /// 0 while interactive, up to `IDLE_BOOST_STAGES`.
+    pub fn stage(&self) -> u32 {
+        (self.idle_frames / self.delay_frames).min(IDLE_BOOST_STAGES)
+    }
+
+    /// `interactive` raised to the current stage; never lowers a setting.
+    pub fn apply(&self, interactive: QualitySettings) -> QualitySettings {
+        let stage = self.stage();
+        let mut settings = interactive;
+        if stage >= 1 {
+            settings.samples_per_pixel =
+                (interactive.samples_per_pixel * 2).min(MAX_SAMPLES_PER_PIXEL);
+        }
+        if stage >= 2 {
+            settings.soft_shadows = interactive.shadows;
+            settings.ambient_occlusion_samples = interactive.ambient_occlusion_samples.max(2);
+        }
+        if stage >= 3 {
+            settings.samples_per_pixel = MAX_SAMPLES_PER_PIXEL;
+            settings.max_bounces = MAX_BOUNCES;
+            settings.volume_steps = 32;
+        }
+        settings
+    }
+}
+
+/// What `get_current_quality_state` reports.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct QualityState {
+    /// Whether the idle boost is enabled
+    pub idle_boost: bool,
+    /// Frames since the camera or scene last changed
+    pub idle_frames: u32,
+    /// Boost stage in force, 0 while interactive
+    pub boost_stage: u32,
+    /// The settings the last frame was drawn with
+    #[serde(flatten)]
+    pub settings: QualitySettings,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEVELS: [QualityLevel; 4] = [
+        QualityLevel::Low,
+        QualityLevel::Medium,
+        QualityLevel::High,
+        QualityLevel::Ultra,
+    ];
+
+    #[test]
+    fn levels_round_trip_and_step() {
+        for (i, level) in LEVELS.iter().enumerate() {
+            assert_eq!(QualityLevel::from_u32(i as u32), Some(*level));
+            if let Some(lower) = level.lower() {
+                assert_eq!(lower.higher(), Some(*level));
+            }
+        }
+        assert_eq!(QualityLevel::from_u32(4), None);
+        assert_eq!(QualityLevel::Low.lower(), None);
+        assert_eq!(QualityLevel::Ultra.higher(), None);
+    }
+
+    #[test]
+    fn presets_stay_within_shader_limits() {
+        for level in LEVELS {
+            let settings = level.settings();
+            assert!((1..=MAX_BOUNCES).contains(&settings.max_bounces), "{:?}", level);
+            assert!((1..=MAX_SAMPLES_PER_PIXEL).contains(&settings.samples_per_pixel));
+            assert!(settings.ambient_occlusion_samples <= 4);
+            assert!(settings.volume_steps <= 32);
+            assert!(settings.render_scale > 0.0 && settings.render_scale <= 1.0);
+            assert!(settings.shadows || !settings.soft_shadows);
+        }
+    }
+
+    #[test]
+    fn presets_never_get_cheaper_going_up() {
+        for pair in LEVELS.windows(2) {
+            let (low, high) = (pair[0].settings(), pair[1].settings());
+            assert!(high.max_bounces >= low.max_bounces);
+            assert!(high.samples_per_pixel >= low.samples_per_pixel);
+            assert!(high.ambient_occlusion_samples >= low.ambient_occlusion_samples);
+            assert!(high.render_scale >= low.render_scale);
+            assert!(high.volume_steps >= low.volume_steps);
+            assert!(high.shadows >= low.shadows);
+            assert!(high.soft_shadows >= low.soft_shadows);
+        }
+    }
+
+    #[test]
+    fn governor_hysteresis_with_simulated_fps() {
+        let mut governor = QualityGovernor::new(60.0, QualityLevel::High, 0.0);
+
+        // Too slow, but the level was only just chosen
+        assert_eq!(governor.update(50.0, 1000.0), None);
+        assert_eq!(governor.update(50.0, 2000.0), Some(QualityLevel::Medium));
+        // Fast again, but within the dwell time of the last change
+        assert_eq!(governor.update(90.0, 3000.0), None);
+        // Inside the band around the target nothing changes
+        assert_eq!(governor.update(55.0, 5000.0), None);
+        assert_eq!(governor.update(77.0, 5000.0), None);
+        // High is still off limits for a while after stepping down from it
+        assert_eq!(governor.update(90.0, 6000.0), None);
+        assert_eq!(governor.update(90.0, 12_000.0), Some(QualityLevel::High));
+        assert_eq!(governor.update(200.0, 14_000.0), Some(QualityLevel::Ultra));
+        assert_eq!(governor.update(200.0, 20_000.0), None);
+
+        // A collapse steps down one level per dwell period, down to Low
+        let mut levels = Vec::new();
+        for second in 0..10 {
+            if let Some(level) = governor.update(5.0, 22_000.0 + second as f64 * 1000.0) {
+                levels.push(level);
+            }
+        }
+        assert_eq!(
+            levels,
+            [QualityLevel::High, QualityLevel::Medium, QualityLevel::Low]
+        );
+    }
+
+    #[test]
+    fn adaptive_scale_aims_for_the_target() {
+        let mut adaptive = AdaptiveScale::new(60.0, 1.0);
+        // A quarter of the target: half the resolution on each axis
+        assert!(adaptive.update(15.0));
+        assert!((adaptive.scale() - 0.5).abs() < 1e-6);
+        // Landing inside the band leaves it there
+        assert!(!adaptive.update(60.0));
+        assert!(!adaptive.update(70.0));
+        // Never below the floor or above full resolution
+        assert!(adaptive.update(1.0));
+        assert!((adaptive.scale() - MIN_ADAPTIVE_SCALE).abs() < 1e-6);
+        adaptive.update(10_000.0);
+        assert!((adaptive.scale() - 1.0).abs() < 1e-6);
+    }
+
+    #[cfg(feature = "webgl")]
+    #[test]
+    fn idle_boost_drops_back_on_the_first_changed_frame() {
+        // Manual time: the scene clock is held, so a day/night cycle doesn't
+        // count as activity and every frame below is idle unless it says so
+        let mut clock = crate::clock::Clock::new();
+        clock.pause_at(12_000.0);
+        let interactive = QualityLevel::Medium.settings();
+        let mut boost = IdleBoost::new(2);
+        let frame = |boost: &mut IdleBoost, camera_changed: bool, revision: u32| {
+            let animating = !clock.is_paused();
+            let stage_changed = boost.update(camera_changed || animating, revision);
+            (stage_changed, boost.apply(interactive))
+        };
+
+        // The first frame has nothing to compare the revision to
+        assert_eq!(frame(&mut boost, true, 7), (false, interactive));
+        let mut stages = Vec::new();
+        for _ in 0..8 {
+            let (_, settings) = frame(&mut boost, false, 7);
+            stages.push(boost.stage());
+            assert_eq!(settings, boost.apply(interactive));
+        }
+        assert_eq!(stages, [0, 1, 1, 2, 2, 3, 3, 3]);
+        let boosted = boost.apply(interactive);
+        assert_eq!(boosted.samples_per_pixel, MAX_SAMPLES_PER_PIXEL);
+        assert_eq!(boosted.render_scale, interactive.render_scale);
+
+        // Input lands: that same frame is already back to the interactive
+        // settings, in one step rather than stage by stage
+        assert_eq!(frame(&mut boost, true, 7), (true, interactive));
+        assert_eq!(boost.idle_frames(), 0);
+
+        // An edit to the scene drops back the same way
+        for _ in 0..4 {
+            frame(&mut boost, false, 7);
+        }
+        assert_eq!(boost.stage(), 2);
+        assert_eq!(frame(&mut boost, false, 8), (true, interactive));
+        assert_eq!(frame(&mut boost, false, 8), (false, interactive));
+        assert_eq!(clock.now(), 12_000.0);
+    }
+}
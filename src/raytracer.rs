@@ -0,0 +1,5141 @@
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+
+use js_sys::Date;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use wasm_bindgen::prelude::*;
+use web_sys::{
+    console, WebGlBuffer, WebGlProgram, WebGlRenderingContext, WebGlTexture, WebGlUniformLocation,
+};
+
+use crate::accumulation::Accumulation;
+use crate::axes::ImportAxes;
+use crate::bvh::BvhCache;
+use crate::camera::{
+    Camera, CameraFlight, CameraKeyframe, CameraPath, CameraPathPlayback, CameraPreset,
+    CameraState,
+};
+use crate::clock::Clock;
+use crate::controls::{ControlOptions, Controls};
+use crate::daynight::DayNightCycle;
+use crate::error::RaytracerError;
+use crate::exposure::{self, AutoExposure};
+use crate::generate::GridRamp;
+use crate::intersect::{self, Ray};
+use crate::loader::{ChunkedSceneLoad, SCENE_LOAD_BATCH};
+use crate::material::{Material, MaterialType, Texture, TextureSpace};
+use crate::math::{sampling, Aabb, Vec3};
+use crate::motion::{MotionTracker, Region};
+use crate::png::CaptureOptions;
+use crate::quality::{
+    AdaptiveScale, IdleBoost, QualityGovernor, QualityLevel, QualityReport, QualitySettings,
+    QualityState, ADAPTIVE_WINDOW_FRAMES, MAX_BOUNCES, MAX_SAMPLES_PER_PIXEL,
+};
+use crate::scene::{
+    Box, Cone, CsgOp, CsgOperation, Cylinder, Disk, Editable, InstancedGrid, Light, MaterialOverride,
+    Mesh, ObjectLocation, ObjectRef, ObjectType, PackingOptions, Plane, Scene, ScenePatch, Sphere,
+    Starfield, Triangle, Volume, MAX_LIGHTS, MAX_VOLUMES,
+};
+use crate::scene_data::SceneDataTexture;
+use crate::scene_uniforms::SceneUniforms;
+use crate::shaders::{ShaderFeatures, ShaderVariants};
+use crate::textures::{ImageTextures, MAX_IMAGE_TEXTURES};
+use crate::viewport::Viewport;
+use crate::webgl::{ContextKind, ContextOptions, GlState, RenderTarget, UniformBuffer};
+use crate::{
+    collision, csv, gbuffer, generate, lighting, loader, png, presets, selection, shaders, webgl,
+};
+
+/// FPS must exceed the LOD threshold by this factor before full detail returns.
+const LOD_RECOVERY_FACTOR: f64 = 1.25;
+
+/// Default movement below which the camera counts as stationary.
+const CAMERA_POSITION_EPSILON: f32 = 1e-5;
+const CAMERA_DIRECTION_EPSILON: f32 = 1e-6;
+
+/// Where the camera starts, in meters, and the point it looks at.
+const DEFAULT_CAMERA_POSITION: Vec3 = Vec3::new(0.0, 2.0, 5.0);
+const DEFAULT_CAMERA_TARGET: Vec3 = Vec3::new(0.0, 0.0, 0.0);
+
+/// Direction from a thumbnail's subject to its camera: front-right, above.
+const THUMBNAIL_VIEW_DIRECTION: Vec3 = Vec3::new(1.0, 0.75, 1.0);
+
+/// Frame rate the adaptive render scale aims for unless set otherwise.
+const DEFAULT_TARGET_FPS: f64 = 60.0;
+
+/// Most jittered primary rays `set_antialiasing` asks for per pixel.
+const MAX_ANTIALIASING_SAMPLES: u32 = 4;
+
+/// Radius of the sphere lights are spread over when soft shadows are on.
+const SOFT_SHADOW_RADIUS: f32 = 0.25;
+
+/// Alpha of a fully shadowed shadow-catcher pixel unless set otherwise.
+const DEFAULT_SHADOW_CATCHER_OPACITY: f32 = 0.6;
+
+/// Amplification of the reference difference overlay unless set otherwise.
+const DEFAULT_DIFFERENCE_GAIN: f32 = 4.0;
+
+/// `object_type` value for lights in the calls that also accept them.
+const LIGHT_OBJECT_TYPE: u32 = 5;
+
+/// Uniform buffer binding point of the `SceneLights` block.
+const LIGHT_BLOCK_BINDING: u32 = 0;
+
+/// Resolution divisor of the buffer `get_nan_pixel_count` renders.
+const NAN_GUARD_DOWNSAMPLE: u32 = 4;
+/// What the NaN guard shader variant draws for a non-finite pixel.
+const NAN_GUARD_COLOR: [u8; 3] = [255, 0, 255];
+
+#[wasm_bindgen]
+pub struct Raytracer {
+    gl: WebGlRenderingContext,
+    // Raytracing program of the active shader variant
+    program: WebGlProgram,
+    shader_variants: ShaderVariants,
+    shader_features: ShaderFeatures,
+    blit_program: WebGlProgram,
+    quad_buffer: WebGlBuffer,
+    // Reduced-resolution color buffer used while the render scale is below 1
+    scaled_target: Option<RenderTarget>,
+    accumulate_program: WebGlProgram,
+    accumulation_enabled: bool,
+    // Running average of the frames since the view last changed, made at the
+    // render size on first use while accumulation is enabled
+    accumulation: Option<Accumulation>,
+    // What the average was drawn with
+    accumulation_key: Option<AccumulationKey>,
+    overlay_program: WebGlProgram,
+    reference_image: Option<WebGlTexture>,
+    reference_image_bytes: usize,
+    image_textures: ImageTextures,
+    // Objects as a float texture; `None` when float textures are unsupported
+    // and objects go into uniform arrays
+    scene_data: Option<RefCell<SceneDataTexture>>,
+    // BVH over the triangles in `scene_data`
+    bvh: RefCell<BvhCache>,
+    // Buffer of the shader's `SceneLights` block; WebGL2 only
+    light_block: Option<RefCell<UniformBuffer>>,
+    reference_overlay: Option<ReferenceOverlay>,
+    // Tiny target the auto-exposure meter renders into, made on first use
+    meter_target: Option<RenderTarget>,
+    camera: Camera,
+    // Eased camera move in progress, advanced by render()
+    camera_flight: Option<CameraFlight>,
+    // Replay of the scene's camera path, advanced by render()
+    camera_path_playback: Option<CameraPathPlayback>,
+    scene: Scene,
+    scene_load: Option<ChunkedSceneLoad>,
+    clock: Clock,
+    day_night: Option<DayNightCycle>,
+    ambient: f32,
+    shadow_catcher_opacity: f32,
+    texture_filtering: bool,
+    // Exposure in stops applied before tone mapping
+    exposure_ev: f32,
+    auto_exposure: Option<AutoExposure>,
+
+    // Uniform locations of `program`
+    uniforms: RaytracingUniforms,
+    scene_uniforms: SceneUniforms,
+
+    // Performance tracking
+    last_frame_time: f64,
+    frame_times: Vec<f64>,
+    fps: f64,
+    frame_index: u32,
+    lod_threshold_fps: Option<f64>,
+    active_lod: u32,
+
+    viewport: Viewport,
+    controls: Option<Controls>,
+    transparent_background: bool,
+    // Color the canvas is cleared to; shows in letterbox bars and wherever
+    // the scene isn't drawn
+    clear_color: [f32; 4],
+    active_light_count: usize,
+    material_preview: Option<MaterialOverride>,
+    // Object drawn highlighted
+    selected_object: Option<(ObjectType, usize)>,
+    // Camera values currently held by the shader uniforms
+    uploaded_camera: Cell<Option<CameraState>>,
+    // Bytes of scene data the last draw uploaded
+    uploaded_scene_bytes: Cell<usize>,
+    // Live camera as of the last frame that counted as movement
+    last_frame_camera: Option<CameraState>,
+    camera_changed: bool,
+    // Bumped by every camera or scene change
+    generation: u32,
+    // Scene revision the generation last accounted for
+    generation_revision: u32,
+    // Generation of the previous frame, and frames drawn since it changed
+    frame_generation: u32,
+    frames_since_change: u32,
+    // (position, direction) change thresholds
+    camera_thresholds: (f32, f32),
+    // Radius of the sphere kept out of objects when moving, if enabled
+    camera_collision: Option<f32>,
+    // Axis convention of imported files
+    import_axes: ImportAxes,
+    quality: QualitySettings,
+    quality_level: Option<QualityLevel>,
+    quality_governor: Option<QualityGovernor>,
+    // Fewest primary rays per pixel while not accumulating; 1 = off
+    antialiasing: u32,
+    // Frame rate the adaptive render scale aims for
+    target_fps: f64,
+    // Set while the adaptive render scale overrides `quality.render_scale`
+    adaptive_scale: Option<AdaptiveScale>,
+    idle_boost: Option<IdleBoost>,
+    // Entries skipped by the last import_materials_json call
+    material_import_warnings: Vec<String>,
+    // Set while TAA motion rejection is enabled
+    motion_tracker: Option<MotionTracker>,
+    // Screen regions of the last frame where moved objects invalidate history
+    history_reject_regions: Vec<Region>,
+    // Whether the last frame's changes were confined to those regions, so
+    // the accumulated average only restarts inside them
+    history_reject_contained: bool,
+    nan_guard: bool,
+    // Scene revision last checked for degenerate objects by the NaN guard
+    nan_guard_revision: Option<u32>,
+}
+
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+impl Raytracer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(canvas_id: &str, width: u32, height: u32) -> Result<Raytracer, RaytracerError> {
+        Self::new_with_options(canvas_id, width, height, JsValue::UNDEFINED)
+    }
+
+    /// Like the constructor, with `{alpha, transparent_background, webgl2}`
+    /// options. `alpha` (default true) is fixed once the context exists;
+    /// turning it off rules out a transparent background later. `webgl2`
+    /// (default true) tries for a WebGL2 context before falling back to WebGL1.
+    #[wasm_bindgen]
+    pub fn new_with_options(
+        canvas_id: &str,
+        width: u32,
+        height: u32,
+        options: JsValue,
+    ) -> Result<Raytracer, RaytracerError> {
+        let options = options_from_js::<RaytracerOptions>(&options, "raytracer options")?;
+        let gl = webgl::init_webgl_context(canvas_id, &options.context)?;
+
+        let quad_buffer = webgl::create_quad_buffer(&gl)?;
+        let mut shader_variants = ShaderVariants::new(&gl);
+        let scene_data = SceneDataTexture::new(&gl).map(RefCell::new);
+        let light_block = UniformBuffer::new(&gl, LIGHT_BLOCK_BINDING).map(RefCell::new);
+        let shader_features = ShaderFeatures {
+            data_texture: scene_data.is_some(),
+            ..ShaderFeatures::default()
+        };
+        let program = shader_variants.program(&gl, shader_features)?;
+        let uniforms = RaytracingUniforms::locate(&gl, &program);
+        let scene_uniforms = SceneUniforms::locate(&gl, &program);
+        let blit_program = shaders::create_blit_program(&gl)?;
+        let overlay_program = shaders::create_overlay_program(&gl)?;
+        let accumulate_program = shaders::create_accumulate_program(&gl)?;
+
+        let camera = Camera::new(
+            DEFAULT_CAMERA_POSITION,
+            DEFAULT_CAMERA_TARGET,
+            width as f32 / height as f32,
+        );
+
+        let mut scene = Scene::new();
+
+        // Create a default scene
+        scene.add_sphere(Sphere::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            1.0,
+            Material::new(MaterialType::Lambertian, Vec3::new(0.7, 0.3, 0.3), 0.0, 0.0),
+        ));
+
+        scene.add_sphere(Sphere::new(
+            Vec3::new(-2.0, 0.0, -1.0),
+            0.5,
+            Material::new(MaterialType::Metal, Vec3::new(0.8, 0.8, 0.9), 0.1, 0.0),
+        ));
+
+        scene.add_sphere(Sphere::new(
+            Vec3::new(2.0, 0.0, -1.0),
+            0.5,
+            Material::new(MaterialType::Dielectric, Vec3::new(0.9, 1.0, 0.9), 0.0, 1.5),
+        ));
+
+        // Add another glass sphere with different IOR
+        scene.add_sphere(Sphere::new(
+            Vec3::new(0.0, 1.0, -2.0),
+            0.3,
+            Material::new(MaterialType::Dielectric, Vec3::new(1.0, 0.9, 0.9), 0.0, 1.3),
+        ));
+
+        // Add ground plane
+        scene.add_plane(Plane::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Material::new(MaterialType::Lambertian, Vec3::new(0.5, 0.5, 0.5), 0.0, 0.0),
+        ));
+
+        // Add some lights for better visibility
+        scene.add_light(Light::new(
+            Vec3::new(10.0, 10.0, 10.0),
+            Vec3::new(1.0, 1.0, 0.9),
+            200.0,
+        )); // Main sun light
+        scene.add_light(Light::new(
+            Vec3::new(-5.0, 8.0, 5.0),
+            Vec3::new(0.7, 0.8, 1.0),
+            80.0,
+        )); // Sky light
+        scene.add_light(Light::new(
+            Vec3::new(0.0, 15.0, 0.0),
+            Vec3::new(0.9, 0.9, 0.8),
+            150.0,
+        )); // Overhead light
+
+        let mut raytracer = Raytracer {
+            gl,
+            program,
+            shader_variants,
+            shader_features,
+            blit_program,
+            quad_buffer,
+            scaled_target: None,
+            accumulate_program,
+            accumulation_enabled: false,
+            accumulation: None,
+            accumulation_key: None,
+            overlay_program,
+            reference_image: None,
+            image_textures: ImageTextures::default(),
+            scene_data,
+            bvh: RefCell::new(BvhCache::default()),
+            light_block,
+            reference_image_bytes: 0,
+            reference_overlay: None,
+            meter_target: None,
+            camera,
+            camera_flight: None,
+            camera_path_playback: None,
+            scene,
+            scene_load: None,
+            clock: Clock::new(),
+            day_night: None,
+            ambient: 0.1,
+            shadow_catcher_opacity: DEFAULT_SHADOW_CATCHER_OPACITY,
+            texture_filtering: true,
+            exposure_ev: 0.0,
+            auto_exposure: None,
+            uniforms,
+            scene_uniforms,
+            last_frame_time: Date::now(),
+            frame_times: Vec::with_capacity(60),
+            fps: 0.0,
+            frame_index: 0,
+            lod_threshold_fps: None,
+            active_lod: 0,
+            viewport: Viewport::new(width, height),
+            controls: None,
+            transparent_background: false,
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            active_light_count: 0,
+            material_preview: None,
+            selected_object: None,
+            uploaded_camera: Cell::new(None),
+            uploaded_scene_bytes: Cell::new(0),
+            last_frame_camera: None,
+            camera_changed: true,
+            generation: 1,
+            generation_revision: 0,
+            frame_generation: 0,
+            frames_since_change: 0,
+            camera_thresholds: (CAMERA_POSITION_EPSILON, CAMERA_DIRECTION_EPSILON),
+            camera_collision: None,
+            import_axes: ImportAxes::default(),
+            quality: QualitySettings::default(),
+            quality_level: None,
+            quality_governor: None,
+            antialiasing: 1,
+            target_fps: DEFAULT_TARGET_FPS,
+            adaptive_scale: None,
+            idle_boost: None,
+            material_import_warnings: Vec::new(),
+            motion_tracker: None,
+            history_reject_regions: Vec::new(),
+            history_reject_contained: false,
+            nan_guard: false,
+            nan_guard_revision: None,
+        };
+        raytracer.set_transparent_background(options.transparent_background)?;
+
+        Ok(raytracer)
+    }
+
+    #[wasm_bindgen]
+    pub fn render(&mut self) -> Result<(), RaytracerError> {
+        let current_time = Date::now();
+        let delta_time = current_time - self.last_frame_time;
+
+        // Update FPS
+        self.frame_times.push(delta_time);
+        if self.frame_times.len() > 60 {
+            self.frame_times.remove(0);
+        }
+
+        if !self.frame_times.is_empty() {
+            let avg_frame_time =
+                self.frame_times.iter().sum::<f64>() / self.frame_times.len() as f64;
+            self.fps = 1000.0 / avg_frame_time;
+        }
+
+        self.last_frame_time = current_time;
+        self.update_lod();
+        self.update_quality(current_time);
+
+        if let Some(controls) = self.controls.as_mut() {
+            let start = self.camera.position();
+            let before = self.camera.state();
+            controls.update(&mut self.camera, (delta_time / 1000.0) as f32);
+            self.constrain_camera_move(start);
+            // Any input takes the camera over from a flight or path
+            if self.camera.state().differs_from(&before, 0.0, 0.0) {
+                self.cancel_camera_motion();
+            }
+        }
+        if let Some(flight) = self.camera_flight
+            && flight.apply(&mut self.camera, current_time)
+        {
+            self.camera_flight = None;
+        }
+        if let Some(playback) = self.camera_path_playback {
+            let done = match &self.scene.camera_path {
+                Some(path) => playback.apply(path, &mut self.camera, self.clock.now()),
+                None => true,
+            };
+            if done {
+                self.camera_path_playback = None;
+            }
+        }
+
+        let camera_state = self.camera.state();
+        self.camera_changed = camera_state.changed_since(
+            &mut self.last_frame_camera,
+            self.camera_thresholds.0,
+            self.camera_thresholds.1,
+        );
+
+        // Advance a pending chunked scene load by one batch
+        if let Some(load) = self.scene_load.as_mut() {
+            match load.step(&mut self.scene, SCENE_LOAD_BATCH) {
+                Ok(false) => {}
+                Ok(true) => self.scene_load = None,
+                Err(e) => {
+                    console::error_1(&e.into());
+                    self.scene_load = None;
+                }
+            }
+        }
+
+        self.image_textures.release_unused(&self.gl, &self.scene);
+
+        // Patches can take the selected object away without saying which
+        if let Some((object_type, index)) = self.selected_object
+            && index >= self.scene.object_count(object_type)
+        {
+            self.selected_object = None;
+        }
+
+        let scene_time = self.clock.now();
+        if let Some(cycle) = &self.day_night {
+            cycle.apply(&mut self.scene, &mut self.ambient, scene_time);
+        }
+
+        if self.nan_guard {
+            self.check_scene_finite();
+        }
+
+        // Camera setters bump the generation themselves; control movement and
+        // scene edits (including the load and day-night steps) are seen here
+        let view_changed = self.camera_changed || self.generation != self.frame_generation;
+        if self.camera_changed || self.scene.revision() != self.generation_revision {
+            self.generation_revision = self.scene.revision();
+            self.bump_generation();
+        }
+        if self.generation == self.frame_generation {
+            self.frames_since_change = self.frames_since_change.saturating_add(1);
+        } else {
+            self.frame_generation = self.generation;
+            self.frames_since_change = 0;
+        }
+
+        // Decided before anything is drawn, so the first frame with a change
+        // is already back at interactive cost
+        if let Some(boost) = self.idle_boost.as_mut() {
+            let animating = self.day_night.is_some() && !self.clock.is_paused();
+            let active = self.camera_changed || animating || self.scene_load.is_some();
+            if boost.update(active, self.scene.revision()) && boost.stage() == 0 {
+                // Boosted frames say nothing about the interactive frame rate
+                self.frame_times.clear();
+            }
+        }
+
+        if let Some(tracker) = self.motion_tracker.as_mut() {
+            let (width, height) = self.viewport.render_size();
+            let motion = tracker.update(&self.scene, &self.camera, width as f32, height as f32);
+            self.history_reject_regions = motion.regions;
+            self.history_reject_contained = motion.contained && !view_changed;
+        }
+
+        let features = self.shader_features(&self.scene);
+        if let Err(e) = self.select_shader_variant(features, false) {
+            // Keep drawing with the current variant
+            console::error_1(&e.into());
+        }
+
+        if let Some(auto_exposure) = self.auto_exposure {
+            self.update_exposure(auto_exposure, (delta_time / 1000.0) as f32)?;
+        }
+
+        // Clear the whole canvas (including any letterbox bars), then restrict
+        // drawing to the content area
+        self.gl.viewport(
+            0,
+            0,
+            self.viewport.width as i32,
+            self.viewport.height as i32,
+        );
+        let [r, g, b, a] = self.canvas_clear_color();
+        self.gl.clear_color(r, g, b, a);
+        self.gl.clear(WebGlRenderingContext::COLOR_BUFFER_BIT);
+
+        let (content_x, content_y, content_width, content_height) = self.viewport.content_rect();
+        // GL window coordinates start at the bottom-left corner
+        let origin_x = content_x.round();
+        let origin_y = (self.viewport.height as f32 - content_y - content_height).round();
+        self.gl.viewport(
+            origin_x as i32,
+            origin_y as i32,
+            content_width.round() as i32,
+            content_height.round() as i32,
+        );
+
+        // Each frame gets the next sub-pixel jitter offset
+        self.frame_index = self.frame_index.wrapping_add(1);
+
+        let (render_width, render_height) = self.viewport.render_size();
+        let overlay = self
+            .reference_overlay
+            .filter(|_| self.reference_image.is_some());
+        if self.accumulation_enabled {
+            self.update_accumulation_key(render_width, render_height);
+        }
+        if render_width == content_width.round() as u32
+            && render_height == content_height.round() as u32
+            && overlay.is_none()
+            && !self.accumulation_enabled
+        {
+            self.active_light_count = self.draw_scene(
+                &self.scene,
+                &self.camera,
+                (content_width.round(), content_height.round()),
+                (origin_x, origin_y),
+                (scene_time / 1000.0) as f32,
+                gbuffer::OUTPUT_COLOR,
+            )?;
+            return Ok(());
+        }
+
+        // Reduced render scale, accumulation or an overlay: trace into a
+        // buffer, then stretch it over the content area, compositing the
+        // reference image if shown
+        let target = if self.accumulation_enabled {
+            self.ensure_accumulation(render_width, render_height)?;
+            self.accumulation.as_ref().map(Accumulation::frame)
+        } else {
+            self.ensure_scaled_target(render_width, render_height)?;
+            self.scaled_target.as_ref()
+        };
+        let Some(target) = target else {
+            return Ok(());
+        };
+        self.gl.bind_framebuffer(
+            WebGlRenderingContext::FRAMEBUFFER,
+            Some(&target.framebuffer),
+        );
+        self.gl
+            .viewport(0, 0, render_width as i32, render_height as i32);
+        self.gl.clear(WebGlRenderingContext::COLOR_BUFFER_BIT);
+        let light_count = self.draw_scene(
+            &self.scene,
+            &self.camera,
+            (render_width as f32, render_height as f32),
+            (0.0, 0.0),
+            (scene_time / 1000.0) as f32,
+            gbuffer::OUTPUT_COLOR,
+        );
+        if light_count.is_ok()
+            && let Some(accumulation) = self.accumulation.as_mut()
+        {
+            accumulation.add_frame(&self.gl, &self.accumulate_program, &self.quad_buffer);
+        }
+
+        self.gl.bind_framebuffer(WebGlRenderingContext::FRAMEBUFFER, None);
+        self.gl.viewport(
+            origin_x as i32,
+            origin_y as i32,
+            content_width.round() as i32,
+            content_height.round() as i32,
+        );
+        self.active_light_count = light_count?;
+        let average = self.accumulation.as_ref().map(Accumulation::average);
+        let Some(target) = average.or(self.scaled_target.as_ref()) else {
+            return Ok(());
+        };
+        match (overlay, &self.reference_image) {
+            (Some(overlay), Some(reference)) => self.draw_overlay(target, reference, overlay),
+            _ => self.blit(target),
+        }
+
+        Ok(())
+    }
+
+    /// Renders a frame and returns the image area of the canvas as PNG bytes,
+    /// sRGB-encoded, with alpha only while the background is transparent.
+    #[wasm_bindgen]
+    pub fn capture_frame_png(&mut self) -> Result<Vec<u8>, RaytracerError> {
+        self.capture_png(CaptureOptions::default())
+    }
+
+    /// Like `capture_frame_png`, with `{color_space: "srgb" | "linear",
+    /// include_alpha: bool, include_overlay: bool}` options. Missing fields
+    /// keep their defaults; the reference overlay is left out unless
+    /// `include_overlay` is set.
+    #[wasm_bindgen]
+    pub fn capture_frame_png_with_options(
+        &mut self,
+        options: JsValue,
+    ) -> Result<Vec<u8>, RaytracerError> {
+        let options = options_from_js::<CaptureOptions>(&options, "capture options")?;
+        self.capture_png(options)
+    }
+
+    /// Renders `scene_json` into an offscreen `width` x `height` buffer with a
+    /// camera framing the scene's bounds, and returns RGBA pixels (top row
+    /// first). The live scene, camera and canvas are left untouched.
+    #[wasm_bindgen]
+    pub fn render_thumbnail(
+        &mut self,
+        scene_json: &str,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>, RaytracerError> {
+        if width == 0 || height == 0 {
+            return Err(RaytracerError::invalid_argument(
+                "size",
+                "thumbnail size must be non-zero",
+            ));
+        }
+
+        let scene = Scene::from_json(scene_json)?;
+        let bounds = scene
+            .bounding_box()
+            .unwrap_or_else(|| Aabb::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::one()));
+
+        let mut camera = Camera::new(
+            Vec3::zero(),
+            Vec3::zero(),
+            width as f32 / height as f32,
+        );
+        camera.frame_bounds(&bounds, THUMBNAIL_VIEW_DIRECTION);
+
+        self.select_shader_variant(self.shader_features(&scene), true)?;
+        let saved = GlState::capture(&self.gl);
+        let result = RenderTarget::new(&self.gl, width, height).and_then(|target| {
+            let pixels = self.render_offscreen(&target, &scene, &camera, gbuffer::OUTPUT_COLOR);
+            target.delete(&self.gl);
+            pixels
+        });
+        saved.restore(&self.gl);
+
+        Ok(gbuffer::flip_rows(&result?, width))
+    }
+
+    /// Renders linear depth and world normals of the current view at render
+    /// resolution and returns `{depth, normals, width, height}`: `depth` has
+    /// one float per pixel, `normals` three, both with the top row first.
+    /// See `gbuffer.rs` for the encoding.
+    #[wasm_bindgen]
+    pub fn render_gbuffer(&mut self) -> Result<JsValue, RaytracerError> {
+        let (width, height) = self.viewport.render_size();
+        self.select_shader_variant(self.shader_features(&self.scene), true)?;
+
+        let saved = GlState::capture(&self.gl);
+        let result = RenderTarget::new(&self.gl, width, height).and_then(|target| {
+            // WebGL1 has no multiple render targets, so one pass per buffer
+            let passes = self
+                .render_offscreen(&target, &self.scene, &self.camera, gbuffer::OUTPUT_DEPTH)
+                .and_then(|depth| {
+                    let normals = self.render_offscreen(
+                        &target,
+                        &self.scene,
+                        &self.camera,
+                        gbuffer::OUTPUT_NORMALS,
+                    )?;
+                    Ok((depth, normals))
+                });
+            target.delete(&self.gl);
+            passes
+        });
+        saved.restore(&self.gl);
+        let (depth, normals) = result?;
+
+        let far = gbuffer::GBUFFER_FAR * self.scene.world_scale;
+        let depth = gbuffer::decode_depth(&gbuffer::flip_rows(&depth, width), far);
+        let normals = gbuffer::decode_normals(&gbuffer::flip_rows(&normals, width));
+
+        let output = js_sys::Object::new();
+        js_sys::Reflect::set(
+            &output,
+            &"depth".into(),
+            &js_sys::Float32Array::from(depth.as_slice()),
+        )?;
+        js_sys::Reflect::set(
+            &output,
+            &"normals".into(),
+            &js_sys::Float32Array::from(normals.as_slice()),
+        )?;
+        js_sys::Reflect::set(&output, &"width".into(), &width.into())?;
+        js_sys::Reflect::set(&output, &"height".into(), &height.into())?;
+        Ok(output.into())
+    }
+
+    /// The WebGL version in use: `"webgl2"`, or `"webgl"` where WebGL2 isn't
+    /// available or was turned off with the `webgl2` option.
+    #[wasm_bindgen]
+    pub fn get_context_kind(&self) -> String {
+        ContextKind::of(&self.gl).name().to_string()
+    }
+
+    /// The shader variant in use, as its space-separated feature defines
+    /// (e.g. `"USE_FOG USE_AO"`) or `"base"`. Expensive features are only
+    /// compiled into the shader while the scene or quality settings use them.
+    #[wasm_bindgen]
+    pub fn get_active_shader_variant(&self) -> String {
+        self.shader_features.name()
+    }
+
+    /// Renders a segmentation mask of the current view at render resolution:
+    /// RGBA rows, top row first, each object a flat color encoding its id and
+    /// the background black. No lighting or anti-aliasing, so the mask lines
+    /// up pixel for pixel with the beauty pass. See `gbuffer.rs` for the
+    /// encoding and `get_segmentation_palette` for the ids in the scene.
+    #[wasm_bindgen]
+    pub fn render_segmentation(&mut self) -> Result<Vec<u8>, RaytracerError> {
+        let (width, height) = self.viewport.render_size();
+        self.select_shader_variant(self.shader_features(&self.scene), true)?;
+
+        let saved = GlState::capture(&self.gl);
+        let result = RenderTarget::new(&self.gl, width, height).and_then(|target| {
+            let pixels = self.render_offscreen(
+                &target,
+                &self.scene,
+                &self.camera,
+                gbuffer::OUTPUT_SEGMENTATION,
+            );
+            target.delete(&self.gl);
+            pixels
+        });
+        saved.restore(&self.gl);
+
+        Ok(gbuffer::flip_rows(&result?, width))
+    }
+
+    /// Maps each segmentation id the current scene can produce to its
+    /// `[r, g, b]` mask color, as `{ "65536": [1, 0, 0], ... }`.
+    #[wasm_bindgen]
+    pub fn get_segmentation_palette(&self) -> Result<JsValue, RaytracerError> {
+        to_js(&gbuffer::segmentation_palette(
+            &self.scene,
+            Some(self.camera.position()),
+            self.scene_data.is_some(),
+        ))
+    }
+
+    /// Makes pixels whose primary ray hits nothing transparent so the page
+    /// shows through; reflections and refractions still see the background
+    /// color. Fails if the context was created without an alpha channel.
+    #[wasm_bindgen]
+    pub fn set_transparent_background(&mut self, enabled: bool) -> Result<(), RaytracerError> {
+        if enabled && !webgl::has_alpha(&self.gl) {
+            return Err(RaytracerError::Unsupported {
+                feature: "a transparent background on a context created with alpha: false; \
+                          create the Raytracer with {alpha: true}"
+                    .to_string(),
+            });
+        }
+        self.transparent_background = enabled;
+        Ok(())
+    }
+
+    /// Sets the color the canvas is cleared to each frame (default opaque
+    /// black). It fills the letterbox bars of an aspect lock and anything
+    /// the scene doesn't draw over; rays that miss use the scene's
+    /// background color instead. Alpha only matters on a context created
+    /// with `{alpha: true}`.
+    #[wasm_bindgen]
+    pub fn set_clear_color(&mut self, r: f32, g: f32, b: f32, a: f32) {
+        self.clear_color = [r, g, b, a].map(|c| c.clamp(0.0, 1.0));
+    }
+
+    /// Sets the scene's background color, which rays that miss everything
+    /// see: the top of the sky gradient, or the sky behind a starfield.
+    #[wasm_bindgen]
+    pub fn set_background_color(&mut self, r: f32, g: f32, b: f32) {
+        self.scene.set_background(Vec3::new(r, g, b));
+    }
+
+    #[wasm_bindgen]
+    pub fn get_background_color(&self) -> Vec<f32> {
+        let color = self.scene.background_color;
+        vec![color.x, color.y, color.z]
+    }
+
+    /// Sets the exposure in stops (0 = unchanged, +1 = twice as bright) and
+    /// turns automatic exposure off.
+    #[wasm_bindgen]
+    pub fn set_exposure(&mut self, ev: f32) {
+        self.auto_exposure = None;
+        self.exposure_ev = ev;
+    }
+
+    /// Adapts the exposure to the scene brightness every frame, like an
+    /// eye: a small metering render finds the view's log-average luminance
+    /// and the exposure eases toward mapping it to middle grey. `speed` is
+    /// the adaptation rate per second (about 1 to 3 feels natural) and the
+    /// exposure stays between `min_ev` and `max_ev` stops.
+    #[wasm_bindgen]
+    pub fn set_auto_exposure(&mut self, enabled: bool, speed: f32, min_ev: f32, max_ev: f32) {
+        self.auto_exposure = enabled.then(|| AutoExposure::new(speed, min_ev, max_ev));
+        if let Some(auto_exposure) = self.auto_exposure {
+            self.exposure_ev = self.exposure_ev.clamp(auto_exposure.min_ev, auto_exposure.max_ev);
+        }
+    }
+
+    /// The exposure in force, in stops.
+    #[wasm_bindgen]
+    pub fn get_current_exposure(&self) -> f32 {
+        self.exposure_ev
+    }
+
+    /// Sets the reference image compared against by `set_reference_overlay`:
+    /// `width` x `height` RGBA pixels, top row first (as in `ImageData`). It
+    /// is stretched over the image area.
+    #[wasm_bindgen]
+    pub fn set_reference_image(
+        &mut self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<(), RaytracerError> {
+        if width == 0 || height == 0 || pixels.len() != (width * height * 4) as usize {
+            return Err(RaytracerError::invalid_argument("pixels", format!(
+                "expected {} bytes of RGBA for a {}x{} reference image, got {}",
+                width as usize * height as usize * 4,
+                width,
+                height,
+                pixels.len()
+            )));
+        }
+
+        let texture = webgl::create_texture_with_pixels(&self.gl, width, height, pixels)?;
+        for filter in [
+            WebGlRenderingContext::TEXTURE_MIN_FILTER,
+            WebGlRenderingContext::TEXTURE_MAG_FILTER,
+        ] {
+            self.gl.tex_parameteri(
+                WebGlRenderingContext::TEXTURE_2D,
+                filter,
+                WebGlRenderingContext::LINEAR as i32,
+            );
+        }
+        if let Some(old) = self.reference_image.replace(texture) {
+            self.gl.delete_texture(Some(&old));
+        }
+        self.reference_image_bytes = pixels.len();
+        Ok(())
+    }
+
+    /// Textures a sphere with `width` x `height` RGBA pixels, top row first
+    /// (as in `ImageData`), wrapped around it by longitude and latitude and
+    /// replacing its albedo. Up to four spheres can have images at once; a
+    /// sphere's image is freed when it is removed or the scene is replaced,
+    /// and isn't saved with the scene.
+    #[wasm_bindgen]
+    pub fn set_sphere_texture(
+        &mut self,
+        index: usize,
+        image_data: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<(), RaytracerError> {
+        let sphere_count = self.scene.spheres.len();
+        let sphere = self
+            .scene
+            .spheres
+            .get(index)
+            .ok_or(RaytracerError::IndexOutOfRange {
+                kind: "sphere",
+                index,
+                len: sphere_count,
+            })?;
+        if sphere.meta.locked {
+            return Err(RaytracerError::Locked {
+                kind: "sphere",
+                index,
+            });
+        }
+        if width == 0 || height == 0 || image_data.len() != (width * height * 4) as usize {
+            return Err(RaytracerError::invalid_argument("image_data", format!(
+                "expected {} bytes of RGBA for a {}x{} image, got {}",
+                width as usize * height as usize * 4,
+                width,
+                height,
+                image_data.len()
+            )));
+        }
+
+        let slot = match sphere.image_texture {
+            Some(slot) => slot,
+            None => self.image_textures.free_slot(&self.scene).ok_or_else(|| {
+                RaytracerError::Unsupported {
+                    feature: format!("more than {} textured spheres", MAX_IMAGE_TEXTURES),
+                }
+            })?,
+        };
+        self.image_textures
+            .upload(&self.gl, slot, image_data, width, height)?;
+        self.scene.spheres[index].image_texture = Some(slot);
+        self.scene.touch(ObjectType::Sphere, index);
+        Ok(())
+    }
+
+    /// Removes a sphere's image texture, if it has one. Returns false if
+    /// there is no such sphere or it is locked.
+    #[wasm_bindgen]
+    pub fn clear_sphere_texture(&mut self, index: usize) -> bool {
+        match self.scene.spheres.get_mut(index) {
+            Some(sphere) if !sphere.meta.locked => {
+                if sphere.image_texture.take().is_some() {
+                    self.scene.touch(ObjectType::Sphere, index);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Drops the reference image; any overlay stops showing.
+    #[wasm_bindgen]
+    pub fn clear_reference_image(&mut self) {
+        if let Some(old) = self.reference_image.take() {
+            self.gl.delete_texture(Some(&old));
+        }
+        self.reference_image_bytes = 0;
+    }
+
+    /// Shows the reference image over the render: `mode` 0 turns it off,
+    /// 1 is a wipe with the reference left of `split` (0 to 1 across the
+    /// image) and the render right of it, and 2 shows the per-pixel
+    /// difference multiplied by `gain` (default 4). Cheap enough to call on
+    /// every move of an A/B slider. Captures leave the overlay out unless
+    /// asked to include it.
+    #[wasm_bindgen]
+    pub fn set_reference_overlay(
+        &mut self,
+        mode: u32,
+        split: f32,
+        gain: Option<f32>,
+    ) -> Result<(), RaytracerError> {
+        self.reference_overlay = match mode {
+            0 => None,
+            1 | 2 => Some(ReferenceOverlay {
+                mode,
+                split: split.clamp(0.0, 1.0),
+                gain: gain.unwrap_or(DEFAULT_DIFFERENCE_GAIN).max(0.0),
+            }),
+            _ => {
+                return Err(RaytracerError::invalid_argument("mode", format!(
+                    "unknown overlay mode {}, expected 0 (off), 1 (wipe) or 2 (difference)",
+                    mode
+                )));
+            }
+        };
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn get_fps(&self) -> f64 {
+        self.fps
+    }
+
+    /// Whether the camera moved beyond the change thresholds between the
+    /// previous `render()` and the latest one.
+    #[wasm_bindgen]
+    pub fn camera_changed_since_last_frame(&self) -> bool {
+        self.camera_changed
+    }
+
+    /// Frames rendered since the camera or scene last changed; 0 for the
+    /// first frame after a change. Feeds the shader's noise seed.
+    #[wasm_bindgen]
+    pub fn get_frames_since_change(&self) -> u32 {
+        self.frames_since_change
+    }
+
+    /// Tracks object motion between frames so temporal history can be reset
+    /// only where objects moved instead of across the whole image. With
+    /// accumulation on, a frame whose only changes are objects moving,
+    /// appearing or disappearing under a still camera restarts the average
+    /// inside their regions and keeps it everywhere else. Shadows and
+    /// reflections they cast elsewhere fade in as the average moves on. See
+    /// `get_history_reject_regions`.
+    #[wasm_bindgen]
+    pub fn set_taa_motion_rejection(&mut self, enabled: bool) {
+        self.motion_tracker = enabled.then(|| MotionTracker::new(&self.scene));
+        self.history_reject_regions.clear();
+        self.history_reject_contained = false;
+    }
+
+    /// Screen rectangles, in render-resolution pixels from the top-left, that
+    /// the last `render()` found covered by a moving object at its old or new
+    /// position, as flat `[min_x, min_y, max_x, max_y, ...]`. At most
+    /// `MAX_REJECT_REGIONS` (8); more are merged into one. Empty unless
+    /// `set_taa_motion_rejection(true)`.
+    #[wasm_bindgen]
+    pub fn get_history_reject_regions(&self) -> Vec<f32> {
+        self.history_reject_regions.concat()
+    }
+
+    /// Sets how far the camera may drift (in world units, and per component
+    /// of its unit basis vectors) before it counts as moved. Negative values
+    /// restore the defaults.
+    #[wasm_bindgen]
+    pub fn set_camera_change_threshold(&mut self, position: f32, direction: f32) {
+        self.camera_thresholds = (
+            if position < 0.0 { CAMERA_POSITION_EPSILON } else { position },
+            if direction < 0.0 { CAMERA_DIRECTION_EPSILON } else { direction },
+        );
+    }
+
+    /// How close the renderer is to the GPU's limits, for diagnosing blank
+    /// renders on weak hardware:
+    ///
+    /// - `max_fragment_uniform_vectors`: what the driver allows
+    /// - `uniform_vectors`: estimated use by the active shader variant
+    /// - `uploaded_scene_bytes`: scene data sent by the last draw
+    /// - `texture_bytes`: memory of the renderer's textures and buffers
+    /// - `limits` and `counts`: per kind of object, how many the shader holds
+    ///   and how many the scene has
+    #[wasm_bindgen]
+    pub fn get_gpu_budget(&self) -> Result<JsValue, RaytracerError> {
+        let mut limits = BTreeMap::new();
+        let mut counts = BTreeMap::new();
+        for (name, limit, count) in self.object_counts() {
+            limits.insert(name, limit);
+            counts.insert(name, count);
+        }
+
+        let texture_bytes = self.reference_image_bytes
+            + self.image_textures.bytes()
+            + self.scene_data.as_ref().map_or(0, |data| data.borrow().bytes())
+            + self.light_block.as_ref().map_or(0, |block| block.borrow().bytes())
+            + self.accumulation.as_ref().map_or(0, Accumulation::bytes)
+            + [&self.scaled_target, &self.meter_target]
+                .into_iter()
+                .flatten()
+                .map(RenderTarget::bytes)
+                .sum::<usize>();
+
+        to_js(&GpuBudget {
+            max_fragment_uniform_vectors: webgl::max_fragment_uniform_vectors(&self.gl),
+            uniform_vectors: webgl::uniform_vectors(&self.gl, &self.program),
+            uploaded_scene_bytes: self.uploaded_scene_bytes.get(),
+            texture_bytes,
+            limits,
+            counts,
+        })
+    }
+
+    /// The BVH the shader walks to find triangles: `triangles`, `nodes` and
+    /// `depth` of the last one built, `build_ms` it took and how many
+    /// `builds` there have been. It's rebuilt whenever triangle vertices
+    /// change. All zero without the scene data texture, which holds it.
+    #[wasm_bindgen]
+    pub fn get_bvh_stats(&self) -> Result<JsValue, RaytracerError> {
+        to_js(&self.bvh.borrow().stats())
+    }
+
+    /// Number of lights uploaded for the last frame after culling.
+    #[wasm_bindgen]
+    pub fn get_active_light_count(&self) -> usize {
+        self.active_light_count
+    }
+
+    /// Development aid for scenes that render black or white speckle. While
+    /// enabled, the scene is checked for degenerate objects (non-finite
+    /// values, zero radii, zero-area triangles, ...) whenever it changes and
+    /// each one is logged, and a debug shader variant draws pixels whose
+    /// shading came out non-finite in magenta. Off by default; when off the
+    /// shader has no trace of it.
+    #[wasm_bindgen]
+    pub fn enable_nan_guard(&mut self, enabled: bool) {
+        self.nan_guard = enabled;
+        self.nan_guard_revision = None;
+    }
+
+    /// Renders the current view with the NaN guard at a fraction of the
+    /// render resolution and counts the magenta pixels, each standing for
+    /// `NAN_GUARD_DOWNSAMPLE`² pixels of the full image. Works whether or not
+    /// the guard is enabled.
+    #[wasm_bindgen]
+    pub fn get_nan_pixel_count(&mut self) -> Result<u32, RaytracerError> {
+        let (width, height) = self.viewport.render_size();
+        let width = (width / NAN_GUARD_DOWNSAMPLE).max(1);
+        let height = (height / NAN_GUARD_DOWNSAMPLE).max(1);
+        let features = ShaderFeatures {
+            nan_guard: true,
+            ..self.shader_features(&self.scene)
+        };
+        self.select_shader_variant(features, true)?;
+
+        let saved = GlState::capture(&self.gl);
+        let result = RenderTarget::new(&self.gl, width, height).and_then(|target| {
+            let pixels = self.render_offscreen(
+                &target,
+                &self.scene,
+                &self.camera,
+                gbuffer::OUTPUT_COLOR,
+            );
+            target.delete(&self.gl);
+            pixels
+        });
+        saved.restore(&self.gl);
+
+        let count = result?
+            .chunks_exact(4)
+            .filter(|pixel| pixel[..3] == NAN_GUARD_COLOR)
+            .count();
+        Ok(count as u32)
+    }
+
+    /// Renders the current view offscreen at `width` x `height` and returns
+    /// its RGBA pixels, top row first. Nothing is drawn to the canvas and the
+    /// overlay is left out, so the result depends only on the scene, camera
+    /// and scene time; meant for comparing renders across changes.
+    #[wasm_bindgen]
+    pub fn render_to_pixels(&mut self, width: u32, height: u32) -> Result<Vec<u8>, RaytracerError> {
+        if width == 0 || height == 0 {
+            return Err(RaytracerError::invalid_argument(
+                "size",
+                "width and height must be non-zero",
+            ));
+        }
+        self.select_shader_variant(self.shader_features(&self.scene), true)?;
+
+        let saved = GlState::capture(&self.gl);
+        let result = RenderTarget::new(&self.gl, width, height).and_then(|target| {
+            let pixels = self.render_offscreen(
+                &target,
+                &self.scene,
+                &self.camera,
+                gbuffer::OUTPUT_COLOR,
+            );
+            target.delete(&self.gl);
+            pixels
+        });
+        saved.restore(&self.gl);
+
+        // GL rows run bottom to top
+        Ok(result?
+            .chunks_exact(width as usize * 4)
+            .rev()
+            .flatten()
+            .copied()
+            .collect())
+    }
+
+    /// Drops imported meshes to their low-detail triangles while the rolling
+    /// FPS is below `threshold_fps`, switching back once it recovers with some
+    /// headroom. A threshold of 0 disables LOD switching.
+    #[wasm_bindgen]
+    pub fn set_lod_policy(&mut self, threshold_fps: f64) {
+        if threshold_fps > 0.0 {
+            self.lod_threshold_fps = Some(threshold_fps);
+        } else {
+            self.lod_threshold_fps = None;
+            self.active_lod = 0;
+        }
+    }
+
+    /// Applies a quality preset, setting bounces, samples per pixel, shadows,
+    /// ambient occlusion, soft shadows and render scale together:
+    /// 0 = low, 1 = medium, 2 = high, 3 = ultra. Turns automatic quality off.
+    #[wasm_bindgen]
+    pub fn set_quality(&mut self, level: u32) -> Result<(), RaytracerError> {
+        let level = QualityLevel::from_u32(level).ok_or_else(|| {
+            RaytracerError::invalid_argument("level", format!(
+                "unknown quality level {}, expected 0 (low) to 3 (ultra)",
+                level
+            ))
+        })?;
+        self.quality_governor = None;
+        self.apply_quality_level(level);
+        Ok(())
+    }
+
+    /// Sets how many path segments are traced per sample, clamped to 1 to 16.
+    /// Fewer bounces are faster but darken reflections, glass and indirect
+    /// light. Overrides the preset's value and turns automatic quality off;
+    /// kept across scene loads and `clear_scene`.
+    #[wasm_bindgen]
+    pub fn set_max_bounces(&mut self, bounces: u32) {
+        self.quality_governor = None;
+        self.quality.max_bounces = bounces.clamp(1, MAX_BOUNCES);
+    }
+
+    #[wasm_bindgen]
+    pub fn get_max_bounces(&self) -> u32 {
+        self.quality.max_bounces
+    }
+
+    /// Sets how many jittered primary rays are averaged per pixel, clamped to
+    /// 1 to 16. Each one costs about a full frame's worth of tracing; 4
+    /// visibly smooths edges. Overrides the preset's value and turns
+    /// automatic quality off.
+    #[wasm_bindgen]
+    pub fn set_samples_per_pixel(&mut self, samples: u32) {
+        self.quality_governor = None;
+        self.quality.samples_per_pixel = samples.clamp(1, MAX_SAMPLES_PER_PIXEL);
+    }
+
+    #[wasm_bindgen]
+    pub fn get_samples_per_pixel(&self) -> u32 {
+        self.quality.samples_per_pixel
+    }
+
+    /// Anti-aliases edges by tracing at least `samples` primary rays per
+    /// pixel (1 = off, at most 4), each through a jittered point of the
+    /// pixel. Costs about `samples` times a single-sample frame. Ignored
+    /// while accumulating, where each frame's jitter already averages out
+    /// over the accumulated frames.
+    #[wasm_bindgen]
+    pub fn set_antialiasing(&mut self, samples: u32) {
+        self.antialiasing = samples.clamp(1, MAX_ANTIALIASING_SAMPLES);
+    }
+
+    #[wasm_bindgen]
+    pub fn get_antialiasing(&self) -> u32 {
+        self.antialiasing
+    }
+
+    /// Traces a buffer `factor` times the size of the image (0.25 to 1.0)
+    /// and stretches it over the canvas, trading sharpness for speed on large
+    /// canvases. The buffer follows canvas resizes. Overrides the preset's
+    /// value and turns automatic quality off.
+    #[wasm_bindgen]
+    pub fn set_render_scale(&mut self, factor: f32) {
+        self.quality_governor = None;
+        self.quality.render_scale = if factor.is_finite() { factor.clamp(0.25, 1.0) } else { 1.0 };
+        self.apply_render_scale();
+    }
+
+    /// The render scale set by `set_render_scale` or the quality preset; see
+    /// `get_effective_render_scale` for the one in use.
+    #[wasm_bindgen]
+    pub fn get_render_scale(&self) -> f32 {
+        self.quality.render_scale
+    }
+
+    /// Sets the frame rate adaptive quality aims for (default 60).
+    #[wasm_bindgen]
+    pub fn set_target_fps(&mut self, fps: f64) -> Result<(), RaytracerError> {
+        if !(fps.is_finite() && fps > 0.0) {
+            return Err(RaytracerError::invalid_argument(
+                "fps",
+                format!("target frame rate must be positive, got {}", fps),
+            ));
+        }
+        self.target_fps = fps;
+        if let Some(adaptive) = self.adaptive_scale.as_mut() {
+            adaptive.target_fps = fps;
+        }
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn get_target_fps(&self) -> f64 {
+        self.target_fps
+    }
+
+    /// Lowers or raises the render scale (0.25 to 1.0) every few frames to
+    /// hold the `set_target_fps` frame rate, leaving every other setting as
+    /// it is. Turns automatic quality off; disabling goes back to the render
+    /// scale set by hand or by the preset.
+    #[wasm_bindgen]
+    pub fn set_adaptive_quality(&mut self, enabled: bool) {
+        if enabled == self.adaptive_scale.is_some() {
+            return;
+        }
+        self.adaptive_scale =
+            enabled.then(|| AdaptiveScale::new(self.target_fps, self.quality.render_scale));
+        if enabled {
+            self.quality_governor = None;
+        }
+        self.apply_render_scale();
+        self.frame_times.clear();
+    }
+
+    /// The render scale frames are currently drawn at, including adaptive
+    /// changes.
+    #[wasm_bindgen]
+    pub fn get_effective_render_scale(&self) -> f32 {
+        self.viewport.render_scale
+    }
+
+    /// Steps the quality level down when FPS drops below 90% of `target_fps`
+    /// and back up above 130% of it, at most once every two seconds.
+    /// Starts from the current level (high if none was chosen). A target of
+    /// 0 or less turns it off and keeps the level in force.
+    #[wasm_bindgen]
+    pub fn set_quality_auto(&mut self, target_fps: f64) {
+        if target_fps <= 0.0 {
+            self.quality_governor = None;
+            return;
+        }
+        self.adaptive_scale = None;
+        let level = self.quality_level.unwrap_or(QualityLevel::High);
+        self.apply_quality_level(level);
+        self.quality_governor = Some(QualityGovernor::new(target_fps, level, Date::now()));
+    }
+
+    /// The quality settings in force: `{level, auto, max_bounces,
+    /// samples_per_pixel, shadows, ambient_occlusion_samples, render_scale,
+    /// soft_shadows, volume_steps}`. `level` is null until a preset is chosen.
+    #[wasm_bindgen]
+    pub fn get_effective_settings(&self) -> Result<JsValue, RaytracerError> {
+        to_js(&QualityReport {
+            level: self.quality_level,
+            auto: self.quality_governor.is_some(),
+            settings: self.quality,
+        })
+    }
+
+    /// Raises quality step by step while the camera and scene stay still:
+    /// after `delay_frames` idle frames samples per pixel double, after twice
+    /// that soft shadows and ambient occlusion turn on, after three times the
+    /// shader's maximum samples, bounces and volume steps are used. The first
+    /// frame with camera movement or a scene change is drawn with the
+    /// interactive settings again. Automatic quality pauses while boosted.
+    #[wasm_bindgen]
+    pub fn set_idle_boost(&mut self, enabled: bool, delay_frames: u32) {
+        if !enabled {
+            if self.idle_boost.take().is_some_and(|boost| boost.stage() > 0) {
+                self.frame_times.clear();
+            }
+            return;
+        }
+        match self.idle_boost.as_mut() {
+            Some(boost) => boost.delay_frames = delay_frames.max(1),
+            None => self.idle_boost = Some(IdleBoost::new(delay_frames)),
+        }
+    }
+
+    /// Turns progressive accumulation on or off (default off). While on,
+    /// each frame is averaged with the frames before it, so a still view
+    /// converges to a noise-free image; any camera movement or change to the
+    /// scene, render settings or canvas size starts the average over.
+    #[wasm_bindgen]
+    pub fn set_accumulation(&mut self, enabled: bool) {
+        self.accumulation_enabled = enabled;
+        if !enabled && let Some(accumulation) = self.accumulation.take() {
+            accumulation.delete(&self.gl);
+        }
+        self.accumulation_key = None;
+    }
+
+    /// Starts the accumulated average over with the next frame.
+    #[wasm_bindgen]
+    pub fn reset_accumulation(&mut self) {
+        if let Some(accumulation) = self.accumulation.as_mut() {
+            accumulation.reset();
+        }
+    }
+
+    /// Frames averaged into the displayed image, each with the quality's
+    /// samples per pixel; 0 while accumulation is off.
+    #[wasm_bindgen]
+    pub fn get_sample_count(&self) -> u32 {
+        self.accumulation.as_ref().map_or(0, Accumulation::samples)
+    }
+
+    /// `{idle_boost, idle_frames, boost_stage, max_bounces, samples_per_pixel,
+    /// shadows, ambient_occlusion_samples, render_scale, soft_shadows,
+    /// volume_steps}`, with the settings frames are currently drawn with.
+    #[wasm_bindgen]
+    pub fn get_current_quality_state(&self) -> Result<JsValue, RaytracerError> {
+        to_js(&QualityState {
+            idle_boost: self.idle_boost.is_some(),
+            idle_frames: self.idle_boost.map_or(0, |boost| boost.idle_frames()),
+            boost_stage: self.idle_boost.map_or(0, |boost| boost.stage()),
+            settings: self.effective_quality(),
+        })
+    }
+
+    /// Current level of detail: 0 = full meshes, 1 = low detail.
+    #[wasm_bindgen]
+    pub fn get_active_lod(&self) -> u32 {
+        self.active_lod
+    }
+
+    #[wasm_bindgen]
+    pub fn move_camera(&mut self, forward: f32, right: f32, up: f32) {
+        self.cancel_camera_motion();
+        let start = self.camera.position();
+        self.camera.move_relative(forward, right, up);
+        self.constrain_camera_move(start);
+        self.bump_generation();
+    }
+
+    /// Keeps camera movement (`move_camera` and the built-in controls) from
+    /// passing through objects: the camera acts as a sphere of `radius` that
+    /// stops on contact and slides along surfaces, so it can walk on the
+    /// ground. Teleports via `set_camera_position` are not checked. Disabled,
+    /// the camera flies freely.
+    #[wasm_bindgen]
+    pub fn set_camera_collision(&mut self, enabled: bool, radius: f32) {
+        self.camera_collision = enabled.then_some(radius.max(0.0));
+    }
+
+    #[wasm_bindgen]
+    pub fn rotate_camera(&mut self, yaw: f32, pitch: f32) {
+        self.cancel_camera_motion();
+        self.camera.rotate(yaw, pitch);
+        self.bump_generation();
+    }
+
+    /// Installs the built-in WASD + mouse-look controller, replacing any
+    /// previous one. Options: `{move_speed, look_sensitivity, zoom_speed,
+    /// invert_y, bindings: {forward, backward, left, right, up, down}}`, with
+    /// bindings given as `KeyboardEvent.code` values.
+    #[wasm_bindgen]
+    pub fn attach_default_controls(&mut self, options: JsValue) -> Result<(), RaytracerError> {
+        let options = options_from_js::<ControlOptions>(&options, "control options")?;
+        let canvas = self
+            .gl
+            .canvas()
+            .and_then(|canvas| canvas.dyn_into::<web_sys::HtmlCanvasElement>().ok())
+            .ok_or_else(|| RaytracerError::Unsupported {
+                feature: "input controls on a context without a canvas".to_string(),
+            })?;
+
+        // Drop the old controller first so its listeners are gone
+        self.controls = None;
+        let mut controls = Controls::attach(&canvas, options)?;
+        controls.set_world_scale(self.scene.world_scale);
+        self.controls = Some(controls);
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn detach_controls(&mut self) {
+        self.controls = None;
+    }
+
+    #[wasm_bindgen]
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), RaytracerError> {
+        self.viewport.width = width;
+        self.viewport.height = height;
+        self.camera.set_aspect_ratio(self.viewport.aspect_ratio());
+        Ok(())
+    }
+
+    /// Sets the ratio between canvas pixels and CSS pixels (`window.devicePixelRatio`).
+    #[wasm_bindgen]
+    pub fn set_pixel_ratio(&mut self, ratio: f32) {
+        if ratio > 0.0 {
+            self.viewport.pixel_ratio = ratio;
+        }
+    }
+
+    /// Locks the rendered image to `ratio` (width / height), letterboxing it
+    /// inside the canvas. Passing `undefined` fills the whole canvas again.
+    #[wasm_bindgen]
+    pub fn set_aspect_lock(&mut self, ratio: Option<f32>) {
+        self.viewport.aspect_lock = ratio.filter(|ratio| *ratio > 0.0);
+        self.camera.set_aspect_ratio(self.viewport.aspect_ratio());
+    }
+
+    /// Maps CSS-pixel coordinates relative to the canvas to render-buffer pixels.
+    ///
+    /// Returns `[x, y]` with the origin at the top-left of the rendered image, or
+    /// `null` if the point falls in a letterbox bar. Pixel ratio, render scale
+    /// and aspect lock are all accounted for.
+    #[wasm_bindgen]
+    pub fn client_to_render_coords(&self, client_x: f32, client_y: f32) -> JsValue {
+        match self.viewport.client_to_render(client_x, client_y) {
+            Some((x, y)) => js_sys::Array::of2(&x.into(), &y.into()).into(),
+            None => JsValue::NULL,
+        }
+    }
+
+    /// Objects whose projected bounds overlap the rectangle between `(x0, y0)`
+    /// and `(x1, y1)` in render-buffer pixels (see `client_to_render_coords`),
+    /// as an array of `{type, index}`.
+    #[wasm_bindgen]
+    pub fn select_in_rect(
+        &self,
+        x0: f32,
+        y0: f32,
+        x1: f32,
+        y1: f32,
+    ) -> Result<JsValue, RaytracerError> {
+        let (width, height) = self.viewport.render_size();
+        let selected = selection::objects_in_rect(
+            &self.scene,
+            &self.camera,
+            (x0, y0),
+            (x1, y1),
+            width as f32,
+            height as f32,
+        );
+        to_js(&selected)
+    }
+
+    /// Like `select_in_rect`, with the corners in CSS pixels relative to the
+    /// canvas. Corners in a letterbox bar are moved to the image's edge.
+    #[wasm_bindgen]
+    pub fn select_in_rect_client(
+        &self,
+        client_x0: f32,
+        client_y0: f32,
+        client_x1: f32,
+        client_y1: f32,
+    ) -> Result<JsValue, RaytracerError> {
+        let (x0, y0) = self.viewport.client_to_render_clamped(client_x0, client_y0);
+        let (x1, y1) = self.viewport.client_to_render_clamped(client_x1, client_y1);
+        self.select_in_rect(x0, y0, x1, y1)
+    }
+
+    /// Highlights an object in the render: seen directly, it is tinted
+    /// orange with a rim that brightens toward its silhouette. Replaces any
+    /// earlier selection. Removing the object clears the selection, and
+    /// removing one before it keeps the highlight on the same object.
+    ///
+    /// `object_type` is 0 = sphere, 1 = plane, 2 = box, 3 = cylinder, 4 = triangle,
+    /// 6 = cone, 7 = disk.
+    #[wasm_bindgen]
+    pub fn set_selected_object(
+        &mut self,
+        object_type: u32,
+        index: usize,
+    ) -> Result<(), RaytracerError> {
+        let object_type = object_type_from_js(object_type)?;
+        if index >= self.scene.object_count(object_type) {
+            return Err(RaytracerError::invalid_argument(
+                "index",
+                format!("no {} at index {}", object_type.name(), index),
+            ));
+        }
+        if self.selected_object != Some((object_type, index)) {
+            self.selected_object = Some((object_type, index));
+            self.bump_generation();
+        }
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_selection(&mut self) {
+        if self.selected_object.take().is_some() {
+            self.bump_generation();
+        }
+    }
+
+    /// The highlighted object as `{type, index}`, or `null` if there is none.
+    #[wasm_bindgen]
+    pub fn get_selected_object(&self) -> Result<JsValue, RaytracerError> {
+        match self.selected_object {
+            Some((object_type, index)) => to_js(&selection::ObjectRef {
+                object_type: object_type.to_u32(),
+                index,
+            }),
+            None => Ok(JsValue::NULL),
+        }
+    }
+
+    /// Like `add_sphere_ex` with roughness 0.1 and IOR 1.5.
+    #[wasm_bindgen]
+    pub fn add_sphere(
+        &mut self,
+        x: f32,
+        y: f32,
+        z: f32,
+        radius: f32,
+        r: f32,
+        g: f32,
+        b: f32,
+        material_type: u32,
+    ) -> u32 {
+        self.add_sphere_ex(x, y, z, radius, r, g, b, material_type, 0.1, 1.5)
+    }
+
+    /// Adds a sphere and returns its id (see `get_object_by_id`).
+    /// `roughness` (metals) is clamped to [0, 1] and `ior` (dielectrics) to
+    /// at least 1.
+    #[wasm_bindgen]
+    pub fn add_sphere_ex(
+        &mut self,
+        x: f32,
+        y: f32,
+        z: f32,
+        radius: f32,
+        r: f32,
+        g: f32,
+        b: f32,
+        material_type: u32,
+        roughness: f32,
+        ior: f32,
+    ) -> u32 {
+        let material_type = MaterialType::from_u32(material_type).unwrap_or_default();
+
+        let sphere = Sphere::new(
+            Vec3::new(x, y, z),
+            radius,
+            Material::new(material_type, Vec3::new(r, g, b), roughness, ior).clamped(),
+        );
+
+        self.scene.add_sphere(sphere)
+    }
+
+    /// Adds an infinite plane through `(px, py, pz)` facing `(nx, ny, nz)`
+    /// and returns its id. The normal is normalized, so any length but 0
+    /// will do. Only the first five planes are drawn, unless objects are
+    /// read from the scene data texture.
+    #[wasm_bindgen]
+    pub fn add_plane(
+        &mut self,
+        px: f32,
+        py: f32,
+        pz: f32,
+        nx: f32,
+        ny: f32,
+        nz: f32,
+        r: f32,
+        g: f32,
+        b: f32,
+        material_type: u32,
+    ) -> Result<u32, RaytracerError> {
+        let normal = Vec3::new(nx, ny, nz);
+        if !normal.is_finite() || normal.length_squared() == 0.0 {
+            return Err(RaytracerError::invalid_argument(
+                "normal",
+                format!("plane normal must be finite and non-zero, got ({}, {}, {})", nx, ny, nz),
+            ));
+        }
+        let material_type = MaterialType::from_u32(material_type).unwrap_or_default();
+
+        let plane = Plane::new(
+            Vec3::new(px, py, pz),
+            normal,
+            Material::new(material_type, Vec3::new(r, g, b), 0.1, 1.5),
+        );
+
+        Ok(self.scene.add_plane(plane))
+    }
+
+    /// Adds an axis-aligned box centered on `(x, y, z)` with edge lengths
+    /// `(sx, sy, sz)` and returns its id. Fails if an edge length isn't
+    /// positive. Only the first five boxes are drawn, unless objects are read
+    /// from the scene data texture.
+    #[wasm_bindgen]
+    pub fn add_box(
+        &mut self,
+        x: f32,
+        y: f32,
+        z: f32,
+        sx: f32,
+        sy: f32,
+        sz: f32,
+        r: f32,
+        g: f32,
+        b: f32,
+        material_type: u32,
+    ) -> Result<u32, RaytracerError> {
+        if !(sx > 0.0 && sy > 0.0 && sz > 0.0 && Vec3::new(sx, sy, sz).is_finite()) {
+            return Err(RaytracerError::invalid_argument(
+                "size",
+                format!("box edge lengths must be positive, got ({}, {}, {})", sx, sy, sz),
+            ));
+        }
+        let material_type = MaterialType::from_u32(material_type).unwrap_or_default();
+
+        let box_obj = Box::new(
+            Vec3::new(x, y, z),
+            Vec3::new(sx, sy, sz),
+            Material::new(material_type, Vec3::new(r, g, b), 0.1, 1.5),
+        );
+
+        Ok(self.scene.add_box(box_obj))
+    }
+
+    /// Adds a cylinder running from `base` along `axis` and returns its id.
+    /// The axis length is the cylinder's height. Only the first five
+    /// cylinders are drawn, unless objects are read from the scene data
+    /// texture.
+    #[wasm_bindgen]
+    pub fn add_cylinder(
+        &mut self,
+        base_x: f32,
+        base_y: f32,
+        base_z: f32,
+        axis_x: f32,
+        axis_y: f32,
+        axis_z: f32,
+        radius: f32,
+        r: f32,
+        g: f32,
+        b: f32,
+        material_type: u32,
+    ) -> u32 {
+        let material_type = MaterialType::from_u32(material_type).unwrap_or_default();
+
+        let cylinder = Cylinder::new(
+            Vec3::new(base_x, base_y, base_z),
+            Vec3::new(axis_x, axis_y, axis_z),
+            radius.max(0.0),
+            Material::new(material_type, Vec3::new(r, g, b), 0.1, 1.5),
+        );
+
+        self.scene.add_cylinder(cylinder)
+    }
+
+    /// Adds a cone with its tip at `apex`, widening along `axis` to a closed
+    /// base of `radius`, and returns its id. The axis length is the cone's
+    /// height. Only the first five cones are drawn, unless objects are read
+    /// from the scene data texture.
+    #[wasm_bindgen]
+    pub fn add_cone(
+        &mut self,
+        apex_x: f32,
+        apex_y: f32,
+        apex_z: f32,
+        axis_x: f32,
+        axis_y: f32,
+        axis_z: f32,
+        radius: f32,
+        r: f32,
+        g: f32,
+        b: f32,
+        material_type: u32,
+    ) -> u32 {
+        let material_type = MaterialType::from_u32(material_type).unwrap_or_default();
+
+        let cone = Cone::new(
+            Vec3::new(apex_x, apex_y, apex_z),
+            Vec3::new(axis_x, axis_y, axis_z),
+            radius.max(0.0),
+            Material::new(material_type, Vec3::new(r, g, b), 0.1, 1.5),
+        );
+
+        self.scene.add_cone(cone)
+    }
+
+    /// Adds a flat disk of `outer_radius` around `(x, y, z)`, facing along
+    /// `(nx, ny, nz)`, and returns its id. A positive `inner_radius` makes it
+    /// a ring; 0 gives a solid disk. Both sides are drawn. Only the first
+    /// eight disks are drawn, unless objects are read from the scene data
+    /// texture.
+    #[wasm_bindgen]
+    pub fn add_disk(
+        &mut self,
+        x: f32,
+        y: f32,
+        z: f32,
+        nx: f32,
+        ny: f32,
+        nz: f32,
+        outer_radius: f32,
+        inner_radius: f32,
+        r: f32,
+        g: f32,
+        b: f32,
+        material_type: u32,
+    ) -> u32 {
+        let material_type = MaterialType::from_u32(material_type).unwrap_or_default();
+
+        let outer_radius = outer_radius.max(0.0);
+        let disk = Disk::new(
+            Vec3::new(x, y, z),
+            Vec3::new(nx, ny, nz),
+            outer_radius,
+            inner_radius.clamp(0.0, outer_radius),
+            Material::new(material_type, Vec3::new(r, g, b), 0.1, 1.5),
+        );
+
+        self.scene.add_disk(disk)
+    }
+
+    /// Adds a triangle with corners `v0`, `v1`, `v2` and returns its id. Its
+    /// front face is the one they wind counter-clockwise around. Only the
+    /// first ten triangles are drawn, unless objects are read from the scene
+    /// data texture, which holds up to `MAX_DATA_TRIANGLES` of them.
+    #[wasm_bindgen]
+    pub fn add_triangle(
+        &mut self,
+        x0: f32,
+        y0: f32,
+        z0: f32,
+        x1: f32,
+        y1: f32,
+        z1: f32,
+        x2: f32,
+        y2: f32,
+        z2: f32,
+        r: f32,
+        g: f32,
+        b: f32,
+        material_type: u32,
+    ) -> u32 {
+        let material_type = MaterialType::from_u32(material_type).unwrap_or_default();
+
+        self.scene.add_triangle(Triangle::new(
+            Vec3::new(x0, y0, z0),
+            Vec3::new(x1, y1, z1),
+            Vec3::new(x2, y2, z2),
+            Material::new(material_type, Vec3::new(r, g, b), 0.1, 1.5),
+        ))
+    }
+
+    /// Adds many triangles in one call, all with the same material. `vertices`
+    /// holds nine floats per triangle (`x0, y0, z0, x1, ..., z2`), so its
+    /// length must be a multiple of 9. Returns how many triangles were added.
+    #[wasm_bindgen]
+    pub fn add_triangles(
+        &mut self,
+        vertices: &[f32],
+        r: f32,
+        g: f32,
+        b: f32,
+        material_type: u32,
+    ) -> Result<usize, RaytracerError> {
+        if !vertices.len().is_multiple_of(9) {
+            return Err(RaytracerError::invalid_argument(
+                "vertices",
+                format!(
+                    "expected 9 floats per triangle, got {} (not a multiple of 9)",
+                    vertices.len()
+                ),
+            ));
+        }
+        let material_type = MaterialType::from_u32(material_type).unwrap_or_default();
+        let material = Material::new(material_type, Vec3::new(r, g, b), 0.1, 1.5);
+
+        for v in vertices.chunks_exact(9) {
+            self.scene.add_triangle(Triangle::new(
+                Vec3::new(v[0], v[1], v[2]),
+                Vec3::new(v[3], v[4], v[5]),
+                Vec3::new(v[6], v[7], v[8]),
+                material,
+            ));
+        }
+        Ok(vertices.len() / 9)
+    }
+
+    /// Adds a point light and returns its id. Only four lights are drawn at
+    /// a time; past that the ones contributing most to the view are picked
+    /// each frame.
+    #[wasm_bindgen]
+    pub fn add_light(
+        &mut self,
+        x: f32,
+        y: f32,
+        z: f32,
+        r: f32,
+        g: f32,
+        b: f32,
+        intensity: f32,
+    ) -> u32 {
+        self.scene.add_light(Light::new(
+            Vec3::new(x, y, z),
+            Vec3::new(r, g, b),
+            intensity.max(0.0),
+        ))
+    }
+
+    /// Adds a glowing ball of fog lit by the brightest light. `density` is
+    /// the extinction per unit length (0 is invisible, around 1 is a thick
+    /// puff). Only the first three volumes are drawn.
+    #[wasm_bindgen]
+    pub fn add_volume(
+        &mut self,
+        x: f32,
+        y: f32,
+        z: f32,
+        radius: f32,
+        density: f32,
+        r: f32,
+        g: f32,
+        b: f32,
+    ) {
+        self.scene.add_volume(Volume::new(
+            Vec3::new(x, y, z),
+            radius.max(0.0),
+            density.max(0.0),
+            Vec3::new(r, g, b),
+        ));
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_volumes(&mut self) {
+        self.scene.clear_volumes();
+    }
+
+    #[wasm_bindgen]
+    pub fn import_obj_file(
+        &mut self,
+        obj_data: &str,
+        name: &str,
+        r: f32,
+        g: f32,
+        b: f32,
+        material_type: u32,
+        roughness: f32,
+        ior: f32,
+    ) -> Result<(), RaytracerError> {
+        let material_type_enum = MaterialType::from_u32(material_type).unwrap_or_default();
+
+        let material = Material::new(material_type_enum, Vec3::new(r, g, b), roughness, ior);
+        
+        let mesh = Mesh::from_blender_obj(obj_data, material, name.to_string())?;
+        self.add_imported_mesh(mesh, 1.0, Vec3::zero());
+        
+        Ok(())
+    }
+
+    /// Appends the faces of an OBJ file to the scene as triangles with one
+    /// material, and returns how many were created. Vertices are converted
+    /// with the import axes, then scaled by `scale` and moved by the offset.
+    /// Quads and larger faces are split into triangles and negative indices
+    /// are followed; normals and texture coordinates are ignored. A face
+    /// that refers to a missing vertex fails with its line number, adding
+    /// nothing.
+    ///
+    /// `material_type` is 0 = Lambertian, 1 = metal, 2 = dielectric,
+    /// 3 = emissive. Triangles past the shader's limit are kept but not
+    /// drawn, so compare the count with `get_gpu_budget` to warn about that.
+    #[allow(clippy::too_many_arguments)]
+    #[wasm_bindgen]
+    pub fn load_obj(
+        &mut self,
+        obj_text: &str,
+        scale: f32,
+        offset_x: f32,
+        offset_y: f32,
+        offset_z: f32,
+        material_type: u32,
+        r: f32,
+        g: f32,
+        b: f32,
+    ) -> Result<u32, RaytracerError> {
+        if !(scale.is_finite() && scale > 0.0) {
+            return Err(RaytracerError::invalid_argument("scale", "scale must be positive"));
+        }
+        let offset = Vec3::new(offset_x, offset_y, offset_z);
+        if !offset.is_finite() {
+            return Err(RaytracerError::invalid_argument("offset", "offset must be finite"));
+        }
+        let material_type = MaterialType::from_u32(material_type).unwrap_or_default();
+        let material = Material::new(material_type, Vec3::new(r, g, b), 0.1, 1.5).clamped();
+
+        let mesh = Mesh::from_blender_obj(obj_text, material, "obj".to_string())?;
+        let count = mesh.triangles.len() as u32;
+        self.add_imported_mesh(mesh, scale, offset);
+        self.warn_dropped_triangles();
+        Ok(count)
+    }
+
+    /// Appends the faces of a PLY file (ascii or binary) to the scene as
+    /// triangles, placed as by `load_obj`, and returns how many were
+    /// created. Polygons are split into triangles. Without vertex colors
+    /// every face gets the material given here; with them each face's
+    /// albedo is the average of its vertices' colors. Elements and
+    /// properties other than vertex positions, colors and face indices are
+    /// skipped. A malformed header fails naming the offending line, and a
+    /// file without faces (a point cloud) fails too, adding nothing.
+    ///
+    /// `material_type` is as for `load_obj`.
+    #[allow(clippy::too_many_arguments)]
+    #[wasm_bindgen]
+    pub fn load_ply(
+        &mut self,
+        data: &[u8],
+        scale: f32,
+        offset_x: f32,
+        offset_y: f32,
+        offset_z: f32,
+        material_type: u32,
+        r: f32,
+        g: f32,
+        b: f32,
+    ) -> Result<u32, RaytracerError> {
+        if !(scale.is_finite() && scale > 0.0) {
+            return Err(RaytracerError::invalid_argument("scale", "scale must be positive"));
+        }
+        let offset = Vec3::new(offset_x, offset_y, offset_z);
+        if !offset.is_finite() {
+            return Err(RaytracerError::invalid_argument("offset", "offset must be finite"));
+        }
+        let material_type = MaterialType::from_u32(material_type).unwrap_or_default();
+        let material = Material::new(material_type, Vec3::new(r, g, b), 0.1, 1.5).clamped();
+
+        let mesh = Mesh::from_ply(data, material, "ply".to_string())?;
+        let count = mesh.triangles.len() as u32;
+        self.add_imported_mesh(mesh, scale, offset);
+        self.warn_dropped_triangles();
+        Ok(count)
+    }
+
+    /// Replaces the scene with one read from a Blender JSON export, converted
+    /// with the import axes (`set_import_axes("z", "y", 1.0)` for Blender's).
+    /// Objects are `{name, type, location, rotation_euler, scale}`. Meshes
+    /// add a `mesh_type` of `sphere`, `cube`, `cylinder` or `plane`, mapped
+    /// onto the matching primitive, and a `material` of `base_color`,
+    /// `metallic`, `roughness`, `transmission` and `ior`. Lights add
+    /// `light_type`, `color` and `energy`. Unsupported objects are skipped
+    /// with a console warning. The module docs of `blender` describe the
+    /// mapping, and `fixtures/blender` holds samples.
+    #[wasm_bindgen]
+    pub fn load_blender_json(&mut self, json_data: &str) -> Result<(), RaytracerError> {
+        let mut scene = Scene::from_blender_json(json_data)?;
+        self.import_axes.scene(&mut scene);
+        self.replace_scene(scene);
+        Ok(())
+    }
+
+    /// Replaces the scene with a glTF 2.0 file: the `.gltf` text, with `bin`
+    /// holding the `.bin` file its first buffer names (empty when buffers
+    /// are embedded). Mesh primitives become triangles placed by their node
+    /// transforms, Lambertian or metal by each material's base color and
+    /// metallic factors. `KHR_lights_punctual` point lights become lights,
+    /// their intensity taken as is. Skins, animations, textures, cameras and
+    /// other parts the renderer can't show are skipped with a warning rather
+    /// than failing the load. The import axes don't apply: glTF already uses
+    /// this renderer's.
+    ///
+    /// Returns `{triangles, lights, warnings}`; the warnings are also logged
+    /// to the console.
+    #[wasm_bindgen]
+    pub fn load_gltf(&mut self, json: &str, bin: &[u8]) -> Result<JsValue, RaytracerError> {
+        let (scene, warnings) = Scene::from_gltf(json, bin)?;
+        self.replace_with_import(scene, warnings)
+    }
+
+    /// Replaces the scene with a binary glTF (`.glb`) file, as `load_gltf`.
+    #[wasm_bindgen]
+    pub fn load_glb(&mut self, data: &[u8]) -> Result<JsValue, RaytracerError> {
+        let (scene, warnings) = Scene::from_glb(data)?;
+        self.replace_with_import(scene, warnings)
+    }
+
+    /// Sets the scene's units per meter, e.g. 1000 for a model in
+    /// millimeters. Camera speeds, clip planes and ray offsets are defined in
+    /// meters and follow it; the value is saved with the scene. Loading a
+    /// scene or a file with a unit hint replaces it.
+    #[wasm_bindgen]
+    pub fn set_world_scale(&mut self, world_scale: f32) -> Result<(), RaytracerError> {
+        if !(world_scale.is_finite() && world_scale > 0.0) {
+            return Err(RaytracerError::invalid_argument(
+                "world_scale",
+                format!("must be a positive number, got {}", world_scale),
+            ));
+        }
+        self.scene.world_scale = world_scale;
+        self.scene.touch_settings();
+        self.apply_world_scale();
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn get_world_scale(&self) -> f32 {
+        self.scene.world_scale
+    }
+
+    /// Sets the axis convention of files imported from now on (OBJ meshes
+    /// and Blender JSON). `up` is the source axis that points up and
+    /// `forward` the one pointing away from the viewer (this renderer's -Z),
+    /// each `x`, `y` or `z` with an optional sign; `scale` converts source
+    /// units to world units. Blender data is `("z", "y", 1.0)`. Sources are
+    /// taken as right-handed unless `left_handed` is set, in which case
+    /// triangle winding is reversed so faces keep pointing the same way.
+    /// The default, `("y", "-z", 1.0)`, leaves data unchanged.
+    #[wasm_bindgen]
+    pub fn set_import_axes(
+        &mut self,
+        up: &str,
+        forward: &str,
+        scale: f32,
+        left_handed: Option<bool>,
+    ) -> Result<(), RaytracerError> {
+        self.import_axes = ImportAxes::new(up, forward, scale, left_handed.unwrap_or(false))?;
+        Ok(())
+    }
+
+    /// Adds one Lambertian sphere per CSV row of `x,y,z[,radius][,r,g,b]` and
+    /// returns how many were created.
+    ///
+    /// Malformed rows are skipped and reported on the console with their line
+    /// numbers; the import only fails if no row could be read at all.
+    #[wasm_bindgen]
+    pub fn load_spheres_csv(
+        &mut self,
+        csv: &str,
+        default_radius: f32,
+    ) -> Result<u32, RaytracerError> {
+        let (spheres, warnings) = csv::parse_spheres_csv(csv, default_radius);
+        if spheres.is_empty() && !warnings.is_empty() {
+            return Err(RaytracerError::scene_parse("", format!(
+                "No spheres could be read from the CSV:\n{}",
+                warnings.join("\n")
+            )));
+        }
+        for warning in &warnings {
+            console::warn_1(&format!("CSV import skipped {}", warning).into());
+        }
+
+        let count = spheres.len() as u32;
+        for sphere in spheres {
+            self.scene.add_sphere(sphere);
+        }
+
+        let max_spheres = self.object_capacity(ObjectType::Sphere);
+        if self.scene.spheres.len() > max_spheres {
+            console::warn_1(
+                &format!(
+                    "Scene has {} spheres but only the first {} are rendered",
+                    self.scene.spheres.len(),
+                    max_spheres
+                )
+                .into(),
+            );
+        }
+
+        Ok(count)
+    }
+
+    /// Adds an `nx` x `ny` grid of spheres centered on the origin and resting
+    /// on the ground plane, with a material parameter ramped across it:
+    /// 0 = metal roughness, 1 = dielectric IOR, 2 = Lambertian hue. Returns the
+    /// number of spheres created; fails without adding anything if the grid
+    /// would not fit in the sphere limit.
+    #[wasm_bindgen]
+    pub fn generate_sphere_grid(
+        &mut self,
+        nx: u32,
+        ny: u32,
+        spacing: f32,
+        radius: f32,
+        mode: u32,
+    ) -> Result<u32, RaytracerError> {
+        let ramp = GridRamp::from_u32(mode)
+            .ok_or_else(|| {
+                RaytracerError::invalid_argument("mode", format!("unknown grid mode {}", mode))
+            })?;
+        let requested = generate::grid_count(&[nx, ny])?;
+        let max_spheres = self.object_capacity(ObjectType::Sphere);
+        let available = max_spheres.saturating_sub(self.scene.spheres.len());
+        if requested > available {
+            return Err(RaytracerError::invalid_argument("grid", format!(
+                "a {}x{} grid needs {} spheres but only {} of {} are free",
+                nx, ny, requested, available, max_spheres
+            )));
+        }
+
+        let spheres = generate::sphere_grid(nx, ny, spacing, radius, self.ground_level(), ramp)?;
+        for sphere in spheres {
+            self.scene.add_sphere(sphere);
+        }
+
+        Ok(requested as u32)
+    }
+
+    /// Adds an `nx` x `ny` x `nz` block of spheres `spacing` apart, centered
+    /// on the origin in X and Z with its bottom layer on the ground plane
+    /// (`ny` counts the layers). `material_type` (0 = Lambertian, 1 = metal,
+    /// 2 = dielectric, 3 = emissive) gives them all one grey material; leave
+    /// it out for materials from a small palette. Only as many spheres as the
+    /// sphere limit leaves room for are added, with a console warning if
+    /// that's fewer than asked for; returns how many were. Fails if a
+    /// dimension is zero, the radius isn't positive or the spacing isn't
+    /// finite.
+    #[wasm_bindgen]
+    pub fn add_sphere_grid(
+        &mut self,
+        nx: u32,
+        ny: u32,
+        nz: u32,
+        spacing: f32,
+        radius: f32,
+        material_type: Option<u32>,
+    ) -> Result<u32, RaytracerError> {
+        let requested = generate::grid_count(&[nx, ny, nz])?;
+        let count = self.free_sphere_slots(requested);
+        let material = material_type.map(fixed_sphere_material);
+        let spheres = generate::sphere_block(
+            [nx, ny, nz],
+            spacing,
+            radius,
+            self.ground_level(),
+            material,
+            count,
+        )?;
+        for sphere in spheres {
+            self.scene.add_sphere(sphere);
+        }
+
+        Ok(count as u32)
+    }
+
+    /// Adds `count` spheres scattered at random between the given bounds,
+    /// with radii between `radius_min` and `radius_max`; they may overlap.
+    /// The same `seed` always gives the same spheres. Materials are picked
+    /// from a small palette unless `material_type` (as for
+    /// `add_sphere_grid`) fixes one. Only as many spheres as the sphere limit
+    /// leaves room for are added, with a console warning if that's fewer
+    /// than asked for; returns how many were.
+    #[wasm_bindgen]
+    pub fn add_sphere_field(
+        &mut self,
+        count: u32,
+        min_x: f32,
+        max_x: f32,
+        min_y: f32,
+        max_y: f32,
+        min_z: f32,
+        max_z: f32,
+        radius_min: f32,
+        radius_max: f32,
+        seed: u32,
+        material_type: Option<u32>,
+    ) -> Result<u32, RaytracerError> {
+        let (min, max) = (Vec3::new(min_x, min_y, min_z), Vec3::new(max_x, max_y, max_z));
+        let bounds = [min.x, min.y, min.z, max.x, max.y, max.z];
+        if bounds.iter().any(|v| !v.is_finite()) || min.x > max.x || min.y > max.y || min.z > max.z
+        {
+            return Err(RaytracerError::invalid_argument(
+                "bounds",
+                "each min must be finite and at most its max",
+            ));
+        }
+        if !(radius_min > 0.0 && radius_min <= radius_max && radius_max.is_finite()) {
+            return Err(RaytracerError::invalid_argument(
+                "radius",
+                "radius_min must be positive and at most radius_max",
+            ));
+        }
+
+        let count = self.free_sphere_slots(count as usize);
+        let material = material_type.map(fixed_sphere_material);
+        let spheres =
+            generate::sphere_field(count, min, max, radius_min, radius_max, material, seed);
+        for sphere in spheres {
+            self.scene.add_sphere(sphere);
+        }
+
+        Ok(count as u32)
+    }
+
+    /// Fills space with copies of one sphere, repeated every `cell_*` units
+    /// (0 on an axis keeps a single layer) and traced analytically in the
+    /// shader. `extent` limits the lattice to that many cells either side of
+    /// the origin; leave it out for an endless field. Replaces any existing
+    /// grid.
+    #[wasm_bindgen]
+    pub fn set_instanced_grid(
+        &mut self,
+        cell_x: f32,
+        cell_y: f32,
+        cell_z: f32,
+        radius: f32,
+        r: f32,
+        g: f32,
+        b: f32,
+        material_type: u32,
+        roughness: f32,
+        ior: f32,
+        extent: Option<u32>,
+    ) {
+        let material_type = MaterialType::from_u32(material_type).unwrap_or_default();
+
+        self.scene.instanced_grid = Some(InstancedGrid {
+            cell_size: Vec3::new(cell_x, cell_y, cell_z),
+            sphere_radius: radius,
+            material: Material::new(material_type, Vec3::new(r, g, b), roughness, ior),
+            extent,
+        });
+        self.scene.touch_settings();
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_instanced_grid(&mut self) {
+        self.scene.instanced_grid = None;
+        self.scene.touch_settings();
+    }
+
+    /// Replaces the sky gradient with stars over the background color, for
+    /// reflections as well as the backdrop. `density` is the fraction of sky
+    /// cells with a star (0 to 1); `twinkle` (0 to 1, default 0) makes stars
+    /// flicker over time. A given `seed` always gives the same sky.
+    #[wasm_bindgen]
+    pub fn set_background_starfield(
+        &mut self,
+        density: f32,
+        brightness: f32,
+        seed: u32,
+        twinkle: Option<f32>,
+    ) {
+        self.scene.starfield = Some(Starfield {
+            density: density.clamp(0.0, 1.0),
+            brightness: brightness.max(0.0),
+            seed,
+            twinkle: twinkle.unwrap_or(0.0).clamp(0.0, 1.0),
+        });
+        self.scene.touch_settings();
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_background_starfield(&mut self) {
+        self.scene.starfield = None;
+        self.scene.touch_settings();
+    }
+
+    /// Removes every object and light, leaving only the ground plane. The
+    /// background color is kept; loading a scene replaces it.
+    #[wasm_bindgen]
+    pub fn clear_scene(&mut self) {
+        let mut scene = Scene::new();
+        scene.background_color = self.scene.background_color;
+        self.replace_scene(scene);
+        // Re-add ground plane
+        self.scene.add_plane(Plane::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Material::new(MaterialType::Lambertian, Vec3::new(0.5, 0.5, 0.5), 0.0, 0.0),
+        ));
+    }
+
+    /// Replaces the scene with the one in `json_data`. A file that parses but
+    /// holds values the renderer can't draw, such as a negative radius or a
+    /// dielectric IOR below 1, is rejected with an `invalid_scene` error whose
+    /// `details.problems` lists each one as `{kind, index, field, path, value,
+    /// message}`. `load_scene_json_lenient` loads such files without the
+    /// offending objects.
+    #[wasm_bindgen]
+    pub fn load_scene_json(&mut self, json_data: &str) -> Result<(), RaytracerError> {
+        self.replace_scene(validated(Scene::from_json(json_data)?)?);
+        Ok(())
+    }
+
+    /// Adds the objects, lights and volumes of a scene file to the current
+    /// scene instead of replacing it, e.g. to bring in props from a library
+    /// file. Everything imported is converted with the import axes, as by
+    /// `load_blender_json`, then moved by `(dx, dy, dz)` (0 where left out)
+    /// so it doesn't land on existing geometry, and gets fresh ids. The
+    /// file's background, sky, camera path and presets, materials and world
+    /// scale are ignored. A file `load_scene_json` would reject is rejected
+    /// the same way, leaving the scene untouched.
+    ///
+    /// Returns `{added, over_limit}`, each counting per kind of object as in
+    /// `get_gpu_budget`: how many were added, and by how many of them the
+    /// scene now exceeds what the shader holds.
+    #[wasm_bindgen]
+    pub fn merge_scene_json(
+        &mut self,
+        json_data: &str,
+        dx: Option<f32>,
+        dy: Option<f32>,
+        dz: Option<f32>,
+    ) -> Result<JsValue, RaytracerError> {
+        let offset = Vec3::new(dx.unwrap_or(0.0), dy.unwrap_or(0.0), dz.unwrap_or(0.0));
+        if !offset.is_finite() {
+            return Err(RaytracerError::invalid_argument("offset", "offset must be finite"));
+        }
+        let mut imported = validated(Scene::from_json(json_data)?)?;
+        self.import_axes.scene(&mut imported);
+
+        let before = self.object_counts();
+        self.scene.merge(imported, offset);
+        let after = self.object_counts();
+        let mut added = BTreeMap::new();
+        let mut over_limit = BTreeMap::new();
+        for ((name, limit, count), (_, _, previous)) in after.into_iter().zip(before) {
+            added.insert(name, count - previous);
+            over_limit.insert(name, (count - previous).min(count.saturating_sub(limit)));
+        }
+        to_js(&serde_json::json!({ "added": added, "over_limit": over_limit }))
+    }
+
+    /// The scene in a compact binary form (a `Uint8Array`) for
+    /// `load_scene_binary`. Triangles are stored as raw numbers with their
+    /// materials shared, so triangle-heavy scenes come out far smaller and
+    /// faster to read than `export_scene_json`.
+    #[wasm_bindgen]
+    pub fn export_scene_binary(&self) -> Vec<u8> {
+        self.scene.to_binary()
+    }
+
+    /// Replaces the scene with one from `export_scene_binary`. Data that
+    /// isn't such a scene, is cut short or comes from a newer raytracer fails
+    /// with a `scene_parse` error saying which; invalid values are rejected
+    /// as by `load_scene_json`.
+    #[wasm_bindgen]
+    pub fn load_scene_binary(&mut self, data: &[u8]) -> Result<(), RaytracerError> {
+        self.replace_scene(validated(Scene::from_binary(data)?)?);
+        Ok(())
+    }
+
+    /// Replaces the scene with one of the built-in presets ("triangle_seam").
+    #[wasm_bindgen]
+    pub fn load_preset(&mut self, name: &str) -> Result<(), RaytracerError> {
+        let scene = presets::scene_by_name(name).ok_or_else(|| {
+            RaytracerError::invalid_argument("name", format!(
+                "unknown preset '{}', expected one of: {}",
+                name,
+                presets::PRESET_NAMES.join(", ")
+            ))
+        })?;
+        self.replace_scene(scene);
+        Ok(())
+    }
+
+    /// Loads whatever parts of `json_data` are valid, skipping malformed
+    /// objects and ones `load_scene_json` would reject instead of rejecting
+    /// the file. Returns `{loaded: {spheres, ...},
+    /// warnings: [{path, message}]}`; fails only if the text isn't JSON.
+    #[wasm_bindgen]
+    pub fn load_scene_json_lenient(&mut self, json_data: &str) -> Result<JsValue, RaytracerError> {
+        let (scene, report) = loader::load_lenient(json_data)?;
+        self.replace_scene(scene);
+        to_js(&report)
+    }
+
+    /// Loads a scene over several frames instead of all at once.
+    ///
+    /// The current scene is replaced immediately by an empty one, and each
+    /// `render()` then moves the next batch of objects into it, calling
+    /// `on_progress(loaded, total)` after every batch.
+    #[wasm_bindgen]
+    pub fn load_scene_json_chunked(
+        &mut self,
+        json_data: &str,
+        on_progress: js_sys::Function,
+    ) -> Result<(), RaytracerError> {
+        let (scene, load) = ChunkedSceneLoad::start(json_data, on_progress)?;
+        self.replace_scene(scene);
+        self.scene_load = Some(load);
+        Ok(())
+    }
+
+    /// Stops a pending chunked load, keeping the objects loaded so far.
+    #[wasm_bindgen]
+    pub fn cancel_scene_load(&mut self) {
+        self.scene_load = None;
+    }
+
+    /// Animates sky, sun light and ambient level through a full day every
+    /// `duration_s` seconds of scene time. The first light acts as the sun
+    /// (one is added if the scene has none).
+    #[wasm_bindgen]
+    pub fn enable_day_night_cycle(&mut self, duration_s: f64) {
+        let now = self.clock.now();
+        match self.day_night.as_mut() {
+            Some(cycle) => cycle.set_duration(duration_s, now),
+            None => {
+                self.day_night = Some(DayNightCycle::start(
+                    &mut self.scene,
+                    self.ambient,
+                    duration_s,
+                    now,
+                ));
+            }
+        }
+    }
+
+    /// Stops the cycle and restores the background, sun light and ambient
+    /// level from before it was enabled.
+    #[wasm_bindgen]
+    pub fn disable_day_night_cycle(&mut self) {
+        if let Some(cycle) = self.day_night.take() {
+            cycle.restore(&mut self.scene, &mut self.ambient);
+        }
+    }
+
+    /// Freezes (or resumes) scene time, pausing animations such as the day/night cycle.
+    #[wasm_bindgen]
+    pub fn set_time_paused(&mut self, paused: bool) {
+        self.clock.set_paused(paused);
+    }
+
+    #[wasm_bindgen]
+    pub fn is_time_paused(&self) -> bool {
+        self.clock.is_paused()
+    }
+
+    /// Pauses scene time at `time_ms`, so animated scenes render the same
+    /// frame every time. `set_time_paused(false)` resumes from there.
+    #[wasm_bindgen]
+    pub fn set_scene_time(&mut self, time_ms: f64) {
+        self.clock.pause_at(time_ms);
+    }
+
+    #[wasm_bindgen]
+    pub fn export_scene_json(&self) -> String {
+        self.scene.to_json()
+    }
+
+    /// The scene as a Wavefront OBJ file, for modeling tools. Spheres and
+    /// cylinders are tessellated with `segments` divisions around (32 if
+    /// left out, 3 to 1024); boxes become 12 triangles, planes large quads,
+    /// and triangles are kept. Objects are grouped under their names (or
+    /// kind and id) with a `usemtl` each, and the file loads its materials
+    /// from `mtl_file` ("scene.mtl" if left out), which `export_mtl` writes.
+    /// Lights, volumes and degenerate primitives such as zero-radius
+    /// spheres are left out.
+    #[wasm_bindgen]
+    pub fn export_obj(
+        &self,
+        segments: Option<u32>,
+        mtl_file: Option<String>,
+    ) -> Result<String, RaytracerError> {
+        let segments = segments.unwrap_or(32);
+        if !(3..=1024).contains(&segments) {
+            return Err(RaytracerError::invalid_argument(
+                "segments",
+                format!("must be from 3 to 1024, got {}", segments),
+            ));
+        }
+        Ok(self.scene.to_obj(segments, mtl_file.as_deref().unwrap_or("scene.mtl")))
+    }
+
+    /// The materials of `export_obj` as a Wavefront MTL file: diffuse
+    /// color, specular color and exponent, IOR, opacity and emission.
+    #[wasm_bindgen]
+    pub fn export_mtl(&self) -> String {
+        self.scene.to_mtl()
+    }
+
+    /// Token for the scene's current state, to pass to
+    /// `export_changes_since` later. Every edit, add or removal advances it.
+    #[wasm_bindgen]
+    pub fn get_revision(&self) -> String {
+        self.scene.revision().to_string()
+    }
+
+    /// A JSON patch with only what changed after the revision `revision`
+    /// (from `get_revision`, or a previous patch's `revision`): changed
+    /// objects by index, per-kind counts, removed ids and the scene-wide
+    /// settings. `apply_patch_json` reads it back.
+    #[wasm_bindgen]
+    pub fn export_changes_since(&self, revision: &str) -> Result<String, RaytracerError> {
+        let baseline = revision
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|&baseline| baseline <= self.scene.revision())
+            .ok_or_else(|| {
+                RaytracerError::invalid_argument("revision", format!(
+                    "'{}' is not a revision of this scene",
+                    revision
+                ))
+            })?;
+        Ok(serde_json::to_string(&self.scene.changes_since(baseline))
+            .unwrap_or_else(|_| "{}".to_string()))
+    }
+
+    /// Applies a patch written by `export_changes_since`, typically by another
+    /// instance that started from the same scene. Nothing changes if the
+    /// patch doesn't fit the current scene.
+    #[wasm_bindgen]
+    pub fn apply_patch_json(&mut self, json_data: &str) -> Result<(), RaytracerError> {
+        let patch: ScenePatch = serde_json::from_str(json_data)
+            .map_err(|e| RaytracerError::scene_parse("", format!("Failed to parse patch: {}", e)))?;
+        self.scene.apply_patch(patch)?;
+        // Indices may have moved under the preview
+        self.material_preview = None;
+        self.apply_world_scale();
+        Ok(())
+    }
+
+    /// Adds (or replaces) a named material in the scene's library.
+    #[wasm_bindgen]
+    pub fn define_material(
+        &mut self,
+        name: &str,
+        r: f32,
+        g: f32,
+        b: f32,
+        material_type: u32,
+        roughness: f32,
+        ior: f32,
+    ) {
+        let material_type = MaterialType::from_u32(material_type).unwrap_or_default();
+        let material = Material::new(material_type, Vec3::new(r, g, b), roughness, ior);
+        self.scene.materials.insert(name.to_string(), material.clamped());
+        self.scene.touch_settings();
+    }
+
+    /// Copies a library material onto an object.
+    ///
+    /// `object_type` is 0 = sphere, 1 = plane, 2 = box, 3 = cylinder, 4 = triangle,
+    /// 6 = cone, 7 = disk.
+    #[wasm_bindgen]
+    pub fn apply_material(
+        &mut self,
+        object_type: u32,
+        index: usize,
+        name: &str,
+    ) -> Result<(), RaytracerError> {
+        let material = *self
+            .scene
+            .materials
+            .get(name)
+            .ok_or_else(|| {
+                RaytracerError::invalid_argument("name", format!("no material named '{}'", name))
+            })?;
+        let object_type = object_type_from_js(object_type)?;
+        *self.editable_material(object_type, index)? = material;
+        self.scene.touch(object_type, index);
+        Ok(())
+    }
+
+    /// The scene's material library on its own, as JSON keyed by name.
+    #[wasm_bindgen]
+    pub fn export_materials_json(&self) -> String {
+        self.scene.export_materials_json()
+    }
+
+    /// Merges a library from `export_materials_json` into the scene's and
+    /// returns how many materials were imported. Geometry is not touched.
+    /// Names that already exist are skipped unless `overwrite` is set;
+    /// skipped and invalid entries are listed by
+    /// `get_material_import_warnings`.
+    #[wasm_bindgen]
+    pub fn import_materials_json(
+        &mut self,
+        json: &str,
+        overwrite: bool,
+    ) -> Result<u32, RaytracerError> {
+        let result = self.scene.import_materials(json, overwrite)?;
+        for warning in &result.warnings {
+            console::warn_1(&format!("Material import skipped {}", warning).into());
+        }
+        self.material_import_warnings = result.warnings;
+        Ok(result.imported)
+    }
+
+    /// Warnings from the last `import_materials_json` call.
+    #[wasm_bindgen]
+    pub fn get_material_import_warnings(&self) -> Vec<String> {
+        self.material_import_warnings.clone()
+    }
+
+    #[wasm_bindgen]
+    pub fn get_sphere_count(&self) -> usize {
+        self.scene.spheres.len()
+    }
+
+    #[wasm_bindgen]
+    pub fn get_sphere_position(&self, index: usize) -> Vec<f32> {
+        if index < self.scene.spheres.len() {
+            let pos = &self.scene.spheres[index].center;
+            vec![pos.x, pos.y, pos.z]
+        } else {
+            vec![0.0, 0.0, 0.0]
+        }
+    }
+
+    /// Returns false if there is no such sphere or it is locked.
+    #[wasm_bindgen]
+    pub fn set_sphere_position(&mut self, index: usize, x: f32, y: f32, z: f32) -> bool {
+        self.with_unlocked_object(index, |sphere: &mut Sphere| sphere.center = Vec3::new(x, y, z))
+    }
+
+    /// Returns false if there is no such sphere or it is locked.
+    #[wasm_bindgen]
+    pub fn set_sphere_radius(&mut self, index: usize, radius: f32) -> bool {
+        self.with_unlocked_object(index, |sphere: &mut Sphere| sphere.radius = radius)
+    }
+
+    /// Stretches a sphere by `(sx, sy, sz)` along the axes into an
+    /// ellipsoid; (1, 1, 1) makes it a sphere again. Returns false if a
+    /// factor isn't positive, or there is no such sphere or it is locked.
+    #[wasm_bindgen]
+    pub fn set_sphere_scale(&mut self, index: usize, sx: f32, sy: f32, sz: f32) -> bool {
+        if !(sx > 0.0 && sy > 0.0 && sz > 0.0 && Vec3::new(sx, sy, sz).is_finite()) {
+            return false;
+        }
+        self.with_unlocked_object(index, |sphere: &mut Sphere| sphere.scale = Vec3::new(sx, sy, sz))
+    }
+
+    #[wasm_bindgen]
+    pub fn get_sphere_radius(&self, index: usize) -> f32 {
+        if index < self.scene.spheres.len() {
+            self.scene.spheres[index].radius
+        } else {
+            1.0
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn get_plane_count(&self) -> usize {
+        self.scene.planes.len()
+    }
+
+    /// Moves a plane to pass through `(x, y, z)`, keeping its normal.
+    /// Returns false if there is no such plane or it is locked.
+    #[wasm_bindgen]
+    pub fn set_plane_point(&mut self, index: usize, x: f32, y: f32, z: f32) -> bool {
+        self.with_unlocked_object(index, |plane: &mut Plane| plane.point = Vec3::new(x, y, z))
+    }
+
+    #[wasm_bindgen]
+    pub fn get_box_count(&self) -> usize {
+        self.scene.boxes.len()
+    }
+
+    #[wasm_bindgen]
+    pub fn get_box_center(&self, index: usize) -> Vec<f32> {
+        match self.scene.boxes.get(index) {
+            Some(box_obj) => vec![box_obj.center.x, box_obj.center.y, box_obj.center.z],
+            None => vec![0.0, 0.0, 0.0],
+        }
+    }
+
+    /// The edge lengths of a box, before its scale.
+    #[wasm_bindgen]
+    pub fn get_box_size(&self, index: usize) -> Vec<f32> {
+        match self.scene.boxes.get(index) {
+            Some(box_obj) => vec![box_obj.size.x, box_obj.size.y, box_obj.size.z],
+            None => vec![0.0, 0.0, 0.0],
+        }
+    }
+
+    /// Moves the center of a box. Returns false if there is no such box or
+    /// it is locked.
+    #[wasm_bindgen]
+    pub fn set_box_center(&mut self, index: usize, x: f32, y: f32, z: f32) -> bool {
+        self.with_unlocked_object(index, |box_obj: &mut Box| box_obj.center = Vec3::new(x, y, z))
+    }
+
+    /// Same as `set_box_center`.
+    #[wasm_bindgen]
+    pub fn set_box_position(&mut self, index: usize, x: f32, y: f32, z: f32) -> bool {
+        self.set_box_center(index, x, y, z)
+    }
+
+    /// Sets the edge lengths of a box. Returns false if a length isn't
+    /// positive, or there is no such box or it is locked.
+    #[wasm_bindgen]
+    pub fn set_box_size(&mut self, index: usize, sx: f32, sy: f32, sz: f32) -> bool {
+        if !(sx > 0.0 && sy > 0.0 && sz > 0.0 && Vec3::new(sx, sy, sz).is_finite()) {
+            return false;
+        }
+        self.with_unlocked_object(index, |box_obj: &mut Box| box_obj.size = Vec3::new(sx, sy, sz))
+    }
+
+    /// Turns a box about its center by `rx`, `ry` and `rz` degrees about X,
+    /// then Y, then Z; all zero makes it axis-aligned again. Returns false
+    /// if an angle isn't finite, or there is no such box or it is locked.
+    #[wasm_bindgen]
+    pub fn set_box_rotation(&mut self, index: usize, rx: f32, ry: f32, rz: f32) -> bool {
+        let Some(rotation) = rotation_from_js(rx, ry, rz) else {
+            return false;
+        };
+        self.with_unlocked_object(index, |box_obj: &mut Box| box_obj.rotation = rotation)
+    }
+
+    /// Stretches a box by `(sx, sy, sz)` along its own axes, on top of its
+    /// size. Returns false if a factor isn't positive, or there is no such
+    /// box or it is locked.
+    #[wasm_bindgen]
+    pub fn set_box_scale(&mut self, index: usize, sx: f32, sy: f32, sz: f32) -> bool {
+        if !(sx > 0.0 && sy > 0.0 && sz > 0.0 && Vec3::new(sx, sy, sz).is_finite()) {
+            return false;
+        }
+        self.with_unlocked_object(index, |box_obj: &mut Box| box_obj.scale = Vec3::new(sx, sy, sz))
+    }
+
+    #[wasm_bindgen]
+    pub fn get_triangle_count(&self) -> usize {
+        self.scene.triangles.len()
+    }
+
+    #[wasm_bindgen]
+    pub fn get_cylinder_count(&self) -> usize {
+        self.scene.cylinders.len()
+    }
+
+    #[wasm_bindgen]
+    pub fn get_cylinder_base(&self, index: usize) -> Vec<f32> {
+        match self.scene.cylinders.get(index) {
+            Some(cylinder) => vec![cylinder.base.x, cylinder.base.y, cylinder.base.z],
+            None => vec![0.0, 0.0, 0.0],
+        }
+    }
+
+    /// The cylinder's axis from base to top; its length is the height.
+    #[wasm_bindgen]
+    pub fn get_cylinder_axis(&self, index: usize) -> Vec<f32> {
+        match self.scene.cylinders.get(index) {
+            Some(cylinder) => vec![cylinder.axis.x, cylinder.axis.y, cylinder.axis.z],
+            None => vec![0.0, 1.0, 0.0],
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn get_cylinder_radius(&self, index: usize) -> f32 {
+        match self.scene.cylinders.get(index) {
+            Some(cylinder) => cylinder.radius,
+            None => 1.0,
+        }
+    }
+
+    /// Returns false if there is no such cylinder or it is locked.
+    #[wasm_bindgen]
+    pub fn set_cylinder_radius(&mut self, index: usize, radius: f32) -> bool {
+        self.with_unlocked_object(index, |cylinder: &mut Cylinder| {
+            cylinder.radius = radius.max(0.0);
+        })
+    }
+
+    /// Moves a cylinder so it starts at `(x, y, z)`, keeping its axis.
+    /// Returns false if there is no such cylinder or it is locked.
+    #[wasm_bindgen]
+    pub fn set_cylinder_base(&mut self, index: usize, x: f32, y: f32, z: f32) -> bool {
+        self.with_unlocked_object(index, |cylinder: &mut Cylinder| {
+            cylinder.base = Vec3::new(x, y, z);
+        })
+    }
+
+    /// Points a cylinder along `(x, y, z)` from its base; the vector's length
+    /// is the height. Returns false if the axis is zero or not finite, or
+    /// there is no such cylinder or it is locked.
+    #[wasm_bindgen]
+    pub fn set_cylinder_axis(&mut self, index: usize, x: f32, y: f32, z: f32) -> bool {
+        let axis = Vec3::new(x, y, z);
+        if !axis.is_finite() || axis.length_squared() == 0.0 {
+            return false;
+        }
+        self.with_unlocked_object(index, |cylinder: &mut Cylinder| cylinder.axis = axis)
+    }
+
+    /// Turns a cylinder about the middle of its axis by `rx`, `ry` and `rz`
+    /// degrees about X, then Y, then Z. Returns false if an angle isn't
+    /// finite, or there is no such cylinder or it is locked.
+    #[wasm_bindgen]
+    pub fn set_cylinder_rotation(&mut self, index: usize, rx: f32, ry: f32, rz: f32) -> bool {
+        let Some(rotation) = rotation_from_js(rx, ry, rz) else {
+            return false;
+        };
+        self.with_unlocked_object(index, |cylinder: &mut Cylinder| cylinder.rotation = rotation)
+    }
+
+    /// Stretches a cylinder by `(sx, sy, sz)` along the world axes about
+    /// the middle of its axis, before its rotation; unequal factors across
+    /// the axis make it elliptic. Returns false if a factor isn't positive,
+    /// or there is no such cylinder or it is locked.
+    #[wasm_bindgen]
+    pub fn set_cylinder_scale(&mut self, index: usize, sx: f32, sy: f32, sz: f32) -> bool {
+        if !(sx > 0.0 && sy > 0.0 && sz > 0.0 && Vec3::new(sx, sy, sz).is_finite()) {
+            return false;
+        }
+        self.with_unlocked_object(index, |cylinder: &mut Cylinder| {
+            cylinder.scale = Vec3::new(sx, sy, sz);
+        })
+    }
+
+    #[wasm_bindgen]
+    pub fn get_cone_count(&self) -> usize {
+        self.scene.cones.len()
+    }
+
+    /// Sets the radius of a cone's base. Returns false if there is no such
+    /// cone or it is locked.
+    #[wasm_bindgen]
+    pub fn set_cone_radius(&mut self, index: usize, radius: f32) -> bool {
+        self.with_unlocked_object(index, |cone: &mut Cone| cone.radius = radius.max(0.0))
+    }
+
+    /// Moves a cone so its tip is at `(x, y, z)`, keeping its axis.
+    /// Returns false if there is no such cone or it is locked.
+    #[wasm_bindgen]
+    pub fn set_cone_apex(&mut self, index: usize, x: f32, y: f32, z: f32) -> bool {
+        self.with_unlocked_object(index, |cone: &mut Cone| cone.apex = Vec3::new(x, y, z))
+    }
+
+    #[wasm_bindgen]
+    pub fn get_disk_count(&self) -> usize {
+        self.scene.disks.len()
+    }
+
+    /// Sets a disk's outer and inner radius; an inner radius of 0 closes
+    /// the hole. Returns false if there is no such disk or it is locked.
+    #[wasm_bindgen]
+    pub fn set_disk_radii(&mut self, index: usize, outer_radius: f32, inner_radius: f32) -> bool {
+        self.with_unlocked_object(index, |disk: &mut Disk| {
+            disk.radius = outer_radius.max(0.0);
+            disk.inner_radius = inner_radius.clamp(0.0, disk.radius);
+        })
+    }
+
+    /// Like `set_sphere_material_ex` with roughness 0.1 and IOR 1.5.
+    /// Returns false if there is no such sphere or it is locked.
+    #[wasm_bindgen]
+    pub fn set_sphere_material(
+        &mut self,
+        index: usize,
+        r: f32,
+        g: f32,
+        b: f32,
+        material_type: u32,
+    ) -> bool {
+        self.set_sphere_material_ex(index, r, g, b, material_type, 0.1, 1.5)
+    }
+
+    /// Replaces a sphere's material. `roughness` is clamped to [0, 1] and
+    /// `ior` to at least 1. Returns false if there is no such sphere or it
+    /// is locked.
+    #[wasm_bindgen]
+    pub fn set_sphere_material_ex(
+        &mut self,
+        index: usize,
+        r: f32,
+        g: f32,
+        b: f32,
+        material_type: u32,
+        roughness: f32,
+        ior: f32,
+    ) -> bool {
+        let Some(material) = self.scene.unlocked_material_mut(ObjectType::Sphere, index) else {
+            return false;
+        };
+        let material_type = MaterialType::from_u32(material_type).unwrap_or_default();
+        *material = Material::new(material_type, Vec3::new(r, g, b), roughness, ior).clamped();
+        true
+    }
+
+    /// Splits light refracted by a dielectric sphere into its colors. `amount`
+    /// is how far the IOR spreads from red to blue as a fraction of `ior - 1`;
+    /// about 0.02 is real crown glass, 0.1 gives a vivid rainbow and 0 turns
+    /// it off. Other material types ignore it. Returns false if there is no
+    /// such sphere or it is locked.
+    #[wasm_bindgen]
+    pub fn set_sphere_dispersion(&mut self, index: usize, amount: f32) -> bool {
+        let Some(material) = self.scene.unlocked_material_mut(ObjectType::Sphere, index) else {
+            return false;
+        };
+        material.dispersion = if amount.is_finite() { amount.clamp(0.0, 1.0) } else { 0.0 };
+        true
+    }
+
+    /// Returns false if there is no such sphere or it is locked.
+    #[wasm_bindgen]
+    pub fn set_sphere_roughness(&mut self, index: usize, value: f32) -> bool {
+        let Some(material) = self.scene.unlocked_material_mut(ObjectType::Sphere, index) else {
+            return false;
+        };
+        material.roughness = value;
+        *material = material.clamped();
+        true
+    }
+
+    /// Returns false if there is no such sphere or it is locked.
+    #[wasm_bindgen]
+    pub fn set_sphere_ior(&mut self, index: usize, value: f32) -> bool {
+        let Some(material) = self.scene.unlocked_material_mut(ObjectType::Sphere, index) else {
+            return false;
+        };
+        material.ior = value;
+        *material = material.clamped();
+        true
+    }
+
+    /// Returns false if there is no such sphere or it is locked.
+    #[wasm_bindgen]
+    pub fn set_sphere_albedo(&mut self, index: usize, r: f32, g: f32, b: f32) -> bool {
+        let Some(material) = self.scene.unlocked_material_mut(ObjectType::Sphere, index) else {
+            return false;
+        };
+        material.albedo = Vec3::new(r, g, b);
+        *material = material.clamped();
+        true
+    }
+
+    /// The sphere's material as saved in scene JSON: `{material_type, albedo:
+    /// {x, y, z}, roughness, ior, emission, emission_strength, opacity, ...}`,
+    /// with `material_type` one of "Lambertian", "Metal" or "Dielectric".
+    #[wasm_bindgen]
+    pub fn get_sphere_material(&self, index: usize) -> Result<JsValue, RaytracerError> {
+        let sphere = self
+            .scene
+            .spheres
+            .get(index)
+            .ok_or(RaytracerError::IndexOutOfRange {
+                kind: "sphere",
+                index,
+                len: self.scene.spheres.len(),
+            })?;
+        to_js(&sphere.material)
+    }
+
+    /// Makes any object glow by adding `(r, g, b) * strength` to its shaded
+    /// color. Returns false if there is no such object or it is locked.
+    ///
+    /// `object_type` is 0 = sphere, 1 = plane, 2 = box, 3 = cylinder, 4 = triangle,
+    /// 6 = cone, 7 = disk.
+    #[wasm_bindgen]
+    pub fn set_object_emission(
+        &mut self,
+        object_type: u32,
+        index: usize,
+        r: f32,
+        g: f32,
+        b: f32,
+        strength: f32,
+    ) -> bool {
+        let material = ObjectType::from_u32(object_type)
+            .and_then(|object_type| self.scene.unlocked_material_mut(object_type, index));
+        let Some(material) = material else {
+            return false;
+        };
+        material.emission = Vec3::new(r, g, b);
+        material.emission_strength = strength.max(0.0);
+        true
+    }
+
+    /// Sets how opaque an object is, from 0 (invisible) to 1 (solid).
+    /// Dielectric objects ignore it. Returns false if there is no such
+    /// object or it is locked.
+    #[wasm_bindgen]
+    pub fn set_object_opacity(&mut self, object_type: u32, index: usize, value: f32) -> bool {
+        let material = ObjectType::from_u32(object_type)
+            .and_then(|object_type| self.scene.unlocked_material_mut(object_type, index));
+        let Some(material) = material else {
+            return false;
+        };
+        material.opacity = value.clamp(0.0, 1.0);
+        true
+    }
+
+    /// Replaces a plane's material, texture and shadow catcher included,
+    /// with roughness 0.1 and IOR 1.5. Returns false if there is no such
+    /// plane or it is locked.
+    #[wasm_bindgen]
+    pub fn set_plane_material(
+        &mut self,
+        index: usize,
+        r: f32,
+        g: f32,
+        b: f32,
+        material_type: u32,
+    ) -> bool {
+        let Some(material) = self.scene.unlocked_material_mut(ObjectType::Plane, index) else {
+            return false;
+        };
+        let material_type = MaterialType::from_u32(material_type).unwrap_or_default();
+        *material = Material::new(material_type, Vec3::new(r, g, b), 0.1, 1.5).clamped();
+        true
+    }
+
+    /// Replaces a box's material with roughness 0.1 and IOR 1.5. Returns
+    /// false if there is no such box or it is locked.
+    #[wasm_bindgen]
+    pub fn set_box_material(
+        &mut self,
+        index: usize,
+        r: f32,
+        g: f32,
+        b: f32,
+        material_type: u32,
+    ) -> bool {
+        let Some(material) = self.scene.unlocked_material_mut(ObjectType::Box, index) else {
+            return false;
+        };
+        let material_type = MaterialType::from_u32(material_type).unwrap_or_default();
+        *material = Material::new(material_type, Vec3::new(r, g, b), 0.1, 1.5).clamped();
+        true
+    }
+
+    /// Replaces a cylinder's material with roughness 0.1 and IOR 1.5.
+    /// Returns false if there is no such cylinder or it is locked.
+    #[wasm_bindgen]
+    pub fn set_cylinder_material(
+        &mut self,
+        index: usize,
+        r: f32,
+        g: f32,
+        b: f32,
+        material_type: u32,
+    ) -> bool {
+        let Some(material) = self.scene.unlocked_material_mut(ObjectType::Cylinder, index) else {
+            return false;
+        };
+        let material_type = MaterialType::from_u32(material_type).unwrap_or_default();
+        *material = Material::new(material_type, Vec3::new(r, g, b), 0.1, 1.5).clamped();
+        true
+    }
+
+    /// Covers a plane with a checker of `(r1, g1, b1)` and `(r2, g2, b2)`
+    /// squares `scale` units wide, replacing its albedo. The pattern is fixed
+    /// in world space. A scale of 0 or less removes it. Returns false if
+    /// there is no such plane or it is locked.
+    #[wasm_bindgen]
+    pub fn set_plane_checker(
+        &mut self,
+        index: usize,
+        r1: f32,
+        g1: f32,
+        b1: f32,
+        r2: f32,
+        g2: f32,
+        b2: f32,
+        scale: f32,
+    ) -> bool {
+        let Some(material) = self.scene.unlocked_material_mut(ObjectType::Plane, index) else {
+            return false;
+        };
+        material.texture = Some(Texture::Checker {
+            color_a: Vec3::new(r1, g1, b1),
+            color_b: Vec3::new(r2, g2, b2),
+            scale,
+        });
+        material.texture_space = TextureSpace::World;
+        *material = material.clamped();
+        true
+    }
+
+    /// Chooses where an object's textures are evaluated: 0 = world space,
+    /// 1 = object space, which keeps the pattern fixed to the object as it
+    /// moves. Returns false if there is no such object or it is locked.
+    ///
+    /// `object_type` is 0 = sphere, 1 = plane, 2 = box, 3 = cylinder, 4 = triangle,
+    /// 6 = cone, 7 = disk.
+    #[wasm_bindgen]
+    pub fn set_object_texture_space(
+        &mut self,
+        object_type: u32,
+        index: usize,
+        space: u32,
+    ) -> Result<bool, RaytracerError> {
+        let space = TextureSpace::from_u32(space).ok_or_else(|| {
+            RaytracerError::invalid_argument("space", format!(
+                "unknown texture space {}, expected 0 (world) or 1 (object)",
+                space
+            ))
+        })?;
+        let material = ObjectType::from_u32(object_type)
+            .and_then(|object_type| self.scene.unlocked_material_mut(object_type, index));
+        let Some(material) = material else {
+            return Ok(false);
+        };
+        material.texture_space = space;
+        Ok(true)
+    }
+
+    /// Turns a plane into a shadow catcher: the plane itself is invisible but
+    /// the shadows falling on it darken whatever is behind it, and with a
+    /// transparent background they are drawn as translucent black for
+    /// compositing over a photo. Returns false if there is no such plane or
+    /// it is locked.
+    #[wasm_bindgen]
+    pub fn set_plane_shadow_catcher(&mut self, index: usize, enabled: bool) -> bool {
+        let Some(material) = self.scene.unlocked_material_mut(ObjectType::Plane, index) else {
+            return false;
+        };
+        material.shadow_catcher = enabled;
+        true
+    }
+
+    /// Sets how dark a fully shadowed shadow-catcher pixel is, from 0 (no
+    /// visible shadow) to 1 (opaque black).
+    #[wasm_bindgen]
+    pub fn set_shadow_catcher_opacity(&mut self, opacity: f32) {
+        self.shadow_catcher_opacity = opacity.clamp(0.0, 1.0);
+    }
+
+    /// Turns analytic filtering of procedural textures on or off (default
+    /// on). Filtered patterns average over each pixel's footprint, so a
+    /// checkered floor fades to an even blend toward the horizon instead of
+    /// sparkling; turning it off point-samples them, for comparison.
+    #[wasm_bindgen]
+    pub fn set_texture_filtering(&mut self, enabled: bool) {
+        self.texture_filtering = enabled;
+    }
+
+    /// Returns false if there is no such sphere or it is locked. Later
+    /// spheres move down an index; `remove_object_by_id` doesn't need
+    /// indices.
+    #[wasm_bindgen]
+    pub fn remove_sphere(&mut self, index: usize) -> bool {
+        self.remove_unlocked(ObjectType::Sphere, index)
+    }
+
+    /// Returns false if there is no such plane or it is locked.
+    #[wasm_bindgen]
+    pub fn remove_plane(&mut self, index: usize) -> bool {
+        self.remove_unlocked(ObjectType::Plane, index)
+    }
+
+    /// Returns false if there is no such box or it is locked.
+    #[wasm_bindgen]
+    pub fn remove_box(&mut self, index: usize) -> bool {
+        self.remove_unlocked(ObjectType::Box, index)
+    }
+
+    /// Removes every triangle that isn't locked, including those of imported
+    /// meshes.
+    #[wasm_bindgen]
+    pub fn clear_triangles(&mut self) {
+        // Back to front, so the indices still to visit don't shift
+        for index in (0..self.scene.triangles.len()).rev() {
+            if !self.scene.triangles[index].meta.locked {
+                self.scene.remove_object(ObjectType::Triangle, index);
+                self.follow_selection_removal(ObjectType::Triangle, index);
+            }
+        }
+        if self
+            .material_preview
+            .is_some_and(|p| p.object_type == ObjectType::Triangle)
+        {
+            self.material_preview = None;
+        }
+    }
+
+    /// Returns false if there is no such cylinder or it is locked.
+    #[wasm_bindgen]
+    pub fn remove_cylinder(&mut self, index: usize) -> bool {
+        self.remove_unlocked(ObjectType::Cylinder, index)
+    }
+
+    /// Returns false if there is no such cone or it is locked.
+    #[wasm_bindgen]
+    pub fn remove_cone(&mut self, index: usize) -> bool {
+        self.remove_unlocked(ObjectType::Cone, index)
+    }
+
+    /// Returns false if there is no such disk or it is locked.
+    #[wasm_bindgen]
+    pub fn remove_disk(&mut self, index: usize) -> bool {
+        self.remove_unlocked(ObjectType::Disk, index)
+    }
+
+    /// Finds an object or light by the id its `add_*` call returned. Ids
+    /// are saved with the scene and stay with their object, while indices
+    /// shift whenever an earlier object of the same kind is removed, so ids
+    /// are the way to keep hold of an object across edits. Returns `{type,
+    /// index, object}` with `type` numbered as in `set_object_locked` and the
+    /// object's fields as in the scene JSON, or `null` if no object has the
+    /// id.
+    #[wasm_bindgen]
+    pub fn get_object_by_id(&self, id: u32) -> Result<JsValue, RaytracerError> {
+        let to_value = |object: Result<serde_json::Value, serde_json::Error>| {
+            object.map_err(|e| {
+                RaytracerError::graphics(format!("Failed to serialize object: {}", e))
+            })
+        };
+        let (object_type, index, object) = match self.scene.find_id(id) {
+            Some(ObjectLocation::Object(object_type, index)) => {
+                let object = match object_type {
+                    ObjectType::Sphere => serde_json::to_value(&self.scene.spheres[index]),
+                    ObjectType::Plane => serde_json::to_value(&self.scene.planes[index]),
+                    ObjectType::Box => serde_json::to_value(&self.scene.boxes[index]),
+                    ObjectType::Cylinder => serde_json::to_value(&self.scene.cylinders[index]),
+                    ObjectType::Triangle => serde_json::to_value(&self.scene.triangles[index]),
+                    ObjectType::Cone => serde_json::to_value(&self.scene.cones[index]),
+                    ObjectType::Disk => serde_json::to_value(&self.scene.disks[index]),
+                };
+                (object_type.to_u32(), index, to_value(object)?)
+            }
+            Some(ObjectLocation::Light(index)) => (
+                LIGHT_OBJECT_TYPE,
+                index,
+                to_value(serde_json::to_value(&self.scene.lights[index]))?,
+            ),
+            None => return Ok(JsValue::NULL),
+        };
+        to_js(&serde_json::json!({ "type": object_type, "index": index, "object": object }))
+    }
+
+    /// Moves the object or light with id `id`: a sphere's or box's center, a
+    /// plane's point, a cylinder's base, a triangle's centroid, a cone's apex,
+    /// a disk's center or a light's position. Returns false if there is no
+    /// such object or it is locked.
+    #[wasm_bindgen]
+    pub fn set_object_position_by_id(&mut self, id: u32, x: f32, y: f32, z: f32) -> bool {
+        let (object_type, index) = match self.scene.find_id(id) {
+            Some(ObjectLocation::Object(object_type, index)) => (object_type, index),
+            Some(ObjectLocation::Light(index)) => return self.set_light_position(index, x, y, z),
+            None => return false,
+        };
+        if self.scene.is_locked(object_type, index) != Some(false) {
+            return false;
+        }
+        let position = Vec3::new(x, y, z);
+        match object_type {
+            ObjectType::Sphere => self.scene.spheres[index].center = position,
+            ObjectType::Plane => self.scene.planes[index].point = position,
+            ObjectType::Box => self.scene.boxes[index].center = position,
+            ObjectType::Cylinder => self.scene.cylinders[index].base = position,
+            ObjectType::Triangle => {
+                let triangle = &mut self.scene.triangles[index];
+                let offset = position - triangle.centroid();
+                triangle.v0 = triangle.v0 + offset;
+                triangle.v1 = triangle.v1 + offset;
+                triangle.v2 = triangle.v2 + offset;
+            }
+            ObjectType::Cone => self.scene.cones[index].apex = position,
+            ObjectType::Disk => self.scene.disks[index].center = position,
+        }
+        self.scene.touch(object_type, index);
+        true
+    }
+
+    /// Removes the object or light with id `id`. Returns false if there is
+    /// no such object or it is locked.
+    #[wasm_bindgen]
+    pub fn remove_object_by_id(&mut self, id: u32) -> bool {
+        match self.scene.find_id(id) {
+            Some(ObjectLocation::Object(object_type, index)) => {
+                self.remove_unlocked(object_type, index)
+            }
+            Some(ObjectLocation::Light(index)) => self.remove_light(index),
+            None => false,
+        }
+    }
+
+    /// Locks or unlocks an object. Locked objects refuse every edit made
+    /// through this API (setters return false or fail) until unlocked; the
+    /// flag is saved with the scene. Returns false if there is no such
+    /// object.
+    ///
+    /// `object_type` is 0 = sphere, 1 = plane, 2 = box, 3 = cylinder,
+    /// 4 = triangle, 5 = light, 6 = cone, 7 = disk.
+    #[wasm_bindgen]
+    pub fn set_object_locked(&mut self, object_type: u32, index: usize, locked: bool) -> bool {
+        match self.lock_flag_mut(object_type, index) {
+            Some(flag) => {
+                *flag = locked;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether an object is locked; false if there is no such object. Takes
+    /// the same `object_type` values as `set_object_locked`.
+    #[wasm_bindgen]
+    pub fn get_object_locked(&self, object_type: u32, index: usize) -> bool {
+        if object_type == LIGHT_OBJECT_TYPE {
+            return self.scene.lights.get(index).is_some_and(|light| light.locked);
+        }
+        ObjectType::from_u32(object_type)
+            .and_then(|object_type| self.scene.is_locked(object_type, index))
+            .unwrap_or(false)
+    }
+
+    /// Names an object, or clears its name with "". Names are saved with the
+    /// scene and need not be unique. Returns false if there is no such object
+    /// or it is locked. Takes the same `object_type` values as
+    /// `set_object_locked`.
+    #[wasm_bindgen]
+    pub fn set_object_name(&mut self, object_type: u32, index: usize, name: &str) -> bool {
+        let Some(location) = object_location(object_type, index) else {
+            return false;
+        };
+        if self.get_object_locked(object_type, index) {
+            return false;
+        }
+        match self.scene.name_mut(location) {
+            Some(object_name) => {
+                *object_name = name.to_string();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// An object's name; empty if it has none or there is no such object.
+    #[wasm_bindgen]
+    pub fn get_object_name(&self, object_type: u32, index: usize) -> String {
+        object_location(object_type, index)
+            .and_then(|location| self.scene.name(location))
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// The first object named `name` as `{type, index}`, or `null` if none
+    /// is. Spheres are searched first, then planes, boxes, cylinders,
+    /// triangles and lights.
+    #[wasm_bindgen]
+    pub fn find_object_by_name(&self, name: &str) -> Result<JsValue, RaytracerError> {
+        match self.scene.find_name(name).first() {
+            Some(&location) => to_js(&object_ref(location)),
+            None => Ok(JsValue::NULL),
+        }
+    }
+
+    /// Every object named `name`, as an array of `{type, index}` in the
+    /// order `find_object_by_name` searches.
+    #[wasm_bindgen]
+    pub fn find_all_by_name(&self, name: &str) -> Result<JsValue, RaytracerError> {
+        let found: Vec<_> = self.scene.find_name(name).into_iter().map(object_ref).collect();
+        to_js(&found)
+    }
+
+    /// Sets which objects keep their place in the shader when a kind of
+    /// object exceeds its uniform limit: higher priorities (default 0) are
+    /// uploaded first, then objects nearer the camera. Saved with the scene.
+    /// Returns false if there is no such object or it is locked.
+    ///
+    /// `object_type` is 0 = sphere, 1 = plane, 2 = box, 3 = cylinder, 4 = triangle,
+    /// 6 = cone, 7 = disk.
+    #[wasm_bindgen]
+    pub fn set_object_priority(&mut self, object_type: u32, index: usize, priority: i32) -> bool {
+        let Some(object_type) = ObjectType::from_u32(object_type) else {
+            return false;
+        };
+        if self.scene.is_locked(object_type, index) != Some(false) {
+            return false;
+        }
+        match self.scene.priority_mut(object_type, index) {
+            Some(value) => {
+                *value = priority;
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn get_light_count(&self) -> usize {
+        self.scene.lights.len()
+    }
+
+    /// Returns false if there is no such light or it is locked.
+    #[wasm_bindgen]
+    pub fn remove_light(&mut self, index: usize) -> bool {
+        if self.scene.lights.get(index).is_none_or(|light| light.locked) {
+            return false;
+        }
+        self.scene.remove_light(index).is_some()
+    }
+
+    /// Moves a light. While the day/night cycle runs it drives the first
+    /// light itself. Returns false if there is no such light or it is locked.
+    #[wasm_bindgen]
+    pub fn set_light_position(&mut self, index: usize, x: f32, y: f32, z: f32) -> bool {
+        self.with_unlocked_object(index, |light: &mut Light| light.position = Vec3::new(x, y, z))
+    }
+
+    /// Returns false if there is no such light or it is locked.
+    #[wasm_bindgen]
+    pub fn set_light_color(&mut self, index: usize, r: f32, g: f32, b: f32) -> bool {
+        self.with_unlocked_object(index, |light: &mut Light| light.color = Vec3::new(r, g, b))
+    }
+
+    /// Returns false if there is no such light or it is locked.
+    #[wasm_bindgen]
+    pub fn set_light_intensity(&mut self, index: usize, value: f32) -> bool {
+        self.with_unlocked_object(index, |light: &mut Light| light.intensity = value.max(0.0))
+    }
+
+    /// Links light `light_index` to an object or unlinks it. An unlinked
+    /// object gets none of the light's direct illumination but still casts
+    /// its shadow. Lights start linked to everything; the links are saved
+    /// with the scene by object id (see `get_segmentation_palette`).
+    #[wasm_bindgen]
+    pub fn set_light_link(
+        &mut self,
+        light_index: usize,
+        object_type: u32,
+        object_index: usize,
+        linked: bool,
+    ) -> Result<(), RaytracerError> {
+        let object_type = object_type_from_js(object_type)?;
+        let object_count = self.scene.object_count(object_type);
+        if object_index >= object_count {
+            return Err(RaytracerError::IndexOutOfRange {
+                kind: object_type.name(),
+                index: object_index,
+                len: object_count,
+            });
+        }
+
+        let light_count = self.scene.lights.len();
+        let light = self
+            .scene
+            .lights
+            .get_mut(light_index)
+            .ok_or(RaytracerError::IndexOutOfRange {
+                kind: "light",
+                index: light_index,
+                len: light_count,
+            })?;
+        if light.locked {
+            return Err(RaytracerError::Locked {
+                kind: "light",
+                index: light_index,
+            });
+        }
+        light.link.set(object_type.object_id(object_index), linked);
+        self.scene.touch_light(light_index);
+        Ok(())
+    }
+
+    /// Combines two objects into one solid and returns the combination's
+    /// index. `op` is 0 for their union, 1 for `a` minus `b` and 2 for their
+    /// intersection; object types are numbered as in `set_object_locked`,
+    /// and only spheres (0) and boxes (2) combine. The two objects are drawn
+    /// as the combination instead of on their own, each surface keeping its
+    /// object's material. Only the first four combinations are drawn.
+    #[wasm_bindgen]
+    pub fn add_csg(
+        &mut self,
+        op: u32,
+        type_a: u32,
+        index_a: usize,
+        type_b: u32,
+        index_b: usize,
+    ) -> Result<usize, RaytracerError> {
+        let op = CsgOperation::from_u32(op).ok_or_else(|| {
+            RaytracerError::invalid_argument(
+                "op",
+                format!("unknown CSG operation {}, expected 0 to 2", op),
+            )
+        })?;
+        let operand = |object_type: u32, index: usize| {
+            let kind = object_type_from_js(object_type)?;
+            if !CsgOp::supports(kind) {
+                return Err(RaytracerError::invalid_argument(
+                    "object_type",
+                    format!("a {} can't be combined, only spheres and boxes", kind.name()),
+                ));
+            }
+            let len = self.scene.object_count(kind);
+            if index >= len {
+                return Err(RaytracerError::IndexOutOfRange {
+                    kind: kind.name(),
+                    index,
+                    len,
+                });
+            }
+            Ok(ObjectRef { kind, index })
+        };
+        let a = operand(type_a, index_a)?;
+        let b = operand(type_b, index_b)?;
+        if a == b {
+            return Err(RaytracerError::invalid_argument(
+                "index_b",
+                "an object can't be combined with itself",
+            ));
+        }
+        Ok(self.scene.add_csg(CsgOp { op, a, b }))
+    }
+
+    /// Removes a combination, drawing its objects on their own again.
+    /// Returns false if there is no such combination.
+    #[wasm_bindgen]
+    pub fn remove_csg(&mut self, index: usize) -> bool {
+        self.scene.remove_csg(index).is_some()
+    }
+
+    #[wasm_bindgen]
+    pub fn get_csg_count(&self) -> usize {
+        self.scene.csg.len()
+    }
+
+    /// Shows a material on one object without changing the scene, replacing
+    /// any earlier preview. Emission, opacity and the shadow-catcher flag are
+    /// kept from the object's own material. Use `commit_preview` to keep it
+    /// or `cancel_preview` to drop it; exports never include it.
+    #[wasm_bindgen]
+    pub fn preview_material(
+        &mut self,
+        object_type: u32,
+        index: usize,
+        r: f32,
+        g: f32,
+        b: f32,
+        material_type: u32,
+        roughness: f32,
+        ior: f32,
+    ) -> Result<(), RaytracerError> {
+        let object_type = object_type_from_js(object_type)?;
+        let mut material = *self.editable_material(object_type, index)?;
+
+        material.material_type = MaterialType::from_u32(material_type).unwrap_or_default();
+        material.albedo = Vec3::new(r, g, b);
+        material.roughness = roughness;
+        material.ior = ior;
+
+        self.material_preview = Some(MaterialOverride {
+            object_type,
+            index,
+            material,
+        });
+        self.reset_accumulation();
+        Ok(())
+    }
+
+    /// Writes the previewed material into the scene. Returns false if there
+    /// was no preview.
+    #[wasm_bindgen]
+    pub fn commit_preview(&mut self) -> bool {
+        let Some(preview) = self.material_preview.take() else {
+            return false;
+        };
+        match self.scene.unlocked_material_mut(preview.object_type, preview.index) {
+            Some(material) => {
+                *material = preview.material;
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn cancel_preview(&mut self) {
+        self.material_preview = None;
+        self.reset_accumulation();
+    }
+
+    #[wasm_bindgen]
+    pub fn set_camera_position(&mut self, x: f32, y: f32, z: f32) {
+        self.cancel_camera_motion();
+        self.camera.set_position(Vec3::new(x, y, z));
+        self.bump_generation();
+    }
+
+    #[wasm_bindgen]
+    pub fn get_camera_position(&self) -> Vec<f32> {
+        let pos = self.camera.get_position();
+        vec![pos.x, pos.y, pos.z]
+    }
+
+    #[wasm_bindgen]
+    pub fn set_camera_target(&mut self, x: f32, y: f32, z: f32) {
+        self.cancel_camera_motion();
+        self.camera.set_target(Vec3::new(x, y, z));
+        self.bump_generation();
+    }
+
+    /// Moves the camera to `(x, y, z)` looking at `(tx, ty, tz)` over
+    /// `duration_ms`, easing in and out; each `render()` advances it. The view
+    /// turns along the shortest arc rather than sweeping its look-at point
+    /// through the scene. Like `set_camera_position`, the path ignores camera
+    /// collision. Camera setters, `move_camera`, `rotate_camera` and input to
+    /// the built-in controls cancel it where it is. A duration of 0 or less
+    /// jumps on the next frame. Stops a playing camera path.
+    #[wasm_bindgen]
+    pub fn fly_to(&mut self, x: f32, y: f32, z: f32, tx: f32, ty: f32, tz: f32, duration_ms: f64) {
+        self.camera_path_playback = None;
+        self.camera_flight = Some(CameraFlight::new(
+            &self.camera,
+            Vec3::new(x, y, z),
+            Vec3::new(tx, ty, tz),
+            Date::now(),
+            duration_ms,
+        ));
+    }
+
+    /// Backs the camera away along its view direction until every finite
+    /// object fits in view, looking at the center of their bounding box: the
+    /// "home" view. Planes are infinite and don't count. A scene without
+    /// finite objects sends the camera back to its starting viewpoint. Stops
+    /// a `fly_to` or camera path.
+    #[wasm_bindgen]
+    pub fn frame_scene(&mut self) {
+        self.cancel_camera_motion();
+        let bounds = self
+            .scene
+            .bounding_box()
+            .filter(|bounds| bounds.min.is_finite() && bounds.max.is_finite());
+        match bounds {
+            Some(bounds) => {
+                let direction = -self.camera.get_forward();
+                self.camera.frame_bounds(&bounds, direction);
+            }
+            None => {
+                let world_scale = self.scene.world_scale;
+                self.camera.set_position(DEFAULT_CAMERA_POSITION * world_scale);
+                self.camera.look_at(DEFAULT_CAMERA_TARGET * world_scale);
+            }
+        }
+        self.bump_generation();
+    }
+
+    #[wasm_bindgen]
+    pub fn is_camera_animating(&self) -> bool {
+        self.camera_flight.is_some()
+    }
+
+    /// Stops a `fly_to` where the camera is now.
+    #[wasm_bindgen]
+    pub fn cancel_camera_animation(&mut self) {
+        self.camera_flight = None;
+    }
+
+    /// Adds a keyframe to the scene's camera path at `time_s` seconds from
+    /// its start: the camera at `(x, y, z)`, turned `yaw` degrees left from
+    /// looking down -z and `pitch` degrees up, with a vertical field of view
+    /// of `fov` degrees. Keyframes are kept in time order whatever order they
+    /// are added in; one at the same time as an existing keyframe replaces
+    /// it. The path is saved with the scene.
+    #[allow(clippy::too_many_arguments)]
+    #[wasm_bindgen]
+    pub fn add_camera_keyframe(
+        &mut self,
+        time_s: f32,
+        x: f32,
+        y: f32,
+        z: f32,
+        yaw: f32,
+        pitch: f32,
+        fov: f32,
+    ) -> Result<(), RaytracerError> {
+        if !(time_s.is_finite() && time_s >= 0.0) {
+            return Err(RaytracerError::invalid_argument(
+                "time_s",
+                "must be a finite number of seconds, 0 or more",
+            ));
+        }
+        if ![x, y, z, yaw, pitch, fov].iter().all(|v| v.is_finite()) {
+            return Err(RaytracerError::invalid_argument(
+                "keyframe",
+                "position and angles must be finite",
+            ));
+        }
+        self.scene
+            .camera_path
+            .get_or_insert_with(CameraPath::new)
+            .insert(CameraKeyframe {
+                time: time_s,
+                position: Vec3::new(x, y, z),
+                yaw,
+                pitch,
+                fov,
+            });
+        self.scene.touch_settings();
+        Ok(())
+    }
+
+    /// Removes the scene's camera path, stopping it if it's playing.
+    #[wasm_bindgen]
+    pub fn clear_camera_path(&mut self) {
+        self.camera_path_playback = None;
+        if self.scene.camera_path.take().is_some() {
+            self.scene.touch_settings();
+        }
+    }
+
+    /// Number of keyframes in the scene's camera path.
+    #[wasm_bindgen]
+    pub fn get_camera_keyframe_count(&self) -> usize {
+        self.scene.camera_path.as_ref().map_or(0, |path| path.keyframes().len())
+    }
+
+    /// Replays the scene's camera path from its start against scene time, so
+    /// pausing time or `set_scene_time` hold or scrub it. Each `render()`
+    /// moves the camera along a smooth curve through the keyframes. Without
+    /// `looping` it stops at the last keyframe; with it, it starts over. Like
+    /// `fly_to`, camera setters and control input stop it; starting it stops
+    /// a flight. Does nothing visible if the scene has no path.
+    #[wasm_bindgen]
+    pub fn play_camera_path(&mut self, looping: bool) {
+        self.camera_flight = None;
+        self.camera_path_playback = Some(CameraPathPlayback::new(self.clock.now(), looping));
+    }
+
+    /// Stops the camera path where the camera is now.
+    #[wasm_bindgen]
+    pub fn stop_camera_path(&mut self) {
+        self.camera_path_playback = None;
+    }
+
+    #[wasm_bindgen]
+    pub fn is_camera_path_playing(&self) -> bool {
+        self.camera_path_playback.is_some()
+    }
+
+    /// Saves the current view (position, direction, field of view and
+    /// aspect ratio) under `name`, replacing any preset of that name.
+    /// Presets are saved with the scene, so an exported scene opens with
+    /// its viewpoints.
+    #[wasm_bindgen]
+    pub fn save_camera_preset(&mut self, name: &str) -> Result<(), RaytracerError> {
+        if name.is_empty() {
+            return Err(RaytracerError::invalid_argument("name", "must not be empty"));
+        }
+        self.scene
+            .camera_presets
+            .insert(name.to_string(), CameraPreset::capture(&self.camera));
+        self.scene.touch_settings();
+        Ok(())
+    }
+
+    /// Moves the camera to the view saved as `name`: at once for a
+    /// `duration_ms` of 0 or less, otherwise eased like `fly_to`, with the
+    /// field of view following along. The aspect ratio stays the canvas's.
+    #[wasm_bindgen]
+    pub fn apply_camera_preset(
+        &mut self,
+        name: &str,
+        duration_ms: f64,
+    ) -> Result<(), RaytracerError> {
+        let preset = *self.scene.camera_presets.get(name).ok_or_else(|| {
+            RaytracerError::invalid_argument("name", format!("no camera preset named '{}'", name))
+        })?;
+        self.cancel_camera_motion();
+        if duration_ms > 0.0 {
+            self.camera_flight = Some(preset.flight(&self.camera, Date::now(), duration_ms));
+        } else {
+            preset.apply(&mut self.camera);
+            self.bump_generation();
+        }
+        Ok(())
+    }
+
+    /// Names of the saved camera presets, in alphabetical order.
+    #[wasm_bindgen]
+    pub fn list_camera_presets(&self) -> Vec<String> {
+        self.scene.camera_presets.keys().cloned().collect()
+    }
+
+    /// Removes the preset saved as `name`. Returns false if there was none.
+    #[wasm_bindgen]
+    pub fn delete_camera_preset(&mut self, name: &str) -> bool {
+        let removed = self.scene.camera_presets.remove(name).is_some();
+        if removed {
+            self.scene.touch_settings();
+        }
+        removed
+    }
+
+    /// Sets the vertical field of view in degrees (default 90), clamped to 1
+    /// to 179. Narrowing it zooms in.
+    #[wasm_bindgen]
+    pub fn set_camera_fov(&mut self, degrees: f32) {
+        self.camera.set_fov(degrees);
+        self.bump_generation();
+    }
+
+    #[wasm_bindgen]
+    pub fn get_camera_fov(&self) -> f32 {
+        self.camera.fov().to_degrees()
+    }
+
+    /// Opens the lens to `radius` in scene units for depth of field: rays
+    /// start anywhere on a disk of that radius and meet on the focal plane
+    /// (see `set_camera_focus_distance`), so everything off that plane blurs.
+    /// 0 (the default) is a pinhole with everything sharp.
+    #[wasm_bindgen]
+    pub fn set_camera_aperture(&mut self, radius: f32) {
+        self.camera.set_aperture(radius);
+        self.bump_generation();
+    }
+
+    #[wasm_bindgen]
+    pub fn get_camera_aperture(&self) -> f32 {
+        self.camera.aperture()
+    }
+
+    /// Sets the distance along the view direction to the plane in focus.
+    #[wasm_bindgen]
+    pub fn set_camera_focus_distance(&mut self, distance: f32) -> Result<(), RaytracerError> {
+        if !(distance.is_finite() && distance > 0.0) {
+            return Err(RaytracerError::invalid_argument(
+                "distance",
+                format!("focus distance must be positive, got {}", distance),
+            ));
+        }
+        self.camera.set_focus_distance(distance);
+        self.bump_generation();
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn get_camera_focus_distance(&self) -> f32 {
+        self.camera.focus_distance()
+    }
+
+    /// The camera ray through `(x, y)` in render-buffer pixels (see
+    /// `client_to_render_coords`) as `[ox, oy, oz, dx, dy, dz]`: the camera
+    /// position and a unit direction, matching the rays the shader traces, for
+    /// picking and placing objects from JavaScript. With depth of field the
+    /// shader spreads rays over the lens around this one.
+    #[wasm_bindgen]
+    pub fn get_ray_direction(&self, x: f32, y: f32) -> Vec<f32> {
+        let Ray { origin, direction } = self.camera_ray(x, y);
+        vec![origin.x, origin.y, origin.z, direction.x, direction.y, direction.z]
+    }
+
+    /// Like `get_ray_direction`, at CSS pixels relative to the canvas.
+    /// Returns `undefined` in a letterbox bar.
+    #[wasm_bindgen]
+    pub fn get_ray_direction_client(&self, client_x: f32, client_y: f32) -> Option<Vec<f32>> {
+        let (x, y) = self.viewport.client_to_render(client_x, client_y)?;
+        Some(self.get_ray_direction(x, y))
+    }
+
+    /// The object under `(x, y)` in render-buffer pixels (see
+    /// `client_to_render_coords`), found by tracing the camera ray on the CPU
+    /// with the shader's intersection tests and near and far limits. Returns
+    /// `{kind, type, index, distance, point: [x, y, z]}` for the nearest hit,
+    /// where `kind` is the type's name ("sphere", ...) and `type` its number
+    /// in the object API, or `null` if the ray hits nothing. Glass is picked
+    /// itself rather than what shows through it. Instanced grid spheres are
+    /// not hit.
+    #[wasm_bindgen]
+    pub fn pick_object(&self, x: f32, y: f32) -> Result<JsValue, RaytracerError> {
+        let ray = self.camera_ray(x, y);
+        let (t_min, t_max) = self.ray_limits();
+        match intersect::closest_hit(&self.scene, &ray, t_min, t_max) {
+            Some(hit) => {
+                let point = ray.at(hit.t);
+                to_js(&PickedObject {
+                    kind: hit.object_type.name(),
+                    object_type: hit.object_type.to_u32(),
+                    index: hit.index,
+                    distance: hit.t,
+                    point: [point.x, point.y, point.z],
+                })
+            }
+            None => Ok(JsValue::NULL),
+        }
+    }
+
+    /// Like `pick_object`, at CSS pixels relative to the canvas. Returns
+    /// `null` in a letterbox bar.
+    #[wasm_bindgen]
+    pub fn pick_object_client(
+        &self,
+        client_x: f32,
+        client_y: f32,
+    ) -> Result<JsValue, RaytracerError> {
+        match self.viewport.client_to_render(client_x, client_y) {
+            Some((x, y)) => self.pick_object(x, y),
+            None => Ok(JsValue::NULL),
+        }
+    }
+
+    /// Moves sphere `index` to where the camera ray through `(x, y)` (in
+    /// render-buffer pixels, as for `pick_object`) crosses the horizontal
+    /// plane at its center's height, for dragging it across the ground.
+    /// Returns the new center as `[x, y, z]`, or `undefined` without moving
+    /// anything if the ray runs parallel to the plane or misses it, or there
+    /// is no such sphere or it is locked.
+    #[wasm_bindgen]
+    pub fn drag_sphere_to(&mut self, index: usize, x: f32, y: f32) -> Option<Vec<f32>> {
+        let center = self.scene.spheres.get(index)?.center;
+        self.drag_sphere(index, x, y, center, Vec3::new(0.0, 1.0, 0.0))
+    }
+
+    /// Like `drag_sphere_to`, at CSS pixels relative to the canvas. Returns
+    /// `undefined` without moving the sphere in a letterbox bar.
+    #[wasm_bindgen]
+    pub fn drag_sphere_to_client(
+        &mut self,
+        index: usize,
+        client_x: f32,
+        client_y: f32,
+    ) -> Option<Vec<f32>> {
+        let (x, y) = self.viewport.client_to_render(client_x, client_y)?;
+        self.drag_sphere_to(index, x, y)
+    }
+
+    /// Like `drag_sphere_to`, on the plane through `(px, py, pz)` with
+    /// normal `(nx, ny, nz)`.
+    #[allow(clippy::too_many_arguments)]
+    #[wasm_bindgen]
+    pub fn drag_sphere_on_plane(
+        &mut self,
+        index: usize,
+        x: f32,
+        y: f32,
+        nx: f32,
+        ny: f32,
+        nz: f32,
+        px: f32,
+        py: f32,
+        pz: f32,
+    ) -> Option<Vec<f32>> {
+        let normal = Vec3::new(nx, ny, nz).normalize();
+        if normal.length_squared() == 0.0 {
+            return None;
+        }
+        self.drag_sphere(index, x, y, Vec3::new(px, py, pz), normal)
+    }
+
+    /// Like `drag_sphere_on_plane`, at CSS pixels relative to the canvas.
+    /// Returns `undefined` without moving the sphere in a letterbox bar.
+    #[allow(clippy::too_many_arguments)]
+    #[wasm_bindgen]
+    pub fn drag_sphere_on_plane_client(
+        &mut self,
+        index: usize,
+        client_x: f32,
+        client_y: f32,
+        nx: f32,
+        ny: f32,
+        nz: f32,
+        px: f32,
+        py: f32,
+        pz: f32,
+    ) -> Option<Vec<f32>> {
+        let (x, y) = self.viewport.client_to_render(client_x, client_y)?;
+        self.drag_sphere_on_plane(index, x, y, nx, ny, nz, px, py, pz)
+    }
+
+    /// Like `drag_sphere_to`, on the plane through the sphere's center facing
+    /// the camera, so it follows the pointer at its current depth.
+    #[wasm_bindgen]
+    pub fn drag_sphere_in_view(&mut self, index: usize, x: f32, y: f32) -> Option<Vec<f32>> {
+        let center = self.scene.spheres.get(index)?.center;
+        self.drag_sphere(index, x, y, center, self.camera.get_forward())
+    }
+
+    /// Like `drag_sphere_in_view`, at CSS pixels relative to the canvas.
+    /// Returns `undefined` without moving the sphere in a letterbox bar.
+    #[wasm_bindgen]
+    pub fn drag_sphere_in_view_client(
+        &mut self,
+        index: usize,
+        client_x: f32,
+        client_y: f32,
+    ) -> Option<Vec<f32>> {
+        let (x, y) = self.viewport.client_to_render(client_x, client_y)?;
+        self.drag_sphere_in_view(index, x, y)
+    }
+
+    /// Focuses on whatever is under `(x, y)` in render-buffer pixels (see
+    /// `client_to_render_coords`), for click-to-focus. Returns the new focus
+    /// distance, or `undefined` with the focus unchanged if nothing is there.
+    /// Instanced grid spheres are not hit.
+    #[wasm_bindgen]
+    pub fn auto_focus(&mut self, x: f32, y: f32) -> Option<f32> {
+        let ray = self.camera_ray(x, y);
+        let t = intersect::ray_scene(&self.scene, &ray, self.camera.near(), self.camera.far())?;
+
+        // The focal plane faces the camera, so its distance is the hit's depth
+        let distance = t * ray.direction.dot(&self.camera.get_forward());
+        self.camera.set_focus_distance(distance);
+        self.bump_generation();
+        Some(self.camera.focus_distance())
+    }
+
+    /// Like `auto_focus`, at CSS pixels relative to the canvas. Returns
+    /// `undefined` with the focus unchanged in a letterbox bar.
+    #[wasm_bindgen]
+    pub fn auto_focus_client(&mut self, client_x: f32, client_y: f32) -> Option<f32> {
+        let (x, y) = self.viewport.client_to_render(client_x, client_y)?;
+        self.auto_focus(x, y)
+    }
+
+    #[wasm_bindgen]
+    pub fn random_scene(&mut self) {
+        self.clear_scene();
+
+        // Add some random spheres
+        for i in 0..8 {
+            let x = (i as f32 - 4.0) * 2.0 + (js_sys::Math::random() as f32 - 0.5) * 1.5;
+            let z = -2.0 + (js_sys::Math::random() as f32) * -4.0;
+            let y = 0.0;
+            let radius = 0.3 + (js_sys::Math::random() as f32) * 0.5;
+
+            let material_type = (js_sys::Math::random() * 3.0) as u32;
+            let r = js_sys::Math::random() as f32;
+            let g = js_sys::Math::random() as f32;
+            let b = js_sys::Math::random() as f32;
+
+            self.add_sphere(x, y, z, radius, r, g, b, material_type);
+        }
+
+        // Re-add better lighting
+        self.scene.add_light(Light::new(
+            Vec3::new(10.0, 10.0, 10.0),
+            Vec3::new(1.0, 1.0, 0.9),
+            200.0,
+        ));
+        self.scene.add_light(Light::new(
+            Vec3::new(-5.0, 8.0, 5.0),
+            Vec3::new(0.7, 0.8, 1.0),
+            80.0,
+        ));
+        self.scene.add_light(Light::new(
+            Vec3::new(0.0, 15.0, 0.0),
+            Vec3::new(0.9, 0.9, 0.8),
+            150.0,
+        ));
+    }
+}
+
+impl Raytracer {
+    fn update_lod(&mut self) {
+        // Only judge once the FPS window is full, so startup frames don't count
+        let Some(threshold) = self.lod_threshold_fps else {
+            return;
+        };
+        if self.frame_times.len() < 60 {
+            return;
+        }
+
+        if self.active_lod == 0 && self.fps < threshold {
+            self.active_lod = 1;
+        } else if self.active_lod == 1 && self.fps > threshold * LOD_RECOVERY_FACTOR {
+            self.active_lod = 0;
+        }
+    }
+
+    fn update_quality(&mut self, now_ms: f64) {
+        let boosted = self.idle_boost.is_some_and(|boost| boost.stage() > 0);
+        if let Some(adaptive) = self.adaptive_scale.as_mut() {
+            if !boosted
+                && self.frame_times.len() >= ADAPTIVE_WINDOW_FRAMES
+                && adaptive.update(self.fps)
+            {
+                self.apply_render_scale();
+                // Judge the new scale on its own frames only
+                self.frame_times.clear();
+            }
+            return;
+        }
+
+        // Same full-window rule as the LOD policy
+        let Some(governor) = self.quality_governor.as_mut() else {
+            return;
+        };
+        if self.frame_times.len() < 60 || boosted {
+            return;
+        }
+        if let Some(level) = governor.update(self.fps, now_ms) {
+            self.apply_quality_level(level);
+            // Judge the new level on its own frames only
+            self.frame_times.clear();
+        }
+    }
+
+    fn apply_quality_level(&mut self, level: QualityLevel) {
+        self.quality_level = Some(level);
+        self.quality = level.settings();
+        self.apply_render_scale();
+    }
+
+    /// Sizes the render buffer by the adaptive scale if enabled, else by the
+    /// quality settings.
+    fn apply_render_scale(&mut self) {
+        self.viewport.render_scale = self
+            .adaptive_scale
+            .map_or(self.quality.render_scale, |adaptive| adaptive.scale());
+    }
+
+    /// How many objects of `object_type` the shader draws.
+    /// Adds a mesh read from a file: converted with the import axes, then
+    /// scaled and moved. A "# units:" hint in the file sets the scale of the
+    /// whole scene.
+    fn add_imported_mesh(&mut self, mut mesh: Mesh, scale: f32, offset: Vec3) {
+        self.import_axes.mesh(&mut mesh);
+        mesh.transform(scale, offset);
+        if let Some(world_scale) = mesh.world_scale {
+            self.scene.world_scale = world_scale;
+            self.scene.touch_settings();
+            self.apply_world_scale();
+        }
+        self.scene.add_mesh(mesh);
+    }
+
+    /// Replaces the scene with an imported one, reporting what the import
+    /// left out.
+    fn replace_with_import(
+        &mut self,
+        scene: Scene,
+        warnings: Vec<String>,
+    ) -> Result<JsValue, RaytracerError> {
+        for warning in &warnings {
+            console::warn_1(&warning.into());
+        }
+        let (triangles, lights) = (scene.triangles.len(), scene.lights.len());
+        self.replace_scene(scene);
+        self.warn_dropped_triangles();
+        to_js(&serde_json::json!({
+            "triangles": triangles,
+            "lights": lights,
+            "warnings": warnings,
+        }))
+    }
+
+    fn warn_dropped_triangles(&self) {
+        let max_triangles = self.object_capacity(ObjectType::Triangle);
+        if self.scene.triangles.len() > max_triangles {
+            console::warn_1(
+                &format!(
+                    "Scene has {} triangles but only the first {} are rendered",
+                    self.scene.triangles.len(),
+                    max_triangles
+                )
+                .into(),
+            );
+        }
+    }
+
+    fn object_capacity(&self, object_type: ObjectType) -> usize {
+        object_type.capacity(self.scene_data.is_some())
+    }
+
+    /// How many of `requested` new spheres fit under the sphere limit,
+    /// warning on the console if not all of them do.
+    fn free_sphere_slots(&self, requested: usize) -> usize {
+        let max_spheres = self.object_capacity(ObjectType::Sphere);
+        let free = max_spheres.saturating_sub(self.scene.spheres.len());
+        if requested > free {
+            console::warn_1(
+                &format!(
+                    "Only {} of {} spheres were added, the renderer draws at most {}",
+                    free, requested, max_spheres
+                )
+                .into(),
+            );
+        }
+        requested.min(free)
+    }
+
+    /// Per kind of object, lights and volumes included: its name, how many
+    /// the shader holds and how many the scene has.
+    fn object_counts(&self) -> Vec<(&'static str, usize, usize)> {
+        let mut counts: Vec<_> = ObjectType::ALL
+            .into_iter()
+            .map(|object_type| {
+                let count = self.scene.object_count(object_type);
+                (object_type.name(), self.object_capacity(object_type), count)
+            })
+            .collect();
+        counts.push(("light", MAX_LIGHTS, self.scene.lights.len()));
+        counts.push(("volume", MAX_VOLUMES, self.scene.volumes.len()));
+        counts
+    }
+
+    /// The quality settings with any idle boost and anti-aliasing applied.
+    fn effective_quality(&self) -> QualitySettings {
+        let mut settings = match self.idle_boost {
+            Some(boost) => boost.apply(self.quality),
+            None => self.quality,
+        };
+        // The shader jitters every primary ray, so anti-aliasing only needs
+        // enough of them; accumulation gets the same from successive frames
+        if !self.accumulation_enabled {
+            settings.samples_per_pixel = settings.samples_per_pixel.max(self.antialiasing);
+        }
+        settings
+    }
+
+    /// The shader features needed to draw `scene` at the current quality.
+    fn shader_features(&self, scene: &Scene) -> ShaderFeatures {
+        ShaderFeatures {
+            fog: !scene.volumes.is_empty(),
+            ambient_occlusion: self.effective_quality().ambient_occlusion_samples > 0,
+            instanced_grid: scene.instanced_grid.is_some(),
+            nan_guard: self.nan_guard,
+            data_texture: self.scene_data.is_some(),
+        }
+    }
+
+    /// Logs the scene's degenerate objects, once per scene revision.
+    fn check_scene_finite(&mut self) {
+        let revision = self.scene.revision();
+        if self.nan_guard_revision == Some(revision) {
+            return;
+        }
+        self.nan_guard_revision = Some(revision);
+        for problem in self.scene.degenerate_objects() {
+            console::warn_1(&format!("NaN guard: {}", problem).into());
+        }
+    }
+
+    /// Switches to the shader variant for `features`. Unless `wait` is set, a
+    /// variant still compiling in the background leaves the current one in
+    /// use until a later frame.
+    fn select_shader_variant(
+        &mut self,
+        features: ShaderFeatures,
+        wait: bool,
+    ) -> Result<(), RaytracerError> {
+        if features == self.shader_features {
+            return Ok(());
+        }
+        let program = if wait {
+            Some(self.shader_variants.program(&self.gl, features)?)
+        } else {
+            self.shader_variants.poll(&self.gl, features)?
+        };
+        if let Some(program) = program {
+            self.uniforms = RaytracingUniforms::locate(&self.gl, &program);
+            self.scene_uniforms = SceneUniforms::locate(&self.gl, &program);
+            self.program = program;
+            self.shader_features = features;
+            // The new program has never been sent the camera
+            self.uploaded_camera.set(None);
+        }
+        Ok(())
+    }
+
+    /// (Re)creates the reduced-resolution buffer when its size changes.
+    fn ensure_scaled_target(&mut self, width: u32, height: u32) -> Result<(), RaytracerError> {
+        if self
+            .scaled_target
+            .as_ref()
+            .is_some_and(|target| target.width == width && target.height == height)
+        {
+            return Ok(());
+        }
+        if let Some(old) = self.scaled_target.take() {
+            old.delete(&self.gl);
+        }
+
+        let target = RenderTarget::new(&self.gl, width, height)?;
+        // Smooth the upscale rather than showing blocky pixels
+        for filter in [
+            WebGlRenderingContext::TEXTURE_MIN_FILTER,
+            WebGlRenderingContext::TEXTURE_MAG_FILTER,
+        ] {
+            self.gl.tex_parameteri(
+                WebGlRenderingContext::TEXTURE_2D,
+                filter,
+                WebGlRenderingContext::LINEAR as i32,
+            );
+        }
+        self.scaled_target = Some(target);
+        Ok(())
+    }
+
+    /// Marks the camera or scene as changed, restarting the frames-since-change
+    /// count with the next frame.
+    fn bump_generation(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    /// (Re)creates the accumulation buffers when the render size changes,
+    /// which drops the average.
+    fn ensure_accumulation(&mut self, width: u32, height: u32) -> Result<(), RaytracerError> {
+        if self
+            .accumulation
+            .as_ref()
+            .is_some_and(|acc| acc.width() == width && acc.height() == height)
+        {
+            return Ok(());
+        }
+        if let Some(old) = self.accumulation.take() {
+            old.delete(&self.gl);
+        }
+        self.accumulation = Some(Accumulation::new(&self.gl, width, height)?);
+        Ok(())
+    }
+
+    /// Resets the accumulated average if the camera or scene changed, the
+    /// scene is animating or anything the frame is drawn with differs from
+    /// what the average was drawn with. Changes the motion tracker found
+    /// confined to its regions only restart the average inside them.
+    fn update_accumulation_key(&mut self, width: u32, height: u32) {
+        let key = AccumulationKey {
+            size: (width, height),
+            quality: self.effective_quality(),
+            features: self.shader_features,
+            // Auto exposure never quite settles; ignore drift below 1/64 EV
+            exposure_steps: (self.exposure_ev * 64.0).round() as i32,
+            ambient: self.ambient,
+            shadow_catcher_opacity: self.shadow_catcher_opacity,
+            texture_filtering: self.texture_filtering,
+            transparent_background: self.transparent_background,
+            lod: self.active_lod,
+        };
+        let animating = self.day_night.is_some() && !self.clock.is_paused();
+        let contained = self.motion_tracker.is_some() && self.history_reject_contained;
+        if (self.frames_since_change == 0 && !contained)
+            || animating
+            || self.scene_load.is_some()
+            || self.accumulation_key != Some(key)
+        {
+            self.reset_accumulation();
+        } else if self.frames_since_change == 0
+            && let Some(accumulation) = self.accumulation.as_mut()
+        {
+            accumulation.reject(&self.history_reject_regions);
+        }
+        self.accumulation_key = Some(key);
+    }
+
+    /// Meters the current view and moves the exposure toward its target.
+    fn update_exposure(
+        &mut self,
+        auto_exposure: AutoExposure,
+        delta_s: f32,
+    ) -> Result<(), RaytracerError> {
+        if self.meter_target.is_none() {
+            self.meter_target = Some(RenderTarget::new(
+                &self.gl,
+                exposure::METER_SIZE,
+                exposure::METER_SIZE,
+            )?);
+        }
+        let Some(target) = &self.meter_target else {
+            return Ok(());
+        };
+        let pixels = self.render_offscreen(
+            target,
+            &self.scene,
+            &self.camera,
+            gbuffer::OUTPUT_LUMINANCE,
+        );
+        self.gl.bind_framebuffer(WebGlRenderingContext::FRAMEBUFFER, None);
+
+        let log_luminances = gbuffer::decode_log_luminance(&pixels?);
+        if let Some(target_ev) = auto_exposure.target_ev(&log_luminances) {
+            self.exposure_ev = auto_exposure.adapt(self.exposure_ev, target_ev, delta_s);
+        }
+        Ok(())
+    }
+
+    /// Draws `source` over the current viewport.
+    fn blit(&self, source: &RenderTarget) {
+        self.gl.use_program(Some(&self.blit_program));
+        self.gl.active_texture(WebGlRenderingContext::TEXTURE0);
+        self.gl
+            .bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&source.texture));
+        let source_location = self.gl.get_uniform_location(&self.blit_program, "u_source");
+        self.gl.uniform1i(source_location.as_ref(), 0);
+
+        self.gl
+            .bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&self.quad_buffer));
+        let position_location = self.gl.get_attrib_location(&self.blit_program, "a_position");
+        self.gl.enable_vertex_attrib_array(position_location as u32);
+        self.gl.vertex_attrib_pointer_with_i32(
+            position_location as u32,
+            2,
+            WebGlRenderingContext::FLOAT,
+            false,
+            0,
+            0,
+        );
+        self.gl.draw_arrays(WebGlRenderingContext::TRIANGLES, 0, 6);
+    }
+
+    /// Draws `source` over the current viewport combined with `reference` as
+    /// `overlay` says.
+    fn draw_overlay(
+        &self,
+        source: &RenderTarget,
+        reference: &WebGlTexture,
+        overlay: ReferenceOverlay,
+    ) {
+        let program = &self.overlay_program;
+        self.gl.use_program(Some(program));
+
+        self.gl.active_texture(WebGlRenderingContext::TEXTURE1);
+        self.gl
+            .bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(reference));
+        let reference_location = self.gl.get_uniform_location(program, "u_reference");
+        self.gl.uniform1i(reference_location.as_ref(), 1);
+
+        self.gl.active_texture(WebGlRenderingContext::TEXTURE0);
+        self.gl
+            .bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&source.texture));
+        let source_location = self.gl.get_uniform_location(program, "u_source");
+        self.gl.uniform1i(source_location.as_ref(), 0);
+
+        let mode_location = self.gl.get_uniform_location(program, "u_mode");
+        self.gl.uniform1i(mode_location.as_ref(), overlay.mode as i32);
+        let split_location = self.gl.get_uniform_location(program, "u_split");
+        self.gl.uniform1f(split_location.as_ref(), overlay.split);
+        let gain_location = self.gl.get_uniform_location(program, "u_gain");
+        self.gl.uniform1f(gain_location.as_ref(), overlay.gain);
+
+        self.gl
+            .bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&self.quad_buffer));
+        let position_location = self.gl.get_attrib_location(program, "a_position");
+        self.gl.enable_vertex_attrib_array(position_location as u32);
+        self.gl.vertex_attrib_pointer_with_i32(
+            position_location as u32,
+            2,
+            WebGlRenderingContext::FLOAT,
+            false,
+            0,
+            0,
+        );
+        self.gl.draw_arrays(WebGlRenderingContext::TRIANGLES, 0, 6);
+    }
+
+    fn clear_alpha(&self) -> f32 {
+        if self.transparent_background { 0.0 } else { 1.0 }
+    }
+
+    /// The clear color, with bars see-through while the background is
+    /// transparent.
+    fn canvas_clear_color(&self) -> [f32; 4] {
+        if self.transparent_background {
+            [0.0, 0.0, 0.0, 0.0]
+        } else {
+            self.clear_color
+        }
+    }
+
+    fn capture_png(&mut self, options: CaptureOptions) -> Result<Vec<u8>, RaytracerError> {
+        // The capture must show every enabled feature, so don't let render()
+        // fall back to a variant while the right one compiles
+        self.select_shader_variant(self.shader_features(&self.scene), true)?;
+
+        // Draw right before reading so the drawing buffer is still valid
+        let overlay = self.reference_overlay;
+        if !options.include_overlay {
+            self.reference_overlay = None;
+        }
+        let rendered = self.render();
+        self.reference_overlay = overlay;
+        rendered?;
+
+        let (content_x, content_y, content_width, content_height) = self.viewport.content_rect();
+        let x = content_x.round() as i32;
+        let y = (self.viewport.height as f32 - content_y - content_height).round() as i32;
+        let width = (content_width.round() as u32).max(1);
+        let height = (content_height.round() as u32).max(1);
+
+        let mut rgba = vec![0u8; (width * height * 4) as usize];
+        self.gl.read_pixels_with_opt_u8_array(
+            x,
+            y,
+            width as i32,
+            height as i32,
+            WebGlRenderingContext::RGBA,
+            WebGlRenderingContext::UNSIGNED_BYTE,
+            Some(&mut rgba),
+        )?;
+
+        let include_alpha = options.include_alpha.unwrap_or(self.transparent_background);
+        let pixels = png::from_readback(&rgba, width, include_alpha, options.color_space);
+
+        Ok(png::encode(
+            &pixels,
+            width,
+            height,
+            include_alpha,
+            options.color_space,
+        ))
+    }
+
+    /// Draws `scene` as seen from `camera` into the current framebuffer and
+    /// viewport. `origin` is the viewport's bottom-left corner in window
+    /// coordinates. Returns the number of lights that survived culling.
+    fn draw_scene(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        resolution: (f32, f32),
+        origin: (f32, f32),
+        time_s: f32,
+        output_mode: i32,
+    ) -> Result<usize, RaytracerError> {
+        // Use our raytracing program
+        self.gl.use_program(Some(&self.program));
+        self.gl.uniform1i(self.uniforms.output_mode.as_ref(), output_mode);
+        self.gl.uniform1i(
+            self.uniforms.transparent_background.as_ref(),
+            self.transparent_background as i32,
+        );
+
+        // Set uniforms
+        self.gl
+            .uniform2f(self.uniforms.resolution.as_ref(), resolution.0, resolution.1);
+        self.gl
+            .uniform2f(self.uniforms.viewport_origin.as_ref(), origin.0, origin.1);
+
+        // Camera uniforms are only re-sent once the camera has really moved
+        let camera_state = camera.state();
+        let uploaded = self.uploaded_camera.get();
+        if uploaded.is_none_or(|last| {
+            last.differs_from(
+                &camera_state,
+                self.camera_thresholds.0,
+                self.camera_thresholds.1,
+            )
+        }) {
+            let camera_pos = camera_state.position;
+            self.gl.uniform3f(
+                self.uniforms.camera_pos.as_ref(),
+                camera_pos.x,
+                camera_pos.y,
+                camera_pos.z,
+            );
+
+            // Replace the matrix with basis vectors
+            let forward = camera_state.forward;
+            let right = camera_state.right;
+            let up = camera_state.up;
+
+            self.gl.uniform3f(
+                self.uniforms.camera_forward.as_ref(),
+                forward.x,
+                forward.y,
+                forward.z,
+            );
+            self.gl
+                .uniform3f(self.uniforms.camera_right.as_ref(), right.x, right.y, right.z);
+            self.gl
+                .uniform3f(self.uniforms.camera_up.as_ref(), up.x, up.y, up.z);
+            self.gl.uniform1f(
+                self.uniforms.tan_half_fov.as_ref(),
+                (camera_state.fov * 0.5).tan(),
+            );
+            self.gl
+                .uniform1f(self.uniforms.aperture.as_ref(), camera_state.aperture);
+            self.gl.uniform1f(
+                self.uniforms.focus_distance.as_ref(),
+                camera_state.focus_distance,
+            );
+
+            self.uploaded_camera.set(Some(camera_state));
+        }
+
+        self.gl.uniform1f(self.uniforms.time.as_ref(), time_s);
+        self.image_textures
+            .bind(&self.gl, &self.uniforms.sphere_textures);
+        self.gl.uniform1f(self.uniforms.ambient.as_ref(), self.ambient);
+        self.gl.uniform1f(
+            self.uniforms.shadow_catcher_opacity.as_ref(),
+            self.shadow_catcher_opacity,
+        );
+        self.gl.uniform1i(
+            self.uniforms.texture_filtering.as_ref(),
+            self.texture_filtering as i32,
+        );
+        // Matches HitRecord.object_id, with type 0 for no selection
+        let (selected_type, selected_index) = self
+            .selected_object
+            .map_or((0.0, 0.0), |(object_type, index)| {
+                ((object_type.to_u32() + 1) as f32, index as f32)
+            });
+        self.gl.uniform2f(
+            self.uniforms.selected_object.as_ref(),
+            selected_type,
+            selected_index,
+        );
+
+        let quality = self.effective_quality();
+        self.gl
+            .uniform1i(self.uniforms.max_bounces.as_ref(), quality.max_bounces as i32);
+        self.gl.uniform1i(
+            self.uniforms.samples_per_pixel.as_ref(),
+            quality.samples_per_pixel as i32,
+        );
+        self.gl.uniform1i(self.uniforms.shadows.as_ref(), quality.shadows as i32);
+        self.gl.uniform1i(
+            self.uniforms.ao_samples.as_ref(),
+            quality.ambient_occlusion_samples as i32,
+        );
+        let soft_shadow_radius = if quality.soft_shadows { SOFT_SHADOW_RADIUS } else { 0.0 };
+        self.gl
+            .uniform1f(self.uniforms.soft_shadow_radius.as_ref(), soft_shadow_radius);
+        self.gl
+            .uniform1i(self.uniforms.volume_steps.as_ref(), quality.volume_steps as i32);
+        self.gl
+            .uniform1f(self.uniforms.exposure.as_ref(), self.exposure_ev.exp2());
+        self.gl
+            .uniform1f(self.uniforms.world_scale.as_ref(), scene.world_scale);
+
+        // Sub-pixel jitter for this frame from the Halton (2, 3) sequence
+        self.gl.uniform2f(
+            self.uniforms.sample_offset.as_ref(),
+            sampling::halton(self.frame_index, 2),
+            sampling::halton(self.frame_index, 3),
+        );
+        self.gl.uniform1f(
+            self.uniforms.frames_since_change.as_ref(),
+            self.frames_since_change as f32,
+        );
+
+        // Set scene uniforms (we'll pass scene data through uniforms for now)
+        let lights = lighting::select_lights(&scene.lights, camera, MAX_LIGHTS);
+        let light_count = lights.len();
+        // Previews belong to the live scene, not to thumbnails
+        let material_override = self
+            .material_preview
+            .filter(|_| std::ptr::eq(scene, &self.scene));
+        let packing = PackingOptions {
+            low_detail: self.active_lod > 0,
+            lights: Some(lights),
+            material_override,
+            camera_position: Some(camera_state.position),
+            data_texture: self.scene_data.is_some(),
+            light_block: self.light_block.is_some(),
+        };
+        let mut uploaded = scene.set_uniforms(&self.gl, &self.scene_uniforms, &packing)?;
+        if let Some(scene_data) = &self.scene_data {
+            let mut scene_data = scene_data.borrow_mut();
+            // Only the live scene is cached, with its BVH; thumbnails pack
+            // every time and build their own
+            let live = std::ptr::eq(scene, &self.scene);
+            let key = live.then(|| scene.pack_key(&packing));
+            if !key.as_ref().is_some_and(|key| scene_data.holds(key)) {
+                let data = if live {
+                    scene.pack_data(&packing, &mut self.bvh.borrow_mut(), js_sys::Date::now)
+                } else {
+                    scene.pack_data(&packing, &mut BvhCache::default(), js_sys::Date::now)
+                };
+                uploaded += scene_data.update(&self.gl, data, key)?;
+            }
+            scene_data.bind(&self.gl, &self.scene_uniforms);
+        }
+        if let Some(light_block) = &self.light_block {
+            let mut light_block = light_block.borrow_mut();
+            uploaded += light_block.update(&self.gl, scene.light_block(&packing).as_bytes());
+            light_block.bind(&self.gl, &self.program, "SceneLights");
+        }
+        self.uploaded_scene_bytes.set(uploaded);
+
+        // Bind quad buffer and draw
+        self.gl
+            .bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&self.quad_buffer));
+        let position_location = self.gl.get_attrib_location(&self.program, "a_position");
+        self.gl.enable_vertex_attrib_array(position_location as u32);
+        self.gl.vertex_attrib_pointer_with_i32(
+            position_location as u32,
+            2,
+            WebGlRenderingContext::FLOAT,
+            false,
+            0,
+            0,
+        );
+
+        self.gl.draw_arrays(WebGlRenderingContext::TRIANGLES, 0, 6);
+
+        Ok(light_count)
+    }
+
+    /// Draws into `target` and reads it back, bottom row first.
+    fn render_offscreen(
+        &self,
+        target: &RenderTarget,
+        scene: &Scene,
+        camera: &Camera,
+        output_mode: i32,
+    ) -> Result<Vec<u8>, RaytracerError> {
+        self.gl.bind_framebuffer(
+            WebGlRenderingContext::FRAMEBUFFER,
+            Some(&target.framebuffer),
+        );
+        self.gl
+            .viewport(0, 0, target.width as i32, target.height as i32);
+        self.gl.clear_color(0.0, 0.0, 0.0, self.clear_alpha());
+        self.gl.clear(WebGlRenderingContext::COLOR_BUFFER_BIT);
+
+        let time_s = (self.clock.now() / 1000.0) as f32;
+        self.draw_scene(
+            scene,
+            camera,
+            (target.width as f32, target.height as f32),
+            (0.0, 0.0),
+            time_s,
+            output_mode,
+        )?;
+        target.read_pixels(&self.gl)
+    }
+
+    /// Height of the first upward-facing plane, or 0 if there is none.
+    fn ground_level(&self) -> f32 {
+        self.scene
+            .planes
+            .iter()
+            .find(|plane| plane.normal.normalize().y > 0.9)
+            .map(|plane| plane.point.y)
+            .unwrap_or(0.0)
+    }
+
+    /// Replays the camera's move from `start` as a collision sweep when
+    /// collision is enabled.
+    fn constrain_camera_move(&mut self, start: Vec3) {
+        let Some(radius) = self.camera_collision else {
+            return;
+        };
+        let end = self.camera.position();
+        let resolved = collision::slide(&self.scene, start, end - start, radius);
+        if (resolved - end).length_squared() > 0.0 {
+            self.camera.set_position(resolved);
+        }
+    }
+
+    /// The material of an object about to be edited; fails if there is no
+    /// such object or it is locked.
+    fn editable_material(
+        &mut self,
+        object_type: ObjectType,
+        index: usize,
+    ) -> Result<&mut Material, RaytracerError> {
+        if self.scene.is_locked(object_type, index) == Some(true) {
+            return Err(RaytracerError::Locked {
+                kind: object_type.name(),
+                index,
+            });
+        }
+        let len = self.scene.object_count(object_type);
+        self.scene
+            .material_mut(object_type, index)
+            .ok_or(RaytracerError::IndexOutOfRange {
+                kind: object_type.name(),
+                index,
+                len,
+            })
+    }
+
+    /// The lock flag behind an `object_type` value of the JS API, which adds
+    /// lights to the primitive types.
+    fn lock_flag_mut(&mut self, object_type: u32, index: usize) -> Option<&mut bool> {
+        if object_type == LIGHT_OBJECT_TYPE {
+            self.scene.touch_light(index);
+            return self.scene.lights.get_mut(index).map(|light| &mut light.locked);
+        }
+        ObjectType::from_u32(object_type)
+            .and_then(|object_type| self.scene.locked_mut(object_type, index))
+    }
+
+    /// The camera ray through `(x, y)` in render-buffer pixels.
+    fn camera_ray(&self, x: f32, y: f32) -> Ray {
+        let (width, height) = self.viewport.render_size();
+        let direction = self
+            .camera
+            .get_ray_direction(x, y, width as f32, height as f32);
+        Ray::new(self.camera.position(), direction)
+    }
+
+    /// Nearest and farthest distances the shader's primary rays hit at.
+    fn ray_limits(&self) -> (f32, f32) {
+        let world_scale = self.scene.world_scale;
+        (intersect::RAY_EPSILON * world_scale, gbuffer::GBUFFER_FAR * world_scale)
+    }
+
+    /// Moves sphere `index` to where the camera ray through `(x, y)` crosses
+    /// the plane through `point` with unit `normal`.
+    fn drag_sphere(
+        &mut self,
+        index: usize,
+        x: f32,
+        y: f32,
+        point: Vec3,
+        normal: Vec3,
+    ) -> Option<Vec<f32>> {
+        if self.scene.spheres.get(index)?.meta.locked {
+            return None;
+        }
+        let ray = self.camera_ray(x, y);
+        let (t_min, t_max) = self.ray_limits();
+        let t = intersect::ray_plane_through(&ray, point, normal, t_min, t_max)?;
+        let center = ray.at(t);
+        self.scene.spheres[index].center = center;
+        self.scene.touch(ObjectType::Sphere, index);
+        Some(vec![center.x, center.y, center.z])
+    }
+
+    /// Runs `edit` on number `index` of `T`'s kind and records the change,
+    /// unless there is no such object or it is locked. Returns whether `edit`
+    /// ran.
+    fn with_unlocked_object<T: Editable>(
+        &mut self,
+        index: usize,
+        edit: impl FnOnce(&mut T),
+    ) -> bool {
+        match T::list_mut(&mut self.scene).get_mut(index) {
+            Some(object) if !object.locked() => {
+                edit(object);
+                T::touch(&mut self.scene, index);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Removes object `index` of `object_type` unless it is locked, dropping
+    /// a material preview on that type and keeping the selection on the same
+    /// object. Returns false if there is no such object or it is locked.
+    fn remove_unlocked(&mut self, object_type: ObjectType, index: usize) -> bool {
+        if self.scene.is_locked(object_type, index) != Some(false) {
+            return false;
+        }
+        self.scene.remove_object(object_type, index);
+        self.follow_selection_removal(object_type, index);
+        if self
+            .material_preview
+            .is_some_and(|p| p.object_type == object_type)
+        {
+            self.material_preview = None;
+        }
+        true
+    }
+
+    /// Keeps the selection on the same object after object `index` of
+    /// `object_type` is removed, or drops it if that was the one.
+    fn follow_selection_removal(&mut self, object_type: ObjectType, index: usize) {
+        if let Some((selected_type, selected_index)) = self.selected_object
+            && selected_type == object_type
+        {
+            if selected_index == index {
+                self.selected_object = None;
+            } else if selected_index > index {
+                self.selected_object = Some((object_type, selected_index - 1));
+            }
+        }
+    }
+
+    /// Hands the camera back from a flight or camera path, where it is now.
+    fn cancel_camera_motion(&mut self) {
+        self.camera_flight = None;
+        self.camera_path_playback = None;
+    }
+
+    /// Swaps in a new scene, dropping anything tied to the old one.
+    fn replace_scene(&mut self, mut scene: Scene) {
+        scene.continue_revisions(&self.scene);
+        self.scene_load = None;
+        self.material_preview = None;
+        self.selected_object = None;
+        if let Some(cycle) = self.day_night.take() {
+            // Only the ambient level lives outside the scene
+            let mut old_scene = std::mem::replace(&mut self.scene, scene);
+            cycle.restore(&mut old_scene, &mut self.ambient);
+        } else {
+            self.scene = scene;
+        }
+        self.apply_world_scale();
+    }
+
+    /// Derives the camera's clip planes and movement speeds from the scene's
+    /// world scale; the shader scales its ray offsets per draw.
+    fn apply_world_scale(&mut self) {
+        // Files can hold anything; fall back to meters
+        if !(self.scene.world_scale.is_finite() && self.scene.world_scale > 0.0) {
+            self.scene.world_scale = 1.0;
+        }
+        let world_scale = self.scene.world_scale;
+        self.camera.apply_world_scale(world_scale);
+        if let Some(controls) = self.controls.as_mut() {
+            controls.set_world_scale(world_scale);
+        }
+    }
+}
+
+/// Deserializes an optional options object; `undefined`/`null` give the defaults.
+fn options_from_js<T: DeserializeOwned + Default>(
+    value: &JsValue,
+    what: &'static str,
+) -> Result<T, RaytracerError> {
+    if value.is_undefined() || value.is_null() {
+        return Ok(T::default());
+    }
+
+    let json = js_sys::JSON::stringify(value)?.as_string().unwrap_or_default();
+    serde_json::from_str(&json)
+        .map_err(|e| RaytracerError::invalid_argument(what, e.to_string()))
+}
+
+/// Converts a serializable value into the equivalent plain JavaScript value.
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, RaytracerError> {
+    let json = serde_json::to_string(value)
+        .map_err(|e| RaytracerError::graphics(format!("Failed to serialize result: {}", e)))?;
+    Ok(js_sys::JSON::parse(&json)?)
+}
+
+/// A freshly read scene file, or an error listing every problem
+/// `Scene::validate` finds in it rather than just the first.
+fn validated(scene: Scene) -> Result<Scene, RaytracerError> {
+    let problems = scene.validate();
+    if problems.is_empty() {
+        Ok(scene)
+    } else {
+        Err(RaytracerError::InvalidScene { problems })
+    }
+}
+
+/// Reads the `object_type` number taken by the JS API.
+fn object_type_from_js(object_type: u32) -> Result<ObjectType, RaytracerError> {
+    ObjectType::from_u32(object_type).ok_or_else(|| {
+        RaytracerError::invalid_argument(
+            "object_type",
+            format!("unknown object type {}, expected 0 to 4, 6 or 7", object_type),
+        )
+    })
+}
+
+/// Euler angles in degrees from the JS API as the radians objects store,
+/// `None` unless all are finite.
+fn rotation_from_js(rx: f32, ry: f32, rz: f32) -> Option<Vec3> {
+    let rotation = Vec3::new(rx.to_radians(), ry.to_radians(), rz.to_radians());
+    rotation.is_finite().then_some(rotation)
+}
+
+/// The one material `add_sphere_grid` and `add_sphere_field` give every
+/// sphere when asked for a `material_type`.
+fn fixed_sphere_material(material_type: u32) -> Material {
+    let material_type = MaterialType::from_u32(material_type).unwrap_or_default();
+    Material::new(material_type, Vec3::new(0.8, 0.8, 0.8), 0.1, 1.5)
+}
+
+/// Where the JS API's `object_type` (lights included) and `index` point.
+fn object_location(object_type: u32, index: usize) -> Option<ObjectLocation> {
+    if object_type == LIGHT_OBJECT_TYPE {
+        return Some(ObjectLocation::Light(index));
+    }
+    ObjectType::from_u32(object_type).map(|object_type| ObjectLocation::Object(object_type, index))
+}
+
+/// `location` addressed the way the JS API addresses objects.
+fn object_ref(location: ObjectLocation) -> selection::ObjectRef {
+    match location {
+        ObjectLocation::Object(object_type, index) => selection::ObjectRef {
+            object_type: object_type.to_u32(),
+            index,
+        },
+        ObjectLocation::Light(index) => selection::ObjectRef {
+            object_type: LIGHT_OBJECT_TYPE,
+            index,
+        },
+    }
+}
+
+/// How the reference image is composited over the render.
+#[derive(Clone, Copy, Debug)]
+struct ReferenceOverlay {
+    /// 1 = wipe, 2 = difference, as in the overlay shader
+    mode: u32,
+    split: f32,
+    gain: f32,
+}
+
+/// Everything besides the camera and scene an accumulated image depends on;
+/// a frame drawn with a different key can't join the average.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct AccumulationKey {
+    size: (u32, u32),
+    quality: QualitySettings,
+    features: ShaderFeatures,
+    exposure_steps: i32,
+    ambient: f32,
+    shadow_catcher_opacity: f32,
+    texture_filtering: bool,
+    transparent_background: bool,
+    lod: u32,
+}
+
+/// What `get_gpu_budget` reports.
+#[derive(Serialize)]
+struct GpuBudget {
+    max_fragment_uniform_vectors: u32,
+    uniform_vectors: u32,
+    uploaded_scene_bytes: usize,
+    texture_bytes: usize,
+    limits: BTreeMap<&'static str, usize>,
+    counts: BTreeMap<&'static str, usize>,
+}
+
+/// What `pick_object` reports.
+#[derive(Serialize)]
+struct PickedObject {
+    kind: &'static str,
+    #[serde(rename = "type")]
+    object_type: u32,
+    index: usize,
+    distance: f32,
+    point: [f32; 3],
+}
+
+/// Options accepted by `Raytracer::new_with_options`.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct RaytracerOptions {
+    #[serde(flatten)]
+    context: ContextOptions,
+    transparent_background: bool,
+}
+
+/// Uniform locations of the raytracing program, looked up again whenever the
+/// shader variant changes.
+struct RaytracingUniforms {
+    resolution: Option<WebGlUniformLocation>,
+    viewport_origin: Option<WebGlUniformLocation>,
+    camera_pos: Option<WebGlUniformLocation>,
+    time: Option<WebGlUniformLocation>,
+    ambient: Option<WebGlUniformLocation>,
+    sample_offset: Option<WebGlUniformLocation>,
+    frames_since_change: Option<WebGlUniformLocation>,
+    output_mode: Option<WebGlUniformLocation>,
+    transparent_background: Option<WebGlUniformLocation>,
+    shadow_catcher_opacity: Option<WebGlUniformLocation>,
+    texture_filtering: Option<WebGlUniformLocation>,
+    selected_object: Option<WebGlUniformLocation>,
+    max_bounces: Option<WebGlUniformLocation>,
+    samples_per_pixel: Option<WebGlUniformLocation>,
+    shadows: Option<WebGlUniformLocation>,
+    ao_samples: Option<WebGlUniformLocation>,
+    soft_shadow_radius: Option<WebGlUniformLocation>,
+    volume_steps: Option<WebGlUniformLocation>,
+    exposure: Option<WebGlUniformLocation>,
+    world_scale: Option<WebGlUniformLocation>,
+    camera_forward: Option<WebGlUniformLocation>,
+    camera_right: Option<WebGlUniformLocation>,
+    camera_up: Option<WebGlUniformLocation>,
+    tan_half_fov: Option<WebGlUniformLocation>,
+    aperture: Option<WebGlUniformLocation>,
+    focus_distance: Option<WebGlUniformLocation>,
+    sphere_textures: [Option<WebGlUniformLocation>; MAX_IMAGE_TEXTURES],
+}
+
+impl RaytracingUniforms {
+    fn locate(gl: &WebGlRenderingContext, program: &WebGlProgram) -> Self {
+        let location = |name: &str| gl.get_uniform_location(program, name);
+        Self {
+            resolution: location("u_resolution"),
+            viewport_origin: location("u_viewport_origin"),
+            camera_pos: location("u_camera_pos"),
+            time: location("u_time"),
+            ambient: location("u_ambient"),
+            sample_offset: location("u_sample_offset"),
+            frames_since_change: location("u_frames_since_change"),
+            output_mode: location("u_output_mode"),
+            transparent_background: location("u_transparent_background"),
+            shadow_catcher_opacity: location("u_shadow_catcher_opacity"),
+            texture_filtering: location("u_texture_filtering"),
+            selected_object: location("u_selected_object"),
+            max_bounces: location("u_max_bounces"),
+            samples_per_pixel: location("u_samples_per_pixel"),
+            shadows: location("u_shadows"),
+            ao_samples: location("u_ao_samples"),
+            soft_shadow_radius: location("u_soft_shadow_radius"),
+            volume_steps: location("u_volume_steps"),
+            exposure: location("u_exposure"),
+            world_scale: location("u_world_scale"),
+            camera_forward: location("u_camera_forward"),
+            camera_right: location("u_camera_right"),
+            camera_up: location("u_camera_up"),
+            tan_half_fov: location("u_tan_half_fov"),
+            aperture: location("u_aperture"),
+            focus_distance: location("u_focus_distance"),
+            sphere_textures: std::array::from_fn(|slot| {
+                location(&format!("u_sphere_textures[{}]", slot))
+            }),
+        }
+    }
+}
@@ -1,14 +1,577 @@
-use crate::material::Material;
-use crate::math::Vec3;
+//! Scene model: primitives, lights and their JSON form.
+//!
+//! Array sizes are capped by the shader (`MAX_SPHERES` and friends, or
+//! `MAX_DATA_OBJECTS` and `MAX_DATA_TRIANGLES` when objects are read from the
+//! scene data texture); objects past the cap are kept in the scene but not
+//! drawn.
+
+#[cfg(feature = "webgl")]
+use crate::bvh::{BvhCache, BvhKey};
+use crate::camera::{CameraPath, CameraPreset};
+use crate::material::{Material, MaterialType};
+#[cfg(feature = "webgl")]
+use crate::material::{Texture, TextureSpace};
+use crate::math::{Aabb, Quat, Transform, Vec3};
+use crate::{blender, gltf, obj_export, ply, scene_binary};
+#[cfg(feature = "webgl")]
+use crate::scene_uniforms::{MaterialUniforms, SceneUniforms};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+#[cfg(feature = "webgl")]
 use wasm_bindgen::prelude::*;
-use web_sys::{console, WebGlProgram, WebGlRenderingContext};
+#[cfg(feature = "webgl")]
+use web_sys::{console, WebGlRenderingContext, WebGlUniformLocation};
+
+// Array sizes of the scene uniforms in the fragment shader
+pub const MAX_SPHERES: usize = 10;
+pub const MAX_PLANES: usize = 5;
+pub const MAX_BOXES: usize = 5;
+pub const MAX_CYLINDERS: usize = 5;
+pub const MAX_TRIANGLES: usize = 10;
+pub const MAX_CONES: usize = 5;
+pub const MAX_DISKS: usize = 8;
+pub const MAX_CSG: usize = 4;
+pub const MAX_LIGHTS: usize = 4;
+pub const MAX_VOLUMES: usize = 3;
+
+/// Version of the scene JSON format `Scene::to_json` writes. Files without a
+/// `version` key predate the marker and are version 1. A change to the format
+/// bumps this, adds the step that upgrades older documents to `MIGRATIONS`
+/// and saves a file in the new version as `fixtures/scenes/v<n>.json`.
+pub const SCENE_VERSION: u32 = 1;
+
+/// `MIGRATIONS[n]` rewrites a version `n + 1` document into version `n + 2`.
+const MIGRATIONS: [fn(&mut Value); SCENE_VERSION as usize - 1] = [];
+
+// Layout of the scene data texture, see `Scene::pack_data`
+pub const DATA_TEXELS: usize = 10;
+pub const DATA_OBJECTS_PER_ROW: usize = 128;
+pub const MAX_DATA_OBJECTS: usize = 256;
+/// Triangles go through a BVH in the data texture, so far more of them fit
+pub const MAX_DATA_TRIANGLES: usize = 16384;
+
+/// Error returned when scene or mesh data can't be parsed.
+#[derive(Clone, Debug)]
+pub struct SceneError(String);
+
+impl SceneError {
+    pub(crate) fn new(message: impl Into<String>) -> Self {
+        Self(message.into())
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+/// A value that deserializes but that the renderer can't draw sensibly, such
+/// as a negative radius or a NaN position. See [`Scene::validate`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SceneProblem {
+    /// `sphere`, `plane`, `box`, `cylinder`, `triangle`, `cone`, `disk`,
+    /// `light` or `volume`
+    pub kind: &'static str,
+    pub index: usize,
+    /// The field within the object, e.g. `radius` or `material.ior`
+    pub field: &'static str,
+    /// Where the value sits in the scene JSON, e.g. `spheres[2].radius`
+    pub path: String,
+    /// The offending value. JSON has no NaN or infinity, so those are `null`.
+    pub value: Value,
+    pub message: &'static str,
+}
+
+impl fmt::Display for SceneProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}, got {}", self.path, self.message, self.value)
+    }
+}
+
+/// Checks of an object's values that deserializing doesn't make.
+pub trait Validate {
+    /// The object's kind, as in [`SceneProblem::kind`].
+    const KIND: &'static str;
+    /// The key the scene JSON lists objects of this kind under.
+    const KEY: &'static str;
+
+    fn check(&self, check: &mut ObjectCheck);
+
+    /// The problems of this object, taken to be number `index` of its kind.
+    fn problems(&self, index: usize) -> Vec<SceneProblem> {
+        let mut check = ObjectCheck {
+            kind: Self::KIND,
+            key: Self::KEY,
+            index,
+            problems: Vec::new(),
+        };
+        self.check(&mut check);
+        check.problems
+    }
+}
+
+/// The problems found in one object, see [`Validate`].
+pub struct ObjectCheck {
+    kind: &'static str,
+    key: &'static str,
+    index: usize,
+    problems: Vec<SceneProblem>,
+}
+
+impl ObjectCheck {
+    fn report(&mut self, field: &'static str, value: impl Serialize, message: &'static str) {
+        self.problems.push(SceneProblem {
+            kind: self.kind,
+            index: self.index,
+            field,
+            path: format!("{}[{}].{}", self.key, self.index, field),
+            value: serde_json::to_value(value).unwrap_or(Value::Null),
+            message,
+        });
+    }
+
+    fn finite(&mut self, field: &'static str, value: Vec3) {
+        if !value.is_finite() {
+            self.report(field, value, "must be finite");
+        }
+    }
+
+    fn direction(&mut self, field: &'static str, value: Vec3) {
+        if !value.is_finite() {
+            self.report(field, value, "must be finite");
+        } else if value.length_squared() == 0.0 {
+            self.report(field, value, "must not have zero length");
+        }
+    }
+
+    fn positive(&mut self, field: &'static str, value: f32) {
+        if !value.is_finite() {
+            self.report(field, value, "must be finite");
+        } else if value <= 0.0 {
+            self.report(field, value, "must be positive");
+        }
+    }
+
+    fn non_negative(&mut self, field: &'static str, value: f32) {
+        if !value.is_finite() {
+            self.report(field, value, "must be finite");
+        } else if value < 0.0 {
+            self.report(field, value, "must not be negative");
+        }
+    }
+
+    fn scale(&mut self, field: &'static str, value: Vec3) {
+        if !value.is_finite() {
+            self.report(field, value, "must be finite");
+        } else if value.x <= 0.0 || value.y <= 0.0 || value.z <= 0.0 {
+            self.report(field, value, "must be positive");
+        }
+    }
+
+    fn material(&mut self, material: &Material) {
+        self.finite("material.albedo", material.albedo);
+        if !(0.0..=1.0).contains(&material.roughness) {
+            self.report("material.roughness", material.roughness, "must be between 0 and 1");
+        }
+        // Only dielectrics refract; the others leave the IOR unused
+        if matches!(material.material_type, MaterialType::Dielectric)
+            && !(material.ior.is_finite() && material.ior >= 1.0)
+        {
+            self.report("material.ior", material.ior, "must be at least 1 for a dielectric");
+        }
+    }
+}
+
+impl Validate for Sphere {
+    const KIND: &'static str = "sphere";
+    const KEY: &'static str = "spheres";
+
+    fn check(&self, check: &mut ObjectCheck) {
+        check.finite("center", self.center);
+        check.positive("radius", self.radius);
+        check.scale("scale", self.scale);
+        check.material(&self.material);
+    }
+}
+
+impl Validate for Plane {
+    const KIND: &'static str = "plane";
+    const KEY: &'static str = "planes";
+
+    fn check(&self, check: &mut ObjectCheck) {
+        check.finite("point", self.point);
+        check.direction("normal", self.normal);
+        check.material(&self.material);
+    }
+}
+
+impl Validate for Box {
+    const KIND: &'static str = "box";
+    const KEY: &'static str = "boxes";
+
+    fn check(&self, check: &mut ObjectCheck) {
+        check.finite("center", self.center);
+        let size = self.size;
+        if !size.is_finite() {
+            check.report("size", size, "must be finite");
+        } else if size.x < 0.0 || size.y < 0.0 || size.z < 0.0 {
+            check.report("size", size, "must not be negative");
+        }
+        check.finite("rotation", self.rotation);
+        check.scale("scale", self.scale);
+        check.material(&self.material);
+    }
+}
+
+impl Validate for Cylinder {
+    const KIND: &'static str = "cylinder";
+    const KEY: &'static str = "cylinders";
+
+    fn check(&self, check: &mut ObjectCheck) {
+        check.finite("base", self.base);
+        check.direction("axis", self.axis);
+        check.positive("radius", self.radius);
+        check.finite("rotation", self.rotation);
+        check.scale("scale", self.scale);
+        check.material(&self.material);
+    }
+}
+
+impl Validate for Triangle {
+    const KIND: &'static str = "triangle";
+    const KEY: &'static str = "triangles";
+
+    fn check(&self, check: &mut ObjectCheck) {
+        check.finite("v0", self.v0);
+        check.finite("v1", self.v1);
+        check.finite("v2", self.v2);
+        check.material(&self.material);
+    }
+}
+
+impl Validate for Cone {
+    const KIND: &'static str = "cone";
+    const KEY: &'static str = "cones";
+
+    fn check(&self, check: &mut ObjectCheck) {
+        check.finite("apex", self.apex);
+        check.direction("axis", self.axis);
+        check.positive("radius", self.radius);
+        check.material(&self.material);
+    }
+}
+
+impl Validate for Disk {
+    const KIND: &'static str = "disk";
+    const KEY: &'static str = "disks";
+
+    fn check(&self, check: &mut ObjectCheck) {
+        check.finite("center", self.center);
+        check.direction("normal", self.normal);
+        check.positive("radius", self.radius);
+        check.non_negative("inner_radius", self.inner_radius);
+        if self.inner_radius >= self.radius {
+            check.report("inner_radius", self.inner_radius, "must be less than the radius");
+        }
+        check.material(&self.material);
+    }
+}
+
+impl Validate for Light {
+    const KIND: &'static str = "light";
+    const KEY: &'static str = "lights";
+
+    fn check(&self, check: &mut ObjectCheck) {
+        check.finite("position", self.position);
+        check.finite("color", self.color);
+        check.non_negative("intensity", self.intensity);
+    }
+}
+
+impl Validate for Volume {
+    const KIND: &'static str = "volume";
+    const KEY: &'static str = "volumes";
+
+    fn check(&self, check: &mut ObjectCheck) {
+        check.finite("center", self.center);
+        check.positive("radius", self.radius);
+        check.non_negative("density", self.density);
+        check.finite("color", self.color);
+    }
+}
+
+/// Objects edited in place one at a time, each kind kept in its own list
+/// on [`Scene`] and carrying a lock flag.
+pub trait Editable: Sized {
+    fn list_mut(scene: &mut Scene) -> &mut Vec<Self>;
+
+    fn locked(&self) -> bool;
+
+    /// Records a change to number `index` of this kind.
+    fn touch(scene: &mut Scene, index: usize);
+}
+
+impl Editable for Sphere {
+    fn list_mut(scene: &mut Scene) -> &mut Vec<Self> {
+        &mut scene.spheres
+    }
+
+    fn locked(&self) -> bool {
+        self.meta.locked
+    }
+
+    fn touch(scene: &mut Scene, index: usize) {
+        scene.touch(ObjectType::Sphere, index);
+    }
+}
+
+impl Editable for Plane {
+    fn list_mut(scene: &mut Scene) -> &mut Vec<Self> {
+        &mut scene.planes
+    }
+
+    fn locked(&self) -> bool {
+        self.meta.locked
+    }
+
+    fn touch(scene: &mut Scene, index: usize) {
+        scene.touch(ObjectType::Plane, index);
+    }
+}
+
+impl Editable for Box {
+    fn list_mut(scene: &mut Scene) -> &mut Vec<Self> {
+        &mut scene.boxes
+    }
+
+    fn locked(&self) -> bool {
+        self.meta.locked
+    }
+
+    fn touch(scene: &mut Scene, index: usize) {
+        scene.touch(ObjectType::Box, index);
+    }
+}
+
+impl Editable for Cylinder {
+    fn list_mut(scene: &mut Scene) -> &mut Vec<Self> {
+        &mut scene.cylinders
+    }
+
+    fn locked(&self) -> bool {
+        self.meta.locked
+    }
+
+    fn touch(scene: &mut Scene, index: usize) {
+        scene.touch(ObjectType::Cylinder, index);
+    }
+}
+
+impl Editable for Triangle {
+    fn list_mut(scene: &mut Scene) -> &mut Vec<Self> {
+        &mut scene.triangles
+    }
+
+    fn locked(&self) -> bool {
+        self.meta.locked
+    }
+
+    fn touch(scene: &mut Scene, index: usize) {
+        scene.touch(ObjectType::Triangle, index);
+    }
+}
+
+impl Editable for Cone {
+    fn list_mut(scene: &mut Scene) -> &mut Vec<Self> {
+        &mut scene.cones
+    }
+
+    fn locked(&self) -> bool {
+        self.meta.locked
+    }
+
+    fn touch(scene: &mut Scene, index: usize) {
+        scene.touch(ObjectType::Cone, index);
+    }
+}
+
+impl Editable for Disk {
+    fn list_mut(scene: &mut Scene) -> &mut Vec<Self> {
+        &mut scene.disks
+    }
+
+    fn locked(&self) -> bool {
+        self.meta.locked
+    }
+
+    fn touch(scene: &mut Scene, index: usize) {
+        scene.touch(ObjectType::Disk, index);
+    }
+}
+
+impl Editable for Light {
+    fn list_mut(scene: &mut Scene) -> &mut Vec<Self> {
+        &mut scene.lights
+    }
+
+    fn locked(&self) -> bool {
+        self.locked
+    }
+
+    fn touch(scene: &mut Scene, index: usize) {
+        scene.touch_light(index);
+    }
+}
+
+/// Primitive categories addressable from JavaScript by number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObjectType {
+    Sphere,
+    Plane,
+    Box,
+    Cylinder,
+    Triangle,
+    Cone,
+    Disk,
+}
+
+impl ObjectType {
+    pub const ALL: [ObjectType; 7] = [
+        ObjectType::Sphere,
+        ObjectType::Plane,
+        ObjectType::Box,
+        ObjectType::Cylinder,
+        ObjectType::Triangle,
+        ObjectType::Cone,
+        ObjectType::Disk,
+    ];
+
+    pub fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(ObjectType::Sphere),
+            1 => Some(ObjectType::Plane),
+            2 => Some(ObjectType::Box),
+            3 => Some(ObjectType::Cylinder),
+            4 => Some(ObjectType::Triangle),
+            6 => Some(ObjectType::Cone),
+            7 => Some(ObjectType::Disk),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ObjectType::Sphere => "sphere",
+            ObjectType::Plane => "plane",
+            ObjectType::Box => "box",
+            ObjectType::Cylinder => "cylinder",
+            ObjectType::Triangle => "triangle",
+            ObjectType::Cone => "cone",
+            ObjectType::Disk => "disk",
+        }
+    }
+
+    pub fn to_u32(self) -> u32 {
+        match self {
+            ObjectType::Sphere => 0,
+            ObjectType::Plane => 1,
+            ObjectType::Box => 2,
+            ObjectType::Cylinder => 3,
+            ObjectType::Triangle => 4,
+            // 5 stands for lights in the JS API
+            ObjectType::Cone => 6,
+            ObjectType::Disk => 7,
+        }
+    }
+
+    /// Position in `ALL`, for arrays with an entry per type.
+    pub fn ordinal(self) -> usize {
+        ObjectType::ALL.iter().position(|&t| t == self).unwrap_or(0)
+    }
+
+    /// Id of object `index` of this type: `(type number + 1) << 16 | index`.
+    /// It names the object in light links and segmentation masks.
+    pub fn object_id(self, index: usize) -> u32 {
+        (self.to_u32() + 1) << 16 | index as u32
+    }
+
+    /// The object type and index an `object_id` refers to.
+    pub fn from_object_id(id: u32) -> Option<(Self, usize)> {
+        let object_type = Self::from_u32((id >> 16).checked_sub(1)?)?;
+        Some((object_type, (id & 0xffff) as usize))
+    }
+
+    /// How many objects of this type the shader draws.
+    pub fn max_count(self) -> usize {
+        match self {
+            ObjectType::Sphere => MAX_SPHERES,
+            ObjectType::Plane => MAX_PLANES,
+            ObjectType::Box => MAX_BOXES,
+            ObjectType::Cylinder => MAX_CYLINDERS,
+            ObjectType::Triangle => MAX_TRIANGLES,
+            ObjectType::Cone => MAX_CONES,
+            ObjectType::Disk => MAX_DISKS,
+        }
+    }
+
+    /// How many objects of this type are drawn, with objects read from the
+    /// scene data texture or from uniforms.
+    pub fn capacity(self, data_texture: bool) -> usize {
+        if data_texture && self == ObjectType::Triangle {
+            MAX_DATA_TRIANGLES
+        } else if data_texture {
+            MAX_DATA_OBJECTS
+        } else {
+            self.max_count()
+        }
+    }
+}
+
+/// Bookkeeping shared by every kind of object, flattened into its JSON
+/// next to the geometry and material.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ObjectMeta {
+    /// Locked objects refuse edits and removal from the editing API
+    #[serde(default)]
+    pub locked: bool,
+    /// Objects with a higher priority take the uniform slots first when
+    /// there are more objects of this kind than the shader holds
+    #[serde(default)]
+    pub priority: i32,
+    /// Stable id assigned by the scene when the object is added, 0 before.
+    /// Unlike the index it survives the removal of other objects.
+    #[serde(default)]
+    pub id: u32,
+    /// Label for finding the object again, such as its name in the
+    /// program it was imported from. Need not be unique.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    /// Scene revision of the last change, see [`Scene::changes_since`]
+    #[serde(skip)]
+    pub revision: u32,
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Sphere {
     pub center: Vec3,
     pub radius: f32,
+    /// Stretches the sphere along each axis into an ellipsoid with semi-axes
+    /// `radius * scale`
+    #[serde(default = "unit_scale", skip_serializing_if = "is_unit_scale")]
+    pub scale: Vec3,
     pub material: Material,
+    #[serde(flatten)]
+    pub meta: ObjectMeta,
+    /// Image texture slot sampled for the albedo. The image lives on the GPU
+    /// only, so it isn't saved with the scene.
+    #[serde(skip)]
+    pub image_texture: Option<usize>,
 }
 
 impl Sphere {
@@ -16,9 +579,52 @@ impl Sphere {
         Self {
             center,
             radius,
+            scale: Vec3::one(),
             material,
+            meta: ObjectMeta::default(),
+            image_texture: None,
         }
     }
+
+    /// Whether `scale` stretches the sphere at all.
+    pub fn is_ellipsoid(&self) -> bool {
+        !is_unit_scale(&self.scale)
+    }
+
+    /// Radius of a sphere around the center enclosing the ellipsoid.
+    pub fn bounding_radius(&self) -> f32 {
+        self.radius * self.scale.x.max(self.scale.y).max(self.scale.z)
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        let extent = self.scale * self.radius;
+        Aabb::new(self.center - extent, self.center + extent)
+    }
+}
+
+fn unit_scale() -> Vec3 {
+    Vec3::one()
+}
+
+fn is_unit_scale(scale: &Vec3) -> bool {
+    *scale == Vec3::one()
+}
+
+fn is_zero_rotation(rotation: &Vec3) -> bool {
+    *rotation == Vec3::zero()
+}
+
+/// The transform of an object with Euler `rotation` and `scale` about
+/// `pivot`, `None` while both leave it as it is.
+fn object_transform(pivot: Vec3, rotation: Vec3, scale: Vec3) -> Option<Transform> {
+    if is_zero_rotation(&rotation) && is_unit_scale(&scale) {
+        return None;
+    }
+    Some(Transform {
+        pivot,
+        rotation: Quat::from_euler(rotation),
+        scale,
+    })
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -26,6 +632,8 @@ pub struct Plane {
     pub point: Vec3,
     pub normal: Vec3,
     pub material: Material,
+    #[serde(flatten)]
+    pub meta: ObjectMeta,
 }
 
 impl Plane {
@@ -34,6 +642,7 @@ impl Plane {
             point,
             normal: normal.normalize(),
             material,
+            meta: ObjectMeta::default(),
         }
     }
 }
@@ -42,7 +651,16 @@ impl Plane {
 pub struct Box {
     pub center: Vec3,
     pub size: Vec3, // width, height, depth
+    /// Turn about the center, as XYZ Euler angles in radians (see
+    /// `Quat::from_euler`)
+    #[serde(default, skip_serializing_if = "is_zero_rotation")]
+    pub rotation: Vec3,
+    /// Per-axis stretch of `size`, along the box's own axes
+    #[serde(default = "unit_scale", skip_serializing_if = "is_unit_scale")]
+    pub scale: Vec3,
     pub material: Material,
+    #[serde(flatten)]
+    pub meta: ObjectMeta,
 }
 
 impl Box {
@@ -50,9 +668,24 @@ impl Box {
         Self {
             center,
             size,
+            rotation: Vec3::zero(),
+            scale: Vec3::one(),
             material,
+            meta: ObjectMeta::default(),
         }
     }
+
+    /// Where the box's own space, in which it is axis-aligned with `size`,
+    /// lies in the world. `None` for boxes without a rotation or scale.
+    pub fn transform(&self) -> Option<Transform> {
+        object_transform(self.center, self.rotation, self.scale)
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        let half = self.size * 0.5;
+        let bounds = Aabb::new(self.center - half, self.center + half);
+        self.transform().map_or(bounds, |t| t.bounds(&bounds))
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -60,7 +693,18 @@ pub struct Cylinder {
     pub base: Vec3,
     pub axis: Vec3, // direction and length
     pub radius: f32,
+    /// Turn about the middle of the axis, as XYZ Euler angles in radians
+    /// (see `Quat::from_euler`)
+    #[serde(default, skip_serializing_if = "is_zero_rotation")]
+    pub rotation: Vec3,
+    /// Per-axis stretch about the middle of the axis, along the world axes
+    /// before the rotation. Unequal factors across the axis make the
+    /// cylinder elliptic.
+    #[serde(default = "unit_scale", skip_serializing_if = "is_unit_scale")]
+    pub scale: Vec3,
     pub material: Material,
+    #[serde(flatten)]
+    pub meta: ObjectMeta,
 }
 
 impl Cylinder {
@@ -69,9 +713,126 @@ impl Cylinder {
             base,
             axis,
             radius,
+            rotation: Vec3::zero(),
+            scale: Vec3::one(),
             material,
+            meta: ObjectMeta::default(),
         }
     }
+
+    /// Where the cylinder given by `base`, `axis` and `radius` is moved by
+    /// its rotation and scale. `None` for cylinders without either.
+    pub fn transform(&self) -> Option<Transform> {
+        object_transform(self.base + self.axis * 0.5, self.rotation, self.scale)
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        // The end caps are disks perpendicular to the axis; along each world
+        // axis a disk reaches radius * sqrt(1 - a²) from its center
+        let a = self.axis.normalize();
+        let extent = Vec3::new(
+            self.radius * (1.0 - a.x * a.x).max(0.0).sqrt(),
+            self.radius * (1.0 - a.y * a.y).max(0.0).sqrt(),
+            self.radius * (1.0 - a.z * a.z).max(0.0).sqrt(),
+        );
+        let top = self.base + self.axis;
+        let bounds = Aabb::from_points(&[
+            self.base - extent,
+            self.base + extent,
+            top - extent,
+            top + extent,
+        ]);
+        self.transform().map_or(bounds, |t| t.bounds(&bounds))
+    }
+}
+
+/// A cone closed by a flat base: its tip at `apex`, widening along `axis`
+/// to a disk of `radius` around `apex + axis`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Cone {
+    pub apex: Vec3,
+    pub axis: Vec3, // direction and height, apex to base
+    pub radius: f32, // of the base
+    pub material: Material,
+    #[serde(flatten)]
+    pub meta: ObjectMeta,
+}
+
+impl Cone {
+    pub fn new(apex: Vec3, axis: Vec3, radius: f32, material: Material) -> Self {
+        Self {
+            apex,
+            axis,
+            radius,
+            material,
+            meta: ObjectMeta::default(),
+        }
+    }
+
+    /// A cone whose side makes `half_angle` radians with its axis.
+    pub fn from_half_angle(apex: Vec3, axis: Vec3, half_angle: f32, material: Material) -> Self {
+        Self::new(apex, axis, axis.length() * half_angle.tan(), material)
+    }
+
+    /// Angle between the axis and the side, in radians.
+    pub fn half_angle(&self) -> f32 {
+        self.radius.atan2(self.axis.length())
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        // The apex and the base disk, bounded as a cylinder's end caps are
+        let a = self.axis.normalize();
+        let extent = Vec3::new(
+            self.radius * (1.0 - a.x * a.x).max(0.0).sqrt(),
+            self.radius * (1.0 - a.y * a.y).max(0.0).sqrt(),
+            self.radius * (1.0 - a.z * a.z).max(0.0).sqrt(),
+        );
+        let base = self.apex + self.axis;
+        Aabb::from_points(&[self.apex, base - extent, base + extent])
+    }
+}
+
+/// A flat disk of `radius` around `center`, facing along `normal`. A
+/// positive `inner_radius` cuts a hole in the middle, making it a ring.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Disk {
+    pub center: Vec3,
+    pub normal: Vec3,
+    pub radius: f32,
+    #[serde(default)]
+    pub inner_radius: f32,
+    pub material: Material,
+    #[serde(flatten)]
+    pub meta: ObjectMeta,
+}
+
+impl Disk {
+    pub fn new(
+        center: Vec3,
+        normal: Vec3,
+        radius: f32,
+        inner_radius: f32,
+        material: Material,
+    ) -> Self {
+        Self {
+            center,
+            normal: normal.normalize(),
+            radius,
+            inner_radius,
+            material,
+            meta: ObjectMeta::default(),
+        }
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        let n = self.normal.normalize();
+        let extent = Vec3::new(
+            self.radius * (1.0 - n.x * n.x).max(0.0).sqrt(),
+            self.radius * (1.0 - n.y * n.y).max(0.0).sqrt(),
+            self.radius * (1.0 - n.z * n.z).max(0.0).sqrt(),
+        );
+        Aabb::new(self.center - extent, self.center + extent)
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -80,6 +841,8 @@ pub struct Triangle {
     pub v1: Vec3,
     pub v2: Vec3,
     pub material: Material,
+    #[serde(flatten)]
+    pub meta: ObjectMeta,
 }
 
 impl Triangle {
@@ -89,6 +852,7 @@ impl Triangle {
             v1,
             v2,
             material,
+            meta: ObjectMeta::default(),
         }
     }
     
@@ -97,6 +861,14 @@ impl Triangle {
         let edge2 = self.v2 - self.v0;
         edge1.cross(&edge2).normalize()
     }
+
+    pub fn bounds(&self) -> Aabb {
+        Aabb::from_points(&[self.v0, self.v1, self.v2])
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.v0 + self.v1 + self.v2) * (1.0 / 3.0)
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -105,81 +877,314 @@ pub struct Mesh {
     pub name: String,
     pub center: Vec3,
     pub scale: f32,
+    // Decimated stand-in used when the renderer drops to low detail
+    #[serde(default)]
+    pub low_detail: Vec<Triangle>,
+    /// Units per meter declared by the file, if it said
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub world_scale: Option<f32>,
 }
 
-impl Mesh {
-    pub fn from_blender_obj(obj_data: &str, material: Material, name: String) -> Result<Self, JsValue> {
-        let mut vertices: Vec<Vec3> = Vec::new();
-        let mut triangles: Vec<Triangle> = Vec::new();
-        
-        console::log_1(&format!("Parsing OBJ data: {} lines", obj_data.lines().count()).into());
-        
-        for line in obj_data.lines() {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.is_empty() { continue; }
-            
-            match parts[0] {
-                "v" => {
-                    // Vertex
-                    if parts.len() >= 4 {
-                        let x: f32 = parts[1].parse().map_err(|_| JsValue::from_str("Invalid vertex x"))?;
-                        let y: f32 = parts[2].parse().map_err(|_| JsValue::from_str("Invalid vertex y"))?;
-                        let z: f32 = parts[3].parse().map_err(|_| JsValue::from_str("Invalid vertex z"))?;
-                        vertices.push(Vec3::new(x, y, z));
-                    }
-                },
-                "f" => {
-                    // Face (assuming triangular faces)
-                    if parts.len() >= 4 {
-                        // Parse vertex indices (OBJ is 1-indexed)
-                        let i0: usize = parts[1].split('/').next().unwrap().parse::<usize>().map_err(|_| JsValue::from_str("Invalid face index"))? - 1;
-                        let i1: usize = parts[2].split('/').next().unwrap().parse::<usize>().map_err(|_| JsValue::from_str("Invalid face index"))? - 1;
-                        let i2: usize = parts[3].split('/').next().unwrap().parse::<usize>().map_err(|_| JsValue::from_str("Invalid face index"))? - 1;
-                        
-                        if i0 < vertices.len() && i1 < vertices.len() && i2 < vertices.len() {
-                            let v0 = vertices[i0];
-                            let v1 = vertices[i1];
-                            let v2 = vertices[i2];
-                            
-                            triangles.push(Triangle::new(v0, v1, v2, material.clone()));
-                        }
-                    }
-                },
-                _ => {} // Ignore other OBJ commands
-            }
+/// Keep every Nth triangle of a mesh for its low-detail representation.
+pub const LOD_TRIANGLE_STRIDE: usize = 4;
+
+/// Where an imported mesh's triangles live in `Scene::triangles`, plus its
+/// low-detail replacement.
+#[derive(Clone, Debug)]
+pub struct MeshLod {
+    pub name: String,
+    pub first_triangle: usize,
+    pub triangle_count: usize,
+    pub low_detail: Vec<Triangle>,
+}
+
+/// Per-frame choices made when packing the scene into uniforms.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PackingOptions {
+    /// Upload each mesh's low-detail triangles instead of the full set
+    pub low_detail: bool,
+    /// Indices of the lights to upload, in slot order; `None` uploads the
+    /// first `MAX_LIGHTS`
+    pub lights: Option<Vec<usize>>,
+    /// Material shown in place of one object's own, without changing the scene
+    pub material_override: Option<MaterialOverride>,
+    /// Breaks priority ties when a kind of object is over its uniform cap:
+    /// nearer objects win
+    pub camera_position: Option<Vec3>,
+    /// Objects are read from the scene data texture, so `set_uniforms`
+    /// leaves them out
+    pub data_texture: bool,
+    /// Lights and volumes go through the `SceneLights` uniform block (see
+    /// `LightBlock`), so `set_uniforms` leaves them out
+    pub light_block: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MaterialOverride {
+    pub object_type: ObjectType,
+    pub index: usize,
+    pub material: Material,
+}
+
+impl PackingOptions {
+    /// The material to upload for object `index` of `object_type`.
+    #[cfg(feature = "webgl")]
+    fn material<'a>(
+        &'a self,
+        object_type: ObjectType,
+        index: usize,
+        own: &'a Material,
+    ) -> &'a Material {
+        match &self.material_override {
+            Some(o) if o.object_type == object_type && o.index == index => &o.material,
+            _ => own,
+        }
+    }
+}
+
+/// Objects packed for the scene data texture by `Scene::pack_data`: one row
+/// of `DATA_TEXELS` RGBA float texels per object, spheres first, then planes,
+/// boxes, cylinders, triangles, cones and disks, each kind in slot order
+/// but triangles in BVH leaf order, then the BVH nodes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SceneData {
+    pub texels: Vec<f32>,
+    /// Rows per kind of object, in `ObjectType::ALL` order
+    pub counts: [usize; 7],
+    pub bvh_nodes: usize,
+}
+
+impl SceneData {
+    pub fn rows(&self) -> usize {
+        self.counts.iter().sum::<usize>() + self.bvh_nodes
+    }
+}
+
+/// What a [`SceneData`] was packed from, see `Scene::pack_key`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackKey {
+    revision: u32,
+    options: PackingOptions,
+}
+
+/// The lights and volumes in the std140 layout of the shader's `SceneLights`
+/// uniform block, uploaded in one call on WebGL2. Padding is spelled out so
+/// the bytes match the block's offsets.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LightBlock {
+    lights: [BlockLight; MAX_LIGHTS],
+    volumes: [BlockVolume; MAX_VOLUMES],
+    light_count: i32,
+    volume_count: i32,
+    _pad: [i32; 2],
+}
+
+/// `Light` in std140: a vec3 takes 16 bytes unless a float follows it.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct BlockLight {
+    position: [f32; 3],
+    _pad: f32,
+    color: [f32; 3],
+    intensity: f32,
+}
+
+/// `Volume` in std140: `color` starts on a 16-byte boundary.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct BlockVolume {
+    center: [f32; 3],
+    radius: f32,
+    density: f32,
+    _pad: [f32; 3],
+    color: [f32; 3],
+    _pad_end: f32,
+}
+
+impl LightBlock {
+    pub fn as_bytes(&self) -> &[u8] {
+        // SAFETY: all fields are 4-byte numbers with the padding spelled out,
+        // so every byte of the struct is initialized
+        unsafe {
+            std::slice::from_raw_parts(
+                (self as *const Self).cast::<u8>(),
+                std::mem::size_of::<Self>(),
+            )
+        }
+    }
+}
+
+impl Mesh {
+    /// Reads the vertices (`v`) and faces (`f`) of an OBJ file, ignoring
+    /// normals, texture coordinates and everything else. Faces with more
+    /// than three corners are split into triangles, and negative indices
+    /// count back from the last vertex read. A face that refers to a vertex
+    /// the file hasn't defined fails with the line number.
+    pub fn from_blender_obj(obj_data: &str, material: Material, name: String) -> Result<Self, SceneError> {
+        let mut vertices: Vec<Vec3> = Vec::new();
+        let mut triangles: Vec<Triangle> = Vec::new();
+        let mut world_scale = None;
+        
+        #[cfg(feature = "webgl")]
+        console::log_1(&format!("Parsing OBJ data: {} lines", obj_data.lines().count()).into());
+        
+        for (line_index, line) in obj_data.lines().enumerate() {
+            let line_number = line_index + 1;
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.is_empty() { continue; }
+            
+            match parts[0] {
+                "v" if parts.len() >= 4 => {
+                    let coordinate = |part: &str| {
+                        part.parse::<f32>().map_err(|_| {
+                            SceneError::new(format!(
+                                "Line {}: invalid vertex coordinate '{}'",
+                                line_number, part
+                            ))
+                        })
+                    };
+                    let x = coordinate(parts[1])?;
+                    let y = coordinate(parts[2])?;
+                    let z = coordinate(parts[3])?;
+                    vertices.push(Vec3::new(x, y, z));
+                },
+                "f" => {
+                    if parts.len() < 4 {
+                        return Err(SceneError::new(format!(
+                            "Line {}: a face needs at least three vertices",
+                            line_number
+                        )));
+                    }
+                    let corners = parts[1..]
+                        .iter()
+                        .map(|corner| obj_face_vertex(corner, &vertices, line_number))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    // Fanned around the first corner, which splits quads and
+                    // other convex polygons
+                    for pair in corners[1..].windows(2) {
+                        triangles.push(Triangle::new(corners[0], pair[0], pair[1], material));
+                    }
+                },
+                // Unit hint comment written by some CAD exporters: "# units: mm"
+                "#" if parts.len() >= 2 && parts[1].eq_ignore_ascii_case("units:") => {
+                    world_scale = parts.get(2).and_then(|unit| units_per_meter(unit));
+                },
+                _ => {} // Ignore other OBJ commands
+            }
         }
         
+        #[cfg(feature = "webgl")]
         console::log_1(&format!("Created mesh with {} triangles", triangles.len()).into());
         
-        // Calculate center
+        Ok(Self::from_triangles(triangles, &vertices, name, world_scale))
+    }
+
+    /// Reads the faces of an ascii or binary PLY file, see `ply` for what's
+    /// supported. If the vertices have colors, each face's albedo is the
+    /// average of its corners' instead of `material`'s. Malformed headers
+    /// fail with the offending line.
+    pub fn from_ply(data: &[u8], material: Material, name: String) -> Result<Self, SceneError> {
+        let ply = ply::parse(data, material)?;
+        Ok(Self::from_triangles(ply.triangles, &ply.vertices, name, None))
+    }
+
+    /// A mesh of freshly read triangles, centered on the mean of the file's
+    /// vertices.
+    fn from_triangles(
+        triangles: Vec<Triangle>,
+        vertices: &[Vec3],
+        name: String,
+        world_scale: Option<f32>,
+    ) -> Self {
         let mut center = Vec3::new(0.0, 0.0, 0.0);
         if !vertices.is_empty() {
-            for vertex in &vertices {
+            for vertex in vertices {
                 center = center + *vertex;
             }
             center = center / vertices.len() as f32;
         }
-        
-        Ok(Mesh {
+
+        let low_detail = triangles.iter().step_by(LOD_TRIANGLE_STRIDE).cloned().collect();
+
+        Mesh {
             triangles,
             name,
             center,
             scale: 1.0,
-        })
+            low_detail,
+            world_scale,
+        }
     }
 
     pub fn add_to_scene_as_triangles(&self, scene: &mut Scene) {
         for triangle in &self.triangles {
-            scene.triangles.push(triangle.clone());
+            scene.add_triangle(triangle.clone());
+        }
+    }
+
+    /// Scales the mesh about the origin, then moves it by `offset`. The
+    /// units per meter the file declared are scaled along.
+    pub fn transform(&mut self, scale: f32, offset: Vec3) {
+        let place = |point: Vec3| point * scale + offset;
+        for triangle in self.triangles.iter_mut().chain(&mut self.low_detail) {
+            triangle.v0 = place(triangle.v0);
+            triangle.v1 = place(triangle.v1);
+            triangle.v2 = place(triangle.v2);
         }
+        self.center = place(self.center);
+        self.world_scale = self.world_scale.map(|world_scale| world_scale * scale);
     }
 }
 
+/// The vertex an OBJ face corner (`7`, `7/2`, `7//3`, or `-1` for the last
+/// vertex read) refers to.
+fn obj_face_vertex(
+    corner: &str,
+    vertices: &[Vec3],
+    line_number: usize,
+) -> Result<Vec3, SceneError> {
+    let index_text = corner.split('/').next().unwrap_or_default();
+    let index: i64 = index_text.parse().map_err(|_| {
+        SceneError::new(format!("Line {}: invalid face index '{}'", line_number, corner))
+    })?;
+    let position = match index {
+        1.. => Some(index as usize - 1),
+        0 => None,
+        _ => vertices.len().checked_sub(index.unsigned_abs() as usize),
+    };
+    position.and_then(|position| vertices.get(position)).copied().ok_or_else(|| {
+        SceneError::new(format!(
+            "Line {}: the face refers to vertex {}, but only {} vertices come before it",
+            line_number,
+            index,
+            vertices.len()
+        ))
+    })
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Light {
     pub position: Vec3,
     pub color: Vec3,
     pub intensity: f32,
+    /// Locked objects refuse edits and removal from the editing API
+    #[serde(default)]
+    pub locked: bool,
+    /// The objects this light shades
+    #[serde(default, skip_serializing_if = "LightLink::is_all")]
+    pub link: LightLink,
+    /// Stable id assigned by the scene when the object is added, 0 before.
+    /// Unlike the index it survives the removal of other objects.
+    #[serde(default)]
+    pub id: u32,
+    /// Label for finding the object again, such as its name in the
+    /// program it was imported from. Need not be unique.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    /// Scene revision of the last change, see [`Scene::changes_since`]
+    #[serde(skip)]
+    pub revision: u32,
 }
 
 impl Light {
@@ -188,10 +1193,231 @@ impl Light {
             position,
             color,
             intensity,
+            locked: false,
+            link: LightLink::All,
+            id: 0,
+            name: String::new(),
+            revision: 0,
+        }
+    }
+}
+
+/// How a `CsgOp` combines its two objects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsgOperation {
+    /// Everything in either object
+    Union,
+    /// `a` with `b` carved out of it
+    Subtract,
+    /// Only what is in both
+    Intersect,
+}
+
+impl CsgOperation {
+    pub fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(CsgOperation::Union),
+            1 => Some(CsgOperation::Subtract),
+            2 => Some(CsgOperation::Intersect),
+            _ => None,
+        }
+    }
+
+    pub fn to_u32(self) -> u32 {
+        match self {
+            CsgOperation::Union => 0,
+            CsgOperation::Subtract => 1,
+            CsgOperation::Intersect => 2,
+        }
+    }
+}
+
+/// An object by kind and index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectRef {
+    pub kind: ObjectType,
+    pub index: usize,
+}
+
+/// Two objects combined into one solid, which is drawn in their place. Only
+/// spheres (ellipsoids too) and boxes combine; see `Scene::drawn_csg`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CsgOp {
+    pub op: CsgOperation,
+    pub a: ObjectRef,
+    pub b: ObjectRef,
+}
+
+impl CsgOp {
+    /// Whether the shader can combine objects of these kinds.
+    pub fn supports(kind: ObjectType) -> bool {
+        matches!(kind, ObjectType::Sphere | ObjectType::Box)
+    }
+}
+
+/// Which objects a light shades, by `ObjectType::object_id`. Unlinked objects
+/// still cast shadows of the light; they just receive none of its light.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LightLink {
+    #[default]
+    All,
+    /// Only these objects
+    Include(Vec<u32>),
+    /// Every object except these
+    Exclude(Vec<u32>),
+}
+
+impl LightLink {
+    pub fn is_all(&self) -> bool {
+        *self == LightLink::All
+    }
+
+    pub fn affects(&self, id: u32) -> bool {
+        match self {
+            LightLink::All => true,
+            LightLink::Include(ids) => ids.contains(&id),
+            LightLink::Exclude(ids) => !ids.contains(&id),
+        }
+    }
+
+    /// Links or unlinks one object, keeping the list as short as possible.
+    pub fn set(&mut self, id: u32, linked: bool) {
+        if self.affects(id) == linked {
+            return;
+        }
+        match self {
+            LightLink::All => *self = LightLink::Exclude(vec![id]),
+            LightLink::Include(ids) if linked => ids.push(id),
+            LightLink::Exclude(ids) if !linked => ids.push(id),
+            LightLink::Include(ids) | LightLink::Exclude(ids) => {
+                ids.retain(|&other| other != id);
+                if ids.is_empty() && matches!(self, LightLink::Exclude(_)) {
+                    *self = LightLink::All;
+                }
+            }
+        }
+    }
+
+    /// Follows the objects being appended to a scene that already has
+    /// `firsts[ordinal]` objects of each type, as `Scene::merge` does.
+    pub fn offset_objects(&mut self, firsts: [usize; 7]) {
+        let (LightLink::Include(ids) | LightLink::Exclude(ids)) = self else {
+            return;
+        };
+        for id in ids.iter_mut() {
+            if let Some((object_type, index)) = ObjectType::from_object_id(*id) {
+                *id = object_type.object_id(index + firsts[object_type.ordinal()]);
+            }
+        }
+    }
+
+    /// Follows the removal of object `index` of `object_type`: drops its id
+    /// and renumbers the objects of that type after it.
+    pub fn remove_object(&mut self, object_type: ObjectType, index: usize) {
+        let (LightLink::Include(ids) | LightLink::Exclude(ids)) = self else {
+            return;
+        };
+        let removed = object_type.object_id(index);
+        ids.retain(|&id| id != removed);
+        for id in ids.iter_mut() {
+            if let Some((other_type, other_index)) = ObjectType::from_object_id(*id)
+                && other_type == object_type
+                && other_index > index
+            {
+                *id = object_type.object_id(other_index - 1);
+            }
+        }
+        if ids.is_empty() && matches!(self, LightLink::Exclude(_)) {
+            *self = LightLink::All;
+        }
+    }
+}
+
+/// A ball of uniform fog that scatters the brightest light's color toward
+/// the camera. `density` is the extinction per unit length; 0 is invisible.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Volume {
+    pub center: Vec3,
+    pub radius: f32,
+    pub density: f32,
+    pub color: Vec3,
+    /// Scene revision of the last change, see [`Scene::changes_since`]
+    #[serde(skip)]
+    pub revision: u32,
+}
+
+impl Volume {
+    pub fn new(center: Vec3, radius: f32, density: f32, color: Vec3) -> Self {
+        Self {
+            center,
+            radius,
+            density,
+            color,
+            revision: 0,
         }
     }
 }
 
+/// An unbounded (or `extent`-limited) lattice of identical spheres, traced
+/// analytically in the shader instead of stored as individual objects.
+///
+/// Spheres sit at every integer multiple of `cell_size`; a component of 0 or
+/// less disables repetition along that axis. `extent` limits the lattice to
+/// `extent` cells either side of the origin.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InstancedGrid {
+    pub cell_size: Vec3,
+    pub sphere_radius: f32,
+    pub material: Material,
+    #[serde(default)]
+    pub extent: Option<u32>,
+}
+
+impl InstancedGrid {
+    /// Radius actually traced: at most half the smallest repeated cell size,
+    /// so no sphere crosses into a neighboring cell.
+    pub fn traced_radius(&self) -> f32 {
+        [self.cell_size.x, self.cell_size.y, self.cell_size.z]
+            .into_iter()
+            .filter(|&size| size > 0.0)
+            .fold(self.sphere_radius, |radius, size| radius.min(size * 0.5))
+    }
+}
+
+/// Procedural night sky: rays that leave the scene see stars over
+/// `background_color` instead of the daytime gradient and sun.
+///
+/// `density` is the fraction of sky cells (0 to 1) that hold a star and
+/// `twinkle` how far, from 0 to 1, a star dims at the low point of its
+/// flicker. The same `seed` always gives the same sky.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Starfield {
+    pub density: f32,
+    pub brightness: f32,
+    pub seed: u32,
+    #[serde(default)]
+    pub twinkle: f32,
+}
+
+impl Starfield {
+    /// Offset added to the shader's cell hash. The seed is mixed first so
+    /// neighboring seeds give unrelated skies, then kept below 1000 where
+    /// floats still resolve it finely.
+    pub fn hash_offset(&self) -> (f32, f32) {
+        let mix = |mut x: u32| {
+            x ^= x >> 16;
+            x = x.wrapping_mul(0x7feb_352d);
+            x ^= x >> 15;
+            x = x.wrapping_mul(0x846c_a68b);
+            x ^= x >> 16;
+            (x % 100_000) as f32 / 100.0
+        };
+        (mix(self.seed), mix(self.seed ^ 0x9e37_79b9))
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Scene {
     pub spheres: Vec<Sphere>,
@@ -199,8 +1425,247 @@ pub struct Scene {
     pub boxes: Vec<Box>,
     pub cylinders: Vec<Cylinder>,
     pub triangles: Vec<Triangle>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cones: Vec<Cone>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disks: Vec<Disk>,
     pub lights: Vec<Light>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<Volume>,
+    /// Boolean combinations of objects, see [`CsgOp`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub csg: Vec<CsgOp>,
+    pub background_color: Vec3,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instanced_grid: Option<InstancedGrid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub starfield: Option<Starfield>,
+    /// Keyframed camera animation to replay with the scene
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera_path: Option<CameraPath>,
+    /// Named viewpoints to open the scene at
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub camera_presets: BTreeMap<String, CameraPreset>,
+    /// Named materials that can be applied to objects
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub materials: BTreeMap<String, Material>,
+    /// Scene units per meter, e.g. 1000 for a scene in millimeters. Camera
+    /// speeds, clip planes and ray offsets are defined in meters and
+    /// converted with it.
+    #[serde(default = "default_world_scale")]
+    pub world_scale: f32,
+    // Derived at import time, so not part of the saved scene
+    #[serde(skip)]
+    pub meshes: Vec<MeshLod>,
+    // Change tracking for `changes_since`, also left out of the saved scene
+    #[serde(skip)]
+    revision: u32,
+    #[serde(skip)]
+    reset_revision: u32,
+    // Last revision that changed more than single objects: lights, volumes,
+    // scene-wide settings or everything at once
+    #[serde(skip)]
+    shared_revision: u32,
+    // Last revision that changed the triangles or their level-of-detail
+    // sets, which the BVH is built over
+    #[serde(skip)]
+    triangles_revision: u32,
+    /// `(revision, object id)` of every removal since the last reset
+    #[serde(skip)]
+    removed: Vec<(u32, u32)>,
+    // Stable id the next object added gets, see `ObjectMeta::id`
+    #[serde(skip)]
+    next_id: u32,
+}
+
+/// Where an object or light sits in the scene.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ObjectLocation {
+    Object(ObjectType, usize),
+    Light(usize),
+}
+
+fn default_world_scale() -> f32 {
+    1.0
+}
+
+/// The one key `Scene::from_json` reads before deciding how to parse the rest.
+#[derive(Deserialize)]
+struct VersionHeader {
+    version: Option<u32>,
+}
+
+/// Brings a parsed scene document up to `SCENE_VERSION` in place. Fails for
+/// documents written by a newer raytracer, whose fields can't be trusted to
+/// mean what this one expects.
+pub fn migrate(document: &mut Value) -> Result<(), SceneError> {
+    let version = match document.get("version") {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| SceneError::new(format!("Invalid scene version {}", version)))?,
+    };
+    check_version(version)?;
+    for migration in &MIGRATIONS[version as usize - 1..] {
+        migration(document);
+    }
+    if let Some(fields) = document.as_object_mut() {
+        fields.insert("version".to_string(), SCENE_VERSION.into());
+    }
+    Ok(())
+}
+
+fn check_version(version: u32) -> Result<(), SceneError> {
+    if version == 0 {
+        return Err(SceneError::new("Invalid scene version 0"));
+    }
+    if version > SCENE_VERSION {
+        return Err(SceneError::new(format!(
+            "The scene file is version {} of the format, which requires a newer raytracer \
+             (this one reads up to version {})",
+            version, SCENE_VERSION
+        )));
+    }
+    Ok(())
+}
+
+/// Units per meter of a length unit named in an imported file (`mm`,
+/// `meters`, `in`, ...), or `None` if it isn't recognised.
+pub fn units_per_meter(unit: &str) -> Option<f32> {
+    let units_per_meter = match unit.to_ascii_lowercase().as_str() {
+        "mm" | "millimeter" | "millimeters" | "millimetre" | "millimetres" => 1000.0,
+        "cm" | "centimeter" | "centimeters" | "centimetre" | "centimetres" => 100.0,
+        "m" | "meter" | "meters" | "metre" | "metres" => 1.0,
+        "km" | "kilometer" | "kilometers" | "kilometre" | "kilometres" => 0.001,
+        "in" | "inch" | "inches" => 1.0 / 0.0254,
+        "ft" | "foot" | "feet" => 1.0 / 0.3048,
+        _ => return None,
+    };
+    Some(units_per_meter)
+}
+
+/// The changes to a scene after some revision, written by
+/// [`Scene::changes_since`] and read back by [`Scene::apply_patch`].
+///
+/// Objects are addressed by index. Each list gives its length after the
+/// patch and the objects that changed, so removals show up as a shorter
+/// list with the shifted objects among the changes; `deleted` names what
+/// was removed for receivers that track objects by id.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScenePatch {
+    /// Revision token the patch starts from
+    pub since: String,
+    /// Revision token to pass as the baseline of the next patch
+    pub revision: String,
+    /// The scene was replaced after `since`; the receiver starts from an
+    /// empty scene and every object is included
+    pub reset: bool,
+    pub spheres: ObjectChanges<Sphere>,
+    pub planes: ObjectChanges<Plane>,
+    pub boxes: ObjectChanges<Box>,
+    pub cylinders: ObjectChanges<Cylinder>,
+    pub triangles: ObjectChanges<Triangle>,
+    pub cones: ObjectChanges<Cone>,
+    pub disks: ObjectChanges<Disk>,
+    pub lights: ObjectChanges<Light>,
+    pub volumes: ObjectChanges<Volume>,
+    /// `ObjectType::object_id`s of removed objects, numbered as they were
+    /// at the time of removal
+    pub deleted: Vec<u32>,
+    pub csg: Vec<CsgOp>,
     pub background_color: Vec3,
+    pub instanced_grid: Option<InstancedGrid>,
+    pub starfield: Option<Starfield>,
+    pub camera_path: Option<CameraPath>,
+    pub camera_presets: BTreeMap<String, CameraPreset>,
+    pub materials: BTreeMap<String, Material>,
+    pub world_scale: f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ObjectChanges<T> {
+    /// Number of objects of this kind after the patch
+    pub count: usize,
+    pub changed: Vec<ChangedObject<T>>,
+}
+
+/// An object as it is now, with its index alongside its own fields.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChangedObject<T> {
+    pub index: usize,
+    #[serde(flatten)]
+    pub object: T,
+}
+
+impl<T: Clone> ObjectChanges<T> {
+    fn since(objects: &[T], baseline: u32, revision: impl Fn(&T) -> u32) -> Self {
+        Self {
+            count: objects.len(),
+            changed: objects
+                .iter()
+                .enumerate()
+                .filter(|(_, object)| revision(object) > baseline)
+                .map(|(index, object)| ChangedObject {
+                    index,
+                    object: object.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Checks that applying to a list of `len` objects leaves no gaps: any
+    /// index past the objects kept must be sent.
+    fn check(&self, kind: &str, len: usize) -> Result<(), SceneError> {
+        let kept = len.min(self.count);
+        let mut added: Vec<usize> = Vec::new();
+        for change in &self.changed {
+            if change.index >= self.count {
+                return Err(SceneError::new(format!(
+                    "{}: index {} is past the count of {}",
+                    kind, change.index, self.count
+                )));
+            }
+            if change.index >= kept {
+                added.push(change.index);
+            }
+        }
+        added.sort_unstable();
+        added.dedup();
+        if added.len() != self.count - kept {
+            return Err(SceneError::new(format!(
+                "{}: the patch expects {} objects but only {} are known",
+                kind,
+                self.count,
+                kept + added.len()
+            )));
+        }
+        Ok(())
+    }
+
+    fn apply(&self, objects: &mut Vec<T>, reset: bool, mut stamp: impl FnMut(&mut T)) {
+        if reset {
+            objects.clear();
+        }
+        let mut changed: Vec<&ChangedObject<T>> = self.changed.iter().collect();
+        changed.sort_by_key(|change| change.index);
+        objects.truncate(self.count);
+        for change in changed {
+            let mut object = change.object.clone();
+            stamp(&mut object);
+            if change.index < objects.len() {
+                objects[change.index] = object;
+            } else {
+                objects.push(object);
+            }
+        }
+    }
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Scene {
@@ -211,399 +1676,2808 @@ impl Scene {
             boxes: Vec::new(),
             cylinders: Vec::new(),
             triangles: Vec::new(),
+            cones: Vec::new(),
+            disks: Vec::new(),
             lights: Vec::new(),
+            volumes: Vec::new(),
+            csg: Vec::new(),
             background_color: Vec3::new(0.5, 0.7, 1.0), // Sky blue
+            instanced_grid: None,
+            starfield: None,
+            camera_path: None,
+            camera_presets: BTreeMap::new(),
+            materials: BTreeMap::new(),
+            world_scale: 1.0,
+            meshes: Vec::new(),
+            revision: 0,
+            reset_revision: 0,
+            shared_revision: 0,
+            triangles_revision: 0,
+            removed: Vec::new(),
+            next_id: 1,
         }
     }
 
-    pub fn add_sphere(&mut self, sphere: Sphere) {
+    /// Starts an empty scene for chained construction; see [`SceneBuilder`].
+    pub fn builder() -> SceneBuilder {
+        SceneBuilder::default()
+    }
+
+    /// Adds `sphere` and returns its id: the one it has, or a fresh one if
+    /// that is 0.
+    pub fn add_sphere(&mut self, mut sphere: Sphere) -> u32 {
+        sphere.meta.revision = self.next_revision();
+        sphere.meta.id = self.claim_id(sphere.meta.id);
+        let id = sphere.meta.id;
         self.spheres.push(sphere);
+        id
     }
 
-    pub fn add_plane(&mut self, plane: Plane) {
+    /// Adds `plane` and returns its id: the one it has, or a fresh one if
+    /// that is 0.
+    pub fn add_plane(&mut self, mut plane: Plane) -> u32 {
+        plane.meta.revision = self.next_revision();
+        plane.meta.id = self.claim_id(plane.meta.id);
+        let id = plane.meta.id;
         self.planes.push(plane);
+        id
     }
     
-    pub fn add_box(&mut self, box_obj: Box) {
+    /// Adds `box_obj` and returns its id: the one it has, or a fresh one if
+    /// that is 0.
+    pub fn add_box(&mut self, mut box_obj: Box) -> u32 {
+        box_obj.meta.revision = self.next_revision();
+        box_obj.meta.id = self.claim_id(box_obj.meta.id);
+        let id = box_obj.meta.id;
         self.boxes.push(box_obj);
+        id
     }
     
-    pub fn add_cylinder(&mut self, cylinder: Cylinder) {
+    /// Adds `cylinder` and returns its id: the one it has, or a fresh one if
+    /// that is 0.
+    pub fn add_cylinder(&mut self, mut cylinder: Cylinder) -> u32 {
+        cylinder.meta.revision = self.next_revision();
+        cylinder.meta.id = self.claim_id(cylinder.meta.id);
+        let id = cylinder.meta.id;
         self.cylinders.push(cylinder);
+        id
     }
     
-    pub fn add_triangle(&mut self, triangle: Triangle) {
+    /// Adds `cone` and returns its id: the one it has, or a fresh one if
+    /// that is 0.
+    pub fn add_cone(&mut self, mut cone: Cone) -> u32 {
+        cone.meta.revision = self.next_revision();
+        cone.meta.id = self.claim_id(cone.meta.id);
+        let id = cone.meta.id;
+        self.cones.push(cone);
+        id
+    }
+
+    /// Adds `disk` and returns its id: the one it has, or a fresh one if
+    /// that is 0.
+    pub fn add_disk(&mut self, mut disk: Disk) -> u32 {
+        disk.meta.revision = self.next_revision();
+        disk.meta.id = self.claim_id(disk.meta.id);
+        let id = disk.meta.id;
+        self.disks.push(disk);
+        id
+    }
+
+    /// Adds `triangle` and returns its id: the one it has, or a fresh one if
+    /// that is 0.
+    pub fn add_triangle(&mut self, mut triangle: Triangle) -> u32 {
+        triangle.meta.revision = self.next_revision();
+        self.triangles_revision = triangle.meta.revision;
+        triangle.meta.id = self.claim_id(triangle.meta.id);
+        let id = triangle.meta.id;
         self.triangles.push(triangle);
+        id
     }
 
     pub fn add_mesh(&mut self, mesh: Mesh) {
+        self.meshes.push(MeshLod {
+            name: mesh.name.clone(),
+            first_triangle: self.triangles.len(),
+            triangle_count: mesh.triangles.len(),
+            low_detail: mesh.low_detail.clone(),
+        });
         mesh.add_to_scene_as_triangles(self);
     }
 
-    pub fn import_obj_file(&mut self, obj_data: &str, material: Material, name: String) -> Result<(), JsValue> {
+    /// Triangles to upload with their index in `self.triangles`, with each
+    /// mesh swapped for its low-detail set when `low_detail` is requested.
+    /// Low-detail triangles take the index of their mesh's first triangle.
+    /// `self.triangles` itself is never changed.
+    pub fn packed_triangles(&self, low_detail: bool) -> Vec<(usize, &Triangle)> {
+        if !low_detail || self.meshes.is_empty() {
+            return self.triangles.iter().enumerate().collect();
+        }
+
+        let mut packed = Vec::with_capacity(self.triangles.len());
+        let mut next = 0;
+        for mesh in &self.meshes {
+            let start = mesh.first_triangle.min(self.triangles.len());
+            let end = (mesh.first_triangle + mesh.triangle_count).min(self.triangles.len());
+            if start < next {
+                continue;
+            }
+            packed.extend((next..start).zip(&self.triangles[next..start]));
+            packed.extend(mesh.low_detail.iter().map(|t| (start, t)));
+            next = end;
+        }
+        packed.extend((next..).zip(&self.triangles[next..]));
+        packed
+    }
+
+    /// The triangles to upload, at most `max` of them, in slot order with
+    /// their index in `self.triangles`; see `packed_triangles`.
+    pub fn uploaded_triangles(
+        &self,
+        options: &PackingOptions,
+        max: usize,
+    ) -> Vec<(usize, &Triangle)> {
+        let packed = self.packed_triangles(options.low_detail);
+        let triangle_order = slot_order(
+            packed.iter().map(|(_, o)| (o.meta.priority, o.centroid())),
+            options.camera_position,
+            max,
+        );
+        triangle_order.iter().map(|&slot| packed[slot]).collect()
+    }
+
+    /// The material to upload for `triangle`, one of `uploaded_triangles`.
+    #[cfg(feature = "webgl")]
+    fn triangle_material<'a>(
+        &'a self,
+        options: &'a PackingOptions,
+        triangle: &'a Triangle,
+    ) -> &'a Material {
+        // LOD packing shifts triangle slots, so match the override by identity
+        match &options.material_override {
+            Some(o)
+                if o.object_type == ObjectType::Triangle
+                    && self.triangles.get(o.index).is_some_and(|t| std::ptr::eq(t, triangle)) =>
+            {
+                &o.material
+            }
+            _ => &triangle.material,
+        }
+    }
+
+    /// The lights to upload, in slot order.
+    pub fn uploaded_lights(&self, options: &PackingOptions) -> Vec<&Light> {
+        let lights = match &options.lights {
+            Some(indices) => indices.iter().filter_map(|&i| self.lights.get(i)).collect(),
+            None => self.lights.iter().collect::<Vec<_>>(),
+        };
+        lights.into_iter().take(MAX_LIGHTS).collect()
+    }
+
+    pub fn import_obj_file(&mut self, obj_data: &str, material: Material, name: String) -> Result<(), SceneError> {
         let mesh = Mesh::from_blender_obj(obj_data, material, name)?;
         self.add_mesh(mesh);
         Ok(())
     }
 
-    pub fn add_light(&mut self, light: Light) {
+    /// Adds `light` and returns its id: the one it has, or a fresh one if
+    /// that is 0.
+    pub fn add_light(&mut self, mut light: Light) -> u32 {
+        light.revision = self.next_shared_revision();
+        light.id = self.claim_id(light.id);
+        let id = light.id;
         self.lights.push(light);
+        id
+    }
+
+    pub fn add_volume(&mut self, mut volume: Volume) {
+        volume.revision = self.next_shared_revision();
+        self.volumes.push(volume);
     }
 
     pub fn set_background(&mut self, color: Vec3) {
         self.background_color = color;
+        self.touch_settings();
     }
 
-    pub fn set_uniforms(
-        &self,
-        gl: &WebGlRenderingContext,
-        program: &WebGlProgram,
-    ) -> Result<(), JsValue> {
-        // Set sphere data
-        let sphere_count = self.spheres.len().min(10); // Limit to 10 spheres for WebGL uniforms
-
-        let sphere_count_location = gl.get_uniform_location(program, "u_sphere_count");
-        gl.uniform1i(sphere_count_location.as_ref(), sphere_count as i32);
-
-        for (i, sphere) in self.spheres.iter().take(10).enumerate() {
-            let center_location =
-                gl.get_uniform_location(program, &format!("u_spheres[{}].center", i));
-            gl.uniform3f(
-                center_location.as_ref(),
-                sphere.center.x,
-                sphere.center.y,
-                sphere.center.z,
-            );
+    pub fn material_mut(&mut self, object_type: ObjectType, index: usize) -> Option<&mut Material> {
+        match object_type {
+            ObjectType::Sphere => self.spheres.get_mut(index).map(|o| &mut o.material),
+            ObjectType::Plane => self.planes.get_mut(index).map(|o| &mut o.material),
+            ObjectType::Box => self.boxes.get_mut(index).map(|o| &mut o.material),
+            ObjectType::Cylinder => self.cylinders.get_mut(index).map(|o| &mut o.material),
+            ObjectType::Triangle => self.triangles.get_mut(index).map(|o| &mut o.material),
+            ObjectType::Cone => self.cones.get_mut(index).map(|o| &mut o.material),
+            ObjectType::Disk => self.disks.get_mut(index).map(|o| &mut o.material),
+        }
+    }
 
-            let radius_location =
-                gl.get_uniform_location(program, &format!("u_spheres[{}].radius", i));
-            gl.uniform1f(radius_location.as_ref(), sphere.radius);
-
-            let albedo_location =
-                gl.get_uniform_location(program, &format!("u_spheres[{}].albedo", i));
-            gl.uniform3f(
-                albedo_location.as_ref(),
-                sphere.material.albedo.x,
-                sphere.material.albedo.y,
-                sphere.material.albedo.z,
-            );
+    /// The object or light with stable id `id`.
+    pub fn find_id(&self, id: u32) -> Option<ObjectLocation> {
+        if id == 0 {
+            return None;
+        }
+        fn position<T>(objects: &[T], id: u32, object_id: impl Fn(&T) -> u32) -> Option<usize> {
+            objects.iter().position(|o| object_id(o) == id)
+        }
+        let object = |object_type, index: Option<usize>| {
+            index.map(|index| ObjectLocation::Object(object_type, index))
+        };
+        object(ObjectType::Sphere, position(&self.spheres, id, |o| o.meta.id))
+            .or_else(|| object(ObjectType::Plane, position(&self.planes, id, |o| o.meta.id)))
+            .or_else(|| object(ObjectType::Box, position(&self.boxes, id, |o| o.meta.id)))
+            .or_else(|| object(ObjectType::Cylinder, position(&self.cylinders, id, |o| o.meta.id)))
+            .or_else(|| object(ObjectType::Triangle, position(&self.triangles, id, |o| o.meta.id)))
+            .or_else(|| object(ObjectType::Cone, position(&self.cones, id, |o| o.meta.id)))
+            .or_else(|| object(ObjectType::Disk, position(&self.disks, id, |o| o.meta.id)))
+            .or_else(|| position(&self.lights, id, |o| o.id).map(ObjectLocation::Light))
+    }
 
-            let material_type_location =
-                gl.get_uniform_location(program, &format!("u_spheres[{}].material_type", i));
-            let material_type = match sphere.material.material_type {
-                crate::material::MaterialType::Lambertian => 0,
-                crate::material::MaterialType::Metal => 1,
-                crate::material::MaterialType::Dielectric => 2,
-            };
-            gl.uniform1i(material_type_location.as_ref(), material_type);
+    /// Every object and light named `name`: spheres, planes, boxes,
+    /// cylinders, triangles, cones, disks and then lights, each in index
+    /// order.
+    pub fn find_name(&self, name: &str) -> Vec<ObjectLocation> {
+        let mut found = Vec::new();
+        for object_type in ObjectType::ALL {
+            for index in 0..self.object_count(object_type) {
+                let location = ObjectLocation::Object(object_type, index);
+                if self.name(location) == Some(name) {
+                    found.push(location);
+                }
+            }
+        }
+        for (index, light) in self.lights.iter().enumerate() {
+            if light.name == name {
+                found.push(ObjectLocation::Light(index));
+            }
+        }
+        found
+    }
 
-            let roughness_location =
-                gl.get_uniform_location(program, &format!("u_spheres[{}].roughness", i));
-            gl.uniform1f(roughness_location.as_ref(), sphere.material.roughness);
+    /// The name of the object or light at `location`, if there is one.
+    pub fn name(&self, location: ObjectLocation) -> Option<&str> {
+        let name = match location {
+            ObjectLocation::Object(object_type, index) => &self.meta(object_type, index)?.name,
+            ObjectLocation::Light(index) => &self.lights.get(index)?.name,
+        };
+        Some(name)
+    }
 
-            let ior_location = gl.get_uniform_location(program, &format!("u_spheres[{}].ior", i));
-            gl.uniform1f(ior_location.as_ref(), sphere.material.ior);
+    /// The name of the object or light at `location`. Counts as a change to
+    /// it.
+    pub fn name_mut(&mut self, location: ObjectLocation) -> Option<&mut String> {
+        match location {
+            ObjectLocation::Object(object_type, index) => self.touch(object_type, index),
+            ObjectLocation::Light(index) => self.touch_light(index),
         }
+        let name = match location {
+            ObjectLocation::Object(object_type, index) => {
+                &mut self.meta_mut(object_type, index)?.name
+            }
+            ObjectLocation::Light(index) => &mut self.lights.get_mut(index)?.name,
+        };
+        Some(name)
+    }
 
-        // Set plane data
-        let plane_count = self.planes.len().min(5); // Limit to 5 planes
+    /// Gives every object and light without an id, or with one an earlier
+    /// object already has, a fresh one. Files written before objects had ids
+    /// (or edited by hand) are loaded through this.
+    pub fn assign_ids(&mut self) {
+        let highest = self.ids_mut().map(|id| *id).max().unwrap_or(0);
+        self.next_id = self.next_id.max(highest.saturating_add(1));
+        let mut next_id = self.next_id;
+        let mut seen = HashSet::new();
+        for id in self.ids_mut() {
+            if *id == 0 || !seen.insert(*id) {
+                *id = next_id;
+                next_id += 1;
+            }
+        }
+        self.next_id = next_id;
+    }
 
-        let plane_count_location = gl.get_uniform_location(program, "u_plane_count");
-        gl.uniform1i(plane_count_location.as_ref(), plane_count as i32);
+    /// `id` if it is set, else a fresh one. Either way later fresh ids
+    /// come after it.
+    fn claim_id(&mut self, id: u32) -> u32 {
+        let id = if id == 0 { self.next_id.max(1) } else { id };
+        self.next_id = self.next_id.max(id.saturating_add(1));
+        id
+    }
 
-        for (i, plane) in self.planes.iter().take(5).enumerate() {
-            let point_location =
-                gl.get_uniform_location(program, &format!("u_planes[{}].point", i));
-            gl.uniform3f(
-                point_location.as_ref(),
-                plane.point.x,
-                plane.point.y,
-                plane.point.z,
-            );
+    fn ids_mut(&mut self) -> impl Iterator<Item = &mut u32> {
+        self.spheres
+            .iter_mut()
+            .map(|o| &mut o.meta.id)
+            .chain(self.planes.iter_mut().map(|o| &mut o.meta.id))
+            .chain(self.boxes.iter_mut().map(|o| &mut o.meta.id))
+            .chain(self.cylinders.iter_mut().map(|o| &mut o.meta.id))
+            .chain(self.triangles.iter_mut().map(|o| &mut o.meta.id))
+            .chain(self.cones.iter_mut().map(|o| &mut o.meta.id))
+            .chain(self.disks.iter_mut().map(|o| &mut o.meta.id))
+            .chain(self.lights.iter_mut().map(|o| &mut o.id))
+    }
 
-            let normal_location =
-                gl.get_uniform_location(program, &format!("u_planes[{}].normal", i));
-            gl.uniform3f(
-                normal_location.as_ref(),
-                plane.normal.x,
-                plane.normal.y,
-                plane.normal.z,
-            );
+    pub fn object_count(&self, object_type: ObjectType) -> usize {
+        match object_type {
+            ObjectType::Sphere => self.spheres.len(),
+            ObjectType::Plane => self.planes.len(),
+            ObjectType::Box => self.boxes.len(),
+            ObjectType::Cylinder => self.cylinders.len(),
+            ObjectType::Triangle => self.triangles.len(),
+            ObjectType::Cone => self.cones.len(),
+            ObjectType::Disk => self.disks.len(),
+        }
+    }
 
-            let albedo_location =
-                gl.get_uniform_location(program, &format!("u_planes[{}].albedo", i));
-            gl.uniform3f(
-                albedo_location.as_ref(),
-                plane.material.albedo.x,
-                plane.material.albedo.y,
-                plane.material.albedo.z,
-            );
+    /// The shared fields of object `index` of `object_type`, if it exists.
+    pub fn meta(&self, object_type: ObjectType, index: usize) -> Option<&ObjectMeta> {
+        match object_type {
+            ObjectType::Sphere => self.spheres.get(index).map(|o| &o.meta),
+            ObjectType::Plane => self.planes.get(index).map(|o| &o.meta),
+            ObjectType::Box => self.boxes.get(index).map(|o| &o.meta),
+            ObjectType::Cylinder => self.cylinders.get(index).map(|o| &o.meta),
+            ObjectType::Triangle => self.triangles.get(index).map(|o| &o.meta),
+            ObjectType::Cone => self.cones.get(index).map(|o| &o.meta),
+            ObjectType::Disk => self.disks.get(index).map(|o| &o.meta),
+        }
+    }
 
-            let material_type_location =
-                gl.get_uniform_location(program, &format!("u_planes[{}].material_type", i));
-            let material_type = match plane.material.material_type {
-                crate::material::MaterialType::Lambertian => 0,
-                crate::material::MaterialType::Metal => 1,
-                crate::material::MaterialType::Dielectric => 2,
-            };
-            gl.uniform1i(material_type_location.as_ref(), material_type);
+    // Unlike the public accessors below this doesn't count as a change
+    fn meta_mut(&mut self, object_type: ObjectType, index: usize) -> Option<&mut ObjectMeta> {
+        match object_type {
+            ObjectType::Sphere => self.spheres.get_mut(index).map(|o| &mut o.meta),
+            ObjectType::Plane => self.planes.get_mut(index).map(|o| &mut o.meta),
+            ObjectType::Box => self.boxes.get_mut(index).map(|o| &mut o.meta),
+            ObjectType::Cylinder => self.cylinders.get_mut(index).map(|o| &mut o.meta),
+            ObjectType::Triangle => self.triangles.get_mut(index).map(|o| &mut o.meta),
+            ObjectType::Cone => self.cones.get_mut(index).map(|o| &mut o.meta),
+            ObjectType::Disk => self.disks.get_mut(index).map(|o| &mut o.meta),
         }
+    }
 
-        // Set box data
-        let box_count = self.boxes.len().min(5); // Limit to 5 boxes
-        let box_count_location = gl.get_uniform_location(program, "u_box_count");
-        gl.uniform1i(box_count_location.as_ref(), box_count as i32);
-
-        for (i, box_obj) in self.boxes.iter().take(5).enumerate() {
-            let center_location = gl.get_uniform_location(program, &format!("u_boxes[{}].center", i));
-            gl.uniform3f(
-                center_location.as_ref(),
-                box_obj.center.x,
-                box_obj.center.y,
-                box_obj.center.z,
-            );
+    /// Whether object `index` of `object_type` is locked, if it exists.
+    pub fn is_locked(&self, object_type: ObjectType, index: usize) -> Option<bool> {
+        self.meta(object_type, index).map(|meta| meta.locked)
+    }
 
-            let size_location = gl.get_uniform_location(program, &format!("u_boxes[{}].size", i));
-            gl.uniform3f(
-                size_location.as_ref(),
-                box_obj.size.x,
-                box_obj.size.y,
-                box_obj.size.z,
-            );
+    /// The lock flag of object `index` of `object_type`. Counts as a change
+    /// to the object.
+    pub fn locked_mut(&mut self, object_type: ObjectType, index: usize) -> Option<&mut bool> {
+        self.touch(object_type, index);
+        self.meta_mut(object_type, index).map(|meta| &mut meta.locked)
+    }
 
-            let albedo_location = gl.get_uniform_location(program, &format!("u_boxes[{}].albedo", i));
-            gl.uniform3f(
-                albedo_location.as_ref(),
-                box_obj.material.albedo.x,
-                box_obj.material.albedo.y,
-                box_obj.material.albedo.z,
-            );
+    /// Indices of the objects of `object_type` the shader is sent, in slot
+    /// order, at most `max` of them; see `ObjectMeta::priority`. Meshes count at
+    /// full detail. Objects drawn as part of a combination are left out.
+    pub fn upload_order(
+        &self,
+        object_type: ObjectType,
+        camera: Option<Vec3>,
+        max: usize,
+    ) -> Vec<usize> {
+        let operands = self.csg_operands(object_type);
+        if operands.is_empty() {
+            return self.ranked_order(object_type, camera, max);
+        }
+        // Room for the operands, so dropping them still leaves `max`
+        let mut order = self.ranked_order(object_type, camera, max + operands.len());
+        order.retain(|index| operands.binary_search(index).is_err());
+        order.truncate(max);
+        order
+    }
+
+    fn ranked_order(
+        &self,
+        object_type: ObjectType,
+        camera: Option<Vec3>,
+        max: usize,
+    ) -> Vec<usize> {
+        match object_type {
+            ObjectType::Sphere => {
+                slot_order(self.spheres.iter().map(|o| (o.meta.priority, o.center)), camera, max)
+            }
+            ObjectType::Plane => {
+                slot_order(self.planes.iter().map(|o| (o.meta.priority, o.point)), camera, max)
+            }
+            ObjectType::Box => {
+                slot_order(self.boxes.iter().map(|o| (o.meta.priority, o.center)), camera, max)
+            }
+            ObjectType::Cylinder => slot_order(
+                self.cylinders.iter().map(|o| (o.meta.priority, o.base + o.axis * 0.5)),
+                camera,
+                max,
+            ),
+            ObjectType::Triangle => slot_order(
+                self.triangles.iter().map(|o| (o.meta.priority, o.centroid())),
+                camera,
+                max,
+            ),
+            ObjectType::Cone => slot_order(
+                self.cones.iter().map(|o| (o.meta.priority, o.apex + o.axis * 0.5)),
+                camera,
+                max,
+            ),
+            ObjectType::Disk => slot_order(
+                self.disks.iter().map(|o| (o.meta.priority, o.center)),
+                camera,
+                max,
+            ),
+        }
+    }
+
+    /// The uniform priority of object `index` of `object_type`. Counts as a
+    /// change to the object.
+    pub fn priority_mut(&mut self, object_type: ObjectType, index: usize) -> Option<&mut i32> {
+        self.touch(object_type, index);
+        self.meta_mut(object_type, index).map(|meta| &mut meta.priority)
+    }
+
+    /// The material of object `index` of `object_type`, or `None` if there is
+    /// no such object or it is locked. Counts as a change to the object.
+    pub fn unlocked_material_mut(
+        &mut self,
+        object_type: ObjectType,
+        index: usize,
+    ) -> Option<&mut Material> {
+        if self.is_locked(object_type, index) != Some(false) {
+            return None;
+        }
+        self.touch(object_type, index);
+        self.material_mut(object_type, index)
+    }
+
+    /// Removes object `index` of `object_type`, dropping it from light links
+    /// and renumbering the objects after it. Returns false if there is no
+    /// such object.
+    pub fn remove_object(&mut self, object_type: ObjectType, index: usize) -> bool {
+        if index >= self.object_count(object_type) {
+            return false;
+        }
+        match object_type {
+            ObjectType::Sphere => {
+                self.spheres.remove(index);
+            }
+            ObjectType::Plane => {
+                self.planes.remove(index);
+            }
+            ObjectType::Box => {
+                self.boxes.remove(index);
+            }
+            ObjectType::Cylinder => {
+                self.cylinders.remove(index);
+            }
+            ObjectType::Triangle => {
+                self.triangles.remove(index);
+                for mesh in &mut self.meshes {
+                    if index < mesh.first_triangle {
+                        mesh.first_triangle -= 1;
+                    } else if index < mesh.first_triangle + mesh.triangle_count {
+                        mesh.triangle_count -= 1;
+                    }
+                }
+            }
+            ObjectType::Cone => {
+                self.cones.remove(index);
+            }
+            ObjectType::Disk => {
+                self.disks.remove(index);
+            }
+        }
+
+        let revision = self.next_revision();
+        self.removed.push((revision, object_type.object_id(index)));
+        if object_type == ObjectType::Triangle {
+            self.triangles_revision = revision;
+        }
+        // Everything after the removed object now sits at a new index
+        for later in index..self.object_count(object_type) {
+            if let Some(later_revision) = self.revision_mut(object_type, later) {
+                *later_revision = revision;
+            }
+        }
+        for light in &mut self.lights {
+            let before = light.link.clone();
+            light.link.remove_object(object_type, index);
+            if light.link != before {
+                light.revision = revision;
+                self.shared_revision = revision;
+            }
+        }
+        // A combination goes with either of its objects; the others follow
+        // the renumbering
+        let removed = ObjectRef {
+            kind: object_type,
+            index,
+        };
+        self.csg.retain(|csg| csg.a != removed && csg.b != removed);
+        for operand in self.csg.iter_mut().flat_map(|csg| [&mut csg.a, &mut csg.b]) {
+            if operand.kind == object_type && operand.index > index {
+                operand.index -= 1;
+            }
+        }
+        true
+    }
+
+    /// Adds a boolean combination, drawn in place of its two objects, and
+    /// returns its index. The objects are not checked; see `drawn_csg`.
+    pub fn add_csg(&mut self, csg: CsgOp) -> usize {
+        self.csg.push(csg);
+        self.touch_settings();
+        self.csg.len() - 1
+    }
+
+    /// Removes combination `index`, drawing its objects on their own again.
+    pub fn remove_csg(&mut self, index: usize) -> Option<CsgOp> {
+        if index >= self.csg.len() {
+            return None;
+        }
+        let csg = self.csg.remove(index);
+        self.touch_settings();
+        Some(csg)
+    }
+
+    /// The combinations the shader draws: the first `MAX_CSG` of two
+    /// distinct, existing spheres or boxes. The rest are kept but leave their
+    /// objects drawn on their own.
+    pub fn drawn_csg(&self) -> Vec<CsgOp> {
+        let valid = |operand: ObjectRef| {
+            CsgOp::supports(operand.kind) && operand.index < self.object_count(operand.kind)
+        };
+        self.csg
+            .iter()
+            .filter(|csg| csg.a != csg.b && valid(csg.a) && valid(csg.b))
+            .take(MAX_CSG)
+            .copied()
+            .collect()
+    }
+
+    /// Indices of the objects of `object_type` drawn only as part of a
+    /// combination.
+    pub fn csg_operands(&self, object_type: ObjectType) -> Vec<usize> {
+        let mut operands: Vec<usize> = self
+            .drawn_csg()
+            .iter()
+            .flat_map(|csg| [csg.a, csg.b])
+            .filter(|operand| operand.kind == object_type)
+            .map(|operand| operand.index)
+            .collect();
+        operands.sort_unstable();
+        operands.dedup();
+        operands
+    }
+
+    /// Removes light `index`, if there is one.
+    pub fn remove_light(&mut self, index: usize) -> Option<Light> {
+        if index >= self.lights.len() {
+            return None;
+        }
+        let light = self.lights.remove(index);
+        let revision = self.next_shared_revision();
+        for later in &mut self.lights[index..] {
+            later.revision = revision;
+        }
+        Some(light)
+    }
+
+    pub fn clear_volumes(&mut self) {
+        self.volumes.clear();
+        self.next_shared_revision();
+    }
+
+    /// The current revision. Every change made through the scene's methods
+    /// starts a new one, stamped on the objects it touched.
+    pub fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    /// The last revision that changed a triangle, added or removed one or
+    /// dropped the level-of-detail sets.
+    pub fn triangles_revision(&self) -> u32 {
+        self.triangles_revision
+    }
+
+    fn next_revision(&mut self) -> u32 {
+        self.revision += 1;
+        self.revision
+    }
+
+    /// Like `next_revision`, for a change that isn't confined to objects.
+    fn next_shared_revision(&mut self) -> u32 {
+        self.shared_revision = self.next_revision();
+        self.shared_revision
+    }
+
+    fn revision_mut(&mut self, object_type: ObjectType, index: usize) -> Option<&mut u32> {
+        self.meta_mut(object_type, index).map(|meta| &mut meta.revision)
+    }
+
+    /// Records a change to object `index` of `object_type` made through its
+    /// public fields.
+    pub fn touch(&mut self, object_type: ObjectType, index: usize) {
+        if index < self.object_count(object_type) {
+            let revision = self.next_revision();
+            if let Some(object_revision) = self.revision_mut(object_type, index) {
+                *object_revision = revision;
+            }
+            if object_type == ObjectType::Triangle {
+                self.triangles_revision = revision;
+            }
+        }
+    }
+
+    /// Records a change to the scene-wide settings (CSG, background, grid,
+    /// starfield, camera path and presets, material library or world
+    /// scale).
+    pub fn touch_settings(&mut self) {
+        self.next_shared_revision();
+    }
+
+    /// Like `touch`, for light `index`.
+    pub fn touch_light(&mut self, index: usize) {
+        if index < self.lights.len() {
+            let revision = self.next_shared_revision();
+            self.lights[index].revision = revision;
+        }
+    }
+
+    /// Takes over the revision sequence of `previous`, the scene this one
+    /// replaces, so its tokens stay valid: a patch from any of them resets
+    /// the receiver and carries every object.
+    pub fn continue_revisions(&mut self, previous: &Scene) {
+        let revision = previous.revision.max(self.revision) + 1;
+        self.revision = revision;
+        // Nor are ids handed out again, so stale ones held by callers miss
+        self.next_id = self.next_id.max(previous.next_id);
+        self.reset_revision = revision;
+        self.shared_revision = revision;
+        self.triangles_revision = revision;
+        self.removed.clear();
+        for sphere in &mut self.spheres {
+            sphere.meta.revision = revision;
+        }
+        for plane in &mut self.planes {
+            plane.meta.revision = revision;
+        }
+        for box_obj in &mut self.boxes {
+            box_obj.meta.revision = revision;
+        }
+        for cylinder in &mut self.cylinders {
+            cylinder.meta.revision = revision;
+        }
+        for triangle in &mut self.triangles {
+            triangle.meta.revision = revision;
+        }
+        for cone in &mut self.cones {
+            cone.meta.revision = revision;
+        }
+        for disk in &mut self.disks {
+            disk.meta.revision = revision;
+        }
+        for light in &mut self.lights {
+            light.revision = revision;
+        }
+        for volume in &mut self.volumes {
+            volume.revision = revision;
+        }
+    }
+
+    /// `ObjectType::object_id`s of the objects changed, added or removed after
+    /// revision `baseline`, or `None` if a light, volume or scene-wide
+    /// setting changed too.
+    pub fn object_changes_since(&self, baseline: u32) -> Option<Vec<u32>> {
+        if self.shared_revision > baseline {
+            return None;
+        }
+        let changed = ObjectType::ALL.into_iter().flat_map(|object_type| {
+            (0..self.object_count(object_type))
+                .filter(move |&index| {
+                    self.meta(object_type, index).is_some_and(|meta| meta.revision > baseline)
+                })
+                .map(move |index| object_type.object_id(index))
+        });
+        let removed = self
+            .removed
+            .iter()
+            .filter(|(revision, _)| *revision > baseline)
+            .map(|(_, id)| *id);
+        Some(changed.chain(removed).collect())
+    }
+
+    /// Everything that changed after revision `baseline`: the objects whose
+    /// revision is newer, the ids removed since, and the scene-wide settings,
+    /// which are small enough to always send.
+    pub fn changes_since(&self, baseline: u32) -> ScenePatch {
+        let reset = baseline < self.reset_revision;
+        let deleted = if reset {
+            Vec::new()
+        } else {
+            self.removed
+                .iter()
+                .filter(|(revision, _)| *revision > baseline)
+                .map(|(_, id)| *id)
+                .collect()
+        };
+        ScenePatch {
+            since: baseline.to_string(),
+            revision: self.revision.to_string(),
+            reset,
+            spheres: ObjectChanges::since(&self.spheres, baseline, |o| o.meta.revision),
+            planes: ObjectChanges::since(&self.planes, baseline, |o| o.meta.revision),
+            boxes: ObjectChanges::since(&self.boxes, baseline, |o| o.meta.revision),
+            cylinders: ObjectChanges::since(&self.cylinders, baseline, |o| o.meta.revision),
+            triangles: ObjectChanges::since(&self.triangles, baseline, |o| o.meta.revision),
+            cones: ObjectChanges::since(&self.cones, baseline, |o| o.meta.revision),
+            disks: ObjectChanges::since(&self.disks, baseline, |o| o.meta.revision),
+            lights: ObjectChanges::since(&self.lights, baseline, |o| o.revision),
+            volumes: ObjectChanges::since(&self.volumes, baseline, |o| o.revision),
+            deleted,
+            csg: self.csg.clone(),
+            background_color: self.background_color,
+            instanced_grid: self.instanced_grid.clone(),
+            starfield: self.starfield.clone(),
+            camera_path: self.camera_path.clone(),
+            camera_presets: self.camera_presets.clone(),
+            materials: self.materials.clone(),
+            world_scale: self.world_scale,
+        }
+    }
+
+    /// Brings this scene up to date with a patch from `changes_since`. The
+    /// patch is checked before anything is changed, so a bad one leaves the
+    /// scene as it was.
+    pub fn apply_patch(&mut self, patch: ScenePatch) -> Result<(), SceneError> {
+        let lens = if patch.reset {
+            [0; 9]
+        } else {
+            [
+                self.spheres.len(),
+                self.planes.len(),
+                self.boxes.len(),
+                self.cylinders.len(),
+                self.triangles.len(),
+                self.cones.len(),
+                self.disks.len(),
+                self.lights.len(),
+                self.volumes.len(),
+            ]
+        };
+        patch.spheres.check("spheres", lens[0])?;
+        patch.planes.check("planes", lens[1])?;
+        patch.boxes.check("boxes", lens[2])?;
+        patch.cylinders.check("cylinders", lens[3])?;
+        patch.triangles.check("triangles", lens[4])?;
+        patch.cones.check("cones", lens[5])?;
+        patch.disks.check("disks", lens[6])?;
+        patch.lights.check("lights", lens[7])?;
+        patch.volumes.check("volumes", lens[8])?;
+
+        let revision = self.next_shared_revision();
+        if patch.reset || patch.triangles.count != self.triangles.len() {
+            // The level-of-detail sets can't follow triangles they weren't
+            // built from
+            self.meshes.clear();
+            self.triangles_revision = revision;
+        }
+        if !patch.triangles.changed.is_empty() {
+            self.triangles_revision = revision;
+        }
+        if patch.reset {
+            self.reset_revision = revision;
+            self.removed.clear();
+        }
+        self.removed.extend(patch.deleted.iter().map(|&id| (revision, id)));
+
+        patch.spheres.apply(&mut self.spheres, patch.reset, |o| o.meta.revision = revision);
+        patch.planes.apply(&mut self.planes, patch.reset, |o| o.meta.revision = revision);
+        patch.boxes.apply(&mut self.boxes, patch.reset, |o| o.meta.revision = revision);
+        patch.cylinders.apply(&mut self.cylinders, patch.reset, |o| o.meta.revision = revision);
+        patch.triangles.apply(&mut self.triangles, patch.reset, |o| o.meta.revision = revision);
+        patch.cones.apply(&mut self.cones, patch.reset, |o| o.meta.revision = revision);
+        patch.disks.apply(&mut self.disks, patch.reset, |o| o.meta.revision = revision);
+        patch.lights.apply(&mut self.lights, patch.reset, |o| o.revision = revision);
+        patch.volumes.apply(&mut self.volumes, patch.reset, |o| o.revision = revision);
 
-            let material_type_location = gl.get_uniform_location(program, &format!("u_boxes[{}].material_type", i));
-            let material_type = match box_obj.material.material_type {
-                crate::material::MaterialType::Lambertian => 0,
-                crate::material::MaterialType::Metal => 1,
-                crate::material::MaterialType::Dielectric => 2,
+        self.csg = patch.csg;
+        self.background_color = patch.background_color;
+        self.instanced_grid = patch.instanced_grid;
+        self.starfield = patch.starfield;
+        self.camera_path = patch.camera_path;
+        self.camera_presets = patch.camera_presets;
+        self.assign_ids();
+        self.materials = patch.materials;
+        self.world_scale = patch.world_scale;
+        Ok(())
+    }
+
+    /// Bounds of every finite object. Planes are infinite and left out, so a
+    /// scene with nothing but planes has no bounding box.
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        self.spheres
+            .iter()
+            .map(Sphere::bounds)
+            .chain(self.boxes.iter().map(Box::bounds))
+            .chain(self.cylinders.iter().map(Cylinder::bounds))
+            .chain(self.triangles.iter().map(Triangle::bounds))
+            .chain(self.cones.iter().map(Cone::bounds))
+            .chain(self.disks.iter().map(Disk::bounds))
+            .reduce(|a, b| a.union(&b))
+    }
+
+    /// Every value of every object and light that the renderer can't draw
+    /// sensibly, in the order the scene JSON lists them.
+    pub fn validate(&self) -> Vec<SceneProblem> {
+        fn problems<T: Validate>(objects: &[T]) -> impl Iterator<Item = SceneProblem> + '_ {
+            objects.iter().enumerate().flat_map(|(index, object)| object.problems(index))
+        }
+        problems(&self.spheres)
+            .chain(problems(&self.planes))
+            .chain(problems(&self.boxes))
+            .chain(problems(&self.cylinders))
+            .chain(problems(&self.triangles))
+            .chain(problems(&self.cones))
+            .chain(problems(&self.disks))
+            .chain(problems(&self.lights))
+            .chain(problems(&self.volumes))
+            .collect()
+    }
+
+    /// Describes each object whose data can make the shader produce NaNs:
+    /// non-finite numbers, non-positive radii and scales, zero-length plane
+    /// normals, cylinder and cone axes and disk normals, and zero-area
+    /// triangles.
+    pub fn degenerate_objects(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut check = |kind: &str, index: usize, finite: bool, problem: Option<&str>| {
+            if !finite {
+                problems.push(format!("{} {}: non-finite value", kind, index));
+            } else if let Some(problem) = problem {
+                problems.push(format!("{} {}: {}", kind, index, problem));
+            }
+        };
+
+        for (i, o) in self.spheres.iter().enumerate() {
+            let finite = o.center.is_finite()
+                && o.radius.is_finite()
+                && o.scale.is_finite()
+                && o.material.is_finite();
+            let problem = if o.radius <= 0.0 {
+                Some("radius is not positive")
+            } else if o.scale.x <= 0.0 || o.scale.y <= 0.0 || o.scale.z <= 0.0 {
+                Some("scale is not positive")
+            } else {
+                None
+            };
+            check("sphere", i, finite, problem);
+        }
+        for (i, o) in self.planes.iter().enumerate() {
+            let finite = o.point.is_finite() && o.normal.is_finite() && o.material.is_finite();
+            let flat = o.normal.length_squared() == 0.0;
+            check("plane", i, finite, flat.then_some("normal has zero length"));
+        }
+        for (i, o) in self.boxes.iter().enumerate() {
+            let finite = o.center.is_finite()
+                && o.size.is_finite()
+                && o.rotation.is_finite()
+                && o.scale.is_finite()
+                && o.material.is_finite();
+            let flat = o.scale.x <= 0.0 || o.scale.y <= 0.0 || o.scale.z <= 0.0;
+            check("box", i, finite, flat.then_some("scale is not positive"));
+        }
+        for (i, o) in self.cylinders.iter().enumerate() {
+            let finite = o.base.is_finite()
+                && o.axis.is_finite()
+                && o.radius.is_finite()
+                && o.rotation.is_finite()
+                && o.scale.is_finite()
+                && o.material.is_finite();
+            let problem = if o.radius <= 0.0 {
+                Some("radius is not positive")
+            } else if o.axis.length_squared() == 0.0 {
+                Some("axis has zero length")
+            } else if o.scale.x <= 0.0 || o.scale.y <= 0.0 || o.scale.z <= 0.0 {
+                Some("scale is not positive")
+            } else {
+                None
+            };
+            check("cylinder", i, finite, problem);
+        }
+        for (i, o) in self.triangles.iter().enumerate() {
+            let finite = o.v0.is_finite()
+                && o.v1.is_finite()
+                && o.v2.is_finite()
+                && o.material.is_finite();
+            let area = (o.v1 - o.v0).cross(&(o.v2 - o.v0)).length_squared();
+            check("triangle", i, finite, (area == 0.0).then_some("zero area"));
+        }
+        for (i, o) in self.cones.iter().enumerate() {
+            let finite = o.apex.is_finite()
+                && o.axis.is_finite()
+                && o.radius.is_finite()
+                && o.material.is_finite();
+            let problem = if o.radius <= 0.0 {
+                Some("radius is not positive")
+            } else if o.axis.length_squared() == 0.0 {
+                Some("axis has zero length")
+            } else {
+                None
+            };
+            check("cone", i, finite, problem);
+        }
+        for (i, o) in self.disks.iter().enumerate() {
+            let finite = o.center.is_finite()
+                && o.normal.is_finite()
+                && o.radius.is_finite()
+                && o.inner_radius.is_finite()
+                && o.material.is_finite();
+            let problem = if o.radius <= 0.0 {
+                Some("radius is not positive")
+            } else if o.normal.length_squared() == 0.0 {
+                Some("normal has zero length")
+            } else {
+                None
             };
-            gl.uniform1i(material_type_location.as_ref(), material_type);
+            check("disk", i, finite, problem);
+        }
+        for (i, o) in self.lights.iter().enumerate() {
+            let finite = o.position.is_finite() && o.color.is_finite() && o.intensity.is_finite();
+            check("light", i, finite, None);
+        }
+        for (i, o) in self.volumes.iter().enumerate() {
+            let finite = o.center.is_finite()
+                && o.radius.is_finite()
+                && o.density.is_finite()
+                && o.color.is_finite();
+            check("volume", i, finite, (o.radius <= 0.0).then_some("radius is not positive"));
+        }
+        problems
+    }
+
+    /// Uploads the scene into the uniforms of the program `uniforms` was built
+    /// for and returns the number of bytes sent.
+    #[cfg(feature = "webgl")]
+    pub fn set_uniforms(
+        &self,
+        gl: &WebGlRenderingContext,
+        uniforms: &SceneUniforms,
+        options: &PackingOptions,
+    ) -> Result<usize, JsValue> {
+        let mut upload = UniformUpload { gl, bytes: 0 };
+        let lights = self.uploaded_lights(options);
+
+        // Objects in the data texture only need their counts, which the
+        // texture sets
+        if !options.data_texture {
+            self.set_object_uniforms(&mut upload, uniforms, options, &lights);
+        }
+
+        // Lights and volumes in the uniform block are uploaded with it
+        if !options.light_block {
+            self.set_light_uniforms(&mut upload, uniforms, &lights);
+        }
+
+        // Combinations are uniforms either way
+        self.set_csg_uniforms(&mut upload, uniforms, options, &lights);
 
-            let roughness_location = gl.get_uniform_location(program, &format!("u_boxes[{}].roughness", i));
-            gl.uniform1f(roughness_location.as_ref(), box_obj.material.roughness);
+        // Set instanced grid data
+        let grid_enabled_location = uniforms.grid.enabled.as_ref();
+        upload.uniform1i(grid_enabled_location, self.instanced_grid.is_some() as i32);
 
-            let ior_location = gl.get_uniform_location(program, &format!("u_boxes[{}].ior", i));
-            gl.uniform1f(ior_location.as_ref(), box_obj.material.ior);
+        if let Some(grid) = &self.instanced_grid {
+            let cell_size_location = uniforms.grid.cell_size.as_ref();
+            upload.uniform3f(
+                cell_size_location,
+                grid.cell_size.x,
+                grid.cell_size.y,
+                grid.cell_size.z,
+            );
+
+            let radius_location = uniforms.grid.radius.as_ref();
+            upload.uniform1f(radius_location, grid.traced_radius());
+
+            let extent_location = uniforms.grid.extent.as_ref();
+            upload.uniform1f(
+                extent_location,
+                grid.extent.map_or(-1.0, |extent| extent as f32),
+            );
+
+            set_material_uniforms(&mut upload, &uniforms.grid.material, &grid.material);
+        }
+
+        // Set background color
+        let bg_color_location = uniforms.background_color.as_ref();
+        upload.uniform3f(
+            bg_color_location,
+            self.background_color.x,
+            self.background_color.y,
+            self.background_color.z,
+        );
+
+        // Set starfield data
+        let starfield_enabled_location = uniforms.starfield.enabled.as_ref();
+        upload.uniform1i(starfield_enabled_location, self.starfield.is_some() as i32);
+
+        if let Some(starfield) = &self.starfield {
+            let density_location = uniforms.starfield.density.as_ref();
+            upload.uniform1f(density_location, starfield.density);
+
+            let brightness_location = uniforms.starfield.brightness.as_ref();
+            upload.uniform1f(brightness_location, starfield.brightness);
+
+            let (offset_x, offset_y) = starfield.hash_offset();
+            let offset_location = uniforms.starfield.offset.as_ref();
+            upload.uniform2f(offset_location, offset_x, offset_y);
+
+            let twinkle_location = uniforms.starfield.twinkle.as_ref();
+            upload.uniform1f(twinkle_location, starfield.twinkle);
+        }
+
+        Ok(upload.bytes)
+    }
+
+    /// Uploads the lights and volumes into the program's uniform arrays.
+    #[cfg(feature = "webgl")]
+    fn set_light_uniforms(
+        &self,
+        upload: &mut UniformUpload,
+        uniforms: &SceneUniforms,
+        lights: &[&Light],
+    ) {
+        // Set light data
+        let light_count = lights.len();
+
+        let light_count_location = uniforms.light_count.as_ref();
+        upload.uniform1i(light_count_location, light_count as i32);
+
+        for (i, light) in lights.iter().enumerate() {
+            let position_location = uniforms.lights[i].position.as_ref();
+            upload.uniform3f(
+                position_location,
+                light.position.x,
+                light.position.y,
+                light.position.z,
+            );
+
+            let color_location = uniforms.lights[i].color.as_ref();
+            upload.uniform3f(
+                color_location,
+                light.color.x,
+                light.color.y,
+                light.color.z,
+            );
+
+            let intensity_location = uniforms.lights[i].intensity.as_ref();
+            upload.uniform1f(intensity_location, light.intensity);
+        }
+
+        // Set volume data
+        let volume_count = self.volumes.len().min(MAX_VOLUMES);
+        let volume_count_location = uniforms.volume_count.as_ref();
+        upload.uniform1i(volume_count_location, volume_count as i32);
+
+        for (i, volume) in self.volumes.iter().take(MAX_VOLUMES).enumerate() {
+            let center_location = uniforms.volumes[i].center.as_ref();
+            upload.uniform3f(
+                center_location,
+                volume.center.x,
+                volume.center.y,
+                volume.center.z,
+            );
+
+            let radius_location = uniforms.volumes[i].radius.as_ref();
+            upload.uniform1f(radius_location, volume.radius);
+
+            let density_location = uniforms.volumes[i].density.as_ref();
+            upload.uniform1f(density_location, volume.density.max(0.0));
+
+            let color_location = uniforms.volumes[i].color.as_ref();
+            upload.uniform3f(
+                color_location,
+                volume.color.x,
+                volume.color.y,
+                volume.color.z,
+            );
+        }
+    }
+
+    /// Uploads the combinations `drawn_csg` lists, each with both of its
+    /// objects. Their slot holds the scene index and light linking mask of
+    /// `a`, then of `b`.
+    #[cfg(feature = "webgl")]
+    fn set_csg_uniforms(
+        &self,
+        upload: &mut UniformUpload,
+        uniforms: &SceneUniforms,
+        options: &PackingOptions,
+        lights: &[&Light],
+    ) {
+        let csg = self.drawn_csg();
+        let csg_count_location = uniforms.csg_count.as_ref();
+        upload.uniform1i(csg_count_location, csg.len() as i32);
+
+        for (i, csg) in csg.iter().enumerate() {
+            let csg_uniforms = &uniforms.csgs[i];
+            let op_location = csg_uniforms.op.as_ref();
+            upload.uniform1i(op_location, csg.op.to_u32() as i32);
+
+            let operands = [(&csg_uniforms.a, csg.a), (&csg_uniforms.b, csg.b)];
+            for (operand_uniforms, operand) in operands {
+                // Spheres send their semi-axes as the size, boxes their size
+                // stretched by their scale
+                let (center, size, rotation, material) = match operand.kind {
+                    ObjectType::Sphere => {
+                        let sphere = &self.spheres[operand.index];
+                        let size = sphere.scale * sphere.radius;
+                        (sphere.center, size, Quat::identity(), &sphere.material)
+                    }
+                    _ => {
+                        let box_obj = &self.boxes[operand.index];
+                        let size = box_obj.size * box_obj.scale;
+                        let rotation = Quat::from_euler(box_obj.rotation);
+                        (box_obj.center, size, rotation, &box_obj.material)
+                    }
+                };
+                let kind_location = operand_uniforms.kind.as_ref();
+                upload.uniform1i(kind_location, operand.kind.to_u32() as i32);
+
+                let center_location = operand_uniforms.center.as_ref();
+                upload.uniform3f(center_location, center.x, center.y, center.z);
+
+                let size_location = operand_uniforms.size.as_ref();
+                upload.uniform3f(size_location, size.x, size.y, size.z);
+
+                let rotation_location = operand_uniforms.rotation.as_ref();
+                upload.uniform4f(rotation_location, rotation.x, rotation.y, rotation.z, rotation.w);
+
+                let material = options.material(operand.kind, operand.index, material);
+                set_material_uniforms(upload, &operand_uniforms.material, material);
+            }
+
+            let mask_a = light_mask(lights, csg.a.kind.object_id(csg.a.index));
+            let mask_b = light_mask(lights, csg.b.kind.object_id(csg.b.index));
+            let slot_location = uniforms.csg_slots[i].as_ref();
+            upload.uniform4f(
+                slot_location,
+                csg.a.index as f32,
+                mask_a as f32,
+                csg.b.index as f32,
+                mask_b as f32,
+            );
+        }
+    }
+
+    /// Uploads the objects and their slots into the program's uniform arrays.
+    #[cfg(feature = "webgl")]
+    fn set_object_uniforms(
+        &self,
+        upload: &mut UniformUpload,
+        uniforms: &SceneUniforms,
+        options: &PackingOptions,
+        lights: &[&Light],
+    ) {
+        let camera = options.camera_position;
+
+        // Set sphere data
+        let sphere_order = self.upload_order(ObjectType::Sphere, camera, MAX_SPHERES);
+        let sphere_count = sphere_order.len();
+
+        let sphere_count_location = uniforms.counts[ObjectType::Sphere.ordinal()].as_ref();
+        upload.uniform1i(sphere_count_location, sphere_count as i32);
+
+        for (i, &index) in sphere_order.iter().enumerate() {
+            let sphere = &self.spheres[index];
+            let center_location = uniforms.spheres[i].center.as_ref();
+            upload.uniform3f(
+                center_location,
+                sphere.center.x,
+                sphere.center.y,
+                sphere.center.z,
+            );
+
+            let radius_location = uniforms.spheres[i].radius.as_ref();
+            upload.uniform1f(radius_location, sphere.radius);
+
+            let scale_location = uniforms.spheres[i].scale.as_ref();
+            upload.uniform3f(scale_location, sphere.scale.x, sphere.scale.y, sphere.scale.z);
+
+            let image_texture_location =
+                uniforms.spheres[i].image_texture.as_ref();
+            let image_texture = sphere.image_texture.map_or(-1.0, |slot| slot as f32);
+            upload.uniform1f(image_texture_location, image_texture);
+
+            let material = options.material(ObjectType::Sphere, index, &sphere.material);
+            set_material_uniforms(upload, &uniforms.spheres[i].material, material);
+        }
+
+        // Set plane data
+        let plane_order = self.upload_order(ObjectType::Plane, camera, MAX_PLANES);
+        let plane_count = plane_order.len();
+
+        let plane_count_location = uniforms.counts[ObjectType::Plane.ordinal()].as_ref();
+        upload.uniform1i(plane_count_location, plane_count as i32);
+
+        for (i, &index) in plane_order.iter().enumerate() {
+            let plane = &self.planes[index];
+            let point_location = uniforms.planes[i].point.as_ref();
+            upload.uniform3f(
+                point_location,
+                plane.point.x,
+                plane.point.y,
+                plane.point.z,
+            );
+
+            let normal_location = uniforms.planes[i].normal.as_ref();
+            upload.uniform3f(
+                normal_location,
+                plane.normal.x,
+                plane.normal.y,
+                plane.normal.z,
+            );
+
+            let material = options.material(ObjectType::Plane, index, &plane.material);
+            set_material_uniforms(upload, &uniforms.planes[i].material, material);
+        }
+
+        // Set box data
+        let box_order = self.upload_order(ObjectType::Box, camera, MAX_BOXES);
+        let box_count = box_order.len();
+        let box_count_location = uniforms.counts[ObjectType::Box.ordinal()].as_ref();
+        upload.uniform1i(box_count_location, box_count as i32);
+
+        for (i, &index) in box_order.iter().enumerate() {
+            let box_obj = &self.boxes[index];
+            let center_location = uniforms.boxes[i].center.as_ref();
+            upload.uniform3f(
+                center_location,
+                box_obj.center.x,
+                box_obj.center.y,
+                box_obj.center.z,
+            );
+
+            let size_location = uniforms.boxes[i].size.as_ref();
+            upload.uniform3f(
+                size_location,
+                box_obj.size.x,
+                box_obj.size.y,
+                box_obj.size.z,
+            );
+
+            // The shader turns boxes by quaternion
+            let rotation = Quat::from_euler(box_obj.rotation);
+            let rotation_location = uniforms.boxes[i].rotation.as_ref();
+            upload.uniform4f(rotation_location, rotation.x, rotation.y, rotation.z, rotation.w);
+
+            let scale_location = uniforms.boxes[i].scale.as_ref();
+            upload.uniform3f(scale_location, box_obj.scale.x, box_obj.scale.y, box_obj.scale.z);
+
+            let material = options.material(ObjectType::Box, index, &box_obj.material);
+            set_material_uniforms(upload, &uniforms.boxes[i].material, material);
         }
 
         // Set cylinder data
-        let cylinder_count = self.cylinders.len().min(5); // Limit to 5 cylinders
-        let cylinder_count_location = gl.get_uniform_location(program, "u_cylinder_count");
-        gl.uniform1i(cylinder_count_location.as_ref(), cylinder_count as i32);
-
-        for (i, cylinder) in self.cylinders.iter().take(5).enumerate() {
-            let base_location = gl.get_uniform_location(program, &format!("u_cylinders[{}].base", i));
-            gl.uniform3f(
-                base_location.as_ref(),
+        let cylinder_order = self.upload_order(ObjectType::Cylinder, camera, MAX_CYLINDERS);
+        let cylinder_count = cylinder_order.len();
+        let cylinder_count_location = uniforms.counts[ObjectType::Cylinder.ordinal()].as_ref();
+        upload.uniform1i(cylinder_count_location, cylinder_count as i32);
+
+        for (i, &index) in cylinder_order.iter().enumerate() {
+            let cylinder = &self.cylinders[index];
+            let base_location = uniforms.cylinders[i].base.as_ref();
+            upload.uniform3f(
+                base_location,
                 cylinder.base.x,
                 cylinder.base.y,
                 cylinder.base.z,
             );
 
-            let axis_location = gl.get_uniform_location(program, &format!("u_cylinders[{}].axis", i));
-            gl.uniform3f(
-                axis_location.as_ref(),
+            let axis_location = uniforms.cylinders[i].axis.as_ref();
+            upload.uniform3f(
+                axis_location,
                 cylinder.axis.x,
                 cylinder.axis.y,
                 cylinder.axis.z,
             );
 
-            let radius_location = gl.get_uniform_location(program, &format!("u_cylinders[{}].radius", i));
-            gl.uniform1f(radius_location.as_ref(), cylinder.radius);
-
-            let albedo_location = gl.get_uniform_location(program, &format!("u_cylinders[{}].albedo", i));
-            gl.uniform3f(
-                albedo_location.as_ref(),
-                cylinder.material.albedo.x,
-                cylinder.material.albedo.y,
-                cylinder.material.albedo.z,
-            );
+            let radius_location = uniforms.cylinders[i].radius.as_ref();
+            upload.uniform1f(radius_location, cylinder.radius);
 
-            let material_type_location = gl.get_uniform_location(program, &format!("u_cylinders[{}].material_type", i));
-            let material_type = match cylinder.material.material_type {
-                crate::material::MaterialType::Lambertian => 0,
-                crate::material::MaterialType::Metal => 1,
-                crate::material::MaterialType::Dielectric => 2,
-            };
-            gl.uniform1i(material_type_location.as_ref(), material_type);
+            let rotation = Quat::from_euler(cylinder.rotation);
+            let rotation_location = uniforms.cylinders[i].rotation.as_ref();
+            upload.uniform4f(rotation_location, rotation.x, rotation.y, rotation.z, rotation.w);
 
-            let roughness_location = gl.get_uniform_location(program, &format!("u_cylinders[{}].roughness", i));
-            gl.uniform1f(roughness_location.as_ref(), cylinder.material.roughness);
+            let scale = cylinder.scale;
+            let scale_location = uniforms.cylinders[i].scale.as_ref();
+            upload.uniform3f(scale_location, scale.x, scale.y, scale.z);
 
-            let ior_location = gl.get_uniform_location(program, &format!("u_cylinders[{}].ior", i));
-            gl.uniform1f(ior_location.as_ref(), cylinder.material.ior);
+            let material = options.material(ObjectType::Cylinder, index, &cylinder.material);
+            set_material_uniforms(upload, &uniforms.cylinders[i].material, material);
         }
 
         // Set triangle data
-        let triangle_count = self.triangles.len().min(10); // Limit to 10 triangles
-        let triangle_count_location = gl.get_uniform_location(program, "u_triangle_count");
-        gl.uniform1i(triangle_count_location.as_ref(), triangle_count as i32);
-
-        for (i, triangle) in self.triangles.iter().take(10).enumerate() {
-            let v0_location = gl.get_uniform_location(program, &format!("u_triangles[{}].v0", i));
-            gl.uniform3f(
-                v0_location.as_ref(),
+        let triangles = self.uploaded_triangles(options, MAX_TRIANGLES);
+        let triangle_count = triangles.len();
+        let triangle_count_location = uniforms.counts[ObjectType::Triangle.ordinal()].as_ref();
+        upload.uniform1i(triangle_count_location, triangle_count as i32);
+
+        for (i, &(_, triangle)) in triangles.iter().enumerate() {
+            let v0_location = uniforms.triangles[i].v0.as_ref();
+            upload.uniform3f(
+                v0_location,
                 triangle.v0.x,
                 triangle.v0.y,
                 triangle.v0.z,
             );
 
-            let v1_location = gl.get_uniform_location(program, &format!("u_triangles[{}].v1", i));
-            gl.uniform3f(
-                v1_location.as_ref(),
+            let v1_location = uniforms.triangles[i].v1.as_ref();
+            upload.uniform3f(
+                v1_location,
                 triangle.v1.x,
                 triangle.v1.y,
                 triangle.v1.z,
             );
 
-            let v2_location = gl.get_uniform_location(program, &format!("u_triangles[{}].v2", i));
-            gl.uniform3f(
-                v2_location.as_ref(),
+            let v2_location = uniforms.triangles[i].v2.as_ref();
+            upload.uniform3f(
+                v2_location,
                 triangle.v2.x,
                 triangle.v2.y,
                 triangle.v2.z,
             );
 
-            let albedo_location = gl.get_uniform_location(program, &format!("u_triangles[{}].albedo", i));
-            gl.uniform3f(
-                albedo_location.as_ref(),
-                triangle.material.albedo.x,
-                triangle.material.albedo.y,
-                triangle.material.albedo.z,
+            let material = self.triangle_material(options, triangle);
+            set_material_uniforms(upload, &uniforms.triangles[i].material, material);
+        }
+
+        // Set cone data
+        let cone_order = self.upload_order(ObjectType::Cone, camera, MAX_CONES);
+        let cone_count_location = uniforms.counts[ObjectType::Cone.ordinal()].as_ref();
+        upload.uniform1i(cone_count_location, cone_order.len() as i32);
+
+        for (i, &index) in cone_order.iter().enumerate() {
+            let cone = &self.cones[index];
+            let apex_location = uniforms.cones[i].apex.as_ref();
+            upload.uniform3f(
+                apex_location,
+                cone.apex.x,
+                cone.apex.y,
+                cone.apex.z,
             );
 
-            let material_type_location = gl.get_uniform_location(program, &format!("u_triangles[{}].material_type", i));
-            let material_type = match triangle.material.material_type {
-                crate::material::MaterialType::Lambertian => 0,
-                crate::material::MaterialType::Metal => 1,
-                crate::material::MaterialType::Dielectric => 2,
-            };
-            gl.uniform1i(material_type_location.as_ref(), material_type);
+            let axis_location = uniforms.cones[i].axis.as_ref();
+            upload.uniform3f(
+                axis_location,
+                cone.axis.x,
+                cone.axis.y,
+                cone.axis.z,
+            );
 
-            let roughness_location = gl.get_uniform_location(program, &format!("u_triangles[{}].roughness", i));
-            gl.uniform1f(roughness_location.as_ref(), triangle.material.roughness);
+            let radius_location = uniforms.cones[i].radius.as_ref();
+            upload.uniform1f(radius_location, cone.radius);
 
-            let ior_location = gl.get_uniform_location(program, &format!("u_triangles[{}].ior", i));
-            gl.uniform1f(ior_location.as_ref(), triangle.material.ior);
+            let material = options.material(ObjectType::Cone, index, &cone.material);
+            set_material_uniforms(upload, &uniforms.cones[i].material, material);
         }
 
-        // Set light data
-        let light_count = self.lights.len().min(4); // Limit to 4 lights
-
-        let light_count_location = gl.get_uniform_location(program, "u_light_count");
-        gl.uniform1i(light_count_location.as_ref(), light_count as i32);
+        // Set disk data
+        let disk_order = self.upload_order(ObjectType::Disk, camera, MAX_DISKS);
+        let disk_count_location = uniforms.counts[ObjectType::Disk.ordinal()].as_ref();
+        upload.uniform1i(disk_count_location, disk_order.len() as i32);
 
-        for (i, light) in self.lights.iter().take(4).enumerate() {
-            let position_location =
-                gl.get_uniform_location(program, &format!("u_lights[{}].position", i));
-            gl.uniform3f(
-                position_location.as_ref(),
-                light.position.x,
-                light.position.y,
-                light.position.z,
+        for (i, &index) in disk_order.iter().enumerate() {
+            let disk = &self.disks[index];
+            let center_location = uniforms.disks[i].center.as_ref();
+            upload.uniform3f(
+                center_location,
+                disk.center.x,
+                disk.center.y,
+                disk.center.z,
             );
 
-            let color_location =
-                gl.get_uniform_location(program, &format!("u_lights[{}].color", i));
-            gl.uniform3f(
-                color_location.as_ref(),
-                light.color.x,
-                light.color.y,
-                light.color.z,
+            let normal_location = uniforms.disks[i].normal.as_ref();
+            upload.uniform3f(
+                normal_location,
+                disk.normal.x,
+                disk.normal.y,
+                disk.normal.z,
             );
 
-            let intensity_location =
-                gl.get_uniform_location(program, &format!("u_lights[{}].intensity", i));
-            gl.uniform1f(intensity_location.as_ref(), light.intensity);
+            let radius_location = uniforms.disks[i].radius.as_ref();
+            upload.uniform1f(radius_location, disk.radius);
+
+            let inner_location = uniforms.disks[i].inner_radius.as_ref();
+            upload.uniform1f(inner_location, disk.inner_radius);
+
+            let material = options.material(ObjectType::Disk, index, &disk.material);
+            set_material_uniforms(upload, &uniforms.disks[i].material, material);
         }
 
-        // Set background color
-        let bg_color_location = gl.get_uniform_location(program, "u_background_color");
-        gl.uniform3f(
-            bg_color_location.as_ref(),
-            self.background_color.x,
-            self.background_color.y,
-            self.background_color.z,
-        );
+        // Each object slot holds the object's scene index and its light
+        // linking mask, where bit i is set when light slot i shades it
+        let triangle_indices: Vec<usize> = triangles.iter().map(|&(index, _)| index).collect();
+        for (object_type, order) in [
+            (ObjectType::Sphere, &sphere_order),
+            (ObjectType::Plane, &plane_order),
+            (ObjectType::Box, &box_order),
+            (ObjectType::Cylinder, &cylinder_order),
+            (ObjectType::Triangle, &triangle_indices),
+            (ObjectType::Cone, &cone_order),
+            (ObjectType::Disk, &disk_order),
+        ] {
+            for (slot, &index) in order.iter().enumerate() {
+                let mask = light_mask(lights, object_type.object_id(index));
+                let slot_location = uniforms.slots[object_type.ordinal()][slot].as_ref();
+                upload.uniform2f(slot_location, index as f32, mask as f32);
+            }
+        }
+    }
 
-        Ok(())
+    /// Packs the lights and volumes for the `SceneLights` uniform block.
+    pub fn light_block(&self, options: &PackingOptions) -> LightBlock {
+        let vec3 = |v: Vec3| [v.x, v.y, v.z];
+        let mut block = LightBlock::default();
+        let lights = self.uploaded_lights(options);
+        for (slot, light) in block.lights.iter_mut().zip(&lights) {
+            *slot = BlockLight {
+                position: vec3(light.position),
+                color: vec3(light.color),
+                intensity: light.intensity,
+                ..BlockLight::default()
+            };
+        }
+        let volumes = &self.volumes[..self.volumes.len().min(MAX_VOLUMES)];
+        for (slot, volume) in block.volumes.iter_mut().zip(volumes) {
+            *slot = BlockVolume {
+                center: vec3(volume.center),
+                radius: volume.radius,
+                density: volume.density.max(0.0),
+                color: vec3(volume.color),
+                ..BlockVolume::default()
+            };
+        }
+        block.light_count = lights.len() as i32;
+        block.volume_count = volumes.len() as i32;
+        block
     }
 
+    /// What `pack_data` packs with `options`: equal keys give equal data, so
+    /// the data texture is only packed again when the key changes.
+    pub fn pack_key(&self, options: &PackingOptions) -> PackKey {
+        let mut options = options.clone();
+        // The camera only breaks ties among objects over their cap
+        let capped = ObjectType::ALL
+            .into_iter()
+            .any(|kind| self.object_count(kind) > kind.capacity(true));
+        if !capped {
+            options.camera_position = None;
+        }
+        PackKey {
+            revision: self.revision,
+            options,
+        }
+    }
+
+    /// Packs the objects for the scene data texture. The texels of a row:
+    ///
+    /// - 0-2: geometry; sphere center and radius, then its scale and image
+    ///   texture slot; plane point and normal; box center and size;
+    ///   cylinder base and radius, then axis; triangle vertices; cone apex
+    ///   and radius, then axis; disk center and radius, then normal and
+    ///   inner radius
+    /// - 3: albedo and material type
+    /// - 4: emission and emission strength
+    /// - 5: roughness, IOR, opacity and shadow catcher flag
+    /// - 6: first checker color and checker scale
+    /// - 7: second checker color and texture space
+    /// - 8: scene index, light linking mask and dispersion
+    ///
+    /// The triangles' BVH, from `bvh`, follows them a node per row: its
+    /// bounds' min and offset, then its max and triangle count. `bvh` must
+    /// only ever see this scene, and `now` is the clock timing its builds.
+    #[cfg(feature = "webgl")]
+    pub fn pack_data(
+        &self,
+        options: &PackingOptions,
+        bvh: &mut BvhCache,
+        now: impl Fn() -> f64,
+    ) -> SceneData {
+        let camera = options.camera_position;
+        let texel = |v: Vec3, w: f32| [v.x, v.y, v.z, w];
+        let mut rows: Vec<(ObjectType, usize, [[f32; 4]; 4], &Material)> = Vec::new();
+
+        for index in self.upload_order(ObjectType::Sphere, camera, MAX_DATA_OBJECTS) {
+            let o = &self.spheres[index];
+            let image_texture = o.image_texture.map_or(-1.0, |slot| slot as f32);
+            let geometry = [
+                texel(o.center, o.radius),
+                texel(o.scale, image_texture),
+                [0.0; 4],
+                [0.0; 4],
+            ];
+            let material = options.material(ObjectType::Sphere, index, &o.material);
+            rows.push((ObjectType::Sphere, index, geometry, material));
+        }
+        for index in self.upload_order(ObjectType::Plane, camera, MAX_DATA_OBJECTS) {
+            let o = &self.planes[index];
+            let geometry = [texel(o.point, 0.0), texel(o.normal, 0.0), [0.0; 4], [0.0; 4]];
+            let material = options.material(ObjectType::Plane, index, &o.material);
+            rows.push((ObjectType::Plane, index, geometry, material));
+        }
+        for index in self.upload_order(ObjectType::Box, camera, MAX_DATA_OBJECTS) {
+            let o = &self.boxes[index];
+            let rotation = Quat::from_euler(o.rotation);
+            let geometry = [
+                texel(o.center, 0.0),
+                texel(o.size, 0.0),
+                [rotation.x, rotation.y, rotation.z, rotation.w],
+                texel(o.scale, 0.0),
+            ];
+            let material = options.material(ObjectType::Box, index, &o.material);
+            rows.push((ObjectType::Box, index, geometry, material));
+        }
+        for index in self.upload_order(ObjectType::Cylinder, camera, MAX_DATA_OBJECTS) {
+            let o = &self.cylinders[index];
+            let rotation = Quat::from_euler(o.rotation);
+            let geometry = [
+                texel(o.base, o.radius),
+                texel(o.axis, 0.0),
+                [rotation.x, rotation.y, rotation.z, rotation.w],
+                texel(o.scale, 0.0),
+            ];
+            let material = options.material(ObjectType::Cylinder, index, &o.material);
+            rows.push((ObjectType::Cylinder, index, geometry, material));
+        }
+        let triangles = self.uploaded_triangles(options, MAX_DATA_TRIANGLES);
+        let key = BvhKey {
+            revision: self.triangles_revision,
+            low_detail: options.low_detail,
+            // Over the cap the camera picks the triangles
+            selection: (triangles.len() == MAX_DATA_TRIANGLES)
+                .then(|| triangles.iter().map(|&(index, _)| index).collect()),
+        };
+        let bvh = bvh.get(key, &triangles.iter().map(|&(_, o)| o).collect::<Vec<_>>(), now);
+        for &slot in &bvh.order {
+            let (index, o) = triangles[slot];
+            let geometry = [texel(o.v0, 0.0), texel(o.v1, 0.0), texel(o.v2, 0.0), [0.0; 4]];
+            let material = self.triangle_material(options, o);
+            rows.push((ObjectType::Triangle, index, geometry, material));
+        }
+        for index in self.upload_order(ObjectType::Cone, camera, MAX_DATA_OBJECTS) {
+            let o = &self.cones[index];
+            let geometry = [texel(o.apex, o.radius), texel(o.axis, 0.0), [0.0; 4], [0.0; 4]];
+            let material = options.material(ObjectType::Cone, index, &o.material);
+            rows.push((ObjectType::Cone, index, geometry, material));
+        }
+        for index in self.upload_order(ObjectType::Disk, camera, MAX_DATA_OBJECTS) {
+            let o = &self.disks[index];
+            let geometry = [
+                texel(o.center, o.radius),
+                texel(o.normal, o.inner_radius),
+                [0.0; 4],
+                [0.0; 4],
+            ];
+            let material = options.material(ObjectType::Disk, index, &o.material);
+            rows.push((ObjectType::Disk, index, geometry, material));
+        }
+
+        let lights = self.uploaded_lights(options);
+        let mut data = SceneData {
+            texels: Vec::with_capacity((rows.len() + bvh.nodes.len()) * DATA_TEXELS * 4),
+            counts: [0; 7],
+            bvh_nodes: bvh.nodes.len(),
+        };
+        for (object_type, index, geometry, material) in rows {
+            data.counts[object_type.ordinal()] += 1;
+            let mask = light_mask(&lights, object_type.object_id(index));
+            let (checker_scale, checker_a, checker_b) = checker_params(material);
+            data.texels.extend(geometry.into_iter().flatten());
+            data.texels.extend(texel(material.albedo, shader_material_type(material) as f32));
+            data.texels.extend(texel(material.emission, material.emission_strength));
+            data.texels.extend([
+                material.roughness,
+                material.ior,
+                material.opacity,
+                material.shadow_catcher as i32 as f32,
+            ]);
+            data.texels.extend(texel(checker_a, checker_scale));
+            data.texels.extend(texel(checker_b, shader_texture_space(material) as f32));
+            data.texels.extend([index as f32, mask as f32, material.dispersion, 0.0]);
+        }
+        for node in &bvh.nodes {
+            data.texels.extend(texel(node.bounds.min, node.offset as f32));
+            data.texels.extend(texel(node.bounds.max, node.count as f32));
+            data.texels.extend([0.0; (DATA_TEXELS - 2) * 4]);
+        }
+        data
+    }
+
+    /// The material library alone, as a JSON object keyed by name.
+    pub fn export_materials_json(&self) -> String {
+        serde_json::to_string_pretty(&self.materials).unwrap_or_else(|_| "{}".to_string())
+    }
+
+    /// Merges a library written by `export_materials_json` into this scene's,
+    /// leaving objects untouched. Entries are clamped like any imported
+    /// material; ones that don't parse, or whose name is taken while
+    /// `overwrite` is false, are skipped with a warning.
+    pub fn import_materials(
+        &mut self,
+        json_data: &str,
+        overwrite: bool,
+    ) -> Result<MaterialImport, SceneError> {
+        let entries: serde_json::Map<String, serde_json::Value> = serde_json::from_str(json_data)
+            .map_err(|e| SceneError::new(format!("Failed to parse material library: {}", e)))?;
+
+        let mut result = MaterialImport::default();
+        for (name, value) in entries {
+            let material = match serde_json::from_value::<Material>(value) {
+                Ok(material) => material.clamped(),
+                Err(e) => {
+                    result.warnings.push(format!("{}: {}", name, e));
+                    continue;
+                }
+            };
+            if !overwrite && self.materials.contains_key(&name) {
+                result.warnings.push(format!("{}: already defined, kept the existing one", name));
+                continue;
+            }
+            self.materials.insert(name, material);
+            result.imported += 1;
+        }
+        if result.imported > 0 {
+            self.touch_settings();
+        }
+        Ok(result)
+    }
+
+    /// Appends the objects, lights, volumes and combinations of `other`,
+    /// moved by `offset`. They get fresh ids, and the light links of
+    /// `other`'s lights and its combinations follow its objects to their new
+    /// indices. The rest of `other`
+    /// (background, starfield, camera path and presets, materials, world
+    /// scale) is left out.
+    pub fn merge(&mut self, other: Scene, offset: Vec3) {
+        let firsts = ObjectType::ALL.map(|object_type| self.object_count(object_type));
+        for mut sphere in other.spheres {
+            sphere.center = sphere.center + offset;
+            sphere.meta.id = 0;
+            self.add_sphere(sphere);
+        }
+        for mut plane in other.planes {
+            plane.point = plane.point + offset;
+            plane.meta.id = 0;
+            self.add_plane(plane);
+        }
+        for mut box_obj in other.boxes {
+            box_obj.center = box_obj.center + offset;
+            box_obj.meta.id = 0;
+            self.add_box(box_obj);
+        }
+        for mut cylinder in other.cylinders {
+            cylinder.base = cylinder.base + offset;
+            cylinder.meta.id = 0;
+            self.add_cylinder(cylinder);
+        }
+        for mut triangle in other.triangles {
+            triangle.v0 = triangle.v0 + offset;
+            triangle.v1 = triangle.v1 + offset;
+            triangle.v2 = triangle.v2 + offset;
+            triangle.meta.id = 0;
+            self.add_triangle(triangle);
+        }
+        for mut cone in other.cones {
+            cone.apex = cone.apex + offset;
+            cone.meta.id = 0;
+            self.add_cone(cone);
+        }
+        for mut disk in other.disks {
+            disk.center = disk.center + offset;
+            disk.meta.id = 0;
+            self.add_disk(disk);
+        }
+        for mut csg in other.csg {
+            for operand in [&mut csg.a, &mut csg.b] {
+                operand.index += firsts[operand.kind.ordinal()];
+            }
+            self.add_csg(csg);
+        }
+        for mut light in other.lights {
+            light.position = light.position + offset;
+            light.link.offset_objects(firsts);
+            light.id = 0;
+            self.add_light(light);
+        }
+        for mut volume in other.volumes {
+            volume.center = volume.center + offset;
+            self.add_volume(volume);
+        }
+    }
+
+    /// The scene as JSON, marked with the `SCENE_VERSION` it is written in.
     pub fn to_json(&self) -> String {
-        serde_json::to_string_pretty(self).unwrap_or_else(|_| "{}".to_string())
+        let mut document = match serde_json::to_value(self) {
+            Ok(document) => document,
+            Err(_) => return "{}".to_string(),
+        };
+        if let Some(fields) = document.as_object_mut() {
+            fields.insert("version".to_string(), SCENE_VERSION.into());
+        }
+        serde_json::to_string_pretty(&document).unwrap_or_else(|_| "{}".to_string())
     }
 
-    pub fn from_json(json_data: &str) -> Result<Self, JsValue> {
-        serde_json::from_str(json_data)
-            .map_err(|e| JsValue::from_str(&format!("Failed to parse JSON: {}", e)))
+    /// The scene in a compact binary form that stores triangles as raw
+    /// numbers, for scenes too large for JSON to be quick; see
+    /// `scene_binary` for the layout.
+    pub fn to_binary(&self) -> Vec<u8> {
+        scene_binary::encode(self)
     }
 
-    // Blender integration helpers
-    pub fn from_blender_json(json_data: &str) -> Result<Self, JsValue> {
-        // This is a simplified version - in practice you'd parse Blender's export format
-        // For now, let's assume a simplified format
-        let blender_data: serde_json::Value = serde_json::from_str(json_data)
-            .map_err(|e| JsValue::from_str(&format!("Failed to parse Blender JSON: {}", e)))?;
+    /// The scene tessellated into Wavefront OBJ text, with `segments`
+    /// divisions around spheres and cylinders; see `obj_export`. The
+    /// materials are in `to_mtl`, which the OBJ loads as `mtl_file`.
+    pub fn to_obj(&self, segments: u32, mtl_file: &str) -> String {
+        obj_export::write_obj(self, segments, mtl_file)
+    }
 
-        let mut scene = Scene::new();
+    /// The materials of `to_obj` as a Wavefront MTL file.
+    pub fn to_mtl(&self) -> String {
+        obj_export::write_mtl(self)
+    }
+
+    /// Reads a scene written by `to_binary`.
+    pub fn from_binary(bytes: &[u8]) -> Result<Self, SceneError> {
+        scene_binary::decode(bytes)
+    }
+
+    /// Imports a glTF 2.0 document, with `bin` as the contents of the
+    /// external file its first buffer names (if any). Returns the scene and
+    /// warnings for what it couldn't bring in; see `gltf` for what's read.
+    pub fn from_gltf(json: &str, bin: &[u8]) -> Result<(Self, Vec<String>), SceneError> {
+        let import = gltf::import(json, bin)?;
+        Ok((import.scene, import.warnings))
+    }
+
+    /// Imports a binary glTF (`.glb`) file, as `from_gltf`.
+    pub fn from_glb(bytes: &[u8]) -> Result<(Self, Vec<String>), SceneError> {
+        let import = gltf::import_glb(bytes)?;
+        Ok((import.scene, import.warnings))
+    }
+
+    /// Reads a scene written by `to_json`, upgrading files of older format
+    /// versions (see `migrate`).
+    pub fn from_json(json_data: &str) -> Result<Self, SceneError> {
+        let parse_error =
+            |e: serde_json::Error| SceneError::new(format!("Failed to parse JSON: {}", e));
+        let header: VersionHeader = serde_json::from_str(json_data).map_err(parse_error)?;
+        let version = header.version.unwrap_or(1);
+        check_version(version)?;
+
+        // Current files are parsed straight from the text, which keeps line
+        // numbers in the error messages
+        let mut scene: Self = if version == SCENE_VERSION {
+            serde_json::from_str(json_data).map_err(parse_error)?
+        } else {
+            let mut document: Value = serde_json::from_str(json_data).map_err(parse_error)?;
+            migrate(&mut document)?;
+            serde_json::from_value(document).map_err(parse_error)?
+        };
+        scene.assign_ids();
+        Ok(scene)
+    }
+
+    /// Reads the JSON written by a Blender export script, still in
+    /// Blender's coordinates; see `blender` for the schema and how objects
+    /// map onto the scene's.
+    pub fn from_blender_json(json_data: &str) -> Result<Self, SceneError> {
+        blender::import(json_data)
+    }
+}
+
+/// Outcome of [`Scene::import_materials`].
+#[derive(Clone, Debug, Default)]
+pub struct MaterialImport {
+    pub imported: u32,
+    pub warnings: Vec<String>,
+}
+
+/// Chained construction of a [`Scene`], mostly for native tools that write
+/// scene JSON for the viewer.
+///
+/// ```
+/// use raytracer::material::Material;
+/// use raytracer::math::Vec3;
+/// use raytracer::scene::Scene;
+///
+/// let scene = Scene::builder()
+///     .plane(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), Material::lambertian(Vec3::new(0.5, 0.5, 0.5)))
+///     .sphere(Vec3::new(0.0, 1.0, 0.0), 1.0, Material::metal(Vec3::new(0.9, 0.9, 0.9), 0.1))
+///     .light(Vec3::new(2.0, 5.0, 2.0), Vec3::one(), 20.0)
+///     .build();
+///
+/// let json = scene.to_json();
+/// let loaded = Scene::from_json(&json).unwrap();
+/// assert_eq!(loaded.spheres.len(), 1);
+/// assert_eq!(loaded.lights.len(), 1);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SceneBuilder {
+    scene: Scene,
+}
+
+impl SceneBuilder {
+    pub fn sphere(mut self, center: Vec3, radius: f32, material: Material) -> Self {
+        self.scene.add_sphere(Sphere::new(center, radius, material));
+        self
+    }
+
+    pub fn plane(mut self, point: Vec3, normal: Vec3, material: Material) -> Self {
+        self.scene.add_plane(Plane::new(point, normal, material));
+        self
+    }
+
+    /// Adds an axis-aligned [`Box`]; `size` is the full edge length per axis.
+    pub fn cuboid(mut self, center: Vec3, size: Vec3, material: Material) -> Self {
+        self.scene.add_box(Box::new(center, size, material));
+        self
+    }
+
+    pub fn cylinder(mut self, base: Vec3, axis: Vec3, radius: f32, material: Material) -> Self {
+        self.scene.add_cylinder(Cylinder::new(base, axis, radius, material));
+        self
+    }
+
+    pub fn triangle(mut self, v0: Vec3, v1: Vec3, v2: Vec3, material: Material) -> Self {
+        self.scene.add_triangle(Triangle::new(v0, v1, v2, material));
+        self
+    }
+
+    pub fn cone(mut self, apex: Vec3, axis: Vec3, radius: f32, material: Material) -> Self {
+        self.scene.add_cone(Cone::new(apex, axis, radius, material));
+        self
+    }
+
+    pub fn disk(
+        mut self,
+        center: Vec3,
+        normal: Vec3,
+        radius: f32,
+        inner_radius: f32,
+        material: Material,
+    ) -> Self {
+        self.scene.add_disk(Disk::new(center, normal, radius, inner_radius, material));
+        self
+    }
+
+    pub fn mesh(mut self, mesh: Mesh) -> Self {
+        self.scene.add_mesh(mesh);
+        self
+    }
+
+    pub fn light(mut self, position: Vec3, color: Vec3, intensity: f32) -> Self {
+        self.scene.add_light(Light::new(position, color, intensity));
+        self
+    }
+
+    pub fn volume(mut self, center: Vec3, radius: f32, density: f32, color: Vec3) -> Self {
+        self.scene.add_volume(Volume::new(center, radius, density, color));
+        self
+    }
+
+    pub fn background(mut self, color: Vec3) -> Self {
+        self.scene.set_background(color);
+        self
+    }
+
+    pub fn starfield(mut self, starfield: Starfield) -> Self {
+        self.scene.starfield = Some(starfield);
+        self
+    }
+
+    pub fn build(self) -> Scene {
+        self.scene
+    }
+}
+
+/// Indices of the objects to upload for one kind of object, in slot order,
+/// from each object's `(priority, position)`. When they all fit they keep
+/// their scene order; otherwise the highest priority wins, then the object
+/// nearest `camera`, then the one added first.
+fn slot_order(
+    objects: impl Iterator<Item = (i32, Vec3)>,
+    camera: Option<Vec3>,
+    max: usize,
+) -> Vec<usize> {
+    let mut keyed: Vec<(usize, i32, f32)> = objects
+        .enumerate()
+        .map(|(index, (priority, position))| {
+            let distance = camera.map_or(0.0, |camera| (position - camera).length_squared());
+            (index, priority, distance)
+        })
+        .collect();
+    if keyed.len() > max {
+        // Stable, so equal keys stay in insertion order
+        keyed.sort_by(|a, b| b.1.cmp(&a.1).then(a.2.total_cmp(&b.2)));
+        keyed.truncate(max);
+    }
+    keyed.into_iter().map(|(index, _, _)| index).collect()
+}
+
+/// Light linking mask of object `id`: bit i is set when light slot i of
+/// `lights` shades it.
+#[cfg(feature = "webgl")]
+fn light_mask(lights: &[&Light], id: u32) -> u32 {
+    lights
+        .iter()
+        .enumerate()
+        .filter(|(_, light)| light.link.affects(id))
+        .map(|(light_slot, _)| 1 << light_slot)
+        .sum()
+}
+
+/// Forwards uniform uploads to `gl`, adding up the bytes actually sent.
+#[cfg(feature = "webgl")]
+struct UniformUpload<'a> {
+    gl: &'a WebGlRenderingContext,
+    bytes: usize,
+}
+
+#[cfg(feature = "webgl")]
+impl UniformUpload<'_> {
+    fn count(&mut self, location: Option<&WebGlUniformLocation>, components: usize) {
+        // Uniforms the compiler dropped have no location and cost nothing
+        if location.is_some() {
+            self.bytes += components * 4;
+        }
+    }
+
+    fn uniform1i(&mut self, location: Option<&WebGlUniformLocation>, x: i32) {
+        self.count(location, 1);
+        self.gl.uniform1i(location, x);
+    }
+
+    fn uniform1f(&mut self, location: Option<&WebGlUniformLocation>, x: f32) {
+        self.count(location, 1);
+        self.gl.uniform1f(location, x);
+    }
+
+    fn uniform2f(&mut self, location: Option<&WebGlUniformLocation>, x: f32, y: f32) {
+        self.count(location, 2);
+        self.gl.uniform2f(location, x, y);
+    }
+
+    fn uniform3f(&mut self, location: Option<&WebGlUniformLocation>, x: f32, y: f32, z: f32) {
+        self.count(location, 3);
+        self.gl.uniform3f(location, x, y, z);
+    }
+
+    fn uniform4f(
+        &mut self,
+        location: Option<&WebGlUniformLocation>,
+        x: f32,
+        y: f32,
+        z: f32,
+        w: f32,
+    ) {
+        self.count(location, 4);
+        self.gl.uniform4f(location, x, y, z, w);
+    }
+}
+
+/// Uploads `material` into the `material` member of a shader struct.
+#[cfg(feature = "webgl")]
+fn set_material_uniforms(
+    upload: &mut UniformUpload,
+    uniforms: &MaterialUniforms,
+    material: &Material,
+) {
+    let albedo_location = uniforms.albedo.as_ref();
+    upload.uniform3f(
+        albedo_location,
+        material.albedo.x,
+        material.albedo.y,
+        material.albedo.z,
+    );
+
+    let material_type_location = uniforms.material_type.as_ref();
+    upload.uniform1i(material_type_location, shader_material_type(material));
+
+    let roughness_location = uniforms.roughness.as_ref();
+    upload.uniform1f(roughness_location, material.roughness);
+
+    let ior_location = uniforms.ior.as_ref();
+    upload.uniform1f(ior_location, material.ior);
+
+    let emission_location = uniforms.emission.as_ref();
+    upload.uniform3f(
+        emission_location,
+        material.emission.x,
+        material.emission.y,
+        material.emission.z,
+    );
+
+    let emission_strength_location =
+        uniforms.emission_strength.as_ref();
+    upload.uniform1f(emission_strength_location, material.emission_strength);
+
+    let opacity_location = uniforms.opacity.as_ref();
+    upload.uniform1f(opacity_location, material.opacity);
+
+    let shadow_catcher_location = uniforms.shadow_catcher.as_ref();
+    upload.uniform1i(shadow_catcher_location, material.shadow_catcher as i32);
+
+    let texture_space_location = uniforms.texture_space.as_ref();
+    upload.uniform1i(texture_space_location, shader_texture_space(material));
+
+    let dispersion_location = uniforms.dispersion.as_ref();
+    upload.uniform1f(dispersion_location, material.dispersion);
+
+    let (checker_scale, checker_a, checker_b) = checker_params(material);
+    let checker_scale_location = uniforms.checker_scale.as_ref();
+    upload.uniform1f(checker_scale_location, checker_scale);
+    let checker_a_location = uniforms.checker_a.as_ref();
+    upload.uniform3f(checker_a_location, checker_a.x, checker_a.y, checker_a.z);
+    let checker_b_location = uniforms.checker_b.as_ref();
+    upload.uniform3f(checker_b_location, checker_b.x, checker_b.y, checker_b.z);
+}
+
+/// `Material.material_type` as the shader numbers it.
+#[cfg(feature = "webgl")]
+fn shader_material_type(material: &Material) -> i32 {
+    match material.material_type {
+        MaterialType::Lambertian => 0,
+        MaterialType::Metal => 1,
+        MaterialType::Dielectric => 2,
+        MaterialType::Emissive => 3,
+    }
+}
+
+/// `Material.texture_space` as the shader numbers it.
+#[cfg(feature = "webgl")]
+fn shader_texture_space(material: &Material) -> i32 {
+    match material.texture_space {
+        TextureSpace::World => 0,
+        TextureSpace::Object => 1,
+    }
+}
+
+/// The checker's scale and two colors. A scale of 0 tells the shader there is
+/// no checker.
+#[cfg(feature = "webgl")]
+fn checker_params(material: &Material) -> (f32, Vec3, Vec3) {
+    match material.texture {
+        Some(Texture::Checker {
+            color_a,
+            color_b,
+            scale,
+        }) => (scale, color_a, color_b),
+        None => (0.0, Vec3::zero(), Vec3::zero()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::{offset_of, size_of};
+
+    const FRAGMENT_SHADER: &str = include_str!("../shaders/fragment.glsl");
+
+    /// Size and base alignment of a GLSL type under std140, with the
+    /// offset of each member of the structs among them.
+    struct Std140 {
+        size: usize,
+        align: usize,
+        members: Vec<(String, usize)>,
+    }
 
-        // Parse objects
-        if let Some(objects) = blender_data.get("objects").and_then(|o| o.as_array()) {
-            for obj in objects {
-                if let Some(obj_type) = obj.get("type").and_then(|t| t.as_str()) {
-                    match obj_type {
-                        "MESH" => {
-                            // For simplicity, treat all meshes as spheres
-                            if let (Some(location), Some(scale)) = (
-                                obj.get("location").and_then(|l| l.as_array()),
-                                obj.get("scale").and_then(|s| s.as_array()),
-                            ) {
-                                if location.len() >= 3 && scale.len() >= 3 {
-                                    let center = Vec3::new(
-                                        location[0].as_f64().unwrap_or(0.0) as f32,
-                                        location[1].as_f64().unwrap_or(0.0) as f32,
-                                        location[2].as_f64().unwrap_or(0.0) as f32,
-                                    );
-                                    let radius = scale[0].as_f64().unwrap_or(1.0) as f32;
-
-                                    // Use default material for now
-                                    let material = Material::lambertian(Vec3::new(0.7, 0.7, 0.7));
-                                    scene.add_sphere(Sphere::new(center, radius, material));
-                                }
-                            }
-                        }
-                        "LIGHT" => {
-                            if let Some(location) = obj.get("location").and_then(|l| l.as_array()) {
-                                if location.len() >= 3 {
-                                    let position = Vec3::new(
-                                        location[0].as_f64().unwrap_or(0.0) as f32,
-                                        location[1].as_f64().unwrap_or(0.0) as f32,
-                                        location[2].as_f64().unwrap_or(0.0) as f32,
-                                    );
-
-                                    let color = Vec3::new(1.0, 1.0, 1.0);
-                                    let intensity =
-                                        obj.get("energy").and_then(|e| e.as_f64()).unwrap_or(10.0)
-                                            as f32;
-
-                                    scene.add_light(Light::new(position, color, intensity));
-                                }
-                            }
-                        }
-                        _ => {}
+    fn round_up(value: usize, align: usize) -> usize {
+        value.div_ceil(align) * align
+    }
+
+    /// The `type name;` or `type name[N];` lines between `header` and the
+    /// next `};` of the fragment shader.
+    fn declared_members(header: &str) -> Vec<(String, String, Option<usize>)> {
+        let start = FRAGMENT_SHADER
+            .find(header)
+            .unwrap_or_else(|| panic!("`{}` is not in fragment.glsl", header));
+        let body = &FRAGMENT_SHADER[start + header.len()..];
+        let body = &body[..body.find("};").unwrap()];
+        body.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with("//"))
+            .map(|line| {
+                let (ty, name) = line.trim_end_matches(';').split_once(' ').unwrap();
+                match name.split_once('[') {
+                    Some((name, count)) => {
+                        let count = count.trim_end_matches(']').parse().unwrap();
+                        (ty.to_string(), name.to_string(), Some(count))
                     }
+                    None => (ty.to_string(), name.to_string(), None),
+                }
+            })
+            .collect()
+    }
+
+    /// Lays out `members` one after the other by the std140 rules.
+    fn layout(members: &[(String, String, Option<usize>)]) -> Std140 {
+        let mut offset = 0;
+        let mut align = 16;
+        let mut offsets = Vec::new();
+        for (ty, name, count) in members {
+            let element = glsl_type(ty);
+            let (size, member_align) = match count {
+                // Array elements are padded out to a vec4
+                Some(count) => (round_up(element.size, 16) * count, 16),
+                None => (element.size, element.align),
+            };
+            offset = round_up(offset, member_align);
+            offsets.push((name.clone(), offset));
+            offset += size;
+            align = align.max(member_align);
+        }
+        Std140 {
+            size: round_up(offset, align),
+            align,
+            members: offsets,
+        }
+    }
+
+    fn glsl_type(ty: &str) -> Std140 {
+        let (size, align) = match ty {
+            "float" | "int" => (4, 4),
+            "vec2" => (8, 8),
+            "vec3" => (12, 16),
+            "vec4" => (16, 16),
+            _ => return layout(&declared_members(&format!("struct {} {{", ty))),
+        };
+        Std140 {
+            size,
+            align,
+            members: Vec::new(),
+        }
+    }
+
+    fn offset(layout: &Std140, member: &str) -> usize {
+        layout
+            .members
+            .iter()
+            .find(|(name, _)| name == member)
+            .unwrap_or_else(|| panic!("no member `{}`", member))
+            .1
+    }
+
+    /// The scene files in `fixtures/scenes`: `v<n>.json` as version `n` of the
+    /// format wrote it, and `unversioned.json` from before files were marked.
+    fn scene_fixtures() -> Vec<(String, String)> {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/scenes");
+        let mut fixtures: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .map(|path| {
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                (name, std::fs::read_to_string(&path).unwrap())
+            })
+            .collect();
+        fixtures.sort();
+        fixtures
+    }
+
+    /// Fails unless every value in `part` is in `full` at the same path,
+    /// numbers to within f32 rounding.
+    fn assert_contains(full: &Value, part: &Value, path: &str) {
+        match (full, part) {
+            (Value::Object(full), Value::Object(part)) => {
+                for (key, value) in part {
+                    let Some(full_value) = full.get(key) else {
+                        panic!("{}.{} was dropped", path, key);
+                    };
+                    assert_contains(full_value, value, &format!("{}.{}", path, key));
                 }
             }
+            (Value::Array(full), Value::Array(part)) => {
+                assert_eq!(full.len(), part.len(), "{} changed length", path);
+                for (index, (full, part)) in full.iter().zip(part).enumerate() {
+                    assert_contains(full, part, &format!("{}[{}]", path, index));
+                }
+            }
+            (Value::Number(full), Value::Number(part)) => {
+                let (full, part) = (full.as_f64().unwrap(), part.as_f64().unwrap());
+                assert!(
+                    (full - part).abs() <= 1e-6 * part.abs().max(1.0),
+                    "{}: {} became {}",
+                    path,
+                    part,
+                    full
+                );
+            }
+            _ => assert_eq!(full, part, "{} changed", path),
         }
+    }
 
-        Ok(scene)
+    #[test]
+    fn every_scene_version_has_a_fixture() {
+        let names: Vec<String> = scene_fixtures().into_iter().map(|(name, _)| name).collect();
+        assert!(names.contains(&"unversioned.json".to_string()));
+        for version in 1..=SCENE_VERSION {
+            let name = format!("v{}.json", version);
+            assert!(names.contains(&name), "no fixture for scene version {}", version);
+        }
+    }
+
+    #[test]
+    fn scene_fixtures_migrate_and_round_trip() {
+        for (name, json) in scene_fixtures() {
+            let mut document: Value = serde_json::from_str(&json).unwrap();
+            migrate(&mut document).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert_eq!(document["version"], SCENE_VERSION, "{}", name);
+
+            let scene = Scene::from_json(&json).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert!(!scene.spheres.is_empty(), "{} loaded no spheres", name);
+            assert!(!scene.lights.is_empty(), "{} loaded no lights", name);
+            let written = scene.to_json();
+            let rewritten: Value = serde_json::from_str(&written).unwrap();
+            assert_contains(&rewritten, &document, &name);
+
+            // Writing it again changes nothing
+            assert_eq!(Scene::from_json(&written).unwrap().to_json(), written, "{}", name);
+        }
+    }
+
+    #[test]
+    fn newer_scene_versions_are_refused() {
+        let mut document: Value = serde_json::from_str(&scene_fixtures()[0].1).unwrap();
+        document["version"] = (SCENE_VERSION + 1).into();
+        let error = Scene::from_json(&document.to_string()).err().unwrap();
+        assert!(error.to_string().contains("requires a newer raytracer"), "{}", error);
+        assert!(migrate(&mut document).is_err());
+    }
+
+    fn grey() -> Material {
+        Material::lambertian(Vec3::new(0.5, 0.5, 0.5))
+    }
+
+    fn sphere_json(sphere: &Sphere) -> Value {
+        serde_json::to_value(sphere).unwrap()
+    }
+
+    #[test]
+    fn unit_sphere_scale_is_left_out_of_json() {
+        let mut sphere = Sphere::new(Vec3::zero(), 1.0, grey());
+        assert!(!sphere.is_ellipsoid());
+        assert!(sphere_json(&sphere).get("scale").is_none());
+
+        sphere.scale = Vec3::new(2.0, 0.5, 1.0);
+        assert!(sphere.is_ellipsoid());
+        let json = sphere_json(&sphere);
+        assert_eq!(json["scale"]["x"], 2.0);
+        let loaded: Sphere = serde_json::from_value(json).unwrap();
+        assert_eq!(loaded.scale, Vec3::new(2.0, 0.5, 1.0));
+    }
+
+    #[test]
+    fn object_meta_sits_flat_in_the_object_json() {
+        let mut sphere = Sphere::new(Vec3::zero(), 1.0, grey());
+        sphere.meta = ObjectMeta {
+            locked: true,
+            priority: -1,
+            id: 7,
+            name: "moon".to_string(),
+            revision: 3,
+        };
+        let json = sphere_json(&sphere);
+        assert_eq!(json["locked"], true);
+        assert_eq!(json["priority"], -1);
+        assert_eq!(json["id"], 7);
+        assert_eq!(json["name"], "moon");
+        assert!(json.get("meta").is_none() && json.get("revision").is_none());
+
+        let loaded: Sphere = serde_json::from_value(json).unwrap();
+        assert!(loaded.meta.locked);
+        assert_eq!((loaded.meta.priority, loaded.meta.id), (-1, 7));
+        assert_eq!(loaded.meta.name, "moon");
+        assert_eq!(loaded.meta.revision, 0);
+    }
+
+    #[test]
+    fn missing_sphere_scale_loads_as_one() {
+        let mut json = sphere_json(&Sphere::new(Vec3::zero(), 1.0, grey()));
+        json.as_object_mut().unwrap().remove("scale");
+        let sphere: Sphere = serde_json::from_value(json).unwrap();
+        assert_eq!(sphere.scale, Vec3::one());
+    }
+
+    #[test]
+    fn ellipsoid_bounds_follow_the_scale() {
+        let mut sphere = Sphere::new(Vec3::new(1.0, 2.0, 3.0), 2.0, grey());
+        sphere.scale = Vec3::new(1.5, 0.25, 1.0);
+        let bounds = sphere.bounds();
+        assert_eq!(bounds.min, Vec3::new(-2.0, 1.5, 1.0));
+        assert_eq!(bounds.max, Vec3::new(4.0, 2.5, 5.0));
+        assert_eq!(sphere.bounding_radius(), 3.0);
+    }
+
+    #[test]
+    fn non_positive_sphere_scale_is_a_problem() {
+        for scale in [Vec3::new(1.0, 0.0, 1.0), Vec3::new(-1.0, 1.0, 1.0)] {
+            let mut sphere = Sphere::new(Vec3::zero(), 1.0, grey());
+            sphere.scale = scale;
+            let problems = sphere.problems(0);
+            assert_eq!(problems.len(), 1, "{:?}", problems);
+            assert!(problems[0].to_string().contains("scale"), "{}", problems[0]);
+        }
+    }
+
+    fn operand(kind: ObjectType, index: usize) -> ObjectRef {
+        ObjectRef { kind, index }
+    }
+
+    #[test]
+    fn drawn_csg_skips_invalid_combinations_and_caps_the_rest() {
+        let mut scene = Scene::new();
+        for x in 0..3 {
+            scene.add_sphere(Sphere::new(Vec3::new(x as f32, 0.0, 0.0), 1.0, grey()));
+        }
+        scene.add_box(Box::new(Vec3::zero(), Vec3::one(), grey()));
+        scene.add_cylinder(Cylinder::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 1.0, grey()));
+        let csg = |a: ObjectRef, b: ObjectRef| CsgOp {
+            op: CsgOperation::Union,
+            a,
+            b,
+        };
+        let (sphere, box_obj) = (ObjectType::Sphere, ObjectType::Box);
+        // An object with itself, an unsupported kind and a missing object
+        scene.add_csg(csg(operand(sphere, 0), operand(sphere, 0)));
+        scene.add_csg(csg(operand(sphere, 0), operand(ObjectType::Cylinder, 0)));
+        scene.add_csg(csg(operand(sphere, 0), operand(sphere, 3)));
+        let valid = [
+            csg(operand(sphere, 0), operand(sphere, 1)),
+            csg(operand(sphere, 1), operand(box_obj, 0)),
+            csg(operand(box_obj, 0), operand(sphere, 2)),
+            csg(operand(sphere, 2), operand(sphere, 0)),
+            csg(operand(sphere, 1), operand(sphere, 2)),
+        ];
+        for csg in valid {
+            scene.add_csg(csg);
+        }
+
+        let drawn = scene.drawn_csg();
+        assert_eq!(drawn.len(), MAX_CSG);
+        assert_eq!(drawn, valid[..MAX_CSG]);
+    }
+
+    #[test]
+    fn csg_survives_a_json_round_trip() {
+        let mut scene = Scene::new();
+        scene.add_sphere(Sphere::new(Vec3::zero(), 1.0, grey()));
+        scene.add_box(Box::new(Vec3::zero(), Vec3::one(), grey()));
+        scene.add_csg(CsgOp {
+            op: CsgOperation::Subtract,
+            a: operand(ObjectType::Box, 0),
+            b: operand(ObjectType::Sphere, 0),
+        });
+        let loaded = Scene::from_json(&scene.to_json()).unwrap();
+        assert_eq!(loaded.csg, scene.csg);
+        let subtract = CsgOperation::Subtract;
+        assert_eq!(CsgOperation::from_u32(subtract.to_u32()), Some(subtract));
+        assert_eq!(CsgOperation::from_u32(3), None);
+    }
+
+    #[test]
+    fn untransformed_boxes_and_cylinders_have_no_transform() {
+        let mut box_obj = Box::new(Vec3::zero(), Vec3::one(), grey());
+        let cylinder = Cylinder::new(Vec3::zero(), Vec3::new(0.0, 2.0, 0.0), 0.5, grey());
+        assert!(box_obj.transform().is_none());
+        assert!(cylinder.transform().is_none());
+        let json = serde_json::to_value(&box_obj).unwrap();
+        assert!(json.get("rotation").is_none() && json.get("scale").is_none());
+
+        box_obj.rotation = Vec3::new(0.0, 0.5, 0.0);
+        box_obj.scale = Vec3::new(1.0, 2.0, 1.0);
+        let loaded: Box = serde_json::from_value(serde_json::to_value(&box_obj).unwrap()).unwrap();
+        assert_eq!(loaded.rotation, box_obj.rotation);
+        assert_eq!(loaded.scale, box_obj.scale);
+        assert_eq!(loaded.transform().unwrap().pivot, Vec3::zero());
+    }
+
+    #[test]
+    fn cylinder_turns_about_the_middle_of_its_axis() {
+        let base = Vec3::new(0.0, 1.0, 0.0);
+        let mut cylinder = Cylinder::new(base, Vec3::new(0.0, 2.0, 0.0), 0.5, grey());
+        cylinder.rotation = Vec3::new(0.0, 0.0, std::f32::consts::FRAC_PI_2);
+        let transform = cylinder.transform().unwrap();
+        assert_eq!(transform.pivot, Vec3::new(0.0, 2.0, 0.0));
+
+        // Lying along x, from x = -1 to x = 1 at the height of the middle
+        let bounds = cylinder.bounds();
+        let close = |a: Vec3, b: Vec3| (a - b).length() < 1e-5;
+        assert!(close(bounds.min, Vec3::new(-1.0, 1.5, -0.5)), "{:?}", bounds);
+        assert!(close(bounds.max, Vec3::new(1.0, 2.5, 0.5)), "{:?}", bounds);
+    }
+
+    #[test]
+    fn scaled_box_bounds_grow_with_the_scale() {
+        let mut box_obj = Box::new(Vec3::new(1.0, 0.0, 0.0), Vec3::one(), grey());
+        box_obj.scale = Vec3::new(3.0, 1.0, 0.5);
+        let bounds = box_obj.bounds();
+        assert_eq!(bounds.min, Vec3::new(-0.5, -0.5, -0.25));
+        assert_eq!(bounds.max, Vec3::new(2.5, 0.5, 0.25));
+    }
+
+    #[test]
+    fn block_light_matches_glsl_light() {
+        let light = glsl_type("Light");
+        assert_eq!(offset(&light, "position"), offset_of!(BlockLight, position));
+        assert_eq!(offset(&light, "color"), offset_of!(BlockLight, color));
+        assert_eq!(offset(&light, "intensity"), offset_of!(BlockLight, intensity));
+        assert_eq!(light.size, size_of::<BlockLight>());
+    }
+
+    #[test]
+    fn block_volume_matches_glsl_volume() {
+        let volume = glsl_type("Volume");
+        assert_eq!(offset(&volume, "center"), offset_of!(BlockVolume, center));
+        assert_eq!(offset(&volume, "radius"), offset_of!(BlockVolume, radius));
+        assert_eq!(offset(&volume, "density"), offset_of!(BlockVolume, density));
+        assert_eq!(offset(&volume, "color"), offset_of!(BlockVolume, color));
+        assert_eq!(volume.size, size_of::<BlockVolume>());
+    }
+
+    #[test]
+    fn light_block_matches_scene_lights_block() {
+        let members = declared_members("uniform SceneLights {");
+        let lights = members.iter().find(|(_, name, _)| name == "u_lights").unwrap();
+        let volumes = members.iter().find(|(_, name, _)| name == "u_volumes").unwrap();
+        assert_eq!(lights.2, Some(MAX_LIGHTS));
+        assert_eq!(volumes.2, Some(MAX_VOLUMES));
+
+        let block = layout(&members);
+        assert_eq!(offset(&block, "u_lights"), offset_of!(LightBlock, lights));
+        assert_eq!(offset(&block, "u_volumes"), offset_of!(LightBlock, volumes));
+        assert_eq!(offset(&block, "u_light_count"), offset_of!(LightBlock, light_count));
+        assert_eq!(offset(&block, "u_volume_count"), offset_of!(LightBlock, volume_count));
+        assert_eq!(block.size, size_of::<LightBlock>());
+        assert_eq!(block.size, 288);
+    }
+
+    #[test]
+    fn light_block_bytes_land_at_the_block_offsets() {
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(Vec3::new(1.0, 2.0, 3.0), Vec3::new(0.5, 0.25, 0.125), 7.0));
+        scene.add_light(Light::new(Vec3::new(4.0, 5.0, 6.0), Vec3::one(), 2.0));
+        let block = scene.light_block(&PackingOptions::default());
+        let bytes = block.as_bytes();
+        let word = |offset: usize| -> [u8; 4] { bytes[offset..offset + 4].try_into().unwrap() };
+        let float_at = |offset: usize| f32::from_ne_bytes(word(offset));
+        let int_at = |offset: usize| i32::from_ne_bytes(word(offset));
+
+        assert_eq!(bytes.len(), 288);
+        assert_eq!([float_at(0), float_at(4), float_at(8)], [1.0, 2.0, 3.0]);
+        assert_eq!([float_at(16), float_at(20), float_at(24)], [0.5, 0.25, 0.125]);
+        assert_eq!(float_at(28), 7.0);
+        // The second light starts one 32-byte array stride later
+        assert_eq!(float_at(32), 4.0);
+        assert_eq!(float_at(60), 2.0);
+        assert_eq!(int_at(272), 2);
+        assert_eq!(int_at(276), 0);
+    }
+
+    #[cfg(feature = "webgl")]
+    #[test]
+    fn bvh_is_rebuilt_only_after_triangle_changes() {
+        let mut scene = Scene::new();
+        scene.add_sphere(Sphere::new(Vec3::zero(), 1.0, grey()));
+        for i in 0..4 {
+            let x = i as f32;
+            scene.add_triangle(Triangle::new(
+                Vec3::new(x, 0.0, 0.0),
+                Vec3::new(x + 1.0, 0.0, 0.0),
+                Vec3::new(x, 1.0, 0.0),
+                grey(),
+            ));
+        }
+        let mut bvh = BvhCache::default();
+        let mut pack = |scene: &Scene| {
+            scene.pack_data(&PackingOptions::default(), &mut bvh, || 0.0);
+            bvh.stats().builds
+        };
+
+        assert_eq!(pack(&scene), 1);
+        assert_eq!(pack(&scene), 1);
+        scene.spheres[0].center = Vec3::new(2.0, 0.0, 0.0);
+        scene.touch(ObjectType::Sphere, 0);
+        assert_eq!(pack(&scene), 1);
+
+        scene.triangles[1].v0 = Vec3::new(0.0, 0.0, 5.0);
+        scene.touch(ObjectType::Triangle, 1);
+        assert_eq!(pack(&scene), 2);
+        // Removing the last triangle stamps no other one
+        scene.remove_object(ObjectType::Triangle, 3);
+        assert_eq!(pack(&scene), 3);
+
+        let mut replacement = scene.clone();
+        replacement.continue_revisions(&scene);
+        assert_eq!(pack(&replacement), 4);
+    }
+
+    #[test]
+    fn pack_key_changes_with_the_scene_and_options_only() {
+        let mut scene = Scene::new();
+        scene.add_sphere(Sphere::new(Vec3::zero(), 1.0, grey()));
+        let at = |position: Vec3| PackingOptions {
+            camera_position: Some(position),
+            data_texture: true,
+            ..PackingOptions::default()
+        };
+        let key = scene.pack_key(&at(Vec3::new(0.0, 0.0, 5.0)));
+
+        // Under every cap the camera doesn't pick anything
+        assert_eq!(scene.pack_key(&at(Vec3::new(3.0, 1.0, 5.0))), key);
+        let low_detail = PackingOptions {
+            low_detail: true,
+            ..at(Vec3::new(0.0, 0.0, 5.0))
+        };
+        assert_ne!(scene.pack_key(&low_detail), key);
+        let preview = PackingOptions {
+            material_override: Some(MaterialOverride {
+                object_type: ObjectType::Sphere,
+                index: 0,
+                material: Material::metal(Vec3::one(), 0.1),
+            }),
+            ..at(Vec3::new(0.0, 0.0, 5.0))
+        };
+        assert_ne!(scene.pack_key(&preview), key);
+
+        scene.spheres[0].radius = 2.0;
+        scene.touch(ObjectType::Sphere, 0);
+        let touched = scene.pack_key(&at(Vec3::new(0.0, 0.0, 5.0)));
+        assert_ne!(touched, key);
+
+        for i in 0..MAX_DATA_OBJECTS {
+            scene.add_sphere(Sphere::new(Vec3::new(i as f32, 0.0, 0.0), 0.1, grey()));
+        }
+        let capped = scene.pack_key(&at(Vec3::new(0.0, 0.0, 5.0)));
+        assert_ne!(scene.pack_key(&at(Vec3::new(300.0, 0.0, 5.0))), capped);
+    }
+
+    #[cfg(feature = "webgl")]
+    #[test]
+    fn material_preview_is_uploaded_but_never_exported() {
+        let mut scene = Scene::new();
+        scene.add_sphere(Sphere::new(Vec3::zero(), 1.0, grey()));
+        let exported = scene.to_json();
+        let preview = PackingOptions {
+            material_override: Some(MaterialOverride {
+                object_type: ObjectType::Sphere,
+                index: 0,
+                material: Material::metal(Vec3::new(1.0, 0.5, 0.0), 0.1),
+            }),
+            data_texture: true,
+            ..PackingOptions::default()
+        };
+
+        let plain = PackingOptions {
+            material_override: None,
+            data_texture: true,
+            ..PackingOptions::default()
+        };
+        let plain = scene.pack_data(&plain, &mut BvhCache::default(), || 0.0);
+        let previewed = scene.pack_data(&preview, &mut BvhCache::default(), || 0.0);
+        assert_eq!(
+            preview.material(ObjectType::Sphere, 0, &scene.spheres[0].material).albedo,
+            Vec3::new(1.0, 0.5, 0.0)
+        );
+        assert_ne!(previewed.texels, plain.texels);
+
+        // Packing with the preview active leaves the scene and its exports alone
+        assert_eq!(scene.to_json(), exported);
+        let reloaded = Scene::from_json(&exported).unwrap();
+        assert_eq!(reloaded.spheres[0].material, grey());
+        assert_eq!(reloaded.to_json(), exported);
+        let binary = Scene::from_binary(&scene.to_binary()).unwrap();
+        assert_eq!(binary.spheres[0].material, grey());
+    }
+
+    #[test]
+    fn upload_order_drops_the_lowest_priority_spheres_over_the_cap() {
+        let mut scene = Scene::new();
+        for i in 0..12 {
+            let mut sphere = Sphere::new(Vec3::new(i as f32 * 3.0, 0.0, 0.0), 1.0, grey());
+            if i == 3 || i == 8 {
+                sphere.meta.priority = -1;
+            }
+            scene.add_sphere(sphere);
+        }
+        // The camera sits right on the two dropped spheres; priority wins
+        for camera in [None, Some(Vec3::new(9.0, 0.0, 0.0)), Some(Vec3::new(24.0, 0.0, 0.0))] {
+            let order = scene.upload_order(ObjectType::Sphere, camera, MAX_SPHERES);
+            assert_eq!(order.len(), 10);
+            assert!(!order.contains(&3) && !order.contains(&8), "{:?}", order);
+        }
+    }
+
+    #[test]
+    fn changes_since_carries_only_the_sphere_that_changed() {
+        let mut scene = Scene::new();
+        for i in 0..400 {
+            scene.add_sphere(Sphere::new(Vec3::new(i as f32, 0.0, 0.0), 0.25, grey()));
+        }
+        scene.add_light(Light::new(Vec3::new(0.0, 10.0, 0.0), Vec3::one(), 1.0));
+        let mut receiver = scene.clone();
+        let baseline = scene.revision();
+
+        scene.spheres[123].center = Vec3::new(1.0, 2.0, 3.0);
+        scene.touch(ObjectType::Sphere, 123);
+        let patch = scene.changes_since(baseline);
+
+        assert_eq!(patch.since, baseline.to_string());
+        assert_eq!(patch.revision, scene.revision().to_string());
+        assert!(!patch.reset && patch.deleted.is_empty());
+        assert_eq!(patch.spheres.count, 400);
+        assert_eq!(patch.spheres.changed.len(), 1);
+        assert_eq!(patch.spheres.changed[0].index, 123);
+        assert_eq!(patch.spheres.changed[0].object.center, Vec3::new(1.0, 2.0, 3.0));
+        assert!(patch.lights.changed.is_empty() && patch.volumes.changed.is_empty());
+        let changed = Some(vec![ObjectType::Sphere.object_id(123)]);
+        assert_eq!(scene.object_changes_since(baseline), changed);
+
+        let next: u32 = patch.revision.parse().unwrap();
+        receiver.apply_patch(patch).unwrap();
+        assert_eq!(receiver.spheres[123].center, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(receiver.spheres[122].center, scene.spheres[122].center);
+        // Nothing changed after the patch's own token
+        let empty = scene.changes_since(next);
+        assert!(empty.spheres.changed.is_empty());
+        assert_eq!(empty.since, next.to_string());
+    }
+
+    fn problem_paths(scene: &Scene) -> Vec<(String, Value)> {
+        scene.validate().into_iter().map(|p| (p.path, p.value)).collect()
+    }
+
+    #[test]
+    fn malformed_numbers_fail_to_parse() {
+        let mut scene = Scene::new();
+        scene.add_sphere(Sphere::new(Vec3::zero(), 0.5, grey()));
+        let json = serde_json::to_string(&scene).unwrap();
+        assert!(json.contains("\"radius\":0.5"));
+
+        for bad in ["\"radius\":\"half\"", "\"radius\":1e999", "\"radius\":0.5.1"] {
+            let error = Scene::from_json(&json.replace("\"radius\":0.5", bad)).unwrap_err();
+            assert!(error.to_string().starts_with("Failed to parse JSON"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn values_the_renderer_cant_draw_are_listed_with_their_paths() {
+        let mut scene = Scene::new();
+        scene.add_sphere(Sphere::new(Vec3::zero(), -1.0, grey()));
+        let mut rough = Material::metal(Vec3::one(), 0.5);
+        rough.roughness = 1.5;
+        scene.add_sphere(Sphere::new(Vec3::zero(), 1.0, rough));
+        scene.add_sphere(Sphere::new(Vec3::zero(), 1.0, Material::dielectric(0.5)));
+        scene.add_plane(Plane::new(Vec3::zero(), Vec3::zero(), grey()));
+        // Out-of-range values still parse, so they reach the validator
+        let scene = Scene::from_json(&scene.to_json()).unwrap();
+        assert_eq!(
+            problem_paths(&scene),
+            vec![
+                ("spheres[0].radius".to_string(), Value::from(-1.0)),
+                ("spheres[1].material.roughness".to_string(), Value::from(1.5)),
+                ("spheres[2].material.ior".to_string(), Value::from(0.5)),
+                ("planes[0].normal".to_string(), serde_json::to_value(Vec3::zero()).unwrap()),
+            ]
+        );
+
+        // JSON has no NaN, so a NaN built in code reports null
+        let mut scene = Scene::new();
+        scene.add_sphere(Sphere::new(Vec3::new(f32::NAN, 0.0, 0.0), 1.0, grey()));
+        let problems = scene.validate();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].field, "center");
+        assert_eq!(problems[0].value["x"], Value::Null);
+    }
+
+    #[test]
+    fn missing_optional_fields_take_their_defaults() {
+        let mut scene = Scene::new();
+        scene.add_sphere(Sphere::new(Vec3::zero(), 1.0, grey()));
+        scene.add_disk(Disk::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 1.0, 0.0, grey()));
+        let mut json: Value = serde_json::from_str(&scene.to_json()).unwrap();
+        json.as_object_mut().unwrap().remove("world_scale");
+        for (list, keys) in [
+            ("spheres", &["locked", "priority", "id", "name", "scale"][..]),
+            ("disks", &["inner_radius"][..]),
+        ] {
+            let object = json[list][0].as_object_mut().unwrap();
+            for key in keys {
+                object.remove(*key);
+            }
+            let material = object["material"].as_object_mut().unwrap();
+            for key in ["emission", "emission_strength", "opacity", "texture_space"] {
+                material.remove(key);
+            }
+        }
+
+        let loaded = Scene::from_json(&json.to_string()).unwrap();
+        let sphere = &loaded.spheres[0];
+        assert!(!sphere.meta.locked);
+        assert_eq!(sphere.meta.priority, 0);
+        assert_ne!(sphere.meta.id, 0);
+        assert_eq!(sphere.scale, Vec3::one());
+        assert_eq!(sphere.material.opacity, 1.0);
+        assert_eq!(sphere.material.emission, Vec3::zero());
+        assert_eq!(sphere.material.texture_space, crate::material::TextureSpace::World);
+        assert_eq!(loaded.disks[0].inner_radius, 0.0);
+        assert_eq!(loaded.world_scale, 1.0);
+        assert!(loaded.validate().is_empty());
+    }
+
+    fn library() -> Scene {
+        let mut scene = Scene::new();
+        scene.materials.insert("gold".to_string(), Material::metal(Vec3::new(1.0, 0.8, 0.3), 0.2));
+        scene.materials.insert("glass".to_string(), Material::dielectric(1.5));
+        scene
+    }
+
+    #[test]
+    fn material_library_round_trips() {
+        let source = library();
+        let mut target = Scene::new();
+        let import = target.import_materials(&source.export_materials_json(), false).unwrap();
+        assert_eq!(import.imported, 2);
+        assert!(import.warnings.is_empty());
+        assert_eq!(target.materials, source.materials);
+        assert_eq!(target.export_materials_json(), source.export_materials_json());
+    }
+
+    #[test]
+    fn material_import_skips_or_overwrites_taken_names() {
+        let json = library().export_materials_json();
+        let mut target = Scene::new();
+        target.materials.insert("gold".to_string(), grey());
+
+        let import = target.import_materials(&json, false).unwrap();
+        assert_eq!(import.imported, 1);
+        assert_eq!(import.warnings.len(), 1);
+        assert!(import.warnings[0].starts_with("gold:"));
+        assert_eq!(target.materials["gold"], grey());
+
+        let import = target.import_materials(&json, true).unwrap();
+        assert_eq!(import.imported, 2);
+        assert_eq!(target.materials["gold"], library().materials["gold"]);
+    }
+
+    #[test]
+    fn material_import_clamps_and_skips_bad_entries() {
+        let mut json: Value = serde_json::from_str(&library().export_materials_json()).unwrap();
+        json["gold"]["roughness"] = Value::from(3.0);
+        json["broken"] = Value::from("not a material");
+        let mut target = Scene::new();
+        let import = target.import_materials(&json.to_string(), false).unwrap();
+        assert_eq!(import.imported, 2);
+        assert_eq!(target.materials["gold"].roughness, 1.0);
+        assert!(!target.materials.contains_key("broken"));
+        assert!(import.warnings[0].starts_with("broken:"), "{:?}", import.warnings);
+        assert!(target.import_materials("[1, 2]", false).is_err());
+    }
+
+    #[test]
+    fn material_import_never_touches_geometry() {
+        let mut scene = Scene::new();
+        scene.add_sphere(Sphere::new(Vec3::new(1.0, 2.0, 3.0), 1.0, grey()));
+        scene.add_box(Box::new(Vec3::zero(), Vec3::one(), grey()));
+        scene.add_light(Light::new(Vec3::new(0.0, 5.0, 0.0), Vec3::one(), 1.0));
+        scene.materials.insert("gold".to_string(), grey());
+        let objects = |scene: &Scene| {
+            let json: Value = serde_json::from_str(&scene.to_json()).unwrap();
+            ["spheres", "boxes", "lights"].map(|key| json[key].clone())
+        };
+        let before = objects(&scene);
+        let baseline = scene.revision();
+
+        scene.import_materials(&library().export_materials_json(), true).unwrap();
+        assert_eq!(objects(&scene), before);
+        let patch = scene.changes_since(baseline);
+        assert!(patch.spheres.changed.is_empty() && patch.boxes.changed.is_empty());
+        assert!(patch.lights.changed.is_empty());
+        assert_eq!(scene.materials.len(), 2);
     }
 }
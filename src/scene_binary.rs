@@ -0,0 +1,331 @@
+//! Compact binary form of a scene, for scenes whose triangles would make the
+//! JSON megabytes long and slow to parse.
+//!
+//! Only the triangles are stored as raw numbers. Everything else is a small
+//! JSON document inside the buffer, so it keeps the JSON format's defaults
+//! and migrations. The layout, little-endian throughout:
+//!
+//! - the magic `RTSB` and the layout version (`u32`)
+//! - the scene without its triangles, as JSON
+//! - the number of distinct triangle materials (`u32`), then each as JSON
+//! - the number of triangles (`u32`), then per triangle `v0`, `v1` and `v2`
+//!   as nine `f32`, the material's index (`u32`), `id` (`u32`), `priority`
+//!   (`i32`), `locked` (`u8`) and `name`
+//!
+//! Strings (the JSON parts and names) are a `u32` byte length followed by
+//! UTF-8.
+
+use std::collections::HashMap;
+
+use crate::material::Material;
+use crate::math::Vec3;
+use crate::scene::{Scene, SceneError, Triangle};
+
+const MAGIC: &[u8; 4] = b"RTSB";
+
+/// Version of the layout above. The embedded JSON carries its own
+/// `SCENE_VERSION`.
+pub const BINARY_VERSION: u32 = 1;
+
+pub fn encode(scene: &Scene) -> Vec<u8> {
+    let mut rest = scene.clone();
+    rest.triangles.clear();
+
+    let mut bytes = Vec::with_capacity(64 * scene.triangles.len() + 1024);
+    bytes.extend_from_slice(MAGIC);
+    write_u32(&mut bytes, BINARY_VERSION);
+    write_str(&mut bytes, &rest.to_json());
+
+    // Triangles of a mesh share a handful of materials, so each is stored
+    // once and referred to by index
+    let mut materials = Vec::new();
+    let mut material_index = HashMap::new();
+    let triangle_materials: Vec<u32> = scene
+        .triangles
+        .iter()
+        .map(|triangle| {
+            let json = serde_json::to_string(&triangle.material).unwrap_or_default();
+            *material_index.entry(json).or_insert_with_key(|json| {
+                materials.push(json.clone());
+                materials.len() as u32 - 1
+            })
+        })
+        .collect();
+    write_u32(&mut bytes, materials.len() as u32);
+    for material in &materials {
+        write_str(&mut bytes, material);
+    }
+
+    write_u32(&mut bytes, scene.triangles.len() as u32);
+    for (triangle, material) in scene.triangles.iter().zip(triangle_materials) {
+        for vertex in [triangle.v0, triangle.v1, triangle.v2] {
+            for component in [vertex.x, vertex.y, vertex.z] {
+                bytes.extend_from_slice(&component.to_le_bytes());
+            }
+        }
+        write_u32(&mut bytes, material);
+        write_u32(&mut bytes, triangle.meta.id);
+        bytes.extend_from_slice(&triangle.meta.priority.to_le_bytes());
+        bytes.push(triangle.meta.locked as u8);
+        write_str(&mut bytes, &triangle.meta.name);
+    }
+    bytes
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Scene, SceneError> {
+    let mut reader = Reader { bytes, offset: 0 };
+    if reader.take(4).ok() != Some(MAGIC.as_slice()) {
+        return Err(SceneError::new(
+            "Not a binary scene: the data doesn't start with RTSB",
+        ));
+    }
+    let version = reader.u32()?;
+    if version == 0 || version > BINARY_VERSION {
+        return Err(SceneError::new(format!(
+            "The binary scene is layout version {}, which requires a newer raytracer \
+             (this one reads up to version {})",
+            version, BINARY_VERSION
+        )));
+    }
+
+    let mut scene = Scene::from_json(reader.str()?)?;
+
+    let material_count = reader.u32()?;
+    let mut materials = Vec::new();
+    for index in 0..material_count {
+        let material = serde_json::from_str::<Material>(reader.str()?).map_err(|e| {
+            SceneError::new(format!("Binary scene material {} is invalid: {}", index, e))
+        })?;
+        materials.push(material);
+    }
+
+    let triangle_count = reader.u32()?;
+    for index in 0..triangle_count {
+        let v0 = reader.vec3()?;
+        let v1 = reader.vec3()?;
+        let v2 = reader.vec3()?;
+        let material = reader.u32()?;
+        let material = *materials.get(material as usize).ok_or_else(|| {
+            SceneError::new(format!(
+                "Binary scene triangle {} uses material {} of {}",
+                index, material, material_count
+            ))
+        })?;
+        let mut triangle = Triangle::new(v0, v1, v2, material);
+        triangle.meta.id = reader.u32()?;
+        triangle.meta.priority = reader.u32()? as i32;
+        triangle.meta.locked = reader.take(1)?[0] != 0;
+        triangle.meta.name = reader.str()?.to_string();
+        scene.add_triangle(triangle);
+    }
+
+    if reader.offset != bytes.len() {
+        return Err(SceneError::new(format!(
+            "The binary scene has {} bytes after its last triangle",
+            bytes.len() - reader.offset
+        )));
+    }
+    scene.assign_ids();
+    Ok(scene)
+}
+
+fn write_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_str(bytes: &mut Vec<u8>, value: &str) {
+    write_u32(bytes, value.len() as u32);
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SceneError> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len());
+        let Some(end) = end else {
+            return Err(SceneError::new(format!(
+                "The binary scene is truncated: {} bytes needed at byte {} of {}",
+                len,
+                self.offset,
+                self.bytes.len()
+            )));
+        };
+        let taken = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, SceneError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn f32(&mut self) -> Result<f32, SceneError> {
+        self.u32().map(f32::from_bits)
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, SceneError> {
+        Ok(Vec3::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+
+    fn str(&mut self) -> Result<&'a str, SceneError> {
+        let len = self.u32()? as usize;
+        let start = self.offset;
+        std::str::from_utf8(self.take(len)?).map_err(|_| {
+            SceneError::new(format!(
+                "The binary scene has invalid UTF-8 at byte {}",
+                start
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::{
+        Box, Cone, CsgOp, CsgOperation, Cylinder, Disk, Light, ObjectRef, ObjectType, Plane,
+        Sphere, Volume,
+    };
+
+    /// A scene with one or more of every kind of object, and triangles that
+    /// share materials and carry every per-triangle field.
+    fn sample_scene() -> Scene {
+        let red = Material::lambertian(Vec3::new(0.8, 0.2, 0.2));
+        let glass = Material::dielectric(1.5);
+        let mut scene = Scene::new();
+        scene.add_sphere(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, glass));
+        scene.add_sphere(Sphere::new(Vec3::new(2.0, 0.5, 1.0), 0.5, red));
+        scene.add_plane(Plane::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), red));
+        scene.add_box(Box::new(Vec3::new(-2.0, 0.5, 0.0), Vec3::one(), red));
+        scene.add_cylinder(Cylinder::new(
+            Vec3::zero(),
+            Vec3::new(0.0, 2.0, 0.0),
+            0.5,
+            glass,
+        ));
+        scene.add_cone(Cone::new(
+            Vec3::new(3.0, 2.0, 0.0),
+            Vec3::new(0.0, -2.0, 0.0),
+            0.5,
+            red,
+        ));
+        scene.add_disk(Disk::new(
+            Vec3::new(0.0, 3.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            1.0,
+            0.25,
+            red,
+        ));
+        scene.add_light(Light::new(Vec3::new(2.0, 5.0, 2.0), Vec3::one(), 20.0));
+        scene.add_volume(Volume::new(Vec3::new(0.0, 1.0, 0.0), 2.0, 0.1, Vec3::one()));
+        scene.add_csg(CsgOp {
+            op: CsgOperation::Subtract,
+            a: ObjectRef {
+                kind: ObjectType::Box,
+                index: 0,
+            },
+            b: ObjectRef {
+                kind: ObjectType::Sphere,
+                index: 1,
+            },
+        });
+        for index in 0..5 {
+            let x = index as f32;
+            let material = if index % 2 == 0 { red } else { glass };
+            let mut triangle = Triangle::new(
+                Vec3::new(x, 0.0, -1.0),
+                Vec3::new(x + 1.0, 0.0, -1.0),
+                Vec3::new(x + 0.5, 1.0, -1.25),
+                material,
+            );
+            triangle.meta.priority = index - 2;
+            triangle.meta.locked = index == 3;
+            triangle.meta.name = format!("tri{}", index);
+            scene.add_triangle(triangle);
+        }
+        scene
+    }
+
+    fn decode_error(bytes: &[u8]) -> String {
+        match decode(bytes) {
+            Ok(_) => panic!("{} bytes decoded", bytes.len()),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn round_trip_keeps_every_object_kind() {
+        let scene = sample_scene();
+        let decoded = decode(&encode(&scene)).unwrap();
+        assert_eq!(decoded.to_json(), scene.to_json());
+        assert_eq!(decoded.triangles.len(), 5);
+        assert_eq!(decoded.triangles[3].meta.name, "tri3");
+        assert!(decoded.triangles[3].meta.locked);
+        assert_eq!(decoded.triangles[0].meta.priority, -2);
+    }
+
+    #[test]
+    fn triangle_materials_are_stored_once() {
+        let bytes = encode(&sample_scene());
+        let mut reader = Reader {
+            bytes: &bytes,
+            offset: 8,
+        };
+        reader.str().unwrap();
+        assert_eq!(reader.u32().unwrap(), 2);
+    }
+
+    #[test]
+    fn empty_scene_round_trips() {
+        let scene = Scene::new();
+        assert_eq!(decode(&encode(&scene)).unwrap().to_json(), scene.to_json());
+    }
+
+    #[test]
+    fn every_truncation_is_an_error() {
+        let bytes = encode(&sample_scene());
+        for len in 0..bytes.len() {
+            let error = decode_error(&bytes[..len]);
+            if len >= MAGIC.len() {
+                assert!(error.contains("truncated"), "cut at {}: {}", len, error);
+            }
+        }
+    }
+
+    #[test]
+    fn trailing_bytes_are_an_error() {
+        let mut bytes = encode(&sample_scene());
+        bytes.push(0);
+        assert!(decode_error(&bytes).contains("1 bytes after its last triangle"));
+    }
+
+    #[test]
+    fn bad_magic_is_an_error() {
+        let mut bytes = encode(&sample_scene());
+        bytes[..4].copy_from_slice(b"RTSC");
+        assert!(decode_error(&bytes).contains("doesn't start with RTSB"));
+        assert!(decode_error(b"{\"spheres\": []}").contains("doesn't start with RTSB"));
+    }
+
+    #[test]
+    fn bad_version_is_an_error() {
+        let mut bytes = encode(&sample_scene());
+        for version in [0, BINARY_VERSION + 1, u32::MAX] {
+            bytes[4..8].copy_from_slice(&version.to_le_bytes());
+            let error = decode_error(&bytes);
+            assert!(
+                error.contains(&format!("layout version {}", version)),
+                "{}",
+                error
+            );
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! The scene's objects as a float texture, read with texel fetches by the
+//! `DATA_TEXTURE` variant of the raytracing shader.
+//!
+//! One texture upload replaces hundreds of uniform calls and lifts the
+//! per-type object caps to `MAX_DATA_OBJECTS`, and to `MAX_DATA_TRIANGLES`
+//! for triangles, which the shader finds through a BVH (see `bvh.rs`). Rows
+//! of objects wrap every `DATA_OBJECTS_PER_ROW` to keep the texture within
+//! size limits. It needs OES_texture_float; without it objects go into the
+//! shader's uniform arrays instead, see `Scene::set_uniforms`.
+
+use web_sys::{WebGlRenderingContext, WebGlTexture};
+
+use crate::error::RaytracerError;
+use crate::scene::{DATA_OBJECTS_PER_ROW, DATA_TEXELS, PackKey, SceneData};
+use crate::textures::MAX_IMAGE_TEXTURES;
+use crate::scene_uniforms::SceneUniforms;
+use crate::webgl;
+
+/// Texture unit of the data texture, after those of the sphere images.
+const DATA_TEXTURE_UNIT: u32 = MAX_IMAGE_TEXTURES as u32;
+
+/// Width of the texture in texels.
+const WIDTH: usize = DATA_TEXELS * DATA_OBJECTS_PER_ROW;
+
+pub struct SceneDataTexture {
+    texture: WebGlTexture,
+    // What the texture holds, and what it was packed from if that scene is
+    // cached
+    data: SceneData,
+    key: Option<PackKey>,
+}
+
+impl SceneDataTexture {
+    /// An empty data texture, or `None` if the context can't sample float
+    /// textures.
+    pub fn new(gl: &WebGlRenderingContext) -> Option<Self> {
+        if !webgl::supports_float_textures(gl) {
+            return None;
+        }
+        Some(Self {
+            texture: gl.create_texture()?,
+            data: SceneData::default(),
+            key: None,
+        })
+    }
+
+    /// Whether the texture holds the data packed from `key`.
+    pub fn holds(&self, key: &PackKey) -> bool {
+        self.key.as_ref() == Some(key)
+    }
+
+    /// Uploads `data`, packed from `key` or from a scene that isn't cached,
+    /// and returns the number of bytes sent.
+    pub fn update(
+        &mut self,
+        gl: &WebGlRenderingContext,
+        data: SceneData,
+        key: Option<PackKey>,
+    ) -> Result<usize, RaytracerError> {
+        // A texture can't be empty, so a scene without objects gets a row of
+        // zeros the shader never reads
+        let mut texels = data.texels.clone();
+        texels.resize(rows(&data) * WIDTH * 4, 0.0);
+        let array = js_sys::Float32Array::from(texels.as_slice());
+
+        gl.active_texture(WebGlRenderingContext::TEXTURE0 + DATA_TEXTURE_UNIT);
+        gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&self.texture));
+        let result = gl
+            .tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_array_buffer_view(
+                WebGlRenderingContext::TEXTURE_2D,
+                0,
+                webgl::rgba_float_format(gl) as i32,
+                WIDTH as i32,
+                rows(&data) as i32,
+                0,
+                WebGlRenderingContext::RGBA,
+                WebGlRenderingContext::FLOAT,
+                Some(&array),
+            );
+        // Texels are fetched exactly, and float textures can't be filtered
+        // without another extension
+        for (parameter, value) in [
+            (
+                WebGlRenderingContext::TEXTURE_MIN_FILTER,
+                WebGlRenderingContext::NEAREST,
+            ),
+            (
+                WebGlRenderingContext::TEXTURE_MAG_FILTER,
+                WebGlRenderingContext::NEAREST,
+            ),
+            (
+                WebGlRenderingContext::TEXTURE_WRAP_S,
+                WebGlRenderingContext::CLAMP_TO_EDGE,
+            ),
+            (
+                WebGlRenderingContext::TEXTURE_WRAP_T,
+                WebGlRenderingContext::CLAMP_TO_EDGE,
+            ),
+        ] {
+            gl.tex_parameteri(WebGlRenderingContext::TEXTURE_2D, parameter, value as i32);
+        }
+        gl.active_texture(WebGlRenderingContext::TEXTURE0);
+        result.map_err(|_| RaytracerError::graphics("Failed to upload the scene data texture"))?;
+
+        self.data = data;
+        self.key = key;
+        Ok(texels.len() * 4)
+    }
+
+    /// Binds the texture to its unit and sets the uniforms the shader reads it
+    /// with, leaving unit 0 active.
+    pub fn bind(&self, gl: &WebGlRenderingContext, uniforms: &SceneUniforms) {
+        gl.active_texture(WebGlRenderingContext::TEXTURE0 + DATA_TEXTURE_UNIT);
+        gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&self.texture));
+        gl.active_texture(WebGlRenderingContext::TEXTURE0);
+
+        gl.uniform1i(uniforms.scene_data.as_ref(), DATA_TEXTURE_UNIT as i32);
+        gl.uniform1f(uniforms.scene_data_rows.as_ref(), rows(&self.data) as f32);
+        for (location, count) in uniforms.counts.iter().zip(self.data.counts) {
+            gl.uniform1i(location.as_ref(), count as i32);
+        }
+        gl.uniform1i(uniforms.bvh_node_count.as_ref(), self.data.bvh_nodes as i32);
+    }
+
+    /// GPU memory held by the texture.
+    pub fn bytes(&self) -> usize {
+        rows(&self.data) * WIDTH * 4 * 4
+    }
+}
+
+/// Height of the texture holding `data`.
+fn rows(data: &SceneData) -> usize {
+    data.rows().div_ceil(DATA_OBJECTS_PER_ROW).max(1)
+}
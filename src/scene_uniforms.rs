@@ -0,0 +1,344 @@
+//! Locations of the uniforms `Scene::set_uniforms` and the scene data
+//! texture fill, resolved once when the program is linked.
+//!
+//! Every object, light and combination slot gets its locations up front, so
+//! uploads index into arrays instead of building and looking up names each
+//! frame. A uniform the program doesn't use, such as the object arrays of the
+//! `DATA_TEXTURE` variant, resolves to `None` and its uploads are skipped.
+
+use std::collections::HashMap;
+
+use web_sys::{WebGlProgram, WebGlRenderingContext, WebGlUniformLocation};
+
+use crate::scene::{
+    MAX_BOXES, MAX_CONES, MAX_CSG, MAX_CYLINDERS, MAX_DISKS, MAX_LIGHTS, MAX_PLANES, MAX_SPHERES,
+    MAX_TRIANGLES, MAX_VOLUMES, ObjectType,
+};
+
+type Location = Option<WebGlUniformLocation>;
+
+/// The active uniforms of a program by name, each taken out as it is
+/// resolved.
+struct Names(HashMap<String, WebGlUniformLocation>);
+
+impl Names {
+    fn new(gl: &WebGlRenderingContext, program: &WebGlProgram) -> Self {
+        let count = gl
+            .get_program_parameter(program, WebGlRenderingContext::ACTIVE_UNIFORMS)
+            .as_f64()
+            .unwrap_or(0.0) as u32;
+        let mut locations = HashMap::new();
+        for info in (0..count).filter_map(|index| gl.get_active_uniform(program, index)) {
+            // Arrays of basic types are listed once, as their first element
+            let name = info.name();
+            let names = match name.strip_suffix("[0]") {
+                Some(base) => (0..info.size().max(1))
+                    .map(|i| format!("{}[{}]", base, i))
+                    .collect(),
+                None => vec![name],
+            };
+            for name in names {
+                if let Some(location) = gl.get_uniform_location(program, &name) {
+                    locations.insert(name, location);
+                }
+            }
+        }
+        Self(locations)
+    }
+
+    fn take(&mut self, name: &str) -> Location {
+        self.0.remove(name)
+    }
+
+    /// Locations of `count` elements, resolved by `element` from their
+    /// `prefix[i]` names.
+    fn array<T>(
+        &mut self,
+        prefix: &str,
+        count: usize,
+        mut element: impl FnMut(&mut Self, &str) -> T,
+    ) -> Vec<T> {
+        (0..count)
+            .map(|i| element(self, &format!("{}[{}]", prefix, i)))
+            .collect()
+    }
+}
+
+/// The `material` member of a shader struct.
+pub struct MaterialUniforms {
+    pub albedo: Location,
+    pub material_type: Location,
+    pub roughness: Location,
+    pub ior: Location,
+    pub emission: Location,
+    pub emission_strength: Location,
+    pub opacity: Location,
+    pub shadow_catcher: Location,
+    pub texture_space: Location,
+    pub dispersion: Location,
+    pub checker_scale: Location,
+    pub checker_a: Location,
+    pub checker_b: Location,
+}
+
+impl MaterialUniforms {
+    fn locate(names: &mut Names, prefix: &str) -> Self {
+        let mut member = |name: &str| names.take(&format!("{}.material.{}", prefix, name));
+        Self {
+            albedo: member("albedo"),
+            material_type: member("material_type"),
+            roughness: member("roughness"),
+            ior: member("ior"),
+            emission: member("emission"),
+            emission_strength: member("emission_strength"),
+            opacity: member("opacity"),
+            shadow_catcher: member("shadow_catcher"),
+            texture_space: member("texture_space"),
+            dispersion: member("dispersion"),
+            checker_scale: member("checker_scale"),
+            checker_a: member("checker_a"),
+            checker_b: member("checker_b"),
+        }
+    }
+}
+
+pub struct SphereUniforms {
+    pub center: Location,
+    pub radius: Location,
+    pub scale: Location,
+    pub image_texture: Location,
+    pub material: MaterialUniforms,
+}
+
+pub struct PlaneUniforms {
+    pub point: Location,
+    pub normal: Location,
+    pub material: MaterialUniforms,
+}
+
+pub struct BoxUniforms {
+    pub center: Location,
+    pub size: Location,
+    pub rotation: Location,
+    pub scale: Location,
+    pub material: MaterialUniforms,
+}
+
+pub struct CylinderUniforms {
+    pub base: Location,
+    pub axis: Location,
+    pub radius: Location,
+    pub rotation: Location,
+    pub scale: Location,
+    pub material: MaterialUniforms,
+}
+
+pub struct TriangleUniforms {
+    pub v0: Location,
+    pub v1: Location,
+    pub v2: Location,
+    pub material: MaterialUniforms,
+}
+
+pub struct ConeUniforms {
+    pub apex: Location,
+    pub axis: Location,
+    pub radius: Location,
+    pub material: MaterialUniforms,
+}
+
+pub struct DiskUniforms {
+    pub center: Location,
+    pub normal: Location,
+    pub radius: Location,
+    pub inner_radius: Location,
+    pub material: MaterialUniforms,
+}
+
+pub struct LightUniforms {
+    pub position: Location,
+    pub color: Location,
+    pub intensity: Location,
+}
+
+pub struct VolumeUniforms {
+    pub center: Location,
+    pub radius: Location,
+    pub density: Location,
+    pub color: Location,
+}
+
+/// One of the two objects of a combination, `a` or `b`.
+pub struct CsgOperandUniforms {
+    pub kind: Location,
+    pub center: Location,
+    pub size: Location,
+    pub rotation: Location,
+    pub material: MaterialUniforms,
+}
+
+pub struct CsgUniforms {
+    pub op: Location,
+    pub a: CsgOperandUniforms,
+    pub b: CsgOperandUniforms,
+}
+
+pub struct GridUniforms {
+    pub enabled: Location,
+    pub cell_size: Location,
+    pub radius: Location,
+    pub extent: Location,
+    pub material: MaterialUniforms,
+}
+
+pub struct StarfieldUniforms {
+    pub enabled: Location,
+    pub density: Location,
+    pub brightness: Location,
+    pub offset: Location,
+    pub twinkle: Location,
+}
+
+/// Uniform locations of the scene, looked up again whenever the shader
+/// variant changes.
+pub struct SceneUniforms {
+    /// `u_<kind>_count`, by `ObjectType::ordinal`
+    pub counts: [Location; 7],
+    /// `u_<kind>_slots`, by `ObjectType::ordinal`
+    pub slots: [Vec<Location>; 7],
+    pub spheres: Vec<SphereUniforms>,
+    pub planes: Vec<PlaneUniforms>,
+    pub boxes: Vec<BoxUniforms>,
+    pub cylinders: Vec<CylinderUniforms>,
+    pub triangles: Vec<TriangleUniforms>,
+    pub cones: Vec<ConeUniforms>,
+    pub disks: Vec<DiskUniforms>,
+    pub light_count: Location,
+    pub lights: Vec<LightUniforms>,
+    pub volume_count: Location,
+    pub volumes: Vec<VolumeUniforms>,
+    pub csg_count: Location,
+    pub csgs: Vec<CsgUniforms>,
+    pub csg_slots: Vec<Location>,
+    pub grid: GridUniforms,
+    pub background_color: Location,
+    pub starfield: StarfieldUniforms,
+    pub scene_data: Location,
+    pub scene_data_rows: Location,
+    pub bvh_node_count: Location,
+}
+
+impl SceneUniforms {
+    pub fn locate(gl: &WebGlRenderingContext, program: &WebGlProgram) -> Self {
+        let mut names = Names::new(gl, program);
+        let counts = ObjectType::ALL.map(|kind| names.take(&format!("u_{}_count", kind.name())));
+        let slots = ObjectType::ALL.map(|kind| {
+            let prefix = format!("u_{}_slots", kind.name());
+            names.array(&prefix, kind.max_count(), |names, name| names.take(name))
+        });
+        Self {
+            counts,
+            slots,
+            spheres: names.array("u_spheres", MAX_SPHERES, |names, prefix| SphereUniforms {
+                center: names.take(&format!("{}.center", prefix)),
+                radius: names.take(&format!("{}.radius", prefix)),
+                scale: names.take(&format!("{}.scale", prefix)),
+                image_texture: names.take(&format!("{}.image_texture", prefix)),
+                material: MaterialUniforms::locate(names, prefix),
+            }),
+            planes: names.array("u_planes", MAX_PLANES, |names, prefix| PlaneUniforms {
+                point: names.take(&format!("{}.point", prefix)),
+                normal: names.take(&format!("{}.normal", prefix)),
+                material: MaterialUniforms::locate(names, prefix),
+            }),
+            boxes: names.array("u_boxes", MAX_BOXES, |names, prefix| BoxUniforms {
+                center: names.take(&format!("{}.center", prefix)),
+                size: names.take(&format!("{}.size", prefix)),
+                rotation: names.take(&format!("{}.rotation", prefix)),
+                scale: names.take(&format!("{}.scale", prefix)),
+                material: MaterialUniforms::locate(names, prefix),
+            }),
+            cylinders: names.array("u_cylinders", MAX_CYLINDERS, |names, prefix| {
+                CylinderUniforms {
+                    base: names.take(&format!("{}.base", prefix)),
+                    axis: names.take(&format!("{}.axis", prefix)),
+                    radius: names.take(&format!("{}.radius", prefix)),
+                    rotation: names.take(&format!("{}.rotation", prefix)),
+                    scale: names.take(&format!("{}.scale", prefix)),
+                    material: MaterialUniforms::locate(names, prefix),
+                }
+            }),
+            triangles: names.array("u_triangles", MAX_TRIANGLES, |names, prefix| {
+                TriangleUniforms {
+                    v0: names.take(&format!("{}.v0", prefix)),
+                    v1: names.take(&format!("{}.v1", prefix)),
+                    v2: names.take(&format!("{}.v2", prefix)),
+                    material: MaterialUniforms::locate(names, prefix),
+                }
+            }),
+            cones: names.array("u_cones", MAX_CONES, |names, prefix| ConeUniforms {
+                apex: names.take(&format!("{}.apex", prefix)),
+                axis: names.take(&format!("{}.axis", prefix)),
+                radius: names.take(&format!("{}.radius", prefix)),
+                material: MaterialUniforms::locate(names, prefix),
+            }),
+            disks: names.array("u_disks", MAX_DISKS, |names, prefix| DiskUniforms {
+                center: names.take(&format!("{}.center", prefix)),
+                normal: names.take(&format!("{}.normal", prefix)),
+                radius: names.take(&format!("{}.radius", prefix)),
+                inner_radius: names.take(&format!("{}.inner_radius", prefix)),
+                material: MaterialUniforms::locate(names, prefix),
+            }),
+            light_count: names.take("u_light_count"),
+            lights: names.array("u_lights", MAX_LIGHTS, |names, prefix| LightUniforms {
+                position: names.take(&format!("{}.position", prefix)),
+                color: names.take(&format!("{}.color", prefix)),
+                intensity: names.take(&format!("{}.intensity", prefix)),
+            }),
+            volume_count: names.take("u_volume_count"),
+            volumes: names.array("u_volumes", MAX_VOLUMES, |names, prefix| VolumeUniforms {
+                center: names.take(&format!("{}.center", prefix)),
+                radius: names.take(&format!("{}.radius", prefix)),
+                density: names.take(&format!("{}.density", prefix)),
+                color: names.take(&format!("{}.color", prefix)),
+            }),
+            csg_count: names.take("u_csg_count"),
+            csgs: names.array("u_csgs", MAX_CSG, |names, prefix| CsgUniforms {
+                op: names.take(&format!("{}.op", prefix)),
+                a: CsgOperandUniforms::locate(names, &format!("{}.a", prefix)),
+                b: CsgOperandUniforms::locate(names, &format!("{}.b", prefix)),
+            }),
+            csg_slots: names.array("u_csg_slots", MAX_CSG, |names, name| names.take(name)),
+            grid: GridUniforms {
+                enabled: names.take("u_grid_enabled"),
+                cell_size: names.take("u_grid.cell_size"),
+                radius: names.take("u_grid.radius"),
+                extent: names.take("u_grid.extent"),
+                material: MaterialUniforms::locate(&mut names, "u_grid"),
+            },
+            background_color: names.take("u_background_color"),
+            starfield: StarfieldUniforms {
+                enabled: names.take("u_starfield_enabled"),
+                density: names.take("u_starfield.density"),
+                brightness: names.take("u_starfield.brightness"),
+                offset: names.take("u_starfield.offset"),
+                twinkle: names.take("u_starfield.twinkle"),
+            },
+            scene_data: names.take("u_scene_data"),
+            scene_data_rows: names.take("u_scene_data_rows"),
+            bvh_node_count: names.take("u_bvh_node_count"),
+        }
+    }
+}
+
+impl CsgOperandUniforms {
+    fn locate(names: &mut Names, prefix: &str) -> Self {
+        Self {
+            kind: names.take(&format!("{}.kind", prefix)),
+            center: names.take(&format!("{}.center", prefix)),
+            size: names.take(&format!("{}.size", prefix)),
+            rotation: names.take(&format!("{}.rotation", prefix)),
+            material: MaterialUniforms::locate(names, prefix),
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use serde::Serialize;
+
+use crate::camera::Camera;
+use crate::scene::{ObjectType, Scene};
+
+/// An object addressed the way the JavaScript API addresses it.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct ObjectRef {
+    #[serde(rename = "type")]
+    pub object_type: u32,
+    pub index: usize,
+}
+
+/// Every finite object whose projected bounding box overlaps the rectangle
+/// spanned by `(x0, y0)` and `(x1, y1)`, in `width` x `height` image pixels.
+/// The corners may be given in any order. Planes have no bounds and are never
+/// selected.
+pub fn objects_in_rect(
+    scene: &Scene,
+    camera: &Camera,
+    (x0, y0): (f32, f32),
+    (x1, y1): (f32, f32),
+    width: f32,
+    height: f32,
+) -> Vec<ObjectRef> {
+    let (min_x, max_x) = (x0.min(x1), x0.max(x1));
+    let (min_y, max_y) = (y0.min(y1), y0.max(y1));
+
+    let candidates = scene
+        .spheres
+        .iter()
+        .map(|o| (ObjectType::Sphere, o.bounds()))
+        .enumerate()
+        .chain(
+            scene
+                .boxes
+                .iter()
+                .map(|o| (ObjectType::Box, o.bounds()))
+                .enumerate(),
+        )
+        .chain(
+            scene
+                .cylinders
+                .iter()
+                .map(|o| (ObjectType::Cylinder, o.bounds()))
+                .enumerate(),
+        )
+        .chain(
+            scene
+                .triangles
+                .iter()
+                .map(|o| (ObjectType::Triangle, o.bounds()))
+                .enumerate(),
+        )
+        .chain(
+            scene
+                .cones
+                .iter()
+                .map(|o| (ObjectType::Cone, o.bounds()))
+                .enumerate(),
+        )
+        .chain(
+            scene
+                .disks
+                .iter()
+                .map(|o| (ObjectType::Disk, o.bounds()))
+                .enumerate(),
+        );
+
+    candidates
+        .filter(|(_, (_, bounds))| {
+            camera.project_bounds(bounds, width, height).is_some_and(
+                |(left, top, right, bottom)| {
+                    left <= max_x && right >= min_x && top <= max_y && bottom >= min_y
+                },
+            )
+        })
+        .map(|(index, (object_type, _))| ObjectRef {
+            object_type: object_type.to_u32(),
+            index,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::math::Vec3;
+    use crate::scene::{Box, Plane, Sphere};
+
+    const SIZE: f32 = 100.0;
+
+    fn grey() -> Material {
+        Material::lambertian(Vec3::new(0.5, 0.5, 0.5))
+    }
+
+    /// Five units back on +Z looking at the origin, with a 90 degree field
+    /// of view on a square image.
+    fn camera() -> Camera {
+        Camera::new(Vec3::new(0.0, 0.0, 5.0), Vec3::zero(), 1.0)
+    }
+
+    fn selected(scene: &Scene, from: (f32, f32), to: (f32, f32)) -> Vec<(u32, usize)> {
+        objects_in_rect(scene, &camera(), from, to, SIZE, SIZE)
+            .into_iter()
+            .map(|o| (o.object_type, o.index))
+            .collect()
+    }
+
+    fn sphere(x: f32, y: f32, z: f32, radius: f32) -> Sphere {
+        Sphere::new(Vec3::new(x, y, z), radius, grey())
+    }
+
+    #[test]
+    fn picks_objects_under_the_rectangle() {
+        let mut scene = Scene::new();
+        // Projects to (37.5, 37.5)-(62.5, 62.5)
+        scene.add_sphere(sphere(0.0, 0.0, 0.0, 1.0));
+        // Projects to about (11, 44)-(27, 56)
+        scene.add_box(Box::new(Vec3::new(3.0, 0.0, 0.0), Vec3::one(), grey()));
+        let sphere_ref = (ObjectType::Sphere.to_u32(), 0);
+        let box_ref = (ObjectType::Box.to_u32(), 0);
+
+        assert_eq!(
+            selected(&scene, (0.0, 0.0), (SIZE, SIZE)),
+            vec![sphere_ref, box_ref]
+        );
+        assert_eq!(
+            selected(&scene, (45.0, 45.0), (55.0, 55.0)),
+            vec![sphere_ref]
+        );
+        assert_eq!(selected(&scene, (0.0, 0.0), (30.0, SIZE)), vec![box_ref]);
+        // Just touching the sphere's projected edge still counts
+        assert_eq!(
+            selected(&scene, (38.0, 0.0), (SIZE, 38.0)),
+            vec![sphere_ref]
+        );
+        assert!(selected(&scene, (30.0, 0.0), (SIZE, 30.0)).is_empty());
+    }
+
+    #[test]
+    fn corners_can_come_in_any_order() {
+        let mut scene = Scene::new();
+        scene.add_sphere(sphere(0.0, 0.0, 0.0, 1.0));
+        scene.add_sphere(sphere(3.0, 3.0, 0.0, 0.5));
+        let forward = selected(&scene, (10.0, 10.0), (55.0, 55.0));
+        assert_eq!(forward.len(), 2);
+        assert_eq!(selected(&scene, (55.0, 55.0), (10.0, 10.0)), forward);
+        assert_eq!(selected(&scene, (55.0, 10.0), (10.0, 55.0)), forward);
+        assert_eq!(selected(&scene, (10.0, 55.0), (55.0, 10.0)), forward);
+    }
+
+    #[test]
+    fn skips_objects_behind_the_camera() {
+        let mut scene = Scene::new();
+        scene.add_sphere(sphere(0.0, 0.0, 10.0, 1.0));
+        scene.add_sphere(sphere(0.0, 0.0, 6.5, 1.0));
+        assert!(selected(&scene, (0.0, 0.0), (SIZE, SIZE)).is_empty());
+    }
+
+    #[test]
+    fn keeps_objects_the_camera_is_partly_inside() {
+        let mut scene = Scene::new();
+        // Reaches from behind the camera to in front of it
+        scene.add_sphere(sphere(1.0, 0.0, 5.0, 2.0));
+        let all = selected(&scene, (0.0, 0.0), (SIZE, SIZE));
+        assert_eq!(all, vec![(ObjectType::Sphere.to_u32(), 0)]);
+        // The camera is inside its bounds, so they cover the whole view
+        assert_eq!(selected(&scene, (45.0, 45.0), (55.0, 55.0)), all);
+    }
+
+    #[test]
+    fn never_picks_planes() {
+        let mut scene = Scene::new();
+        scene.add_plane(Plane::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            grey(),
+        ));
+        assert!(selected(&scene, (0.0, 0.0), (SIZE, SIZE)).is_empty());
+    }
+}
@@ -1,41 +1,280 @@
-use wasm_bindgen::prelude::*;
-use web_sys::{WebGlProgram, WebGlRenderingContext};
+use std::collections::HashMap;
 
-use crate::webgl::create_shader;
+use web_sys::{WebGlProgram, WebGlRenderingContext, WebGlShader};
+
+use crate::bvh::{BVH_LEAF_TRIANGLES, BVH_STACK_SIZE};
+use crate::error::RaytracerError;
+use crate::motion::MAX_REJECT_REGIONS;
+use crate::webgl::{ContextKind, check_shader, compile_shader};
 
 const VERTEX_SHADER_SOURCE: &str = include_str!("../shaders/vertex.glsl");
 const FRAGMENT_SHADER_SOURCE: &str = include_str!("../shaders/fragment.glsl");
+const BLIT_SHADER_SOURCE: &str = include_str!("../shaders/blit.glsl");
+const OVERLAY_SHADER_SOURCE: &str = include_str!("../shaders/overlay.glsl");
+const ACCUMULATE_SHADER_SOURCE: &str = include_str!("../shaders/accumulate.glsl");
+
+// The shaders are written in GLSL ES 1.00. On WebGL2 the raytracing program
+// is compiled as GLSL ES 3.00 behind these headers, which map the keywords
+// and functions 3.00 renamed.
+const VERTEX_HEADER_300: &str = "#version 300 es
+#define attribute in
+#define varying out
+";
+const FRAGMENT_HEADER_300: &str = "#version 300 es
+#define varying in
+#define texture2D texture
+layout(location = 0) out highp vec4 frag_color;
+#define gl_FragColor frag_color
+";
+
+/// `COMPLETION_STATUS_KHR` from KHR_parallel_shader_compile.
+const COMPLETION_STATUS_KHR: u32 = 0x91B1;
+
+/// Expensive features of the raytracing shader. Each is compiled in only
+/// while in use, so a disabled one costs nothing per pixel; cheap features
+/// stay runtime branches to keep the number of variants small.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ShaderFeatures {
+    /// Fog volume ray marching (`USE_FOG`)
+    pub fog: bool,
+    /// Ambient occlusion rays (`USE_AO`)
+    pub ambient_occlusion: bool,
+    /// Instanced sphere grid traversal (`USE_GRID`)
+    pub instanced_grid: bool,
+    /// Magenta for pixels with a non-finite result (`NAN_GUARD`), a debug aid
+    pub nan_guard: bool,
+    /// Objects read from the scene data texture rather than uniform arrays
+    /// (`DATA_TEXTURE`)
+    pub data_texture: bool,
+}
+
+impl ShaderFeatures {
+    fn defines(self) -> impl Iterator<Item = &'static str> {
+        [
+            (self.fog, "USE_FOG"),
+            (self.ambient_occlusion, "USE_AO"),
+            (self.instanced_grid, "USE_GRID"),
+            (self.nan_guard, "NAN_GUARD"),
+            (self.data_texture, "DATA_TEXTURE"),
+        ]
+        .into_iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, define)| define)
+    }
+
+    /// The variant's defines separated by spaces, or `base` if it has none.
+    pub fn name(self) -> String {
+        let defines: Vec<&str> = self.defines().collect();
+        if defines.is_empty() {
+            "base".to_string()
+        } else {
+            defines.join(" ")
+        }
+    }
+
+    fn fragment_source(self, kind: ContextKind) -> String {
+        let mut source = match kind {
+            ContextKind::WebGl1 => String::new(),
+            ContextKind::WebGl2 => FRAGMENT_HEADER_300.to_string(),
+        };
+        for define in self.defines() {
+            source.push_str(&format!("#define {}\n", define));
+        }
+        source.push_str(&format!("#define BVH_STACK_SIZE {}\n", BVH_STACK_SIZE));
+        source.push_str(&format!("#define BVH_LEAF_TRIANGLES {}\n", BVH_LEAF_TRIANGLES));
+        source.push_str(FRAGMENT_SHADER_SOURCE);
+        source
+    }
+}
+
+/// The vertex shader matching the raytracing fragment shader's dialect.
+fn vertex_source(kind: ContextKind) -> String {
+    match kind {
+        ContextKind::WebGl1 => VERTEX_SHADER_SOURCE.to_string(),
+        ContextKind::WebGl2 => format!("{}{}", VERTEX_HEADER_300, VERTEX_SHADER_SOURCE),
+    }
+}
+
+enum Variant {
+    Compiling(PendingProgram),
+    Ready(WebGlProgram),
+    // Kept so a broken variant isn't recompiled every frame
+    Failed(RaytracerError),
+}
+
+/// Raytracing programs by feature set, compiled on first use. With
+/// KHR_parallel_shader_compile the driver compiles in the background and
+/// `poll` only hands a variant out once it's done; without it the first
+/// request for a variant blocks while it compiles.
+pub struct ShaderVariants {
+    kind: ContextKind,
+    parallel_compile: bool,
+    variants: HashMap<ShaderFeatures, Variant>,
+}
+
+impl ShaderVariants {
+    pub fn new(gl: &WebGlRenderingContext) -> Self {
+        let parallel_compile = gl
+            .get_extension("KHR_parallel_shader_compile")
+            .ok()
+            .flatten()
+            .is_some();
+        Self {
+            kind: ContextKind::of(gl),
+            parallel_compile,
+            variants: HashMap::new(),
+        }
+    }
+
+    /// The program for `features`, compiling it now if need be.
+    pub fn program(
+        &mut self,
+        gl: &WebGlRenderingContext,
+        features: ShaderFeatures,
+    ) -> Result<WebGlProgram, RaytracerError> {
+        match self.advance(gl, features, true)? {
+            Variant::Ready(program) => Ok(program.clone()),
+            Variant::Failed(error) => Err(error.clone()),
+            Variant::Compiling(_) => unreachable!("waited for the variant to link"),
+        }
+    }
+
+    /// The program for `features` if it's ready, starting its compilation
+    /// otherwise. A variant that fails is reported once and then never
+    /// becomes ready.
+    pub fn poll(
+        &mut self,
+        gl: &WebGlRenderingContext,
+        features: ShaderFeatures,
+    ) -> Result<Option<WebGlProgram>, RaytracerError> {
+        let was_compiling = matches!(
+            self.variants.get(&features),
+            None | Some(Variant::Compiling(_))
+        );
+        match self.advance(gl, features, false)? {
+            Variant::Ready(program) => Ok(Some(program.clone())),
+            Variant::Failed(error) if was_compiling => Err(error.clone()),
+            _ => Ok(None),
+        }
+    }
+
+    fn advance(
+        &mut self,
+        gl: &WebGlRenderingContext,
+        features: ShaderFeatures,
+        wait: bool,
+    ) -> Result<&Variant, RaytracerError> {
+        let variant = match self.variants.remove(&features) {
+            Some(variant) => variant,
+            None => Variant::Compiling(PendingProgram::start(
+                gl,
+                &vertex_source(self.kind),
+                &features.fragment_source(self.kind),
+            )?),
+        };
+        let variant = match variant {
+            Variant::Compiling(pending)
+                if wait || pending.is_complete(gl, self.parallel_compile) =>
+            {
+                match pending.finish(gl) {
+                    Ok(program) => Variant::Ready(program),
+                    Err(error) => Variant::Failed(error),
+                }
+            }
+            variant => variant,
+        };
+        Ok(self.variants.entry(features).or_insert(variant))
+    }
+}
+
+/// Full-screen textured quad, used to upscale a reduced-resolution render.
+pub fn create_blit_program(gl: &WebGlRenderingContext) -> Result<WebGlProgram, RaytracerError> {
+    link_program(gl, BLIT_SHADER_SOURCE)
+}
+
+/// Full-screen quad compositing the render with a reference image.
+pub fn create_overlay_program(gl: &WebGlRenderingContext) -> Result<WebGlProgram, RaytracerError> {
+    link_program(gl, OVERLAY_SHADER_SOURCE)
+}
+
+/// Full-screen quad averaging a new frame into the accumulated image.
+pub fn create_accumulate_program(
+    gl: &WebGlRenderingContext,
+) -> Result<WebGlProgram, RaytracerError> {
+    let source = format!(
+        "#define MAX_REJECT_REGIONS {}\n{}",
+        MAX_REJECT_REGIONS, ACCUMULATE_SHADER_SOURCE
+    );
+    link_program(gl, &source)
+}
+
+/// Links `fragment_source` with the shared full-screen quad vertex shader.
+fn link_program(
+    gl: &WebGlRenderingContext,
+    fragment_source: &str,
+) -> Result<WebGlProgram, RaytracerError> {
+    PendingProgram::start(gl, VERTEX_SHADER_SOURCE, fragment_source)?.finish(gl)
+}
+
+/// A program handed to the driver whose compile and link results haven't
+/// been asked for yet; asking is what blocks.
+struct PendingProgram {
+    program: WebGlProgram,
+    vertex_shader: WebGlShader,
+    fragment_shader: WebGlShader,
+}
+
+impl PendingProgram {
+    fn start(
+        gl: &WebGlRenderingContext,
+        vertex_source: &str,
+        fragment_source: &str,
+    ) -> Result<Self, RaytracerError> {
+        let vertex_shader =
+            compile_shader(gl, WebGlRenderingContext::VERTEX_SHADER, vertex_source)?;
+        let fragment_shader =
+            compile_shader(gl, WebGlRenderingContext::FRAGMENT_SHADER, fragment_source)?;
+
+        let program = gl
+            .create_program()
+            .ok_or_else(|| RaytracerError::graphics("Failed to create program"))?;
+
+        gl.attach_shader(&program, &vertex_shader);
+        gl.attach_shader(&program, &fragment_shader);
+        gl.link_program(&program);
+
+        Ok(Self {
+            program,
+            vertex_shader,
+            fragment_shader,
+        })
+    }
+
+    /// Whether the driver has finished, so `finish` won't block.
+    fn is_complete(&self, gl: &WebGlRenderingContext, parallel_compile: bool) -> bool {
+        !parallel_compile
+            || gl
+                .get_program_parameter(&self.program, COMPLETION_STATUS_KHR)
+                .as_bool()
+                .unwrap_or(true)
+    }
+
+    fn finish(self, gl: &WebGlRenderingContext) -> Result<WebGlProgram, RaytracerError> {
+        if gl
+            .get_program_parameter(&self.program, WebGlRenderingContext::LINK_STATUS)
+            .as_bool()
+            .unwrap_or(false)
+        {
+            return Ok(self.program);
+        }
 
-pub fn create_raytracing_program(gl: &WebGlRenderingContext) -> Result<WebGlProgram, JsValue> {
-    let vertex_shader = create_shader(
-        gl,
-        WebGlRenderingContext::VERTEX_SHADER,
-        VERTEX_SHADER_SOURCE,
-    )?;
-    let fragment_shader = create_shader(
-        gl,
-        WebGlRenderingContext::FRAGMENT_SHADER,
-        FRAGMENT_SHADER_SOURCE,
-    )?;
-
-    let program = gl
-        .create_program()
-        .ok_or_else(|| JsValue::from_str("Failed to create program"))?;
-
-    gl.attach_shader(&program, &vertex_shader);
-    gl.attach_shader(&program, &fragment_shader);
-    gl.link_program(&program);
-
-    if gl
-        .get_program_parameter(&program, WebGlRenderingContext::LINK_STATUS)
-        .as_bool()
-        .unwrap_or(false)
-    {
-        Ok(program)
-    } else {
-        Err(JsValue::from_str(
-            &gl.get_program_info_log(&program)
+        // A shader that didn't compile explains the failure better
+        check_shader(gl, &self.vertex_shader)?;
+        check_shader(gl, &self.fragment_shader)?;
+        Err(RaytracerError::ProgramLink {
+            log: gl
+                .get_program_info_log(&self.program)
                 .unwrap_or_else(|| "Unknown error linking program".into()),
-        ))
+        })
     }
 }
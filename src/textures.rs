@@ -0,0 +1,106 @@
+//! Images uploaded for spheres to sample as their albedo.
+//!
+//! Each image takes one of a fixed number of slots, bound to the matching
+//! texture unit while the scene is drawn. Spheres refer to their slot in
+//! `Sphere::image_texture`; a slot no sphere refers to any more is freed on
+//! the next frame, so removing a sphere or replacing the scene doesn't leak
+//! its texture.
+
+use web_sys::{WebGlRenderingContext, WebGlTexture, WebGlUniformLocation};
+
+use crate::error::RaytracerError;
+use crate::scene::Scene;
+use crate::webgl;
+
+/// Sampler slots the shader has for sphere images (`u_sphere_textures`).
+pub const MAX_IMAGE_TEXTURES: usize = 4;
+
+struct ImageTexture {
+    texture: WebGlTexture,
+    bytes: usize,
+}
+
+#[derive(Default)]
+pub struct ImageTextures {
+    slots: [Option<ImageTexture>; MAX_IMAGE_TEXTURES],
+}
+
+impl ImageTextures {
+    /// Uploads `width` x `height` RGBA `pixels` (top row first) into `slot`,
+    /// replacing any image there.
+    pub fn upload(
+        &mut self,
+        gl: &WebGlRenderingContext,
+        slot: usize,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<(), RaytracerError> {
+        let texture = webgl::create_texture_with_pixels(gl, width, height, pixels)?;
+        for filter in [
+            WebGlRenderingContext::TEXTURE_MIN_FILTER,
+            WebGlRenderingContext::TEXTURE_MAG_FILTER,
+        ] {
+            gl.tex_parameteri(
+                WebGlRenderingContext::TEXTURE_2D,
+                filter,
+                WebGlRenderingContext::LINEAR as i32,
+            );
+        }
+        let image = ImageTexture {
+            texture,
+            bytes: pixels.len(),
+        };
+        if let Some(old) = self.slots[slot].replace(image) {
+            gl.delete_texture(Some(&old.texture));
+        }
+        Ok(())
+    }
+
+    /// A slot holding no image, or one only spheres no longer in `scene`
+    /// referred to.
+    pub fn free_slot(&self, scene: &Scene) -> Option<usize> {
+        (0..MAX_IMAGE_TEXTURES).find(|&slot| !in_use(scene, slot))
+    }
+
+    /// Deletes the images no sphere in `scene` refers to.
+    pub fn release_unused(&mut self, gl: &WebGlRenderingContext, scene: &Scene) {
+        for (slot, image) in self.slots.iter_mut().enumerate() {
+            if !in_use(scene, slot)
+                && let Some(old) = image.take()
+            {
+                gl.delete_texture(Some(&old.texture));
+            }
+        }
+    }
+
+    /// Binds each slot to its texture unit and points `samplers` at them,
+    /// leaving unit 0 active.
+    pub fn bind(
+        &self,
+        gl: &WebGlRenderingContext,
+        samplers: &[Option<WebGlUniformLocation>; MAX_IMAGE_TEXTURES],
+    ) {
+        for (unit, (image, sampler)) in self.slots.iter().zip(samplers).enumerate() {
+            gl.active_texture(WebGlRenderingContext::TEXTURE0 + unit as u32);
+            gl.bind_texture(
+                WebGlRenderingContext::TEXTURE_2D,
+                image.as_ref().map(|image| &image.texture),
+            );
+            gl.uniform1i(sampler.as_ref(), unit as i32);
+        }
+        gl.active_texture(WebGlRenderingContext::TEXTURE0);
+    }
+
+    /// GPU memory held by the images.
+    pub fn bytes(&self) -> usize {
+        self.slots.iter().flatten().map(|image| image.bytes).sum()
+    }
+}
+
+fn in_use(scene: &Scene, slot: usize) -> bool {
+    scene
+        .spheres
+        .iter()
+        .any(|sphere| sphere.image_texture == Some(slot))
+}
@@ -0,0 +1,173 @@
+/// Describes how the render buffer maps onto the canvas.
+///
+/// `width`/`height` are the canvas drawing-buffer size in device pixels. With an
+/// aspect lock the rendered image is letterboxed (or pillarboxed) inside the
+/// canvas, and the render buffer itself is `render_scale` times the size of that
+/// content area.
+#[derive(Clone, Copy, Debug)]
+pub struct Viewport {
+    pub width: u32,
+    pub height: u32,
+    pub pixel_ratio: f32,
+    pub render_scale: f32,
+    pub aspect_lock: Option<f32>,
+}
+
+impl Viewport {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixel_ratio: 1.0,
+            render_scale: 1.0,
+            aspect_lock: None,
+        }
+    }
+
+    /// The part of the canvas showing the image, as `(x, y, width, height)` in
+    /// device pixels with the origin at the top-left corner.
+    pub fn content_rect(&self) -> (f32, f32, f32, f32) {
+        let width = self.width as f32;
+        let height = self.height as f32;
+
+        match self.aspect_lock {
+            Some(aspect) if aspect > 0.0 && height > 0.0 => {
+                if width / height > aspect {
+                    // Canvas is wider than the image: bars on the left and right
+                    let content_width = height * aspect;
+                    ((width - content_width) * 0.5, 0.0, content_width, height)
+                } else {
+                    // Canvas is taller than the image: bars on the top and bottom
+                    let content_height = width / aspect;
+                    (0.0, (height - content_height) * 0.5, width, content_height)
+                }
+            }
+            _ => (0.0, 0.0, width, height),
+        }
+    }
+
+    /// Size of the render buffer in pixels.
+    pub fn render_size(&self) -> (u32, u32) {
+        let (_, _, width, height) = self.content_rect();
+        (
+            ((width * self.render_scale).round() as u32).max(1),
+            ((height * self.render_scale).round() as u32).max(1),
+        )
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        let (_, _, width, height) = self.content_rect();
+        width / height.max(1.0)
+    }
+
+    /// Converts CSS-pixel coordinates relative to the canvas into render-buffer
+    /// pixels (origin top-left), or `None` if the point lies in a letterbox bar.
+    pub fn client_to_render(&self, client_x: f32, client_y: f32) -> Option<(f32, f32)> {
+        let device_x = client_x * self.pixel_ratio;
+        let device_y = client_y * self.pixel_ratio;
+
+        let (x, y, width, height) = self.content_rect();
+        let local_x = device_x - x;
+        let local_y = device_y - y;
+        if local_x < 0.0 || local_y < 0.0 || local_x >= width || local_y >= height {
+            return None;
+        }
+
+        let (render_width, render_height) = self.render_size();
+        Some((
+            local_x * render_width as f32 / width,
+            local_y * render_height as f32 / height,
+        ))
+    }
+
+    /// Like `client_to_render`, but a point in a letterbox bar or off the
+    /// canvas is moved to the nearest edge of the image.
+    pub fn client_to_render_clamped(&self, client_x: f32, client_y: f32) -> (f32, f32) {
+        let (x, y, width, height) = self.content_rect();
+        let local_x = (client_x * self.pixel_ratio - x).clamp(0.0, width);
+        let local_y = (client_y * self.pixel_ratio - y).clamp(0.0, height);
+
+        let (render_width, render_height) = self.render_size();
+        (
+            local_x * render_width as f32 / width.max(1.0),
+            local_y * render_height as f32 / height.max(1.0),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_ratio_and_render_scale() {
+        // A 400x300 CSS canvas on a 2x display, rendered at half resolution
+        let mut viewport = Viewport::new(800, 600);
+        viewport.pixel_ratio = 2.0;
+        viewport.render_scale = 0.5;
+
+        assert_eq!(viewport.render_size(), (400, 300));
+        assert_eq!(viewport.client_to_render(0.0, 0.0), Some((0.0, 0.0)));
+        assert_eq!(viewport.client_to_render(100.0, 50.0), Some((100.0, 50.0)));
+        assert_eq!(viewport.client_to_render(399.0, 299.0), Some((399.0, 299.0)));
+    }
+
+    #[test]
+    fn render_scale_alone() {
+        let mut viewport = Viewport::new(640, 480);
+        viewport.render_scale = 0.25;
+
+        assert_eq!(viewport.render_size(), (160, 120));
+        assert_eq!(viewport.client_to_render(320.0, 240.0), Some((80.0, 60.0)));
+    }
+
+    #[test]
+    fn letterboxed_aspect_lock() {
+        // 16:9 inside a square canvas leaves bars above and below the image
+        let mut viewport = Viewport::new(1600, 1600);
+        viewport.aspect_lock = Some(16.0 / 9.0);
+
+        assert_eq!(viewport.content_rect(), (0.0, 350.0, 1600.0, 900.0));
+        assert_eq!(viewport.render_size(), (1600, 900));
+        assert_eq!(viewport.client_to_render(800.0, 350.0), Some((800.0, 0.0)));
+        assert_eq!(viewport.client_to_render(800.0, 800.0), Some((800.0, 450.0)));
+        assert_eq!(viewport.client_to_render(800.0, 349.0), None);
+        assert_eq!(viewport.client_to_render(800.0, 1250.0), None);
+    }
+
+    #[test]
+    fn pillarboxed_aspect_lock_with_pixel_ratio_and_render_scale() {
+        // 1:1 inside a 2:1 canvas leaves bars left and right
+        let mut viewport = Viewport::new(800, 400);
+        viewport.pixel_ratio = 2.0;
+        viewport.render_scale = 0.5;
+        viewport.aspect_lock = Some(1.0);
+
+        assert_eq!(viewport.content_rect(), (200.0, 0.0, 400.0, 400.0));
+        assert_eq!(viewport.render_size(), (200, 200));
+        assert_eq!(viewport.client_to_render(100.0, 0.0), Some((0.0, 0.0)));
+        assert_eq!(viewport.client_to_render(200.0, 100.0), Some((100.0, 100.0)));
+        assert_eq!(viewport.client_to_render(99.0, 100.0), None);
+        assert_eq!(viewport.client_to_render(300.0, 100.0), None);
+    }
+
+    #[test]
+    fn points_outside_the_canvas() {
+        let viewport = Viewport::new(200, 100);
+
+        assert_eq!(viewport.client_to_render(-1.0, 50.0), None);
+        assert_eq!(viewport.client_to_render(50.0, -1.0), None);
+        assert_eq!(viewport.client_to_render(200.0, 50.0), None);
+        assert_eq!(viewport.client_to_render(50.0, 100.0), None);
+    }
+
+    #[test]
+    fn clamped_points_stay_on_the_image() {
+        let mut viewport = Viewport::new(1600, 1600);
+        viewport.aspect_lock = Some(16.0 / 9.0);
+
+        assert_eq!(viewport.client_to_render_clamped(800.0, 0.0), (800.0, 0.0));
+        assert_eq!(viewport.client_to_render_clamped(-50.0, 2000.0), (0.0, 900.0));
+        assert_eq!(viewport.client_to_render_clamped(800.0, 800.0), (800.0, 450.0));
+    }
+}
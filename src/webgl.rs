@@ -1,15 +1,95 @@
+use serde::Deserialize;
 use wasm_bindgen::prelude::*;
-use web_sys::{WebGlBuffer, WebGlRenderingContext, WebGlShader, WebGlTexture};
-
-pub fn init_webgl_context(canvas_id: &str) -> Result<WebGlRenderingContext, JsValue> {
-    let document = web_sys::window().unwrap().document().unwrap();
-    let canvas = document.get_element_by_id(canvas_id).unwrap();
-    let canvas: web_sys::HtmlCanvasElement = canvas.dyn_into::<web_sys::HtmlCanvasElement>()?;
-    let gl: WebGlRenderingContext = canvas
-        .get_context("webgl")?
-        .unwrap()
-        .dyn_into::<WebGlRenderingContext>()
-        .unwrap();
+use web_sys::{
+    WebGl2RenderingContext, WebGlBuffer, WebGlContextAttributes, WebGlFramebuffer, WebGlProgram,
+    WebGlRenderingContext, WebGlShader, WebGlTexture,
+};
+
+use crate::error::RaytracerError;
+
+/// Attributes requested when the WebGL context is created. They are fixed for
+/// the lifetime of the context.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct ContextOptions {
+    /// Give the drawing buffer an alpha channel, needed for a transparent
+    /// background
+    pub alpha: bool,
+    /// Ask for a WebGL2 context before falling back to WebGL1
+    pub webgl2: bool,
+}
+
+impl Default for ContextOptions {
+    fn default() -> Self {
+        Self {
+            alpha: true,
+            webgl2: true,
+        }
+    }
+}
+
+/// The WebGL version of a context. Both are driven through the WebGL1
+/// bindings, see `init_webgl_context`; the version picks the shader dialect
+/// and what is core rather than an extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContextKind {
+    WebGl1,
+    WebGl2,
+}
+
+impl ContextKind {
+    pub fn of(gl: &WebGlRenderingContext) -> Self {
+        if gl.is_instance_of::<WebGl2RenderingContext>() {
+            ContextKind::WebGl2
+        } else {
+            ContextKind::WebGl1
+        }
+    }
+
+    /// The context type passed to `getContext`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ContextKind::WebGl1 => "webgl",
+            ContextKind::WebGl2 => "webgl2",
+        }
+    }
+}
+
+pub fn init_webgl_context(
+    canvas_id: &str,
+    options: &ContextOptions,
+) -> Result<WebGlRenderingContext, RaytracerError> {
+    let context_error = |reason: String| RaytracerError::ContextCreation { reason };
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| context_error("no document to look the canvas up in".to_string()))?;
+    let canvas = document
+        .get_element_by_id(canvas_id)
+        .ok_or_else(|| context_error(format!("no element with id '{}'", canvas_id)))?;
+    let canvas: web_sys::HtmlCanvasElement = canvas
+        .dyn_into::<web_sys::HtmlCanvasElement>()
+        .map_err(|_| context_error(format!("element '{}' is not a canvas", canvas_id)))?;
+
+    let attributes = WebGlContextAttributes::new();
+    attributes.set_alpha(options.alpha);
+    let kinds: &[ContextKind] = if options.webgl2 {
+        &[ContextKind::WebGl2, ContextKind::WebGl1]
+    } else {
+        &[ContextKind::WebGl1]
+    };
+    // A WebGL2 context has every WebGL1 method with the same signature, and
+    // the bindings call methods by name, so it is used through the WebGL1
+    // type too
+    let gl: WebGlRenderingContext = kinds
+        .iter()
+        .find_map(|kind| {
+            canvas
+                .get_context_with_context_options(kind.name(), &attributes)
+                .ok()
+                .flatten()
+        })
+        .map(|context| context.unchecked_into::<WebGlRenderingContext>())
+        .ok_or_else(|| context_error("WebGL is not available".to_string()))?;
 
     gl.viewport(0, 0, canvas.width() as i32, canvas.height() as i32);
     gl.get_extension("OES_texture_float").ok();
@@ -17,18 +97,178 @@ pub fn init_webgl_context(canvas_id: &str) -> Result<WebGlRenderingContext, JsVa
     Ok(gl)
 }
 
+/// Whether the context can sample float textures: core in WebGL2, and
+/// OES_texture_float (which `init_webgl_context` enables) in WebGL1.
+pub fn supports_float_textures(gl: &WebGlRenderingContext) -> bool {
+    ContextKind::of(gl) == ContextKind::WebGl2
+        || gl
+            .get_extension("OES_texture_float")
+            .ok()
+            .flatten()
+            .is_some()
+}
+
+/// Whether the context can render into float textures, enabling the
+/// extension that allows it: EXT_color_buffer_float in WebGL2 and
+/// WEBGL_color_buffer_float in WebGL1.
+pub fn supports_float_rendering(gl: &WebGlRenderingContext) -> bool {
+    let extension = match ContextKind::of(gl) {
+        ContextKind::WebGl1 => "WEBGL_color_buffer_float",
+        ContextKind::WebGl2 => "EXT_color_buffer_float",
+    };
+    supports_float_textures(gl) && gl.get_extension(extension).ok().flatten().is_some()
+}
+
+/// Whether float textures may use LINEAR filtering (OES_texture_float_linear).
+pub fn supports_float_filtering(gl: &WebGlRenderingContext) -> bool {
+    gl.get_extension("OES_texture_float_linear")
+        .ok()
+        .flatten()
+        .is_some()
+}
+
+/// Internal format of an RGBA float texture: WebGL2 needs the sized format
+/// where WebGL1 takes the unsized one.
+pub fn rgba_float_format(gl: &WebGlRenderingContext) -> u32 {
+    match ContextKind::of(gl) {
+        ContextKind::WebGl1 => WebGlRenderingContext::RGBA,
+        ContextKind::WebGl2 => WebGl2RenderingContext::RGBA32F,
+    }
+}
+
+/// Whether the context's drawing buffer actually has an alpha channel.
+pub fn has_alpha(gl: &WebGlRenderingContext) -> bool {
+    gl.get_context_attributes()
+        .and_then(|attributes| attributes.get_alpha())
+        .unwrap_or(false)
+}
+
+/// The driver's `MAX_FRAGMENT_UNIFORM_VECTORS`: how many vec4 uniform slots
+/// a fragment shader may use. WebGL only guarantees 16.
+pub fn max_fragment_uniform_vectors(gl: &WebGlRenderingContext) -> u32 {
+    gl.get_parameter(WebGlRenderingContext::MAX_FRAGMENT_UNIFORM_VECTORS)
+        .ok()
+        .and_then(|value| value.as_f64())
+        .map_or(0, |value| value as u32)
+}
+
+/// Estimate of the vec4 uniform slots `program` uses: one per scalar or
+/// vector array element and one per matrix column, as drivers that don't
+/// pack uniforms together allocate them.
+pub fn uniform_vectors(gl: &WebGlRenderingContext, program: &WebGlProgram) -> u32 {
+    let count = gl
+        .get_program_parameter(program, WebGlRenderingContext::ACTIVE_UNIFORMS)
+        .as_f64()
+        .unwrap_or(0.0) as u32;
+    (0..count)
+        .filter_map(|index| gl.get_active_uniform(program, index))
+        .map(|info| {
+            let columns = match info.type_() {
+                WebGlRenderingContext::FLOAT_MAT2 => 2,
+                WebGlRenderingContext::FLOAT_MAT3 => 3,
+                WebGlRenderingContext::FLOAT_MAT4 => 4,
+                _ => 1,
+            };
+            info.size().max(0) as u32 * columns
+        })
+        .sum()
+}
+
+/// `gl` as a WebGL2 context, if it is one.
+pub fn webgl2(gl: &WebGlRenderingContext) -> Option<&WebGl2RenderingContext> {
+    (ContextKind::of(gl) == ContextKind::WebGl2).then(|| gl.unchecked_ref())
+}
+
+/// A uniform buffer feeding a uniform block at a fixed binding point
+/// (WebGL2 only). Its contents are only re-sent when they change.
+pub struct UniformBuffer {
+    buffer: WebGlBuffer,
+    binding: u32,
+    contents: Vec<u8>,
+}
+
+impl UniformBuffer {
+    /// An empty buffer for binding point `binding`, or `None` without WebGL2.
+    pub fn new(gl: &WebGlRenderingContext, binding: u32) -> Option<Self> {
+        let buffer = webgl2(gl)?.create_buffer()?;
+        Some(Self {
+            buffer,
+            binding,
+            contents: Vec::new(),
+        })
+    }
+
+    /// Uploads `bytes` unless the buffer already holds them, and returns the
+    /// number of bytes sent.
+    pub fn update(&mut self, gl: &WebGlRenderingContext, bytes: &[u8]) -> usize {
+        let Some(gl2) = webgl2(gl) else {
+            return 0;
+        };
+        if bytes == self.contents {
+            return 0;
+        }
+        gl2.bind_buffer(WebGl2RenderingContext::UNIFORM_BUFFER, Some(&self.buffer));
+        if bytes.len() == self.contents.len() {
+            gl2.buffer_sub_data_with_i32_and_u8_array(
+                WebGl2RenderingContext::UNIFORM_BUFFER,
+                0,
+                bytes,
+            );
+        } else {
+            gl2.buffer_data_with_u8_array(
+                WebGl2RenderingContext::UNIFORM_BUFFER,
+                bytes,
+                WebGl2RenderingContext::DYNAMIC_DRAW,
+            );
+        }
+        gl2.bind_buffer(WebGl2RenderingContext::UNIFORM_BUFFER, None);
+        self.contents = bytes.to_vec();
+        bytes.len()
+    }
+
+    /// Feeds `program`'s uniform block `block` from the buffer.
+    pub fn bind(&self, gl: &WebGlRenderingContext, program: &WebGlProgram, block: &str) {
+        let Some(gl2) = webgl2(gl) else {
+            return;
+        };
+        let index = gl2.get_uniform_block_index(program, block);
+        if index != WebGl2RenderingContext::INVALID_INDEX {
+            gl2.uniform_block_binding(program, index, self.binding);
+        }
+        gl2.bind_buffer_base(
+            WebGl2RenderingContext::UNIFORM_BUFFER,
+            self.binding,
+            Some(&self.buffer),
+        );
+    }
+
+    /// GPU memory held by the buffer.
+    pub fn bytes(&self) -> usize {
+        self.contents.len()
+    }
+}
+
 pub fn create_texture(
     gl: &WebGlRenderingContext,
     width: u32,
     height: u32,
-) -> Result<WebGlTexture, JsValue> {
+) -> Result<WebGlTexture, RaytracerError> {
+    create_texture_with_pixels(gl, width, height, &vec![0u8; (width * height * 4) as usize])
+}
+
+/// Like `create_texture`, filled with RGBA `pixels` (first row at t = 0).
+pub fn create_texture_with_pixels(
+    gl: &WebGlRenderingContext,
+    width: u32,
+    height: u32,
+    pixels: &[u8],
+) -> Result<WebGlTexture, RaytracerError> {
     let texture = gl
         .create_texture()
-        .ok_or_else(|| JsValue::from_str("Failed to create texture"))?;
+        .ok_or_else(|| RaytracerError::graphics("Failed to create texture"))?;
 
     gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, Some(&texture));
 
-    let data = vec![0u8; (width * height * 4) as usize];
     gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
         WebGlRenderingContext::TEXTURE_2D,
         0,
@@ -38,7 +278,7 @@ pub fn create_texture(
         0,
         WebGlRenderingContext::RGBA,
         WebGlRenderingContext::UNSIGNED_BYTE,
-        Some(&data),
+        Some(pixels),
     )?;
 
     gl.tex_parameteri(
@@ -65,10 +305,203 @@ pub fn create_texture(
     Ok(texture)
 }
 
-pub fn create_quad_buffer(gl: &WebGlRenderingContext) -> Result<WebGlBuffer, JsValue> {
+/// An offscreen color buffer to render into instead of the canvas.
+pub struct RenderTarget {
+    pub framebuffer: WebGlFramebuffer,
+    pub texture: WebGlTexture,
+    pub width: u32,
+    pub height: u32,
+    // Whether the texture holds 32-bit floats rather than bytes
+    float: bool,
+}
+
+impl RenderTarget {
+    pub fn new(
+        gl: &WebGlRenderingContext,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, RaytracerError> {
+        let texture = create_texture(gl, width, height)?;
+        Self::with_texture(gl, texture, width, height, false)
+    }
+
+    /// Like `new` with an RGBA float texture, which keeps values outside
+    /// [0, 1] and far more precision. Needs `supports_float_rendering`.
+    pub fn new_float(
+        gl: &WebGlRenderingContext,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, RaytracerError> {
+        if !supports_float_rendering(gl) {
+            return Err(RaytracerError::graphics("Float render targets are not supported"));
+        }
+        let texture = create_texture(gl, 1, 1)?;
+        let pixels = js_sys::Float32Array::new_with_length(width * height * 4);
+        let result = gl
+            .tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_array_buffer_view(
+                WebGlRenderingContext::TEXTURE_2D,
+                0,
+                rgba_float_format(gl) as i32,
+                width as i32,
+                height as i32,
+                0,
+                WebGlRenderingContext::RGBA,
+                WebGlRenderingContext::FLOAT,
+                Some(&pixels),
+            );
+        if let Err(e) = result {
+            gl.delete_texture(Some(&texture));
+            return Err(e.into());
+        }
+        Self::with_texture(gl, texture, width, height, true)
+    }
+
+    /// Attaches `texture` to a new framebuffer, deleting both if the driver
+    /// can't render into it.
+    fn with_texture(
+        gl: &WebGlRenderingContext,
+        texture: WebGlTexture,
+        width: u32,
+        height: u32,
+        float: bool,
+    ) -> Result<Self, RaytracerError> {
+        let Some(framebuffer) = gl.create_framebuffer() else {
+            gl.delete_texture(Some(&texture));
+            return Err(RaytracerError::graphics("Failed to create framebuffer"));
+        };
+
+        gl.bind_framebuffer(WebGlRenderingContext::FRAMEBUFFER, Some(&framebuffer));
+        gl.framebuffer_texture_2d(
+            WebGlRenderingContext::FRAMEBUFFER,
+            WebGlRenderingContext::COLOR_ATTACHMENT0,
+            WebGlRenderingContext::TEXTURE_2D,
+            Some(&texture),
+            0,
+        );
+
+        let status = gl.check_framebuffer_status(WebGlRenderingContext::FRAMEBUFFER);
+        let target = Self {
+            framebuffer,
+            texture,
+            width,
+            height,
+            float,
+        };
+        if status != WebGlRenderingContext::FRAMEBUFFER_COMPLETE {
+            target.delete(gl);
+            return Err(RaytracerError::graphics(format!(
+                "Framebuffer incomplete (status 0x{:x})",
+                status
+            )));
+        }
+
+        Ok(target)
+    }
+
+    /// Reads the target back as RGBA rows, bottom row first.
+    pub fn read_pixels(&self, gl: &WebGlRenderingContext) -> Result<Vec<u8>, RaytracerError> {
+        let mut pixels = vec![0u8; (self.width * self.height * 4) as usize];
+        gl.bind_framebuffer(WebGlRenderingContext::FRAMEBUFFER, Some(&self.framebuffer));
+        gl.read_pixels_with_opt_u8_array(
+            0,
+            0,
+            self.width as i32,
+            self.height as i32,
+            WebGlRenderingContext::RGBA,
+            WebGlRenderingContext::UNSIGNED_BYTE,
+            Some(&mut pixels),
+        )?;
+        Ok(pixels)
+    }
+
+    /// Memory held by the color texture.
+    pub fn bytes(&self) -> usize {
+        let texel_bytes = if self.float { 16 } else { 4 };
+        self.width as usize * self.height as usize * texel_bytes
+    }
+
+    pub fn is_float(&self) -> bool {
+        self.float
+    }
+
+    pub fn delete(&self, gl: &WebGlRenderingContext) {
+        gl.delete_framebuffer(Some(&self.framebuffer));
+        gl.delete_texture(Some(&self.texture));
+    }
+}
+
+/// The bits of GL state that offscreen passes change, captured so they can be
+/// put back exactly as they were.
+pub struct GlState {
+    framebuffer: Option<WebGlFramebuffer>,
+    viewport: [i32; 4],
+    clear_color: [f32; 4],
+    program: Option<WebGlProgram>,
+    array_buffer: Option<WebGlBuffer>,
+    active_texture: u32,
+    texture: Option<WebGlTexture>,
+}
+
+impl GlState {
+    pub fn capture(gl: &WebGlRenderingContext) -> Self {
+        let viewport = gl
+            .get_parameter(WebGlRenderingContext::VIEWPORT)
+            .ok()
+            .and_then(|v| v.dyn_into::<js_sys::Int32Array>().ok())
+            .map(|v| {
+                let mut values = [0; 4];
+                v.copy_to(&mut values);
+                values
+            })
+            .unwrap_or_default();
+        let clear_color = gl
+            .get_parameter(WebGlRenderingContext::COLOR_CLEAR_VALUE)
+            .ok()
+            .and_then(|v| v.dyn_into::<js_sys::Float32Array>().ok())
+            .map(|v| {
+                let mut values = [0.0; 4];
+                v.copy_to(&mut values);
+                values
+            })
+            .unwrap_or([0.0, 0.0, 0.0, 1.0]);
+
+        Self {
+            framebuffer: parameter(gl, WebGlRenderingContext::FRAMEBUFFER_BINDING),
+            viewport,
+            clear_color,
+            program: parameter(gl, WebGlRenderingContext::CURRENT_PROGRAM),
+            array_buffer: parameter(gl, WebGlRenderingContext::ARRAY_BUFFER_BINDING),
+            active_texture: gl
+                .get_parameter(WebGlRenderingContext::ACTIVE_TEXTURE)
+                .ok()
+                .and_then(|v| v.as_f64())
+                .map(|v| v as u32)
+                .unwrap_or(WebGlRenderingContext::TEXTURE0),
+            texture: parameter(gl, WebGlRenderingContext::TEXTURE_BINDING_2D),
+        }
+    }
+
+    pub fn restore(&self, gl: &WebGlRenderingContext) {
+        gl.bind_framebuffer(WebGlRenderingContext::FRAMEBUFFER, self.framebuffer.as_ref());
+        let [x, y, width, height] = self.viewport;
+        gl.viewport(x, y, width, height);
+        let [r, g, b, a] = self.clear_color;
+        gl.clear_color(r, g, b, a);
+        gl.use_program(self.program.as_ref());
+        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, self.array_buffer.as_ref());
+        gl.active_texture(self.active_texture);
+        gl.bind_texture(WebGlRenderingContext::TEXTURE_2D, self.texture.as_ref());
+    }
+}
+
+fn parameter<T: JsCast>(gl: &WebGlRenderingContext, name: u32) -> Option<T> {
+    gl.get_parameter(name).ok().and_then(|v| v.dyn_into::<T>().ok())
+}
+
+pub fn create_quad_buffer(gl: &WebGlRenderingContext) -> Result<WebGlBuffer, RaytracerError> {
     let buffer = gl
         .create_buffer()
-        .ok_or_else(|| JsValue::from_str("Failed to create buffer"))?;
+        .ok_or_else(|| RaytracerError::graphics("Failed to create buffer"))?;
 
     let vertices: [f32; 12] = [
         -1.0, -1.0, 1.0, -1.0, -1.0, 1.0, -1.0, 1.0, 1.0, -1.0, 1.0, 1.0,
@@ -85,29 +518,37 @@ pub fn create_quad_buffer(gl: &WebGlRenderingContext) -> Result<WebGlBuffer, JsV
     Ok(buffer)
 }
 
-pub fn create_shader(
+/// Submits `source` for compilation without waiting for the result, which
+/// only `check_shader` (or linking) blocks on.
+pub fn compile_shader(
     gl: &WebGlRenderingContext,
     shader_type: u32,
     source: &str,
-) -> Result<WebGlShader, JsValue> {
+) -> Result<WebGlShader, RaytracerError> {
     let shader = gl
         .create_shader(shader_type)
-        .ok_or_else(|| JsValue::from_str("Unable to create shader object"))?;
+        .ok_or_else(|| RaytracerError::graphics("Unable to create shader object"))?;
 
     gl.shader_source(&shader, source);
     gl.compile_shader(&shader);
+    Ok(shader)
+}
 
+pub fn check_shader(
+    gl: &WebGlRenderingContext,
+    shader: &WebGlShader,
+) -> Result<(), RaytracerError> {
     if gl
-        .get_shader_parameter(&shader, WebGlRenderingContext::COMPILE_STATUS)
+        .get_shader_parameter(shader, WebGlRenderingContext::COMPILE_STATUS)
         .as_bool()
         .unwrap_or(false)
     {
-        Ok(shader)
+        Ok(())
     } else {
         let error_log = gl
-            .get_shader_info_log(&shader)
+            .get_shader_info_log(shader)
             .unwrap_or_else(|| "Unknown error creating shader".into());
         web_sys::console::log_1(&format!("Shader compilation error: {}", error_log).into());
-        Err(JsValue::from_str(&error_log))
+        Err(RaytracerError::ShaderCompile { log: error_log })
     }
 }
@@ -0,0 +1,10 @@
+#!/bin/sh
+
+# Writes the renders printed by the golden-image tests when built with
+# GOLDEN_BLESS into tests/golden; see tests/golden.rs
+
+cd "$(dirname "$0")/golden" || exit 1
+grep -o 'golden [a-z0-9_]* [0-9a-f]*' | while read -r _ name hex; do
+    echo "$hex" | xxd -r -p > "$name.rgba"
+    echo "Blessed $name"
+done
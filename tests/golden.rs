@@ -0,0 +1,415 @@
+//! Golden-image regression tests: small deterministic scenes rendered with
+//! `Raytracer::render_to_pixels` in a headless browser and compared against
+//! the reference buffers in `tests/golden`.
+//!
+//! ```bash
+//! wasm-pack test --headless --chrome -- --test golden
+//! ```
+//!
+//! (`--firefox` works too.) Every case is built in Rust, drawn at `SIZE` x
+//! `SIZE` with scene time paused at 0 and fixed quality settings, so the same
+//! build on the same GPU draws the same pixels each run. A render passes if
+//! its mean absolute error against the reference stays under
+//! `MAX_MEAN_ERROR` and no single pixel is off by more than
+//! `MAX_PIXEL_ERROR`: the first catches drift across the whole image, the
+//! second a small object drawn wrong.
+//!
+//! References are raw RGBA bytes, top row first. After an intended change
+//! to the output, regenerate them by building with `GOLDEN_BLESS` set; each
+//! case then prints its render instead of comparing, and `tests/bless.sh`
+//! writes those into place:
+//!
+//! ```bash
+//! GOLDEN_BLESS=1 wasm-pack test --headless --chrome -- --test golden -- --nocapture \
+//!     | sh tests/bless.sh
+//! ```
+//!
+//! To add a case, write a function building its `Scene` and a test that
+//! hands it to `check` with a name and a camera, then create an empty
+//! `tests/golden/<name>.rgba` (it is compiled in) and bless it:
+//!
+//! ```ignore
+//! #[wasm_bindgen_test]
+//! fn lone_sphere() {
+//!     let scene = Scene::builder()
+//!         .sphere(Vec3::zero(), 1.0, Material::lambertian(Vec3::new(0.8, 0.2, 0.2)))
+//!         .light(Vec3::new(2.0, 4.0, 3.0), Vec3::one(), 1.0)
+//!         .build();
+//!     check("lone_sphere", &scene, &FRONT, include_bytes!("golden/lone_sphere.rgba"));
+//! }
+//! ```
+
+#![cfg(all(target_arch = "wasm32", feature = "webgl"))]
+
+use raytracer::Raytracer;
+use raytracer::material::{Material, Texture, TextureSpace};
+use raytracer::math::Vec3;
+use raytracer::scene::{CsgOp, CsgOperation, ObjectRef, ObjectType, Scene};
+use wasm_bindgen_test::{console_log, wasm_bindgen_test, wasm_bindgen_test_configure};
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+/// Width and height of every render.
+const SIZE: u32 = 128;
+/// Largest mean difference per color channel, on the 0 to 255 scale.
+const MAX_MEAN_ERROR: f64 = 1.5;
+/// Largest difference in any channel of any one pixel.
+const MAX_PIXEL_ERROR: u8 = 48;
+
+/// Where the camera sits and what it looks at.
+struct View {
+    position: [f32; 3],
+    target: [f32; 3],
+}
+
+const FRONT: View = View {
+    position: [0.0, 1.5, 6.0],
+    target: [0.0, 0.3, 0.0],
+};
+
+fn ground() -> Material {
+    Material::lambertian(Vec3::new(0.6, 0.6, 0.6))
+}
+
+fn checker(space: TextureSpace) -> Material {
+    Material {
+        texture_space: space,
+        texture: Some(Texture::Checker {
+            color_a: Vec3::new(0.9, 0.9, 0.9),
+            color_b: Vec3::new(0.1, 0.2, 0.6),
+            scale: 0.5,
+        }),
+        ..Material::lambertian(Vec3::one())
+    }
+}
+
+/// Renders `scene` from `view` on a fresh raytracer with fixed settings.
+fn render(name: &str, scene: &Scene, view: &View) -> Vec<u8> {
+    let canvas_id = format!("golden-{}", name);
+    let body = web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.body())
+        .expect("the test page has a body");
+    body.insert_adjacent_html(
+        "beforeend",
+        &format!(
+            r#"<canvas id="{}" width="{}" height="{}"></canvas>"#,
+            canvas_id, SIZE, SIZE
+        ),
+    )
+    .expect("canvas added");
+
+    let mut raytracer = Raytracer::new(&canvas_id, SIZE, SIZE).expect("raytracer created");
+    raytracer
+        .load_scene_json(&scene.to_json())
+        .expect("scene loaded");
+    raytracer.set_scene_time(0.0);
+    raytracer.set_max_bounces(6);
+    raytracer.set_samples_per_pixel(16);
+    let [x, y, z] = view.position;
+    raytracer.set_camera_position(x, y, z);
+    let [x, y, z] = view.target;
+    raytracer.set_camera_target(x, y, z);
+    raytracer.render_to_pixels(SIZE, SIZE).expect("rendered")
+}
+
+/// Mean absolute error per color channel and the largest channel
+/// difference of any pixel; alpha is left out.
+fn difference(actual: &[u8], expected: &[u8]) -> (f64, u8) {
+    let mut total = 0u64;
+    let mut max = 0u8;
+    for (a, e) in actual.chunks_exact(4).zip(expected.chunks_exact(4)) {
+        for channel in 0..3 {
+            let d = a[channel].abs_diff(e[channel]);
+            total += d as u64;
+            max = max.max(d);
+        }
+    }
+    (total as f64 / (actual.len() / 4 * 3) as f64, max)
+}
+
+fn assert_matches(name: &str, actual: &[u8], expected: &[u8]) {
+    assert_eq!(
+        actual.len(),
+        expected.len(),
+        "{}: buffer sizes differ",
+        name
+    );
+    let (mean, max) = difference(actual, expected);
+    assert!(
+        mean <= MAX_MEAN_ERROR && max <= MAX_PIXEL_ERROR,
+        "{}: mean error {:.3} (at most {}), worst pixel off by {} (at most {})",
+        name,
+        mean,
+        MAX_MEAN_ERROR,
+        max,
+        MAX_PIXEL_ERROR
+    );
+}
+
+/// Compares the render of `scene` against `reference`, or prints it for
+/// `tests/bless.sh` when built with `GOLDEN_BLESS`.
+fn check(name: &str, scene: &Scene, view: &View, reference: &[u8]) -> Vec<u8> {
+    let pixels = render(name, scene, view);
+    if option_env!("GOLDEN_BLESS").is_some() {
+        let hex: String = pixels.iter().map(|byte| format!("{:02x}", byte)).collect();
+        console_log!("golden {} {}", name, hex);
+        return pixels;
+    }
+    assert!(
+        !reference.is_empty(),
+        "{}: no reference yet; run with GOLDEN_BLESS set to create tests/golden/{}.rgba",
+        name,
+        name
+    );
+    assert_matches(name, &pixels, reference);
+    pixels
+}
+
+#[wasm_bindgen_test]
+fn spheres_in_every_material() {
+    let glowing = Material::emissive(Vec3::new(1.0, 0.6, 0.2), 3.0);
+    let scene = Scene::builder()
+        .plane(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            ground(),
+        )
+        .sphere(
+            Vec3::new(-2.4, 0.0, 0.0),
+            0.9,
+            Material::lambertian(Vec3::new(0.8, 0.2, 0.2)),
+        )
+        .sphere(
+            Vec3::new(-0.8, 0.0, -0.5),
+            0.9,
+            Material::metal(Vec3::new(0.9, 0.9, 0.9), 0.05),
+        )
+        .sphere(Vec3::new(0.8, 0.0, 0.5), 0.9, Material::dielectric(1.5))
+        .sphere(Vec3::new(2.4, 0.0, 0.0), 0.9, glowing)
+        .light(Vec3::new(2.0, 5.0, 4.0), Vec3::one(), 1.0)
+        .build();
+    check(
+        "spheres_in_every_material",
+        &scene,
+        &FRONT,
+        include_bytes!("golden/spheres_in_every_material.rgba"),
+    );
+}
+
+#[wasm_bindgen_test]
+fn boxes_and_cylinders() {
+    let scene = Scene::builder()
+        .plane(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            ground(),
+        )
+        .cuboid(
+            Vec3::new(-1.5, -0.25, 0.0),
+            Vec3::new(1.5, 1.5, 1.5),
+            Material::lambertian(Vec3::new(0.2, 0.7, 0.3)),
+        )
+        .cuboid(
+            Vec3::new(1.5, -0.5, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            Material::metal(Vec3::new(0.8, 0.6, 0.3), 0.3),
+        )
+        .cylinder(
+            Vec3::new(0.5, -1.0, 1.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            0.5,
+            Material::dielectric(1.33),
+        )
+        .light(Vec3::new(-3.0, 5.0, 4.0), Vec3::one(), 1.0)
+        .build();
+    check(
+        "boxes_and_cylinders",
+        &scene,
+        &FRONT,
+        include_bytes!("golden/boxes_and_cylinders.rgba"),
+    );
+}
+
+#[wasm_bindgen_test]
+fn cones_disks_and_rings() {
+    let scene = Scene::builder()
+        .plane(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            ground(),
+        )
+        .cone(
+            Vec3::new(-1.5, 1.0, 0.0),
+            Vec3::new(0.0, -2.0, 0.0),
+            0.8,
+            Material::lambertian(Vec3::new(0.9, 0.5, 0.1)),
+        )
+        .disk(
+            Vec3::new(0.5, 0.2, 0.0),
+            Vec3::new(0.0, 0.3, 1.0),
+            0.9,
+            0.0,
+            Material::metal(Vec3::new(0.7, 0.7, 0.8), 0.1),
+        )
+        .disk(
+            Vec3::new(2.0, 0.0, -0.5),
+            Vec3::new(-0.5, 0.2, 1.0),
+            0.8,
+            0.4,
+            Material::lambertian(Vec3::new(0.3, 0.3, 0.9)),
+        )
+        .light(Vec3::new(0.0, 5.0, 4.0), Vec3::one(), 1.0)
+        .build();
+    check(
+        "cones_disks_and_rings",
+        &scene,
+        &FRONT,
+        include_bytes!("golden/cones_disks_and_rings.rgba"),
+    );
+}
+
+#[wasm_bindgen_test]
+fn triangle_pyramid() {
+    let apex = Vec3::new(0.0, 1.2, 0.0);
+    let corners = [
+        Vec3::new(-1.2, -1.0, 1.2),
+        Vec3::new(1.2, -1.0, 1.2),
+        Vec3::new(1.2, -1.0, -1.2),
+        Vec3::new(-1.2, -1.0, -1.2),
+    ];
+    let faces = [
+        Material::lambertian(Vec3::new(0.8, 0.8, 0.2)),
+        Material::metal(Vec3::new(0.6, 0.8, 0.9), 0.2),
+        Material::dielectric(1.5),
+        Material::lambertian(Vec3::new(0.7, 0.3, 0.7)),
+    ];
+    let mut builder = Scene::builder()
+        .plane(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            ground(),
+        )
+        .light(Vec3::new(3.0, 5.0, 4.0), Vec3::one(), 1.0);
+    for (i, material) in faces.into_iter().enumerate() {
+        builder = builder.triangle(corners[i], corners[(i + 1) % 4], apex, material);
+    }
+    check(
+        "triangle_pyramid",
+        &builder.build(),
+        &FRONT,
+        include_bytes!("golden/triangle_pyramid.rgba"),
+    );
+}
+
+#[wasm_bindgen_test]
+fn checkered_floor_and_csg() {
+    let mut scene = Scene::builder()
+        .plane(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            checker(TextureSpace::World),
+        )
+        .cuboid(
+            Vec3::zero(),
+            Vec3::new(1.6, 1.6, 1.6),
+            Material::lambertian(Vec3::new(0.8, 0.3, 0.3)),
+        )
+        .sphere(
+            Vec3::new(0.5, 0.5, 0.5),
+            0.9,
+            Material::lambertian(Vec3::one()),
+        )
+        .light(Vec3::new(2.0, 5.0, 4.0), Vec3::one(), 1.0)
+        .build();
+    scene.add_csg(CsgOp {
+        op: CsgOperation::Subtract,
+        a: ObjectRef {
+            kind: ObjectType::Box,
+            index: 0,
+        },
+        b: ObjectRef {
+            kind: ObjectType::Sphere,
+            index: 0,
+        },
+    });
+    check(
+        "checkered_floor_and_csg",
+        &scene,
+        &FRONT,
+        include_bytes!("golden/checkered_floor_and_csg.rgba"),
+    );
+}
+
+#[wasm_bindgen_test]
+fn fog_and_translucency() {
+    let translucent = Material {
+        opacity: 0.5,
+        ..Material::lambertian(Vec3::new(0.2, 0.4, 0.9))
+    };
+    let scene = Scene::builder()
+        .plane(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            ground(),
+        )
+        .sphere(Vec3::new(-1.2, 0.0, 0.0), 1.0, translucent)
+        .cuboid(
+            Vec3::new(1.5, -0.4, -1.5),
+            Vec3::new(1.2, 1.2, 1.2),
+            Material::lambertian(Vec3::new(0.9, 0.8, 0.2)),
+        )
+        .volume(Vec3::new(1.0, 0.0, 0.0), 1.5, 0.6, Vec3::new(0.9, 0.9, 1.0))
+        .light(Vec3::new(0.0, 5.0, 4.0), Vec3::one(), 1.0)
+        .build();
+    check(
+        "fog_and_translucency",
+        &scene,
+        &FRONT,
+        include_bytes!("golden/fog_and_translucency.rgba"),
+    );
+}
+
+/// A checkered box with the light and ground, moved along with the camera
+/// by `offset`; only the texture space decides whether the picture changes.
+fn checkered_box(space: TextureSpace, offset: Vec3) -> (Scene, View) {
+    let scene = Scene::builder()
+        .plane(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            ground(),
+        )
+        .cuboid(offset, Vec3::new(1.6, 1.6, 1.6), checker(space))
+        .light(offset + Vec3::new(2.0, 5.0, 4.0), Vec3::one(), 1.0)
+        .build();
+    let position = offset + Vec3::new(2.5, 2.0, 4.5);
+    let view = View {
+        position: [position.x, position.y, position.z],
+        target: [offset.x, offset.y, offset.z],
+    };
+    (scene, view)
+}
+
+#[wasm_bindgen_test]
+fn moving_checkered_box_keeps_its_pattern() {
+    let reference = include_bytes!("golden/moving_checkered_box.rgba");
+    let offset = Vec3::new(0.37, 0.0, 0.21);
+
+    let (scene, view) = checkered_box(TextureSpace::Object, Vec3::zero());
+    let still = check("moving_checkered_box", &scene, &view, reference);
+    let (scene, view) = checkered_box(TextureSpace::Object, offset);
+    let moved = render("moving_checkered_box_moved", &scene, &view);
+    assert_matches("moving_checkered_box_moved", &moved, &still);
+
+    // In world space the same move slides the squares across the faces,
+    // which the comparison above has to be able to see
+    let (scene, view) = checkered_box(TextureSpace::World, Vec3::zero());
+    let world_still = render("world_checkered_box", &scene, &view);
+    let (scene, view) = checkered_box(TextureSpace::World, offset);
+    let world_moved = render("world_checkered_box_moved", &scene, &view);
+    let (mean, max) = difference(&world_moved, &world_still);
+    assert!(
+        mean > MAX_MEAN_ERROR || max > MAX_PIXEL_ERROR,
+        "a world-space checker should swim on a moving box"
+    );
+}
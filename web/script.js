@@ -374,20 +374,13 @@ class RaytracerApp {
         if (file) {
             try {
                 const text = await file.text();
-                const blenderData = JSON.parse(text);
-                
-                // Convert Blender data to our format
-                // This is a simplified conversion - you'd need to adapt based on your Blender export format
-                this.raytracer.clear_scene();
-                
-                if (blenderData.objects) {
-                    blenderData.objects.forEach(obj => {
-                        if (obj.type === 'MESH' && obj.name.includes('Sphere')) {
-                            const pos = obj.location || [0, 0, 0];
-                            const scale = obj.scale ? obj.scale[0] : 1;
-                            this.raytracer.add_sphere(pos[0], pos[2], -pos[1], scale, 0.7, 0.7, 0.7, 0);
-                        }
-                    });
+
+                // Blender is Z-up; switch the import axes for this file only
+                this.raytracer.set_import_axes('z', 'y', 1.0);
+                try {
+                    this.raytracer.load_blender_json(text);
+                } finally {
+                    this.raytracer.set_import_axes('y', '-z', 1.0);
                 }
                 
                 this.updateObjectCount();